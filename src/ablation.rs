@@ -0,0 +1,112 @@
+use crate::{
+    history::History,
+    training::{create_artifact_dir, train, TrainError, TrainingConfig},
+};
+use burn::tensor::backend::AutodiffBackend;
+use std::path::Path;
+
+// A component `ablation_study` can turn off: its name, whether a config enables it, and the
+// config without it
+struct Component {
+    name: &'static str,
+    enabled: fn(&TrainingConfig) -> bool,
+    disable: fn(TrainingConfig) -> TrainingConfig,
+}
+
+// The model's only augmentations are mixup and CutMix
+const COMPONENTS: [Component; 3] = [
+    Component {
+        name: "dropout",
+        enabled: |config| config.model.dropout > 0.0,
+        disable: |mut config| {
+            config.model.dropout = 0.0;
+            config
+        },
+    },
+    Component {
+        name: "augmentation",
+        enabled: |config| config.mixup_alpha.is_some() || config.cutmix_alpha.is_some(),
+        disable: |config| config.with_mixup_alpha(None).with_cutmix_alpha(None),
+    },
+    Component {
+        name: "batch_norm",
+        enabled: |config| config.model.batch_norm,
+        disable: |mut config| {
+            config.model.batch_norm = false;
+            config
+        },
+    },
+];
+
+// Best epoch mean of the validation accuracy of the run in `artifact_dir`, in percent
+fn best_accuracy(artifact_dir: &str) -> Result<f64, TrainError> {
+    let history = History::load(Path::new(artifact_dir).join("history.json"))?;
+    history
+        .best_valid("Accuracy")
+        .ok_or_else(|| TrainError::Logs(format!("{artifact_dir}/history.json records no validation accuracy")))
+}
+
+/// Wipes `artifact_dir` and trains `base_config` into `artifact_dir/base`, then once more per
+/// component it enables (dropout, augmentation, batch_norm) with only that component turned
+/// off, into `artifact_dir/no_<name>`. Returns every ablated component with the change of the
+/// best validation accuracy its removal causes, in percentage points: negative when the
+/// component helps.
+///
+/// This departs from a plain `(base_config, device) -> Vec<(String, f32)>`: the runs need a
+/// directory of their own to train into, since the accuracies are read back from their
+/// histories, and a run that fails to train is returned as its [`TrainError`] rather than
+/// panicking halfway through the study.
+pub fn ablation_study<B: AutodiffBackend>(
+    artifact_dir: &str,
+    base_config: &TrainingConfig,
+    device: B::Device,
+) -> Result<Vec<(String, f32)>, TrainError> {
+    create_artifact_dir(artifact_dir)?;
+    let base_dir = format!("{artifact_dir}/base");
+    train::<B>(&base_dir, base_config.clone(), device.clone())?;
+    let base = best_accuracy(&base_dir)?;
+
+    let mut deltas = Vec::new();
+    for component in COMPONENTS.iter().filter(|component| (component.enabled)(base_config)) {
+        let dir = format!("{artifact_dir}/no_{}", component.name);
+        train::<B>(&dir, (component.disable)(base_config.clone()), device.clone())?;
+        deltas.push((component.name.to_string(), (best_accuracy(&dir)? - base) as f32));
+    }
+    Ok(deltas)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{data::DatasetSource, synthetic::tiny_config};
+    use burn::backend::{ndarray::NdArrayDevice, Autodiff, NdArray};
+
+    fn config(dropout: f64, mixup_alpha: Option<f64>, batch_norm: bool) -> TrainingConfig {
+        let mut config =
+            tiny_config().with_dataset(DatasetSource::Synthetic { num_samples: 32, seed: 1 }).with_mixup_alpha(mixup_alpha);
+        config.model = config.model.with_dropout(dropout).with_batch_norm(batch_norm);
+        config
+    }
+
+    #[test]
+    fn study_has_one_entry_per_enabled_component() {
+        let artifact_dir = std::env::temp_dir().join("my_first_rust_DL_app-ablation");
+        let dir = artifact_dir.to_str().unwrap();
+        let device = NdArrayDevice::default();
+
+        let deltas = ablation_study::<Autodiff<NdArray>>(dir, &config(0.5, Some(0.2), true), device).unwrap();
+        let names: Vec<&str> = deltas.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["dropout", "augmentation", "batch_norm"]);
+        assert!(deltas.iter().all(|(_, delta)| delta.is_finite()), "{deltas:?}");
+        let runs = ["base", "no_dropout", "no_augmentation", "no_batch_norm"]
+            .map(|run| artifact_dir.join(run).join("history.json").exists());
+        assert_eq!(runs, [true; 4]);
+
+        // Nothing to turn off: the study is the base run alone
+        let deltas = ablation_study::<Autodiff<NdArray>>(dir, &config(0.0, None, false), device).unwrap();
+        let no_dropout = artifact_dir.join("no_dropout").exists();
+        std::fs::remove_dir_all(&artifact_dir).unwrap();
+        assert!(deltas.is_empty(), "{deltas:?}");
+        assert!(!no_dropout, "the study reuses the runs of a wiped artifact dir");
+    }
+}
@@ -0,0 +1,213 @@
+use crate::{
+    artifact::ArtifactDir,
+    checkpoint::LoadError,
+    data::{MnistBatch, MnistBatcher, MnistSplit},
+    evaluation::ordered_batches,
+    inference::load_model,
+    model::Model,
+    training::TrainingConfig,
+};
+use burn::{
+    data::dataloader::batcher::Batcher,
+    data::dataset::{vision::MnistDataset, vision::MnistItem, Dataset},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+
+// Statistics of the output of one layer over a batch, every value of every item pooled
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LayerActivationStats {
+    // The name of the layer in `ModelConfig::describe`
+    pub layer: String,
+    // [batch_size, channels, height, width], dense layers as [batch_size, features, 1, 1]
+    pub shape: [usize; 4],
+    pub mean: f64,
+    pub std: f64,
+    // Share of exact zeros: close to 1 after a ReLU means dead units
+    pub zero_fraction: f64,
+    pub max: f64,
+}
+
+impl LayerActivationStats {
+    fn new(layer: String, output: Tensor<impl Backend, 4>) -> Self {
+        let shape = output.dims();
+        let values = output.into_data().convert::<f32>().value;
+        let count = values.len().max(1) as f64;
+
+        // In f64: a conv output of a large batch has millions of values
+        let mean = values.iter().map(|&value| value as f64).sum::<f64>() / count;
+        let variance = values.iter().map(|&value| (value as f64 - mean).powi(2)).sum::<f64>() / count;
+        Self {
+            layer,
+            shape,
+            mean,
+            std: variance.sqrt(),
+            zero_fraction: values.iter().filter(|&&value| value == 0.0).count() as f64 / count,
+            max: values.iter().copied().fold(f32::NEG_INFINITY, f32::max) as f64,
+        }
+    }
+}
+
+/// Runs `batch` through `model` and summarizes the output of every layer, in forward order:
+/// the layers of [`Model::activations`], dropout inactive. The normal `forward` hands its
+/// layer outputs to a no-op instead, for the cost of a reference count each.
+pub fn activation_report<B: Backend>(model: &Model<B>, batch: &MnistBatch<B>) -> Vec<LayerActivationStats> {
+    model
+        .activations(batch.images.clone())
+        .into_iter()
+        .map(|(layer, output)| LayerActivationStats::new(layer, output))
+        .collect()
+}
+
+/// [`activation_report`] of the model trained into `artifact_dir`, on its first validation
+/// batch of `batch_size` items.
+pub fn valid_activation_report<B: Backend>(
+    artifact_dir: &str,
+    batch_size: usize,
+    device: &B::Device,
+) -> Result<Vec<LayerActivationStats>, LoadError> {
+    let config = TrainingConfig::load(ArtifactDir::new(artifact_dir).config_path())
+        .map_err(|err| LoadError::Config(err.to_string()))?;
+    let model = load_model::<B>(artifact_dir, device)?;
+
+    let dataset = config.dataset.load(MnistSplit::Test, config.cache);
+    let items: Vec<_> = (0..batch_size.min(dataset.len())).filter_map(|index| dataset.get(index)).collect();
+    let batch: MnistBatch<B> = MnistBatcher::new(device.clone()).batch(items);
+    Ok(activation_report(&model, &batch))
+}
+
+/// For every ReLU layer of `model` (the `.relu` layers of [`Model::activations`]), the fraction of
+/// its units, channels of convolutions and features of dense layers, that output 0 for every
+/// image of the MNIST test set at every position: dead units, which no input activates.
+pub fn dead_units<B: Backend>(model: &Model<B>, device: &B::Device) -> Vec<(String, f32)> {
+    dead_units_on(model, &MnistDataset::test(), device)
+}
+
+// `dead_units` over any dataset of MNIST items
+pub fn dead_units_on<B: Backend, D: Dataset<MnistItem>>(model: &Model<B>, dataset: &D, device: &B::Device) -> Vec<(String, f32)> {
+    // Largest output of every unit of every ReLU layer so far, in forward order
+    let mut maxima: Vec<(String, Vec<f32>)> = Vec::new();
+    for (_, batch) in ordered_batches::<B, _>(dataset, device) {
+        let relus = model.activations(batch.images).into_iter().filter(|(layer, _)| layer.ends_with(".relu"));
+        for (position, (layer, output)) in relus.enumerate() {
+            let [batch_size, units, height, width] = output.dims();
+            let unit_max = output.swap_dims(0, 1).reshape([units, batch_size * height * width]).max_dim(1);
+            let unit_max = unit_max.into_data().convert::<f32>().value;
+            match maxima.get_mut(position) {
+                Some((_, maxima)) => maxima.iter_mut().zip(unit_max).for_each(|(max, value)| *max = max.max(value)),
+                None => maxima.push((layer, unit_max)),
+            }
+        }
+    }
+
+    maxima
+        .into_iter()
+        .map(|(layer, maxima)| {
+            let dead = maxima.iter().filter(|&&max| max <= 0.0).count();
+            (layer, dead as f32 / maxima.len().max(1) as f32)
+        })
+        .collect()
+}
+
+/// [`dead_units_on`] the model trained into `artifact_dir`, over the test split of its dataset.
+pub fn valid_dead_units<B: Backend>(artifact_dir: &str, device: &B::Device) -> Result<Vec<(String, f32)>, LoadError> {
+    let config = TrainingConfig::load(ArtifactDir::new(artifact_dir).config_path())
+        .map_err(|err| LoadError::Config(err.to_string()))?;
+    let model = load_model::<B>(artifact_dir, device)?;
+    Ok(dead_units_on(&model, &config.dataset.load(MnistSplit::Test, config.cache), device))
+}
+
+// The report as a table, one row per layer
+pub fn activation_table(layers: &[LayerActivationStats]) -> String {
+    let mut table = format!("| {:<14} | {:>16} | {:>9} | {:>9} | {:>7} | {:>9} |\n", "Layer", "Shape", "Mean", "Std", "Zeros", "Max");
+    table.push_str("|----------------|------------------|-----------|-----------|---------|-----------|\n");
+    for stats in layers {
+        let [batch_size, channels, height, width] = stats.shape;
+        table.push_str(&format!(
+            "| {:<14} | {:>16} | {:>9.4} | {:>9.4} | {:>6.1}% | {:>9.4} |\n",
+            stats.layer,
+            format!("{batch_size}x{channels}x{height}x{width}"),
+            stats.mean,
+            stats.std,
+            stats.zero_fraction * 100.0,
+            stats.max,
+        ));
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        model::{LayerKind, ModelConfig},
+        params::{named_params, with_named_params, NamedParam},
+        synthetic::SyntheticDigits,
+    };
+    use burn::backend::{ndarray::NdArrayDevice, NdArray};
+
+    #[test]
+    fn relu_fed_negative_pre_activations_is_all_zeros() {
+        let device = NdArrayDevice::default();
+        let config = ModelConfig::new(10, 8);
+        // conv2 outputs far below zero whatever its input
+        let params: Vec<NamedParam> = named_params(&config.init::<NdArray>(&device))
+            .into_iter()
+            .map(|param| match param.name.as_str() {
+                "conv2.bias" => NamedParam { values: vec![-1e3; param.values.len()], ..param },
+                _ => param,
+            })
+            .collect();
+        let model = with_named_params(config.init::<NdArray>(&device), &params, &device);
+        let dataset = SyntheticDigits::new(8, 1);
+        let batch: MnistBatch<NdArray> = MnistBatcher::new(device).batch(dataset.iter().collect());
+
+        let report = activation_report(&model, &batch);
+        let layers: Vec<String> = config
+            .describe()
+            .layers
+            .into_iter()
+            .filter(|layer| !matches!(layer.layer, LayerKind::Dropout { .. }))
+            .map(|layer| layer.name)
+            .collect();
+        assert_eq!(report.iter().map(|stats| stats.layer.clone()).collect::<Vec<_>>(), layers);
+        let stats = |name: &str| report.iter().find(|stats| stats.layer == name).unwrap();
+        assert!(stats("conv2").max < 0.0);
+        assert_eq!(stats("conv2.relu").zero_fraction, 1.0);
+        assert_eq!((stats("conv2.relu").mean, stats("conv2.relu").max), (0.0, 0.0));
+        assert!(stats("conv1").zero_fraction < 1.0);
+        assert_eq!(dead_units_on(&model, &dataset, &device)[0], ("conv2.relu".to_string(), 1.0));
+    }
+
+    #[test]
+    fn dead_units_are_the_share_of_relu_units_no_image_activates() {
+        let device = NdArrayDevice::default();
+        let config = ModelConfig::new(10, 8);
+        // Half of the conv2 channels output far below zero whatever their input, the other half
+        // far above
+        let params: Vec<NamedParam> = named_params(&config.init::<NdArray>(&device))
+            .into_iter()
+            .map(|param| match param.name.as_str() {
+                "conv2.bias" => {
+                    let half = param.values.len() / 2;
+                    let values = (0..param.values.len()).map(|unit| if unit < half { -1e3 } else { 1e3 }).collect();
+                    NamedParam { values, ..param }
+                }
+                _ => param,
+            })
+            .collect();
+        let model = with_named_params(config.init::<NdArray>(&device), &params, &device);
+
+        let dead = dead_units_on(&model, &SyntheticDigits::new(8, 1), &device);
+        let relus: Vec<String> = config
+            .describe()
+            .layers
+            .into_iter()
+            .map(|layer| layer.name)
+            .filter(|name| name.ends_with(".relu"))
+            .collect();
+        assert_eq!(dead.iter().map(|(layer, _)| layer.clone()).collect::<Vec<_>>(), relus);
+        assert_eq!(dead[0], ("conv2.relu".to_string(), 0.5));
+        assert!(dead.iter().all(|(_, fraction)| (0.0..=1.0).contains(fraction)), "{dead:?}");
+    }
+}
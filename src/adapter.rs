@@ -0,0 +1,239 @@
+use crate::checkpoint::LoadError;
+use burn::{
+    module::Param,
+    prelude::*,
+    record::{CompactRecorder, Record, Recorder, RecorderError},
+    tensor::{module::conv2d, ops::ConvOptions},
+};
+use std::path::Path;
+
+/// The layers [`AdapterConfig::target_modules`] can name. The convolutions are adapted as the
+/// linear projections of their unrolled patches, `in * kernel * kernel` to `out`.
+pub const ADAPTER_TARGETS: [&str; 4] = ["conv1", "conv2", "linear1", "linear2"];
+
+/// LoRA-style fine-tuning: the weights of the model are frozen and each target layer learns a
+/// low-rank update `down * up * alpha / rank` of its weight instead, `down` being
+/// `[fan_in, rank]` and `up` `[rank, fan_out]`. `up` starts at zero, so training starts from
+/// the base model exactly.
+#[derive(Config, Debug, PartialEq)]
+pub struct AdapterConfig {
+    pub rank: usize,
+    pub alpha: f64,
+    // Names of the adapted layers, among `ADAPTER_TARGETS`
+    #[config(default = "vec![\"linear1\".to_string(), \"linear2\".to_string()]")]
+    pub target_modules: Vec<String>,
+}
+
+impl AdapterConfig {
+    // What the low-rank product is scaled by
+    pub fn scale(&self) -> f64 {
+        self.alpha / self.rank as f64
+    }
+
+    pub fn targets(&self, name: &str) -> bool {
+        self.target_modules.iter().any(|target| target == name)
+    }
+}
+
+/// The low-rank update of one layer's weight, see [`AdapterConfig`].
+#[derive(Module, Debug)]
+pub struct LoraAdapter<B: Backend> {
+    down: Factor<B>,
+    up: Factor<B>,
+    scale: f64,
+}
+
+impl<B: Backend> LoraAdapter<B> {
+    pub(crate) fn new(down: Factor<B>, up: Factor<B>, scale: f64) -> Self {
+        Self { down, up, scale }
+    }
+
+    pub fn rank(&self) -> usize {
+        self.down.val().dims()[1]
+    }
+
+    /// The update of the weight, `[fan_in, fan_out]` as burn lays out linear weights.
+    pub fn delta(&self) -> Tensor<B, 2> {
+        self.down.val().matmul(self.up.val()).mul_scalar(self.scale)
+    }
+
+    // The update of a linear layer's output for its input `x`, `[batch_size, fan_in]`, without
+    // forming the full `delta`
+    pub(crate) fn forward_linear(&self, x: Tensor<B, 2>) -> Tensor<B, 2> {
+        x.matmul(self.down.val()).matmul(self.up.val()).mul_scalar(self.scale)
+    }
+
+    // The update of a conv kernel of `shape`, `[out, in, kernel, kernel]`
+    pub(crate) fn conv_delta(&self, shape: [usize; 4]) -> Tensor<B, 4> {
+        self.delta().transpose().reshape(shape)
+    }
+
+    // The update of the output of a stride 1, unpadded conv layer with a kernel of `shape`
+    pub(crate) fn forward_conv(&self, x: Tensor<B, 4>, shape: [usize; 4]) -> Tensor<B, 4> {
+        conv2d(x, self.conv_delta(shape), None, ConvOptions::new([1, 1], [0, 0], [1, 1], 1))
+    }
+}
+
+/// The adapters of a model, one per adapted layer. Saved on their own as the `adapter.mpk` of
+/// the run that trained them, a small file next to the frozen base weights.
+#[derive(Module, Debug)]
+pub struct Adapters<B: Backend> {
+    pub conv1: Option<LoraAdapter<B>>,
+    pub conv2: Option<LoraAdapter<B>>,
+    pub linear1: Option<LoraAdapter<B>>,
+    pub linear2: Option<LoraAdapter<B>>,
+}
+
+// `down` or `up` of a `LoraAdapter`
+type Factor<B> = Param<Tensor<B, 2>>;
+
+// How `Adapters` are saved: self-describing, so that they load without their config
+#[derive(Record)]
+struct AdapterFile<B: Backend> {
+    // Layer name, scale, `down` and `up` of every adapter
+    layers: Vec<(String, f64, Factor<B>, Factor<B>)>,
+}
+
+impl<B: Backend> Adapters<B> {
+    fn layers(self) -> [(&'static str, Option<LoraAdapter<B>>); 4] {
+        [("conv1", self.conv1), ("conv2", self.conv2), ("linear1", self.linear1), ("linear2", self.linear2)]
+    }
+
+    /// Writes the adapters to `path`, without the extension the recorder adds (`.mpk`).
+    pub fn save(self, path: &str) -> Result<(), RecorderError> {
+        let layers = self
+            .layers()
+            .into_iter()
+            .filter_map(|(name, adapter)| adapter.map(|adapter| (name.to_string(), adapter.scale, adapter.down, adapter.up)))
+            .collect();
+        CompactRecorder::new().record(AdapterFile::<B> { layers }, path.into())
+    }
+
+    /// Reads adapters saved by [`Adapters::save`], from `path` with or without its extension.
+    pub fn load(path: impl AsRef<Path>, device: &B::Device) -> Result<Self, LoadError> {
+        let path = path.as_ref();
+        let stem = match path.extension() {
+            Some(extension) if extension == "mpk" => path.with_extension(""),
+            _ => path.to_path_buf(),
+        };
+        let file: AdapterFile<B> = CompactRecorder::new()
+            .load(stem, device)
+            .map_err(|err| LoadError::Record(format!("{}: {err}", path.display())))?;
+        let mut adapters = Adapters { conv1: None, conv2: None, linear1: None, linear2: None };
+        for (name, scale, down, up) in file.layers {
+            let slot = match name.as_str() {
+                "conv1" => &mut adapters.conv1,
+                "conv2" => &mut adapters.conv2,
+                "linear1" => &mut adapters.linear1,
+                "linear2" => &mut adapters.linear2,
+                _ => return Err(LoadError::Record(format!("{}: adapter of unknown layer {name:?}", path.display()))),
+            };
+            *slot = Some(LoraAdapter::new(down, up, scale));
+        }
+        Ok(adapters)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{model::Model, ModelConfig};
+    use burn::{
+        backend::{ndarray::NdArrayDevice, NdArray},
+        tensor::Distribution,
+    };
+
+    type B = NdArray;
+
+    fn images(device: &NdArrayDevice) -> Tensor<B, 3> {
+        Tensor::random([4, 28, 28], Distribution::Uniform(0.0, 1.0), device)
+    }
+
+    // The model with adapters on every target whose `up` factors are redrawn away from zero,
+    // as training would move them
+    fn adapted_model(device: &NdArrayDevice) -> Model<B> {
+        let config = AdapterConfig::new(2, 4.0)
+            .with_target_modules(ADAPTER_TARGETS.iter().map(|name| name.to_string()).collect());
+        let (model, adapters) = ModelConfig::new(10, 16)
+            .init::<B>(device)
+            .with_new_adapters(&config, 7, device)
+            .split_adapters();
+        let perturb = |adapter: Option<LoraAdapter<B>>| {
+            adapter.map(|mut adapter| {
+                let shape = adapter.up.val().dims();
+                adapter.up = Param::from_tensor(Tensor::random(shape, Distribution::Uniform(-0.1, 0.1), device));
+                adapter
+            })
+        };
+        let adapters = adapters.expect("with_new_adapters adds adapters");
+        model.with_adapters(Adapters {
+            conv1: perturb(adapters.conv1),
+            conv2: perturb(adapters.conv2),
+            linear1: perturb(adapters.linear1),
+            linear2: perturb(adapters.linear2),
+        })
+    }
+
+    fn max_difference(a: Tensor<B, 2>, b: Tensor<B, 2>) -> f32 {
+        (a - b).abs().max().into_scalar()
+    }
+
+    #[test]
+    fn fresh_adapters_leave_the_outputs_unchanged() {
+        let device = NdArrayDevice::default();
+        let model = ModelConfig::new(10, 16).init::<B>(&device);
+        let images = images(&device);
+        let before = model.forward(images.clone());
+
+        let config = AdapterConfig::new(4, 8.0);
+        let adapted = model.with_new_adapters(&config, 3, &device);
+        let adapters = adapted.adapters().unwrap();
+        assert!(adapters.conv1.is_none() && adapters.conv2.is_none());
+        let linear1 = adapters.linear1.as_ref().unwrap();
+        assert_eq!(linear1.rank(), 4);
+        assert_eq!(linear1.scale, config.scale());
+        assert_eq!(linear1.delta().dims(), [16 * 8 * 8, 16]);
+        assert_eq!(max_difference(adapted.forward(images), before), 0.0);
+    }
+
+    #[test]
+    fn merged_adapters_compute_what_composed_adapters_do() {
+        let device = NdArrayDevice::default();
+        let model = adapted_model(&device);
+        let images = images(&device);
+        let (base, _) = model.clone().split_adapters();
+
+        let composed = model.forward(images.clone());
+        // The perturbed adapters change the outputs, so the comparison below is not vacuous
+        assert!(max_difference(composed.clone(), base.forward(images.clone())) > 1e-3);
+        let merged = model.merge_adapters();
+        assert!(merged.adapters().is_none());
+        assert!(max_difference(merged.forward(images), composed) < 1e-5);
+    }
+
+    #[test]
+    fn saved_adapters_load_back_with_or_without_their_extension() {
+        let device = NdArrayDevice::default();
+        let model = adapted_model(&device);
+        let images = images(&device);
+        let expected = model.forward(images.clone());
+        let (base, adapters) = model.split_adapters();
+        let dir = std::env::temp_dir().join("my_first_rust_DL_app-adapter-round-trip");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("adapter");
+
+        adapters.unwrap().save(path.to_str().unwrap()).unwrap();
+        let loaded = Adapters::<B>::load(&path, &device).unwrap();
+        let reloaded = Adapters::<B>::load(path.with_extension("mpk"), &device).unwrap();
+        let missing = Adapters::<B>::load(dir.join("missing"), &device);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(loaded.linear2.as_ref().unwrap().scale, 2.0);
+        // Adapters are saved in half precision
+        let composed = base.clone().with_adapters(loaded).forward(images.clone());
+        assert!(max_difference(composed, expected.clone()) < 1e-4);
+        let merged = base.with_adapters(reloaded).merge_adapters().forward(images);
+        assert!(max_difference(merged, expected) < 1e-4);
+        assert!(matches!(missing, Err(LoadError::Record(_))), "{missing:?}");
+    }
+}
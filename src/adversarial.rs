@@ -0,0 +1,117 @@
+use crate::{
+    data::{MNIST_MEAN, MNIST_STD},
+    evaluation::ordered_batches,
+    model::Model,
+};
+use burn::{
+    data::dataset::{
+        vision::{MnistDataset, MnistItem},
+        Dataset,
+    },
+    module::AutodiffModule,
+    nn::loss::CrossEntropyLossConfig,
+    prelude::*,
+    tensor::backend::AutodiffBackend,
+};
+
+/// Fast gradient sign method (FGSM) adversarial examples of `images`, normalized
+/// `[batch_size, height, width]` as [`crate::MnistBatcher`] makes them, of true labels `targets`.
+/// Each pixel moves by `epsilon`, on the [0, 1] pixel scale, in the direction that increases
+/// the cross-entropy loss of `model`. It is then clamped to the range of real pixels, so an
+/// `epsilon` of 0 returns the images unchanged. Dropout takes no part in the gradient.
+pub fn fgsm_attack<B: AutodiffBackend>(
+    model: &Model<B>,
+    device: &B::Device,
+    images: Tensor<B, 3>,
+    targets: Tensor<B, 1, Int>,
+    epsilon: f32,
+) -> Tensor<B, 3> {
+    let model = model.clone().without_dropout();
+    let images = images.detach().require_grad();
+    let loss = CrossEntropyLossConfig::new().init(device).forward(model.forward(images.clone()), targets);
+    let gradients = loss.backward();
+    let gradient = images.grad(&gradients).expect("The images require their gradient");
+
+    // A pixel in [0, 1] is normalized to (pixel - mean) / std
+    let normalized = |pixel: f64| (pixel - MNIST_MEAN) / MNIST_STD;
+    let step = epsilon as f64 / MNIST_STD;
+    let adversarial = images.inner() + gradient.sign().mul_scalar(step);
+    Tensor::from_inner(adversarial.clamp(normalized(0.0), normalized(1.0)))
+}
+
+/// Fraction of the test set `model` still classifies correctly once every image is replaced by
+/// its [`fgsm_attack`] at `epsilon`. An `epsilon` of 0 gives the clean accuracy. Labels are the
+/// raw test labels, as in [`accuracy`](crate::evaluation::accuracy).
+pub fn fgsm_accuracy<B: AutodiffBackend>(model: &Model<B>, device: &B::Device, epsilon: f32) -> f32 {
+    dataset_fgsm_accuracy(model, &MnistDataset::test(), device, epsilon)
+}
+
+// `fgsm_accuracy` over any dataset of MNIST items. The attacked images are scored without
+// dropout, by the inference side of the model.
+pub fn dataset_fgsm_accuracy<B: AutodiffBackend, D: Dataset<MnistItem>>(
+    model: &Model<B>,
+    dataset: &D,
+    device: &B::Device,
+    epsilon: f32,
+) -> f32 {
+    let inference = model.valid();
+    let (mut correct, mut total) = (0, 0);
+
+    for (_, batch) in ordered_batches::<B, _>(dataset, device) {
+        let adversarial = fgsm_attack(model, device, batch.images, batch.targets.clone(), epsilon);
+        let predicted = inference.forward(adversarial.inner()).argmax(1).flatten::<1>(0, 1);
+        total += batch.targets.dims()[0];
+        correct += predicted.equal(batch.targets.inner()).int().sum().into_scalar().elem::<i64>() as usize;
+    }
+
+    correct as f32 / total.max(1) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        convert::RecordFormat,
+        evaluation::{dataset_predictions, outcome_accuracy},
+        synthetic::{trained_model, SyntheticDigits},
+        ModelConfig,
+    };
+    use burn::backend::{ndarray::NdArrayDevice, Autodiff, NdArray};
+
+    type B = Autodiff<NdArray>;
+
+    fn model(device: &NdArrayDevice) -> Model<B> {
+        let weights = trained_model().to_bytes(RecordFormat::NamedMpk);
+        RecordFormat::NamedMpk.decode(ModelConfig::new(10, 16).init::<B>(device), &weights, device).unwrap()
+    }
+
+    #[test]
+    fn every_pixel_moves_by_at_most_epsilon() {
+        let device = NdArrayDevice::default();
+        let model = model(&device);
+        let dataset = SyntheticDigits::new(32, 3);
+        let (_, batch) = ordered_batches::<B, _>(&dataset, &device).next().unwrap();
+        let images = batch.images;
+
+        let unchanged = fgsm_attack(&model, &device, images.clone(), batch.targets.clone(), 0.0);
+        assert!((unchanged - images.clone()).abs().max().into_scalar() < 1e-6);
+
+        let attacked = fgsm_attack(&model, &device, images.clone(), batch.targets, 0.1);
+        let moved = (attacked.clone() - images).abs().max().into_scalar() as f64;
+        assert!(moved > 0.0 && moved <= 0.1 / MNIST_STD + 1e-5, "pixels moved by up to {moved}");
+        let (lowest, highest) = (attacked.clone().min().into_scalar() as f64, attacked.max().into_scalar() as f64);
+        assert!(lowest >= -MNIST_MEAN / MNIST_STD - 1e-5 && highest <= (1.0 - MNIST_MEAN) / MNIST_STD + 1e-5);
+    }
+
+    #[test]
+    fn the_attack_lowers_the_clean_accuracy() {
+        let device = NdArrayDevice::default();
+        let model = model(&device);
+        let dataset = SyntheticDigits::new(64, 2);
+
+        let clean = outcome_accuracy(&dataset_predictions(&model.valid(), &dataset, &device));
+        assert_eq!(dataset_fgsm_accuracy(&model, &dataset, &device, 0.0), clean);
+        let attacked = dataset_fgsm_accuracy(&model, &dataset, &device, 0.25);
+        assert!(attacked < clean, "accuracy {attacked} under attack, {clean} clean");
+    }
+}
@@ -0,0 +1,331 @@
+use crate::{
+    checkpoint::LoadError, labels::CLASSES_FILE, meta::META_FILE, model_card::MODEL_CARD_FILE,
+    prometheus::PROMETHEUS_FILE, report::REPORT_FILE, run_record::RUN_RECORD_FILE, split::SPLIT_FILE,
+    training::SUMMARY_FILE,
+};
+use std::{
+    fmt,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+// Held in the artifact dir by the training run writing it, with the process id of that run
+pub const LOCK_FILE: &str = ".lock";
+
+/// Which weights of a run [`ArtifactDir::model_path`] names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModelKind {
+    // The model at the end of training
+    Final,
+    // The stochastic weight average of `TrainingConfig::swa`
+    Swa,
+}
+
+impl ModelKind {
+    fn file_stem(self) -> &'static str {
+        match self {
+            ModelKind::Final => "model",
+            ModelKind::Swa => "model_swa",
+        }
+    }
+}
+
+/// The directory of one training run and where each of its files lives, so that training,
+/// evaluation and inference agree on the layout:
+///
+/// | File | Accessor |
+/// |---|---|
+/// | `config.json` | [`config_path`](Self::config_path) |
+/// | `model.mpk`, `model_swa.mpk` | [`model_path`](Self::model_path) (without the extension) |
+/// | `adapter.mpk` | [`adapter_path`](Self::adapter_path) (without the extension) |
+/// | `model_meta.json` | [`meta_path`](Self::meta_path) |
+/// | `classes.json` | [`classes_path`](Self::classes_path) |
+/// | `history.json` | [`history_path`](Self::history_path) |
+/// | `split_indices.json` | [`split_path`](Self::split_path) |
+/// | `metrics.csv` | [`metrics_path`](Self::metrics_path) |
+/// | `metrics.prom` | [`prometheus_path`](Self::prometheus_path) |
+/// | `curves.svg` | [`curves_path`](Self::curves_path) |
+/// | `summary.txt` | [`summary_path`](Self::summary_path) |
+/// | `model_card.json` | [`model_card_path`](Self::model_card_path) |
+/// | `run_record.json` | [`run_record_path`](Self::run_record_path) |
+/// | `eval.json` | [`eval_report_path`](Self::eval_report_path) |
+/// | `report.html` | [`report_path`](Self::report_path) |
+/// | `model_epoch{epoch}.onnx` | [`onnx_snapshot_path`](Self::onnx_snapshot_path) |
+/// | `checkpoint/model-{epoch}.mpk` | [`checkpoint_path`](Self::checkpoint_path) (without the extension) |
+/// | `checkpoint/optim-{epoch}.mpk` | [`optimizer_checkpoint_path`](Self::optimizer_checkpoint_path) (without the extension) |
+/// | `.lock` | [`lock_path`](Self::lock_path) |
+///
+/// Paths are strings of the form `{dir}/{file}`, as the recorders and file writers take them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArtifactDir {
+    root: String,
+}
+
+impl ArtifactDir {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self { root: path.as_ref().to_string_lossy().into_owned() }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.root
+    }
+
+    fn file(&self, name: &str) -> String {
+        format!("{}/{name}", self.root)
+    }
+
+    pub fn config_path(&self) -> String {
+        self.file("config.json")
+    }
+
+    // Path the recorders of `kind` save to and load from, their extension left to them
+    pub fn model_path(&self, kind: ModelKind) -> String {
+        self.file(kind.file_stem())
+    }
+
+    // `model_path` with the extension of the `CompactRecorder` that writes it
+    pub fn weights_path(&self, kind: ModelKind) -> String {
+        format!("{}.mpk", self.model_path(kind))
+    }
+
+    // The low-rank adapters of `TrainingConfig::adapters`, saved apart from the frozen base
+    // weights, without their extension
+    pub fn adapter_path(&self) -> String {
+        self.file("adapter")
+    }
+
+    pub fn meta_path(&self) -> String {
+        self.file(META_FILE)
+    }
+
+    pub fn classes_path(&self) -> String {
+        self.file(CLASSES_FILE)
+    }
+
+    pub fn history_path(&self) -> String {
+        self.file("history.json")
+    }
+
+    // The training and validation indices of the run, see `split::SplitIndices`
+    pub fn split_path(&self) -> String {
+        self.file(SPLIT_FILE)
+    }
+
+    // The per-epoch metrics of `TrainingConfig::metrics_csv`
+    pub fn metrics_path(&self) -> String {
+        self.file("metrics.csv")
+    }
+
+    // The latest epoch in the Prometheus text format, of `TrainingConfig::prometheus_metrics`
+    pub fn prometheus_path(&self) -> String {
+        self.file(PROMETHEUS_FILE)
+    }
+
+    pub fn curves_path(&self) -> String {
+        self.file("curves.svg")
+    }
+
+    pub fn summary_path(&self) -> String {
+        self.file(SUMMARY_FILE)
+    }
+
+    pub fn model_card_path(&self) -> String {
+        self.file(MODEL_CARD_FILE)
+    }
+
+    pub fn run_record_path(&self) -> String {
+        self.file(RUN_RECORD_FILE)
+    }
+
+    pub fn eval_report_path(&self) -> String {
+        self.file("eval.json")
+    }
+
+    // The failure-mode page of `report::report`
+    pub fn report_path(&self) -> String {
+        self.file(REPORT_FILE)
+    }
+
+    // The model at the end of `epoch` exported to ONNX, of `TrainingConfig::onnx_export_every`
+    pub fn onnx_snapshot_path(&self, epoch: usize) -> String {
+        self.file(&format!("model_epoch{epoch}.onnx"))
+    }
+
+    // Where the learner checkpoints of every kept epoch are
+    pub fn checkpoint_dir(&self) -> String {
+        self.file("checkpoint")
+    }
+
+    // The learner checkpoint of the model weights at the end of `epoch`, without its extension
+    pub fn checkpoint_path(&self, epoch: usize) -> String {
+        format!("{}/model-{epoch}", self.checkpoint_dir())
+    }
+
+    // The learner checkpoint of the optimizer state at the end of `epoch`, without its extension
+    pub fn optimizer_checkpoint_path(&self, epoch: usize) -> String {
+        format!("{}/optim-{epoch}", self.checkpoint_dir())
+    }
+
+    pub fn lock_path(&self) -> String {
+        self.file(LOCK_FILE)
+    }
+
+    /// Checks that the directory holds a training run, as far as its `config.json` goes.
+    pub fn validate(&self) -> Result<(), LoadError> {
+        if !Path::new(&self.root).is_dir() {
+            return Err(LoadError::Config(format!("{} is not a directory", self.root)));
+        }
+        if !Path::new(&self.config_path()).is_file() {
+            return Err(LoadError::Config(format!("{} has no config.json, it is not a training run", self.root)));
+        }
+        Ok(())
+    }
+
+    /// Creates the directory if needed and takes its lock, released when the returned guard is
+    /// dropped. Fails when another run holds it: two trainings never write the same directory.
+    pub fn lock(&self) -> Result<ArtifactLock, LockError> {
+        fs::create_dir_all(&self.root).map_err(LockError::Io)?;
+        let path = PathBuf::from(self.lock_path());
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(mut file) => {
+                write!(file, "{}", std::process::id()).map_err(LockError::Io)?;
+                Ok(ArtifactLock { path })
+            }
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                let pid = fs::read_to_string(&path).ok().and_then(|pid| pid.trim().parse().ok());
+                Err(LockError::Held { lock_file: path, pid })
+            }
+            Err(err) => Err(LockError::Io(err)),
+        }
+    }
+
+    /// Empties the directory for a new run, all but the lock `lock` proves is held.
+    pub fn reset(&self, lock: &ArtifactLock) -> io::Result<()> {
+        for entry in fs::read_dir(&self.root)? {
+            let path = entry?.path();
+            if path == lock.path {
+                continue;
+            }
+            if path.is_dir() {
+                fs::remove_dir_all(path)?;
+            } else {
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl AsRef<Path> for ArtifactDir {
+    fn as_ref(&self) -> &Path {
+        Path::new(&self.root)
+    }
+}
+
+impl fmt::Display for ArtifactDir {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.root)
+    }
+}
+
+/// The lock of an [`ArtifactDir`], removed on drop.
+#[derive(Debug)]
+pub struct ArtifactLock {
+    path: PathBuf,
+}
+
+impl Drop for ArtifactLock {
+    fn drop(&mut self) {
+        fs::remove_file(&self.path).ok();
+    }
+}
+
+#[derive(Debug)]
+pub enum LockError {
+    // The directory or the lock file could not be created
+    Io(io::Error),
+    // Another run holds the lock, `pid` being its process id when the file says
+    Held { lock_file: PathBuf, pid: Option<u32> },
+}
+
+impl fmt::Display for LockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LockError::Io(err) => write!(f, "could not lock the artifact dir: {err}"),
+            LockError::Held { lock_file, pid } => {
+                let holder = pid.map_or("another run".to_string(), |pid| format!("the run of process {pid}"));
+                write!(
+                    f,
+                    "the artifact dir is locked by {holder}; delete {} if no training is writing it",
+                    lock_file.display()
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for LockError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> ArtifactDir {
+        let dir = std::env::temp_dir().join(format!("my_first_rust_DL_app-artifact-{name}"));
+        let _ = fs::remove_dir_all(&dir);
+        ArtifactDir::new(dir)
+    }
+
+    #[test]
+    fn paths_follow_the_documented_layout() {
+        let dir = ArtifactDir::new("runs/a");
+        assert_eq!(dir.config_path(), "runs/a/config.json");
+        assert_eq!(dir.model_path(ModelKind::Final), "runs/a/model");
+        assert_eq!(dir.model_path(ModelKind::Swa), "runs/a/model_swa");
+        assert_eq!(dir.meta_path(), format!("runs/a/{META_FILE}"));
+        assert_eq!(dir.onnx_snapshot_path(3), "runs/a/model_epoch3.onnx");
+        assert_eq!(dir.checkpoint_path(2), "runs/a/checkpoint/model-2");
+        assert_eq!(dir.optimizer_checkpoint_path(2), "runs/a/checkpoint/optim-2");
+        assert_eq!(dir.lock_path(), "runs/a/.lock");
+        assert_eq!(dir.to_string(), "runs/a");
+    }
+
+    #[test]
+    fn a_locked_dir_cannot_be_locked_again_until_released() {
+        let dir = temp_dir("lock");
+        let lock = dir.lock().unwrap();
+        let pid = fs::read_to_string(dir.lock_path()).unwrap();
+        assert_eq!(pid, std::process::id().to_string());
+
+        match dir.lock() {
+            Err(LockError::Held { lock_file, pid }) => {
+                assert_eq!(lock_file, PathBuf::from(dir.lock_path()));
+                assert_eq!(pid, Some(std::process::id()));
+            }
+            other => panic!("locked twice: {other:?}"),
+        }
+        drop(lock);
+        assert!(!Path::new(&dir.lock_path()).exists());
+        let relocked = dir.lock();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(relocked.is_ok());
+    }
+
+    #[test]
+    fn reset_keeps_only_the_lock() {
+        let dir = temp_dir("reset");
+        let lock = dir.lock().unwrap();
+        fs::write(dir.config_path(), "{}").unwrap();
+        fs::create_dir_all(dir.checkpoint_dir()).unwrap();
+        fs::write(format!("{}.mpk", dir.checkpoint_path(1)), "weights").unwrap();
+        assert!(dir.validate().is_ok());
+
+        dir.reset(&lock).unwrap();
+        let left: Vec<_> = fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+        assert!(matches!(dir.validate(), Err(LoadError::Config(_))));
+        drop(lock);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(left, [LOCK_FILE]);
+    }
+}
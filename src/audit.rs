@@ -0,0 +1,219 @@
+use crate::{
+    artifact::ArtifactDir,
+    checkpoint::LoadError,
+    data::{ClassificationDataset, ClassificationItem, MnistBatcher, MnistSplit},
+    inference::load_model,
+    model::Model,
+    split::{ClassSubset, OneVsRest},
+    training::TrainingConfig,
+};
+use burn::{data::dataloader::batcher::Batcher, prelude::*};
+use image::{GrayImage, Luma};
+use serde::{Deserialize, Serialize};
+use std::{fmt, io, path::Path};
+
+// Number of training images scored at once
+const AUDIT_BATCH_SIZE: usize = 256;
+// Where `save_top_images` writes the highest-loss images, inside the artifact dir
+const TOP_IMAGES_DIR: &str = "audit_top";
+
+// The loss of one training sample under the final model. `label` and `predicted` are classes
+// of the model: positions in `classes`, or 0/1 for one-vs-rest runs.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct SampleLoss {
+    // Index of the sample in the training split of `config.dataset`
+    pub index: usize,
+    pub label: usize,
+    pub predicted: usize,
+    pub loss: f32,
+}
+
+#[derive(Debug)]
+pub enum AuditError {
+    Load(LoadError),
+    Io(io::Error),
+}
+
+impl fmt::Display for AuditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuditError::Load(err) => write!(f, "cannot audit the run: {err}"),
+            AuditError::Io(err) => write!(f, "could not write the audit: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for AuditError {}
+
+impl From<io::Error> for AuditError {
+    fn from(err: io::Error) -> Self {
+        AuditError::Io(err)
+    }
+}
+
+impl From<image::ImageError> for AuditError {
+    fn from(err: image::ImageError) -> Self {
+        AuditError::Io(io::Error::other(err))
+    }
+}
+
+// Scores every item of `dataset` in order. `parent` maps a position in `dataset` to the index
+// reported for it. The batches carry the index of each of their items, so that an unreadable
+// item, left out of its batch, does not shift the losses of the others.
+pub(crate) fn sample_losses<B: Backend, D: ClassificationDataset + ?Sized>(
+    model: &Model<B>,
+    dataset: &D,
+    parent: impl Fn(usize) -> usize,
+    device: &B::Device,
+) -> Vec<SampleLoss> {
+    let batcher = MnistBatcher::<B>::new(device.clone());
+    let mut losses = Vec::with_capacity(dataset.len());
+
+    for start in (0..dataset.len()).step_by(AUDIT_BATCH_SIZE) {
+        let end = usize::min(start + AUDIT_BATCH_SIZE, dataset.len());
+        let items: Vec<ClassificationItem> = (start..end)
+            .filter_map(|index| {
+                let (pixels, label) = dataset.get(index)?;
+                Some(ClassificationItem { pixels, shape: dataset.image_shape(), label, index: Some(index) })
+            })
+            .collect();
+        if items.is_empty() {
+            continue;
+        }
+        let labels: Vec<usize> = items.iter().map(|item| item.label).collect();
+
+        let batch = batcher.batch(items);
+        let indices = batch.indices.expect("Every audited item has an index");
+        let (loss, output) = model.per_sample_losses(batch.images, batch.targets);
+        let loss = loss.into_data().convert::<f32>().value;
+        let predicted = output.argmax(1).into_data().convert::<i64>().value;
+
+        for (((index, label), predicted), loss) in indices.into_iter().zip(labels).zip(predicted).zip(loss) {
+            losses.push(SampleLoss { index: parent(index), label, predicted: predicted as usize, loss });
+        }
+    }
+    losses
+}
+
+fn training_losses<B: Backend>(model: &Model<B>, config: &TrainingConfig, device: &B::Device) -> Vec<SampleLoss> {
+    let dataset = config.dataset.load(MnistSplit::Train, config.cache);
+    match (&config.classes, config.binary_target) {
+        (Some(classes), _) => {
+            let subset = ClassSubset::new(dataset, classes);
+            sample_losses(model, &subset, |index| subset.indices()[index], device)
+        }
+        (None, Some(target)) => sample_losses(model, &OneVsRest::new(dataset, target), |index| index, device),
+        (None, None) => sample_losses(model, &dataset, |index| index, device),
+    }
+}
+
+/// Scores every training sample with the final model of `artifact_dir`, in dataset order and
+/// with its own cross-entropy rather than a batch mean, on the same labels the run trained on.
+/// Writes them to `sample_losses.csv` as `index,label,predicted,loss`, from the highest loss
+/// down: mislabeled and unusually hard samples come first. Returns them in the same order.
+pub fn audit<B: Backend>(artifact_dir: &str, device: &B::Device) -> Result<Vec<SampleLoss>, AuditError> {
+    let config = TrainingConfig::load(ArtifactDir::new(artifact_dir).config_path())
+        .map_err(|err| AuditError::Load(LoadError::Config(err.to_string())))?;
+    let model = load_model::<B>(artifact_dir, device).map_err(AuditError::Load)?;
+
+    let mut losses = training_losses(&model, &config, device);
+    losses.sort_by(|a, b| b.loss.total_cmp(&a.loss));
+
+    let mut csv = String::from("index,label,predicted,loss\n");
+    for sample in &losses {
+        csv.push_str(&format!("{},{},{},{}\n", sample.index, sample.label, sample.predicted, sample.loss));
+    }
+    std::fs::write(format!("{artifact_dir}/sample_losses.csv"), csv)?;
+    Ok(losses)
+}
+
+/// Writes the images of the first `n` of `losses` (as returned by [`audit`], highest loss
+/// first) to `artifact_dir/audit_top`, named by rank, index, label and prediction, e.g.
+/// `001_index-4821_label-3_predicted-5.png`. Returns the directory.
+pub fn save_top_images(artifact_dir: &str, losses: &[SampleLoss], n: usize) -> Result<String, AuditError> {
+    let config = TrainingConfig::load(ArtifactDir::new(artifact_dir).config_path())
+        .map_err(|err| AuditError::Load(LoadError::Config(err.to_string())))?;
+    let dataset = config.dataset.load(MnistSplit::Train, config.cache);
+    let [height, width] = dataset.image_shape();
+
+    let dir = format!("{artifact_dir}/{TOP_IMAGES_DIR}");
+    std::fs::create_dir_all(&dir)?;
+    for (rank, sample) in losses.iter().take(n).enumerate() {
+        let Some((pixels, _)) = dataset.get(sample.index) else {
+            continue;
+        };
+        let image = GrayImage::from_fn(width as u32, height as u32, |x, y| {
+            Luma([pixels[y as usize * width + x as usize].clamp(0.0, 255.0) as u8])
+        });
+        let name = format!(
+            "{:03}_index-{}_label-{}_predicted-{}.png",
+            rank + 1,
+            sample.index,
+            sample.label,
+            sample.predicted
+        );
+        image.save(Path::new(&dir).join(name))?;
+    }
+    Ok(dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{artifact::ModelKind, data::DatasetSource, model::ModelConfig, synthetic::SyntheticDigits};
+    use burn::{
+        backend::{ndarray::NdArrayDevice, NdArray},
+        optim::AdamConfig,
+        record::CompactRecorder,
+    };
+
+    #[test]
+    fn sample_losses_stay_aligned_with_their_indices_across_batches() {
+        let device = NdArrayDevice::default();
+        let dir = std::env::temp_dir().join("my_first_rust_DL_app-audit");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let dir = ArtifactDir::new(dir.to_str().unwrap());
+        // Digits 3 and 7 only, 300 of them: two audit batches
+        let config = TrainingConfig::new(ModelConfig::new(2, 8), AdamConfig::new())
+            .with_dataset(DatasetSource::Synthetic { num_samples: 1500, seed: 1 })
+            .with_classes(Some(vec![3, 7]));
+        config.save(dir.config_path()).unwrap();
+        let model = config.model.init::<NdArray>(&device);
+        model.save_file(dir.model_path(ModelKind::Final), &CompactRecorder::new()).unwrap();
+        let model = load_model::<NdArray>(dir.as_str(), &device).unwrap();
+
+        let losses = audit::<NdArray>(dir.as_str(), &device).unwrap();
+        assert_eq!(losses.len(), 300);
+        assert!(losses.windows(2).all(|pair| pair[0].loss >= pair[1].loss));
+
+        // The same items scored in one batch, by their index in the training split
+        let dataset = SyntheticDigits::new(1500, 1);
+        let items: Vec<ClassificationItem> = (0..1500)
+            .filter(|index| [3, 7].contains(&(index % 10)))
+            .map(|index| {
+                let (pixels, label) = ClassificationDataset::get(&dataset, index).unwrap();
+                let label = usize::from(label == 7);
+                ClassificationItem { pixels, shape: [28, 28], label, index: Some(index) }
+            })
+            .collect();
+        let batch = MnistBatcher::<NdArray>::new(device).batch(items);
+        let (expected, _) = model.per_sample_losses(batch.images, batch.targets);
+        let expected = expected.into_data().value;
+        for sample in &losses {
+            assert_eq!(sample.label, usize::from(sample.index % 10 == 7));
+            let position = sample.index / 10 * 2 + usize::from(sample.index % 10 == 7);
+            assert!((sample.loss - expected[position]).abs() < 1e-5, "sample {}", sample.index);
+        }
+        let csv = std::fs::read_to_string(format!("{}/sample_losses.csv", dir.as_str())).unwrap();
+        assert_eq!(csv.lines().count(), 301);
+
+        let top = save_top_images(dir.as_str(), &losses, 3).unwrap();
+        let mut names: Vec<String> =
+            std::fs::read_dir(top).unwrap().map(|entry| entry.unwrap().file_name().into_string().unwrap()).collect();
+        names.sort();
+        let first = &losses[0];
+        assert_eq!(names.len(), 3);
+        assert_eq!(names[0], format!("001_index-{}_label-{}_predicted-{}.png", first.index, first.label, first.predicted));
+    }
+}
@@ -0,0 +1,122 @@
+use crate::{
+    data::{mix_batch, ClassificationDataset, ClassificationItem, MnistSplit},
+    preprocess::PreprocessedDataset,
+    training::{TrainError, TrainingConfig},
+};
+use image::{imageops::FilterType, GrayImage, Luma};
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+// Side of each pixel in the previews, 28x28 being too small to eyeball
+const PREVIEW_SCALE: u32 = 8;
+
+/// Writes what the augmentation of `config` (mixup, CutMix) does to the first `num_samples`
+/// training images into `out_dir`: `NN-before.png` and `NN-after.png` per sample, `NN` its
+/// index in the training set, each pixel drawn as an 8x8 block. Returns the pairs of paths, in
+/// sample order.
+///
+/// The samples are mixed as one batch of a training run would be, with their partners drawn
+/// among them from `config.seed`: the same config always writes the same images. Both images
+/// of a pair went through the `preprocess` steps, so `before` is what training would see
+/// without augmentation. Without mixup or CutMix the two are the same.
+pub fn preview_augmentation(
+    config: &TrainingConfig,
+    num_samples: usize,
+    out_dir: impl AsRef<Path>,
+) -> Result<Vec<[PathBuf; 2]>, TrainError> {
+    config.validate().map_err(TrainError::InvalidConfig)?;
+    let dataset = PreprocessedDataset::new(config.dataset.load(MnistSplit::Train, config.cache), config.preprocess.clone());
+    let shape = dataset.image_shape();
+    let items: Vec<ClassificationItem> = (0..num_samples.min(dataset.len()))
+        .filter_map(|index| {
+            let (pixels, label) = dataset.get(index)?;
+            Some(ClassificationItem { pixels, shape, label, index: Some(index) })
+        })
+        .collect();
+    let mixed = mix_batch(items.clone(), config.mixup_alpha, config.cutmix_alpha, dataset.num_classes(), config.seed);
+
+    let out_dir = out_dir.as_ref();
+    std::fs::create_dir_all(out_dir)?;
+    let mut paths = Vec::with_capacity(items.len());
+    for (before, after) in items.iter().zip(&mixed) {
+        let index = before.index.expect("Previewed items know their dataset index");
+        let pair = [out_dir.join(format!("{index:02}-before.png")), out_dir.join(format!("{index:02}-after.png"))];
+        for (path, item) in pair.iter().zip([before, after]) {
+            save_scaled(item, path).map_err(io::Error::other)?;
+        }
+        paths.push(pair);
+    }
+    Ok(paths)
+}
+
+// Writes the pixels of `item` as a grayscale PNG, each pixel a `PREVIEW_SCALE` block
+fn save_scaled(item: &ClassificationItem, path: &Path) -> image::ImageResult<()> {
+    let [height, width] = item.shape;
+    let gray = GrayImage::from_fn(width as u32, height as u32, |x, y| {
+        Luma([item.pixels[y as usize * width + x as usize].round().clamp(0.0, 255.0) as u8])
+    });
+    let (width, height) = (width as u32 * PREVIEW_SCALE, height as u32 * PREVIEW_SCALE);
+    image::imageops::resize(&gray, width, height, FilterType::Nearest).save(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{data::DatasetSource, ModelConfig};
+    use burn::optim::AdamConfig;
+
+    fn config(mixup_alpha: Option<f64>, cutmix_alpha: Option<f64>) -> TrainingConfig {
+        TrainingConfig::new(ModelConfig::new(10, 8), AdamConfig::new())
+            .with_dataset(DatasetSource::Synthetic { num_samples: 12, seed: 1 })
+            .with_mixup_alpha(mixup_alpha)
+            .with_cutmix_alpha(cutmix_alpha)
+    }
+
+    fn read_pairs(paths: &[[PathBuf; 2]]) -> Vec<[Vec<u8>; 2]> {
+        paths.iter().map(|[before, after]| [std::fs::read(before).unwrap(), std::fs::read(after).unwrap()]).collect()
+    }
+
+    #[test]
+    fn previews_are_one_before_after_pair_per_sample() {
+        let out_dir = std::env::temp_dir().join("my_first_rust_DL_app-augmentation-preview");
+        let _ = std::fs::remove_dir_all(&out_dir);
+
+        let plain = preview_augmentation(&config(None, None), 5, &out_dir).unwrap();
+        let plain_files = read_pairs(&plain);
+        let mixed = preview_augmentation(&config(Some(0.4), None), 5, &out_dir).unwrap();
+        let mixed_files = read_pairs(&mixed);
+        let again = read_pairs(&preview_augmentation(&config(Some(0.4), None), 5, &out_dir).unwrap());
+        let cut = read_pairs(&preview_augmentation(&config(None, Some(1.0)), 5, &out_dir).unwrap());
+        let num_files = std::fs::read_dir(&out_dir).unwrap().count();
+        let image = image::open(&mixed[0][1]).unwrap();
+        // More samples than the dataset has: one pair per training image
+        let all = preview_augmentation(&config(None, None), 100, &out_dir).unwrap();
+        std::fs::remove_dir_all(&out_dir).unwrap();
+
+        assert_eq!(num_files, 10);
+        let names = plain[4].clone().map(|path| path.file_name().unwrap().to_string_lossy().into_owned());
+        assert_eq!(names, ["04-before.png", "04-after.png"]);
+        assert_eq!((image.width(), image.height()), (28 * PREVIEW_SCALE, 28 * PREVIEW_SCALE));
+        assert_eq!(all.len(), 12);
+
+        assert!(plain_files.iter().all(|[before, after]| before == after));
+        for files in [&mixed_files, &cut] {
+            assert!(files.iter().any(|[before, after]| before != after));
+            // The images before augmentation are the same whatever it is
+            let befores: Vec<&Vec<u8>> = files.iter().map(|[before, _]| before).collect();
+            assert_eq!(befores, plain_files.iter().map(|[before, _]| before).collect::<Vec<_>>());
+        }
+        assert_eq!(again, mixed_files);
+    }
+
+    #[test]
+    fn invalid_alpha_is_reported_before_anything_is_written() {
+        let out_dir = std::env::temp_dir().join("my_first_rust_DL_app-augmentation-preview-invalid");
+
+        let result = preview_augmentation(&config(Some(-1.0), None), 5, &out_dir);
+        assert!(matches!(result, Err(TrainError::InvalidConfig(_))), "{:?}", result.map(|_| ()));
+        assert!(!out_dir.exists());
+    }
+}
@@ -0,0 +1,187 @@
+use crate::{
+    data::{ClassificationDataset, MnistBatch},
+    hard_mining::ResampledDataset,
+    seed::derive_seed,
+};
+use burn::{
+    data::dataloader::{DataLoader, DataLoaderIterator},
+    prelude::*,
+};
+use rand::{rngs::StdRng, seq::index, Rng, SeedableRng};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, RwLock,
+};
+
+/// Class-balanced sampling: every epoch, the training set is drawn anew with replacement, as
+/// many items as it has, the same number from every class give or take one (the classes
+/// drawing one more picked at random), uniformly among the items of the class. Every class
+/// thus comes out about as often however skewed the dataset is, each item being drawn with a
+/// probability inversely proportional to the size of its class. Each epoch's draw is seeded by
+/// the run seed and the epoch, and the training loader shuffles the draw as usual.
+pub struct BalancedSampler {
+    mapping: Arc<RwLock<Vec<usize>>>,
+    // The readable items of every class that has any. Empty without a readable item, which
+    // leaves the dataset as it is
+    classes: Vec<Vec<usize>>,
+    seed: u64,
+    epoch: AtomicUsize,
+}
+
+impl BalancedSampler {
+    // Wraps `dataset` into the one the training loader should serve, redrawn by `loader`
+    pub fn new<D: ClassificationDataset>(dataset: D, seed: u64) -> (Self, ResampledDataset<D>) {
+        let classes = class_items(&dataset);
+        let mapping = Arc::new(RwLock::new((0..dataset.len()).collect()));
+        let resampled = ResampledDataset::new(Arc::new(dataset), mapping.clone());
+        (Self { mapping, classes, seed, epoch: AtomicUsize::new(0) }, resampled)
+    }
+
+    // `inner`, loading the dataset of `new`, with a draw before every epoch
+    pub fn loader<B: Backend>(self, inner: Box<dyn DataLoader<MnistBatch<B>>>) -> BalancedDataLoader<B> {
+        BalancedDataLoader { inner, sampler: self }
+    }
+
+    fn draw(&self) {
+        if self.classes.is_empty() {
+            return;
+        }
+        let epoch = self.epoch.fetch_add(1, Ordering::Relaxed);
+        let mut rng = StdRng::seed_from_u64(derive_seed(self.seed, &format!("balanced-{epoch}")));
+        let len = self.mapping.read().unwrap().len();
+        let num_classes = self.classes.len();
+        let mut counts = vec![len / num_classes; num_classes];
+        for class in index::sample(&mut rng, num_classes, len % num_classes) {
+            counts[class] += 1;
+        }
+        let mut mapping = Vec::with_capacity(len);
+        for (items, count) in self.classes.iter().zip(counts) {
+            mapping.extend((0..count).map(|_| items[rng.gen_range(0..items.len())]));
+        }
+        *self.mapping.write().unwrap() = mapping;
+    }
+}
+
+// The indices of the readable items of `dataset` by class, for the classes with any
+fn class_items<D: ClassificationDataset + ?Sized>(dataset: &D) -> Vec<Vec<usize>> {
+    let mut classes: Vec<Vec<usize>> = Vec::new();
+    for index in 0..dataset.len() {
+        let Some((_, label)) = dataset.get(index) else {
+            continue;
+        };
+        if classes.len() <= label {
+            classes.resize(label + 1, Vec::new());
+        }
+        classes[label].push(index);
+    }
+    classes.retain(|items| !items.is_empty());
+    classes
+}
+
+// The training loader of a `BalancedSampler`. Each call to `iter` is one epoch, drawn before
+// `inner` reads any of it.
+pub struct BalancedDataLoader<B: Backend> {
+    inner: Box<dyn DataLoader<MnistBatch<B>>>,
+    sampler: BalancedSampler,
+}
+
+impl<B: Backend> DataLoader<MnistBatch<B>> for BalancedDataLoader<B> {
+    fn iter<'a>(&'a self) -> Box<dyn DataLoaderIterator<MnistBatch<B>> + 'a> {
+        self.sampler.draw();
+        self.inner.iter()
+    }
+
+    fn num_items(&self) -> usize {
+        self.inner.num_items()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // One-pixel images labeled as given, unreadable where `None`
+    struct Labels(Vec<Option<usize>>);
+
+    impl ClassificationDataset for Labels {
+        fn len(&self) -> usize {
+            self.0.len()
+        }
+
+        fn get(&self, index: usize) -> Option<(Vec<f32>, usize)> {
+            Some((vec![index as f32], (*self.0.get(index)?)?))
+        }
+
+        fn num_classes(&self) -> usize {
+            4
+        }
+
+        fn image_shape(&self) -> [usize; 2] {
+            [1, 1]
+        }
+    }
+
+    // 90 items of class 0, 9 of class 1, one of class 3 and an unreadable one, in that order
+    fn skewed() -> Labels {
+        Labels([vec![Some(0); 90], vec![Some(1); 9], vec![Some(3), None]].concat())
+    }
+
+    // The labels of the readable items served
+    fn labels(dataset: &ResampledDataset<Labels>) -> Vec<usize> {
+        (0..dataset.len()).filter_map(|index| Some(dataset.get(index)?.1)).collect()
+    }
+
+    #[test]
+    fn every_epoch_draws_each_class_within_one_of_the_others() {
+        let (sampler, resampled) = BalancedSampler::new(skewed(), 5);
+        assert_eq!(labels(&resampled).iter().filter(|&&label| label == 0).count(), 90);
+
+        let mut draws = Vec::new();
+        for _ in 0..5 {
+            sampler.draw();
+            let labels = labels(&resampled);
+            let counts = [0, 1, 3].map(|class| labels.iter().filter(|&&label| label == class).count());
+            assert_eq!(labels.len(), 101);
+            assert!(counts.iter().all(|&count| count == 33 || count == 34), "{counts:?}");
+            assert_eq!(counts.iter().sum::<usize>(), 101);
+            draws.push(sampler.mapping.read().unwrap().clone());
+        }
+        // The unreadable item is never drawn, the single item of class 3 always
+        assert!(draws.iter().flatten().all(|&index| index != 100));
+        assert!(draws.iter().all(|draw| draw.contains(&99)));
+        assert_ne!(draws[0], draws[1]);
+
+        let (again, _) = BalancedSampler::new(skewed(), 5);
+        again.draw();
+        assert_eq!(*again.mapping.read().unwrap(), draws[0]);
+        let (other_seed, _) = BalancedSampler::new(skewed(), 6);
+        other_seed.draw();
+        assert_ne!(*other_seed.mapping.read().unwrap(), draws[0]);
+    }
+
+    #[test]
+    fn items_of_a_class_are_drawn_alike() {
+        let (sampler, _) = BalancedSampler::new(skewed(), 1);
+        let mut times_drawn = vec![0usize; 101];
+        for _ in 0..200 {
+            sampler.draw();
+            for &index in sampler.mapping.read().unwrap().iter() {
+                times_drawn[index] += 1;
+            }
+        }
+        // About 200 * 33.7 / 9 draws of every class 1 item, 200 * 33.7 / 90 of every class 0 one
+        let class_1 = &times_drawn[90..99];
+        assert!(class_1.iter().all(|&count| (500..=1000).contains(&count)), "{class_1:?}");
+        let class_0_mean = times_drawn[..90].iter().sum::<usize>() as f64 / 90.0;
+        assert!((class_0_mean - 200.0 * 101.0 / 3.0 / 90.0).abs() < 1.0, "{class_0_mean}");
+        assert!(times_drawn[..90].iter().all(|&count| count > 30));
+    }
+
+    #[test]
+    fn dataset_without_a_readable_item_is_left_as_it_is() {
+        let (sampler, resampled) = BalancedSampler::new(Labels(vec![None; 3]), 1);
+        sampler.draw();
+        assert_eq!(*sampler.mapping.read().unwrap(), [0, 1, 2]);
+        assert_eq!(resampled.len(), 3);
+    }
+}
@@ -0,0 +1,123 @@
+use crate::data::MnistBatch;
+use burn::{
+    data::dataloader::{DataLoader, DataLoaderIterator, Progress},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+// The items of one training step, as listed in `batch_order.json`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BatchRecord {
+    // 1-based, counted across epochs
+    pub step: usize,
+    // 1-based
+    pub epoch: usize,
+    // Indices of the items in the training set, in batch order
+    pub indices: Vec<usize>,
+}
+
+// Every training batch of a run, in the order the learner consumed them. Clones share the
+// records, so that the run can save what its dataloader saw.
+#[derive(Clone, Default)]
+pub struct BatchOrder {
+    records: Arc<Mutex<Vec<BatchRecord>>>,
+}
+
+impl BatchOrder {
+    fn push(&self, epoch: usize, indices: Vec<usize>) {
+        let mut records = self.records.lock().unwrap();
+        let step = records.len() + 1;
+        records.push(BatchRecord { step, epoch, indices });
+    }
+
+    // Writes the records as `batch_order.json`
+    pub fn save(&self, artifact_dir: &str) -> io::Result<()> {
+        let json = serde_json::to_string(&*self.records.lock().unwrap()).expect("Batch order should serialize to JSON");
+        std::fs::write(format!("{artifact_dir}/batch_order.json"), json)
+    }
+}
+
+// Records the dataset indices of every batch a training dataloader yields. Each `iter` is one
+// epoch of the learner. Batches whose items carry no index are recorded with none.
+pub struct BatchOrderDataLoader<B: Backend> {
+    inner: Box<dyn DataLoader<MnistBatch<B>>>,
+    order: BatchOrder,
+    epoch: AtomicUsize,
+}
+
+impl<B: Backend> BatchOrderDataLoader<B> {
+    pub fn new(inner: Box<dyn DataLoader<MnistBatch<B>>>, order: BatchOrder) -> Self {
+        Self { inner, order, epoch: AtomicUsize::new(0) }
+    }
+}
+
+struct BatchOrderIterator<'a, B: Backend> {
+    inner: Box<dyn DataLoaderIterator<MnistBatch<B>> + 'a>,
+    order: &'a BatchOrder,
+    epoch: usize,
+}
+
+impl<B: Backend> DataLoader<MnistBatch<B>> for BatchOrderDataLoader<B> {
+    fn iter<'a>(&'a self) -> Box<dyn DataLoaderIterator<MnistBatch<B>> + 'a> {
+        let epoch = self.epoch.fetch_add(1, Ordering::Relaxed) + 1;
+        Box::new(BatchOrderIterator { inner: self.inner.iter(), order: &self.order, epoch })
+    }
+
+    fn num_items(&self) -> usize {
+        self.inner.num_items()
+    }
+}
+
+impl<B: Backend> Iterator for BatchOrderIterator<'_, B> {
+    type Item = MnistBatch<B>;
+
+    fn next(&mut self) -> Option<MnistBatch<B>> {
+        let batch = self.inner.next()?;
+        self.order.push(self.epoch, batch.indices.clone().unwrap_or_default());
+        Some(batch)
+    }
+}
+
+impl<B: Backend> DataLoaderIterator<MnistBatch<B>> for BatchOrderIterator<'_, B> {
+    fn progress(&self) -> Progress {
+        self.inner.progress()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::synthetic::{temp_artifact_dir, tiny_config, train_synthetic};
+
+    #[test]
+    fn every_epoch_covers_the_training_set_once() {
+        let artifact_dir = temp_artifact_dir("batch-order");
+        let artifact_dir = artifact_dir.to_str().unwrap();
+        // Two workers, each ending on its own partial batch
+        let config = tiny_config().with_export_batch_order(true).with_num_epochs(2).with_num_workers(2);
+
+        train_synthetic(artifact_dir, config, 50, 20).unwrap();
+        let json = std::fs::read_to_string(format!("{artifact_dir}/batch_order.json")).unwrap();
+        std::fs::remove_dir_all(artifact_dir).unwrap();
+
+        let records: Vec<BatchRecord> = serde_json::from_str(&json).unwrap();
+        let steps: Vec<usize> = records.iter().map(|record| record.step).collect();
+        assert_eq!(steps, (1..=records.len()).collect::<Vec<_>>());
+        for epoch in [1, 2] {
+            let mut indices: Vec<usize> = records
+                .iter()
+                .filter(|record| record.epoch == epoch)
+                .flat_map(|record| record.indices.clone())
+                .collect();
+            indices.sort_unstable();
+            assert_eq!(indices, (0..50).collect::<Vec<_>>(), "epoch {epoch}");
+        }
+    }
+}
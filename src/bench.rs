@@ -0,0 +1,135 @@
+use crate::{checkpoint::LoadError, inference::load_model, meta::trained_image_shape, model::Model};
+use burn::{prelude::*, tensor::Distribution};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+pub const DEFAULT_WARMUP: usize = 10;
+pub const DEFAULT_ITERATIONS: usize = 100;
+
+// Inference speed of a model on one device, from the timings of every iteration after warmup
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BenchmarkReport {
+    pub batch_size: usize,
+    pub iterations: usize,
+    // Images per second over all the timed iterations
+    pub throughput: f64,
+    // Latencies of one batch, in milliseconds: the mean and the 50th, 90th and 99th percentiles
+    pub mean_ms: f64,
+    pub p50_ms: f64,
+    pub p90_ms: f64,
+    pub p99_ms: f64,
+}
+
+impl BenchmarkReport {
+    // Summarizes the latency of each timed iteration
+    pub fn from_latencies(batch_size: usize, latencies: &[Duration]) -> Self {
+        let mut millis: Vec<f64> = latencies.iter().map(|latency| latency.as_secs_f64() * 1e3).collect();
+        millis.sort_by(f64::total_cmp);
+        let total: f64 = millis.iter().sum();
+        Self {
+            batch_size,
+            iterations: millis.len(),
+            throughput: if total > 0.0 { (batch_size * millis.len()) as f64 / (total / 1e3) } else { 0.0 },
+            mean_ms: total / millis.len().max(1) as f64,
+            p50_ms: percentile(&millis, 50.0),
+            p90_ms: percentile(&millis, 90.0),
+            p99_ms: percentile(&millis, 99.0),
+        }
+    }
+}
+
+impl fmt::Display for BenchmarkReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Batch size {}, {} iterations: {:.1} images/s", self.batch_size, self.iterations, self.throughput)?;
+        write!(
+            f,
+            "Latency per batch: mean {:.3} ms, p50 {:.3} ms, p90 {:.3} ms, p99 {:.3} ms",
+            self.mean_ms, self.p50_ms, self.p90_ms, self.p99_ms
+        )
+    }
+}
+
+// Nearest-rank percentile of ascending `sorted` values, 0 when there are none
+fn percentile(sorted: &[f64], percent: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = (percent / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Times `iterations` forward passes of `model` over a random batch of `batch_size` images of
+/// `image_shape`, after `warmup` untimed ones (shader compilation, allocator warmup). The
+/// device is synchronized after every pass, so each timing covers the whole computation and
+/// not only its queuing.
+pub fn benchmark<B: Backend>(
+    model: &Model<B>,
+    device: &B::Device,
+    batch_size: usize,
+    image_shape: [usize; 2],
+    warmup: usize,
+    iterations: usize,
+) -> BenchmarkReport {
+    let [height, width] = image_shape;
+    let images = Tensor::<B, 3>::random([batch_size, height, width], Distribution::Normal(0.0, 1.0), device);
+
+    for _ in 0..warmup {
+        let _ = model.forward(images.clone());
+        B::sync(device);
+    }
+    let latencies: Vec<Duration> = (0..iterations)
+        .map(|_| {
+            let start = Instant::now();
+            let _ = model.forward(images.clone());
+            B::sync(device);
+            start.elapsed()
+        })
+        .collect();
+    BenchmarkReport::from_latencies(batch_size, &latencies)
+}
+
+/// [`benchmark`] of the model trained into `artifact_dir`, on images of the shape it was
+/// trained on.
+pub fn benchmark_run<B: Backend>(
+    artifact_dir: &str,
+    device: &B::Device,
+    batch_size: usize,
+    warmup: usize,
+    iterations: usize,
+) -> Result<BenchmarkReport, LoadError> {
+    let model = load_model::<B>(artifact_dir, device)?;
+    Ok(benchmark(&model, device, batch_size, trained_image_shape(artifact_dir)?, warmup, iterations))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::ModelConfig;
+    use burn::backend::{ndarray::NdArrayDevice, NdArray};
+
+    #[test]
+    fn percentiles_are_nearest_ranks_of_the_latencies() {
+        let latencies: Vec<Duration> = (1..=10).rev().map(Duration::from_millis).collect();
+
+        let report = BenchmarkReport::from_latencies(4, &latencies);
+        assert_eq!(report.iterations, 10);
+        assert_eq!((report.p50_ms, report.p90_ms, report.p99_ms), (5.0, 9.0, 10.0));
+        assert!((report.mean_ms - 5.5).abs() < 1e-9);
+        // 40 images in 55 ms
+        assert!((report.throughput - 40.0 / 0.055).abs() < 1e-6);
+    }
+
+    #[test]
+    fn benchmark_percentiles_are_ordered() {
+        let device = NdArrayDevice::default();
+        let model = ModelConfig::new(10, 8).init::<NdArray>(&device);
+
+        let report = benchmark(&model, &device, 4, [28, 28], 2, 20);
+        assert_eq!(report.iterations, 20);
+        assert!(report.p50_ms <= report.p90_ms && report.p90_ms <= report.p99_ms, "{report}");
+        assert!(report.p50_ms > 0.0 && report.throughput > 0.0);
+    }
+}
@@ -0,0 +1,180 @@
+use burn::{
+    data::dataloader::{DataLoader, DataLoaderIterator, Progress},
+    train::TrainingInterrupter,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// A training budget of `TrainingConfig`, `max_steps` or `max_duration`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum BudgetLimit {
+    MaxSteps(usize),
+    MaxDuration(Duration),
+}
+
+/// How a budget ended a run before its last epoch, as recorded in `model_meta.json`. The
+/// budget ran out after `step` training steps, `elapsed_seconds` into training, with
+/// `epoch_fraction` of epoch `epoch` trained: 1 when it ran out between two epochs.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BudgetStop {
+    pub budget: BudgetLimit,
+    pub step: usize,
+    pub epoch: usize,
+    pub epoch_fraction: f64,
+    pub elapsed_seconds: f64,
+}
+
+impl BudgetStop {
+    pub fn is_mid_epoch(&self) -> bool {
+        self.epoch_fraction < 1.0
+    }
+}
+
+impl fmt::Display for BudgetStop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.budget {
+            BudgetLimit::MaxSteps(steps) => write!(f, "The budget of {steps} steps ran out")?,
+            BudgetLimit::MaxDuration(duration) => write!(f, "The budget of {:.1}s ran out", duration.as_secs_f64())?,
+        }
+        write!(f, " after step {} ({:.1}s)", self.step, self.elapsed_seconds)?;
+        match self.is_mid_epoch() {
+            true => write!(f, ", {:.0}% into epoch {}", self.epoch_fraction * 100.0, self.epoch),
+            false => write!(f, ", at the end of epoch {}", self.epoch),
+        }
+    }
+}
+
+// Steps trained so far, and the stop once a budget ran out. The interrupter is the learner's,
+// which only exists once the loader is built.
+struct BudgetState {
+    start: Instant,
+    step: usize,
+    stop: Option<BudgetStop>,
+    interrupter: Option<TrainingInterrupter>,
+}
+
+impl BudgetState {
+    fn interrupt(&self) {
+        if let Some(interrupter) = &self.interrupter {
+            interrupter.stop();
+        }
+    }
+}
+
+// The training loader of a run with a budget. Once the budget runs out, the epoch being trained
+// ends after the batch in flight, so that the learner still validates and checkpoints it, and
+// the next one stops the learner through its interrupter before its first step. An epoch ending
+// with the budget is left whole.
+pub struct BudgetDataLoader<I> {
+    inner: Box<dyn DataLoader<I>>,
+    max_steps: Option<usize>,
+    max_duration: Option<Duration>,
+    state: Arc<Mutex<BudgetState>>,
+    epoch: AtomicUsize,
+}
+
+/// Hands the learner's interrupter to a [`BudgetDataLoader`], and reads the [`BudgetStop`] of
+/// the run once training is over.
+pub struct BudgetTracker {
+    state: Arc<Mutex<BudgetState>>,
+}
+
+impl BudgetTracker {
+    // The clock starts here
+    pub fn watch(&self, interrupter: TrainingInterrupter) {
+        let mut state = self.state.lock().unwrap();
+        state.start = Instant::now();
+        state.interrupter = Some(interrupter);
+    }
+
+    pub fn stop(&self) -> Option<BudgetStop> {
+        self.state.lock().unwrap().stop.clone()
+    }
+}
+
+impl<I> BudgetDataLoader<I> {
+    pub fn new(
+        inner: Box<dyn DataLoader<I>>,
+        max_steps: Option<usize>,
+        max_duration: Option<Duration>,
+    ) -> (Self, BudgetTracker) {
+        let state = BudgetState { start: Instant::now(), step: 0, stop: None, interrupter: None };
+        let state = Arc::new(Mutex::new(state));
+        let tracker = BudgetTracker { state: state.clone() };
+        let loader = Self { inner, max_steps, max_duration, state, epoch: AtomicUsize::new(0) };
+        (loader, tracker)
+    }
+
+    // The budget that ran out after `state.step` steps, if any, steps first
+    fn exhausted(&self, state: &BudgetState) -> Option<BudgetLimit> {
+        if let Some(max_steps) = self.max_steps.filter(|&max_steps| state.step >= max_steps) {
+            return Some(BudgetLimit::MaxSteps(max_steps));
+        }
+        self.max_duration
+            .filter(|&max_duration| state.start.elapsed() >= max_duration)
+            .map(BudgetLimit::MaxDuration)
+    }
+}
+
+struct BudgetIterator<'a, I> {
+    inner: Box<dyn DataLoaderIterator<I> + 'a>,
+    loader: &'a BudgetDataLoader<I>,
+    epoch: usize,
+    // Steps of this epoch
+    steps: usize,
+}
+
+impl<I> DataLoader<I> for BudgetDataLoader<I> {
+    fn iter<'a>(&'a self) -> Box<dyn DataLoaderIterator<I> + 'a> {
+        let epoch = self.epoch.fetch_add(1, Ordering::Relaxed) + 1;
+        Box::new(BudgetIterator { inner: self.inner.iter(), loader: self, epoch, steps: 0 })
+    }
+
+    fn num_items(&self) -> usize {
+        self.inner.num_items()
+    }
+}
+
+impl<I> Iterator for BudgetIterator<'_, I> {
+    type Item = I;
+
+    fn next(&mut self) -> Option<I> {
+        let mut state = self.loader.state.lock().unwrap();
+        if state.stop.is_some() {
+            state.interrupt();
+            return None;
+        }
+        let progress = self.inner.progress();
+        // An epoch that ends with the budget is a whole one
+        let item = self.inner.next()?;
+        let Some(budget) = self.loader.exhausted(&state) else {
+            state.step += 1;
+            self.steps += 1;
+            return Some(item);
+        };
+
+        let (epoch, epoch_fraction) = match self.steps {
+            0 => (self.epoch - 1, 1.0),
+            _ => (self.epoch, progress.items_processed as f64 / progress.items_total.max(1) as f64),
+        };
+        let elapsed_seconds = state.start.elapsed().as_secs_f64();
+        state.stop = Some(BudgetStop { budget, step: state.step, epoch, epoch_fraction, elapsed_seconds });
+        if self.steps == 0 {
+            state.interrupt();
+        }
+        None
+    }
+}
+
+impl<I> DataLoaderIterator<I> for BudgetIterator<'_, I> {
+    fn progress(&self) -> Progress {
+        self.inner.progress()
+    }
+}
@@ -0,0 +1,254 @@
+use crate::{
+    checkpoint::LoadError,
+    convert::RecordFormat,
+    labels::{ClassLabels, CLASSES_FILE},
+    meta::ModelMeta,
+    model::Model,
+    training::TrainingConfig,
+};
+use burn::prelude::*;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::{self, File},
+    io::{self, Read, Write},
+    path::Path,
+};
+
+// Version of the bundle layout, recorded in its manifest
+pub const BUNDLE_FORMAT_VERSION: u32 = 1;
+
+const MANIFEST_FILE: &str = "bundle.json";
+// Files of the artifact dir a bundle cannot do without
+const MANDATORY_FILES: [&str; 3] = ["model.mpk", "model_meta.json", "config.json"];
+// Files of the artifact dir a bundle carries when they exist: the `evaluate` report, with the
+// calibration of the model
+const OPTIONAL_FILES: [&str; 1] = ["eval.json"];
+
+// Tar block size: headers and the padded contents of every file
+const BLOCK_SIZE: usize = 512;
+
+// One file of a bundle, as listed by its manifest
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BundleFile {
+    pub name: String,
+    pub size: u64,
+    // Hex SHA-256 of the contents
+    pub sha256: String,
+}
+
+// The `bundle.json` index of a bundle, its first entry: every other file with its hash
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BundleManifest {
+    pub format_version: u32,
+    pub files: Vec<BundleFile>,
+}
+
+#[derive(Debug)]
+pub enum ExportError {
+    // Mandatory files missing from the artifact dir, all of them
+    Missing(Vec<String>),
+    // The config or metadata of the artifact dir could not be read
+    Load(LoadError),
+    Io(io::Error),
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportError::Missing(files) => {
+                write!(f, "cannot export the bundle, missing from the artifact dir: {}", files.join(", "))
+            }
+            ExportError::Load(err) => write!(f, "cannot export the bundle: {err}"),
+            ExportError::Io(err) => write!(f, "could not write the bundle: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for ExportError {}
+
+impl From<io::Error> for ExportError {
+    fn from(err: io::Error) -> Self {
+        ExportError::Io(err)
+    }
+}
+
+fn sha256(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+// A ustar header for a regular file, with a zero mtime so that the same files always make the
+// same archive
+fn tar_header(name: &str, size: usize) -> [u8; BLOCK_SIZE] {
+    let mut header = [0u8; BLOCK_SIZE];
+    header[..name.len()].copy_from_slice(name.as_bytes());
+    header[100..108].copy_from_slice(b"0000644\0");
+    header[108..116].copy_from_slice(b"0000000\0");
+    header[116..124].copy_from_slice(b"0000000\0");
+    header[124..136].copy_from_slice(format!("{size:011o}\0").as_bytes());
+    header[136..148].copy_from_slice(b"00000000000\0");
+    header[156] = b'0';
+    header[257..265].copy_from_slice(b"ustar\x0000");
+
+    // The checksum is computed with its own field filled with spaces
+    header[148..156].copy_from_slice(b"        ");
+    let checksum: u32 = header.iter().map(|&byte| byte as u32).sum();
+    header[148..156].copy_from_slice(format!("{checksum:06o}\0 ").as_bytes());
+    header
+}
+
+fn write_tar(writer: &mut impl Write, files: &[(String, Vec<u8>)]) -> io::Result<()> {
+    for (name, data) in files {
+        writer.write_all(&tar_header(name, data.len()))?;
+        writer.write_all(data)?;
+        let padding = data.len().next_multiple_of(BLOCK_SIZE) - data.len();
+        writer.write_all(&vec![0; padding])?;
+    }
+    // End of archive: two zero blocks
+    writer.write_all(&[0; 2 * BLOCK_SIZE])
+}
+
+// The regular files of a tar archive, by name
+fn read_tar(data: &[u8]) -> Result<HashMap<String, Vec<u8>>, String> {
+    let field = |bytes: &[u8]| {
+        let end = bytes.iter().position(|&byte| byte == 0).unwrap_or(bytes.len());
+        String::from_utf8_lossy(&bytes[..end]).trim().to_string()
+    };
+
+    let mut files = HashMap::new();
+    let mut offset = 0;
+    while offset + BLOCK_SIZE <= data.len() {
+        let header = &data[offset..offset + BLOCK_SIZE];
+        if header.iter().all(|&byte| byte == 0) {
+            break;
+        }
+        let name = field(&header[..100]);
+        let size = usize::from_str_radix(&field(&header[124..136]), 8)
+            .map_err(|_| format!("invalid size in the header of {name}"))?;
+        offset += BLOCK_SIZE;
+        let contents = data.get(offset..offset + size).ok_or_else(|| format!("{name} is truncated"))?;
+        if matches!(header[156], b'0' | 0) {
+            files.insert(name, contents.to_vec());
+        }
+        offset += size.next_multiple_of(BLOCK_SIZE);
+    }
+    Ok(files)
+}
+
+/// Packs everything needed to serve the model of `artifact_dir` into a single `.tar.gz` at
+/// `out_path`: the `model.mpk` weights, `model_meta.json`, `config.json`, `eval.json` (with the
+/// calibration) when `evaluate` wrote one, and a `classes.json` of the class names. The first
+/// entry, `bundle.json`, lists every other file with its size and SHA-256, which
+/// [`Bundle::open`] checks.
+///
+/// Fails without writing anything when a mandatory file is missing, naming all of them.
+pub fn export_bundle(artifact_dir: &str, out_path: &str) -> Result<BundleManifest, ExportError> {
+    let path = |name: &str| Path::new(artifact_dir).join(name);
+    let missing: Vec<String> =
+        MANDATORY_FILES.iter().filter(|name| !path(name).is_file()).map(|name| name.to_string()).collect();
+    if !missing.is_empty() {
+        return Err(ExportError::Missing(missing));
+    }
+
+    let config = TrainingConfig::load(path("config.json"))
+        .map_err(|err| ExportError::Load(LoadError::Config(err.to_string())))?;
+    // `Bundle::open` reads the metadata, it must be one this build reads
+    ModelMeta::load(artifact_dir).map_err(ExportError::Load)?;
+
+    let mut files = Vec::new();
+    for name in MANDATORY_FILES.iter().chain(&OPTIONAL_FILES) {
+        if path(name).is_file() {
+            files.push((name.to_string(), fs::read(path(name))?));
+        }
+    }
+    // Artifact dirs trained before `classes.json` existed get one from their config
+    let labels = match path(CLASSES_FILE).is_file() {
+        true => ClassLabels::load(artifact_dir),
+        false => ClassLabels::from_config(&config),
+    };
+    let classes = serde_json::to_vec_pretty(&labels).expect("Class names should serialize to JSON");
+    files.push((CLASSES_FILE.to_string(), classes));
+
+    let manifest = BundleManifest {
+        format_version: BUNDLE_FORMAT_VERSION,
+        files: files
+            .iter()
+            .map(|(name, data)| BundleFile { name: name.clone(), size: data.len() as u64, sha256: sha256(data) })
+            .collect(),
+    };
+    let index = serde_json::to_vec_pretty(&manifest).expect("Bundle manifest should serialize to JSON");
+    files.insert(0, (MANIFEST_FILE.to_string(), index));
+
+    let mut encoder = GzEncoder::new(File::create(out_path)?, Compression::default());
+    write_tar(&mut encoder, &files)?;
+    encoder.finish()?;
+    Ok(manifest)
+}
+
+/// A bundle written by [`export_bundle`], read into memory and checked against its manifest.
+pub struct Bundle {
+    pub manifest: BundleManifest,
+    pub config: TrainingConfig,
+    pub meta: ModelMeta,
+    pub labels: ClassLabels,
+    weights: Vec<u8>,
+}
+
+impl Bundle {
+    /// Reads the bundle at `path` straight from the archive. Every file must match the size and
+    /// hash of the manifest, and the metadata must agree with the config, as for an artifact
+    /// dir.
+    pub fn open(path: &str) -> Result<Self, LoadError> {
+        let invalid = |err: &dyn fmt::Display| LoadError::Bundle(format!("{path}: {err}"));
+
+        let mut archive = Vec::new();
+        GzDecoder::new(File::open(path).map_err(|err| invalid(&err))?)
+            .read_to_end(&mut archive)
+            .map_err(|err| invalid(&err))?;
+        let mut files = read_tar(&archive).map_err(|err| invalid(&err))?;
+
+        let index = files.remove(MANIFEST_FILE).ok_or_else(|| invalid(&format!("no {MANIFEST_FILE}")))?;
+        let manifest: BundleManifest = serde_json::from_slice(&index).map_err(|err| invalid(&err))?;
+        if manifest.format_version != BUNDLE_FORMAT_VERSION {
+            return Err(invalid(&format!(
+                "bundle format version {}, this build reads version {BUNDLE_FORMAT_VERSION}",
+                manifest.format_version
+            )));
+        }
+        for file in &manifest.files {
+            let data = files.get(&file.name).ok_or_else(|| invalid(&format!("{} is missing", file.name)))?;
+            if data.len() as u64 != file.size || sha256(data) != file.sha256 {
+                return Err(invalid(&format!("{} does not match its hash in the manifest", file.name)));
+            }
+        }
+        let mut take = |name: &str| match manifest.files.iter().any(|file| file.name == name) {
+            true => files.remove(name).ok_or_else(|| invalid(&format!("{name} is missing"))),
+            false => Err(invalid(&format!("{name} is not in the manifest"))),
+        };
+
+        let config = TrainingConfig::load_binary(&take("config.json")?).map_err(|err| invalid(&err))?;
+        let meta_json = String::from_utf8(take("model_meta.json")?).map_err(|err| invalid(&err))?;
+        let meta = ModelMeta::from_json(&meta_json, &format!("{path}/model_meta.json"))?;
+        meta.check(&config.model)?;
+        let labels = serde_json::from_slice(&take(CLASSES_FILE)?).map_err(|err| invalid(&err))?;
+        let weights = take("model.mpk")?;
+
+        Ok(Self { manifest, config, meta, labels, weights })
+    }
+
+    /// The model of the bundle, built from its metadata.
+    pub fn model<B: Backend>(&self, device: &B::Device) -> Result<Model<B>, LoadError> {
+        RecordFormat::Compact
+            .decode(self.meta.model.init::<B>(device), &self.weights, device)
+            .map_err(|err| LoadError::Record(err.to_string()))
+    }
+}
+
+/// Loads the model of the bundle at `path`, the bundle counterpart of
+/// [`load_model`](crate::inference::load_model).
+pub fn load_bundle<B: Backend>(path: &str, device: &B::Device) -> Result<Model<B>, LoadError> {
+    Bundle::open(path)?.model(device)
+}
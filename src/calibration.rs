@@ -0,0 +1,221 @@
+use crate::{
+    artifact::ArtifactDir,
+    checkpoint::LoadError,
+    data::{ClassificationDataset, MnistSplit},
+    meta::Normalization,
+    split::{ClassSubset, OneVsRest},
+    training::TrainingConfig,
+};
+use burn::config::Config;
+use image::{GrayImage, Luma};
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::{fmt, io, path::Path};
+
+// Written into the output directory next to the images
+pub const CALIBRATION_MANIFEST_FILE: &str = "manifest.json";
+
+// One image of the calibration set
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CalibrationImage {
+    // Name of the PNG, relative to the manifest
+    pub file: String,
+    // Index of the image in the training split
+    pub index: usize,
+    pub label: usize,
+}
+
+/// Content of `manifest.json`, as written by [`export_calibration_set`]. The images are raw
+/// pixels on the MNIST scale: a quantizer must apply `normalization` to them, as the batcher
+/// does, to reproduce the model inputs.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CalibrationManifest {
+    pub image_shape: [usize; 2],
+    pub normalization: Normalization,
+    // Number of images of each label, by label
+    pub class_counts: Vec<usize>,
+    pub images: Vec<CalibrationImage>,
+}
+
+#[derive(Debug)]
+pub enum CalibrationError {
+    Load(LoadError),
+    Io(io::Error),
+}
+
+impl fmt::Display for CalibrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CalibrationError::Load(err) => write!(f, "cannot select the calibration set: {err}"),
+            CalibrationError::Io(err) => write!(f, "could not write the calibration set: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for CalibrationError {}
+
+impl From<io::Error> for CalibrationError {
+    fn from(err: io::Error) -> Self {
+        CalibrationError::Io(err)
+    }
+}
+
+/// Writes a class-balanced sample of `num_samples` images of the training split of the run in
+/// `artifact_dir` to `out_dir` as PNGs, with a `manifest.json` listing them, for the
+/// post-training quantization of the model by an external toolchain. Labels are those of the
+/// model: positions in `classes`, or 0/1 for one-vs-rest runs. The sample is drawn from the
+/// seed of the run.
+pub fn export_calibration_set(
+    artifact_dir: &str,
+    num_samples: usize,
+    out_dir: &str,
+) -> Result<CalibrationManifest, CalibrationError> {
+    let config = TrainingConfig::load(ArtifactDir::new(artifact_dir).config_path())
+        .map_err(|err| CalibrationError::Load(LoadError::Config(err.to_string())))?;
+    let dataset = config.dataset.load(MnistSplit::Train, config.cache);
+    let manifest = match (&config.classes, config.binary_target) {
+        (Some(classes), _) => {
+            export_dataset_calibration_set(&ClassSubset::new(dataset, classes), num_samples, config.seed, out_dir)
+        }
+        (None, Some(target)) => {
+            export_dataset_calibration_set(&OneVsRest::new(dataset, target), num_samples, config.seed, out_dir)
+        }
+        (None, None) => export_dataset_calibration_set(&dataset, num_samples, config.seed, out_dir),
+    }?;
+    Ok(manifest)
+}
+
+/// [`export_calibration_set`] of any dataset, sampled with `seed`. Every label gets the same
+/// number of images, give or take one, unless it has fewer items than its share: the other labels
+/// then make up for it. The whole dataset is written when it has no more than `num_samples`
+/// readable items.
+pub fn export_dataset_calibration_set<D: ClassificationDataset + ?Sized>(
+    dataset: &D,
+    num_samples: usize,
+    seed: u64,
+    out_dir: &str,
+) -> io::Result<CalibrationManifest> {
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    for index in 0..dataset.len() {
+        // Unreadable items are never selected
+        let Some((_, label)) = dataset.get(index) else {
+            continue;
+        };
+        if groups.len() <= label {
+            groups.resize(label + 1, Vec::new());
+        }
+        groups[label].push(index);
+    }
+    let mut rng = StdRng::seed_from_u64(seed);
+    for group in &mut groups {
+        group.shuffle(&mut rng);
+    }
+
+    // Round robin over the labels, each round taking the next image of every label left
+    let mut selected = Vec::with_capacity(num_samples);
+    let rounds = groups.iter().map(Vec::len).max().unwrap_or(0);
+    'rounds: for round in 0..rounds {
+        for (label, group) in groups.iter().enumerate() {
+            if selected.len() == num_samples {
+                break 'rounds;
+            }
+            if let Some(&index) = group.get(round) {
+                selected.push((index, label));
+            }
+        }
+    }
+
+    std::fs::create_dir_all(out_dir)?;
+    let [height, width] = dataset.image_shape();
+    let mut class_counts = vec![0; groups.len()];
+    let mut images = Vec::with_capacity(selected.len());
+    for (position, (index, label)) in selected.into_iter().enumerate() {
+        let Some((pixels, _)) = dataset.get(index) else {
+            continue;
+        };
+        let image = GrayImage::from_fn(width as u32, height as u32, |x, y| {
+            Luma([pixels[y as usize * width + x as usize].round().clamp(0.0, 255.0) as u8])
+        });
+        let file = format!("{position:05}_label-{label}.png");
+        image.save(Path::new(out_dir).join(&file)).map_err(io::Error::other)?;
+        class_counts[label] += 1;
+        images.push(CalibrationImage { file, index, label });
+    }
+
+    let manifest = CalibrationManifest {
+        image_shape: [height, width],
+        normalization: Normalization::current(),
+        class_counts,
+        images,
+    };
+    let json = serde_json::to_string_pretty(&manifest).expect("Calibration manifest should serialize");
+    std::fs::write(Path::new(out_dir).join(CALIBRATION_MANIFEST_FILE), json)?;
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // One-pixel images labeled as given, the pixel being the index
+    struct Labels(Vec<usize>);
+
+    impl ClassificationDataset for Labels {
+        fn len(&self) -> usize {
+            self.0.len()
+        }
+
+        fn get(&self, index: usize) -> Option<(Vec<f32>, usize)> {
+            Some((vec![index as f32], *self.0.get(index)?))
+        }
+
+        fn num_classes(&self) -> usize {
+            3
+        }
+
+        fn image_shape(&self) -> [usize; 2] {
+            [1, 1]
+        }
+    }
+
+    fn out_dir(name: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("my_first_rust_DL_app-calibration-{name}"));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn labels_short_of_their_share_are_made_up_for_by_the_others() {
+        let labels = Labels([vec![0; 2], vec![1; 10], vec![2; 4]].concat());
+        let dir = out_dir("balanced");
+        let manifest = export_dataset_calibration_set(&labels, 9, 3, &dir).unwrap();
+
+        assert_eq!(manifest.image_shape, [1, 1]);
+        assert_eq!(manifest.class_counts, [2, 4, 3]);
+        assert_eq!(manifest.images.len(), 9);
+        for image in &manifest.images {
+            assert_eq!(image.label, labels.0[image.index]);
+            let png = image::open(Path::new(&dir).join(&image.file)).unwrap().to_luma8();
+            assert_eq!(png.get_pixel(0, 0)[0] as usize, image.index);
+        }
+        let written: CalibrationManifest =
+            serde_json::from_str(&std::fs::read_to_string(Path::new(&dir).join(CALIBRATION_MANIFEST_FILE)).unwrap())
+                .unwrap();
+        assert_eq!(written, manifest);
+        assert_eq!(export_dataset_calibration_set(&labels, 9, 3, &dir).unwrap(), manifest);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn the_whole_dataset_is_written_when_it_is_small_enough() {
+        let labels = Labels(vec![0, 1, 1, 2]);
+        let dir = out_dir("small");
+        let manifest = export_dataset_calibration_set(&labels, 10, 0, &dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(manifest.class_counts, [1, 2, 1]);
+        let mut indices: Vec<_> = manifest.images.iter().map(|image| image.index).collect();
+        indices.sort();
+        assert_eq!(indices, [0, 1, 2, 3]);
+    }
+}
@@ -0,0 +1,329 @@
+use crate::{
+    artifact::{ArtifactDir, ModelKind},
+    meta::ModelMeta,
+    model::{Model, ModelConfig},
+    params::{named_params, with_named_params, NamedParam},
+    training::TrainingConfig,
+};
+use burn::{
+    prelude::*,
+    record::{CompactRecorder, Recorder},
+    tensor::{bf16, f16},
+};
+use safetensors::{Dtype, SafeTensors};
+use std::{fmt, path::Path};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShapeMismatch {
+    pub name: String,
+    // Shape the model built from the current config expects
+    pub expected: Vec<usize>,
+    // Shape stored in the checkpoint
+    pub found: Vec<usize>,
+}
+
+// Differences between the parameters of a checkpoint and those of the model being loaded into
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LoadReport {
+    // In the model but not in the checkpoint: left at their fresh initialization
+    pub missing: Vec<String>,
+    // In the checkpoint but not in the model: ignored
+    pub unexpected: Vec<String>,
+    // In both but with different shapes: left at their fresh initialization
+    pub shape_mismatches: Vec<ShapeMismatch>,
+    // Copied over from the checkpoint
+    pub loaded: Vec<String>,
+}
+
+impl LoadReport {
+    pub fn is_exact(&self) -> bool {
+        self.missing.is_empty() && self.unexpected.is_empty() && self.shape_mismatches.is_empty()
+    }
+
+    pub(crate) fn compare(expected: &[NamedParam], found: &[NamedParam]) -> Self {
+        let mut report = Self::default();
+
+        for param in expected {
+            match found.iter().find(|other| other.name == param.name) {
+                None => report.missing.push(param.name.clone()),
+                Some(other) if other.shape != param.shape => {
+                    report.shape_mismatches.push(ShapeMismatch {
+                        name: param.name.clone(),
+                        expected: param.shape.clone(),
+                        found: other.shape.clone(),
+                    })
+                }
+                Some(_) => report.loaded.push(param.name.clone()),
+            }
+        }
+        report.unexpected = found
+            .iter()
+            .filter(|param| !expected.iter().any(|other| other.name == param.name))
+            .map(|param| param.name.clone())
+            .collect();
+
+        report
+    }
+}
+
+impl fmt::Display for LoadReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "loaded {} parameter(s)", self.loaded.len())?;
+        for name in &self.missing {
+            writeln!(f, "  missing from checkpoint: {name}")?;
+        }
+        for name in &self.unexpected {
+            writeln!(f, "  unexpected in checkpoint: {name}")?;
+        }
+        for mismatch in &self.shape_mismatches {
+            writeln!(
+                f,
+                "  shape mismatch for {}: model expects {:?}, checkpoint has {:?}",
+                mismatch.name, mismatch.expected, mismatch.found
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+    // The artifact's config.json is missing or unreadable
+    Config(String),
+    // The weights file is missing or corrupted
+    Record(String),
+    // Strict loading found parameters that don't line up with the requested config
+    Mismatch(LoadReport),
+    // The artifact's model_meta.json has a layout version this build does not read
+    Version { found: u32, supported: u32 },
+    // The artifact's model_meta.json disagrees with the config being loaded
+    Incompatible { field: String, meta: String, config: String },
+    // A bundle archive is unreadable, incomplete or does not match its manifest
+    Bundle(String),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Config(err) => write!(f, "could not read the artifact config: {err}"),
+            LoadError::Record(err) => write!(f, "could not read the model weights: {err}"),
+            LoadError::Mismatch(report) => {
+                write!(f, "checkpoint does not match the model config, {report}")
+            }
+            LoadError::Version { found, supported } => write!(
+                f,
+                "the artifact metadata has format version {found}, this build reads versions {} to {supported}",
+                crate::meta::OLDEST_FORMAT_VERSION
+            ),
+            LoadError::Incompatible { field, meta, config } => write!(
+                f,
+                "`{field}` is {meta} in the artifact metadata but {config} in the config being loaded"
+            ),
+            LoadError::Bundle(err) => write!(f, "invalid model bundle {err}"),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+// Loads the weights saved in `artifact_dir` into a model built from `config`.
+//
+// The checkpoint is first restored into the model it was trained with (described by the
+// artifact's own config.json), then copied parameter by parameter into the requested model.
+// With `strict` any difference is an error; otherwise every compatible parameter is copied, the
+// rest keep their fresh initialization, and the report says which were which.
+pub fn load_weights<B: Backend>(
+    artifact_dir: &str,
+    config: &ModelConfig,
+    strict: bool,
+    device: &B::Device,
+) -> Result<(Model<B>, LoadReport), LoadError> {
+    let saved_config = TrainingConfig::load(ArtifactDir::new(artifact_dir).config_path())
+        .map_err(|err| LoadError::Config(err.to_string()))?;
+    // The metadata, when there is one, is what the weights were saved with
+    let saved_model = match ModelMeta::load(artifact_dir)? {
+        Some(meta) => meta.model,
+        None => saved_config.model,
+    };
+    let record = CompactRecorder::new()
+        .load(ArtifactDir::new(artifact_dir).model_path(ModelKind::Final).into(), device)
+        .map_err(|err| LoadError::Record(err.to_string()))?;
+    let saved = saved_model.init::<B>(device).load_record(record);
+
+    let model = config.init::<B>(device);
+    let saved_params = named_params(&saved);
+    let report = LoadReport::compare(&named_params(&model), &saved_params);
+
+    if strict && !report.is_exact() {
+        return Err(LoadError::Mismatch(report));
+    }
+
+    let compatible: Vec<NamedParam> = saved_params
+        .into_iter()
+        .filter(|param| report.loaded.contains(&param.name))
+        .collect();
+
+    Ok((with_named_params(model, &compatible, device), report))
+}
+
+// The tensors of a safetensors file as parameters, converted to f32
+pub(crate) fn read_safetensors(path: &Path) -> Result<Vec<NamedParam>, LoadError> {
+    let invalid = |err: &dyn fmt::Display| LoadError::Record(format!("{}: {err}", path.display()));
+    let bytes = std::fs::read(path).map_err(|err| invalid(&err))?;
+    let tensors = SafeTensors::deserialize(&bytes).map_err(|err| invalid(&err))?;
+
+    let mut params = Vec::new();
+    for (name, tensor) in tensors.tensors() {
+        let data = tensor.data();
+        let values: Vec<f32> = match tensor.dtype() {
+            Dtype::F32 => data.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect(),
+            Dtype::F64 => data
+                .chunks_exact(8)
+                .map(|b| f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]) as f32)
+                .collect(),
+            Dtype::F16 => data.chunks_exact(2).map(|b| f16::from_le_bytes([b[0], b[1]]).to_f32()).collect(),
+            Dtype::BF16 => data.chunks_exact(2).map(|b| bf16::from_le_bytes([b[0], b[1]]).to_f32()).collect(),
+            dtype => return Err(invalid(&format!("tensor {name} has dtype {dtype:?}, expected a float type"))),
+        };
+        params.push(NamedParam { name, shape: tensor.shape().to_vec(), values });
+    }
+    Ok(params)
+}
+
+// Copies the parameters of `found` (read from `source`) whose name and shape match into
+// `model`, warning about those of another shape, see `load_partial_weights`
+pub(crate) fn load_partial_params<B: Backend>(model: Model<B>, found: Vec<NamedParam>, source: &str) -> (Model<B>, LoadReport) {
+    let report = LoadReport::compare(&named_params(&model), &found);
+    for mismatch in &report.shape_mismatches {
+        eprintln!(
+            "Warning: skipping {} from {source}: the model expects shape {:?}, the file has {:?}",
+            mismatch.name, mismatch.expected, mismatch.found
+        );
+    }
+
+    let compatible: Vec<NamedParam> = found.into_iter().filter(|param| report.loaded.contains(&param.name)).collect();
+    let device = model.devices()[0].clone();
+    (with_named_params(model, &compatible, &device), report)
+}
+
+/// Warm start from weights trained elsewhere: copies into `model` every tensor of the
+/// safetensors file at `path` whose name (e.g. `conv1.weight`, as in
+/// [`named_params`](crate::params::named_params)) and shape match one of its parameters. Float
+/// tensors of any precision are converted to f32.
+///
+/// Tensors with another shape are skipped with a warning; those parameters, and the ones the
+/// file does not have, keep their current values. The report lists which were loaded,
+/// skipped, missing or unknown to the model. Burn stores linear weights as `[in, out]`, so those
+/// of a PyTorch state dict (`[out, in]`) only load once transposed.
+pub fn load_partial_weights<B: Backend>(model: Model<B>, path: impl AsRef<Path>) -> Result<(Model<B>, LoadReport), LoadError> {
+    let path = path.as_ref();
+    let found = read_safetensors(path)?;
+    Ok(load_partial_params(model, found, &path.display().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::AuxTask;
+    use burn::{
+        backend::{ndarray::NdArrayDevice, NdArray},
+        module::Module,
+        optim::AdamConfig,
+    };
+
+    // An artifact dir holding only the config and final weights of a fresh `ModelConfig::new(10, 8)`
+    fn saved_run(name: &str, device: &NdArrayDevice) -> (String, Vec<NamedParam>) {
+        let dir = std::env::temp_dir().join(format!("my_first_rust_DL_app-checkpoint-{name}"));
+        std::fs::create_dir_all(&dir).unwrap();
+        let dir = ArtifactDir::new(dir.to_str().unwrap());
+        let config = TrainingConfig::new(ModelConfig::new(10, 8), AdamConfig::new());
+        config.save(dir.config_path()).unwrap();
+        let model = config.model.init::<NdArray>(device);
+        let params = named_params(&model);
+        model.save_file(dir.model_path(ModelKind::Final), &CompactRecorder::new()).unwrap();
+        (dir.as_str().to_string(), params)
+    }
+
+    #[test]
+    fn widened_linear_layer_is_a_shape_mismatch() {
+        let device = NdArrayDevice::default();
+        let (dir, saved) = saved_run("widened", &device);
+        let widened = ModelConfig::new(10, 12);
+
+        let strict = load_weights::<NdArray>(&dir, &widened, true, &device);
+        let (model, report) = load_weights::<NdArray>(&dir, &widened, false, &device).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        match strict {
+            Err(LoadError::Mismatch(strict_report)) => assert_eq!(strict_report, report),
+            other => panic!("strict loading gave {other:?}"),
+        }
+        let mismatched: Vec<&str> = report.shape_mismatches.iter().map(|mismatch| mismatch.name.as_str()).collect();
+        assert_eq!(mismatched, ["linear1.bias", "linear1.weight", "linear2.weight"]);
+        assert_eq!(report.shape_mismatches[1].expected, [1024, 12]);
+        assert_eq!(report.shape_mismatches[1].found, [1024, 8]);
+        assert!(report.missing.is_empty() && report.unexpected.is_empty());
+        // The conv layers and the bias of linear2 are copied over, through the f16 of the file
+        let loaded = named_params(&model);
+        for name in &report.loaded {
+            let values = |params: &[NamedParam]| params.iter().find(|param| &param.name == name).unwrap().values.clone();
+            let close = values(&loaded).iter().zip(values(&saved)).all(|(loaded, saved)| (loaded - saved).abs() < 1e-3);
+            assert!(close, "{name} was not loaded");
+        }
+        assert!(report.loaded.contains(&"conv1.weight".to_string()));
+    }
+
+    #[test]
+    fn added_module_is_missing_from_the_checkpoint() {
+        let device = NdArrayDevice::default();
+        let (dir, _) = saved_run("added", &device);
+        let with_aux = ModelConfig::new(10, 8).with_aux_task(Some(AuxTask::Parity));
+
+        let strict = load_weights::<NdArray>(&dir, &with_aux, true, &device);
+        let (_, report) = load_weights::<NdArray>(&dir, &with_aux, false, &device).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(matches!(strict, Err(LoadError::Mismatch(_))));
+        assert_eq!(report.missing, ["aux_head.linear.bias", "aux_head.linear.weight"]);
+        assert!(report.shape_mismatches.is_empty() && report.unexpected.is_empty());
+        assert_eq!(report.loaded.len(), 8);
+    }
+
+    #[test]
+    fn partial_weights_load_the_matching_tensors_and_report_the_others() {
+        let device = NdArrayDevice::default();
+        let saved = named_params(&ModelConfig::new(10, 8).init::<NdArray>(&device));
+        let param = |name: &str| saved.iter().find(|param| param.name == name).unwrap().clone();
+        let conv1 = param("conv1.weight");
+        // linear1 of a wider model, and a layer the model does not have
+        let linear1 = NamedParam { shape: vec![1024, 12], values: vec![0.5; 1024 * 12], ..param("linear1.weight") };
+        let extra = NamedParam { name: "fc3.weight".to_string(), shape: vec![2, 2], values: vec![1.0; 4] };
+        let bytes: Vec<(String, Vec<u8>, Vec<usize>)> = [&conv1, &linear1, &extra]
+            .into_iter()
+            .map(|param| {
+                let bytes = param.values.iter().flat_map(|value| value.to_le_bytes()).collect();
+                (param.name.clone(), bytes, param.shape.clone())
+            })
+            .collect();
+        let views = bytes.iter().map(|(name, bytes, shape)| {
+            (name.clone(), safetensors::tensor::TensorView::new(Dtype::F32, shape.clone(), bytes).unwrap())
+        });
+        let path = std::env::temp_dir().join("my_first_rust_DL_app-checkpoint-partial.safetensors");
+        std::fs::write(&path, safetensors::serialize(views, &None).unwrap()).unwrap();
+
+        let model = ModelConfig::new(10, 8).init::<NdArray>(&device);
+        let before = named_params(&model);
+        let (model, report) = load_partial_weights(model, &path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(report.loaded, ["conv1.weight"]);
+        let skipped: Vec<&str> = report.shape_mismatches.iter().map(|mismatch| mismatch.name.as_str()).collect();
+        assert_eq!(skipped, ["linear1.weight"]);
+        assert_eq!(report.unexpected, ["fc3.weight"]);
+        assert_eq!(report.missing.len(), 6);
+        let loaded = named_params(&model);
+        let values = |params: &[NamedParam], name: &str| params.iter().find(|param| param.name == name).unwrap().values.clone();
+        assert_eq!(values(&loaded, "conv1.weight"), conv1.values);
+        assert_eq!(values(&loaded, "linear1.weight"), values(&before, "linear1.weight"));
+    }
+}
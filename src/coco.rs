@@ -0,0 +1,88 @@
+use crate::inference::Prediction;
+use serde::{Deserialize, Serialize};
+use std::{fs, io, path::Path};
+
+/// The id of an image in a COCO-style results file, derived from its file name: the number of
+/// an all-digit stem (`00042.png` is 42), as COCO tooling expects, the stem itself otherwise.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(untagged)]
+pub enum ImageId {
+    Number(u64),
+    Name(String),
+}
+
+impl ImageId {
+    pub fn from_path(path: impl AsRef<Path>) -> Self {
+        let stem = path.as_ref().file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+        match stem.bytes().all(|byte| byte.is_ascii_digit()) {
+            true => stem.parse().map_or(ImageId::Name(stem), ImageId::Number),
+            false => ImageId::Name(stem),
+        }
+    }
+}
+
+/// One entry of a COCO-style classification results file: the predicted class of an image and
+/// its confidence.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CocoResult {
+    pub image_id: ImageId,
+    // Class index, as in `Prediction::index`
+    pub category_id: usize,
+    pub score: f32,
+}
+
+impl CocoResult {
+    pub fn new(image_id: ImageId, prediction: &Prediction) -> Self {
+        Self { image_id, category_id: prediction.index, score: prediction.confidence }
+    }
+}
+
+/// Writes the predictions of the images, each with its [`ImageId`], in input order as a JSON
+/// array of `{image_id, category_id, score}` objects, the results format of COCO evaluation
+/// tools.
+pub fn export_predictions_json(predictions: &[(ImageId, Prediction)], path: impl AsRef<Path>) -> io::Result<()> {
+    let results: Vec<CocoResult> =
+        predictions.iter().map(|(image_id, prediction)| CocoResult::new(image_id.clone(), prediction)).collect();
+    let json = serde_json::to_string_pretty(&results).expect("COCO results should serialize to JSON");
+    fs::write(path, json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::labels::ClassLabels;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn image_ids_are_numbers_for_all_digit_stems() {
+        assert_eq!(ImageId::from_path("test/00042.png"), ImageId::Number(42));
+        assert_eq!(ImageId::from_path("7"), ImageId::Number(7));
+        assert_eq!(ImageId::from_path("scans/seven-b.png"), ImageId::Name("seven-b".to_string()));
+        // Too large for a u64
+        let huge = "1".repeat(25);
+        assert_eq!(ImageId::from_path(format!("{huge}.png")), ImageId::Name(huge));
+    }
+
+    #[test]
+    fn results_are_written_in_input_order() {
+        let labels = ClassLabels::indices(3);
+        let predictions = [
+            (ImageId::from_path("b/00012.png"), Prediction::from_probabilities(vec![0.25, 0.25, 0.5], &labels, 1)),
+            (ImageId::from_path("a/cat.png"), Prediction::from_probabilities(vec![0.75, 0.125, 0.125], &labels, 1)),
+        ];
+        let path = std::env::temp_dir().join("my_first_rust_DL_app-coco-results.json");
+
+        export_predictions_json(&predictions, &path).unwrap();
+        let json: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            json,
+            json!([
+                {"image_id": 12, "category_id": 2, "score": 0.5},
+                {"image_id": "cat", "category_id": 0, "score": 0.75},
+            ])
+        );
+        let results: Vec<CocoResult> = serde_json::from_value(json).unwrap();
+        assert_eq!(results[1].image_id, ImageId::Name("cat".to_string()));
+    }
+}
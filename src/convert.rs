@@ -0,0 +1,362 @@
+use crate::{
+    checkpoint::LoadError,
+    labels::ClassLabels,
+    meta::{ModelMeta, RECORDER},
+    model::Model,
+    training::{create_artifact_dir, TrainingConfig},
+};
+use burn::{
+    prelude::*,
+    record::{
+        BinBytesRecorder, BinFileRecorder, CompactRecorder, FullPrecisionSettings, HalfPrecisionSettings,
+        NamedMpkBytesRecorder, NamedMpkFileRecorder, NamedMpkGzFileRecorder, PrettyJsonFileRecorder, Recorder,
+        RecorderError,
+    },
+};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    fmt, fs,
+    io::{self, Read, Write},
+    path::Path,
+};
+
+/// A burn file recorder the `model` weights of an artifact dir can be saved with. Artifacts are
+/// trained as [`RecordFormat::Compact`], the only format [`load_model`](crate::load_model)
+/// reads; the others are for tools that expect them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordFormat {
+    // `CompactRecorder`: named MessagePack with f16 floats, `model.mpk`
+    Compact,
+    // `NamedMpkFileRecorder` in full precision, `model.mpk`
+    NamedMpk,
+    // `NamedMpkGzFileRecorder` in full precision, `model.mpk.gz`
+    NamedMpkGz,
+    // `BinFileRecorder` in full precision, `model.bin`
+    Bin,
+    // `PrettyJsonFileRecorder` in full precision, `model.json`
+    Json,
+}
+
+impl RecordFormat {
+    // The name recorded as `recorder` in `model_meta.json`
+    pub fn recorder_name(self) -> &'static str {
+        match self {
+            RecordFormat::Compact => RECORDER,
+            RecordFormat::NamedMpk => "NamedMpkFileRecorder",
+            RecordFormat::NamedMpkGz => "NamedMpkGzFileRecorder",
+            RecordFormat::Bin => "BinFileRecorder",
+            RecordFormat::Json => "PrettyJsonFileRecorder",
+        }
+    }
+
+    // Loads the weights at `path` (without extension, the recorder adds it) into `model`
+    fn load<B: Backend>(self, model: Model<B>, path: &str, device: &B::Device) -> Result<Model<B>, RecorderError> {
+        match self {
+            RecordFormat::Compact => model.load_file(path, &CompactRecorder::new(), device),
+            RecordFormat::NamedMpk => model.load_file(path, &NamedMpkFileRecorder::<FullPrecisionSettings>::new(), device),
+            RecordFormat::NamedMpkGz => {
+                model.load_file(path, &NamedMpkGzFileRecorder::<FullPrecisionSettings>::new(), device)
+            }
+            RecordFormat::Bin => model.load_file(path, &BinFileRecorder::<FullPrecisionSettings>::new(), device),
+            RecordFormat::Json => model.load_file(path, &PrettyJsonFileRecorder::<FullPrecisionSettings>::new(), device),
+        }
+    }
+
+    fn save<B: Backend>(self, model: Model<B>, path: &str) -> Result<(), RecorderError> {
+        match self {
+            RecordFormat::Compact => model.save_file(path, &CompactRecorder::new()),
+            RecordFormat::NamedMpk => model.save_file(path, &NamedMpkFileRecorder::<FullPrecisionSettings>::new()),
+            RecordFormat::NamedMpkGz => model.save_file(path, &NamedMpkGzFileRecorder::<FullPrecisionSettings>::new()),
+            RecordFormat::Bin => model.save_file(path, &BinFileRecorder::<FullPrecisionSettings>::new()),
+            RecordFormat::Json => model.save_file(path, &PrettyJsonFileRecorder::<FullPrecisionSettings>::new()),
+        }
+    }
+}
+
+impl RecordFormat {
+    /// The format of weights serialized by [`RecordFormat::encode`], or saved by the format's
+    /// file recorder, told from the bytes themselves. `None` for bytes of no format, which
+    /// includes bincode weights in f16.
+    pub fn detect(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(&GZIP_MAGIC) {
+            return Some(RecordFormat::NamedMpkGz);
+        }
+        if bytes.iter().find(|byte| !byte.is_ascii_whitespace()) == Some(&b'{') {
+            return Some(RecordFormat::Json);
+        }
+        // Every recorder writes the float type of the record first
+        if let Some(float) = mpk_float_type(bytes) {
+            return Some(if float.ends_with("f16") { RecordFormat::Compact } else { RecordFormat::NamedMpk });
+        }
+        bin_float_type(bytes).filter(|float| *float == "f32").map(|_| RecordFormat::Bin)
+    }
+
+    /// The weights of `model` in the serialization of this format's file recorder, in memory. Only
+    /// the recorder named in the record metadata differs from a saved file.
+    pub fn encode<B: Backend>(self, model: Model<B>) -> Vec<u8> {
+        let record = model.into_record();
+        let bytes = match self {
+            RecordFormat::Compact => NamedMpkBytesRecorder::<HalfPrecisionSettings>::new().record(record, ()),
+            RecordFormat::NamedMpk | RecordFormat::NamedMpkGz => {
+                NamedMpkBytesRecorder::<FullPrecisionSettings>::new().record(record, ())
+            }
+            RecordFormat::Bin => BinBytesRecorder::<FullPrecisionSettings>::new().record(record, ()),
+            RecordFormat::Json => PrettyJsonBytesRecorder.record(record, ()),
+        };
+        let bytes = bytes.expect("Model weights should serialize");
+        match self {
+            RecordFormat::NamedMpkGz => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(&bytes).and_then(|_| encoder.finish()).expect("Writing to memory should not fail")
+            }
+            _ => bytes,
+        }
+    }
+
+    /// Loads weights serialized by [`RecordFormat::encode`] into `model`.
+    pub fn decode<B: Backend>(self, model: Model<B>, bytes: &[u8], device: &B::Device) -> Result<Model<B>, RecorderError> {
+        let mut bytes = bytes.to_vec();
+        if self == RecordFormat::NamedMpkGz {
+            let mut decompressed = Vec::new();
+            GzDecoder::new(bytes.as_slice())
+                .read_to_end(&mut decompressed)
+                .map_err(|err| RecorderError::DeserializeError(err.to_string()))?;
+            bytes = decompressed;
+        }
+        let record = match self {
+            RecordFormat::Compact => NamedMpkBytesRecorder::<HalfPrecisionSettings>::new().load(bytes, device)?,
+            RecordFormat::NamedMpk | RecordFormat::NamedMpkGz => {
+                NamedMpkBytesRecorder::<FullPrecisionSettings>::new().load(bytes, device)?
+            }
+            // Checked first: burn's bincode reader panics on bytes of another format
+            RecordFormat::Bin if bin_float_type(&bytes) == Some("f32") => {
+                BinBytesRecorder::<FullPrecisionSettings>::new().load(bytes, device)?
+            }
+            RecordFormat::Bin => return Err(RecorderError::DeserializeError("not f32 bincode weights".to_string())),
+            RecordFormat::Json => PrettyJsonBytesRecorder.load(bytes, device)?,
+        };
+        Ok(model.load_record(record))
+    }
+}
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+// The float type of a named MessagePack record, the first field of its metadata:
+// `{"metadata": {"float": ...`
+fn mpk_float_type(bytes: &[u8]) -> Option<&str> {
+    // A fixmap, fixstr key "metadata", a fixmap of 5 entries, fixstr key "float"
+    if !(0x81..=0x8f).contains(bytes.first()?) {
+        return None;
+    }
+    let prefix = [&[0xa8][..], b"metadata", &[0x85, 0xa5], b"float"].concat();
+    let rest = bytes[1..].strip_prefix(prefix.as_slice())?;
+    let (len, rest) = match *rest.first()? {
+        marker @ 0xa0..=0xbf => ((marker & 0x1f) as usize, &rest[1..]),
+        0xd9 => (*rest.get(1)? as usize, &rest[2..]),
+        _ => return None,
+    };
+    std::str::from_utf8(rest.get(..len)?).ok()
+}
+
+// The float type of a bincode record, its first field: a string prefixed by its varint length
+fn bin_float_type(bytes: &[u8]) -> Option<&str> {
+    // Lengths below 251 are a single byte, and no float type name is longer
+    let len = *bytes.first()? as usize;
+    if len >= 251 {
+        return None;
+    }
+    std::str::from_utf8(bytes.get(1..1 + len)?).ok()
+}
+
+// `PrettyJsonFileRecorder` in memory: burn has no bytes recorder for JSON
+#[derive(Debug, Default, Clone)]
+struct PrettyJsonBytesRecorder;
+
+impl<B: Backend> Recorder<B> for PrettyJsonBytesRecorder {
+    type Settings = FullPrecisionSettings;
+    type RecordArgs = ();
+    type RecordOutput = Vec<u8>;
+    type LoadArgs = Vec<u8>;
+
+    fn save_item<I: Serialize>(&self, item: I, _args: ()) -> Result<Vec<u8>, RecorderError> {
+        serde_json::to_vec_pretty(&item).map_err(|err| RecorderError::Unknown(err.to_string()))
+    }
+
+    fn load_item<I: DeserializeOwned>(&self, args: Vec<u8>) -> Result<I, RecorderError> {
+        serde_json::from_slice(&args).map_err(|err| RecorderError::DeserializeError(err.to_string()))
+    }
+}
+
+impl std::str::FromStr for RecordFormat {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "compact" => Ok(RecordFormat::Compact),
+            "named-mpk" => Ok(RecordFormat::NamedMpk),
+            "named-mpk-gz" => Ok(RecordFormat::NamedMpkGz),
+            "bin" => Ok(RecordFormat::Bin),
+            "json" => Ok(RecordFormat::Json),
+            _ => Err(format!("unknown record format {name:?}, expected compact, named-mpk, named-mpk-gz, bin or json")),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConvertError {
+    // The output dir is the input dir, which converting would wipe
+    SameDir(String),
+    Load(LoadError),
+    Io(io::Error),
+    Record(RecorderError),
+}
+
+impl fmt::Display for ConvertError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConvertError::SameDir(dir) => write!(f, "cannot convert {dir} into itself, pick another output dir"),
+            ConvertError::Load(err) => write!(f, "cannot convert the model: {err}"),
+            ConvertError::Io(err) => write!(f, "could not write the converted model: {err}"),
+            ConvertError::Record(err) => write!(f, "could not read or save the model weights: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for ConvertError {}
+
+impl From<io::Error> for ConvertError {
+    fn from(err: io::Error) -> Self {
+        ConvertError::Io(err)
+    }
+}
+
+impl From<RecorderError> for ConvertError {
+    fn from(err: RecorderError) -> Self {
+        ConvertError::Record(err)
+    }
+}
+
+/// Reads the `model` weights of `input_dir`, saved with `input_format`, and saves them with
+/// `output_format` into the `output_dir` artifact dir (wiped first), along with the config,
+/// class names and metadata, whose `recorder` then names the new format. Returns the model.
+///
+/// Every parameter is kept exactly, except when converting into [`RecordFormat::Compact`] from
+/// a full precision format: the floats are then rounded to f16.
+pub fn convert_record<B: Backend>(
+    input_dir: &str,
+    input_format: RecordFormat,
+    output_dir: &str,
+    output_format: RecordFormat,
+    device: &B::Device,
+) -> Result<Model<B>, ConvertError> {
+    let same_dir = match (fs::canonicalize(input_dir), fs::canonicalize(output_dir)) {
+        (Ok(input), Ok(output)) => input == output,
+        _ => Path::new(input_dir) == Path::new(output_dir),
+    };
+    if same_dir {
+        return Err(ConvertError::SameDir(input_dir.to_string()));
+    }
+
+    let config = TrainingConfig::load(format!("{input_dir}/config.json"))
+        .map_err(|err| ConvertError::Load(LoadError::Config(err.to_string())))?;
+    let meta = ModelMeta::load(input_dir).map_err(ConvertError::Load)?;
+    if let Some(meta) = &meta {
+        if meta.recorder != input_format.recorder_name() {
+            return Err(ConvertError::Load(LoadError::Incompatible {
+                field: "recorder".to_string(),
+                meta: meta.recorder.clone(),
+                config: input_format.recorder_name().to_string(),
+            }));
+        }
+    }
+    // Not `resolve_model_config`, whose check also rejects converted artifacts: it only accepts
+    // weights saved with `CompactRecorder`
+    let model_config = meta.as_ref().map_or_else(|| config.model.clone(), |meta| meta.model.clone());
+    let labels = ClassLabels::load(input_dir);
+    let model = input_format.load(model_config.init::<B>(device), &format!("{input_dir}/model"), device)?;
+
+    create_artifact_dir(output_dir)?;
+    config.save(format!("{output_dir}/config.json"))?;
+    labels.save(output_dir)?;
+    // Cloning a module only bumps tensor reference counts
+    output_format.save(model.clone(), &format!("{output_dir}/model"))?;
+    if let Some(mut meta) = meta {
+        meta.recorder = output_format.recorder_name().to_string();
+        meta.save(output_dir)?;
+    }
+    Ok(model)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{data::MnistBatcher, inference::load_model, synthetic::SyntheticDigits, ModelConfig};
+    use burn::{
+        backend::{ndarray::NdArrayDevice, NdArray},
+        data::{dataloader::batcher::Batcher, dataset::Dataset},
+    };
+
+    #[test]
+    fn half_precision_weights_reload_close_to_full_precision() {
+        let device = NdArrayDevice::default();
+        let config = ModelConfig::new(10, 16);
+        let model = config.init::<NdArray>(&device);
+        let dir = std::env::temp_dir().join("my_first_rust_DL_app-convert-half");
+        fs::create_dir_all(&dir).unwrap();
+        let half_path = dir.join("half").to_str().unwrap().to_string();
+        let full_path = dir.join("full").to_str().unwrap().to_string();
+        RecordFormat::Compact.save(model.clone(), &half_path).unwrap();
+        RecordFormat::NamedMpk.save(model.clone(), &full_path).unwrap();
+
+        let half_size = fs::metadata(format!("{half_path}.mpk")).unwrap().len() as f64;
+        let full_size = fs::metadata(format!("{full_path}.mpk")).unwrap().len() as f64;
+        let reloaded = RecordFormat::Compact.load(config.init::<NdArray>(&device), &half_path, &device).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        // MessagePack tags every value with a byte: 3 bytes per f16 against 5 per f32
+        let ratio = half_size / full_size;
+        assert!((0.5..0.65).contains(&ratio), "half precision weights are {ratio} of the full size");
+
+        let digits = SyntheticDigits::new(32, 1);
+        let items = (0..digits.len()).filter_map(|index| digits.get(index)).collect();
+        let images = MnistBatcher::<NdArray>::new(device).batch(items).images;
+        let expected = model.forward(images.clone());
+        let max_error = (reloaded.forward(images) - expected.clone()).abs().max().into_scalar();
+        let scale = expected.abs().max().into_scalar();
+        assert!(max_error <= 1e-2 * scale.max(1.0), "logits are off by up to {max_error}");
+    }
+
+    #[test]
+    fn converted_records_predict_exactly_like_the_original() {
+        let device = NdArrayDevice::default();
+        let root = std::env::temp_dir().join("my_first_rust_DL_app-convert-round-trip");
+        let dir = |name: &str| root.join(name).to_str().unwrap().to_string();
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(dir("compact")).unwrap();
+        let config = TrainingConfig::new(ModelConfig::new(10, 16), burn::optim::AdamConfig::new());
+        config.save(format!("{}/config.json", dir("compact"))).unwrap();
+        let model = config.model.init::<NdArray>(&device);
+        RecordFormat::Compact.save(model, &format!("{}/model", dir("compact"))).unwrap();
+
+        let original = load_model::<NdArray>(&dir("compact"), &device).unwrap();
+        let convert = |from: &str, from_format, to: &str, to_format| {
+            convert_record::<NdArray>(&dir(from), from_format, &dir(to), to_format, &device)
+        };
+        convert("compact", RecordFormat::Compact, "named", RecordFormat::NamedMpk).unwrap();
+        convert("named", RecordFormat::NamedMpk, "bin", RecordFormat::Bin).unwrap();
+        let converted = convert("bin", RecordFormat::Bin, "back", RecordFormat::Compact).unwrap();
+        let reloaded = load_model::<NdArray>(&dir("back"), &device).unwrap();
+        let same_dir = convert("bin", RecordFormat::Bin, "bin", RecordFormat::Json);
+        let bin_exists = Path::new(&format!("{}/model.bin", dir("bin"))).exists();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert!(bin_exists);
+        assert!(matches!(same_dir, Err(ConvertError::SameDir(_))));
+        let digits = SyntheticDigits::new(16, 1);
+        let items = (0..digits.len()).filter_map(|index| digits.get(index)).collect();
+        let images = MnistBatcher::<NdArray>::new(device).batch(items).images;
+        let expected = original.forward(images.clone()).into_data();
+        assert_eq!(converted.forward(images.clone()).into_data(), expected);
+        assert_eq!(reloaded.forward(images).into_data(), expected);
+    }
+}
@@ -0,0 +1,272 @@
+use crate::{
+    checkpoint::LoadError,
+    evaluation::{dataset_predictions, outcome_accuracy, relabel_binary},
+    inference::{load_model, rotate_and_shift, RawImage},
+    meta::ModelMeta,
+    model::Model,
+};
+use burn::{
+    data::dataset::{vision::MnistDataset, vision::MnistItem, Dataset},
+    prelude::*,
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rand_distr::{Distribution, Normal};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+// Seed of the noise and occlusion positions, combined with the index of each test image so that
+// every image always gets the same corruption, however it is batched
+const CORRUPTION_SEED: u64 = 42;
+
+// Severities every corruption is evaluated at, 1 the mildest
+pub const SEVERITIES: [usize; 5] = [1, 2, 3, 4, 5];
+
+/// A systematic corruption of the test images, in the style of MNIST-C. Each is applied at the
+/// fixed strength of a severity (see [`CorruptionKind::strength`]) rather than a random one.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CorruptionKind {
+    // Additive Gaussian noise on the pixels
+    GaussianNoise,
+    // Gaussian blur
+    Blur,
+    // Rotation around the image center, as in test-time augmentation
+    Rotation,
+    // The same value added to every pixel
+    Brightness,
+    // A square of background over the digit
+    Occlusion,
+}
+
+impl CorruptionKind {
+    pub const ALL: [CorruptionKind; 5] = [
+        CorruptionKind::GaussianNoise,
+        CorruptionKind::Blur,
+        CorruptionKind::Rotation,
+        CorruptionKind::Brightness,
+        CorruptionKind::Occlusion,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CorruptionKind::GaussianNoise => "gaussian_noise",
+            CorruptionKind::Blur => "blur",
+            CorruptionKind::Rotation => "rotation",
+            CorruptionKind::Brightness => "brightness",
+            CorruptionKind::Occlusion => "occlusion",
+        }
+    }
+
+    /// What `severity` (1 to 5) sets: the noise standard deviation and the brightness shift in
+    /// pixel values (of 255), the blur standard deviation in pixels, the rotation in degrees and
+    /// the side of the occluding square in pixels.
+    pub fn strength(self, severity: usize) -> f32 {
+        let level = severity.clamp(1, SEVERITIES.len()) as f32;
+        match self {
+            CorruptionKind::GaussianNoise => 25.0 * level,
+            CorruptionKind::Blur => 0.5 * level,
+            CorruptionKind::Rotation => 10.0 * level,
+            CorruptionKind::Brightness => 30.0 * level,
+            CorruptionKind::Occlusion => 2.0 * level + 4.0,
+        }
+    }
+
+    /// `image` (pixel values in [0, 255]) corrupted at `severity`. The noise and the position of
+    /// the occlusion are drawn from `rng`, the other corruptions do not use it.
+    pub fn apply(self, image: &RawImage, severity: usize, rng: &mut impl Rng) -> RawImage {
+        let strength = self.strength(severity);
+        match self {
+            CorruptionKind::GaussianNoise => {
+                let noise = Normal::new(0.0, strength).expect("Noise strength should be finite and >= 0");
+                image.map(|row| row.map(|value| (value + noise.sample(rng)).clamp(0.0, 255.0)))
+            }
+            CorruptionKind::Blur => blur(image, strength),
+            CorruptionKind::Rotation => rotate_and_shift(image, strength, 0.0, 0.0),
+            CorruptionKind::Brightness => image.map(|row| row.map(|value| (value + strength).min(255.0))),
+            CorruptionKind::Occlusion => {
+                let side = strength as usize;
+                let (top, left) = (rng.gen_range(0..=28 - side), rng.gen_range(0..=28 - side));
+                let mut image = *image;
+                for row in &mut image[top..top + side] {
+                    row[left..left + side].fill(0.0);
+                }
+                image
+            }
+        }
+    }
+}
+
+impl fmt::Display for CorruptionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl std::str::FromStr for CorruptionKind {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let name = name.to_ascii_lowercase().replace('-', "_");
+        CorruptionKind::ALL.into_iter().find(|kind| kind.name() == name).ok_or_else(|| {
+            let names: Vec<&str> = CorruptionKind::ALL.iter().map(|kind| kind.name()).collect();
+            format!("unknown corruption {name:?}, expected one of {}", names.join(", "))
+        })
+    }
+}
+
+// Separable Gaussian blur of standard deviation `sigma` pixels, the canvas surrounded by
+// background
+fn blur(image: &RawImage, sigma: f32) -> RawImage {
+    let radius = (3.0 * sigma).ceil() as isize;
+    let weights: Vec<f32> =
+        (-radius..=radius).map(|offset| (-((offset * offset) as f32) / (2.0 * sigma * sigma)).exp()).collect();
+    let total: f32 = weights.iter().sum();
+    let weights: Vec<f32> = weights.into_iter().map(|weight| weight / total).collect();
+
+    let pass = |image: &RawImage, horizontal: bool| -> RawImage {
+        let mut output = [[0.0; 28]; 28];
+        for (y, row) in output.iter_mut().enumerate() {
+            for (x, value) in row.iter_mut().enumerate() {
+                *value = (-radius..=radius)
+                    .zip(&weights)
+                    .filter_map(|(offset, weight)| {
+                        let (sx, sy) = match horizontal {
+                            true => (x as isize + offset, y as isize),
+                            false => (x as isize, y as isize + offset),
+                        };
+                        let in_canvas = (0..28).contains(&sx) && (0..28).contains(&sy);
+                        in_canvas.then(|| image[sy as usize][sx as usize] * weight)
+                    })
+                    .sum();
+            }
+        }
+        output
+    };
+    pass(&pass(image, true), false)
+}
+
+// The items of a dataset with `kind` applied at `severity`, each from its own seed
+struct CorruptedDataset<'a, D> {
+    inner: &'a D,
+    kind: CorruptionKind,
+    severity: usize,
+}
+
+impl<D: Dataset<MnistItem>> Dataset<MnistItem> for CorruptedDataset<'_, D> {
+    fn get(&self, index: usize) -> Option<MnistItem> {
+        let item = self.inner.get(index)?;
+        let mut rng = StdRng::seed_from_u64(CORRUPTION_SEED ^ index as u64);
+        Some(MnistItem { image: self.kind.apply(&item.image, self.severity, &mut rng), ..item })
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+}
+
+// Test accuracy under one corruption, by severity, with `strength` for reference
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CorruptionRow {
+    // `clean` for the baseline row, whose only severity is 0
+    pub corruption: String,
+    pub severities: Vec<usize>,
+    pub strengths: Vec<f32>,
+    pub accuracies: Vec<f32>,
+    // Mean error (1 - accuracy) over the severities
+    pub mean_error: f32,
+}
+
+impl CorruptionRow {
+    fn new(corruption: String, cells: Vec<(usize, f32, f32)>) -> Self {
+        let mean_error = cells.iter().map(|&(_, _, accuracy)| 1.0 - accuracy).sum::<f32>() / cells.len().max(1) as f32;
+        Self {
+            corruption,
+            severities: cells.iter().map(|&(severity, _, _)| severity).collect(),
+            strengths: cells.iter().map(|&(_, strength, _)| strength).collect(),
+            accuracies: cells.iter().map(|&(_, _, accuracy)| accuracy).collect(),
+            mean_error,
+        }
+    }
+}
+
+/// Content of `robustness.json`, as returned by [`evaluate_corruptions`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RobustnessReport {
+    pub num_samples: usize,
+    // The clean test set first, then one row per corruption evaluated
+    pub rows: Vec<CorruptionRow>,
+    // Mean error over every corruption and severity, to compare with the error of the clean row
+    pub mean_corruption_error: f32,
+}
+
+impl fmt::Display for RobustnessReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "| {:<14} |", "Corruption")?;
+        for severity in SEVERITIES {
+            write!(f, " {:>7} |", format!("s{severity}"))?;
+        }
+        writeln!(f, " {:>10} |", "Mean error")?;
+        writeln!(f, "|----------------|{}------------|", "---------|".repeat(SEVERITIES.len()))?;
+        for row in &self.rows {
+            write!(f, "| {:<14} |", row.corruption)?;
+            for position in 0..SEVERITIES.len() {
+                // The clean row has the same accuracy under every column
+                let accuracy = row.accuracies.get(position).or(row.accuracies.first());
+                write!(f, " {:>6.2}% |", accuracy.map_or(0.0, |accuracy| accuracy * 100.0))?;
+            }
+            writeln!(f, " {:>9.2}% |", row.mean_error * 100.0)?;
+        }
+        write!(f, "Mean corruption error: {:.2}%", self.mean_corruption_error * 100.0)
+    }
+}
+
+// Accuracy of `model` on `dataset`, single-label or one-vs-rest as `evaluate` scores it
+fn accuracy_on<B: Backend, D: Dataset<MnistItem>>(
+    model: &Model<B>,
+    dataset: &D,
+    binary_target: Option<usize>,
+    device: &B::Device,
+) -> f32 {
+    outcome_accuracy(&relabel_binary(dataset_predictions(model, dataset, device), binary_target))
+}
+
+/// Evaluates the model trained in `artifact_dir` on the clean test set, then on every one of
+/// `corruptions` at every severity, and writes the accuracies and the mean corruption error to
+/// `robustness.json` there. No corruptions means all of [`CorruptionKind::ALL`]. The corrupted
+/// images are generated on the fly and always the same.
+pub fn evaluate_corruptions<B: Backend>(
+    artifact_dir: &str,
+    device: &B::Device,
+    corruptions: &[CorruptionKind],
+) -> Result<RobustnessReport, LoadError> {
+    let model = load_model::<B>(artifact_dir, device)?;
+    let binary_target = ModelMeta::load(artifact_dir)?.and_then(|meta| meta.binary_target);
+    let dataset = MnistDataset::test();
+
+    let clean = accuracy_on(&model, &dataset, binary_target, device);
+    let mut rows = vec![CorruptionRow::new("clean".to_string(), vec![(0, 0.0, clean)])];
+    let corruptions = if corruptions.is_empty() { &CorruptionKind::ALL[..] } else { corruptions };
+    for &kind in corruptions {
+        let cells = SEVERITIES
+            .iter()
+            .map(|&severity| {
+                let corrupted = CorruptedDataset { inner: &dataset, kind, severity };
+                (severity, kind.strength(severity), accuracy_on(&model, &corrupted, binary_target, device))
+            })
+            .collect();
+        rows.push(CorruptionRow::new(kind.name().to_string(), cells));
+    }
+
+    let corrupted = &rows[1..];
+    let report = RobustnessReport {
+        num_samples: dataset.len(),
+        mean_corruption_error: corrupted.iter().map(|row| row.mean_error).sum::<f32>() / corrupted.len().max(1) as f32,
+        rows,
+    };
+
+    let json = serde_json::to_string_pretty(&report).expect("Report should serialize");
+    std::fs::write(format!("{artifact_dir}/robustness.json"), json)
+        .expect("Robustness report should be saved successfully!");
+
+    Ok(report)
+}
@@ -0,0 +1,223 @@
+use crate::{
+    checkpoint::LoadError,
+    data::{ClassificationDataset, ClassificationItem, MnistBatch, MnistBatcher},
+    inference::load_model,
+    split::SubsetDataset,
+};
+use burn::{
+    data::dataloader::{batcher::Batcher, DataLoader, DataLoaderIterator, Progress},
+    prelude::*,
+    tensor::activation::log_softmax,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Number of training images scored at once by the pretrained model
+const SCORE_BATCH_SIZE: usize = 256;
+
+// Pixels brighter than this (on the 0-255 scale) count as ink for `CurriculumScore::PixelCount`
+const INK_THRESHOLD: f32 = 127.5;
+
+/// How the difficulty of a training sample is measured; lower scores are served first.
+#[derive(Config, Debug, PartialEq)]
+pub enum CurriculumScore {
+    // Number of ink pixels: thin, simple strokes come before heavy or noisy ones
+    PixelCount,
+    // Cross-entropy of the sample under the model saved in `artifact_dir`
+    PretrainedLoss { artifact_dir: String },
+}
+
+/// Curriculum learning: serve the training samples from easy to hard for the first
+/// `num_epochs` epochs, then go back to the usual shuffling.
+#[derive(Config, Debug, PartialEq)]
+pub struct CurriculumConfig {
+    pub score: CurriculumScore,
+    #[config(default = 1)]
+    pub num_epochs: usize,
+}
+
+// The scores of a curriculum and the order they give, saved as `curriculum.json`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CurriculumOrder {
+    // Training set indices in the order they are served, easiest first
+    pub order: Vec<usize>,
+    // Score of every training sample, indexed like the training set. Unreadable samples score
+    // infinity (written as null) and come last.
+    pub scores: Vec<f64>,
+}
+
+impl CurriculumOrder {
+    // Sorts by ascending score; equal scores keep their dataset order
+    pub fn from_scores(scores: Vec<f64>) -> Self {
+        let mut order: Vec<usize> = (0..scores.len()).collect();
+        order.sort_by(|&a, &b| scores[a].total_cmp(&scores[b]));
+        Self { order, scores }
+    }
+}
+
+fn pixel_counts<D: ClassificationDataset>(dataset: &D) -> Vec<f64> {
+    (0..dataset.len())
+        .map(|index| match dataset.get(index) {
+            Some((pixels, _)) => pixels.iter().filter(|&&pixel| pixel > INK_THRESHOLD).count() as f64,
+            None => f64::INFINITY,
+        })
+        .collect()
+}
+
+fn pretrained_losses<B: Backend, D: ClassificationDataset>(
+    dataset: &D,
+    artifact_dir: &str,
+    device: &B::Device,
+) -> Result<Vec<f64>, LoadError> {
+    let model = load_model::<B>(artifact_dir, device)?;
+    let batcher = MnistBatcher::<B>::new(device.clone());
+    let mut scores = vec![f64::INFINITY; dataset.len()];
+
+    for start in (0..dataset.len()).step_by(SCORE_BATCH_SIZE) {
+        let end = usize::min(start + SCORE_BATCH_SIZE, dataset.len());
+        let (indices, items): (Vec<usize>, Vec<ClassificationItem>) = (start..end)
+            .filter_map(|index| {
+                let (pixels, label) = dataset.get(index)?;
+                Some((index, ClassificationItem { pixels, shape: dataset.image_shape(), label, index: Some(index) }))
+            })
+            .unzip();
+        if items.is_empty() {
+            continue;
+        }
+
+        let batch = batcher.batch(items);
+        let num_items = indices.len();
+        let losses = log_softmax(model.forward(batch.images), 1)
+            .gather(1, batch.targets.reshape([num_items, 1]))
+            .neg()
+            .into_data()
+            .convert::<f32>()
+            .value;
+        for (index, loss) in indices.into_iter().zip(losses) {
+            scores[index] = loss as f64;
+        }
+    }
+    Ok(scores)
+}
+
+// Scores every sample of `dataset`, indexed like the dataset
+pub fn score_samples<B: Backend, D: ClassificationDataset>(
+    dataset: &D,
+    score: &CurriculumScore,
+    device: &B::Device,
+) -> Result<Vec<f64>, LoadError> {
+    match score {
+        CurriculumScore::PixelCount => Ok(pixel_counts(dataset)),
+        CurriculumScore::PretrainedLoss { artifact_dir } => {
+            pretrained_losses::<B, D>(dataset, artifact_dir, device)
+        }
+    }
+}
+
+// Serves `ordered` front to back, unshuffled, for the first `curriculum_epochs` epochs and
+// defers to `shuffled` afterwards. Each call to `iter` is one epoch. Ordered epochs are cut into
+// `num_batches` near-equal batches, as many as the shuffled loader yields, so that schedules
+// stepped per iteration line up the same way either way.
+pub struct CurriculumDataLoader<B: Backend, D> {
+    shuffled: Box<dyn DataLoader<MnistBatch<B>>>,
+    ordered: SubsetDataset<D>,
+    batcher: MnistBatcher<B>,
+    num_batches: usize,
+    curriculum_epochs: usize,
+    epoch: AtomicUsize,
+}
+
+impl<B: Backend, D> CurriculumDataLoader<B, D> {
+    pub fn new(
+        shuffled: Box<dyn DataLoader<MnistBatch<B>>>,
+        ordered: SubsetDataset<D>,
+        batcher: MnistBatcher<B>,
+        num_batches: usize,
+        curriculum_epochs: usize,
+    ) -> Self {
+        Self { shuffled, ordered, batcher, num_batches, curriculum_epochs, epoch: AtomicUsize::new(0) }
+    }
+}
+
+struct OrderedIterator<'a, B: Backend, D> {
+    loader: &'a CurriculumDataLoader<B, D>,
+    batch: usize,
+}
+
+impl<B: Backend, D: ClassificationDataset> Iterator for OrderedIterator<'_, B, D> {
+    type Item = MnistBatch<B>;
+
+    fn next(&mut self) -> Option<MnistBatch<B>> {
+        let (dataset, num_batches) = (&self.loader.ordered, self.loader.num_batches);
+        while self.batch < num_batches {
+            let start = self.batch * dataset.len() / num_batches;
+            let end = (self.batch + 1) * dataset.len() / num_batches;
+            self.batch += 1;
+
+            let items: Vec<ClassificationItem> = (start..end)
+                .filter_map(|index| {
+                    let (pixels, label) = dataset.get(index)?;
+                    let index = Some(dataset.indices()[index]);
+                    Some(ClassificationItem { pixels, shape: dataset.image_shape(), label, index })
+                })
+                .collect();
+            if !items.is_empty() {
+                return Some(self.loader.batcher.batch(items));
+            }
+        }
+        None
+    }
+}
+
+impl<B: Backend, D: ClassificationDataset> DataLoaderIterator<MnistBatch<B>> for OrderedIterator<'_, B, D> {
+    fn progress(&self) -> Progress {
+        let len = self.loader.ordered.len();
+        Progress::new(self.batch * len / self.loader.num_batches.max(1), len)
+    }
+}
+
+impl<B: Backend, D: ClassificationDataset + Send + Sync> DataLoader<MnistBatch<B>>
+    for CurriculumDataLoader<B, D>
+{
+    fn iter<'a>(&'a self) -> Box<dyn DataLoaderIterator<MnistBatch<B>> + 'a> {
+        if self.epoch.fetch_add(1, Ordering::Relaxed) < self.curriculum_epochs {
+            Box::new(OrderedIterator { loader: self, batch: 0 })
+        } else {
+            self.shuffled.iter()
+        }
+    }
+
+    fn num_items(&self) -> usize {
+        self.shuffled.num_items()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{data::boxed_mnist_dataloader, synthetic::SyntheticDigits};
+    use burn::backend::{ndarray::NdArrayDevice, NdArray};
+    use std::sync::Arc;
+
+    #[test]
+    fn first_curriculum_batch_holds_the_lowest_scores() {
+        let device = NdArrayDevice::default();
+        let digits = SyntheticDigits::new(40, 1);
+        let scores = score_samples::<NdArray, _>(&digits, &CurriculumScore::PixelCount, &device).unwrap();
+        let order = CurriculumOrder::from_scores(scores.clone());
+        let batcher = MnistBatcher::<NdArray>::new(device);
+        let shuffled = boxed_mnist_dataloader(batcher.clone(), digits.clone(), 10, 0, 1, 0);
+        let ordered = SubsetDataset::new(Arc::new(digits), order.order.clone());
+        let loader = CurriculumDataLoader::new(shuffled, ordered, batcher, 4, 1);
+
+        let first = loader.iter().next().unwrap().indices.unwrap();
+        assert_eq!(first, order.order[..10]);
+        let hardest_first = first.iter().map(|&index| scores[index]).fold(f64::NEG_INFINITY, f64::max);
+        let rest = (0..40).filter(|index| !first.contains(index));
+        assert!(rest.map(|index| scores[index]).all(|score| score >= hardest_first));
+        // Past the curriculum epochs, the shuffled loader takes over
+        let batches: Vec<MnistBatch<NdArray>> = loader.iter().collect();
+        assert_eq!(batches.len(), 4);
+        assert_ne!(batches[0].indices.as_deref(), Some(&order.order[..10]));
+    }
+}
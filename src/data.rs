@@ -1,38 +1,357 @@
 
 use burn::{
-    data::{dataloader::batcher::Batcher, dataset::vision::MnistItem},
+    data::{
+        dataloader::{
+            batcher::Batcher, BatchDataLoader, DataLoader, DataLoaderIterator,
+            DynDataLoader, FixBatchStrategy, Progress,
+        },
+        dataset::{
+            transform::PartialDataset,
+            vision::{MnistDataset, MnistItem},
+            Dataset,
+        },
+    },
     prelude::*
 };
+use crate::{soft_labels::SoftLabels, training::LossSettings};
+use rand::{distributions::Standard, rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use rand_distr::{Beta, Distribution};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Read, Write},
+    path::PathBuf,
+    sync::{mpsc, Arc, Mutex, OnceLock},
+    thread,
+};
+
+// Number of distinct labels (digits 0-9) in the MNIST dataset
+pub const MNIST_NUM_CLASSES: usize = 10;
+
+// Batch normalization of the pixels: `(pixel / PIXEL_SCALE - MNIST_MEAN) / MNIST_STD`. The mean
+// and std are those of the official PyTorch example.
+pub const PIXEL_SCALE: f64 = 255.0;
+pub const MNIST_MEAN: f64 = 0.1307;
+pub const MNIST_STD: f64 = 0.3081;
+
+/// A labelled grayscale image dataset that [`train_on`](crate::training::train_on) can learn
+/// from. Every burn dataset of [`MnistItem`]s implements it, as 10 classes of 28x28 images.
+pub trait ClassificationDataset: Send + Sync {
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Row-major pixels of item `index` on the MNIST scale ([0, 255], `height * width` values)
+    /// and its label in `0..num_classes()`.
+    fn get(&self, index: usize) -> Option<(Vec<f32>, usize)>;
+
+    fn num_classes(&self) -> usize;
+
+    /// `[height, width]` of every image.
+    fn image_shape(&self) -> [usize; 2];
+}
+
+impl<D: Dataset<MnistItem>> ClassificationDataset for D {
+    fn len(&self) -> usize {
+        Dataset::len(self)
+    }
+
+    fn get(&self, index: usize) -> Option<(Vec<f32>, usize)> {
+        let item = Dataset::get(self, index)?;
+        Some((item.image.iter().flatten().copied().collect(), item.label as usize))
+    }
+
+    fn num_classes(&self) -> usize {
+        MNIST_NUM_CLASSES
+    }
+
+    fn image_shape(&self) -> [usize; 2] {
+        [28, 28]
+    }
+}
+
+/// One image of a [`ClassificationDataset`], as fed to [`MnistBatcher`].
+#[derive(Clone, Debug)]
+pub struct ClassificationItem {
+    pub pixels: Vec<f32>,
+    pub shape: [usize; 2],
+    pub label: usize,
+    /// Position of the item in the dataset it was read from, when known, so that batches can
+    /// tell which items they hold.
+    pub index: Option<usize>,
+}
+
+impl From<MnistItem> for ClassificationItem {
+    fn from(item: MnistItem) -> Self {
+        Self {
+            pixels: item.image.iter().flatten().copied().collect(),
+            shape: [28, 28],
+            label: item.label as usize,
+            index: None,
+        }
+    }
+}
+
+// A classification dataset seen as a burn dataset, so that it can go through a dataloader
+struct ClassificationItems<D>(D);
 
+impl<D: ClassificationDataset> Dataset<ClassificationItem> for ClassificationItems<D> {
+    fn get(&self, index: usize) -> Option<ClassificationItem> {
+        let (pixels, label) = self.0.get(index)?;
+        Some(ClassificationItem { pixels, shape: self.0.image_shape(), label, index: Some(index) })
+    }
+
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+}
+
+/// Turns MNIST (or any [`ClassificationDataset`]) items into normalized image and label
+/// tensors on `device`.
 #[derive(Clone)]
 pub struct MnistBatcher<B: Backend>{
     device: B::Device,
+    mixing: Option<Mixing>,
+    // The targets of `with_soft_labels` and the weight of the hard labels mixed in
+    soft_labels: Option<(Arc<SoftLabels>, f32)>,
+    // Handed to the steps with every batch, see `with_loss`
+    loss: LossSettings,
+}
+
+// Mixup and CutMix settings of a training batcher. The rng is shared by the clones burn hands to
+// each dataloader worker.
+#[derive(Clone)]
+struct Mixing {
+    mixup: Option<Beta<f64>>,
+    cutmix: Option<Beta<f64>>,
+    num_classes: usize,
+    rng: Arc<Mutex<StdRng>>,
 }
 
 impl<B: Backend> MnistBatcher<B> {
     pub fn new(device: B::Device) -> Self {
-        Self { device }
+        Self { device, mixing: None, soft_labels: None, loss: LossSettings::default() }
+    }
+
+    // The mixing settings, created seeded from `seed` by the first of `with_mixup` and
+    // `with_cutmix`
+    fn mixing(&mut self, num_classes: usize, seed: u64) -> &mut Mixing {
+        self.mixing.get_or_insert_with(|| Mixing {
+            mixup: None,
+            cutmix: None,
+            num_classes,
+            rng: Arc::new(Mutex::new(StdRng::seed_from_u64(seed))),
+        })
+    }
+
+    // Blends every batch with a shuffled copy of itself (mixup): images and one-hot labels are
+    // mixed with a weight drawn from Beta(alpha, alpha) per batch, and the blended labels are
+    // returned as `soft_targets` over `num_classes` classes. Only meant for training batches.
+    pub fn with_mixup(mut self, alpha: f64, num_classes: usize, seed: u64) -> Self {
+        let beta = Beta::new(alpha, alpha).expect("Mixup alpha should be finite and > 0");
+        self.mixing(num_classes, seed).mixup = Some(beta);
+        self
+    }
+
+    // Pastes a rectangle of a shuffled copy of every batch onto it (CutMix), the rectangle
+    // covering a share 1 - lambda of the image for lambda drawn from Beta(alpha, alpha) per
+    // batch. The labels are mixed by the area actually pasted, see `cutmix_box`. With mixup
+    // also set, each batch gets one or the other, on a coin flip.
+    pub fn with_cutmix(mut self, alpha: f64, num_classes: usize, seed: u64) -> Self {
+        let beta = Beta::new(alpha, alpha).expect("CutMix alpha should be finite and > 0");
+        self.mixing(num_classes, seed).cutmix = Some(beta);
+        self
+    }
+
+    // Attaches to every batch the rows of `soft_labels` of its items, found by their dataset
+    // index, for `Model::forward_soft` with `hard_weight`. Only meant for training batches, and
+    // not together with mixup or CutMix, which mix the hard labels.
+    pub fn with_soft_labels(mut self, soft_labels: Arc<SoftLabels>, hard_weight: f32) -> Self {
+        self.soft_labels = Some((soft_labels, hard_weight));
+        self
+    }
+
+    // Attaches `loss` to every batch, for the train and validation steps to compute the loss of
+    // the batch with. The default is the batch mean of the class loss alone.
+    pub fn with_loss(mut self, loss: LossSettings) -> Self {
+        self.loss = loss;
+        self
+    }
+
+    pub(crate) fn loss(&self) -> LossSettings {
+        self.loss
     }
 }
 
+/// The CutMix rectangle `[top, left, bottom, right)` of an image of `shape` ([height, width]),
+/// for the drawn `lambda` and the rectangle center `center` ([y, x], anywhere in the image): its
+/// sides are those of the image scaled by sqrt(1 - lambda), clipped to the image borders.
+pub fn cutmix_box(shape: [usize; 2], lambda: f64, center: [usize; 2]) -> [usize; 4] {
+    let ratio = (1.0 - lambda).clamp(0.0, 1.0).sqrt();
+    let [(top, bottom), (left, right)] = [0, 1].map(|axis| {
+        let side = (shape[axis] as f64 * ratio).round() as usize;
+        let start = center[axis].saturating_sub(side / 2);
+        (start.min(shape[axis]), (center[axis] + side - side / 2).min(shape[axis]))
+    });
+    [top, left, bottom, right]
+}
+
+/// The label weight of the image a `cutmix_box` rectangle is pasted onto: 1 minus the share of
+/// the image the rectangle covers, once clipped.
+pub fn cutmix_lambda(shape: [usize; 2], [top, left, bottom, right]: [usize; 4]) -> f64 {
+    let area = (bottom - top) * (right - left);
+    1.0 - area as f64 / (shape[0] * shape[1]).max(1) as f64
+}
+
+/// A batch of normalized images `[batch_size, height, width]` (28x28 for MNIST) and their
+/// labels `[batch_size]`.
 #[derive(Clone, Debug)]
 pub struct MnistBatch<B: Backend> {
     pub images: Tensor<B, 3>,
     pub targets: Tensor<B, 1, Int>,
+    /// Label distributions `[batch_size, num_classes]` of mixup and CutMix batches, `None`
+    /// otherwise. `targets` then holds the label of the dominant image of each mixed pair.
+    pub soft_targets: Option<Tensor<B, 2>>,
+    /// Teacher distributions of the items, on training batches when `soft_labels` is set.
+    pub soft_labels: Option<SoftLabelBatch<B>>,
+    /// Holdout images the validation step scores along with this batch, on the first batch of
+    /// each epoch when a holdout directory is configured.
+    pub holdout: Option<Box<MnistBatch<B>>>,
+    /// Preview samples the validation step predicts along with this batch, on the first batch
+    /// of each epoch when `preview_samples` is set.
+    pub preview: Option<Box<MnistBatch<B>>>,
+    /// Dataset indices of the items, in batch order, when every item knows its own.
+    pub indices: Option<Vec<usize>>,
+    /// How the steps compute the loss of the batch: the settings of the training config on the
+    /// batches of a run.
+    pub loss: LossSettings,
 }
 
-impl<B: Backend> Batcher<MnistItem, MnistBatch<B>> for MnistBatcher<B> {
-    fn batch(&self, items: Vec<MnistItem>) -> MnistBatch<B> {
-        let images: Vec<Tensor<B, 3>> = items // Takes items from the Vec<MnistItem>
-            .iter() // Creates an iterator over the items
-            .map(| item | Data::<f32, 2>::from(item.image)) // For each item, convert the image to float32 data struct
-            .map(| data | Tensor::<B, 2>::from_data(data.convert(), &self.device)) // for each data struct, create a tensor on the device
-            .map(| tensor | tensor.reshape([1, 28, 28])) // For each tensor, reshape to the dimensions [C, H, W]
+/// The [`SoftLabels`] rows of a batch, as [`Model::forward_soft`](crate::Model::forward_soft)
+/// takes them.
+#[derive(Clone, Debug)]
+pub struct SoftLabelBatch<B: Backend> {
+    /// Distributions `[batch_size, num_classes]`
+    pub probabilities: Tensor<B, 2>,
+    /// Share of the hard-label cross-entropy in the loss, the rest being the KL divergence
+    pub hard_weight: f32,
+}
+
+// Mixes each item with a random partner of the same batch, by mixup or CutMix. Returns the
+// mixed items, labelled with their dominant image, and the mixed label distributions.
+fn mix_items(items: Vec<ClassificationItem>, mixing: &Mixing) -> (Vec<ClassificationItem>, Vec<f32>) {
+    let mut rng = mixing.rng.lock().unwrap();
+    // Mixup blends whole images, CutMix pastes `rectangle`. With both, a coin flip picks one.
+    let (lambda, rectangle) = match (&mixing.mixup, &mixing.cutmix) {
+        (Some(mixup), cutmix) if cutmix.is_none() || rng.gen_bool(0.5) => (mixup.sample(&mut *rng) as f32, None),
+        (_, Some(cutmix)) => {
+            let shape = items.first().map_or([0, 0], |item| item.shape);
+            let drawn = cutmix.sample(&mut *rng);
+            let center = [rng.gen_range(0..shape[0].max(1)), rng.gen_range(0..shape[1].max(1))];
+            let rectangle = cutmix_box(shape, drawn, center);
+            (cutmix_lambda(shape, rectangle) as f32, Some(rectangle))
+        }
+        (_, None) => unreachable!("Mixing is only set with mixup or CutMix"),
+    };
+    let mut partners: Vec<usize> = (0..items.len()).collect();
+    partners.shuffle(&mut *rng);
+
+    let mut soft_targets = vec![0.0; items.len() * mixing.num_classes];
+    let mixed = items
+        .iter()
+        .zip(&partners)
+        .enumerate()
+        .map(|(index, (item, &partner))| {
+            let partner = &items[partner];
+            let pixels = match rectangle {
+                None => item
+                    .pixels
+                    .iter()
+                    .zip(&partner.pixels)
+                    .map(|(&pixel, &other)| lambda * pixel + (1.0 - lambda) * other)
+                    .collect(),
+                Some([top, left, bottom, right]) => {
+                    let width = item.shape[1];
+                    let mut pixels = item.pixels.clone();
+                    for row in top..bottom {
+                        pixels[row * width + left..row * width + right]
+                            .copy_from_slice(&partner.pixels[row * width + left..row * width + right]);
+                    }
+                    pixels
+                }
+            };
+
+            let row = &mut soft_targets[index * mixing.num_classes..][..mixing.num_classes];
+            row[item.label] += lambda;
+            row[partner.label] += 1.0 - lambda;
+
+            let label = if lambda >= 0.5 { item.label } else { partner.label };
+            // The item keeps its own index, its partner only blends in
+            ClassificationItem { pixels, shape: item.shape, label, index: item.index }
+        })
+        .collect();
+
+    (mixed, soft_targets)
+}
+
+// `items` mixed as one batch of a training batcher `with_mixup(mixup)` and `with_cutmix(cutmix)`
+// seeded with `seed` mixes them, e.g. to preview the augmentation. Unchanged without either.
+pub(crate) fn mix_batch(
+    items: Vec<ClassificationItem>,
+    mixup: Option<f64>,
+    cutmix: Option<f64>,
+    num_classes: usize,
+    seed: u64,
+) -> Vec<ClassificationItem> {
+    let beta = |alpha: f64| Beta::new(alpha, alpha).expect("Mixing alpha should be finite and > 0");
+    let mixing = Mixing {
+        mixup: mixup.map(beta),
+        cutmix: cutmix.map(beta),
+        num_classes,
+        rng: Arc::new(Mutex::new(StdRng::seed_from_u64(seed))),
+    };
+    match mixing.mixup.is_some() || mixing.cutmix.is_some() {
+        true => mix_items(items, &mixing).0,
+        false => items,
+    }
+}
+
+impl<B: Backend> MnistBatcher<B> {
+    // Normalized image batch `[batch_size, height, width]` of the given pixels and shapes
+    pub(crate) fn images<'a>(&self, items: impl Iterator<Item = (&'a Vec<f32>, [usize; 2])>) -> Tensor<B, 3> {
+        let images: Vec<Tensor<B, 3>> = items // Takes the pixels and shape of each item
+            .map(| (pixels, shape) | Data::new(pixels.clone(), Shape::new([1, shape[0], shape[1]]))) // For each item, wrap the pixels in a [C, H, W] float32 data struct
+            .map(| data | Tensor::<B, 3>::from_data(data.convert(), &self.device)) // for each data struct, create a tensor on the device
             // Normalize: Make each pixel between [0, 1] and make the mean=0, std=1
             // Values mean=0.1307, std=0.3081 are from the official PyTorch example
-            .map(| tensor | ((tensor / 255) - 0.1307) / 0.3081) // For each Tensor, Apply Normalization
+            .map(| tensor | ((tensor / PIXEL_SCALE) - MNIST_MEAN) / MNIST_STD) // For each Tensor, Apply Normalization
             .collect(); // Consume the resulting iterator & Collect the values into a new vector
 
+        Tensor::cat(images, 0).to_device(&self.device)
+    }
+
+    pub(crate) fn device(&self) -> &B::Device {
+        &self.device
+    }
+}
+
+impl<B: Backend> Batcher<ClassificationItem, MnistBatch<B>> for MnistBatcher<B> {
+    fn batch(&self, items: Vec<ClassificationItem>) -> MnistBatch<B> {
+        let (items, soft_targets) = match &self.mixing {
+            Some(mixing) => {
+                let (items, soft_targets) = mix_items(items, mixing);
+                let shape = [items.len(), mixing.num_classes];
+                (items, Some(Data::new(soft_targets, Shape::new(shape))))
+            }
+            None => (items, None),
+        };
+
+        let images = self.images(items.iter().map(|item| (&item.pixels, item.shape)));
+        let indices = items.iter().map(|item| item.index).collect();
+
         let targets = items
             .iter()
             .map(| item| Tensor::<B, 1, Int>::from_data(
@@ -41,9 +360,584 @@ impl<B: Backend> Batcher<MnistItem, MnistBatch<B>> for MnistBatcher<B> {
             ))
             .collect();
 
-        let images = Tensor::cat(images, 0).to_device(&self.device);
         let targets = Tensor::cat(targets, 0).to_device(&self.device);
 
-        MnistBatch { images, targets }
+        let soft_targets = soft_targets.map(|data| Tensor::from_data(data.convert(), &self.device));
+
+        let soft_labels = self.soft_labels.as_ref().map(|(soft_labels, hard_weight)| {
+            let probabilities: Vec<f32> = items
+                .iter()
+                .flat_map(|item| {
+                    let index = item.index.expect("Soft-labelled items should know their dataset index");
+                    soft_labels.row(index).expect("Soft labels should have a row per training item").iter().copied()
+                })
+                .collect();
+            let shape = Shape::new([items.len(), soft_labels.num_classes()]);
+            let probabilities = Tensor::from_data(Data::new(probabilities, shape).convert(), &self.device);
+            SoftLabelBatch { probabilities, hard_weight: *hard_weight }
+        });
+
+        MnistBatch { images, targets, soft_targets, soft_labels, holdout: None, preview: None, indices, loss: self.loss }
+    }
+}
+
+impl<B: Backend> Batcher<MnistItem, MnistBatch<B>> for MnistBatcher<B> {
+    fn batch(&self, items: Vec<MnistItem>) -> MnistBatch<B> {
+        self.batch(items.into_iter().map(ClassificationItem::from).collect::<Vec<_>>())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MnistSplit {
+    Train,
+    Test,
+}
+
+impl MnistSplit {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            MnistSplit::Train => "train",
+            MnistSplit::Test => "test",
+        }
+    }
+}
+
+// MNIST decoded once and shared: clones are cheap, so the same items can back several
+// dataloaders (or several runs of a hyperparameter sweep) in one process.
+#[derive(Clone)]
+pub struct CachedMnist {
+    items: Arc<Vec<MnistItem>>,
+}
+
+impl Dataset<MnistItem> for CachedMnist {
+    fn get(&self, index: usize) -> Option<MnistItem> {
+        self.items.get(index).cloned()
+    }
+
+    fn len(&self) -> usize {
+        self.items.len()
+    }
+}
+
+// Process-wide cache, filled on first use of each split
+fn memory_cache() -> &'static Mutex<HashMap<MnistSplit, CachedMnist>> {
+    static CACHE: OnceLock<Mutex<HashMap<MnistSplit, CachedMnist>>> = OnceLock::new();
+    CACHE.get_or_init(Default::default)
+}
+
+fn cache_file(split: MnistSplit) -> PathBuf {
+    let base = std::env::var_os("HOME").map(PathBuf::from).unwrap_or_else(std::env::temp_dir);
+    base.join(".cache")
+        .join("my_first_rust_DL_app")
+        .join(format!("mnist-{}.bin", split.name()))
+}
+
+// Cache file layout: u32 little-endian item count, then per item the label byte followed by
+// the 28x28 pixels as bytes. MNIST pixels are integers in [0, 255] so nothing is lost.
+fn write_cache_file(path: &PathBuf, items: &[MnistItem]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut bytes = Vec::with_capacity(4 + items.len() * (1 + 28 * 28));
+    bytes.extend_from_slice(&(items.len() as u32).to_le_bytes());
+    for item in items {
+        bytes.push(item.label);
+        bytes.extend(item.image.iter().flatten().map(|&pixel| pixel as u8));
+    }
+
+    // Write then rename so an interrupted run never leaves a truncated cache behind
+    let tmp = path.with_extension("tmp");
+    fs::File::create(&tmp)?.write_all(&bytes)?;
+    fs::rename(tmp, path)
+}
+
+fn read_cache_file(path: &PathBuf) -> io::Result<Vec<MnistItem>> {
+    let mut bytes = Vec::new();
+    fs::File::open(path)?.read_to_end(&mut bytes)?;
+
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "corrupted MNIST cache file");
+    let count = u32::from_le_bytes(bytes.get(..4).ok_or_else(invalid)?.try_into().unwrap());
+    let records = bytes[4..].chunks_exact(1 + 28 * 28);
+    if records.len() != count as usize || !records.remainder().is_empty() {
+        return Err(invalid());
+    }
+
+    Ok(records
+        .map(|record| {
+            let mut image = [[0.0; 28]; 28];
+            for (pixel, &value) in image.iter_mut().flatten().zip(&record[1..]) {
+                *pixel = value as f32;
+            }
+            MnistItem { image, label: record[0] }
+        })
+        .collect())
+}
+
+// Panics when the dataset files cannot be downloaded, with the error naming the file at fault
+fn decode_split(split: MnistSplit) -> Vec<MnistItem> {
+    // burn downloads missing files without checking them: fetch and verify them first
+    if let Err(err) = crate::download::ensure_mnist(split) {
+        panic!("Could not get the MNIST {split:?} files: {err}");
+    }
+    let dataset = match split {
+        MnistSplit::Train => MnistDataset::train(),
+        MnistSplit::Test => MnistDataset::test(),
+    };
+    dataset.iter().collect()
+}
+
+// Loads a split of MNIST. Without `cache` this always decodes the raw dataset files; with it the
+// decoded items are kept for the rest of the process and mirrored to a cache file so later runs
+// skip the decode step as well.
+pub fn mnist_dataset(split: MnistSplit, cache: bool) -> CachedMnist {
+    if !cache {
+        return CachedMnist { items: Arc::new(decode_split(split)) };
+    }
+
+    let mut memory = memory_cache().lock().unwrap();
+    if let Some(dataset) = memory.get(&split) {
+        return dataset.clone();
+    }
+
+    let path = cache_file(split);
+    let items = read_cache_file(&path).unwrap_or_else(|_| {
+        let items = decode_split(split);
+        if let Err(err) = write_cache_file(&path, &items) {
+            eprintln!("Could not write the dataset cache to {}: {err}", path.display());
+        }
+        items
+    });
+
+    let dataset = CachedMnist { items: Arc::new(items) };
+    memory.insert(split, dataset.clone());
+    dataset
+}
+
+/// Where [`train`](crate::train) gets its training and validation items from.
+#[derive(Config, Debug, PartialEq)]
+pub enum DatasetSource {
+    // The real MNIST dataset, downloaded on first use
+    Mnist,
+    // Procedurally generated digits (see `SyntheticDigits`). The test split uses a seed derived
+    // from `seed` so it never repeats a training item.
+    #[cfg(any(test, feature = "test-utils"))]
+    Synthetic { num_samples: usize, seed: u64 },
+    // Every item of each source, split by split, interleaved (see `ConcatDataset`). The sources
+    // must have the same class names, which `TrainingConfig::validate` checks.
+    Concat(Vec<DatasetSource>),
+    // Images sorted into `<dir>/train/<label>/` and `<dir>/test/<label>/`, indexed on first use
+    // (see `ImageFolderDataset`)
+    ImageFolder { dir: String },
+}
+
+// Number of items one source of a dataset contributed to a split
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SourceCount {
+    pub source: String,
+    pub num_samples: usize,
+}
+
+impl DatasetSource {
+    // Loads one split. `cache` only applies to MNIST.
+    pub fn load(&self, split: MnistSplit, cache: bool) -> Arc<dyn Dataset<MnistItem>> {
+        self.load_counted(split, cache).0
+    }
+
+    // Same as `load`, along with the number of items of each source: every child of a `Concat`,
+    // or the one source of the others
+    pub fn load_counted(&self, split: MnistSplit, cache: bool) -> (Arc<dyn Dataset<MnistItem>>, Vec<SourceCount>) {
+        let dataset: Arc<dyn Dataset<MnistItem>> = match self {
+            DatasetSource::Mnist => Arc::new(mnist_dataset(split, cache)),
+            #[cfg(any(test, feature = "test-utils"))]
+            DatasetSource::Synthetic { num_samples, seed } => {
+                let seed = match split {
+                    MnistSplit::Train => *seed,
+                    MnistSplit::Test => seed.wrapping_add(1),
+                };
+                Arc::new(crate::synthetic::SyntheticDigits::new(*num_samples, seed))
+            }
+            DatasetSource::Concat(sources) => {
+                let children: Vec<_> = sources.iter().map(|source| source.load(split, cache)).collect();
+                let counts = sources
+                    .iter()
+                    .zip(&children)
+                    .map(|(source, child)| SourceCount { source: format!("{source:?}"), num_samples: Dataset::len(child.as_ref()) })
+                    .collect();
+                return (Arc::new(ConcatDataset::new(children)), counts);
+            }
+            DatasetSource::ImageFolder { dir } => {
+                let dataset = crate::image_folder::ImageFolderDataset::open(crate::image_folder::split_dir(dir, split), false)
+                    .unwrap_or_else(|err| panic!("Could not load the image folder {dir}: {err}"));
+                Arc::new(dataset)
+            }
+        };
+        let counts = vec![SourceCount { source: format!("{self:?}"), num_samples: Dataset::len(dataset.as_ref()) }];
+        (dataset, counts)
+    }
+
+    // Name of every dataset label, by label: every source is of digits, and a `Concat` has the
+    // names of its first source
+    pub fn class_names(&self) -> Vec<String> {
+        match self {
+            DatasetSource::Concat(sources) => sources.first().map(DatasetSource::class_names).unwrap_or_default(),
+            _ => (0..MNIST_NUM_CLASSES).map(|label| label.to_string()).collect(),
+        }
+    }
+}
+
+/// The items of several datasets as one, interleaved in proportion to their lengths: item `i` of
+/// a child of `n` items sits at `(i + 0.5) / n` of the way through, ties going to the earlier
+/// child. Two equal-sized children alternate, and a child ten times bigger than another
+/// contributes ten items between two of the other's. The order only depends on the lengths.
+///
+/// Every child yields [`MnistItem`]s, so they all share the 28x28 image size.
+pub struct ConcatDataset {
+    children: Vec<Arc<dyn Dataset<MnistItem>>>,
+    // (child, index in the child) of every item, in order
+    order: Vec<(usize, usize)>,
+}
+
+impl ConcatDataset {
+    pub fn new(children: Vec<Arc<dyn Dataset<MnistItem>>>) -> Self {
+        let lens: Vec<usize> = children.iter().map(|child| Dataset::len(child.as_ref())).collect();
+        let mut order: Vec<(usize, usize)> =
+            lens.iter().enumerate().flat_map(|(child, &len)| (0..len).map(move |index| (child, index))).collect();
+        // Compares (2i + 1) / 2n exactly, in integers
+        let position = |&(child, index): &(usize, usize)| ((2 * index + 1) as u128, lens[child] as u128);
+        order.sort_by(|a, b| {
+            let ((num_a, den_a), (num_b, den_b)) = (position(a), position(b));
+            (num_a * den_b).cmp(&(num_b * den_a)).then(a.0.cmp(&b.0))
+        });
+        Self { children, order }
+    }
+}
+
+impl Dataset<MnistItem> for ConcatDataset {
+    fn get(&self, index: usize) -> Option<MnistItem> {
+        let &(child, index) = self.order.get(index)?;
+        Dataset::get(self.children[child].as_ref(), index)
+    }
+
+    fn len(&self) -> usize {
+        self.order.len()
+    }
+}
+
+/// The items of `inner` at `indices`, in that order, such as a `TrainingConfig::train_subset`.
+pub struct IndexedDataset {
+    inner: Arc<dyn Dataset<MnistItem>>,
+    indices: Vec<usize>,
+}
+
+impl IndexedDataset {
+    pub fn new(inner: Arc<dyn Dataset<MnistItem>>, indices: Vec<usize>) -> Self {
+        Self { inner, indices }
+    }
+}
+
+impl Dataset<MnistItem> for IndexedDataset {
+    fn get(&self, index: usize) -> Option<MnistItem> {
+        Dataset::get(self.inner.as_ref(), *self.indices.get(index)?)
+    }
+
+    fn len(&self) -> usize {
+        self.indices.len()
+    }
+}
+
+// Batches queued ahead of the training loop by a dataloader, across all of its workers
+enum Prefetched<O> {
+    Batch(usize, O, Progress),
+    Done,
+}
+
+// A multi-worker dataloader like burn's, except that the number of batches the workers may
+// queue ahead of the consumer is configurable (burn hardcodes 100).
+struct PrefetchDataLoader<O> {
+    workers: Vec<Box<dyn DynDataLoader<O>>>,
+    depth: usize,
+}
+
+struct PrefetchIterator<O> {
+    receiver: mpsc::Receiver<Prefetched<O>>,
+    progresses: Vec<Progress>,
+    running: usize,
+}
+
+impl<O: Send + 'static> DataLoader<O> for PrefetchDataLoader<O> {
+    fn iter<'a>(&'a self) -> Box<dyn DataLoaderIterator<O> + 'a> {
+        let (sender, receiver) = mpsc::sync_channel(self.depth);
+
+        let progresses = self
+            .workers
+            .iter()
+            .enumerate()
+            .map(|(index, worker)| {
+                let worker = worker.clone_dyn();
+                let sender = sender.clone();
+                let progress = Progress::new(0, worker.num_items());
+
+                thread::spawn(move || {
+                    let mut batches = worker.iter();
+                    while let Some(batch) = batches.next() {
+                        // The consumer was dropped mid-epoch, nothing left to do
+                        if sender.send(Prefetched::Batch(index, batch, batches.progress())).is_err() {
+                            return;
+                        }
+                    }
+                    sender.send(Prefetched::Done).ok();
+                });
+                progress
+            })
+            .collect();
+
+        Box::new(PrefetchIterator { receiver, progresses, running: self.workers.len() })
+    }
+
+    fn num_items(&self) -> usize {
+        self.workers.iter().map(|worker| worker.num_items()).sum()
+    }
+}
+
+impl<O> Iterator for PrefetchIterator<O> {
+    type Item = O;
+
+    fn next(&mut self) -> Option<O> {
+        while self.running > 0 {
+            match self.receiver.recv().ok()? {
+                Prefetched::Batch(index, batch, progress) => {
+                    self.progresses[index] = progress;
+                    return Some(batch);
+                }
+                Prefetched::Done => self.running -= 1,
+            }
+        }
+        None
+    }
+}
+
+impl<O> DataLoaderIterator<O> for PrefetchIterator<O> {
+    fn progress(&self) -> Progress {
+        let (processed, total) = self.progresses.iter().fold((0, 0), |(processed, total), p| {
+            (processed + p.items_processed, total + p.items_total)
+        });
+        Progress::new(processed, total)
+    }
+}
+
+// Builds a shuffled dataloader over any classification dataset. `prefetch` is the number of
+// batches the workers may prepare ahead of the training loop: 0 keeps burn's own dataloader and
+// its fixed queue of 100, anything else bounds the queue to that many batches. A deeper queue hides slow batching
+// behind GPU work, but every queued batch is a fully built tensor held in device memory, so
+// memory grows linearly with the depth. Burn's backends have no pinned host memory, so there is
+// no pin-memory counterpart to configure.
+pub fn mnist_dataloader<B: Backend, D: ClassificationDataset + 'static>(
+    batcher: MnistBatcher<B>,
+    dataset: D,
+    batch_size: usize,
+    seed: u64,
+    num_workers: usize,
+    prefetch: usize,
+) -> Arc<dyn DataLoader<MnistBatch<B>>> {
+    Arc::from(boxed_mnist_dataloader(batcher, dataset, batch_size, seed, num_workers, prefetch))
+}
+
+// Same as `mnist_dataloader`, boxed instead of shared so that another dataloader can own it: an
+// `Arc<dyn DataLoader>` is not `Send`
+pub fn boxed_mnist_dataloader<B: Backend, D: ClassificationDataset + 'static>(
+    batcher: MnistBatcher<B>,
+    dataset: D,
+    batch_size: usize,
+    seed: u64,
+    num_workers: usize,
+    prefetch: usize,
+) -> Box<dyn DataLoader<MnistBatch<B>>> {
+    boxed_dataloader(batcher, ClassificationItems(dataset), batch_size, seed, num_workers, prefetch)
+}
+
+// The dataloader of `boxed_mnist_dataloader`, for any item and batch type
+pub(crate) fn boxed_dataloader<I, O, T, D>(
+    batcher: T,
+    dataset: D,
+    batch_size: usize,
+    seed: u64,
+    num_workers: usize,
+    prefetch: usize,
+) -> Box<dyn DataLoader<O>>
+where
+    I: Send + Sync + Clone + 'static,
+    O: Send + Clone + std::fmt::Debug + 'static,
+    T: Batcher<I, O> + Clone + 'static,
+    D: Dataset<I> + 'static,
+{
+    if num_workers == 0 {
+        // What burn's `DataLoaderBuilder` builds with a shuffle seed and no workers: the batches
+        // are loaded on the iterating thread, with nothing to prefetch them
+        return Box::new(BatchDataLoader::new(
+            Box::new(FixBatchStrategy::new(batch_size)),
+            Arc::new(dataset),
+            Box::new(batcher),
+            Some(StdRng::seed_from_u64(seed)),
+        ));
+    }
+    if prefetch == 0 {
+        // What burn's `DataLoaderBuilder` builds with a shuffle seed and workers
+        return Box::new(BatchDataLoader::multi_thread(
+            Box::new(FixBatchStrategy::new(batch_size)),
+            Arc::new(dataset),
+            Box::new(batcher),
+            num_workers,
+            Some(StdRng::seed_from_u64(seed)),
+        ));
+    }
+
+    // Same split and per-worker seeding as burn's multi-threaded dataloader
+    let mut rng = StdRng::seed_from_u64(seed);
+    let workers = PartialDataset::split(Arc::new(dataset), num_workers)
+        .into_iter()
+        .map(|part| {
+            let worker_rng = StdRng::seed_from_u64(rng.sample(Standard));
+            let worker: Box<dyn DynDataLoader<O>> = Box::new(BatchDataLoader::new(
+                Box::new(FixBatchStrategy::new(batch_size)),
+                Arc::new(part),
+                Box::new(batcher.clone()),
+                Some(worker_rng),
+            ));
+            worker
+        })
+        .collect();
+
+    Box::new(PrefetchDataLoader { workers, depth: prefetch })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{schedule::batches_per_epoch, synthetic::SyntheticDigits};
+    use burn::{
+        backend::{ndarray::NdArrayDevice, NdArray},
+        data::dataset::InMemDataset,
+    };
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    fn items(dataset: &impl Dataset<MnistItem>) -> Vec<(u8, Vec<f32>)> {
+        dataset.iter().map(|item| (item.label, item.image.iter().flatten().copied().collect())).collect()
+    }
+
+    #[test]
+    fn cache_file_loads_the_items_it_was_written_with() {
+        let digits = SyntheticDigits::new(20, 1);
+        let path = std::env::temp_dir().join("my_first_rust_DL_app-data-cache.bin");
+        write_cache_file(&path, &digits.iter().collect::<Vec<_>>()).unwrap();
+
+        let first = CachedMnist { items: Arc::new(read_cache_file(&path).unwrap()) };
+        let second = CachedMnist { items: Arc::new(read_cache_file(&path).unwrap()) };
+        assert_eq!(items(&first), items(&digits));
+        assert_eq!(items(&second), items(&first));
+
+        // A cache cut short by a crash is rejected, not read as fewer items
+        let bytes = fs::read(&path).unwrap();
+        fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+        let err = read_cache_file(&path).unwrap_err();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    // Batches one index per batch, counting the batches built so far
+    #[derive(Clone)]
+    struct CountingBatcher(Arc<AtomicUsize>);
+
+    impl Batcher<usize, usize> for CountingBatcher {
+        fn batch(&self, items: Vec<usize>) -> usize {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            items[0]
+        }
+    }
+
+    #[test]
+    fn workers_prepare_at_most_the_prefetch_depth_ahead() {
+        let depth = 3;
+        let built = Arc::new(AtomicUsize::new(0));
+        let dataset = InMemDataset::new((0..20).collect::<Vec<usize>>());
+        let loader = boxed_dataloader(CountingBatcher(built.clone()), dataset, 1, 0, 1, depth);
+
+        let mut batches = loader.iter();
+        // The queue holds `depth` batches and the worker blocks on sending the next one
+        thread::sleep(Duration::from_millis(300));
+        assert_eq!(built.load(Ordering::SeqCst), depth + 1);
+
+        let first = batches.next().unwrap();
+        let mut indices: Vec<usize> = std::iter::once(first).chain(batches).collect();
+        indices.sort();
+        assert_eq!(indices, (0..20).collect::<Vec<_>>());
+        assert_eq!(loader.num_items(), 20);
+    }
+
+    #[test]
+    fn no_workers_load_every_batch_on_the_calling_thread() {
+        let built = Arc::new(AtomicUsize::new(0));
+        let dataset = InMemDataset::new((0..20).collect::<Vec<usize>>());
+        let loader = boxed_dataloader(CountingBatcher(built.clone()), dataset, 3, 0, 0, 2);
+
+        let mut batches = loader.iter();
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(built.load(Ordering::SeqCst), 0, "a batch was built before it was asked for");
+        batches.next().unwrap();
+        assert_eq!(built.load(Ordering::SeqCst), 1);
+        assert_eq!(1 + batches.count(), batches_per_epoch(20, 3, 0));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn mixup_batches_have_blended_soft_targets() {
+        let device = NdArrayDevice::default();
+        let items = || SyntheticDigits::new(16, 1).iter().map(ClassificationItem::from).collect::<Vec<_>>();
+
+        let mixed = MnistBatcher::<NdArray>::new(device).with_mixup(0.4, 10, 3).batch(items());
+        let soft_targets = mixed.soft_targets.expect("mixup batches carry soft targets").into_data().value;
+        let rows: Vec<&[f32]> = soft_targets.chunks(10).collect();
+        assert_eq!(rows.len(), 16);
+        assert!(rows.iter().all(|row| (row.iter().sum::<f32>() - 1.0).abs() < 1e-5));
+        // Pairs of distinct labels are blended
+        assert!(rows.iter().any(|row| row.iter().all(|&p| p < 1.0)), "every target is one-hot");
+
+        // Validation batchers do not mix
+        let plain = MnistBatcher::<NdArray>::new(device).batch(items());
+        assert!(plain.soft_targets.is_none());
+    }
+
+    #[test]
+    fn cutmix_lambda_is_the_share_of_the_image_left_unpasted() {
+        // A centered 14x14 rectangle, then the same one clipped to its top-left 7x7 quarter
+        assert_eq!(cutmix_box([28, 28], 0.75, [14, 14]), [7, 7, 21, 21]);
+        assert_eq!(cutmix_lambda([28, 28], [7, 7, 21, 21]), 0.75);
+        assert_eq!(cutmix_box([28, 28], 0.75, [0, 0]), [0, 0, 7, 7]);
+        assert_eq!(cutmix_lambda([28, 28], [0, 0, 7, 7]), 1.0 - 49.0 / 784.0);
+
+        // Item `i` is labeled `i`, every pixel at `i`: its pixels tell which image they come from
+        let items = (0..10)
+            .map(|label| ClassificationItem {
+                pixels: vec![label as f32; 784],
+                shape: [28, 28],
+                label,
+                index: Some(label),
+            })
+            .collect();
+        let mixing = Mixing {
+            mixup: None,
+            cutmix: Some(Beta::new(1.0, 1.0).unwrap()),
+            num_classes: 10,
+            rng: Arc::new(Mutex::new(StdRng::seed_from_u64(3))),
+        };
+        let (mixed, soft_targets) = mix_items(items, &mixing);
+        for (item, row) in mixed.iter().zip(soft_targets.chunks(10)) {
+            let own = item.index.unwrap();
+            let kept = item.pixels.iter().filter(|&&pixel| pixel == own as f32).count();
+            let weight = row[own];
+            assert!((weight - kept as f32 / 784.0).abs() < 1e-6, "item {own} kept {kept} pixels, weighs {weight}");
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use crate::data::{DatasetSource, MnistSplit, SourceCount};
+use burn::data::dataset::{vision::MnistItem, Dataset};
+use image::{GrayImage, Luma};
+use serde::{Deserialize, Serialize};
+use std::{fmt, fs, path::Path};
+
+// Names of the files `write_data_info` writes: the statistics, into the artifact dir, and the
+// grid of samples, into the preview dir
+pub const DATA_INFO_FILE: &str = "data_info.json";
+pub const PREVIEW_FILE: &str = "preview.png";
+
+// Summary statistics of one dataset split, saved as `data_info.json`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DataInfo {
+    pub split: String,
+    // Items that could be read
+    pub num_samples: usize,
+    // Indices the dataset reported but could not return
+    pub skipped: usize,
+    // Number of items per label, indexed by label
+    pub class_counts: Vec<usize>,
+    // Smallest and largest image size seen, as [height, width]
+    pub min_size: [usize; 2],
+    pub max_size: [usize; 2],
+    // Pixel statistics on the [0, 1] scale, over every pixel of every item: the values the
+    // batcher's normalization (`(pixel / 255 - mean) / std`) expects
+    pub pixel_mean: f64,
+    pub pixel_std: f64,
+    // Items of each source the split was loaded from, when known (see `DatasetSource::Concat`)
+    #[serde(default)]
+    pub sources: Vec<SourceCount>,
+}
+
+// Computes the statistics of `dataset` in a single pass, one item at a time. `sources` is left
+// empty for the caller to fill in.
+pub fn data_info<D: Dataset<MnistItem> + ?Sized>(dataset: &D, split: &str) -> DataInfo {
+    let mut info = DataInfo {
+        split: split.to_string(),
+        num_samples: 0,
+        skipped: 0,
+        class_counts: Vec::new(),
+        min_size: [usize::MAX; 2],
+        max_size: [0; 2],
+        pixel_mean: 0.0,
+        pixel_std: 0.0,
+        sources: Vec::new(),
+    };
+    // Sums in f64: 47M pixels for the MNIST train split would lose precision in f32
+    let (mut sum, mut sum_squares, mut num_pixels) = (0.0f64, 0.0f64, 0u64);
+
+    for index in 0..dataset.len() {
+        let Some(item) = dataset.get(index) else {
+            info.skipped += 1;
+            continue;
+        };
+        info.num_samples += 1;
+
+        let label = item.label as usize;
+        if info.class_counts.len() <= label {
+            info.class_counts.resize(label + 1, 0);
+        }
+        info.class_counts[label] += 1;
+
+        let size = [item.image.len(), item.image[0].len()];
+        info.min_size = [info.min_size[0].min(size[0]), info.min_size[1].min(size[1])];
+        info.max_size = [info.max_size[0].max(size[0]), info.max_size[1].max(size[1])];
+
+        for &pixel in item.image.iter().flatten() {
+            let value = pixel as f64 / 255.0;
+            sum += value;
+            sum_squares += value * value;
+            num_pixels += 1;
+        }
+    }
+
+    if num_pixels > 0 {
+        let mean = sum / num_pixels as f64;
+        info.pixel_mean = mean;
+        info.pixel_std = (sum_squares / num_pixels as f64 - mean * mean).max(0.0).sqrt();
+    } else {
+        info.min_size = [0; 2];
+    }
+    info
+}
+
+/// [`data_info`] of the `split` of `source`, with the items of each of its sources, written as
+/// `data_info.json` into `artifact_dir`. With `preview`, a `(preview_dir, per_class)` pair, also
+/// writes the [`write_preview`] grid of the split as `preview.png` into `preview_dir`.
+pub fn write_data_info(
+    source: &DatasetSource,
+    cache: bool,
+    split: MnistSplit,
+    artifact_dir: &str,
+    preview: Option<(&str, usize)>,
+) -> image::ImageResult<DataInfo> {
+    let (dataset, sources) = source.load_counted(split, cache);
+    let mut info = data_info(dataset.as_ref(), split.name());
+    info.sources = sources;
+
+    fs::create_dir_all(artifact_dir)?;
+    let json = serde_json::to_string_pretty(&info).expect("Data info should serialize");
+    fs::write(Path::new(artifact_dir).join(DATA_INFO_FILE), json)?;
+    if let Some((preview_dir, per_class)) = preview {
+        fs::create_dir_all(preview_dir)?;
+        write_preview(dataset.as_ref(), info.class_counts.len(), per_class, &Path::new(preview_dir).join(PREVIEW_FILE))?;
+    }
+    Ok(info)
+}
+
+// Writes a grid of the first `per_class` items of every label: one row per label, in label
+// order. Stops reading the dataset as soon as every row is full.
+pub fn write_preview<D: Dataset<MnistItem> + ?Sized>(
+    dataset: &D,
+    num_classes: usize,
+    per_class: usize,
+    path: &Path,
+) -> image::ImageResult<()> {
+    let mut grid = GrayImage::new((28 * per_class) as u32, (28 * num_classes) as u32);
+    let mut filled = vec![0; num_classes];
+
+    for item in (0..dataset.len()).filter_map(|index| dataset.get(index)) {
+        let label = item.label as usize;
+        if label >= num_classes || filled[label] == per_class {
+            continue;
+        }
+
+        let (x0, y0) = (28 * filled[label] as u32, 28 * label as u32);
+        for (y, row) in item.image.iter().enumerate() {
+            for (x, &pixel) in row.iter().enumerate() {
+                grid.put_pixel(x0 + x as u32, y0 + y as u32, Luma([pixel.clamp(0.0, 255.0) as u8]));
+            }
+        }
+        filled[label] += 1;
+
+        if filled.iter().all(|&count| count == per_class) {
+            break;
+        }
+    }
+
+    grid.save(path)
+}
+
+impl fmt::Display for DataInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "| {:<12} | {:>12} |", "Split", self.split)?;
+        writeln!(f, "|--------------|--------------|")?;
+        writeln!(f, "| {:<12} | {:>12} |", "Samples", self.num_samples)?;
+        writeln!(f, "| {:<12} | {:>12} |", "Skipped", self.skipped)?;
+        let size = |[height, width]: [usize; 2]| format!("{height}x{width}");
+        writeln!(f, "| {:<12} | {:>12} |", "Min size", size(self.min_size))?;
+        writeln!(f, "| {:<12} | {:>12} |", "Max size", size(self.max_size))?;
+        writeln!(f, "| {:<12} | {:>12.4} |", "Pixel mean", self.pixel_mean)?;
+        writeln!(f, "| {:<12} | {:>12.4} |", "Pixel std", self.pixel_std)?;
+        for (label, count) in self.class_counts.iter().enumerate() {
+            let share = *count as f64 / self.num_samples.max(1) as f64 * 100.0;
+            writeln!(f, "| {:<12} | {:>5} {:>5.1}% |", format!("Class {label}"), count, share)?;
+        }
+        for (position, source) in self.sources.iter().enumerate() {
+            writeln!(f, "| {:<12} | {:>12} | {}", format!("Source {position}"), source.num_samples, source.source)?;
+        }
+        Ok(())
+    }
+}
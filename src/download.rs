@@ -0,0 +1,410 @@
+use crate::data::MnistSplit;
+use flate2::read::GzDecoder;
+use sha2::{Digest, Sha256};
+use std::{
+    fmt, fs,
+    io::{self, IsTerminal, Read, Write},
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+// Where burn's `MnistDataset` downloads MNIST from
+pub const MNIST_URL: &str = "https://storage.googleapis.com/cvdf-datasets/mnist/";
+
+// Downloads of one file before giving up, the wait doubling after every failed one
+const MAX_ATTEMPTS: u32 = 5;
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+// One gzipped IDX file of a dataset: the name it is extracted to, the SHA-256 of its `.gz`
+// download and what its extracted header must hold
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IdxFile {
+    pub name: &'static str,
+    pub sha256: &'static str,
+    pub magic: u32,
+    pub num_items: usize,
+    // Bytes of one item after the header: 28 * 28 for images, 1 for labels
+    pub item_size: usize,
+}
+
+impl IdxFile {
+    // Length of the extracted file: the magic number, one u32 per dimension, then the items
+    fn extracted_len(&self) -> u64 {
+        let num_dimensions = (self.magic & 0xff) as usize;
+        (4 + 4 * num_dimensions + self.num_items * self.item_size) as u64
+    }
+}
+
+const IMAGES_MAGIC: u32 = 0x0803;
+const LABELS_MAGIC: u32 = 0x0801;
+
+pub const MNIST_TRAIN_FILES: [IdxFile; 2] = [
+    IdxFile {
+        name: "train-images-idx3-ubyte",
+        sha256: "440fcabf73cc546fa21475e81ea370265605f56be210a4024d2ca8f203523609",
+        magic: IMAGES_MAGIC,
+        num_items: 60_000,
+        item_size: 28 * 28,
+    },
+    IdxFile {
+        name: "train-labels-idx1-ubyte",
+        sha256: "3552534a0a558bbed6aed32b30c495cca23d567ec52cac8be1a0730e8010255c",
+        magic: LABELS_MAGIC,
+        num_items: 60_000,
+        item_size: 1,
+    },
+];
+
+pub const MNIST_TEST_FILES: [IdxFile; 2] = [
+    IdxFile {
+        name: "t10k-images-idx3-ubyte",
+        sha256: "8d422c7b0a1c1c79245a5bcf07fe86e33eeafee792b84584aec276f5a2dbc4e6",
+        magic: IMAGES_MAGIC,
+        num_items: 10_000,
+        item_size: 28 * 28,
+    },
+    IdxFile {
+        name: "t10k-labels-idx1-ubyte",
+        sha256: "f7ae60f92e00ec6debd23a6088c31dbd2371eca3ffa0defaefb259924204aec6",
+        magic: LABELS_MAGIC,
+        num_items: 10_000,
+        item_size: 1,
+    },
+];
+
+#[derive(Debug)]
+pub enum DownloadError {
+    // Every attempt at `url` failed, the last one with `message`
+    Http { url: String, attempts: u32, message: String },
+    // A downloaded file does not hash to its known checksum, even after downloading it again
+    Checksum { file: String, expected: String, actual: String, path: PathBuf },
+    // A file extracted from a verified download is not the IDX file it should be
+    Corrupt { file: String, reason: String, path: PathBuf },
+    Io { path: PathBuf, err: io::Error },
+}
+
+impl fmt::Display for DownloadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DownloadError::Http { url, attempts, message } => {
+                write!(f, "could not download {url} after {attempts} attempt(s): {message}")
+            }
+            DownloadError::Checksum { file, expected, actual, path } => write!(
+                f,
+                "{file} has SHA-256 {actual}, expected {expected}; delete {} and run again",
+                path.display()
+            ),
+            DownloadError::Corrupt { file, reason, path } => {
+                write!(f, "{file} is corrupt ({reason}); delete {} and run again", path.display())
+            }
+            DownloadError::Io { path, err } => write!(f, "{}: {err}", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for DownloadError {}
+
+fn io_error(path: &Path) -> impl FnOnce(io::Error) -> DownloadError + '_ {
+    move |err| DownloadError::Io { path: path.to_path_buf(), err }
+}
+
+// Lowercase hex SHA-256 of the file at `path`, read in chunks
+pub fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 1 << 16];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(format!("{:x}", hasher.finalize()));
+        }
+        hasher.update(&buffer[..read]);
+    }
+}
+
+/// Checks the file at `path` against its known SHA-256, with the error naming the file, both
+/// checksums and the path to delete.
+pub fn verify_checksum(path: &Path, expected: &str) -> Result<(), DownloadError> {
+    let actual = sha256_file(path).map_err(io_error(path))?;
+    if actual == expected {
+        return Ok(());
+    }
+    let file = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+    Err(DownloadError::Checksum { file, expected: expected.to_string(), actual, path: path.to_path_buf() })
+}
+
+/// Checks that the extracted IDX file at `path` has the magic number and length `file` says:
+/// what a truncated or garbled extraction breaks.
+pub fn verify_idx(path: &Path, file: &IdxFile) -> Result<(), DownloadError> {
+    let corrupt = |reason: String| DownloadError::Corrupt { file: file.name.to_string(), reason, path: path.to_path_buf() };
+
+    let mut header = [0; 8];
+    let mut handle = fs::File::open(path).map_err(io_error(path))?;
+    handle.read_exact(&mut header).map_err(|_| corrupt("shorter than its header".to_string()))?;
+    let magic = u32::from_be_bytes(header[..4].try_into().unwrap());
+    if magic != file.magic {
+        return Err(corrupt(format!("magic number {magic:#x} instead of {:#x}", file.magic)));
+    }
+    let num_items = u32::from_be_bytes(header[4..].try_into().unwrap()) as usize;
+    if num_items != file.num_items {
+        return Err(corrupt(format!("{num_items} items instead of {}", file.num_items)));
+    }
+    let len = handle.metadata().map_err(io_error(path))?.len();
+    if len != file.extracted_len() {
+        return Err(corrupt(format!("{len} bytes instead of {}", file.extracted_len())));
+    }
+    Ok(())
+}
+
+// Download progress on stderr: a line rewritten in place on a terminal, only the start and end
+// of each file otherwise (logs, CI), so that redirected output is not flooded
+struct Progress {
+    name: String,
+    interactive: bool,
+    total: Option<u64>,
+    // Last percentage shown, to redraw once per percent
+    shown: Option<u64>,
+}
+
+impl Progress {
+    fn new(name: &str, total: Option<u64>, resumed_from: u64) -> Self {
+        let interactive = io::stderr().is_terminal();
+        if !interactive {
+            match resumed_from {
+                0 => eprintln!("Downloading {name}"),
+                _ => eprintln!("Resuming the download of {name} at {resumed_from} bytes"),
+            }
+        }
+        Self { name: name.to_string(), interactive, total, shown: None }
+    }
+
+    fn update(&mut self, done: u64) {
+        if !self.interactive {
+            return;
+        }
+        match self.total {
+            Some(total) if total > 0 => {
+                let percent = done * 100 / total;
+                if self.shown != Some(percent) {
+                    self.shown = Some(percent);
+                    eprint!("\r{}: {:.1}/{:.1} MB ({percent}%)", self.name, done as f64 / 1e6, total as f64 / 1e6);
+                }
+            }
+            _ => eprint!("\r{}: {:.1} MB", self.name, done as f64 / 1e6),
+        }
+    }
+
+    fn finish(&self, done: u64) {
+        if self.interactive {
+            eprintln!();
+        } else {
+            eprintln!("Downloaded {} ({done} bytes)", self.name);
+        }
+    }
+}
+
+// One attempt at downloading `url` into `part`, resuming after the bytes a previous attempt left
+// there when the server supports ranges
+fn fetch(url: &str, part: &Path) -> Result<(), String> {
+    let resumed_from = fs::metadata(part).map(|metadata| metadata.len()).unwrap_or(0);
+    let mut request = ureq::get(url).timeout(Duration::from_secs(60));
+    if resumed_from > 0 {
+        request = request.set("Range", &format!("bytes={resumed_from}-"));
+    }
+    let response = request.call().map_err(|err| err.to_string())?;
+
+    // A server ignoring the range resends the whole file
+    let resumed = response.status() == 206;
+    let length = response.header("Content-Length").and_then(|length| length.parse::<u64>().ok());
+    let (mut done, total) = match resumed {
+        true => (resumed_from, length.map(|length| length + resumed_from)),
+        false => (0, length),
+    };
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(resumed)
+        .truncate(!resumed)
+        .open(part)
+        .map_err(|err| err.to_string())?;
+
+    let name = part.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+    let mut progress = Progress::new(&name, total, if resumed { resumed_from } else { 0 });
+    let mut reader = response.into_reader();
+    let mut buffer = vec![0; 1 << 16];
+    loop {
+        let read = reader.read(&mut buffer).map_err(|err| err.to_string())?;
+        if read == 0 {
+            break;
+        }
+        file.write_all(&buffer[..read]).map_err(|err| err.to_string())?;
+        done += read as u64;
+        progress.update(done);
+    }
+    progress.finish(done);
+
+    match total {
+        Some(total) if done != total => Err(format!("connection closed after {done} of {total} bytes")),
+        _ => Ok(()),
+    }
+}
+
+/// Makes sure `path` holds the file at `url` with SHA-256 `sha256`. A file already there is
+/// kept if it verifies, and deleted to be downloaded again otherwise. Downloads go to a `.part`
+/// file next to `path`, resumed after a dropped connection and deleted when they fail
+/// verification, and are retried up to 5 times with exponential backoff.
+pub fn ensure_download(url: &str, path: &Path, sha256: &str) -> Result<(), DownloadError> {
+    if path.exists() {
+        match verify_checksum(path, sha256) {
+            Ok(()) => return Ok(()),
+            Err(err) => {
+                eprintln!("Warning: {err}; downloading it again");
+                fs::remove_file(path).map_err(io_error(path))?;
+            }
+        }
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(io_error(parent))?;
+    }
+
+    let part = path.with_extension("gz.part");
+    let mut backoff = INITIAL_BACKOFF;
+    let mut last_error = None;
+    for attempt in 1..=MAX_ATTEMPTS {
+        if attempt > 1 {
+            thread::sleep(backoff);
+            backoff *= 2;
+        }
+        if let Err(message) = fetch(url, &part) {
+            eprintln!("Attempt {attempt}/{MAX_ATTEMPTS} at {url} failed: {message}");
+            last_error = Some(DownloadError::Http { url: url.to_string(), attempts: attempt, message });
+            continue;
+        }
+        match verify_checksum(&part, sha256) {
+            Ok(()) => return fs::rename(&part, path).map_err(io_error(path)),
+            Err(DownloadError::Checksum { file, expected, actual, .. }) => {
+                eprintln!("Attempt {attempt}/{MAX_ATTEMPTS}: {file} has SHA-256 {actual}, expected {expected}");
+                fs::remove_file(&part).map_err(io_error(&part))?;
+                let file = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+                last_error = Some(DownloadError::Checksum { file, expected, actual, path: path.to_path_buf() });
+            }
+            Err(err) => return Err(err),
+        }
+    }
+    Err(last_error.expect("At least one download attempt is made"))
+}
+
+/// Extracts the verified gzip file `archive` into `path`, through a temporary file so that an
+/// interrupted extraction never leaves a truncated file behind, and checks the result.
+pub fn extract_idx(archive: &Path, path: &Path, file: &IdxFile) -> Result<(), DownloadError> {
+    let tmp = path.with_extension("tmp");
+    let mut decoder = GzDecoder::new(fs::File::open(archive).map_err(io_error(archive))?);
+    let mut output = fs::File::create(&tmp).map_err(io_error(&tmp))?;
+    io::copy(&mut decoder, &mut output).map_err(|err| DownloadError::Corrupt {
+        file: file.name.to_string(),
+        reason: format!("could not be extracted: {err}"),
+        path: archive.to_path_buf(),
+    })?;
+    verify_idx(&tmp, file)?;
+    fs::rename(&tmp, path).map_err(io_error(path))
+}
+
+// The burn-dataset cache, where `MnistDataset` reads (and would otherwise download) MNIST
+fn mnist_cache_dir() -> PathBuf {
+    dirs::home_dir().unwrap_or_default().join(".cache").join("burn-dataset").join("mnist")
+}
+
+/// Makes sure the extracted IDX files of an MNIST split are in the burn-dataset cache and
+/// intact, so that burn's `MnistDataset` reads them instead of downloading them unchecked.
+/// Corrupt files are extracted again, from a verified download kept in `mnist/downloads`.
+pub fn ensure_mnist(split: MnistSplit) -> Result<(), DownloadError> {
+    let (files, split_dir) = match split {
+        MnistSplit::Train => (MNIST_TRAIN_FILES, "train"),
+        MnistSplit::Test => (MNIST_TEST_FILES, "test"),
+    };
+    let cache_dir = mnist_cache_dir();
+    let split_dir = cache_dir.join(split_dir);
+    fs::create_dir_all(&split_dir).map_err(io_error(&split_dir))?;
+
+    for file in &files {
+        let path = split_dir.join(file.name);
+        if path.exists() {
+            match verify_idx(&path, file) {
+                Ok(()) => continue,
+                Err(err) => eprintln!("Warning: {err}; extracting it again"),
+            }
+        }
+        let archive = cache_dir.join("downloads").join(format!("{}.gz", file.name));
+        ensure_download(&format!("{MNIST_URL}{}.gz", file.name), &archive, file.sha256)?;
+        extract_idx(&archive, &path, file)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{write::GzEncoder, Compression};
+
+    // SHA-256 of "abc"
+    const ABC_SHA256: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+
+    // Three labels, as a gzipped IDX file
+    const LABELS: IdxFile = IdxFile { name: "labels", sha256: "", magic: LABELS_MAGIC, num_items: 3, item_size: 1 };
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("my_first_rust_DL_app-download-{name}"));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn checksum_mismatch_names_the_file_and_both_checksums() {
+        let dir = temp_dir("checksum");
+        let path = dir.join("file.gz");
+        fs::write(&path, "abc").unwrap();
+
+        assert_eq!(sha256_file(&path).unwrap(), ABC_SHA256);
+        assert!(verify_checksum(&path, ABC_SHA256).is_ok());
+        match verify_checksum(&path, &"0".repeat(64)) {
+            Err(DownloadError::Checksum { file, expected, actual, path: reported }) => {
+                assert_eq!(file, "file.gz");
+                assert_eq!((expected, actual), ("0".repeat(64), ABC_SHA256.to_string()));
+                assert_eq!(reported, path);
+            }
+            other => panic!("expected a checksum mismatch, got {other:?}"),
+        }
+        // A verified file is kept without being downloaded again
+        assert!(ensure_download("http://localhost:9/file.gz", &path, ABC_SHA256).is_ok());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn extracted_idx_files_are_checked_against_their_header() {
+        let dir = temp_dir("extract");
+        let gzip = |bytes: &[u8], name: &str| {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(bytes).unwrap();
+            let path = dir.join(name);
+            fs::write(&path, encoder.finish().unwrap()).unwrap();
+            path
+        };
+        let mut idx = LABELS_MAGIC.to_be_bytes().to_vec();
+        idx.extend(3u32.to_be_bytes());
+        idx.extend([7, 2, 1]);
+
+        let path = dir.join("labels");
+        extract_idx(&gzip(&idx, "labels.gz"), &path, &LABELS).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), idx);
+
+        let truncated = extract_idx(&gzip(&idx[..10], "truncated.gz"), &dir.join("truncated"), &LABELS);
+        assert!(matches!(truncated, Err(DownloadError::Corrupt { reason, .. }) if reason == "10 bytes instead of 11"));
+        let images = IdxFile { magic: IMAGES_MAGIC, ..LABELS };
+        assert!(matches!(verify_idx(&path, &images), Err(DownloadError::Corrupt { .. })));
+        // A failed extraction never reaches the destination
+        assert!(!dir.join("truncated").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,1410 @@
+use crate::{
+    artifact::ArtifactDir,
+    checkpoint::LoadError,
+    data::{MnistBatch, MnistBatcher, MNIST_NUM_CLASSES},
+    inference::{load_model, load_model_from},
+    labels::{ClassLabels, CLASSES_FILE},
+    meta::ModelMeta,
+    model::Classifier,
+    npy::NpyWriter,
+    store::CheckpointBackend,
+    training::ConfigError,
+};
+use burn::{
+    data::{
+        dataloader::{batcher::Batcher, Dataset},
+        dataset::vision::{MnistDataset, MnistItem},
+    },
+    prelude::*,
+    record::{FullPrecisionSettings, NamedMpkFileRecorder, Record, Recorder, RecorderError},
+    tensor::activation::softmax,
+};
+use rand::{rngs::StdRng, seq::index, SeedableRng};
+use rand_distr::{Distribution, Normal, StandardNormal};
+use image::{Rgb, RgbImage};
+use serde::{Deserialize, Serialize};
+use std::{fmt, io, path::Path};
+
+// Number of test images pushed through the model at once during a full pass
+const EVAL_BATCH_SIZE: usize = 256;
+
+pub type ConfusionMatrix = [[u32; MNIST_NUM_CLASSES]; MNIST_NUM_CLASSES];
+
+// Seed of the noise added by the robustness evaluations, fixed so reports are comparable
+const NOISE_SEED: u64 = 42;
+
+// Batches a dataset in order (no shuffling, no worker threads), yielding each batch along with
+// the dataset index of its first sample
+pub fn ordered_batches<'a, B: Backend, D: Dataset<MnistItem>>(
+    dataset: &'a D,
+    device: &B::Device,
+) -> impl Iterator<Item = (usize, MnistBatch<B>)> + 'a {
+    let batcher = MnistBatcher::<B>::new(device.clone());
+
+    (0..dataset.len()).step_by(EVAL_BATCH_SIZE).map(move |start| {
+        let end = usize::min(start + EVAL_BATCH_SIZE, dataset.len());
+        let items = (start..end).filter_map(|index| dataset.get(index)).collect();
+        (start, batcher.batch(items))
+    })
+}
+
+// Runs the model over the whole test set in dataset order and hands every batch's logits to
+// `f` along with the batch and the dataset index of its first sample.
+pub fn test_set_pass<B: Backend, M: Classifier<B> + ?Sized>(
+    model: &M,
+    device: &B::Device,
+    f: impl FnMut(usize, Tensor<B, 2>, MnistBatch<B>),
+) {
+    dataset_pass(model, &MnistDataset::test(), device, f)
+}
+
+// `test_set_pass` over any dataset of MNIST items
+pub fn dataset_pass<B: Backend, M: Classifier<B> + ?Sized, D: Dataset<MnistItem>>(
+    model: &M,
+    dataset: &D,
+    device: &B::Device,
+    mut f: impl FnMut(usize, Tensor<B, 2>, MnistBatch<B>),
+) {
+    for (start, batch) in ordered_batches(dataset, device) {
+        let output = model.forward(batch.images.clone());
+        f(start, output, batch);
+    }
+}
+
+fn count_correct<B: Backend>(output: Tensor<B, 2>, targets: Tensor<B, 1, Int>) -> usize {
+    let predicted = output.argmax(1).flatten::<1>(0, 1);
+    predicted.equal(targets).int().sum().into_scalar().elem::<i64>() as usize
+}
+
+// Fraction of the test set classified correctly
+pub fn accuracy<B: Backend, M: Classifier<B> + ?Sized>(model: &M, device: &B::Device) -> f32 {
+    let (mut correct, mut total) = (0, 0);
+
+    test_set_pass(model, device, |_, output, batch| {
+        total += batch.targets.dims()[0];
+        correct += count_correct(output, batch.targets);
+    });
+
+    correct as f32 / total.max(1) as f32
+}
+
+// Test accuracy under additive Gaussian noise, one `(sigma, accuracy)` pair per sigma.
+// The noise is added to the normalized images and drawn from a fixed seed, so sigma = 0 gives
+// the clean accuracy and repeated calls give the same numbers. Every sigma must be finite and
+// >= 0, they are all checked before the test set is read.
+pub fn noise_robustness<B: Backend, M: Classifier<B> + ?Sized>(
+    model: &M,
+    device: &B::Device,
+    sigmas: &[f32],
+) -> Result<Vec<(f32, f32)>, ConfigError> {
+    let noises = noise_distributions(sigmas)?;
+    Ok(noisy_accuracies(model, &MnistDataset::test(), device, noises))
+}
+
+// `noise_robustness` over any dataset of MNIST items
+pub fn dataset_noise_robustness<B: Backend, M: Classifier<B> + ?Sized, D: Dataset<MnistItem>>(
+    model: &M,
+    dataset: &D,
+    device: &B::Device,
+    sigmas: &[f32],
+) -> Result<Vec<(f32, f32)>, ConfigError> {
+    let noises = noise_distributions(sigmas)?;
+    Ok(noisy_accuracies(model, dataset, device, noises))
+}
+
+fn noise_distributions(sigmas: &[f32]) -> Result<Vec<(f32, Normal<f32>)>, ConfigError> {
+    sigmas
+        .iter()
+        .map(|&sigma| match Normal::new(0.0, sigma) {
+            Ok(noise) if sigma >= 0.0 => Ok((sigma, noise)),
+            _ => Err(ConfigError::new("sigmas", sigma, "finite values >= 0")),
+        })
+        .collect()
+}
+
+fn noisy_accuracies<B: Backend, M: Classifier<B> + ?Sized, D: Dataset<MnistItem>>(
+    model: &M,
+    dataset: &D,
+    device: &B::Device,
+    noises: Vec<(f32, Normal<f32>)>,
+) -> Vec<(f32, f32)> {
+    noises
+        .into_iter()
+        .map(|(sigma, noise)| {
+            let mut rng = StdRng::seed_from_u64(NOISE_SEED);
+            let (mut correct, mut total) = (0, 0);
+
+            for (_, batch) in ordered_batches::<B, _>(dataset, device) {
+                let shape = batch.images.shape();
+                let values: Vec<f32> = (0..shape.num_elements()).map(|_| noise.sample(&mut rng)).collect();
+                let noise = Tensor::<B, 1>::from_floats(values.as_slice(), device).reshape(shape);
+
+                let output = model.forward(batch.images + noise);
+                total += batch.targets.dims()[0];
+                correct += count_correct(output, batch.targets);
+            }
+
+            (sigma, correct as f32 / total.max(1) as f32)
+        })
+        .collect()
+}
+
+// Empirical Lipschitz-style sensitivity of the logits to the input: for `num_samples` test
+// images drawn from a fixed seed, each perturbed in a random direction by noise of L2 norm
+// `epsilon` (in normalized pixel units), the mean of |f(x + d) - f(x)| / |d|, both L2 norms.
+// A local estimate around the data, not a bound. `epsilon` must be finite and > 0, it is
+// checked before the test set is read.
+pub fn sensitivity<B: Backend, M: Classifier<B> + ?Sized>(
+    model: &M,
+    device: &B::Device,
+    num_samples: usize,
+    epsilon: f32,
+) -> Result<f32, ConfigError> {
+    check_epsilon(epsilon)?;
+    dataset_sensitivity(model, &MnistDataset::test(), device, num_samples, epsilon)
+}
+
+// `sensitivity` over any dataset of MNIST items, all of them when it has fewer than
+// `num_samples`
+pub fn dataset_sensitivity<B: Backend, M: Classifier<B> + ?Sized, D: Dataset<MnistItem>>(
+    model: &M,
+    dataset: &D,
+    device: &B::Device,
+    num_samples: usize,
+    epsilon: f32,
+) -> Result<f32, ConfigError> {
+    check_epsilon(epsilon)?;
+    let mut rng = StdRng::seed_from_u64(NOISE_SEED);
+    let mut indices = index::sample(&mut rng, dataset.len(), num_samples.min(dataset.len())).into_vec();
+    indices.sort_unstable();
+    let batcher = MnistBatcher::<B>::new(device.clone());
+
+    let (mut ratio_sum, mut count) = (0.0f64, 0usize);
+    for chunk in indices.chunks(EVAL_BATCH_SIZE) {
+        let batch = batcher.batch(chunk.iter().filter_map(|&index| dataset.get(index)).collect());
+        let shape = batch.images.shape();
+        let [batch_size, height, width] = batch.images.dims();
+        // A random direction per image, scaled to norm `epsilon`
+        let mut noise: Vec<f32> = (0..shape.num_elements()).map(|_| StandardNormal.sample(&mut rng)).collect();
+        for direction in noise.chunks_mut(height * width) {
+            let norm = direction.iter().map(|value| value * value).sum::<f32>().sqrt().max(f32::MIN_POSITIVE);
+            direction.iter_mut().for_each(|value| *value *= epsilon / norm);
+        }
+        let noise = Tensor::<B, 1>::from_floats(noise.as_slice(), device).reshape(shape);
+
+        let clean = model.forward(batch.images.clone());
+        let perturbed = model.forward(batch.images + noise);
+        let changes = (perturbed - clean).powf_scalar(2.0).sum_dim(1).sqrt().reshape([batch_size]);
+        for change in changes.into_data().convert::<f32>().value {
+            ratio_sum += (change / epsilon) as f64;
+            count += 1;
+        }
+    }
+
+    Ok((ratio_sum / count.max(1) as f64) as f32)
+}
+
+fn check_epsilon(epsilon: f32) -> Result<(), ConfigError> {
+    match epsilon.is_finite() && epsilon > 0.0 {
+        true => Ok(()),
+        false => Err(ConfigError::new("epsilon", epsilon, "a finite value > 0")),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SampleOutcome {
+    pub target: usize,
+    pub predicted: usize,
+    // Softmax probability of the predicted class
+    pub confidence: f32,
+}
+
+impl SampleOutcome {
+    pub fn is_correct(&self) -> bool {
+        self.target == self.predicted
+    }
+}
+
+// Returns the outcome of every test sample, in dataset order
+pub fn test_set_predictions<B: Backend, M: Classifier<B> + ?Sized>(model: &M, device: &B::Device) -> Vec<SampleOutcome> {
+    dataset_predictions(model, &MnistDataset::test(), device)
+}
+
+// `test_set_predictions` over any dataset of MNIST items
+pub fn dataset_predictions<B: Backend, M: Classifier<B> + ?Sized, D: Dataset<MnistItem>>(
+    model: &M,
+    dataset: &D,
+    device: &B::Device,
+) -> Vec<SampleOutcome> {
+    let mut outcomes = Vec::new();
+
+    dataset_pass(model, dataset, device, |_, output, batch| {
+        outcomes.extend(batch_outcomes(output, batch.targets));
+    });
+
+    outcomes
+}
+
+// The outcome of every sample of one batch, from its logits
+pub(crate) fn batch_outcomes<B: Backend>(output: Tensor<B, 2>, targets: Tensor<B, 1, Int>) -> Vec<SampleOutcome> {
+    let (confidence, predicted) = softmax(output, 1).max_dim_with_indices(1);
+    let confidence = confidence.into_data().convert::<f32>().value;
+    let predicted = predicted.into_data().convert::<i64>().value;
+    let targets = targets.into_data().convert::<i64>().value;
+
+    targets
+        .into_iter()
+        .zip(predicted)
+        .zip(confidence)
+        .map(|((target, predicted), confidence)| SampleOutcome {
+            target: target as usize,
+            predicted: predicted as usize,
+            confidence,
+        })
+        .collect()
+}
+
+// Top-1 minus top-2 softmax probability of every test sample, in dataset order. The smallest
+// margins are the samples closest to a decision boundary, those active learning labels first.
+pub fn prediction_margins<B: Backend, M: Classifier<B> + ?Sized>(model: &M, device: &B::Device) -> Vec<f32> {
+    dataset_margins(model, &MnistDataset::test(), device)
+}
+
+// `prediction_margins` over any dataset of MNIST items
+pub fn dataset_margins<B: Backend, M: Classifier<B> + ?Sized, D: Dataset<MnistItem>>(
+    model: &M,
+    dataset: &D,
+    device: &B::Device,
+) -> Vec<f32> {
+    let mut margins = Vec::new();
+    dataset_pass(model, dataset, device, |_, output, _| margins.extend(logit_margins(output)));
+    margins
+}
+
+// The margin of every row of the logits `[batch_size, num_classes]`, in [0, 1]. A single class
+// has a margin of 1, its probability.
+pub fn logit_margins<B: Backend>(output: Tensor<B, 2>) -> Vec<f32> {
+    let [_, num_classes] = output.dims();
+    let probabilities = softmax(output, 1).into_data().convert::<f32>().value;
+    probabilities
+        .chunks(num_classes.max(1))
+        .map(|row| {
+            let (first, second) = row.iter().fold((0.0f32, 0.0f32), |(first, second), &p| match p > first {
+                true => (p, first),
+                false => (first, second.max(p)),
+            });
+            first - second
+        })
+        .collect()
+}
+
+// Rows are the true labels, columns the predicted ones
+pub fn confusion_matrix<B: Backend, M: Classifier<B> + ?Sized>(model: &M, device: &B::Device) -> ConfusionMatrix {
+    let mut accumulator = EvalAccumulator::new(1, None);
+    test_set_pass(model, device, |_, output, batch| accumulator.extend(batch_outcomes(output, batch.targets)));
+    accumulator.confusion_matrix
+}
+
+// Side of one heatmap cell, in pixels
+const HEATMAP_CELL_SIZE: u32 = 32;
+// Heatmap colors of an empty cell and of a full one
+pub(crate) const HEATMAP_EMPTY: [u8; 3] = [255, 255, 255];
+pub(crate) const HEATMAP_FULL: [u8; 3] = [8, 48, 107];
+
+/// Writes `matrix` as a CSV to `csv_path`, with a `true\predicted` header row and the true label
+/// at the start of every row, and as a heatmap PNG to `png_path`: one square per cell, rows
+/// being the true labels, from white to dark blue with the cell's share of all the samples. The
+/// scale saturates at a tenth of the samples, what every diagonal cell of a perfect model holds
+/// on a balanced test set, so that off-diagonal mistakes stay visible.
+pub fn save_confusion_matrix(
+    matrix: &ConfusionMatrix,
+    labels: &ClassLabels,
+    csv_path: &Path,
+    png_path: &Path,
+) -> image::ImageResult<()> {
+    let mut csv = String::from("true\\predicted");
+    for predicted in 0..MNIST_NUM_CLASSES {
+        csv.push_str(&format!(",{}", labels.name(predicted)));
+    }
+    csv.push('\n');
+    for (target, row) in matrix.iter().enumerate() {
+        csv.push_str(&labels.name(target));
+        for count in row {
+            csv.push_str(&format!(",{count}"));
+        }
+        csv.push('\n');
+    }
+    std::fs::write(csv_path, csv)?;
+
+    let total: u32 = matrix.iter().flatten().sum();
+    let side = HEATMAP_CELL_SIZE * MNIST_NUM_CLASSES as u32;
+    let heatmap = RgbImage::from_fn(side, side, |x, y| {
+        let count = matrix[(y / HEATMAP_CELL_SIZE) as usize][(x / HEATMAP_CELL_SIZE) as usize];
+        let share = count as f32 / total.max(1) as f32;
+        let t = (share * MNIST_NUM_CLASSES as f32).min(1.0);
+        Rgb(std::array::from_fn(|channel| {
+            let (empty, full) = (HEATMAP_EMPTY[channel] as f32, HEATMAP_FULL[channel] as f32);
+            (empty + (full - empty) * t).round() as u8
+        }))
+    });
+    heatmap.save(png_path)
+}
+
+/// Precision, recall and F1 of one class, from a confusion matrix.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ClassScores {
+    pub class: usize,
+    pub precision: f32,
+    pub recall: f32,
+    pub f1: f32,
+    // Samples of the class, the row sum
+    pub support: u32,
+}
+
+// The scores of each of the first `num_classes` classes of `matrix`, rows being the true labels.
+// A class never predicted has a precision of 0, as one without samples has a recall of 0.
+pub fn class_scores(matrix: &ConfusionMatrix, num_classes: usize) -> Vec<ClassScores> {
+    let ratio = |num: f32, den: f32| if den > 0.0 { num / den } else { 0.0 };
+    (0..num_classes.min(MNIST_NUM_CLASSES))
+        .map(|class| {
+            let true_pos = matrix[class][class] as f32;
+            let support: u32 = matrix[class].iter().sum();
+            let predicted: u32 = matrix.iter().map(|row| row[class]).sum();
+            let precision = ratio(true_pos, predicted as f32);
+            let recall = ratio(true_pos, support as f32);
+            ClassScores { class, precision, recall, f1: ratio(2.0 * precision * recall, precision + recall), support }
+        })
+        .collect()
+}
+
+// Test-set indices (in dataset order) of every sample the model gets wrong
+pub fn misclassified_indices<B: Backend, M: Classifier<B> + ?Sized>(model: &M, device: &B::Device) -> Vec<usize> {
+    dataset_misclassified_indices(model, &MnistDataset::test(), device)
+}
+
+// `misclassified_indices` over any dataset of MNIST items
+pub fn dataset_misclassified_indices<B: Backend, M: Classifier<B> + ?Sized, D: Dataset<MnistItem>>(
+    model: &M,
+    dataset: &D,
+    device: &B::Device,
+) -> Vec<usize> {
+    dataset_predictions(model, dataset, device)
+        .into_iter()
+        .enumerate()
+        .filter(|(_, outcome)| !outcome.is_correct())
+        .map(|(index, _)| index)
+        .collect()
+}
+
+/// Options of [`evaluate`].
+#[derive(Config, Debug)]
+pub struct EvaluationConfig {
+    // Number of equal-width confidence bins used for the calibration error
+    #[config(default = 15)]
+    pub calibration_bins: usize,
+    // Also write `logits.npy`, `predictions.npy` and `targets.npy` for the test set
+    #[config(default = false)]
+    pub export_npy: bool,
+    // Also write the confusion matrix as `confusion_matrix.csv` and `confusion_matrix.png`
+    #[config(default = false)]
+    pub export_confusion: bool,
+    // Also draw the calibration bins as `reliability_diagram.png`
+    #[config(default = false)]
+    pub export_reliability: bool,
+}
+
+// One confidence bin of the reliability diagram, `(lower, upper]`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ReliabilityBin {
+    pub lower: f32,
+    pub upper: f32,
+    // Mean confidence and accuracy of the samples in the bin (0 when the bin is empty)
+    pub confidence: f32,
+    pub accuracy: f32,
+    pub count: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CalibrationReport {
+    // Expected calibration error: bin |accuracy - confidence| weighted by the bin's sample share
+    pub ece: f32,
+    // Maximum calibration error: the largest |accuracy - confidence| over non-empty bins
+    pub mce: f32,
+    pub bins: Vec<ReliabilityBin>,
+}
+
+// Calibration of `(confidence, correct)` pairs over `num_bins` equal-width confidence bins.
+// Empty bins are reported with a zero count and take no part in either error.
+pub fn calibration(samples: &[(f32, bool)], num_bins: usize) -> CalibrationReport {
+    let mut bins = CalibrationBins::new(num_bins);
+    for &(confidence, correct) in samples {
+        bins.add(confidence, correct);
+    }
+    bins.report()
+}
+
+// The counts behind `calibration`, accumulated one sample at a time
+#[derive(Debug, Clone)]
+pub struct CalibrationBins {
+    counts: Vec<usize>,
+    confidence_sums: Vec<f64>,
+    correct_counts: Vec<usize>,
+}
+
+impl CalibrationBins {
+    pub fn new(num_bins: usize) -> Self {
+        let num_bins = num_bins.max(1);
+        Self { counts: vec![0; num_bins], confidence_sums: vec![0.0; num_bins], correct_counts: vec![0; num_bins] }
+    }
+
+    pub fn add(&mut self, confidence: f32, correct: bool) {
+        let num_bins = self.counts.len();
+        // Bins are right-closed, confidence 0 falls in the first one
+        let bin = ((confidence as f64 * num_bins as f64).ceil() as usize).clamp(1, num_bins) - 1;
+        self.counts[bin] += 1;
+        self.confidence_sums[bin] += confidence as f64;
+        self.correct_counts[bin] += correct as usize;
+    }
+
+    pub fn report(&self) -> CalibrationReport {
+        let num_bins = self.counts.len();
+        let total = self.counts.iter().sum::<usize>().max(1) as f64;
+        let (mut ece, mut mce) = (0.0f64, 0.0f64);
+        let bins = (0..num_bins)
+            .map(|bin| {
+                let count = self.counts[bin];
+                let (confidence, accuracy) = if count == 0 {
+                    (0.0, 0.0)
+                } else {
+                    let confidence = self.confidence_sums[bin] / count as f64;
+                    let accuracy = self.correct_counts[bin] as f64 / count as f64;
+                    let gap = (accuracy - confidence).abs();
+                    ece += gap * count as f64 / total;
+                    mce = mce.max(gap);
+                    (confidence, accuracy)
+                };
+
+                ReliabilityBin {
+                    lower: bin as f32 / num_bins as f32,
+                    upper: (bin + 1) as f32 / num_bins as f32,
+                    confidence: confidence as f32,
+                    accuracy: accuracy as f32,
+                    count,
+                }
+            })
+            .collect();
+
+        CalibrationReport { ece: ece as f32, mce: mce as f32, bins }
+    }
+}
+
+/// The reliability diagram of the model on the test set: accuracy against mean confidence in
+/// each of `num_bins` equal-width confidence bins, in increasing order. Empty bins are kept,
+/// with a zero count, so the counts sum to the size of the test set.
+pub fn reliability_diagram<B: Backend, M: Classifier<B> + ?Sized>(
+    model: &M,
+    device: &B::Device,
+    num_bins: usize,
+) -> Vec<ReliabilityBin> {
+    let mut bins = CalibrationBins::new(num_bins);
+    test_set_pass(model, device, |_, output, batch| {
+        for outcome in batch_outcomes(output, batch.targets) {
+            bins.add(outcome.confidence, outcome.is_correct());
+        }
+    });
+    bins.report().bins
+}
+
+// Side of the square plot area of the reliability diagram PNG, and of its margin, in pixels
+const RELIABILITY_PLOT_SIZE: u32 = 300;
+const RELIABILITY_MARGIN: u32 = 10;
+const RELIABILITY_BACKGROUND: [u8; 3] = [255, 255, 255];
+const RELIABILITY_DIAGONAL: [u8; 3] = [160, 160, 160];
+const RELIABILITY_ACCURACY: [u8; 3] = [70, 130, 180];
+const RELIABILITY_CONFIDENCE: [u8; 3] = [200, 40, 40];
+
+/// Writes `bins` as a reliability diagram PNG to `path`, see [`reliability_diagram_image`].
+pub fn save_reliability_diagram(bins: &[ReliabilityBin], path: &Path) -> image::ImageResult<()> {
+    reliability_diagram_image(bins).save(path)
+}
+
+/// `bins` drawn as a reliability diagram: a bar per bin up to its accuracy, a red tick at its
+/// mean confidence, and the diagonal of perfect calibration in gray, over confidence 0 to 1
+/// left to right and accuracy 0 to 1 bottom to top. Empty bins have no bar.
+pub fn reliability_diagram_image(bins: &[ReliabilityBin]) -> RgbImage {
+    let (size, margin) = (RELIABILITY_PLOT_SIZE, RELIABILITY_MARGIN);
+    let mut image = RgbImage::from_pixel(size + 2 * margin, size + 2 * margin, Rgb(RELIABILITY_BACKGROUND));
+    // Plot coordinates, from the bottom left corner, to image ones
+    let pixel = |x: f32, y: f32| (margin + (x * size as f32).round() as u32, margin + size - (y * size as f32).round() as u32);
+
+    for bin in bins.iter().filter(|bin| bin.count > 0) {
+        let (left, top) = pixel(bin.lower, bin.accuracy);
+        let (right, bottom) = pixel(bin.upper, 0.0);
+        let (_, tick) = pixel(0.0, bin.confidence);
+        // One pixel of space between neighbouring bars
+        for x in left + 1..right {
+            for y in top..bottom {
+                image.put_pixel(x, y, Rgb(RELIABILITY_ACCURACY));
+            }
+            for y in tick - 1..=tick.min(margin + size) {
+                image.put_pixel(x, y, Rgb(RELIABILITY_CONFIDENCE));
+            }
+        }
+    }
+    for step in 0..=size {
+        let t = step as f32 / size as f32;
+        let (x, y) = pixel(t, t);
+        image.put_pixel(x, y, Rgb(RELIABILITY_DIAGONAL));
+    }
+    for step in 0..=size {
+        // The axes: accuracy on the left, confidence at the bottom
+        image.put_pixel(margin, margin + step, Rgb([0, 0, 0]));
+        image.put_pixel(margin + step, margin + size, Rgb([0, 0, 0]));
+    }
+    image
+}
+
+// `(mean confidence, accuracy)` of each tenth of the test set, by increasing confidence: the
+// data behind a reliability diagram, with bins of equal counts rather than equal widths
+pub fn accuracy_by_confidence<B: Backend, M: Classifier<B> + ?Sized>(model: &M, device: &B::Device) -> Vec<(f32, f32)> {
+    confidence_deciles(&test_set_predictions(model, device))
+}
+
+// `accuracy_by_confidence` of any outcomes. Deciles differ in size by at most one sample, ties
+// in confidence split in dataset order, and there are fewer than ten with fewer samples.
+pub fn confidence_deciles(outcomes: &[SampleOutcome]) -> Vec<(f32, f32)> {
+    let mut sorted = outcomes.to_vec();
+    sorted.sort_by(|a, b| a.confidence.total_cmp(&b.confidence));
+
+    (0..10)
+        .map(|decile| &sorted[decile * sorted.len() / 10..(decile + 1) * sorted.len() / 10])
+        .filter(|decile| !decile.is_empty())
+        .map(|decile| {
+            let confidence = decile.iter().map(|outcome| outcome.confidence as f64).sum::<f64>();
+            let correct = decile.iter().filter(|outcome| outcome.is_correct()).count();
+            ((confidence / decile.len() as f64) as f32, correct as f32 / decile.len() as f32)
+        })
+        .collect()
+}
+
+// Writes the logits ([n, num_classes] f32), predicted labels and targets (both [n] i64) of the
+// whole test set into `dir` as `.npy` files, in dataset order, one batch at a time
+pub fn export_npy<B: Backend, M: Classifier<B> + ?Sized>(model: &M, device: &B::Device, dir: &str) -> io::Result<()> {
+    let mut export = NpyExport::create(dir)?;
+    let mut result = Ok(());
+
+    test_set_pass(model, device, |_, output, batch| {
+        if result.is_ok() {
+            result = export.write(output, batch.targets);
+        }
+    });
+    result?;
+    export.finish()
+}
+
+// The files of `export_npy`, appended to a batch at a time. The logits file is created with the
+// first batch, which gives the number of classes.
+struct NpyExport {
+    dir: String,
+    logits: Option<NpyWriter<f32>>,
+    predictions: NpyWriter<i64>,
+    targets: NpyWriter<i64>,
+}
+
+impl NpyExport {
+    fn create(dir: &str) -> io::Result<Self> {
+        Ok(Self {
+            dir: dir.to_string(),
+            logits: None,
+            predictions: NpyWriter::create(format!("{dir}/predictions.npy"), &[])?,
+            targets: NpyWriter::create(format!("{dir}/targets.npy"), &[])?,
+        })
+    }
+
+    fn write<B: Backend>(&mut self, output: Tensor<B, 2>, targets: Tensor<B, 1, Int>) -> io::Result<()> {
+        let writer = match &mut self.logits {
+            Some(writer) => writer,
+            None => self.logits.insert(NpyWriter::create(format!("{}/logits.npy", self.dir), &[output.dims()[1]])?),
+        };
+        let predicted = output.clone().argmax(1).flatten::<1>(0, 1);
+        writer.write(&output.into_data().convert::<f32>().value)?;
+        self.predictions.write(&predicted.into_data().convert::<i64>().value)?;
+        self.targets.write(&targets.into_data().convert::<i64>().value)
+    }
+
+    // Writes the final row counts into the headers
+    fn finish(self) -> io::Result<()> {
+        if let Some(logits) = self.logits {
+            logits.finish()?;
+        }
+        self.predictions.finish()?;
+        self.targets.finish()
+    }
+}
+
+/// The predicted label and target of every test sample, in dataset order, as saved by
+/// [`dump_predictions`].
+#[derive(Record)]
+pub struct PredictionDump<B: Backend> {
+    pub predictions: Tensor<B, 1, Int>,
+    pub targets: Tensor<B, 1, Int>,
+}
+
+// The recorder of `dump_predictions`: full precision keeps every label whatever the class count
+type DumpRecorder = NamedMpkFileRecorder<FullPrecisionSettings>;
+
+/// Runs the whole test set through `model` and saves the predicted labels and the targets, both
+/// `[N]` tensors, as one [`PredictionDump`] record at `path` (its extension becomes `.mpk`).
+pub fn dump_predictions<B: Backend, M: Classifier<B> + ?Sized>(
+    model: &M,
+    device: &B::Device,
+    path: &str,
+) -> Result<(), RecorderError> {
+    dump_dataset_predictions(model, &MnistDataset::test(), device, path)
+}
+
+// `dump_predictions` of any dataset of MNIST items
+pub fn dump_dataset_predictions<B: Backend, M: Classifier<B> + ?Sized, D: Dataset<MnistItem>>(
+    model: &M,
+    dataset: &D,
+    device: &B::Device,
+    path: &str,
+) -> Result<(), RecorderError> {
+    let outcomes = dataset_predictions(model, dataset, device);
+    let tensor = |values: Vec<i64>| {
+        let shape = Shape::new([values.len()]);
+        Tensor::<B, 1, Int>::from_data(Data::new(values, shape).convert(), device)
+    };
+    let dump = PredictionDump {
+        predictions: tensor(outcomes.iter().map(|outcome| outcome.predicted as i64).collect()),
+        targets: tensor(outcomes.iter().map(|outcome| outcome.target as i64).collect()),
+    };
+    DumpRecorder::new().record(dump, path.into())
+}
+
+/// Reads a [`PredictionDump`] saved by [`dump_predictions`] at `path`.
+pub fn load_predictions<B: Backend>(path: &str, device: &B::Device) -> Result<PredictionDump<B>, RecorderError> {
+    DumpRecorder::new().load(path.into(), device)
+}
+
+// Area under the ROC curve of `(positive score, is positive)` pairs: the probability that a
+// random positive scores above a random negative, ties counting half. `None` without both a
+// positive and a negative sample.
+pub fn roc_auc(samples: &[(f32, bool)]) -> Option<f32> {
+    let mut sorted = samples.to_vec();
+    sorted.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    // Mann-Whitney U from the ranks of the positives, tied scores sharing their mean rank
+    let (mut positive_rank_sum, mut start) = (0.0f64, 0);
+    while start < sorted.len() {
+        let end = start + sorted[start..].iter().take_while(|sample| sample.0 == sorted[start].0).count();
+        let mean_rank = (start + end + 1) as f64 / 2.0;
+        positive_rank_sum += mean_rank * sorted[start..end].iter().filter(|sample| sample.1).count() as f64;
+        start = end;
+    }
+
+    let positives = sorted.iter().filter(|sample| sample.1).count() as f64;
+    let negatives = sorted.len() as f64 - positives;
+    if positives == 0.0 || negatives == 0.0 {
+        return None;
+    }
+    let u = positive_rank_sum - positives * (positives + 1.0) / 2.0;
+    Some((u / (positives * negatives)) as f32)
+}
+
+// Scores of a one-vs-rest model, class 1 being the positive class
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BinaryReport {
+    // Original label of the positive class
+    pub positive_class: usize,
+    // At the default threshold: positive when its probability is the larger one
+    pub precision: f32,
+    pub recall: f32,
+    pub f1: f32,
+    // From the positive-class probability, `None` when the test set lacks one of the classes
+    pub roc_auc: Option<f32>,
+}
+
+// Counts and positive-class scores of a one-vs-rest model, over outcomes relabeled to 0 / 1
+#[derive(Debug, Clone)]
+struct BinaryCounts {
+    positive_class: usize,
+    // Samples by (predicted, target)
+    counts: [[usize; 2]; 2],
+    // `(positive score, is positive)` of every sample, for the ROC-AUC
+    scores: Vec<(f32, bool)>,
+}
+
+impl BinaryCounts {
+    fn add(&mut self, outcome: SampleOutcome) {
+        if outcome.predicted < 2 {
+            self.counts[outcome.predicted][outcome.target] += 1;
+        }
+        // Two classes: the positive probability is the confidence or its complement
+        let positive = if outcome.predicted == 1 { outcome.confidence } else { 1.0 - outcome.confidence };
+        self.scores.push((positive, outcome.target == 1));
+    }
+
+    fn report(&self) -> BinaryReport {
+        let count = |predicted: usize, target: usize| self.counts[predicted][target] as f32;
+        let (true_pos, false_pos, false_neg) = (count(1, 1), count(1, 0), count(0, 1));
+        let ratio = |num: f32, den: f32| if den > 0.0 { num / den } else { 0.0 };
+
+        let precision = ratio(true_pos, true_pos + false_pos);
+        let recall = ratio(true_pos, true_pos + false_neg);
+        BinaryReport {
+            positive_class: self.positive_class,
+            precision,
+            recall,
+            f1: ratio(2.0 * precision * recall, precision + recall),
+            roc_auc: roc_auc(&self.scores),
+        }
+    }
+}
+
+/// The scores of [`evaluate`], accumulated a batch of outcomes at a time so that a test set of
+/// any size is scored in a single pass without keeping its logits or outcomes around: memory
+/// stays that of the counts, apart from one-vs-rest models, whose ROC-AUC needs the 8-byte
+/// positive score of every sample.
+#[derive(Debug, Clone)]
+pub struct EvalAccumulator {
+    num_samples: usize,
+    correct: usize,
+    confusion_matrix: ConfusionMatrix,
+    calibration: CalibrationBins,
+    binary: Option<BinaryCounts>,
+}
+
+impl EvalAccumulator {
+    /// Scores over `calibration_bins` calibration bins, one-vs-rest when trained on
+    /// `binary_target`: raw test labels are then scored as 1 for that class and 0 for the rest.
+    pub fn new(calibration_bins: usize, binary_target: Option<usize>) -> Self {
+        Self {
+            num_samples: 0,
+            correct: 0,
+            confusion_matrix: [[0; MNIST_NUM_CLASSES]; MNIST_NUM_CLASSES],
+            calibration: CalibrationBins::new(calibration_bins),
+            binary: binary_target.map(|positive_class| BinaryCounts {
+                positive_class,
+                counts: [[0; 2]; 2],
+                scores: Vec::new(),
+            }),
+        }
+    }
+
+    pub fn add(&mut self, mut outcome: SampleOutcome) {
+        if let Some(binary) = &self.binary {
+            outcome.target = (outcome.target == binary.positive_class) as usize;
+        }
+        self.num_samples += 1;
+        self.correct += outcome.is_correct() as usize;
+        // A head wider than the dataset can predict labels that never occur as targets
+        if outcome.predicted < MNIST_NUM_CLASSES {
+            self.confusion_matrix[outcome.target][outcome.predicted] += 1;
+        }
+        self.calibration.add(outcome.confidence, outcome.is_correct());
+        if let Some(binary) = &mut self.binary {
+            binary.add(outcome);
+        }
+    }
+
+    pub fn extend(&mut self, outcomes: impl IntoIterator<Item = SampleOutcome>) {
+        for outcome in outcomes {
+            self.add(outcome);
+        }
+    }
+
+    /// The report of every outcome added so far, classes named by `labels`.
+    pub fn report(&self, labels: ClassLabels) -> EvalReport {
+        EvalReport {
+            num_samples: self.num_samples,
+            accuracy: self.correct as f32 / self.num_samples.max(1) as f32,
+            confusion_matrix: self.confusion_matrix,
+            calibration: self.calibration.report(),
+            binary: self.binary.as_ref().map(BinaryCounts::report),
+            labels,
+        }
+    }
+}
+
+/// Content of `eval.json`, as returned by [`evaluate`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EvalReport {
+    pub num_samples: usize,
+    pub accuracy: f32,
+    pub confusion_matrix: ConfusionMatrix,
+    pub calibration: CalibrationReport,
+    /// Precision, recall, F1 and ROC-AUC of one-vs-rest models, whose test labels are then
+    /// scored as 1 for the positive class and 0 for the rest (also in the confusion matrix).
+    pub binary: Option<BinaryReport>,
+    // Names of the classes, by index into the confusion matrix
+    pub labels: ClassLabels,
+}
+
+// Scores the test labels of a one-vs-rest model trained on `binary_target` as 1 for that class
+// and 0 for the rest, like its predictions
+pub(crate) fn relabel_binary(mut outcomes: Vec<SampleOutcome>, binary_target: Option<usize>) -> Vec<SampleOutcome> {
+    if let Some(target) = binary_target {
+        for outcome in &mut outcomes {
+            outcome.target = (outcome.target == target) as usize;
+        }
+    }
+    outcomes
+}
+
+// Fraction of `outcomes` that are correct
+pub(crate) fn outcome_accuracy(outcomes: &[SampleOutcome]) -> f32 {
+    outcomes.iter().filter(|outcome| outcome.is_correct()).count() as f32 / outcomes.len().max(1) as f32
+}
+
+impl fmt::Display for EvalReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Accuracy: {:.2}% over {} samples", self.accuracy * 100.0, self.num_samples)?;
+        write!(f, "ECE: {:.4}  MCE: {:.4}", self.calibration.ece, self.calibration.mce)?;
+        if let Some(binary) = &self.binary {
+            let roc_auc = binary.roc_auc.map_or("n/a".to_string(), |auc| format!("{auc:.4}"));
+            write!(
+                f,
+                "\n{} vs rest: precision {:.4}  recall {:.4}  F1 {:.4}  ROC-AUC {roc_auc}",
+                binary.positive_class, binary.precision, binary.recall, binary.f1
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// One point of the risk-coverage curve of [`evaluate_with_reject`]: predictions less
+/// confident than `threshold` are rejected, `coverage` is the fraction of samples left and
+/// `accuracy` the accuracy on them (0 when every sample is rejected).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RejectPoint {
+    pub threshold: f32,
+    pub coverage: f32,
+    pub accuracy: f32,
+    pub num_covered: usize,
+}
+
+impl fmt::Display for RejectPoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Reject below {:.2}: coverage {:.2}%, accuracy {:.2}% over {} samples",
+            self.threshold,
+            self.coverage * 100.0,
+            self.accuracy * 100.0,
+            self.num_covered
+        )
+    }
+}
+
+// The risk-coverage curve of `outcomes`, a point per threshold in the given order. A threshold
+// of 0 covers every sample, so its accuracy is the plain accuracy.
+pub fn risk_coverage(outcomes: &[SampleOutcome], thresholds: &[f32]) -> Vec<RejectPoint> {
+    let mut curve = RiskCoverage::new(thresholds);
+    for &outcome in outcomes {
+        curve.add(outcome);
+    }
+    curve.points()
+}
+
+// The counts behind `risk_coverage`, accumulated one sample at a time
+#[derive(Debug, Clone)]
+pub struct RiskCoverage {
+    thresholds: Vec<f32>,
+    num_samples: usize,
+    // Samples covered by each threshold, and those of them that are correct
+    covered: Vec<usize>,
+    correct: Vec<usize>,
+}
+
+impl RiskCoverage {
+    pub fn new(thresholds: &[f32]) -> Self {
+        Self {
+            thresholds: thresholds.to_vec(),
+            num_samples: 0,
+            covered: vec![0; thresholds.len()],
+            correct: vec![0; thresholds.len()],
+        }
+    }
+
+    pub fn add(&mut self, outcome: SampleOutcome) {
+        self.num_samples += 1;
+        for (position, &threshold) in self.thresholds.iter().enumerate() {
+            if outcome.confidence >= threshold {
+                self.covered[position] += 1;
+                self.correct[position] += outcome.is_correct() as usize;
+            }
+        }
+    }
+
+    pub fn points(&self) -> Vec<RejectPoint> {
+        self.thresholds
+            .iter()
+            .enumerate()
+            .map(|(position, &threshold)| RejectPoint {
+                threshold,
+                coverage: self.covered[position] as f32 / self.num_samples.max(1) as f32,
+                accuracy: self.correct[position] as f32 / self.covered[position].max(1) as f32,
+                num_covered: self.covered[position],
+            })
+            .collect()
+    }
+}
+
+/// Evaluates the model trained in `artifact_dir` on the test set with the reject option of
+/// [`Prediction::reject_below`](crate::Prediction::reject_below), at every one of `thresholds`,
+/// and writes the resulting risk-coverage curve as `eval_reject.json` there.
+pub fn evaluate_with_reject<B: Backend>(
+    artifact_dir: &str,
+    thresholds: &[f32],
+    device: &B::Device,
+) -> Result<Vec<RejectPoint>, EvalError> {
+    let model = load_model::<B>(artifact_dir, device)?;
+    let binary_target = ModelMeta::load(artifact_dir)?.and_then(|meta| meta.binary_target);
+    let mut accumulator = RiskCoverage::new(thresholds);
+    test_set_pass(&model, device, |_, output, batch| {
+        for outcome in relabel_binary(batch_outcomes(output, batch.targets), binary_target) {
+            accumulator.add(outcome);
+        }
+    });
+    let curve = accumulator.points();
+
+    let json = serde_json::to_string_pretty(&curve).expect("Risk-coverage curve should serialize");
+    std::fs::write(format!("{artifact_dir}/eval_reject.json"), json)?;
+    Ok(curve)
+}
+
+#[derive(Debug)]
+pub enum EvalError {
+    Load(LoadError),
+    // A model from outside the artifact dirs, such as an ONNX file, could not be read
+    Model(String),
+    Io(io::Error),
+    Image(image::ImageError),
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvalError::Load(err) => write!(f, "cannot evaluate the model: {err}"),
+            EvalError::Model(err) => write!(f, "could not read the model to evaluate: {err}"),
+            EvalError::Io(err) => write!(f, "could not write the evaluation results: {err}"),
+            EvalError::Image(err) => write!(f, "could not write the evaluation plots: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for EvalError {}
+
+impl From<LoadError> for EvalError {
+    fn from(err: LoadError) -> Self {
+        EvalError::Load(err)
+    }
+}
+
+impl From<io::Error> for EvalError {
+    fn from(err: io::Error) -> Self {
+        EvalError::Io(err)
+    }
+}
+
+impl From<image::ImageError> for EvalError {
+    fn from(err: image::ImageError) -> Self {
+        EvalError::Image(err)
+    }
+}
+
+/// Evaluates the model trained in `artifact_dir` (a path or an [`ArtifactDir`]) on the test set
+/// and writes `eval.json` there.
+pub fn evaluate<B: Backend>(
+    artifact_dir: impl AsRef<Path>,
+    config: &EvaluationConfig,
+    device: &B::Device,
+) -> Result<EvalReport, EvalError> {
+    let dir = ArtifactDir::new(artifact_dir);
+    dir.validate()?;
+    let model = load_model::<B>(&dir, device)?;
+    let binary_target = ModelMeta::load(dir.as_str())?.and_then(|meta| meta.binary_target);
+    evaluate_model(&model, ClassLabels::load(dir.as_str()), binary_target, config, dir.as_str(), device)
+}
+
+/// [`evaluate`] of a run kept in a checkpoint store, loaded with [`load_model_from`]. Writes
+/// `eval.json` and the exports into `output_dir`, which must exist.
+pub fn evaluate_from<B: Backend>(
+    backend: &dyn CheckpointBackend,
+    config: &EvaluationConfig,
+    output_dir: &str,
+    device: &B::Device,
+) -> Result<EvalReport, EvalError> {
+    let model = load_model_from::<B>(backend, device)?;
+    let meta = ModelMeta::load_from(backend)?;
+    // Runs from before `classes.json` existed name their classes by index
+    let labels = backend
+        .get(CLASSES_FILE)
+        .ok()
+        .and_then(|json| serde_json::from_slice(&json).ok())
+        .unwrap_or_else(|| ClassLabels::indices(meta.as_ref().map_or(0, |meta| meta.num_classes)));
+    let binary_target = meta.and_then(|meta| meta.binary_target);
+    evaluate_model(&model, labels, binary_target, config, output_dir, device)
+}
+
+/// [`evaluate`] of a model already loaded, native or imported, named by `labels` and scored
+/// one-vs-rest when trained on `binary_target`. Writes `eval.json` and the exports into
+/// `output_dir`, which must exist.
+pub fn evaluate_model<B: Backend, M: Classifier<B> + ?Sized>(
+    model: &M,
+    labels: ClassLabels,
+    binary_target: Option<usize>,
+    config: &EvaluationConfig,
+    output_dir: &str,
+    device: &B::Device,
+) -> Result<EvalReport, EvalError> {
+    evaluate_dataset(model, &MnistDataset::test(), labels, binary_target, config, output_dir, device)
+}
+
+/// [`evaluate_model`] over any dataset of MNIST items. The dataset is read in a single pass, a
+/// batch at a time, every batch updating an [`EvalAccumulator`] and being appended to the NPY
+/// exports before the next one is read, so memory does not grow with the size of the dataset.
+pub fn evaluate_dataset<B: Backend, M: Classifier<B> + ?Sized, D: Dataset<MnistItem>>(
+    model: &M,
+    dataset: &D,
+    labels: ClassLabels,
+    binary_target: Option<usize>,
+    config: &EvaluationConfig,
+    output_dir: &str,
+    device: &B::Device,
+) -> Result<EvalReport, EvalError> {
+    let mut accumulator = EvalAccumulator::new(config.calibration_bins, binary_target);
+    let mut npy = config.export_npy.then(|| NpyExport::create(output_dir)).transpose()?;
+    // The first failed write of the exports, the batches after it are not written
+    let mut npy_written = Ok(());
+
+    dataset_pass(model, dataset, device, |_, output, batch| {
+        if let (Some(npy), true) = (&mut npy, npy_written.is_ok()) {
+            npy_written = npy.write(output.clone(), batch.targets.clone());
+        }
+        accumulator.extend(batch_outcomes(output, batch.targets));
+    });
+    npy_written?;
+    if let Some(npy) = npy {
+        npy.finish()?;
+    }
+    let report = accumulator.report(labels);
+
+    if config.export_confusion {
+        let dir = Path::new(output_dir);
+        save_confusion_matrix(
+            &report.confusion_matrix,
+            &report.labels,
+            &dir.join("confusion_matrix.csv"),
+            &dir.join("confusion_matrix.png"),
+        )?;
+    }
+
+    if config.export_reliability {
+        save_reliability_diagram(&report.calibration.bins, &Path::new(output_dir).join("reliability_diagram.png"))?;
+    }
+
+    let json = serde_json::to_string_pretty(&report).expect("Report should serialize");
+    std::fs::write(ArtifactDir::new(output_dir).eval_report_path(), json)?;
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        synthetic::{trained_model, SyntheticDigits},
+        Model, ModelConfig,
+    };
+    use burn::{
+        backend::{ndarray::NdArrayDevice, NdArray},
+        module::{ModuleMapper, ParamId},
+    };
+
+    #[test]
+    fn noise_robustness_rejects_invalid_sigmas() {
+        let device = NdArrayDevice::default();
+        let model = ModelConfig::new(10, 8).init::<NdArray>(&device);
+
+        for sigma in [-0.1, f32::NAN, f32::INFINITY] {
+            let err = noise_robustness(&model, &device, &[0.0, sigma]).unwrap_err();
+            assert_eq!(err.field, "sigmas");
+        }
+    }
+
+    #[test]
+    fn misclassified_indices_are_the_wrong_predictions() {
+        let device = NdArrayDevice::default();
+        let model = ModelConfig::new(10, 8).init::<NdArray>(&device);
+        let digits = SyntheticDigits::new(100, 1);
+
+        let indices = dataset_misclassified_indices(&model, &digits, &device);
+        let outcomes = dataset_predictions(&model, &digits, &device);
+        let expected = ((1.0 - outcome_accuracy(&outcomes)) * outcomes.len() as f32).round() as usize;
+        assert_eq!(indices.len(), expected);
+        assert!(indices.windows(2).all(|pair| pair[0] < pair[1]), "{indices:?} are not in dataset order");
+        assert!(indices.iter().all(|&index| !outcomes[index].is_correct()));
+    }
+
+    #[test]
+    fn noise_robustness_degrades_from_the_clean_accuracy() {
+        let device = NdArrayDevice::default();
+        let model = trained_model();
+        let digits = SyntheticDigits::new(200, 3);
+
+        let accuracies = dataset_noise_robustness(&model, &digits, &device, &[0.0, 1.0, 4.0]).unwrap();
+        let clean = outcome_accuracy(&dataset_predictions(&model, &digits, &device));
+        let sigmas: Vec<f32> = accuracies.iter().map(|(sigma, _)| *sigma).collect();
+        assert_eq!(sigmas, [0.0, 1.0, 4.0]);
+        assert_eq!(accuracies[0].1, clean);
+        assert!(accuracies[1].1 < clean && accuracies[2].1 < accuracies[1].1, "{accuracies:?} from {clean}");
+    }
+
+    #[test]
+    fn calibration_errors_weight_the_non_empty_bins_by_their_share() {
+        // Bin (0, 0.25]: confidence 0.2, accuracy 0.5. Bin (0.75, 1]: confidence 0.9, accuracy 0.75
+        let samples = [(0.2, false), (0.2, true), (0.9, true), (0.9, false), (0.9, true), (0.9, true)];
+
+        let report = calibration(&samples, 4);
+        let counts: Vec<usize> = report.bins.iter().map(|bin| bin.count).collect();
+        assert_eq!(counts, [2, 0, 0, 4]);
+        assert!((report.bins[3].accuracy - 0.75).abs() < 1e-6);
+        // 2/6 * 0.3 + 4/6 * 0.15, the empty bins do not count as zero accuracy
+        assert!((report.ece - 0.2).abs() < 1e-6, "ece {}", report.ece);
+        assert!((report.mce - 0.3).abs() < 1e-6, "mce {}", report.mce);
+
+        let mut accumulator = EvalAccumulator::new(4, None);
+        accumulator.extend(samples.iter().map(|&(confidence, correct)| SampleOutcome {
+            target: 1,
+            predicted: if correct { 1 } else { 2 },
+            confidence,
+        }));
+        let eval = accumulator.report(ClassLabels::indices(10));
+        assert_eq!(eval.calibration, report);
+        assert!((eval.accuracy - 4.0 / 6.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn confusion_matrix_files_hold_every_test_sample() {
+        let device = NdArrayDevice::default();
+        let model = ModelConfig::new(10, 8).init::<NdArray>(&device);
+        let mut accumulator = EvalAccumulator::new(15, None);
+        accumulator.extend(dataset_predictions(&model, &SyntheticDigits::new(50, 1), &device));
+        let matrix = accumulator.report(ClassLabels::indices(10)).confusion_matrix;
+        let dir = std::env::temp_dir().join("my_first_rust_DL_app-confusion");
+        std::fs::create_dir_all(&dir).unwrap();
+        let (csv_path, png_path) = (dir.join("confusion_matrix.csv"), dir.join("confusion_matrix.png"));
+
+        save_confusion_matrix(&matrix, &ClassLabels::indices(10), &csv_path, &png_path).unwrap();
+        let csv = std::fs::read_to_string(&csv_path).unwrap();
+        let heatmap = image::open(&png_path).unwrap().to_rgb8();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "true\\predicted,0,1,2,3,4,5,6,7,8,9");
+        assert_eq!(lines.len(), 11);
+        let cells: Vec<u32> = lines[1..]
+            .iter()
+            .flat_map(|line| line.split(',').skip(1).map(|cell| cell.parse::<u32>().unwrap()))
+            .collect();
+        assert_eq!(cells.len(), 100);
+        assert_eq!(cells.iter().sum::<u32>(), 50);
+        let side = HEATMAP_CELL_SIZE * 10;
+        assert_eq!(heatmap.dimensions(), (side, side));
+        // Cells without samples stay white
+        let empty = (0..100).find(|&cell| cells[cell] == 0).unwrap() as u32;
+        let (x, y) = ((empty % 10) * HEATMAP_CELL_SIZE, (empty / 10) * HEATMAP_CELL_SIZE);
+        assert_eq!(heatmap.get_pixel(x, y).0, HEATMAP_EMPTY);
+    }
+
+    #[test]
+    fn confidence_deciles_split_the_sorted_outcomes_into_equal_counts() {
+        // 25 outcomes out of order, the 13 most confident ones correct
+        let outcomes: Vec<SampleOutcome> = (0..25)
+            .map(|i| (i * 7) % 25)
+            .map(|i| SampleOutcome { target: 0, predicted: usize::from(i < 12), confidence: i as f32 / 25.0 })
+            .collect();
+
+        let deciles = confidence_deciles(&outcomes);
+        assert_eq!(deciles.len(), 10);
+        assert!(deciles.windows(2).all(|pair| pair[0].0 < pair[1].0), "{deciles:?}");
+        // Sorted, the deciles hold 2, 3, 2, 3... outcomes: [10, 12) is the last wrong one
+        assert!((deciles[0].0 - 0.5 / 25.0).abs() < 1e-6);
+        assert!((deciles[1].0 - 3.0 / 25.0).abs() < 1e-6);
+        let accuracies: Vec<f32> = deciles.iter().map(|&(_, accuracy)| accuracy).collect();
+        assert_eq!(accuracies, [0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 1.0, 1.0, 1.0, 1.0]);
+
+        // Fewer than ten outcomes make fewer deciles
+        assert_eq!(confidence_deciles(&outcomes[..4]).len(), 4);
+        assert!(confidence_deciles(&[]).is_empty());
+    }
+
+    #[test]
+    fn dumped_predictions_load_back_with_every_sample() {
+        let device = NdArrayDevice::default();
+        let model = ModelConfig::new(10, 8).init::<NdArray>(&device);
+        let dataset = SyntheticDigits::new(37, 1);
+        let path = std::env::temp_dir().join("my_first_rust_DL_app-prediction-dump");
+        let path = path.to_str().unwrap();
+
+        dump_dataset_predictions(&model, &dataset, &device, path).unwrap();
+        let dump = load_predictions::<NdArray>(path, &device).unwrap();
+        std::fs::remove_file(format!("{path}.mpk")).unwrap();
+
+        let outcomes = dataset_predictions(&model, &dataset, &device);
+        let values = |tensor: Tensor<NdArray, 1, Int>| tensor.into_data().convert::<i64>().value;
+        let predicted: Vec<i64> = outcomes.iter().map(|outcome| outcome.predicted as i64).collect();
+        let targets: Vec<i64> = outcomes.iter().map(|outcome| outcome.target as i64).collect();
+        assert_eq!(values(dump.predictions), predicted);
+        assert_eq!(values(dump.targets), targets);
+        assert_eq!(targets, (0..37).map(|index| index % 10).collect::<Vec<i64>>());
+    }
+
+    #[test]
+    fn reliability_diagrams_draw_a_bar_and_a_tick_per_non_empty_bin() {
+        // Bin (0, 0.5] is empty, bin (0.5, 1] has confidence 0.75 and accuracy 0.5
+        let bins = calibration(&[(0.7, true), (0.8, false)], 2).bins;
+        let image = reliability_diagram_image(&bins);
+        assert_eq!(image.dimensions(), (320, 320));
+
+        let color = |x, y| image.get_pixel(x, y).0;
+        // The bar spans x in (160, 310) up to accuracy 0.5 at y 160, the tick sits at y 85
+        assert_eq!(color(200, 250), RELIABILITY_ACCURACY);
+        assert_eq!(color(200, 161), RELIABILITY_ACCURACY);
+        assert_eq!(color(200, 150), RELIABILITY_BACKGROUND);
+        assert_eq!(color(200, 85), RELIABILITY_CONFIDENCE);
+        assert_eq!(color(160, 250), RELIABILITY_BACKGROUND);
+        // No bar for the empty bin, only the diagonal and the axes
+        assert_eq!(color(80, 250), RELIABILITY_BACKGROUND);
+        assert_eq!(color(80, 240), RELIABILITY_DIAGONAL);
+        assert_eq!((color(10, 100), color(100, 310)), ([0, 0, 0], [0, 0, 0]));
+
+        let path = std::env::temp_dir().join("my_first_rust_DL_app-reliability_diagram.png");
+        save_reliability_diagram(&bins, &path).unwrap();
+        let saved = image::open(&path).unwrap().to_rgb8();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(saved, image);
+    }
+
+    #[test]
+    fn streaming_evaluation_matches_scoring_the_collected_predictions() {
+        let device = NdArrayDevice::default();
+        let dataset = SyntheticDigits::new(300, 1);
+        let dir = std::env::temp_dir().join("my_first_rust_DL_app-evaluate_dataset");
+        std::fs::create_dir_all(&dir).unwrap();
+        let output_dir = dir.to_str().unwrap();
+        let config = EvaluationConfig::new().with_export_npy(true).with_export_reliability(true);
+
+        for (num_classes, binary_target) in [(10, None), (2, Some(3))] {
+            let model = ModelConfig::new(num_classes, 8).init::<NdArray>(&device);
+            let labels = ClassLabels::indices(num_classes);
+            let report =
+                evaluate_dataset(&model, &dataset, labels.clone(), binary_target, &config, output_dir, &device)
+                    .unwrap();
+
+            let mut naive = EvalAccumulator::new(15, binary_target);
+            naive.extend(dataset_predictions(&model, &dataset, &device));
+            assert_eq!(report, naive.report(labels));
+            assert_eq!(report.num_samples, 300);
+            assert_eq!(report.binary.is_some(), binary_target.is_some());
+            let written: EvalReport =
+                serde_json::from_str(&std::fs::read_to_string(dir.join("eval.json")).unwrap()).unwrap();
+            assert_eq!(written, report);
+            let logits = std::fs::read(dir.join("logits.npy")).unwrap();
+            let header = String::from_utf8_lossy(&logits[..128]).to_string();
+            assert!(header.contains(&format!("(300, {num_classes})")), "{header}");
+            assert!(dir.join("reliability_diagram.png").exists());
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn margins_are_large_when_peaked_and_small_for_near_ties() {
+        let device = NdArrayDevice::default();
+        let logits = Tensor::<NdArray, 2>::from_floats(
+            [[10.0, 0.0, 0.0], [1.0, 0.99, -5.0], [-1.0, 2.0, 2.0], [0.0, 0.0, 0.0]],
+            &device,
+        );
+
+        let margins = logit_margins(logits);
+        assert_eq!(margins.len(), 4);
+        assert!(margins[0] > 0.999, "{margins:?}");
+        assert!((margins[1] - 0.005).abs() < 1e-3, "{margins:?}");
+        assert_eq!(margins[2..], [0.0, 0.0]);
+        assert_eq!(logit_margins(Tensor::<NdArray, 2>::from_floats([[-3.0], [4.0]], &device)), [1.0, 1.0]);
+    }
+
+    #[test]
+    fn dataset_margins_follow_the_dataset_order() {
+        let device = NdArrayDevice::default();
+        let model = trained_model();
+        let dataset = SyntheticDigits::new(300, 3);
+
+        let margins = dataset_margins(&model, &dataset, &device);
+        assert_eq!(margins.len(), 300);
+        assert!(margins.iter().all(|margin| (0.0..=1.0).contains(margin)), "{margins:?}");
+        // In order across the batches of the pass
+        for index in [0, 150, 299] {
+            let item = dataset.get(index).unwrap();
+            let batch = MnistBatcher::<NdArray>::new(device).batch(vec![item]);
+            let expected = logit_margins(model.forward(batch.images))[0];
+            assert!((margins[index] - expected).abs() < 1e-6, "sample {index}: {} vs {expected}", margins[index]);
+        }
+    }
+
+    // Multiplies the weight of `linear2`, the only `[hidden_size, num_classes]` parameter of a
+    // `ModelConfig::new(num_classes, hidden_size)` model with hidden_size != num_classes, by a
+    // factor, or every parameter with `all`
+    struct ScaleParams {
+        factor: f32,
+        shape: [usize; 2],
+        all: bool,
+    }
+
+    impl ModuleMapper<NdArray> for ScaleParams {
+        fn map_float<const D: usize>(&mut self, _: &ParamId, tensor: Tensor<NdArray, D>) -> Tensor<NdArray, D> {
+            match self.all || tensor.dims()[..] == self.shape[..] {
+                true => tensor.mul_scalar(self.factor),
+                false => tensor,
+            }
+        }
+    }
+
+    #[test]
+    fn sensitivity_is_zero_for_zero_weights_and_scales_with_the_head() {
+        let device = NdArrayDevice::default();
+        let dataset = SyntheticDigits::new(40, 1);
+        let model = trained_model();
+        let scaled = |factor, all| model.clone().map(&mut ScaleParams { factor, shape: [16, 10], all });
+
+        let measure = |model: &Model<NdArray>, num_samples| {
+            dataset_sensitivity(model, &dataset, &device, num_samples, 0.5).unwrap()
+        };
+
+        let base = measure(&model, 30);
+        assert!(base.is_finite() && base > 0.0, "{base}");
+        assert_eq!(measure(&scaled(0.0, true), 30), 0.0);
+        // The logits move twice as far when the last layer is doubled, on the same noise
+        let doubled = measure(&scaled(2.0, false), 30);
+        assert!((doubled / base - 2.0).abs() < 1e-3, "{doubled} vs {base}");
+        // All of a small dataset, and the same samples and noise on every call
+        assert_eq!(measure(&model, 1000), measure(&model, 40));
+        assert_eq!(measure(&model, 30), base);
+    }
+
+    #[test]
+    fn sensitivity_rejects_invalid_epsilons() {
+        let device = NdArrayDevice::default();
+        let model = ModelConfig::new(10, 8).init::<NdArray>(&device);
+        let dataset = SyntheticDigits::new(4, 1);
+
+        for epsilon in [0.0, -0.5, f32::NAN] {
+            let err = sensitivity(&model, &device, 4, epsilon).unwrap_err();
+            assert_eq!(err.field, "epsilon");
+            let err = dataset_sensitivity(&model, &dataset, &device, 4, epsilon).unwrap_err();
+            assert_eq!(err.field, "epsilon");
+        }
+    }
+}
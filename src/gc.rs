@@ -0,0 +1,305 @@
+use crate::{artifact::LOCK_FILE, history::History};
+use std::{
+    collections::HashMap,
+    fmt, fs, io,
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+// What makes a directory a run: training writes its config before anything else
+const MANIFEST_FILE: &str = "config.json";
+// The final weights, missing from interrupted runs (and from runs with `save_model` off)
+const MODEL_FILE: &str = "model.mpk";
+
+// One directory found under an artifacts root, with what `select_runs` decides on
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunRecord {
+    pub path: String,
+    // The directory the run is in when it is not directly under the root, `""` otherwise
+    pub sweep: String,
+    // Whether the run has a `config.json`. Directories without one are never deleted unless
+    // `include_unknown` is set.
+    pub has_manifest: bool,
+    pub has_model: bool,
+    // Whether a training run holds the lock of the directory, still writing it: locked runs are
+    // never deleted
+    pub locked: bool,
+    // Best epoch mean of the validation accuracy, in percent, when the history records one
+    pub best_valid_accuracy: Option<f64>,
+    // Since the last change to any file of the run
+    pub age: Duration,
+    pub size_bytes: u64,
+}
+
+// Which runs `select_runs` deletes: those matching any of the criteria set. None set selects
+// nothing.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GcCriteria {
+    pub older_than: Option<Duration>,
+    // Runs whose best validation accuracy is below this, in percent. Runs that record none
+    // (multi-label or unfinished runs) are not selected by it.
+    pub min_accuracy: Option<f64>,
+    // Runs without final weights
+    pub interrupted: bool,
+    // Everything but the `k` most accurate runs of each sweep, unscored runs ranked last
+    pub keep_top_k: Option<usize>,
+    pub include_unknown: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GcReason {
+    Old,
+    LowAccuracy,
+    Interrupted,
+    NotTopK,
+}
+
+impl fmt::Display for GcReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            GcReason::Old => "old",
+            GcReason::LowAccuracy => "low accuracy",
+            GcReason::Interrupted => "interrupted",
+            GcReason::NotTopK => "not in the top k",
+        })
+    }
+}
+
+/// The runs to delete among `runs`, as indices into it (in order) with every criterion each
+/// matches. Pure: it only looks at the records.
+pub fn select_runs(runs: &[RunRecord], criteria: &GcCriteria) -> Vec<(usize, Vec<GcReason>)> {
+    let eligible = |run: &RunRecord| !run.locked && (run.has_manifest || criteria.include_unknown);
+
+    // Rank of every eligible run within its sweep, most accurate first, ties by path
+    let mut ranks = HashMap::new();
+    if criteria.keep_top_k.is_some() {
+        let mut sweeps: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (index, run) in runs.iter().enumerate().filter(|(_, run)| eligible(run)) {
+            sweeps.entry(run.sweep.as_str()).or_default().push(index);
+        }
+        for mut members in sweeps.into_values() {
+            members.sort_by(|&a, &b| {
+                let accuracy = |index: usize| runs[index].best_valid_accuracy.unwrap_or(f64::NEG_INFINITY);
+                accuracy(b).total_cmp(&accuracy(a)).then_with(|| runs[a].path.cmp(&runs[b].path))
+            });
+            ranks.extend(members.into_iter().enumerate().map(|(rank, index)| (index, rank)));
+        }
+    }
+
+    runs.iter()
+        .enumerate()
+        .filter(|(_, run)| eligible(run))
+        .filter_map(|(index, run)| {
+            let mut reasons = Vec::new();
+            if criteria.older_than.is_some_and(|older_than| run.age > older_than) {
+                reasons.push(GcReason::Old);
+            }
+            if let (Some(min), Some(accuracy)) = (criteria.min_accuracy, run.best_valid_accuracy) {
+                if accuracy < min {
+                    reasons.push(GcReason::LowAccuracy);
+                }
+            }
+            if criteria.interrupted && !run.has_model {
+                reasons.push(GcReason::Interrupted);
+            }
+            if criteria.keep_top_k.is_some_and(|k| ranks[&index] >= k) {
+                reasons.push(GcReason::NotTopK);
+            }
+            (!reasons.is_empty()).then_some((index, reasons))
+        })
+        .collect()
+}
+
+// Total size and latest modification time of everything under `path`
+fn disk_usage(path: &Path) -> io::Result<(u64, SystemTime)> {
+    let metadata = fs::symlink_metadata(path)?;
+    let (mut size, mut modified) = (metadata.len(), metadata.modified()?);
+    if metadata.is_dir() {
+        for entry in fs::read_dir(path)? {
+            let (entry_size, entry_modified) = disk_usage(&entry?.path())?;
+            size += entry_size;
+            modified = modified.max(entry_modified);
+        }
+    }
+    Ok((size, modified))
+}
+
+fn read_run(path: &Path, sweep: &str, now: SystemTime) -> io::Result<RunRecord> {
+    let (size_bytes, modified) = disk_usage(path)?;
+    let best_valid_accuracy = History::load(path.join("history.json")).ok().and_then(|history| history.best_valid("Accuracy"));
+    Ok(RunRecord {
+        path: path.to_string_lossy().into_owned(),
+        sweep: sweep.to_string(),
+        has_manifest: path.join(MANIFEST_FILE).exists(),
+        has_model: path.join(MODEL_FILE).exists(),
+        locked: path.join(LOCK_FILE).exists(),
+        best_valid_accuracy,
+        age: now.duration_since(modified).unwrap_or_default(),
+        size_bytes,
+    })
+}
+
+/// Every run under `root`: each directory directly under it, except those without a manifest
+/// that hold runs themselves, sweeps whose runs are listed instead. Sorted by path.
+pub fn scan_runs(root: &str) -> io::Result<Vec<RunRecord>> {
+    let now = SystemTime::now();
+    let subdirs = |path: &Path| -> io::Result<Vec<_>> {
+        let mut dirs: Vec<_> = fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<io::Result<Vec<_>>>()?
+            .into_iter()
+            .filter(|path| path.is_dir())
+            .collect();
+        dirs.sort();
+        Ok(dirs)
+    };
+
+    let mut runs = Vec::new();
+    for dir in subdirs(Path::new(root))? {
+        if dir.join(MANIFEST_FILE).exists() {
+            runs.push(read_run(&dir, "", now)?);
+            continue;
+        }
+        let children = subdirs(&dir)?;
+        if children.iter().any(|child| child.join(MANIFEST_FILE).exists()) {
+            let sweep = dir.file_name().unwrap_or_default().to_string_lossy().into_owned();
+            for child in children {
+                runs.push(read_run(&child, &sweep, now)?);
+            }
+        } else {
+            runs.push(read_run(&dir, "", now)?);
+        }
+    }
+    Ok(runs)
+}
+
+// Outcome of `gc`: the runs selected, with why, and the bytes they took
+#[derive(Debug, Clone, PartialEq)]
+pub struct GcReport {
+    pub runs: Vec<(RunRecord, Vec<GcReason>)>,
+    pub reclaimed_bytes: u64,
+    pub dry_run: bool,
+}
+
+impl fmt::Display for GcReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let verb = if self.dry_run { "Would delete" } else { "Deleted" };
+        for (run, reasons) in &self.runs {
+            let reasons: Vec<String> = reasons.iter().map(GcReason::to_string).collect();
+            writeln!(f, "{verb} {} ({:.2} MB: {})", run.path, run.size_bytes as f64 / 1e6, reasons.join(", "))?;
+        }
+        let verb = if self.dry_run { "would be reclaimed" } else { "reclaimed" };
+        write!(f, "{} run(s), {:.2} MB {verb}", self.runs.len(), self.reclaimed_bytes as f64 / 1e6)
+    }
+}
+
+/// Deletes the runs under `root` that `criteria` selects (see [`select_runs`]), or only lists
+/// them with `dry_run`.
+pub fn gc(root: &str, criteria: &GcCriteria, dry_run: bool) -> io::Result<GcReport> {
+    let runs = scan_runs(root)?;
+    let mut selected = Vec::new();
+    for (index, reasons) in select_runs(&runs, criteria) {
+        let run = runs[index].clone();
+        if !dry_run {
+            fs::remove_dir_all(&run.path)?;
+        }
+        selected.push((run, reasons));
+    }
+    let reclaimed_bytes = selected.iter().map(|(run, _)| run.size_bytes).sum();
+    Ok(GcReport { runs: selected, reclaimed_bytes, dry_run })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    fn run(path: &str, sweep: &str, accuracy: Option<f64>, days: u64) -> RunRecord {
+        RunRecord {
+            path: path.to_string(),
+            sweep: sweep.to_string(),
+            has_manifest: true,
+            has_model: true,
+            locked: false,
+            best_valid_accuracy: accuracy,
+            age: DAY * days as u32,
+            size_bytes: 1000,
+        }
+    }
+
+    fn runs() -> Vec<RunRecord> {
+        vec![
+            run("runs/a", "", Some(98.0), 1),
+            run("runs/b", "", Some(90.0), 40),
+            RunRecord { has_model: false, ..run("runs/c", "", None, 2) },
+            RunRecord { has_manifest: false, ..run("runs/notes", "", None, 90) },
+            RunRecord { locked: true, ..run("runs/d", "", Some(50.0), 60) },
+            run("runs/sweep/lr-1", "sweep", Some(97.0), 3),
+            run("runs/sweep/lr-2", "sweep", Some(99.0), 3),
+            run("runs/sweep/lr-3", "sweep", None, 3),
+        ]
+    }
+
+    #[test]
+    fn every_criterion_selects_its_runs() {
+        let runs = runs();
+        let select = |criteria: GcCriteria| select_runs(&runs, &criteria);
+
+        assert_eq!(select(GcCriteria::default()), []);
+        assert_eq!(select(GcCriteria { older_than: Some(30 * DAY), ..GcCriteria::default() }), [(1, vec![GcReason::Old])]);
+        assert_eq!(
+            select(GcCriteria { min_accuracy: Some(95.0), ..GcCriteria::default() }),
+            [(1, vec![GcReason::LowAccuracy])]
+        );
+        assert_eq!(
+            select(GcCriteria { interrupted: true, ..GcCriteria::default() }),
+            [(2, vec![GcReason::Interrupted])]
+        );
+        // The best run of the root and of the sweep, the unscored runs ranked last
+        assert_eq!(
+            select(GcCriteria { keep_top_k: Some(1), ..GcCriteria::default() }),
+            [(1, vec![GcReason::NotTopK]), (2, vec![GcReason::NotTopK]), (5, vec![GcReason::NotTopK]), (7, vec![GcReason::NotTopK])]
+        );
+        assert_eq!(
+            select(GcCriteria { older_than: Some(30 * DAY), min_accuracy: Some(95.0), ..GcCriteria::default() }),
+            [(1, vec![GcReason::Old, GcReason::LowAccuracy])]
+        );
+    }
+
+    #[test]
+    fn runs_without_a_manifest_are_only_deleted_with_include_unknown() {
+        let runs = runs();
+        let old = GcCriteria { older_than: Some(30 * DAY), ..GcCriteria::default() };
+
+        let selected: Vec<usize> = select_runs(&runs, &old).into_iter().map(|(index, _)| index).collect();
+        assert_eq!(selected, [1]);
+        let selected: Vec<usize> = select_runs(&runs, &GcCriteria { include_unknown: true, ..old })
+            .into_iter()
+            .map(|(index, _)| index)
+            .collect();
+        // Never the locked run
+        assert_eq!(selected, [1, 3]);
+    }
+
+    #[test]
+    fn dry_run_lists_the_runs_and_deletes_nothing() {
+        let root = std::env::temp_dir().join("my_first_rust_DL_app-gc");
+        let _ = fs::remove_dir_all(&root);
+        for run in ["finished", "interrupted"] {
+            fs::create_dir_all(root.join(run)).unwrap();
+            fs::write(root.join(run).join(MANIFEST_FILE), "{}").unwrap();
+        }
+        fs::write(root.join("finished").join(MODEL_FILE), [0; 100]).unwrap();
+        let criteria = GcCriteria { interrupted: true, ..GcCriteria::default() };
+
+        let report = gc(root.to_str().unwrap(), &criteria, true).unwrap();
+        assert_eq!(report.runs.len(), 1);
+        assert!(report.runs[0].0.path.ends_with("interrupted"));
+        assert!(root.join("interrupted").exists());
+        let report = gc(root.to_str().unwrap(), &criteria, false).unwrap();
+        assert_eq!(report.reclaimed_bytes, report.runs[0].0.size_bytes);
+        assert!(!root.join("interrupted").exists() && root.join("finished").exists());
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,113 @@
+use crate::model::Model;
+use burn::{
+    prelude::*,
+    tensor::{
+        backend::AutodiffBackend,
+        module::interpolate,
+        ops::{InterpolateMode, InterpolateOptions},
+    },
+};
+
+/// Grad-CAM class activation map of `image`, normalized `[height, width]` as
+/// [`crate::MnistBatcher`] makes them, for `target_class`: where in the image the evidence for
+/// that class lies, according to `model`.
+///
+/// The feature maps of the last conv layer (`conv2.relu`) are weighted by the spatial mean of
+/// the gradient of the `target_class` logit with respect to them, summed over channels and
+/// passed through a ReLU. The map is then upsampled bilinearly to `[height, width]` and scaled
+/// so that its maximum is 1: every value is in [0, 1], all zeros when no location raises the
+/// logit. Dropout takes no part in the gradient.
+pub fn grad_cam<B: AutodiffBackend>(
+    model: &Model<B>,
+    device: &B::Device,
+    image: Tensor<B, 2>,
+    target_class: usize,
+) -> Tensor<B, 2> {
+    let [height, width] = image.dims();
+    let model = model.clone().without_dropout();
+
+    let image = image.detach().to_device(device).reshape([1, height, width]);
+    // Burn only keeps the gradients of leaf tensors: the head is run again from a detached copy
+    // of the feature maps
+    let features = model.forward_conv(image).detach().require_grad();
+    let logits = model.forward_head(features.clone());
+    let num_classes = logits.dims()[1];
+    assert!(target_class < num_classes, "Target class {target_class} is not one of the {num_classes} classes");
+    let logit = logits.slice([0..1, target_class..target_class + 1]).sum();
+    let gradients = logit.backward();
+    let gradient = features.grad(&gradients).expect("The feature maps require their gradient");
+
+    let features = features.inner();
+    let [_, _, map_height, map_width] = features.dims();
+    let weights = gradient.mean_dim(3).mean_dim(2); // [1, channels, 1, 1]
+    let cam = (features * weights).sum_dim(1).clamp_min(0.0); // [1, 1, map_height, map_width]
+    let cam = interpolate(
+        cam.reshape([1, 1, map_height, map_width]),
+        [height, width],
+        InterpolateOptions::new(InterpolateMode::Bilinear),
+    );
+
+    let max = cam.clone().max().into_scalar().elem::<f32>();
+    let cam = if max > 0.0 { cam / max } else { cam };
+    Tensor::from_inner(cam.reshape([height, width]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        convert::RecordFormat,
+        data::MnistBatcher,
+        synthetic::{trained_model, SyntheticDigits},
+        ModelConfig,
+    };
+    use burn::{
+        backend::{ndarray::NdArrayDevice, Autodiff, NdArray},
+        data::{dataloader::batcher::Batcher, dataset::Dataset},
+    };
+
+    #[test]
+    fn forward_is_the_head_of_the_conv_features() {
+        let device = NdArrayDevice::default();
+        let model = ModelConfig::new(10, 8).init::<NdArray>(&device);
+        let images = MnistBatcher::<NdArray>::new(device).batch(SyntheticDigits::new(4, 1).iter().collect()).images;
+
+        let split = model.forward_head(model.forward_conv(images.clone()));
+        assert_eq!(split.into_data(), model.forward(images).into_data());
+    }
+
+    #[test]
+    fn maps_are_in_the_unit_range_and_follow_the_ink() {
+        let device = NdArrayDevice::default();
+        let weights = trained_model().to_bytes(RecordFormat::NamedMpk);
+        let model = ModelConfig::new(10, 16).init::<Autodiff<NdArray>>(&device);
+        let model = RecordFormat::NamedMpk.decode(model, &weights, &device).unwrap();
+        let digits = SyntheticDigits::new(8, 2);
+        let images = MnistBatcher::<Autodiff<NdArray>>::new(device).batch(digits.iter().collect()).images;
+
+        let mut correlation = 0.0;
+        for (index, item) in digits.iter().enumerate() {
+            let image = images.clone().slice([index..index + 1, 0..28, 0..28]).reshape([28, 28]);
+            let cam = grad_cam(&model, &device, image.clone(), item.label as usize);
+            assert_eq!(cam.dims(), [28, 28]);
+            let values = cam.into_data().convert::<f32>().value;
+            let max = values.iter().copied().fold(0.0, f32::max);
+            assert!(values.iter().all(|&value| (0.0..=1.0 + 1e-6).contains(&value)), "digit {index}");
+            assert!(max == 0.0 || (max - 1.0).abs() < 1e-6, "digit {index} peaks at {max}");
+            correlation += pearson(&values, &image.into_data().convert::<f32>().value) / digits.len() as f32;
+        }
+        assert!(correlation > 0.0, "maps do not follow the digits, mean correlation {correlation}");
+    }
+
+    fn pearson(a: &[f32], b: &[f32]) -> f32 {
+        let mean = |values: &[f32]| values.iter().sum::<f32>() / values.len() as f32;
+        let (mean_a, mean_b) = (mean(a), mean(b));
+        let (mut covariance, mut variance_a, mut variance_b) = (0.0, 0.0, 0.0);
+        for (&a, &b) in a.iter().zip(b) {
+            covariance += (a - mean_a) * (b - mean_b);
+            variance_a += (a - mean_a) * (a - mean_a);
+            variance_b += (b - mean_b) * (b - mean_b);
+        }
+        covariance / (variance_a * variance_b).sqrt().max(f32::EPSILON)
+    }
+}
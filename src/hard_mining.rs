@@ -0,0 +1,190 @@
+use crate::{audit::sample_losses, data::ClassificationDataset, model::Model, profile};
+use burn::{
+    module::AutodiffModule,
+    optim::{GradientsParams, Optimizer},
+    prelude::*,
+    tensor::backend::AutodiffBackend,
+    LearningRate,
+};
+use rand::{distributions::WeightedIndex, rngs::StdRng, Rng, SeedableRng};
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    sync::{Arc, RwLock},
+    time::Instant,
+};
+
+/// Hard-negative mining: every `interval_epochs` epochs, score every training sample with the
+/// model as it stands and draw the next `interval_epochs` epochs with the hardest `top_fraction`
+/// of them `oversample_factor` times as likely as the others. The first `interval_epochs`
+/// epochs shuffle as usual.
+#[derive(Config, Debug, PartialEq)]
+pub struct HardMiningConfig {
+    #[config(default = 1)]
+    pub interval_epochs: usize,
+    // Share of the readable training samples oversampled, the highest losses first
+    #[config(default = 0.1)]
+    pub top_fraction: f64,
+    #[config(default = 3.0)]
+    pub oversample_factor: f64,
+}
+
+// The training set as the sampler draws it: position `i` of an epoch serves item `mapping[i]`.
+// The mapping starts as the identity, which serves exactly what the dataset would, and is only
+// redrawn between epochs, once the training loader has served every item of the last one.
+pub struct ResampledDataset<D> {
+    dataset: Arc<D>,
+    mapping: Arc<RwLock<Vec<usize>>>,
+}
+
+impl<D> ResampledDataset<D> {
+    // Serves `dataset` through `mapping`, which its owner redraws between epochs
+    pub(crate) fn new(dataset: Arc<D>, mapping: Arc<RwLock<Vec<usize>>>) -> Self {
+        Self { dataset, mapping }
+    }
+}
+
+impl<D: ClassificationDataset> ClassificationDataset for ResampledDataset<D> {
+    fn len(&self) -> usize {
+        self.dataset.len()
+    }
+
+    fn get(&self, index: usize) -> Option<(Vec<f32>, usize)> {
+        let source = *self.mapping.read().unwrap().get(index)?;
+        self.dataset.get(source)
+    }
+
+    fn num_classes(&self) -> usize {
+        self.dataset.num_classes()
+    }
+
+    fn image_shape(&self) -> [usize; 2] {
+        self.dataset.image_shape()
+    }
+}
+
+// The mining passes of a run, after the last step of every `interval_epochs`-th epoch but the
+// last one, and the draw of every epoch after the first pass. Passes are logged to
+// `hard_mining.csv` as `epoch,seconds,hard_samples,min_hard_loss,mean_loss`, and show up in the
+// profile trace as `hard mining` spans.
+pub struct HardMining<D> {
+    config: HardMiningConfig,
+    dataset: Arc<D>,
+    mapping: Arc<RwLock<Vec<usize>>>,
+    // Sampling weight of every training sample, `None` until the first pass
+    weights: Option<WeightedIndex<f64>>,
+    seed: u64,
+    steps_per_epoch: usize,
+    num_epochs: usize,
+    log: File,
+    step: usize,
+}
+
+impl<D: ClassificationDataset> HardMining<D> {
+    // Wraps `dataset` into the one the training loader should serve, in `steps_per_epoch`
+    // batches per epoch
+    pub fn new(
+        artifact_dir: &str,
+        config: HardMiningConfig,
+        dataset: D,
+        seed: u64,
+        steps_per_epoch: usize,
+        num_epochs: usize,
+    ) -> io::Result<(Self, ResampledDataset<D>)> {
+        let mut log = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(format!("{artifact_dir}/hard_mining.csv"))?;
+        writeln!(log, "epoch,seconds,hard_samples,min_hard_loss,mean_loss")?;
+        let dataset = Arc::new(dataset);
+        let mapping = Arc::new(RwLock::new((0..dataset.len()).collect()));
+        let resampled = ResampledDataset::new(dataset.clone(), mapping.clone());
+        let steps_per_epoch = steps_per_epoch.max(1);
+        let mining = Self { config, dataset, mapping, weights: None, seed, steps_per_epoch, num_epochs, log, step: 0 };
+        Ok((mining, resampled))
+    }
+
+    // Counts a training step, and at the end of an epoch mines if it is due and draws the next
+    // epoch
+    fn after_step<B: AutodiffBackend>(&mut self, model: &Model<B>) {
+        self.step += 1;
+        if !self.step.is_multiple_of(self.steps_per_epoch) {
+            return;
+        }
+        let epoch = self.step / self.steps_per_epoch;
+        if epoch >= self.num_epochs {
+            return;
+        }
+        if epoch.is_multiple_of(self.config.interval_epochs) {
+            profile::span("hard mining", "mining", || self.mine(&model.valid(), epoch));
+        }
+        if let Some(weights) = &self.weights {
+            // Seeded by the epoch drawn, so a run draws the same epochs whatever came before
+            let mut rng = StdRng::seed_from_u64(self.seed ^ (epoch + 1) as u64);
+            let mapping: Vec<usize> = (0..self.dataset.len()).map(|_| rng.sample(weights)).collect();
+            *self.mapping.write().unwrap() = mapping;
+        }
+    }
+
+    // Scores every training sample without gradients and reweights the hardest ones
+    fn mine<B: Backend>(&mut self, model: &Model<B>, epoch: usize) {
+        let start = Instant::now();
+        let device = model.devices().into_iter().next().expect("The model should live on a device");
+        let mut losses = sample_losses(model, self.dataset.as_ref(), |index| index, &device);
+        // Highest loss first, ties in dataset order
+        losses.sort_by(|a, b| b.loss.total_cmp(&a.loss).then(a.index.cmp(&b.index)));
+        let num_hard = ((losses.len() as f64 * self.config.top_fraction).ceil() as usize).min(losses.len());
+
+        // Unreadable samples keep a weight of 1: the training loader skips them either way
+        let mut weights = vec![1.0; self.dataset.len()];
+        for sample in &losses[..num_hard] {
+            weights[sample.index] = self.config.oversample_factor;
+        }
+        self.weights = WeightedIndex::new(weights).ok();
+
+        let min_hard_loss = losses[..num_hard].last().map_or(0.0, |sample| sample.loss);
+        let mean_loss = losses.iter().map(|sample| sample.loss as f64).sum::<f64>() / losses.len().max(1) as f64;
+        let seconds = start.elapsed().as_secs_f64();
+        // Like the learner logs, a failed write does not stop training
+        writeln!(self.log, "{epoch},{seconds},{num_hard},{min_hard_loss},{mean_loss}").ok();
+    }
+}
+
+// Runs the mining passes of `mining`, if any, after every step of the wrapped optimizer, like
+// `StepValidatedOptimizer` does the mid-epoch validations
+pub struct HardMiningOptimizer<O, D> {
+    inner: O,
+    mining: Option<HardMining<D>>,
+}
+
+impl<O, D> HardMiningOptimizer<O, D> {
+    pub fn new(inner: O, mining: Option<HardMining<D>>) -> Self {
+        Self { inner, mining }
+    }
+}
+
+impl<O, B, D> Optimizer<Model<B>, B> for HardMiningOptimizer<O, D>
+where
+    B: AutodiffBackend,
+    O: Optimizer<Model<B>, B>,
+    D: ClassificationDataset,
+{
+    type Record = O::Record;
+
+    fn step(&mut self, lr: LearningRate, module: Model<B>, grads: GradientsParams) -> Model<B> {
+        let module = self.inner.step(lr, module, grads);
+        if let Some(mining) = &mut self.mining {
+            mining.after_step(&module);
+        }
+        module
+    }
+
+    fn to_record(&self) -> Self::Record {
+        self.inner.to_record()
+    }
+
+    fn load_record(self, record: Self::Record) -> Self {
+        Self { inner: self.inner.load_record(record), mining: self.mining }
+    }
+}
@@ -0,0 +1,172 @@
+use burn::train::LearnerSummary;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::{collections::BTreeMap, fs, io, path::Path};
+
+// Mean value of each metric over one epoch, for both splits
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct EpochMetrics {
+    pub epoch: usize,
+    #[serde(deserialize_with = "non_finite_as_nan")]
+    pub train: BTreeMap<String, f64>,
+    #[serde(deserialize_with = "non_finite_as_nan")]
+    pub valid: BTreeMap<String, f64>,
+    // Train minus validation accuracy, in points, with `log_accuracy_gap`: a growing gap is
+    // overfitting
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub accuracy_gap: Option<f64>,
+    // Share of the epoch trained when a training budget cut it short, `None` for whole epochs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fraction: Option<f64>,
+}
+
+impl EpochMetrics {
+    // The train minus validation mean of `metric`, when both splits log it
+    pub fn split_gap(&self, metric: &str) -> Option<f64> {
+        Some(self.train.get(metric)? - self.valid.get(metric)?)
+    }
+}
+
+// JSON has no NaN or infinity, serde_json writes them as `null`: read those back as NaN
+fn non_finite_as_nan<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<BTreeMap<String, f64>, D::Error> {
+    let values = BTreeMap::<String, Option<f64>>::deserialize(deserializer)?;
+    Ok(values.into_iter().map(|(name, value)| (name, value.unwrap_or(f64::NAN))).collect())
+}
+
+// Per-epoch metrics of a training run, saved as `history.json` in the artifact dir
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct History {
+    pub epochs: Vec<EpochMetrics>,
+}
+
+impl History {
+    // Aggregates the per-iteration metric logs burn's learner writes under `train/` and `valid/`
+    pub fn from_logs(artifact_dir: &str, metrics: &[&str]) -> Result<Self, String> {
+        let summary = LearnerSummary::new(artifact_dir, metrics)?;
+
+        let mut epochs: Vec<EpochMetrics> = (1..=summary.epochs)
+            .map(|epoch| EpochMetrics { epoch, ..Default::default() })
+            .collect();
+        let splits = [
+            (&summary.metrics.train, true),
+            (&summary.metrics.valid, false),
+        ];
+        for (metrics, is_train) in splits {
+            for metric in metrics {
+                for entry in &metric.entries {
+                    let Some(record) = epochs.get_mut(entry.step - 1) else {
+                        continue;
+                    };
+                    let split = if is_train { &mut record.train } else { &mut record.valid };
+                    split.insert(metric.name.clone(), entry.value);
+                }
+            }
+        }
+
+        Ok(Self { epochs })
+    }
+
+    // Highest finite epoch mean of the validation `metric`, `None` when no epoch records one
+    pub fn best_valid(&self, metric: &str) -> Option<f64> {
+        self.epochs
+            .iter()
+            .filter_map(|record| record.valid.get(metric).copied())
+            .filter(|value| value.is_finite())
+            .reduce(f64::max)
+    }
+
+    // Sets the `accuracy_gap` of every epoch that logs the accuracy of both splits
+    pub fn with_accuracy_gap(mut self) -> Self {
+        for record in &mut self.epochs {
+            record.accuracy_gap = record.split_gap("Accuracy");
+        }
+        self
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    // Column names as `<split>/<metric>`, in a stable order
+    fn columns(&self) -> Vec<String> {
+        let mut columns: Vec<String> = Vec::new();
+        for record in &self.epochs {
+            let names = record
+                .train
+                .keys()
+                .map(|name| format!("train/{name}"))
+                .chain(record.valid.keys().map(|name| format!("valid/{name}")));
+            for name in names {
+                if !columns.contains(&name) {
+                    columns.push(name);
+                }
+            }
+        }
+        columns
+    }
+
+    // CSV in the layout W&B's offline CSV import expects: a `step` column (the epoch), then one
+    // column per metric. Metrics missing for an epoch are left empty. A history with an epoch cut
+    // short by a budget ends with an `epoch_fraction` column, 1 for whole epochs.
+    pub fn write_wandb_csv<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut columns = self.columns();
+        let partial = self.epochs.iter().any(|record| record.fraction.is_some());
+        if partial {
+            columns.push("epoch_fraction".to_string());
+        }
+        let mut csv = format!("step,{}\n", columns.join(","));
+
+        for record in &self.epochs {
+            let values: Vec<String> = columns
+                .iter()
+                .map(|column| {
+                    if column == "epoch_fraction" {
+                        return record.fraction.unwrap_or(1.0).to_string();
+                    }
+                    let (split, name) = column.split_once('/').expect("Columns are split/name");
+                    let values = if split == "train" { &record.train } else { &record.valid };
+                    values.get(name).map(f64::to_string).unwrap_or_default()
+                })
+                .collect();
+            csv.push_str(&format!("{},{}\n", record.epoch, values.join(",")));
+        }
+
+        fs::write(path, csv)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn epoch(epoch: usize, loss: f64, accuracy: Option<f64>) -> EpochMetrics {
+        let mut record = EpochMetrics { epoch, ..Default::default() };
+        record.train.insert("Loss".to_string(), loss);
+        record.valid.insert("Loss".to_string(), 2.0 * loss);
+        if let Some(accuracy) = accuracy {
+            record.valid.insert("Accuracy".to_string(), accuracy);
+        }
+        record
+    }
+
+    #[test]
+    fn wandb_csv_has_a_step_column_and_a_row_per_epoch() {
+        let history = History { epochs: vec![epoch(1, 0.5, None), epoch(2, 0.25, Some(90.0)), epoch(3, 0.125, Some(95.0))] };
+        let path = std::env::temp_dir().join("my_first_rust_DL_app-history-metrics.csv");
+        history.write_wandb_csv(&path).unwrap();
+        let csv = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "step,train/Loss,valid/Loss,valid/Accuracy");
+        assert_eq!(lines.len(), 1 + history.epochs.len());
+        assert_eq!(lines[1], "1,0.5,1,");
+        assert_eq!(lines[3], "3,0.125,0.25,95");
+        assert!(lines.iter().all(|line| line.split(',').count() == 4));
+    }
+}
@@ -0,0 +1,274 @@
+use crate::{
+    data::{ClassificationItem, MnistBatch},
+    inference::{load_image_with_policy, LoadImageError, RawImage, ResizePolicy},
+    preprocess::PreprocessConfig,
+};
+use burn::{
+    data::dataloader::{DataLoader, DataLoaderIterator, Progress},
+    prelude::*,
+    train::metric::{
+        state::{FormatOptions, NumericMetricState},
+        Metric, MetricEntry, MetricMetadata, Numeric,
+    },
+};
+use std::{fmt, fs, io, path::{Path, PathBuf}};
+
+#[derive(Debug)]
+pub enum HoldoutError {
+    // The directory could not be listed
+    Io(io::Error),
+    // An image file could not be decoded
+    Image { path: PathBuf, error: image::ImageError },
+    // Images the resize policy rejects, every one of them with why
+    Size(Vec<(PathBuf, String)>),
+    // A file name does not start with its label, e.g. `7_slanted.png`
+    Unlabeled(PathBuf),
+    // No labeled image is left to score
+    Empty(String),
+}
+
+impl fmt::Display for HoldoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HoldoutError::Io(err) => write!(f, "could not list the holdout dir: {err}"),
+            HoldoutError::Image { path, error } => {
+                write!(f, "could not read holdout image {}: {error}", path.display())
+            }
+            HoldoutError::Size(files) => {
+                write!(f, "{} holdout image(s) do not fit the resize policy:", files.len())?;
+                for (path, reason) in files {
+                    write!(f, "\n  {} {reason}", path.display())?;
+                }
+                Ok(())
+            }
+            HoldoutError::Unlabeled(path) => write!(
+                f,
+                "holdout image {} has no label, its file name should start with it (e.g. `7_name.png`)",
+                path.display()
+            ),
+            HoldoutError::Empty(dir) => write!(f, "no holdout images to score in {dir}"),
+        }
+    }
+}
+
+impl std::error::Error for HoldoutError {}
+
+// The label a holdout file name starts with: `7.png`, `7_slanted.png` and `7-2.jpg` are all 7s
+fn label_of(path: &Path) -> Option<usize> {
+    let stem = path.file_stem()?.to_str()?;
+    let digits: String = stem.chars().take_while(char::is_ascii_digit).collect();
+    digits.parse().ok()
+}
+
+// Reads every image of `dir`, sorted by file name, labeled from their names and then through
+// `relabel`, like the training data (see `TrainingConfig::relabel`): the images it maps to `None`
+// are left out. `natural` images go through the same preprocessing as `infer --natural`, the
+// others are fitted onto the canvas with `policy`. All then go through the `preprocess` steps.
+pub fn load_holdout(
+    dir: &str,
+    natural: bool,
+    policy: ResizePolicy,
+    preprocess: &[PreprocessConfig],
+    relabel: impl Fn(usize) -> Option<usize>,
+) -> Result<Vec<(RawImage, usize)>, HoldoutError> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+        .map_err(HoldoutError::Io)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file())
+        .collect();
+    paths.sort();
+
+    let (mut images, mut mismatched) = (Vec::new(), Vec::new());
+    for path in paths {
+        let label = label_of(&path).ok_or_else(|| HoldoutError::Unlabeled(path.clone()))?;
+        let Some(label) = relabel(label) else {
+            continue;
+        };
+        match load_image_with_policy(&path.to_string_lossy(), natural, policy, preprocess) {
+            Ok(image) => images.push((image, label)),
+            Err(LoadImageError::Decode(error)) => return Err(HoldoutError::Image { path, error }),
+            // Collected to report every file to fix at once
+            Err(LoadImageError::Size(reason)) => mismatched.push((path, reason)),
+        }
+    }
+    if !mismatched.is_empty() {
+        return Err(HoldoutError::Size(mismatched));
+    }
+
+    if images.is_empty() {
+        return Err(HoldoutError::Empty(dir.to_string()));
+    }
+    Ok(images)
+}
+
+pub fn holdout_items(images: Vec<(RawImage, usize)>) -> Vec<ClassificationItem> {
+    images
+        .into_iter()
+        .map(|(image, label)| ClassificationItem {
+            pixels: image.iter().flatten().copied().collect(),
+            shape: [28, 28],
+            label,
+            index: None,
+        })
+        .collect()
+}
+
+// Attaches the holdout batch to the first batch of every epoch of a validation dataloader, so
+// the validation step scores it once per epoch with the current weights
+pub struct HoldoutDataLoader<B: Backend> {
+    inner: Box<dyn DataLoader<MnistBatch<B>>>,
+    holdout: MnistBatch<B>,
+}
+
+impl<B: Backend> HoldoutDataLoader<B> {
+    pub fn new(inner: Box<dyn DataLoader<MnistBatch<B>>>, holdout: MnistBatch<B>) -> Self {
+        Self { inner, holdout }
+    }
+}
+
+struct HoldoutIterator<'a, B: Backend> {
+    inner: Box<dyn DataLoaderIterator<MnistBatch<B>> + 'a>,
+    holdout: Option<MnistBatch<B>>,
+}
+
+impl<B: Backend> DataLoader<MnistBatch<B>> for HoldoutDataLoader<B> {
+    fn iter<'a>(&'a self) -> Box<dyn DataLoaderIterator<MnistBatch<B>> + 'a> {
+        Box::new(HoldoutIterator { inner: self.inner.iter(), holdout: Some(self.holdout.clone()) })
+    }
+
+    fn num_items(&self) -> usize {
+        self.inner.num_items()
+    }
+}
+
+impl<B: Backend> Iterator for HoldoutIterator<'_, B> {
+    type Item = MnistBatch<B>;
+
+    fn next(&mut self) -> Option<MnistBatch<B>> {
+        let mut batch = self.inner.next()?;
+        if let Some(holdout) = self.holdout.take() {
+            batch.holdout = Some(Box::new(holdout));
+        }
+        Some(batch)
+    }
+}
+
+impl<B: Backend> DataLoaderIterator<MnistBatch<B>> for HoldoutIterator<'_, B> {
+    fn progress(&self) -> Progress {
+        self.inner.progress()
+    }
+}
+
+// Holdout accuracy of a validation step, in percent: only the step that scored the holdout
+// batch has one
+pub struct HoldoutInput {
+    accuracy: Option<f64>,
+}
+
+impl HoldoutInput {
+    pub fn new(accuracy: Option<f64>) -> Self {
+        Self { accuracy }
+    }
+}
+
+// Accuracy on the holdout images, scored once per epoch. Every validation iteration logs the
+// latest score, so that the epoch mean is the score itself.
+#[derive(Default)]
+pub struct HoldoutAccuracyMetric {
+    state: NumericMetricState,
+    accuracy: f64,
+}
+
+impl HoldoutAccuracyMetric {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Metric for HoldoutAccuracyMetric {
+    const NAME: &'static str = "Holdout Accuracy";
+
+    type Input = HoldoutInput;
+
+    fn update(&mut self, item: &HoldoutInput, _metadata: &MetricMetadata) -> MetricEntry {
+        if let Some(accuracy) = item.accuracy {
+            self.accuracy = accuracy;
+        }
+        self.state.update(self.accuracy, 1, FormatOptions::new(Self::NAME).unit("%").precision(2))
+    }
+
+    fn clear(&mut self) {
+        self.state.reset()
+    }
+}
+
+impl Numeric for HoldoutAccuracyMetric {
+    fn value(&self) -> f64 {
+        self.state.value()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        artifact::ArtifactDir,
+        history::History,
+        synthetic::{temp_artifact_dir, tiny_config, train_synthetic, SyntheticDigits},
+    };
+    use burn::data::dataset::Dataset;
+    use image::{GrayImage, Luma};
+
+    // A holdout dir of synthetic digits named after their labels, `<label>_<index>.png`
+    fn holdout_dir(name: &str, digits: &SyntheticDigits) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("my_first_rust_DL_app-holdout-{name}"));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for (index, item) in digits.iter().enumerate() {
+            let image = GrayImage::from_fn(28, 28, |x, y| Luma([item.image[y as usize][x as usize] as u8]));
+            image.save(dir.join(format!("{}_{index}.png", item.label))).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn holdout_images_are_labeled_from_their_file_names() {
+        let digits = SyntheticDigits::new(12, 5);
+        let dir = holdout_dir("labels", &digits);
+        let dir_str = dir.to_str().unwrap();
+
+        let images = load_holdout(dir_str, false, ResizePolicy::default(), &[], Some).unwrap();
+        let mut labels: Vec<usize> = images.iter().map(|(_, label)| *label).collect();
+        labels.sort();
+        assert_eq!(labels, [0, 0, 1, 1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        let first = images.iter().find(|(_, label)| *label == 0).unwrap();
+        assert_eq!(first.0, digits.get(0).unwrap().image);
+        // Relabeled like the training data, the unmapped classes left out
+        let relabeled = load_holdout(dir_str, false, ResizePolicy::default(), &[], |label| (label < 2).then_some(label));
+        assert_eq!(relabeled.unwrap().len(), 4);
+
+        fs::write(dir.join("slanted.png"), fs::read(dir.join("0_0.png")).unwrap()).unwrap();
+        let unlabeled = load_holdout(dir_str, false, ResizePolicy::default(), &[], Some);
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(matches!(unlabeled, Err(HoldoutError::Unlabeled(path)) if path.ends_with("slanted.png")));
+    }
+
+    #[test]
+    fn holdout_accuracy_is_logged_every_epoch() {
+        let holdout = holdout_dir("training", &SyntheticDigits::new(20, 5));
+        let artifact_dir = temp_artifact_dir("holdout-training-run");
+        let config = tiny_config().with_holdout_dir(Some(holdout.to_str().unwrap().to_string())).with_num_epochs(2);
+
+        let artifact_dir_str = artifact_dir.to_str().unwrap();
+        train_synthetic(&artifact_dir, config, 64, 32).unwrap();
+        let history = History::load(ArtifactDir::new(artifact_dir_str).history_path()).unwrap();
+        fs::remove_dir_all(&holdout).unwrap();
+        fs::remove_dir_all(&artifact_dir).unwrap();
+
+        assert_eq!(history.epochs.len(), 2);
+        for epoch in &history.epochs {
+            let accuracy = epoch.valid["Holdout Accuracy"];
+            assert!((0.0..=100.0).contains(&accuracy), "holdout accuracy {accuracy}");
+        }
+    }
+}
@@ -0,0 +1,331 @@
+use crate::{
+    data::{DatasetSource, MnistSplit, MNIST_NUM_CLASSES},
+    inference::{load_image_with_policy, ResizePolicy},
+};
+use burn::data::dataset::{vision::MnistItem, Dataset};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+// Written into the folder of every split, next to the label directories
+pub const INDEX_FILE: &str = "index.json";
+
+// Bumped whenever `FolderIndex` changes shape: an index of another version is rebuilt
+const INDEX_VERSION: u32 = 1;
+
+// One image of a split, as recorded in `index.json`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct IndexEntry {
+    // Path relative to the split folder, with `/` separators, e.g. `7/00042.png`
+    pub path: String,
+    pub label: usize,
+    // Size and modification time of the file when it was last decoded
+    pub size: u64,
+    pub mtime_secs: u64,
+    pub mtime_nanos: u32,
+    // Whether it decoded and fitted onto the canvas: unusable files are recorded too, so that
+    // they are not decoded again until they change
+    pub usable: bool,
+}
+
+impl IndexEntry {
+    // Whether the file is still the one `other` recorded
+    fn same_file(&self, other: &IndexEntry) -> bool {
+        (&self.path, self.label, self.size, self.mtime_secs, self.mtime_nanos)
+            == (&other.path, other.label, other.size, other.mtime_secs, other.mtime_nanos)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct FolderIndex {
+    version: u32,
+    entries: Vec<IndexEntry>,
+}
+
+// What opening a split did with its previous index
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct IndexStats {
+    // Files whose size and mtime match the index, taken from it without decoding them
+    pub reused: usize,
+    // New or changed files, decoded to check them
+    pub scanned: usize,
+    // Indexed files that are gone
+    pub removed: usize,
+    // Files that could not be decoded or fitted onto the canvas, left out, whether they were
+    // just scanned or indexed as such
+    pub skipped: usize,
+}
+
+#[derive(Debug)]
+pub enum ImageFolderError {
+    // A directory could not be listed, or a file stat'ed
+    Io { path: PathBuf, error: io::Error },
+    // A directory of the split is not named after a digit, e.g. `cats/`
+    Label(PathBuf),
+    // No readable image in the split
+    Empty(PathBuf),
+}
+
+impl fmt::Display for ImageFolderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageFolderError::Io { path, error } => write!(f, "could not read {}: {error}", path.display()),
+            ImageFolderError::Label(path) => write!(
+                f,
+                "{} is not a label directory, its name should be a digit from 0 to {}",
+                path.display(),
+                MNIST_NUM_CLASSES - 1
+            ),
+            ImageFolderError::Empty(path) => write!(f, "no readable image in {}", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for ImageFolderError {}
+
+/// One split of an image folder: every image of `<split dir>/<label>/`, the label being a digit,
+/// fitted onto the 28x28 canvas with the default [`ResizePolicy`]. Images are decoded on every
+/// [`get`](Dataset::get).
+///
+/// Listing the folder is cheap but decoding every image to check it is not, so every file is
+/// recorded in an `index.json` in the split folder, with its size, mtime and whether it is usable. Opening
+/// the split again only decodes the files that are new or whose size or mtime changed, drops the
+/// deleted ones and rewrites the index when anything changed. An unreadable index is rebuilt.
+pub struct ImageFolderDataset {
+    root: PathBuf,
+    // Usable entries only
+    entries: Vec<IndexEntry>,
+    stats: IndexStats,
+}
+
+impl ImageFolderDataset {
+    /// Opens the split in `root`, through its index unless `reindex`, in which case every image
+    /// is decoded again as on the first run.
+    pub fn open(root: impl Into<PathBuf>, reindex: bool) -> Result<Self, ImageFolderError> {
+        let root = root.into();
+        let index_path = root.join(INDEX_FILE);
+        let previous = if reindex { Vec::new() } else { read_index(&index_path) };
+        let mut previous: HashMap<String, IndexEntry> =
+            previous.into_iter().map(|entry| (entry.path.clone(), entry)).collect();
+
+        let mut stats = IndexStats::default();
+        let mut index = Vec::new();
+        for (path, label) in list_images(&root)? {
+            let metadata = fs::metadata(root.join(&path))
+                .map_err(|error| ImageFolderError::Io { path: root.join(&path), error })?;
+            let (mtime_secs, mtime_nanos) = metadata
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map_or((0, 0), |time| (time.as_secs(), time.subsec_nanos()));
+            let mut entry = IndexEntry { path, label, size: metadata.len(), mtime_secs, mtime_nanos, usable: true };
+
+            match previous.remove(&entry.path) {
+                Some(indexed) if indexed.same_file(&entry) => {
+                    stats.reused += 1;
+                    entry.usable = indexed.usable;
+                }
+                _ => {
+                    stats.scanned += 1;
+                    let path = root.join(&entry.path);
+                    entry.usable = load_image_with_policy(&path.to_string_lossy(), false, ResizePolicy::default(), &[]).is_ok();
+                }
+            }
+            stats.skipped += usize::from(!entry.usable);
+            index.push(entry);
+        }
+        // What is left of the previous index was not found again
+        stats.removed = previous.len();
+
+        if stats.scanned > 0 || stats.removed > 0 {
+            let index = FolderIndex { version: INDEX_VERSION, entries: index.clone() };
+            let json = serde_json::to_string(&index).expect("Folder index should serialize");
+            if let Err(err) = fs::write(&index_path, json) {
+                eprintln!("Could not write the folder index to {}: {err}", index_path.display());
+            }
+        }
+        if stats.skipped > 0 {
+            eprintln!("Warning: left out {} unreadable image(s) of {}", stats.skipped, root.display());
+        }
+        let entries: Vec<IndexEntry> = index.into_iter().filter(|entry| entry.usable).collect();
+        if entries.is_empty() {
+            return Err(ImageFolderError::Empty(root));
+        }
+        Ok(Self { root, entries, stats })
+    }
+
+    pub fn entries(&self) -> &[IndexEntry] {
+        &self.entries
+    }
+
+    pub fn stats(&self) -> IndexStats {
+        self.stats
+    }
+}
+
+impl Dataset<MnistItem> for ImageFolderDataset {
+    fn get(&self, index: usize) -> Option<MnistItem> {
+        let entry = self.entries.get(index)?;
+        let path = self.root.join(&entry.path);
+        let image = load_image_with_policy(&path.to_string_lossy(), false, ResizePolicy::default(), &[]).ok()?;
+        Some(MnistItem { image, label: entry.label as u8 })
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+// The entries of the index at `path`: none when there is none yet, or when it cannot be used,
+// with a warning, so that the split is scanned in full
+fn read_index(path: &Path) -> Vec<IndexEntry> {
+    let json = match fs::read_to_string(path) {
+        Ok(json) => json,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Vec::new(),
+        Err(err) => {
+            eprintln!("Warning: could not read the folder index {} ({err}), rescanning the folder", path.display());
+            return Vec::new();
+        }
+    };
+    match serde_json::from_str::<FolderIndex>(&json) {
+        Ok(index) if index.version == INDEX_VERSION => index.entries,
+        Ok(index) => {
+            eprintln!(
+                "Warning: the folder index {} is of version {}, expected {INDEX_VERSION}, rescanning the folder",
+                path.display(),
+                index.version
+            );
+            Vec::new()
+        }
+        Err(err) => {
+            eprintln!("Warning: the folder index {} is corrupt ({err}), rescanning the folder", path.display());
+            Vec::new()
+        }
+    }
+}
+
+// Relative path and label of every file of the label directories of `root`, sorted by path.
+// Files directly in `root`, such as the index, are not images of the split.
+fn list_images(root: &Path) -> Result<Vec<(String, usize)>, ImageFolderError> {
+    let read_dir = |dir: &Path| {
+        let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+            .map_err(|error| ImageFolderError::Io { path: dir.to_path_buf(), error })?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .collect();
+        paths.sort();
+        Ok::<_, ImageFolderError>(paths)
+    };
+
+    let mut images = Vec::new();
+    for dir in read_dir(root)?.into_iter().filter(|path| path.is_dir()) {
+        let name = dir.file_name().and_then(|name| name.to_str()).unwrap_or_default().to_string();
+        let label = match name.parse::<usize>() {
+            Ok(label) if label < MNIST_NUM_CLASSES => label,
+            _ => return Err(ImageFolderError::Label(dir)),
+        };
+        for file in read_dir(&dir)?.into_iter().filter(|path| path.is_file()) {
+            let file_name = file.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+            images.push((format!("{name}/{file_name}"), label));
+        }
+    }
+    Ok(images)
+}
+
+// Folder of `split` in an image folder dataset
+pub fn split_dir(dir: &str, split: MnistSplit) -> PathBuf {
+    Path::new(dir).join(match split {
+        MnistSplit::Train => "train",
+        MnistSplit::Test => "test",
+    })
+}
+
+impl DatasetSource {
+    /// Rebuilds the index of both splits of every image folder of the source from scratch, for a
+    /// folder whose index went out of sync in a way sizes and mtimes do not show.
+    pub fn reindex(&self) -> Result<(), ImageFolderError> {
+        match self {
+            DatasetSource::ImageFolder { dir } => {
+                for split in [MnistSplit::Train, MnistSplit::Test] {
+                    ImageFolderDataset::open(split_dir(dir, split), true)?;
+                }
+                Ok(())
+            }
+            DatasetSource::Concat(sources) => sources.iter().try_for_each(DatasetSource::reindex),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GrayImage, Luma};
+
+    // A white bar on black, `side` pixels wide
+    fn save_png(path: &Path, side: u32) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        GrayImage::from_fn(side, side, |x, _| Luma([if x == side / 2 { 255 } else { 0 }])).save(path).unwrap();
+    }
+
+    fn counts(stats: IndexStats) -> [usize; 4] {
+        [stats.reused, stats.scanned, stats.removed, stats.skipped]
+    }
+
+    #[test]
+    fn reopening_only_decodes_new_and_changed_files() {
+        let root = std::env::temp_dir().join("my_first_rust_DL_app-image_folder");
+        let _ = fs::remove_dir_all(&root);
+        for label in 0..3 {
+            for file in 0..4 {
+                save_png(&root.join(format!("{label}/{file}.png")), 28);
+            }
+        }
+        fs::write(root.join("2/broken.png"), b"not a png").unwrap();
+
+        let first = ImageFolderDataset::open(&root, false).unwrap();
+        assert_eq!(counts(first.stats()), [0, 13, 0, 1]);
+        assert_eq!(first.len(), 12);
+        assert!(root.join(INDEX_FILE).exists());
+        let item = first.get(4).unwrap();
+        assert_eq!(item.label, 1);
+        assert_eq!(item.image.len(), 28);
+
+        let second = ImageFolderDataset::open(&root, false).unwrap();
+        assert_eq!(counts(second.stats()), [13, 0, 0, 1]);
+        assert_eq!(second.entries(), first.entries());
+
+        fs::remove_file(root.join("0/0.png")).unwrap();
+        fs::remove_file(root.join("0/1.png")).unwrap();
+        save_png(&root.join("1/new.png"), 28);
+        // Another size, whatever the resolution of the mtimes
+        save_png(&root.join("2/3.png"), 20);
+        let changed = ImageFolderDataset::open(&root, false).unwrap();
+        assert_eq!(counts(changed.stats()), [10, 2, 2, 1]);
+        assert_eq!(changed.len(), 11);
+
+        fs::write(root.join(INDEX_FILE), "{\"version\": 1, \"entr").unwrap();
+        assert_eq!(counts(ImageFolderDataset::open(&root, false).unwrap().stats()), [0, 12, 0, 1]);
+        assert_eq!(counts(ImageFolderDataset::open(&root, true).unwrap().stats()), [0, 12, 0, 1]);
+
+        fs::create_dir(root.join("cats")).unwrap();
+        let not_a_label = ImageFolderDataset::open(&root, false);
+        fs::remove_dir_all(&root).unwrap();
+        assert!(matches!(not_a_label, Err(ImageFolderError::Label(dir)) if dir.ends_with("cats")));
+    }
+
+    #[test]
+    fn a_split_without_a_readable_image_is_an_error() {
+        let root = std::env::temp_dir().join("my_first_rust_DL_app-image_folder-empty");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("5")).unwrap();
+        fs::write(root.join("5/broken.png"), b"not a png").unwrap();
+
+        let result = ImageFolderDataset::open(&root, false);
+        fs::remove_dir_all(&root).unwrap();
+        assert!(matches!(result, Err(ImageFolderError::Empty(dir)) if dir == root));
+    }
+}
@@ -1,24 +1,1803 @@
-use crate::{data::MnistBatcher, model::Model, training::TrainingConfig};
+use crate::{
+    adapter::Adapters,
+    artifact::{ArtifactDir, ModelKind},
+    bundle::Bundle,
+    checkpoint::LoadError,
+    convert::RecordFormat,
+    data::MnistBatcher,
+    download::sha256_file,
+    labels::ClassLabels,
+    meta::{resolve_model_config, resolve_with_meta, ModelMeta},
+    model::{Classifier, Model, ModelConfig},
+    preprocess::{apply_steps_raw, PreprocessConfig},
+    store::CheckpointBackend,
+    training::TrainingConfig,
+};
+use base64::Engine;
 use burn::{
     data::{dataloader::batcher::Batcher, dataset::vision::MnistItem},
     prelude::*,
-    record::{CompactRecorder, Recorder},
+    tensor::activation::softmax,
+};
+use image::{imageops::FilterType, DynamicImage, GrayImage, Luma, Rgb};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{self, BufRead, Read, Write},
+    path::Path,
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::{Duration, Instant},
 };
 
-pub fn infer<B: Backend>(artifact_dir: &str, device: B::Device, item: MnistItem) {
-    let config = TrainingConfig::load(format!("{artifact_dir}/config.json"))
-        .expect("Config should exist for the model");
-    let record = CompactRecorder::new()
-        .load(format!("{artifact_dir}/model").into(), &device)
-        .expect("Trained model should exist");
+// Test-time augmentation draws its rotations and shifts from this seed, so that predictions are
+// reproducible
+const TTA_SEED: u64 = 0;
+// Largest rotation (in degrees) and shift (in pixels) of a test-time augmentation
+const TTA_MAX_ROTATION: f32 = 10.0;
+const TTA_MAX_SHIFT: f32 = 2.0;
+
+// Side of the MNIST canvas, and of the box the digit itself is scaled into (MNIST preparation
+// leaves a 4 pixel margin around it)
+const CANVAS_SIZE: u32 = 28;
+const DIGIT_BOX_SIZE: u32 = 20;
+
+// Raw MNIST-style pixels: white ink on black, values in [0, 255], before normalization
+pub type RawImage = [[f32; 28]; 28];
+
+/// Loads the model trained into `artifact_dir` (a path or an [`ArtifactDir`]), rebuilt from the
+/// `config.json` saved next to it.
+/// The `model_meta.json` saved with the weights is checked against that config: a different
+/// format version or layer shape is an error, and on any other difference the metadata wins.
+///
+/// Reads the files and leaves the rest to the in-memory path of [`Model::from_bytes`].
+pub fn load_model<B: Backend>(artifact_dir: impl AsRef<Path>, device: &B::Device) -> Result<Model<B>, LoadError> {
+    let dir = ArtifactDir::new(artifact_dir);
+    let config = TrainingConfig::load(dir.config_path()).map_err(|err| LoadError::Config(err.to_string()))?;
+    let model_config = resolve_model_config(dir.as_str(), &config.model)?;
+    let path = dir.weights_path(ModelKind::Final);
+    let weights = std::fs::read(&path).map_err(|err| LoadError::Record(format!("{path}: {err}")))?;
+
+    model_from_bytes(&model_config, &weights, device)
+}
+
+/// [`load_model`] of a run kept in a checkpoint store, such as the `checkpoint_store` it was
+/// trained with (see [`store::open`](crate::store::open)): its `config.json`, `model_meta.json`
+/// and `model.mpk` are read from the store, with nothing written to the filesystem.
+pub fn load_model_from<B: Backend>(backend: &dyn CheckpointBackend, device: &B::Device) -> Result<Model<B>, LoadError> {
+    let config = backend.get("config.json").map_err(|err| LoadError::Config(err.to_string()))?;
+    let config = TrainingConfig::load_binary(&config).map_err(|err| LoadError::Config(err.to_string()))?;
+    let model_config = resolve_with_meta(ModelMeta::load_from(backend)?, &config.model)?;
+    let weights = backend.get("model.mpk").map_err(|err| LoadError::Record(err.to_string()))?;
+
+    model_from_bytes(&model_config, &weights, device)
+}
+
+impl<B: Backend> Model<B> {
+    /// Rebuilds a model from the `config.json` of its artifact dir and its weights as a
+    /// [`RecordFormat`] recorder writes them, such as the `model.mpk` of the artifact dir, told
+    /// apart from the bytes. Nothing is read from the filesystem, so both can be embedded with
+    /// `include_bytes!` or fetched over the network.
+    pub fn from_bytes(config_json: &str, weights: &[u8], device: &B::Device) -> Result<Self, LoadError> {
+        let config = TrainingConfig::load_binary(config_json.as_bytes())
+            .map_err(|err| LoadError::Config(err.to_string()))?;
+        model_from_bytes(&config.model, weights, device)
+    }
+
+    /// The weights of the model as the recorder of `format` saves them, for
+    /// [`Model::from_bytes`]. [`RecordFormat::Compact`] gives the bytes of a `model.mpk`.
+    pub fn to_bytes(&self, format: RecordFormat) -> Vec<u8> {
+        // Cloning a module only bumps tensor reference counts
+        format.encode(self.clone())
+    }
+}
+
+/// `model` with the adapters of the `adapter.mpk` at `adapter_path` (with or without its
+/// extension), such as one an adapter run saved next to the base weights it trained against.
+/// With `merge` they are added into the weights once, otherwise composed with the layers at
+/// every forward pass: both predict the same.
+pub fn with_adapter_file<B: Backend>(
+    model: Model<B>,
+    adapter_path: impl AsRef<Path>,
+    merge: bool,
+    device: &B::Device,
+) -> Result<Model<B>, LoadError> {
+    let model = model.with_adapters(Adapters::load(adapter_path, device)?);
+    Ok(match merge {
+        true => model.merge_adapters(),
+        false => model,
+    })
+}
+
+// Batch size of streamed inference for artifact dirs without a readable config
+const DEFAULT_STREAM_BATCH_SIZE: usize = 64;
 
-    let model = config.model.init::<B>(&device).load_record(record);
+/// A trained model with what classifying image files takes from its run: the class names, the
+/// resize policy and contrast steps its training images went through, and its training batch
+/// size, the default batch of [`infer_stream`].
+pub struct InferenceModel<B: Backend> {
+    pub model: Model<B>,
+    pub labels: ClassLabels,
+    pub resize_policy: ResizePolicy,
+    pub preprocess: Vec<PreprocessConfig>,
+    pub batch_size: usize,
+}
+
+impl<B: Backend> InferenceModel<B> {
+    /// Loads the model of the artifact dir or [`Bundle`] file at `path`. Artifacts without
+    /// metadata get the default resize policy and no contrast steps.
+    pub fn load(path: &str, device: &B::Device) -> Result<Self, LoadError> {
+        // A bundle file carries its config and class names, a directory may not
+        if Path::new(path).is_file() {
+            let bundle = Bundle::open(path)?;
+            return Ok(Self {
+                model: bundle.model(device)?,
+                labels: bundle.labels,
+                resize_policy: bundle.meta.resize_policy,
+                preprocess: bundle.meta.preprocess,
+                batch_size: bundle.config.batch_size,
+            });
+        }
+        let model = load_model::<B>(path, device)?;
+        let meta = ModelMeta::load(path)?;
+        let batch_size = TrainingConfig::load(ArtifactDir::new(path).config_path())
+            .map_or(DEFAULT_STREAM_BATCH_SIZE, |config| config.batch_size);
+        Ok(Self {
+            model,
+            labels: ClassLabels::load(path),
+            resize_policy: meta.as_ref().map(|meta| meta.resize_policy).unwrap_or_default(),
+            preprocess: meta.map(|meta| meta.preprocess).unwrap_or_default(),
+            batch_size,
+        })
+    }
+}
+
+// The model of `config` with the weights of `weights`, in any `RecordFormat`
+fn model_from_bytes<B: Backend>(config: &ModelConfig, weights: &[u8], device: &B::Device) -> Result<Model<B>, LoadError> {
+    let format = RecordFormat::detect(weights)
+        .ok_or_else(|| LoadError::Record("the weights are in no format this build reads".to_string()))?;
+    format
+        .decode(config.init::<B>(device), weights, device)
+        .map_err(|err| LoadError::Record(err.to_string()))
+}
+
+/// Classifies one dataset item with the model in `artifact_dir`, printing and returning the
+/// predicted label.
+pub fn infer<B: Backend>(artifact_dir: &str, device: B::Device, item: MnistItem) -> Result<usize, LoadError> {
+    let model = load_model::<B>(artifact_dir, &device)?;
 
     let label = item.label;
     let batcher = MnistBatcher::new(device);
     let batch = batcher.batch(vec![item]);
     let output = model.forward(batch.images);
-    let predicted = output.argmax(1).flatten::<1>(0, 1).into_scalar();
+    let predicted = output.argmax(1).flatten::<1>(0, 1).into_scalar().elem::<i64>() as usize;
 
     println!("Predicted {} Expected {}", predicted, label);
-}
\ No newline at end of file
+    Ok(predicted)
+}
+
+/// Classifies an image file. Clean MNIST-like inputs are only resized to 28x28; photos or scans
+/// of real handwriting should set `natural` so they go through [`preprocess_natural_image`] first.
+pub fn predict_image_file<B: Backend, M: Classifier<B> + ?Sized>(
+    model: &M,
+    device: &B::Device,
+    path: &str,
+    natural: bool,
+) -> Result<usize, image::ImageError> {
+    let pixels = load_image(path, natural)?;
+    let output = forward_raw(model, device, vec![pixels]);
+
+    Ok(output.argmax(1).flatten::<1>(0, 1).into_scalar().elem::<i64>() as usize)
+}
+
+// Number of classes listed in `Prediction::top_k`
+pub const DEFAULT_TOP_K: usize = 3;
+
+/// One class of a [`Prediction`] with its probability.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ClassProbability {
+    pub index: usize,
+    pub label: String,
+    pub probability: f32,
+}
+
+/// The prediction for one image: the most likely class, by index and by name (see
+/// [`ClassLabels`]), its probability, the probability of every class, and the `k` most likely
+/// classes, most likely first. The one representation of a prediction in JSON outputs.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Prediction {
+    pub index: usize,
+    pub label: String,
+    pub confidence: f32,
+    pub probabilities: Vec<f32>,
+    pub top_k: Vec<ClassProbability>,
+}
+
+impl Prediction {
+    pub fn from_probabilities(probabilities: Vec<f32>, labels: &ClassLabels, k: usize) -> Self {
+        let index = argmax(&probabilities);
+        let mut ranked: Vec<usize> = (0..probabilities.len()).collect();
+        ranked.sort_by(|&a, &b| probabilities[b].total_cmp(&probabilities[a]).then(a.cmp(&b)));
+        let top_k = ranked
+            .into_iter()
+            .take(k)
+            .map(|class| ClassProbability { index: class, label: labels.name(class), probability: probabilities[class] })
+            .collect();
+        Self { index, label: labels.name(index), confidence: probabilities[index], probabilities, top_k }
+    }
+}
+
+impl fmt::Display for Prediction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({:.1}%)", self.label, self.confidence * 100.0)
+    }
+}
+
+/// A [`Prediction`] that may be turned down as not confident enough, for callers that would
+/// rather answer "not sure" than guess (see [`Prediction::reject_below`]). Rejections keep the
+/// probability of every class. Serialized with a `status` tag, `accepted` (with the fields of
+/// the prediction) or `rejected`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Decision {
+    Accepted(Prediction),
+    Rejected { probabilities: Vec<f32> },
+}
+
+impl Prediction {
+    /// Rejects the prediction when its confidence is below `threshold`. A threshold of 0 accepts
+    /// every prediction.
+    pub fn reject_below(self, threshold: f32) -> Decision {
+        match self.confidence < threshold {
+            true => Decision::Rejected { probabilities: self.probabilities },
+            false => Decision::Accepted(self),
+        }
+    }
+}
+
+impl fmt::Display for Decision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Decision::Accepted(prediction) => prediction.fmt(f),
+            Decision::Rejected { probabilities } => {
+                let confidence = probabilities.iter().copied().fold(0.0, f32::max);
+                write!(f, "not sure ({:.1}% at most)", confidence * 100.0)
+            }
+        }
+    }
+}
+
+/// Classifies one raw image, with the [`DEFAULT_TOP_K`] most likely classes.
+pub fn predict<B: Backend, M: Classifier<B> + ?Sized>(model: &M, device: &B::Device, image: RawImage, labels: &ClassLabels) -> Prediction {
+    predict_topk(model, device, image, labels, DEFAULT_TOP_K)
+}
+
+/// [`predict`], rejecting predictions less confident than `threshold`.
+pub fn predict_with_reject<B: Backend, M: Classifier<B> + ?Sized>(
+    model: &M,
+    device: &B::Device,
+    image: RawImage,
+    labels: &ClassLabels,
+    threshold: f32,
+) -> Decision {
+    predict(model, device, image, labels).reject_below(threshold)
+}
+
+/// Classifies one raw image, listing the `k` most likely classes.
+pub fn predict_topk<B: Backend, M: Classifier<B> + ?Sized>(
+    model: &M,
+    device: &B::Device,
+    image: RawImage,
+    labels: &ClassLabels,
+    k: usize,
+) -> Prediction {
+    let probabilities = predict_probabilities(model, device, vec![image]);
+    let probabilities = probabilities.into_iter().next().expect("One prediction per image");
+    Prediction::from_probabilities(probabilities, labels, k)
+}
+
+/// Like [`predict_image_file`], as a [`Prediction`] with class names.
+pub fn classify_image_file<B: Backend, M: Classifier<B> + ?Sized>(
+    model: &M,
+    device: &B::Device,
+    path: &str,
+    natural: bool,
+    labels: &ClassLabels,
+) -> Result<Prediction, image::ImageError> {
+    Ok(predict(model, device, load_image(path, natural)?, labels))
+}
+
+/// The filter images are resized with to fit the 28x28 canvas (or, for natural images, the 20x20
+/// digit box). Lanczos by default: the sharpest, and the least prone to aliasing when a large
+/// photo is shrunk.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub enum Interpolation {
+    Nearest,
+    Bilinear,
+    #[default]
+    Lanczos,
+}
+
+impl Interpolation {
+    fn filter(self) -> FilterType {
+        match self {
+            Interpolation::Nearest => FilterType::Nearest,
+            Interpolation::Bilinear => FilterType::Triangle,
+            Interpolation::Lanczos => FilterType::Lanczos3,
+        }
+    }
+}
+
+impl std::str::FromStr for Interpolation {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "nearest" => Ok(Interpolation::Nearest),
+            "bilinear" => Ok(Interpolation::Bilinear),
+            "lanczos" => Ok(Interpolation::Lanczos),
+            _ => Err(format!("unknown interpolation {name:?}, expected nearest, bilinear or lanczos")),
+        }
+    }
+}
+
+/// How an image that is not 28x28 is fitted onto the MNIST canvas, at training time (holdout
+/// images) and at inference, recorded in `model_meta.json` so that both agree.
+#[derive(Config, Debug, Copy, PartialEq)]
+pub enum ResizePolicy {
+    // Reject any other size
+    Strict,
+    // Stretch to 28x28 with `filter`
+    Resize { filter: Interpolation },
+    // Center on a 28x28 canvas of the `background` pixel value (0 is MNIST black), leaving the
+    // strokes untouched. Larger images are rejected.
+    PadToFit { background: u8 },
+}
+
+impl Default for ResizePolicy {
+    fn default() -> Self {
+        ResizePolicy::Resize { filter: Interpolation::default() }
+    }
+}
+
+impl ResizePolicy {
+    // The filter natural images scale their digit box with, whatever the policy of the canvas
+    fn filter(self) -> Interpolation {
+        match self {
+            ResizePolicy::Resize { filter } => filter,
+            ResizePolicy::Strict | ResizePolicy::PadToFit { .. } => Interpolation::default(),
+        }
+    }
+
+    /// Fits the grayscale `image` onto the canvas, or says why the policy rejects it.
+    pub fn apply(self, image: &GrayImage) -> Result<RawImage, String> {
+        let (width, height) = image.dimensions();
+        if (width, height) == (CANVAS_SIZE, CANVAS_SIZE) {
+            return Ok(to_raw_image(image));
+        }
+        match self {
+            ResizePolicy::Strict => Err(format!("is {width}x{height}, the strict resize policy only accepts 28x28 images")),
+            ResizePolicy::Resize { filter } => {
+                Ok(to_raw_image(&image::imageops::resize(image, CANVAS_SIZE, CANVAS_SIZE, filter.filter())))
+            }
+            ResizePolicy::PadToFit { .. } if width > CANVAS_SIZE || height > CANVAS_SIZE => {
+                Err(format!("is {width}x{height}, larger than the 28x28 canvas the pad-to-fit policy pads onto"))
+            }
+            ResizePolicy::PadToFit { background } => {
+                let mut canvas = GrayImage::from_pixel(CANVAS_SIZE, CANVAS_SIZE, Luma([background]));
+                let (x, y) = ((CANVAS_SIZE - width) / 2, (CANVAS_SIZE - height) / 2);
+                image::imageops::overlay(&mut canvas, image, x as i64, y as i64);
+                Ok(to_raw_image(&canvas))
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum LoadImageError {
+    Decode(image::ImageError),
+    // The resize policy rejects the size of the image
+    Size(String),
+}
+
+impl fmt::Display for LoadImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadImageError::Decode(err) => write!(f, "{err}"),
+            LoadImageError::Size(reason) => write!(f, "the image {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for LoadImageError {}
+
+impl From<image::ImageError> for LoadImageError {
+    fn from(err: image::ImageError) -> Self {
+        LoadImageError::Decode(err)
+    }
+}
+
+/// Reads an image file into raw MNIST pixels as a model trained with `policy` and the
+/// `preprocess` steps saw them: fitted onto the canvas by the policy, or with `natural` through
+/// [`preprocess_natural_image_with`] (cropped and scaled with the filter of a `Resize` policy,
+/// Lanczos otherwise), then through the steps.
+pub fn load_image_with_policy(
+    path: &str,
+    natural: bool,
+    policy: ResizePolicy,
+    preprocess: &[PreprocessConfig],
+) -> Result<RawImage, LoadImageError> {
+    fit_image(&image::open(path)?, natural, policy, preprocess)
+}
+
+/// [`load_image_with_policy`] of an encoded image (PNG, JPEG...) held in memory.
+pub fn decode_image_with_policy(
+    bytes: &[u8],
+    natural: bool,
+    policy: ResizePolicy,
+    preprocess: &[PreprocessConfig],
+) -> Result<RawImage, LoadImageError> {
+    fit_image(&image::load_from_memory(bytes)?, natural, policy, preprocess)
+}
+
+fn fit_image(
+    image: &DynamicImage,
+    natural: bool,
+    policy: ResizePolicy,
+    preprocess: &[PreprocessConfig],
+) -> Result<RawImage, LoadImageError> {
+    let pixels = match natural {
+        true => preprocess_natural_image_with(image, policy.filter()),
+        false => policy.apply(&image.to_luma8()).map_err(LoadImageError::Size)?,
+    };
+    Ok(apply_steps_raw(preprocess, &pixels))
+}
+
+// Reads an image file into raw MNIST pixels, see `predict_image_file` for `natural`
+pub fn load_image(path: &str, natural: bool) -> Result<RawImage, image::ImageError> {
+    load_image_with(path, natural, Interpolation::default())
+}
+
+// Same as `load_image`, resizing with `interpolation`
+pub fn load_image_with(path: &str, natural: bool, interpolation: Interpolation) -> Result<RawImage, image::ImageError> {
+    let image = image::open(path)?;
+    Ok(image_to_raw(&image, natural, interpolation))
+}
+
+/// An image as raw MNIST pixels: resized to 28x28 with `interpolation`, or, with `natural`, through
+/// [`preprocess_natural_image_with`].
+pub fn image_to_raw(image: &DynamicImage, natural: bool, interpolation: Interpolation) -> RawImage {
+    match natural {
+        true => preprocess_natural_image_with(image, interpolation),
+        false => resize_to_canvas(image, interpolation),
+    }
+}
+
+// Normalizes raw images through the batcher and returns the logits, [n, num_classes]
+fn forward_raw<B: Backend, M: Classifier<B> + ?Sized>(model: &M, device: &B::Device, images: Vec<RawImage>) -> Tensor<B, 2> {
+    let items = images.into_iter().map(|image| MnistItem { image, label: 0 }).collect();
+    let batch = MnistBatcher::new(device.clone()).batch(items);
+    model.forward(batch.images)
+}
+
+/// Class probabilities for each raw image, in input order.
+pub fn predict_probabilities<B: Backend, M: Classifier<B> + ?Sized>(
+    model: &M,
+    device: &B::Device,
+    images: Vec<RawImage>,
+) -> Vec<Vec<f32>> {
+    if images.is_empty() {
+        return Vec::new();
+    }
+
+    let probabilities = softmax(forward_raw(model, device, images), 1);
+    let [_, num_classes] = probabilities.dims();
+
+    probabilities
+        .into_data()
+        .convert::<f32>()
+        .value
+        .chunks(num_classes)
+        .map(<[f32]>::to_vec)
+        .collect()
+}
+
+/// Classifies any number of raw images, in input order, running the model on chunks of at most
+/// `max_batch` images (0 counts as 1) so that a long list never becomes one oversized tensor.
+/// Chunking does not change the results.
+pub fn predict_batch<B: Backend, M: Classifier<B> + ?Sized>(
+    model: &M,
+    device: &B::Device,
+    images: Vec<RawImage>,
+    max_batch: usize,
+    labels: &ClassLabels,
+) -> Vec<Prediction> {
+    images
+        .chunks(max_batch.max(1))
+        .flat_map(|chunk| predict_probabilities(model, device, chunk.to_vec()))
+        .map(|probabilities| Prediction::from_probabilities(probabilities, labels, DEFAULT_TOP_K))
+        .collect()
+}
+
+/// Hit and miss counts of a [`CachedPredictor`], since it was created.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    // Predictions currently cached, at most `capacity`
+    pub len: usize,
+    pub capacity: usize,
+}
+
+impl fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let lookups = (self.hits + self.misses).max(1);
+        write!(
+            f,
+            "{} hits, {} misses ({:.1}% hit rate), {}/{} cached",
+            self.hits,
+            self.misses,
+            self.hits as f64 / lookups as f64 * 100.0,
+            self.len,
+            self.capacity
+        )
+    }
+}
+
+// Files of an artifact dir whose contents identify the loaded model
+const MODEL_IDENTITY_FILES: [&str; 2] = ["model.mpk", "model_meta.json"];
+
+/// A model with a least-recently-used cache of its predictions, for callers that see the same
+/// images over and over. Predictions are keyed by the SHA-256 of the model hash and of the raw
+/// pixels, which the batcher normalizes the same way every time: a cached prediction is the one
+/// the model computed for those exact pixels, and swapping the model (see
+/// [`CachedPredictor::set_model`]) never serves the predictions of the previous one.
+pub struct CachedPredictor<B: Backend> {
+    model: Model<B>,
+    device: B::Device,
+    labels: ClassLabels,
+    model_hash: String,
+    capacity: usize,
+    // Prediction and last use of every cached key, `tick` counting the lookups
+    entries: HashMap<[u8; 32], (Prediction, u64)>,
+    tick: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl<B: Backend> CachedPredictor<B> {
+    /// `model_hash` identifies the weights of `model`, see [`CachedPredictor::load`]. A
+    /// `capacity` of 0 caches nothing.
+    pub fn new(model: Model<B>, device: B::Device, labels: ClassLabels, model_hash: String, capacity: usize) -> Self {
+        Self {
+            model,
+            device,
+            labels,
+            model_hash,
+            capacity,
+            entries: HashMap::new(),
+            tick: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Loads the model of `artifact_dir` (see [`load_model`]) with its class names, hashed from
+    /// its `model.mpk` weights and `model_meta.json`.
+    pub fn load(artifact_dir: &str, device: B::Device, capacity: usize) -> Result<Self, LoadError> {
+        let model = load_model::<B>(artifact_dir, &device)?;
+        let model_hash = model_hash(artifact_dir).map_err(|err| LoadError::Record(err.to_string()))?;
+        Ok(Self::new(model, device, ClassLabels::load(artifact_dir), model_hash, capacity))
+    }
+
+    /// Replaces the model, and drops the predictions of the previous one.
+    pub fn set_model(&mut self, model: Model<B>, labels: ClassLabels, model_hash: String) {
+        self.model = model;
+        self.labels = labels;
+        self.model_hash = model_hash;
+        self.entries.clear();
+    }
+
+    pub fn model_hash(&self) -> &str {
+        &self.model_hash
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats { hits: self.hits, misses: self.misses, len: self.entries.len(), capacity: self.capacity }
+    }
+
+    fn key(&self, image: &RawImage) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(self.model_hash.as_bytes());
+        for value in image.iter().flatten() {
+            hasher.update(value.to_le_bytes());
+        }
+        hasher.finalize().into()
+    }
+
+    /// [`predict`] of one image, from the cache when it holds it.
+    pub fn predict(&mut self, image: RawImage) -> Prediction {
+        self.predict_batch(vec![image]).pop().expect("One prediction per image")
+    }
+
+    /// [`predict_batch`] of `images`, in input order: the cached ones are looked up and the
+    /// others run through the model together, then cached.
+    pub fn predict_batch(&mut self, images: Vec<RawImage>) -> Vec<Prediction> {
+        let keys: Vec<[u8; 32]> = images.iter().map(|image| self.key(image)).collect();
+        let predictions: Vec<Option<Prediction>> = keys
+            .iter()
+            .map(|key| {
+                self.tick += 1;
+                let (prediction, last_use) = self.entries.get_mut(key)?;
+                *last_use = self.tick;
+                Some(prediction.clone())
+            })
+            .collect();
+
+        // The first image of every uncached key: repeats within the batch are computed once
+        let mut missing: Vec<usize> = Vec::new();
+        for index in (0..images.len()).filter(|&index| predictions[index].is_none()) {
+            if !missing.iter().any(|&first| keys[first] == keys[index]) {
+                missing.push(index);
+            }
+        }
+        self.hits += (images.len() - missing.len()) as u64;
+        self.misses += missing.len() as u64;
+        let computed = predict_batch(
+            &self.model,
+            &self.device,
+            missing.iter().map(|&index| images[index]).collect(),
+            missing.len(),
+            &self.labels,
+        );
+        let computed: HashMap<[u8; 32], Prediction> =
+            missing.into_iter().map(|index| keys[index]).zip(computed).collect();
+        for (key, prediction) in &computed {
+            self.insert(*key, prediction.clone());
+        }
+        predictions
+            .into_iter()
+            .zip(&keys)
+            .map(|(prediction, key)| prediction.unwrap_or_else(|| computed[key].clone()))
+            .collect()
+    }
+
+    // Caches `prediction`, evicting the least recently used one when full
+    fn insert(&mut self, key: [u8; 32], prediction: Prediction) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&key) {
+            let oldest = self.entries.iter().min_by_key(|(_, (_, last_use))| *last_use).map(|(key, _)| *key);
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.tick += 1;
+        self.entries.insert(key, (prediction, self.tick));
+    }
+}
+
+// Hex SHA-256 of the files identifying the model of `artifact_dir`, those that exist
+fn model_hash(artifact_dir: &str) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    for name in MODEL_IDENTITY_FILES {
+        let path = Path::new(artifact_dir).join(name);
+        if path.exists() {
+            hasher.update(sha256_file(&path)?.as_bytes());
+        }
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+// One line of streamed input: either a path to an image file, or the 784 raw pixel bytes
+// (row-major, white ink on black) encoded as base64. Both go through the `preprocess` steps.
+pub fn parse_input_line(line: &str, policy: ResizePolicy, preprocess: &[PreprocessConfig]) -> Result<RawImage, String> {
+    if Path::new(line).is_file() {
+        return load_image_with_policy(line, false, policy, preprocess).map_err(|err| format!("could not read image: {err}"));
+    }
+
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(line)
+        .map_err(|_| "neither an existing image path nor a base64 payload".to_string())?;
+    if bytes.len() != 28 * 28 {
+        return Err(format!("base64 payload has {} bytes, expected {}", bytes.len(), 28 * 28));
+    }
+
+    let mut pixels = [[0.0; 28]; 28];
+    for (pixel, byte) in pixels.iter_mut().flatten().zip(bytes) {
+        *pixel = byte as f32;
+    }
+    Ok(apply_steps_raw(preprocess, &pixels))
+}
+
+// A line of input that could not be read as text: the line with its invalid bytes replaced,
+// empty when the input itself failed, and why
+#[derive(Debug, Clone, PartialEq)]
+struct LineError {
+    input: String,
+    error: String,
+}
+
+// The lines of `input`, without their `\n`. A line that is not UTF-8 is an error and the lines
+// go on after it; a read error is the last line, as the input cannot be read past it.
+fn input_lines<R: BufRead>(input: R) -> impl Iterator<Item = Result<String, LineError>> {
+    let mut input = Some(input);
+    std::iter::from_fn(move || {
+        let mut bytes = Vec::new();
+        match input.as_mut()?.read_until(b'\n', &mut bytes) {
+            Ok(0) => {
+                input = None;
+                None
+            }
+            Ok(_) => {
+                if bytes.last() == Some(&b'\n') {
+                    bytes.pop();
+                }
+                Some(String::from_utf8(bytes).map_err(|err| LineError {
+                    input: String::from_utf8_lossy(err.as_bytes()).into_owned(),
+                    error: "the line is not valid UTF-8".to_string(),
+                }))
+            }
+            Err(err) => {
+                input = None;
+                Some(Err(LineError { input: String::new(), error: format!("could not read the input: {err}") }))
+            }
+        }
+    })
+}
+
+// Classifies a stream of lines (see `parse_input_line`) and writes one JSON object per input
+// line to `output`, in input order: the `Prediction` with the `input` line. Lines are grouped into batches of up to `batch_size`; a
+// batch is flushed early once `timeout` has passed since its first line so that a slow producer
+// still gets timely answers. Malformed lines, those that are not UTF-8 included, produce an
+// `{"input", "error"}` object in place; an input that fails to read ends the stream with one.
+// Image files are fitted onto the canvas with `policy`, and every image goes through the
+// `preprocess` steps.
+#[allow(clippy::too_many_arguments)]
+pub fn infer_stream<B: Backend, M: Classifier<B> + ?Sized, R: BufRead + Send + 'static, W: Write>(
+    model: &M,
+    device: &B::Device,
+    input: R,
+    mut output: W,
+    batch_size: usize,
+    timeout: Duration,
+    labels: &ClassLabels,
+    policy: ResizePolicy,
+    preprocess: &[PreprocessConfig],
+) -> io::Result<()> {
+    let (sender, receiver) = mpsc::channel();
+    let reader = thread::spawn(move || {
+        for line in input_lines(input) {
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut pending: Vec<Result<String, LineError>> = Vec::new();
+    let mut deadline: Option<Instant> = None;
+    let mut finished = false;
+
+    while !finished {
+        let received = match deadline {
+            None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            Some(deadline) => receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())),
+        };
+
+        match received {
+            Ok(line) => {
+                let line = line.map(|line| line.trim().to_string());
+                if line.as_ref().is_ok_and(|line| line.is_empty()) {
+                    continue;
+                }
+                deadline.get_or_insert_with(|| Instant::now() + timeout);
+                pending.push(line);
+                if pending.len() < batch_size {
+                    continue;
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => finished = true,
+        }
+
+        write_predictions(model, device, &pending, &mut output, labels, policy, preprocess)?;
+        pending.clear();
+        deadline = None;
+    }
+
+    reader.join().ok();
+    Ok(())
+}
+
+// Largest image blob `infer_framed` accepts, so that a corrupt length prefix cannot make it
+// allocate gigabytes
+pub const MAX_FRAME_BYTES: usize = 64 << 20;
+
+// Reads the next blob of a length-prefixed stream into `buffer`, false at a clean end of the
+// stream (between frames)
+fn read_frame<R: Read>(input: &mut R, buffer: &mut Vec<u8>) -> io::Result<bool> {
+    let mut prefix = [0u8; 4];
+    let mut filled = 0;
+    while filled < prefix.len() {
+        match input.read(&mut prefix[filled..]) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "stream ends inside a length prefix")),
+            Ok(read) => filled += read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    let len = u32::from_be_bytes(prefix) as usize;
+    if len > MAX_FRAME_BYTES {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("image of {len} bytes, the largest accepted is {MAX_FRAME_BYTES}"),
+        ));
+    }
+    buffer.resize(len, 0);
+    input.read_exact(buffer)?;
+    Ok(true)
+}
+
+// Classifies a stream of images, each an encoded image file (PNG...) preceded by its length in
+// bytes as a big-endian u32, and writes the label of each to `output` as soon as it is read, one
+// per line and in input order. Only one image is held in memory at a time, so the stream can be
+// unbounded. An image that does not decode writes an `error: ...` line in its place and the
+// stream goes on; a truncated frame or one over `MAX_FRAME_BYTES` ends it with an error.
+// Returns the number of images read.
+#[allow(clippy::too_many_arguments)]
+pub fn infer_framed<B: Backend, M: Classifier<B> + ?Sized, R: Read, W: Write>(
+    model: &M,
+    device: &B::Device,
+    mut input: R,
+    mut output: W,
+    natural: bool,
+    labels: &ClassLabels,
+    policy: ResizePolicy,
+    preprocess: &[PreprocessConfig],
+) -> io::Result<usize> {
+    let mut buffer = Vec::new();
+    let mut count = 0;
+    while read_frame(&mut input, &mut buffer)? {
+        count += 1;
+        match decode_image_with_policy(&buffer, natural, policy, preprocess) {
+            Ok(image) => writeln!(output, "{}", predict(model, device, image, labels).label)?,
+            Err(err) => writeln!(output, "error: {err}")?,
+        }
+        output.flush()?;
+    }
+    Ok(count)
+}
+
+// Interactive loop: prompts on `output` for an image path, reads it from `input`, and prints the
+// predicted label with its confidence, until `input` ends. An image that cannot be read prints
+// an error and the loop goes on; blank lines prompt again. Images are read with `policy` and
+// the `preprocess` steps.
+#[allow(clippy::too_many_arguments)]
+pub fn repl<B: Backend, M: Classifier<B> + ?Sized, R: BufRead, W: Write>(
+    model: &M,
+    device: &B::Device,
+    input: R,
+    mut output: W,
+    natural: bool,
+    labels: &ClassLabels,
+    policy: ResizePolicy,
+    preprocess: &[PreprocessConfig],
+) -> io::Result<()> {
+    let mut lines = input_lines(input);
+    loop {
+        write!(output, "image> ")?;
+        output.flush()?;
+        let Some(line) = lines.next() else {
+            writeln!(output)?;
+            return Ok(());
+        };
+        let path = match line {
+            Ok(path) => path,
+            Err(LineError { input, error }) => {
+                writeln!(output, "{}: error: {error}", input.trim())?;
+                continue;
+            }
+        };
+        let path = path.trim();
+        if path.is_empty() {
+            continue;
+        }
+        match load_image_with_policy(path, natural, policy, preprocess).map(|image| predict(model, device, image, labels)) {
+            Ok(prediction) => writeln!(output, "{path}: {prediction}")?,
+            Err(err) => writeln!(output, "{path}: error: {err}")?,
+        }
+    }
+}
+
+fn write_predictions<B: Backend, M: Classifier<B> + ?Sized, W: Write>(
+    model: &M,
+    device: &B::Device,
+    lines: &[Result<String, LineError>],
+    output: &mut W,
+    labels: &ClassLabels,
+    policy: ResizePolicy,
+    preprocess: &[PreprocessConfig],
+) -> io::Result<()> {
+    let parsed: Vec<(&str, Result<RawImage, String>)> = lines
+        .iter()
+        .map(|line| match line {
+            Ok(line) => (line.as_str(), parse_input_line(line, policy, preprocess)),
+            Err(LineError { input, error }) => (input.as_str(), Err(error.clone())),
+        })
+        .collect();
+    let images = parsed.iter().filter_map(|(_, image)| image.as_ref().ok().copied()).collect();
+    let mut probabilities = predict_probabilities(model, device, images).into_iter();
+
+    for (line, image) in parsed {
+        let record = match image {
+            Ok(_) => {
+                let probs = probabilities.next().expect("One prediction per valid input");
+                let prediction = Prediction::from_probabilities(probs, labels, DEFAULT_TOP_K);
+                let mut record = json!({ "input": line });
+                if let Value::Object(fields) = serde_json::to_value(prediction).expect("Prediction should serialize") {
+                    record.as_object_mut().expect("A JSON object").extend(fields);
+                }
+                record
+            }
+            Err(error) => json!({ "input": line, "error": error }),
+        };
+        writeln!(output, "{record}")?;
+    }
+
+    output.flush()
+}
+
+// `image` rotated by `degrees` around its center, then shifted by `(dx, dy)` pixels, with
+// bilinear interpolation. Pixels moved in from outside the canvas are background (0).
+pub(crate) fn rotate_and_shift(image: &RawImage, degrees: f32, dx: f32, dy: f32) -> RawImage {
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let center = (CANVAS_SIZE as f32 - 1.0) / 2.0;
+    let pixel = |x: isize, y: isize| match (usize::try_from(x), usize::try_from(y)) {
+        (Ok(x), Ok(y)) if x < 28 && y < 28 => image[y][x],
+        _ => 0.0,
+    };
+
+    let mut output = [[0.0; 28]; 28];
+    for (y, row) in output.iter_mut().enumerate() {
+        for (x, value) in row.iter_mut().enumerate() {
+            // Inverse mapping: where in the source the output pixel comes from
+            let (u, v) = (x as f32 - dx - center, y as f32 - dy - center);
+            let (sx, sy) = (cos * u + sin * v + center, -sin * u + cos * v + center);
+            let (x0, y0) = (sx.floor(), sy.floor());
+            let (fx, fy) = (sx - x0, sy - y0);
+            let (x0, y0) = (x0 as isize, y0 as isize);
+            *value = pixel(x0, y0) * (1.0 - fx) * (1.0 - fy)
+                + pixel(x0 + 1, y0) * fx * (1.0 - fy)
+                + pixel(x0, y0 + 1) * (1.0 - fx) * fy
+                + pixel(x0 + 1, y0 + 1) * fx * fy;
+        }
+    }
+    output
+}
+
+/// Test-time augmentation: classifies `num_augments` variants of `image` (0 counts as 1) in one
+/// batch and predicts from their mean probabilities. The first variant is the image
+/// itself, the others are small rotations (up to 10 degrees) and shifts (up to 2 pixels) drawn
+/// from a fixed seed, so the same image always gets the same prediction. With a single variant
+/// this is the plain prediction.
+pub fn predict_tta<B: Backend, M: Classifier<B> + ?Sized>(
+    model: &M,
+    device: &B::Device,
+    image: RawImage,
+    num_augments: usize,
+    labels: &ClassLabels,
+) -> Prediction {
+    let mut rng = StdRng::seed_from_u64(TTA_SEED);
+    let mut images = vec![image];
+    for _ in 1..num_augments {
+        let degrees = rng.gen_range(-TTA_MAX_ROTATION..=TTA_MAX_ROTATION);
+        let dx = rng.gen_range(-TTA_MAX_SHIFT..=TTA_MAX_SHIFT);
+        let dy = rng.gen_range(-TTA_MAX_SHIFT..=TTA_MAX_SHIFT);
+        images.push(rotate_and_shift(&image, degrees, dx, dy));
+    }
+
+    let probabilities = predict_probabilities(model, device, images);
+    let num_variants = probabilities.len() as f32;
+    let mut mean = vec![0.0; probabilities[0].len()];
+    for variant in &probabilities {
+        for (mean, probability) in mean.iter_mut().zip(variant) {
+            *mean += probability / num_variants;
+        }
+    }
+    Prediction::from_probabilities(mean, labels, DEFAULT_TOP_K)
+}
+
+// Index of the largest value, the first one on ties
+pub(crate) fn argmax(values: &[f32]) -> usize {
+    values
+        .iter()
+        .enumerate()
+        .fold((0, f32::NEG_INFINITY), |best, (index, &value)| if value > best.1 { (index, value) } else { best })
+        .0
+}
+
+// Summary of the stochastic forward passes of `predict_mc_dropout`
+#[derive(Debug, Clone, PartialEq)]
+pub struct McDropoutPrediction {
+    // Mean softmax output over the passes
+    pub mean: Vec<f32>,
+    // Per-class standard deviation of the softmax output over the passes
+    pub std: Vec<f32>,
+    // Entropy (in nats) of the mean probabilities
+    pub entropy: f32,
+}
+
+// Monte Carlo dropout: `n_samples` forward passes with dropout kept active, run as one batch.
+// The spread of the outputs is a cheap uncertainty estimate; masks are drawn from `seed` so the
+// result is reproducible. A model built with dropout = 0 gives identical passes (zero std).
+pub fn predict_mc_dropout<B: Backend>(
+    model: &Model<B>,
+    device: &B::Device,
+    item: MnistItem,
+    n_samples: usize,
+    seed: u64,
+) -> McDropoutPrediction {
+    if model.dropout_prob() == 0.0 {
+        eprintln!("Warning: the model has no dropout, MC dropout uncertainty will be degenerate");
+    }
+
+    let n_samples = n_samples.max(1);
+    let batch = MnistBatcher::new(device.clone()).batch(vec![item; n_samples]);
+    let mut rng = StdRng::seed_from_u64(seed);
+    let probabilities = softmax(model.forward_mc_dropout(batch.images, &mut rng), 1);
+
+    let mean = probabilities.clone().mean_dim(0);
+    let variance = (probabilities - mean.clone()).powf_scalar(2.0).mean_dim(0);
+    let mean = mean.into_data().convert::<f32>().value;
+    let std = variance.sqrt().into_data().convert::<f32>().value;
+    let entropy = -mean.iter().filter(|&&p| p > 0.0).map(|&p| p * p.ln()).sum::<f32>();
+
+    McDropoutPrediction { mean, std, entropy }
+}
+
+// Windows `detect_digits` runs through the model at once
+const DETECTION_BATCH_SIZE: usize = 256;
+// Largest distance, in pixels of a 28x28 window, between the center of a window and the center
+// of mass of its ink for `detect_digits` to classify it
+const DETECTION_CENTERING_TOLERANCE: f32 = 3.0;
+// Windows of a class overlapping a more confident one of that class by more than this
+// intersection over union are suppressed
+const NMS_IOU_THRESHOLD: f32 = 0.3;
+// One color per class of the boxes `draw_detections` draws, cycled past ten classes
+const DETECTION_COLORS: [[u8; 3]; 10] = [
+    [230, 25, 75],
+    [60, 180, 75],
+    [255, 225, 25],
+    [0, 130, 200],
+    [245, 130, 48],
+    [145, 30, 180],
+    [70, 240, 240],
+    [240, 50, 230],
+    [210, 245, 60],
+    [0, 128, 128],
+];
+
+/// A digit found by [`detect_digits`]: the window it was classified in, in pixels of the image,
+/// the class and its softmax probability.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Detection {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub class: usize,
+    pub confidence: f32,
+}
+
+impl Detection {
+    fn iou(&self, other: &Detection) -> f32 {
+        let overlap = |start: u32, len: u32, other_start: u32, other_len: u32| {
+            (start + len).min(other_start + other_len).saturating_sub(start.max(other_start))
+        };
+        let intersection = overlap(self.x, self.width, other.x, other.width) as f32
+            * overlap(self.y, self.height, other.y, other.height) as f32;
+        let union = (self.width * self.height + other.width * other.height) as f32 - intersection;
+        intersection / union.max(1.0)
+    }
+}
+
+/// Finds the digits of an image larger than 28x28, such as a strip of handwritten digits, by
+/// sliding the classifier over it in 28x28 windows `stride` pixels apart (0 counts as 1). See
+/// [`detect_digits_multiscale`].
+pub fn detect_digits<B: Backend, M: Classifier<B> + ?Sized>(
+    model: &M,
+    device: &B::Device,
+    image: &DynamicImage,
+    stride: u32,
+    confidence_threshold: f32,
+) -> Vec<Detection> {
+    detect_digits_multiscale(model, device, image, stride, confidence_threshold, &[CANVAS_SIZE])
+}
+
+/// [`detect_digits`] with square windows of every size of `window_sizes`, each resized to 28x28
+/// before being classified, for digits of different sizes. Dark ink on a light background is
+/// inverted first, as for [`preprocess_natural_image`], and windows whose ink is not about
+/// centered are skipped, as MNIST digits are centered on their center of mass. The
+/// windows whose prediction reaches `confidence_threshold` are kept, but for those overlapping a
+/// more confident window of the same class, and returned left to right. Window sizes larger than
+/// the image are skipped.
+pub fn detect_digits_multiscale<B: Backend, M: Classifier<B> + ?Sized>(
+    model: &M,
+    device: &B::Device,
+    image: &DynamicImage,
+    stride: u32,
+    confidence_threshold: f32,
+    window_sizes: &[u32],
+) -> Vec<Detection> {
+    let ink = ink_image(&image.to_luma8());
+    let stride = stride.max(1);
+    // The last window of a row or column is flush with the edge, however the stride falls
+    let positions = |len: u32, size: u32| {
+        let mut positions: Vec<u32> = (0..=len - size).step_by(stride as usize).collect();
+        if positions.last() != Some(&(len - size)) {
+            positions.push(len - size);
+        }
+        positions
+    };
+
+    let mut windows = Vec::new();
+    let mut images = Vec::new();
+    for &size in window_sizes.iter().filter(|&&size| size > 0 && size <= ink.width() && size <= ink.height()) {
+        for y in positions(ink.height(), size) {
+            for x in positions(ink.width(), size) {
+                let window = image::imageops::crop_imm(&ink, x, y, size, size).to_image();
+                // MNIST digits are centered on their center of mass, a window that is not sees
+                // part of a digit or several
+                let Some((com_x, com_y)) = center_of_mass(&window) else {
+                    continue;
+                };
+                let offset = (com_x - size as f32 / 2.0).hypot(com_y - size as f32 / 2.0);
+                if offset > DETECTION_CENTERING_TOLERANCE * size as f32 / CANVAS_SIZE as f32 {
+                    continue;
+                }
+                let window = match size {
+                    CANVAS_SIZE => window,
+                    _ => image::imageops::resize(&window, CANVAS_SIZE, CANVAS_SIZE, FilterType::Triangle),
+                };
+                windows.push((x, y, size));
+                images.push(to_raw_image(&window));
+            }
+        }
+    }
+
+    let predictions = predict_batch(model, device, images, DETECTION_BATCH_SIZE, &ClassLabels::default());
+    let mut candidates: Vec<Detection> = windows
+        .into_iter()
+        .zip(predictions)
+        .filter(|(_, prediction)| prediction.confidence >= confidence_threshold)
+        .map(|((x, y, size), prediction)| Detection {
+            x,
+            y,
+            width: size,
+            height: size,
+            class: prediction.index,
+            confidence: prediction.confidence,
+        })
+        .collect();
+    candidates.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+
+    let mut detections: Vec<Detection> = Vec::new();
+    for candidate in candidates {
+        let suppressed = detections
+            .iter()
+            .any(|kept| kept.class == candidate.class && kept.iou(&candidate) > NMS_IOU_THRESHOLD);
+        if !suppressed {
+            detections.push(candidate);
+        }
+    }
+    detections.sort_by_key(|detection| (detection.x, detection.y));
+    detections
+}
+
+/// Draws the box of every detection onto a copy of `image`, in one color per class, and saves
+/// it as a PNG at `path`.
+pub fn draw_detections(image: &DynamicImage, detections: &[Detection], path: &str) -> image::ImageResult<()> {
+    let mut canvas = image.to_rgb8();
+    for detection in detections {
+        let color = Rgb(DETECTION_COLORS[detection.class % DETECTION_COLORS.len()]);
+        let right = (detection.x + detection.width).min(canvas.width()) - 1;
+        let bottom = (detection.y + detection.height).min(canvas.height()) - 1;
+        for x in detection.x..=right {
+            canvas.put_pixel(x, detection.y, color);
+            canvas.put_pixel(x, bottom, color);
+        }
+        for y in detection.y..=bottom {
+            canvas.put_pixel(detection.x, y, color);
+            canvas.put_pixel(right, y, color);
+        }
+    }
+    canvas.save_with_format(path, image::ImageFormat::Png)
+}
+
+fn to_raw_image(image: &GrayImage) -> RawImage {
+    let mut pixels = [[0.0; 28]; 28];
+    for (x, y, Luma([value])) in image.enumerate_pixels() {
+        pixels[y as usize][x as usize] = *value as f32;
+    }
+    pixels
+}
+
+fn resize_to_canvas(image: &DynamicImage, interpolation: Interpolation) -> RawImage {
+    ResizePolicy::Resize { filter: interpolation }.apply(&image.to_luma8()).expect("Resizing accepts every image size")
+}
+
+// Otsu's method: the threshold maximizing the between-class variance of the histogram
+fn otsu_threshold(image: &GrayImage) -> u8 {
+    let mut histogram = [0u64; 256];
+    for Luma([value]) in image.pixels() {
+        histogram[*value as usize] += 1;
+    }
+
+    let total = image.pixels().len() as f64;
+    let sum_all: f64 = histogram.iter().enumerate().map(|(v, &n)| v as f64 * n as f64).sum();
+
+    let (mut best_threshold, mut best_variance) = (0, 0.0);
+    let (mut weight_bg, mut sum_bg) = (0.0, 0.0);
+    for (value, &count) in histogram.iter().enumerate() {
+        weight_bg += count as f64;
+        sum_bg += value as f64 * count as f64;
+        let weight_fg = total - weight_bg;
+        if weight_bg == 0.0 || weight_fg == 0.0 {
+            continue;
+        }
+
+        let mean_bg = sum_bg / weight_bg;
+        let mean_fg = (sum_all - sum_bg) / weight_fg;
+        let variance = weight_bg * weight_fg * (mean_bg - mean_fg).powi(2);
+        if variance > best_variance {
+            best_variance = variance;
+            best_threshold = value as u8;
+        }
+    }
+
+    best_threshold
+}
+
+// Paper is usually brighter than ink; the border of a photo is almost always paper
+fn has_dark_ink(image: &GrayImage, threshold: u8) -> bool {
+    let (width, height) = image.dimensions();
+    let border: Vec<u8> = image
+        .enumerate_pixels()
+        .filter(|(x, y, _)| *x == 0 || *y == 0 || *x == width - 1 || *y == height - 1)
+        .map(|(_, _, Luma([value]))| *value)
+        .collect();
+    let bright = border.iter().filter(|&&value| value > threshold).count();
+
+    bright * 2 > border.len()
+}
+
+// Reproduces the MNIST preparation on an arbitrary photo of a single digit:
+// grayscale, Otsu binarization with inversion so that ink is bright, crop to the ink bounding
+// box, scale into a 20x20 box and paste it on the 28x28 canvas so that its center of mass lands
+// in the middle. The output goes through the usual batcher normalization afterwards.
+pub fn preprocess_natural_image(image: &DynamicImage) -> RawImage {
+    preprocess_natural_image_with(image, Interpolation::default())
+}
+
+// Same as `preprocess_natural_image`, scaling the digit with `interpolation`
+pub fn preprocess_natural_image_with(image: &DynamicImage, interpolation: Interpolation) -> RawImage {
+    let ink = ink_image(&image.to_luma8());
+
+    let Some((min_x, min_y, max_x, max_y)) = ink_bounding_box(&ink) else {
+        // Blank image: nothing to center
+        return [[0.0; 28]; 28];
+    };
+    let digit = image::imageops::crop_imm(&ink, min_x, min_y, max_x - min_x + 1, max_y - min_y + 1);
+    let digit = digit.to_image();
+
+    let scale = DIGIT_BOX_SIZE as f32 / digit.width().max(digit.height()) as f32;
+    let width = ((digit.width() as f32 * scale).round() as u32).clamp(1, DIGIT_BOX_SIZE);
+    let height = ((digit.height() as f32 * scale).round() as u32).clamp(1, DIGIT_BOX_SIZE);
+    let digit = image::imageops::resize(&digit, width, height, interpolation.filter());
+
+    let (com_x, com_y) = center_of_mass(&digit).unwrap_or((width as f32 / 2.0, height as f32 / 2.0));
+    let center = CANVAS_SIZE as f32 / 2.0;
+    let max_offset_x = (CANVAS_SIZE - width) as i64;
+    let max_offset_y = (CANVAS_SIZE - height) as i64;
+    let offset_x = ((center - com_x).round() as i64).clamp(0, max_offset_x);
+    let offset_y = ((center - com_y).round() as i64).clamp(0, max_offset_y);
+
+    let mut canvas = GrayImage::new(CANVAS_SIZE, CANVAS_SIZE);
+    image::imageops::overlay(&mut canvas, &digit, offset_x, offset_y);
+
+    to_raw_image(&canvas)
+}
+
+// The ink of a photo as MNIST draws it, bright on black: Otsu binarization, with inversion when
+// the ink is dark. The background becomes 0 and the ink keeps its (inverted if needed) distance
+// to the threshold, stretched so that the strongest stroke is 255.
+fn ink_image(gray: &GrayImage) -> GrayImage {
+    let threshold = otsu_threshold(gray);
+    let invert = has_dark_ink(gray, threshold);
+
+    // Otsu splits into `<= t` and `> t`
+    let strength = |value: u8| match (invert, value > threshold) {
+        (false, true) => (value - threshold) as f32,
+        (true, false) => (threshold - value) as f32 + 1.0,
+        _ => 0.0,
+    };
+    let max_strength = gray.pixels().map(|Luma([value])| strength(*value)).fold(0.0, f32::max);
+    GrayImage::from_fn(gray.width(), gray.height(), |x, y| {
+        let value = strength(gray.get_pixel(x, y)[0]) / max_strength.max(1.0);
+        Luma([(value * 255.0).round() as u8])
+    })
+}
+
+fn ink_bounding_box(image: &GrayImage) -> Option<(u32, u32, u32, u32)> {
+    image
+        .enumerate_pixels()
+        .filter(|(_, _, Luma([value]))| *value > 0)
+        .fold(None, |bbox, (x, y, _)| match bbox {
+            None => Some((x, y, x, y)),
+            Some((min_x, min_y, max_x, max_y)) => {
+                Some((min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y)))
+            }
+        })
+}
+
+fn center_of_mass(image: &GrayImage) -> Option<(f32, f32)> {
+    let (mut mass, mut sum_x, mut sum_y) = (0.0, 0.0, 0.0);
+    for (x, y, Luma([value])) in image.enumerate_pixels() {
+        let value = *value as f32;
+        mass += value;
+        sum_x += (x as f32 + 0.5) * value;
+        sum_y += (y as f32 + 0.5) * value;
+    }
+
+    (mass > 0.0).then(|| (sum_x / mass, sum_y / mass))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::synthetic::SyntheticDigits;
+    use burn::{
+        backend::{ndarray::NdArrayDevice, NdArray},
+        data::dataset::Dataset,
+    };
+
+    // A phone photo of a handwritten 1: dark ink, off-center on grayish, unevenly lit paper
+    fn photo_fixture() -> DynamicImage {
+        let photo = GrayImage::from_fn(90, 120, |x, y| match (60..68).contains(&x) && (20..80).contains(&y) {
+            true => Luma([40 + (y % 7) as u8]),
+            false => Luma([180 + (x / 10) as u8]),
+        });
+        DynamicImage::ImageLuma8(photo)
+    }
+
+    #[test]
+    fn natural_images_come_out_as_centered_white_ink_on_black() {
+        let pixels = preprocess_natural_image(&photo_fixture());
+
+        assert_eq!((pixels.len(), pixels[0].len()), (28, 28));
+        assert!(pixels.iter().flatten().all(|value| (0.0..=255.0).contains(value)));
+        assert!(pixels.iter().flatten().copied().fold(0.0, f32::max) > 128.0, "the ink is not bright");
+        // Paper becomes background, the corners of MNIST digits are empty
+        assert_eq!([pixels[0][0], pixels[0][27], pixels[27][0], pixels[27][27]], [0.0; 4]);
+        let canvas = GrayImage::from_fn(28, 28, |x, y| Luma([pixels[y as usize][x as usize] as u8]));
+        let (com_x, com_y) = center_of_mass(&canvas).unwrap();
+        assert!((com_x - 14.0).abs() <= 1.0 && (com_y - 14.0).abs() <= 1.0, "center of mass at {com_x}, {com_y}");
+        // The digit fills the 20 pixel box along its long side
+        let rows = pixels.iter().filter(|row| row.iter().any(|value| *value > 0.0)).count();
+        assert_eq!(rows, DIGIT_BOX_SIZE as usize);
+    }
+
+    #[test]
+    fn predict_image_file_preprocesses_natural_images_on_request() {
+        let device = NdArrayDevice::default();
+        let model = ModelConfig::new(10, 8).init::<NdArray>(&device);
+        let path = std::env::temp_dir().join("my_first_rust_DL_app-photo.png");
+        photo_fixture().save(&path).unwrap();
+
+        for natural in [false, true] {
+            let predicted = predict_image_file(&model, &device, path.to_str().unwrap(), natural).unwrap();
+            assert!(predicted < 10);
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn infer_stream_answers_every_line_in_order() {
+        let device = NdArrayDevice::default();
+        let model = ModelConfig::new(10, 8).init::<NdArray>(&device);
+        let pixels = |value: u8| base64::engine::general_purpose::STANDARD.encode([value; 28 * 28]);
+        let lines = [pixels(0), "not an image".to_string(), pixels(255)];
+        let input = io::Cursor::new(lines.join("\n").into_bytes());
+        let mut output = Vec::new();
+
+        // Three lines by two: EOF flushes the last, partial batch
+        let labels = ClassLabels::indices(10);
+        infer_stream(&model, &device, input, &mut output, 2, Duration::from_secs(5), &labels, ResizePolicy::default(), &[])
+            .unwrap();
+
+        let records: Vec<Value> =
+            String::from_utf8(output).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(records.len(), 3);
+        for (record, line) in records.iter().zip(&lines) {
+            assert_eq!(record["input"], line.as_str());
+        }
+        let probs = |record: &Value| record["probabilities"].as_array().unwrap().len();
+        assert_eq!((probs(&records[0]), probs(&records[2])), (10, 10));
+        assert!(records[0]["label"].is_string() && records[1]["label"].is_null());
+        assert_eq!(records[1]["error"], "neither an existing image path nor a base64 payload");
+    }
+
+    // Fails every read, as an input whose device is gone
+    struct BrokenInput;
+
+    impl Read for BrokenInput {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::other("device gone"))
+        }
+    }
+
+    #[test]
+    fn infer_stream_answers_lines_that_are_not_utf8_and_a_failed_read() {
+        let device = NdArrayDevice::default();
+        let model = ModelConfig::new(10, 8).init::<NdArray>(&device);
+        let pixels = base64::engine::general_purpose::STANDARD.encode([0u8; 28 * 28]);
+        let bytes = [pixels.as_bytes(), b"\n\xff\xfe bad\n", pixels.as_bytes(), b"\n"].concat();
+        let input = io::BufReader::new(io::Cursor::new(bytes).chain(BrokenInput));
+        let mut output = Vec::new();
+
+        let labels = ClassLabels::indices(10);
+        infer_stream(&model, &device, input, &mut output, 8, Duration::from_secs(5), &labels, ResizePolicy::default(), &[])
+            .unwrap();
+
+        let records: Vec<Value> =
+            String::from_utf8(output).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(records.len(), 4);
+        assert!(records[0]["label"].is_string() && records[2]["label"].is_string());
+        assert_eq!(records[1]["input"], "\u{fffd}\u{fffd} bad");
+        assert_eq!(records[1]["error"], "the line is not valid UTF-8");
+        assert_eq!(records[3]["error"], "could not read the input: device gone");
+    }
+
+    #[test]
+    fn repl_goes_on_after_a_line_that_is_not_utf8() {
+        let device = NdArrayDevice::default();
+        let model = ModelConfig::new(10, 8).init::<NdArray>(&device);
+        let labels = ClassLabels::indices(10);
+        let input: &[u8] = b"/nonexistent/a.png\n\xff.png\n/nonexistent/b.png\n";
+
+        let mut output = Vec::new();
+        repl(&model, &device, input, &mut output, false, &labels, ResizePolicy::default(), &[]).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.split("image> ").filter(|line| !line.is_empty()).collect();
+        assert_eq!(lines.len(), 4, "{output}");
+        assert!(lines[0].starts_with("/nonexistent/a.png: error: "), "{output}");
+        assert_eq!(lines[1], "\u{fffd}.png: error: the line is not valid UTF-8\n");
+        assert!(lines[2].starts_with("/nonexistent/b.png: error: "), "{output}");
+    }
+
+    #[test]
+    fn mc_dropout_is_reproducible_under_a_seed() {
+        let device = NdArrayDevice::default();
+        let item = SyntheticDigits::new(1, 0).get(0).unwrap();
+        let model = ModelConfig::new(10, 8).with_dropout(0.5).init::<NdArray>(&device);
+
+        let first = predict_mc_dropout(&model, &device, item.clone(), 16, 7);
+        assert_eq!(predict_mc_dropout(&model, &device, item.clone(), 16, 7), first);
+        assert_ne!(predict_mc_dropout(&model, &device, item.clone(), 16, 8), first);
+        assert!((first.mean.iter().sum::<f32>() - 1.0).abs() < 1e-4);
+        assert!(first.std.iter().any(|std| *std > 0.0));
+
+        // Without dropout every pass is the plain forward pass
+        let model = ModelConfig::new(10, 8).with_dropout(0.0).init::<NdArray>(&device);
+        let prediction = predict_mc_dropout(&model, &device, item, 16, 7);
+        assert!(prediction.std.iter().all(|std| *std < 1e-6));
+    }
+
+    #[test]
+    fn prediction_json_has_the_label_confidence_and_probabilities() {
+        let device = NdArrayDevice::default();
+        let model = ModelConfig::new(10, 8).init::<NdArray>(&device);
+        let prediction = predict(&model, &device, [[0.0; 28]; 28], &ClassLabels::indices(10));
+
+        // What `infer --json` prints
+        let json: Value = serde_json::from_str(&serde_json::to_string(&prediction).unwrap()).unwrap();
+        assert_eq!(json["label"], prediction.index.to_string());
+        assert_eq!(json["index"], prediction.index);
+        let probabilities: Vec<f64> =
+            json["probabilities"].as_array().unwrap().iter().map(|p| p.as_f64().unwrap()).collect();
+        assert_eq!(probabilities.len(), 10);
+        assert!((probabilities.iter().sum::<f64>() - 1.0).abs() < 1e-4);
+        let confidence = json["confidence"].as_f64().unwrap();
+        assert_eq!(confidence, probabilities[prediction.index]);
+    }
+
+    #[test]
+    fn chunked_batches_predict_like_one_batch() {
+        let device = NdArrayDevice::default();
+        let model = ModelConfig::new(10, 8).init::<NdArray>(&device);
+        let labels = ClassLabels::indices(10);
+        let images: Vec<RawImage> = SyntheticDigits::new(100, 1).iter().map(|item| item.image).collect();
+
+        let whole = predict_batch(&model, &device, images.clone(), 100, &labels);
+        let chunked = predict_batch(&model, &device, images, 7, &labels);
+        assert_eq!(whole.len(), 100);
+        for (whole, chunked) in whole.iter().zip(&chunked) {
+            assert_eq!(whole.index, chunked.index);
+            let close = whole.probabilities.iter().zip(&chunked.probabilities).all(|(a, b)| (a - b).abs() < 1e-6);
+            assert!(close, "{:?} != {:?}", whole.probabilities, chunked.probabilities);
+        }
+        assert!(predict_batch(&model, &device, Vec::new(), 7, &labels).is_empty());
+    }
+
+    #[test]
+    fn tta_with_a_single_variant_is_the_plain_prediction() {
+        let device = NdArrayDevice::default();
+        let model = ModelConfig::new(10, 8).init::<NdArray>(&device);
+        let labels = ClassLabels::indices(10);
+        let image = SyntheticDigits::new(1, 0).get(0).unwrap().image;
+
+        let plain = predict(&model, &device, image, &labels);
+        assert_eq!(predict_tta(&model, &device, image, 1, &labels), plain);
+        assert_eq!(predict_tta(&model, &device, image, 0, &labels), plain);
+        // The augmentations are drawn from a fixed seed
+        let augmented = predict_tta(&model, &device, image, 8, &labels);
+        assert_eq!(predict_tta(&model, &device, image, 8, &labels), augmented);
+        assert_ne!(augmented.probabilities, plain.probabilities);
+    }
+
+    #[test]
+    fn repl_answers_each_path_and_goes_on_after_errors() {
+        let device = NdArrayDevice::default();
+        let model = ModelConfig::new(10, 8).init::<NdArray>(&device);
+        let labels = ClassLabels::indices(10);
+        let path = std::env::temp_dir().join("my_first_rust_DL_app-repl.png");
+        let image = SyntheticDigits::new(1, 0).get(0).unwrap().image;
+        GrayImage::from_fn(28, 28, |x, y| Luma([image[y as usize][x as usize] as u8])).save(&path).unwrap();
+        let path = path.to_str().unwrap();
+        let input = format!("/nonexistent/my_first_rust_DL_app-repl.png\n\n{path}\n");
+
+        let mut output = Vec::new();
+        repl(&model, &device, input.as_bytes(), &mut output, false, &labels, ResizePolicy::default(), &[]).unwrap();
+        let loaded = load_image_with_policy(path, false, ResizePolicy::default(), &[]).unwrap();
+        let expected = predict(&model, &device, loaded, &labels);
+        std::fs::remove_file(path).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.split("image> ").filter(|line| !line.is_empty()).collect();
+        // A prompt per line read, and one more at EOF
+        assert_eq!(lines.len(), 3, "{output}");
+        assert!(lines[0].starts_with("/nonexistent/my_first_rust_DL_app-repl.png: error: "), "{output}");
+        assert_eq!(lines[1], format!("{path}: {expected}\n"));
+        assert_eq!(lines[2], "\n");
+    }
+
+    #[test]
+    fn nearest_and_lanczos_resize_a_checkerboard_differently() {
+        // One-pixel checkerboard, far finer than the 28x28 grid it is shrunk to
+        let pattern = GrayImage::from_fn(100, 100, |x, y| Luma([if (x + y) % 2 == 0 { 255 } else { 0 }]));
+        let pattern = DynamicImage::ImageLuma8(pattern);
+
+        let nearest = image_to_raw(&pattern, false, Interpolation::Nearest);
+        let lanczos = image_to_raw(&pattern, false, Interpolation::Lanczos);
+        let bilinear = image_to_raw(&pattern, false, Interpolation::Bilinear);
+        let distance = |a: &RawImage, b: &RawImage| {
+            a.iter().flatten().zip(b.iter().flatten()).map(|(a, b)| (a - b).abs()).sum::<f32>() / (28.0 * 28.0)
+        };
+        // Nearest picks single pixels, black or white; the filters average them towards gray
+        assert!(nearest.iter().flatten().all(|&value| value == 0.0 || value == 255.0));
+        assert!(distance(&nearest, &lanczos) > 50.0, "mean difference {}", distance(&nearest, &lanczos));
+        assert!(distance(&nearest, &bilinear) > 50.0);
+        assert_eq!(image_to_raw(&pattern, false, Interpolation::default()), lanczos);
+    }
+
+    #[test]
+    fn every_resize_policy_fits_or_rejects_20x20_and_40x40_images() {
+        // A white square framed by a one-pixel black border
+        let square = |side: u32| {
+            GrayImage::from_fn(side, side, |x, y| match x == 0 || y == 0 || x == side - 1 || y == side - 1 {
+                true => Luma([0]),
+                false => Luma([255]),
+            })
+        };
+        let (small, large) = (square(20), square(40));
+        let strict = ResizePolicy::Strict;
+        let resize = ResizePolicy::Resize { filter: Interpolation::Nearest };
+        let pad = ResizePolicy::PadToFit { background: 7 };
+
+        assert!(strict.apply(&small).unwrap_err().contains("20x20"));
+        assert!(strict.apply(&large).unwrap_err().contains("40x40"));
+        for image in [&small, &large] {
+            let pixels = resize.apply(image).unwrap();
+            // The border shrinks or grows with the image, the center stays white
+            assert_eq!((pixels[0][0], pixels[14][14]), (0.0, 255.0));
+        }
+        let padded = pad.apply(&small).unwrap();
+        // 4 pixels of background around the untouched 20x20 image
+        assert_eq!((padded[0][0], padded[3][3], padded[4][4], padded[5][5]), (7.0, 7.0, 0.0, 255.0));
+        assert_eq!(padded.iter().flatten().filter(|&&value| value == 255.0).count(), 18 * 18);
+        assert!(pad.apply(&large).unwrap_err().contains("larger than the 28x28 canvas"));
+        // The canvas size passes through every policy as it is
+        let canvas = square(28);
+        for policy in [strict, resize, pad] {
+            assert_eq!(policy.apply(&canvas).unwrap(), to_raw_image(&canvas));
+        }
+    }
+
+    #[test]
+    fn cache_hits_are_identical_to_cold_predictions() {
+        let device = NdArrayDevice::default();
+        let model = ModelConfig::new(10, 8).init::<NdArray>(&device);
+        let labels = ClassLabels::indices(10);
+        let images: Vec<RawImage> = SyntheticDigits::new(3, 1).iter().map(|item| item.image).collect();
+        let cold = predict_batch(&model, &device, images.clone(), 3, &labels);
+
+        let mut cached = CachedPredictor::new(model, device, labels.clone(), "a".to_string(), 2);
+        // Repeats within a batch are computed once
+        let first = cached.predict_batch(vec![images[0], images[1], images[0]]);
+        assert_eq!(first, [cold[0].clone(), cold[1].clone(), cold[0].clone()]);
+        assert_eq!((cached.stats().hits, cached.stats().misses), (1, 2));
+        assert_eq!(cached.predict(images[1]), cold[1]);
+        assert_eq!((cached.stats().hits, cached.stats().misses), (2, 2));
+
+        // Full, the least recently used image makes room: the first one, not the second
+        assert_eq!(cached.predict(images[2]), cold[2]);
+        assert_eq!(cached.predict(images[1]), cold[1]);
+        assert_eq!(cached.predict(images[0]), cold[0]);
+        assert_eq!(cached.stats(), CacheStats { hits: 3, misses: 4, len: 2, capacity: 2 });
+
+        // Another model computes its own predictions
+        let other = ModelConfig::new(10, 8).init::<NdArray>(&device);
+        let expected = predict_batch(&other, &device, vec![images[0]], 1, &labels);
+        cached.set_model(other, labels, "b".to_string());
+        assert_eq!(cached.predict(images[0]), expected[0]);
+        assert_eq!((cached.stats().misses, cached.stats().len), (5, 1));
+    }
+
+    #[test]
+    fn framed_stream_labels_every_image_in_order() {
+        let device = NdArrayDevice::default();
+        let model = ModelConfig::new(10, 8).init::<NdArray>(&device);
+        let labels = ClassLabels::indices(10);
+        let framed = |input: &[u8], output: &mut dyn Write| {
+            infer_framed(&model, &device, input, output, false, &labels, ResizePolicy::Strict, &[])
+        };
+        // Whole pixel values, which PNGs hold exactly
+        let images: Vec<RawImage> =
+            SyntheticDigits::new(2, 1).iter().map(|item| item.image.map(|row| row.map(f32::round))).collect();
+        let frame = |bytes: &[u8]| [&(bytes.len() as u32).to_be_bytes()[..], bytes].concat();
+        let png = |image: &RawImage| {
+            let canvas = GrayImage::from_fn(28, 28, |x, y| Luma([image[y as usize][x as usize] as u8]));
+            let mut bytes = io::Cursor::new(Vec::new());
+            canvas.write_to(&mut bytes, image::ImageFormat::Png).unwrap();
+            bytes.into_inner()
+        };
+
+        // An undecodable image in the middle takes its line and the stream goes on
+        let input = [frame(&png(&images[0])), frame(b"not a png"), frame(&png(&images[1]))].concat();
+        let mut output = Vec::new();
+        let count = framed(&input, &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!((count, lines.len()), (3, 3));
+        assert_eq!(lines[0], predict(&model, &device, images[0], &labels).label);
+        assert!(lines[1].starts_with("error: "), "{}", lines[1]);
+        assert_eq!(lines[2], predict(&model, &device, images[1], &labels).label);
+
+        // A frame cut short, and a length prefix over the limit, end the stream
+        let truncated = [frame(&png(&images[0])), 100u32.to_be_bytes().to_vec(), vec![0; 10]].concat();
+        let mut output = Vec::new();
+        let err = framed(&truncated, &mut output).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(String::from_utf8(output).unwrap().lines().count(), 1);
+        let oversized = (MAX_FRAME_BYTES as u32 + 1).to_be_bytes();
+        let err = framed(&oversized, &mut io::sink()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(framed(&[], &mut io::sink()).unwrap(), 0);
+    }
+
+    #[test]
+    fn models_round_trip_through_the_bytes_of_every_format() {
+        let device = NdArrayDevice::default();
+        let config = TrainingConfig::new(ModelConfig::new(10, 8), burn::optim::AdamConfig::new());
+        let config_json = serde_json::to_string(&config).unwrap();
+        let model = config.model.init::<NdArray>(&device);
+        let digits = SyntheticDigits::new(8, 1);
+        let images = MnistBatcher::<NdArray>::new(device).batch(digits.iter().collect()).images;
+        let expected = model.forward(images.clone());
+
+        let formats = [
+            RecordFormat::Compact,
+            RecordFormat::NamedMpk,
+            RecordFormat::NamedMpkGz,
+            RecordFormat::Bin,
+            RecordFormat::Json,
+        ];
+        for format in formats {
+            let bytes = model.to_bytes(format);
+            assert_eq!(RecordFormat::detect(&bytes), Some(format));
+            let reloaded = Model::<NdArray>::from_bytes(&config_json, &bytes, &device).unwrap();
+            let output = reloaded.forward(images.clone());
+            // Compact weights are stored in f16
+            let tolerance = if format == RecordFormat::Compact { 1e-2 } else { 0.0 };
+            let difference = (output - expected.clone()).abs().max().into_scalar();
+            assert!(difference <= tolerance, "{format:?} logits differ by {difference}");
+        }
+
+        assert_eq!(RecordFormat::detect(b"not weights"), None);
+        assert!(matches!(
+            Model::<NdArray>::from_bytes(&config_json, b"not weights", &device),
+            Err(LoadError::Record(_))
+        ));
+        assert!(matches!(
+            Model::<NdArray>::from_bytes("{", &model.to_bytes(RecordFormat::Compact), &device),
+            Err(LoadError::Config(_))
+        ));
+    }
+
+    // Class 1 for any window with ink, class 0 for an empty one
+    struct InkClassifier;
+
+    impl Classifier<NdArray> for InkClassifier {
+        fn forward(&self, images: Tensor<NdArray, 3>) -> Tensor<NdArray, 2> {
+            let [batch_size, height, width] = images.dims();
+            let ink = images.reshape([batch_size, height * width]).max_dim(1).mul_scalar(10.0);
+            Tensor::cat(vec![Tensor::zeros([batch_size, 1], &ink.device()), ink], 1)
+        }
+    }
+
+    #[test]
+    fn sliding_windows_find_each_digit_once() {
+        let device = NdArrayDevice::default();
+        // Three 10x10 blots on a 140x28 strip, centered at x = 14, 70 and 126
+        let centers = [14, 70, 126];
+        let blotted = |x: u32, y: u32| centers.iter().any(|&center| x.abs_diff(center) < 5) && y.abs_diff(14) < 5;
+        let strip = GrayImage::from_fn(140, 28, |x, y| Luma([if blotted(x, y) { 255 } else { 0 }]));
+        let mut inverted = strip.clone();
+        image::imageops::invert(&mut inverted);
+
+        for image in [strip, inverted] {
+            let detections = detect_digits(&InkClassifier, &device, &DynamicImage::ImageLuma8(image), 2, 0.9);
+            assert_eq!(detections.len(), 3, "{detections:?}");
+            for (detection, center) in detections.iter().zip(centers) {
+                assert_eq!((detection.width, detection.height, detection.class), (28, 28, 1));
+                assert!((detection.x + 14).abs_diff(center) <= 3, "{detection:?} is off the blot at {center}");
+            }
+        }
+
+        let blank = DynamicImage::ImageLuma8(GrayImage::new(140, 28));
+        assert!(detect_digits(&InkClassifier, &device, &blank, 2, 0.9).is_empty());
+        let small = DynamicImage::ImageLuma8(GrayImage::new(20, 20));
+        assert!(detect_digits(&InkClassifier, &device, &small, 2, 0.9).is_empty());
+
+        let detection = |x: u32| Detection { x, y: 0, width: 28, height: 28, class: 1, confidence: 1.0 };
+        assert_eq!(detection(0).iou(&detection(0)), 1.0);
+        assert!((detection(0).iou(&detection(14)) - 1.0 / 3.0).abs() < 1e-6);
+        assert_eq!(detection(0).iou(&detection(28)), 0.0);
+    }
+
+    #[test]
+    fn inference_models_of_artifact_dirs_fall_back_to_defaults_without_metadata() {
+        let device = NdArrayDevice::default();
+        let artifact_dir = crate::synthetic::temp_artifact_dir("inference-model");
+        let mut config = crate::synthetic::tiny_config();
+        config.batch_size = 24;
+        crate::synthetic::train_synthetic(&artifact_dir, config, 32, 16).unwrap();
+        let artifact_dir = artifact_dir.to_str().unwrap();
+
+        let loaded = InferenceModel::<NdArray>::load(artifact_dir, &device).unwrap();
+        assert_eq!(loaded.batch_size, 24);
+        assert_eq!(loaded.labels, ClassLabels::load(artifact_dir));
+
+        std::fs::remove_file(ArtifactDir::new(artifact_dir).meta_path()).unwrap();
+        let loaded = InferenceModel::<NdArray>::load(artifact_dir, &device).unwrap();
+        assert_eq!(loaded.resize_policy, ResizePolicy::default());
+        assert!(loaded.preprocess.is_empty());
+    }
+}
@@ -0,0 +1,130 @@
+use crate::{artifact::ArtifactDir, meta::ModelMeta, training::TrainingConfig};
+use burn::config::Config;
+use serde::{Deserialize, Serialize};
+use std::{fs, io};
+
+// Where training writes the class names, in the artifact dir and in bundles
+pub const CLASSES_FILE: &str = "classes.json";
+
+/// Name of every class a model predicts, by class index: the dataset label a class stands for
+/// (`"3"` for MNIST), or `not-7` / `7` for a one-vs-rest model of 7s. Serialized as a plain
+/// JSON array, `classes.json` in the artifact dir.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(transparent)]
+pub struct ClassLabels {
+    names: Vec<String>,
+}
+
+impl ClassLabels {
+    pub fn new(names: Vec<String>) -> Self {
+        Self { names }
+    }
+
+    // `"0"`, `"1"`, ... up to `num_classes`: what a model without label names predicts
+    pub fn indices(num_classes: usize) -> Self {
+        Self::new((0..num_classes).map(|class| class.to_string()).collect())
+    }
+
+    /// The classes a run of `config` trains: the dataset labels, narrowed to `classes` (in list
+    /// order) or to one-vs-rest of `binary_target` when set.
+    pub fn from_config(config: &TrainingConfig) -> Self {
+        let dataset = config.dataset.class_names();
+        let name = |label: usize| dataset.get(label).cloned().unwrap_or_else(|| label.to_string());
+        match (&config.classes, config.binary_target) {
+            (Some(classes), _) => Self::new(classes.iter().map(|&label| name(label)).collect()),
+            (None, Some(target)) => Self::new(vec![format!("not-{}", name(target)), name(target)]),
+            (None, None) => Self::new((0..config.model.num_classes).map(name).collect()),
+        }
+    }
+
+    /// The labels written into `artifact_dir` by training. Artifact dirs from before
+    /// `classes.json` existed, or with an unreadable one, fall back to the class indices with a
+    /// warning, as many as `model_meta.json` (or else `config.json`) has classes.
+    pub fn load(artifact_dir: &str) -> Self {
+        let path = ArtifactDir::new(artifact_dir).classes_path();
+        let error = match fs::read_to_string(&path) {
+            Ok(json) => match serde_json::from_str(&json) {
+                Ok(labels) => return labels,
+                Err(err) => err.to_string(),
+            },
+            Err(err) => err.to_string(),
+        };
+        eprintln!("Warning: could not read the class names from {path} ({error}), labeling classes by index");
+
+        let num_classes = match ModelMeta::load(artifact_dir) {
+            Ok(Some(meta)) => Some(meta.num_classes),
+            _ => TrainingConfig::load(ArtifactDir::new(artifact_dir).config_path()).ok().map(|config| config.model.num_classes),
+        };
+        Self::indices(num_classes.unwrap_or(0))
+    }
+
+    pub fn save(&self, artifact_dir: &str) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(self).expect("Class names should serialize to JSON");
+        fs::write(ArtifactDir::new(artifact_dir).classes_path(), json)
+    }
+
+    // Name of class `index`; classes past the known names are named by their index
+    pub fn name(&self, index: usize) -> String {
+        self.names.get(index).cloned().unwrap_or_else(|| index.to_string())
+    }
+
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::ModelConfig;
+    use burn::optim::AdamConfig;
+
+    fn artifact_dir(name: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("my_first_rust_DL_app-labels-{name}"));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn saved_labels_load_back_and_missing_ones_fall_back_to_indices() {
+        let config = TrainingConfig::new(ModelConfig::new(4, 8), AdamConfig::new());
+
+        let saved = artifact_dir("saved");
+        let labels = ClassLabels::new(["zero", "one", "two", "three"].map(String::from).to_vec());
+        labels.save(&saved).unwrap();
+        assert_eq!(ClassLabels::load(&saved), labels);
+        assert_eq!(labels.name(1), "one");
+        assert_eq!(labels.name(7), "7");
+
+        // Only the config, as artifact dirs from before the class names were written
+        let missing = artifact_dir("missing");
+        config.save(ArtifactDir::new(&missing).config_path()).unwrap();
+        assert_eq!(ClassLabels::load(&missing), ClassLabels::indices(4));
+        fs::write(ArtifactDir::new(&missing).classes_path(), "not json").unwrap();
+        assert_eq!(ClassLabels::load(&missing).names(), ["0", "1", "2", "3"]);
+
+        fs::remove_dir_all(saved).unwrap();
+        fs::remove_dir_all(missing).unwrap();
+    }
+
+    #[test]
+    fn labels_of_a_config_follow_its_classes_and_binary_target() {
+        let config = TrainingConfig::new(ModelConfig::new(10, 8), AdamConfig::new());
+        assert_eq!(ClassLabels::from_config(&config), ClassLabels::indices(10));
+
+        let subset = config.clone().with_classes(Some(vec![7, 3]));
+        assert_eq!(ClassLabels::from_config(&subset).names(), ["7", "3"]);
+
+        let binary = config.with_binary_target(Some(7));
+        assert_eq!(ClassLabels::from_config(&binary).names(), ["not-7", "7"]);
+    }
+}
@@ -0,0 +1,379 @@
+use crate::data::{ClassificationDataset, DatasetSource, MnistSplit};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, fmt, fs, io, path::Path};
+
+// Largest Hamming distance between the average hashes of two images for `leakage-check` to call
+// them near duplicates, of 64 bits. Kept low: at 8x8, distinct digits of a class written alike
+// (most `1`s) already come within a few bits of each other.
+pub const DEFAULT_THRESHOLD: u32 = 2;
+
+// Name of the de-duplicated training index file, in the format `TrainingConfig::train_subset`
+// reads: a JSON list of indices
+pub const TRAIN_SUBSET_FILE: &str = "train_subset.json";
+
+// Name of the report file `write_leakage_report` writes
+pub const LEAKAGE_FILE: &str = "leakage.json";
+
+// Side of the grid `average_hash` downsamples to, 64 cells for a 64 bit hash
+const HASH_SIDE: usize = 8;
+
+/// The first 8 bytes of the SHA-256 of the pixel values: equal for identical images only.
+pub fn exact_hash(pixels: &[f32]) -> u64 {
+    let mut hasher = Sha256::new();
+    for pixel in pixels {
+        hasher.update(pixel.to_le_bytes());
+    }
+    let digest = hasher.finalize();
+    u64::from_le_bytes(digest[..8].try_into().expect("A SHA-256 digest has 32 bytes"))
+}
+
+/// The average hash of a `[height, width]` image: one bit per cell of its 8x8 area-average
+/// downsample, set when the cell is brighter than the mean of the 64 cells. Slightly shifted,
+/// noisy or retouched copies of an image differ in few bits, see [`hamming_distance`].
+pub fn average_hash(pixels: &[f32], [height, width]: [usize; 2]) -> u64 {
+    // Cells of images smaller than the grid repeat their pixels
+    let span = |cell: usize, size: usize| {
+        let start = cell * size / HASH_SIDE;
+        start..((cell + 1) * size / HASH_SIDE).max(start + 1).min(size.max(1))
+    };
+    let mut cells = [0.0f32; HASH_SIDE * HASH_SIDE];
+    for (position, cell) in cells.iter_mut().enumerate() {
+        let (rows, columns) = (span(position / HASH_SIDE, height), span(position % HASH_SIDE, width));
+        let count = (rows.len() * columns.len()).max(1) as f32;
+        let sum: f32 = rows.flat_map(|y| columns.clone().map(move |x| y * width + x)).filter_map(|i| pixels.get(i)).sum();
+        *cell = sum / count;
+    }
+    let mean = cells.iter().sum::<f32>() / cells.len() as f32;
+    cells.iter().enumerate().fold(0, |hash, (bit, &cell)| if cell > mean { hash | 1 << bit } else { hash })
+}
+
+/// Number of bits two hashes differ in.
+pub fn hamming_distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// Both hashes of one image, 16 bytes whatever its size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageHashes {
+    pub exact: u64,
+    pub average: u64,
+}
+
+/// The hashes of every item of `dataset`, reading one item at a time. Items it cannot return
+/// are `None`.
+pub fn hash_dataset<D: ClassificationDataset + ?Sized>(dataset: &D) -> Vec<Option<ImageHashes>> {
+    let shape = dataset.image_shape();
+    (0..dataset.len())
+        .map(|index| {
+            let (pixels, _) = dataset.get(index)?;
+            Some(ImageHashes { exact: exact_hash(&pixels), average: average_hash(&pixels, shape) })
+        })
+        .collect()
+}
+
+// The images indexed so far, found by exact hash or by average hash within `threshold` bits.
+// Near matches are looked up through the pigeonhole principle: two hashes within `threshold`
+// bits agree on at least one of `threshold + 1` disjoint bit ranges, so only the images sharing
+// a range with the query are compared.
+struct HashIndex {
+    threshold: u32,
+    exact: HashMap<u64, usize>,
+    // Per bit range, the images by the bits of their average hash in it
+    ranges: Vec<HashMap<u64, Vec<usize>>>,
+    averages: Vec<(usize, u64)>,
+}
+
+impl HashIndex {
+    fn new(threshold: u32) -> Self {
+        assert!(threshold < 64, "The Hamming distance threshold should be below 64");
+        Self {
+            threshold,
+            exact: HashMap::new(),
+            ranges: vec![HashMap::new(); threshold as usize + 1],
+            averages: Vec::new(),
+        }
+    }
+
+    // The bits of `hash` in each range, keyed by range
+    fn range_keys(&self, hash: u64) -> impl Iterator<Item = u64> + '_ {
+        let count = self.ranges.len() as u32;
+        (0..count).map(move |range| {
+            let (start, end) = (range * 64 / count, (range + 1) * 64 / count);
+            let mask = if end - start == 64 { u64::MAX } else { ((1u64 << (end - start)) - 1) << start };
+            hash & mask
+        })
+    }
+
+    fn insert(&mut self, index: usize, hashes: ImageHashes) {
+        self.exact.entry(hashes.exact).or_insert(index);
+        let keys: Vec<u64> = self.range_keys(hashes.average).collect();
+        for (range, key) in keys.into_iter().enumerate() {
+            self.ranges[range].entry(key).or_default().push(self.averages.len());
+        }
+        self.averages.push((index, hashes.average));
+    }
+
+    // The first image indexed with the same pixels, or else the closest one within the
+    // threshold, with its distance
+    fn find(&self, hashes: ImageHashes) -> Option<Match> {
+        if let Some(&index) = self.exact.get(&hashes.exact) {
+            return Some(Match { index, distance: 0, exact: true });
+        }
+        self.range_keys(hashes.average)
+            .enumerate()
+            .filter_map(|(range, key)| self.ranges[range].get(&key))
+            .flatten()
+            .map(|&position| {
+                let (index, average) = self.averages[position];
+                (hamming_distance(average, hashes.average), index)
+            })
+            .filter(|&(distance, _)| distance <= self.threshold)
+            .min()
+            .map(|(distance, index)| Match { index, distance, exact: false })
+    }
+}
+
+struct Match {
+    index: usize,
+    distance: u32,
+    exact: bool,
+}
+
+/// Two images found to be copies: `first` comes before `second` in the training split, or is
+/// the training image `second` of the test split collides with. `distance` is that of their
+/// average hashes, 0 for exact copies.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DuplicatePair {
+    pub first: usize,
+    pub second: usize,
+    pub distance: u32,
+}
+
+/// Content of `leakage.json`, as returned by [`check_leakage`]. The test split is the one
+/// training validates on.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LeakageReport {
+    pub threshold: u32,
+    pub train_len: usize,
+    pub test_len: usize,
+    // Items of either split that could not be read, left out of every count
+    pub skipped: usize,
+    // Training images identical to an earlier training image
+    pub train_exact_duplicates: usize,
+    // Training images within `threshold` bits of an earlier one without being identical to any
+    pub train_near_duplicates: usize,
+    // Training images identical to a test image
+    pub cross_exact_collisions: usize,
+    // Training images within `threshold` bits of a test image without being identical to any
+    pub cross_near_collisions: usize,
+    // The first pairs of each kind, as many as `check_leakage` was asked for
+    pub train_exact_examples: Vec<DuplicatePair>,
+    pub train_near_examples: Vec<DuplicatePair>,
+    pub cross_exact_examples: Vec<DuplicatePair>,
+    pub cross_near_examples: Vec<DuplicatePair>,
+    // Training indices left once every duplicate and collision is dropped, in order: what
+    // `TRAIN_SUBSET_FILE` holds
+    #[serde(skip)]
+    pub kept: Vec<usize>,
+}
+
+/// Hashes both splits, one item at a time, then finds the training images that are copies of
+/// an earlier training image or of a test image: identical, or with average hashes within
+/// `threshold` bits (below 64). Keeps up to `max_examples` index pairs of each kind.
+///
+/// The first of a group of training duplicates is kept in [`LeakageReport::kept`], the others
+/// are dropped, as is every training image colliding with a test image.
+pub fn check_leakage<D: ClassificationDataset + ?Sized>(
+    train_set: &D,
+    test_set: &D,
+    threshold: u32,
+    max_examples: usize,
+) -> LeakageReport {
+    let mut report = LeakageReport {
+        threshold,
+        train_len: train_set.len(),
+        test_len: test_set.len(),
+        skipped: 0,
+        train_exact_duplicates: 0,
+        train_near_duplicates: 0,
+        cross_exact_collisions: 0,
+        cross_near_collisions: 0,
+        train_exact_examples: Vec::new(),
+        train_near_examples: Vec::new(),
+        cross_exact_examples: Vec::new(),
+        cross_near_examples: Vec::new(),
+        kept: Vec::new(),
+    };
+    let record = |count: &mut usize, examples: &mut Vec<DuplicatePair>, pair: DuplicatePair| {
+        *count += 1;
+        if examples.len() < max_examples {
+            examples.push(pair);
+        }
+    };
+
+    let mut test_index = HashIndex::new(threshold);
+    for (index, hashes) in hash_dataset(test_set).into_iter().enumerate() {
+        match hashes {
+            Some(hashes) => test_index.insert(index, hashes),
+            None => report.skipped += 1,
+        }
+    }
+
+    // Duplicates are matched against the kept training images only, so that a chain of near
+    // duplicates keeps one image per group of images within the threshold of it
+    let mut train_index = HashIndex::new(threshold);
+    for (index, hashes) in hash_dataset(train_set).into_iter().enumerate() {
+        let Some(hashes) = hashes else {
+            report.skipped += 1;
+            continue;
+        };
+        let mut leaked = false;
+        if let Some(found) = train_index.find(hashes) {
+            let pair = DuplicatePair { first: found.index, second: index, distance: found.distance };
+            match found.exact {
+                true => record(&mut report.train_exact_duplicates, &mut report.train_exact_examples, pair),
+                false => record(&mut report.train_near_duplicates, &mut report.train_near_examples, pair),
+            }
+            leaked = true;
+        }
+        if let Some(found) = test_index.find(hashes) {
+            let pair = DuplicatePair { first: index, second: found.index, distance: found.distance };
+            match found.exact {
+                true => record(&mut report.cross_exact_collisions, &mut report.cross_exact_examples, pair),
+                false => record(&mut report.cross_near_collisions, &mut report.cross_near_examples, pair),
+            }
+            leaked = true;
+        }
+        if !leaked {
+            train_index.insert(index, hashes);
+            report.kept.push(index);
+        }
+    }
+    report
+}
+
+/// Writes `indices` as the JSON list `TrainingConfig::train_subset` reads.
+pub fn save_train_subset(path: impl AsRef<Path>, indices: &[usize]) -> io::Result<()> {
+    let json = serde_json::to_string(indices).expect("Training subset should serialize to JSON");
+    std::fs::write(path, json)
+}
+
+/// [`check_leakage`] of the train split of `source` against its test split, written as
+/// `leakage.json` into `artifact_dir`, with the kept training indices as `train_subset.json`
+/// next to it when `write_subset` is set.
+pub fn write_leakage_report(
+    source: &DatasetSource,
+    cache: bool,
+    artifact_dir: &str,
+    threshold: u32,
+    max_examples: usize,
+    write_subset: bool,
+) -> io::Result<LeakageReport> {
+    let train_set = source.load(MnistSplit::Train, cache);
+    let test_set = source.load(MnistSplit::Test, cache);
+    let report = check_leakage(&train_set, &test_set, threshold, max_examples);
+
+    fs::create_dir_all(artifact_dir)?;
+    let json = serde_json::to_string_pretty(&report).expect("Leakage report should serialize");
+    fs::write(Path::new(artifact_dir).join(LEAKAGE_FILE), json)?;
+    if write_subset {
+        save_train_subset(Path::new(artifact_dir).join(TRAIN_SUBSET_FILE), &report.kept)?;
+    }
+    Ok(report)
+}
+
+impl fmt::Display for LeakageReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Train: {} images, {} exact duplicates, {} near duplicates (Hamming distance <= {} of 64)",
+            self.train_len, self.train_exact_duplicates, self.train_near_duplicates, self.threshold
+        )?;
+        writeln!(
+            f,
+            "Train/test: {} exact collisions, {} near collisions with the {} test images",
+            self.cross_exact_collisions, self.cross_near_collisions, self.test_len
+        )?;
+        if self.skipped > 0 {
+            writeln!(f, "Skipped {} unreadable items", self.skipped)?;
+        }
+        // The split of the second image, and how it compares to the first
+        let examples = [
+            ("train", '=', &self.train_exact_examples),
+            ("train", '~', &self.train_near_examples),
+            ("test", '=', &self.cross_exact_examples),
+            ("test", '~', &self.cross_near_examples),
+        ];
+        if examples.iter().any(|(_, _, pairs)| !pairs.is_empty()) {
+            writeln!(f, "Examples:")?;
+        }
+        for (split, symbol, pairs) in examples {
+            for pair in pairs.iter() {
+                writeln!(f, "  train {} {symbol} {split} {} (distance {})", pair.first, pair.second, pair.distance)?;
+            }
+        }
+        write!(f, "Kept {} of {} training images", self.kept.len(), self.train_len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use burn::data::dataset::{vision::MnistItem, InMemDataset};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    // Noise: the average hashes of two noise images are some 32 bits apart
+    fn noise(seed: u64) -> MnistItem {
+        let mut rng = StdRng::seed_from_u64(seed);
+        MnistItem { image: [[0.0; 28]; 28].map(|row| row.map(|_| rng.gen_range(0..=255) as f32)), label: 0 }
+    }
+
+    // `item` with one pixel a shade brighter: a near duplicate, not an exact one
+    fn retouched(item: &MnistItem) -> MnistItem {
+        let mut image = item.image;
+        image[14][14] = (image[14][14] + 1.0).min(254.0);
+        MnistItem { image, label: item.label }
+    }
+
+    fn distance(a: &MnistItem, b: &MnistItem) -> u32 {
+        let hash = |item: &MnistItem| average_hash(&item.image.concat(), [28, 28]);
+        hamming_distance(hash(a), hash(b))
+    }
+
+    #[test]
+    fn leakage_check_finds_exact_and_near_copies_within_and_across_splits() {
+        let [a, b, c, d, e] = [1, 2, 3, 4, 5].map(noise);
+        let train = InMemDataset::new(vec![a.clone(), b.clone(), a.clone(), retouched(&b), c.clone(), d.clone()]);
+        let test = InMemDataset::new(vec![c.clone(), retouched(&d), e]);
+
+        let report = check_leakage(&train, &test, DEFAULT_THRESHOLD, 10);
+        let counts = [
+            report.train_exact_duplicates,
+            report.train_near_duplicates,
+            report.cross_exact_collisions,
+            report.cross_near_collisions,
+        ];
+        assert_eq!(counts, [1, 1, 1, 1]);
+        assert_eq!(report.train_exact_examples, [DuplicatePair { first: 0, second: 2, distance: 0 }]);
+        let near = distance(&b, &retouched(&b));
+        assert!(near <= DEFAULT_THRESHOLD);
+        assert_eq!(report.train_near_examples, [DuplicatePair { first: 1, second: 3, distance: near }]);
+        assert_eq!(report.cross_exact_examples, [DuplicatePair { first: 4, second: 0, distance: 0 }]);
+        assert_eq!(report.cross_near_examples[0].first, 5);
+        assert_eq!(report.kept, [0, 1]);
+        assert_eq!((report.train_len, report.test_len, report.skipped), (6, 3, 0));
+        assert!(distance(&a, &c) > DEFAULT_THRESHOLD, "the noise fixtures are too alike");
+
+        // No examples asked for, the counts stay
+        let report = check_leakage(&train, &test, DEFAULT_THRESHOLD, 0);
+        assert_eq!(report.train_exact_duplicates, 1);
+        assert!(report.train_exact_examples.is_empty());
+
+        let path = std::env::temp_dir().join("my_first_rust_DL_app-train_subset.json");
+        save_train_subset(&path, &[0, 1, 7]).unwrap();
+        let saved = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(serde_json::from_str::<Vec<usize>>(&saved).unwrap(), [0, 1, 7]);
+    }
+}
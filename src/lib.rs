@@ -1,4 +1,120 @@
+//! A small CNN digit classifier for MNIST built on [burn].
+//!
+//! The library exposes the whole pipeline: [`train`] a model into an artifact directory,
+//! [`evaluate`] it on the test set, and classify images with [`load_model`] and
+//! [`predict_image_file`]. A run uploaded to its `checkpoint_store` loads from the store with
+//! [`load_model_from`] and [`evaluate_from`]. The binary is a thin CLI over these functions.
+//!
+//! ```no_run
+//! use burn::backend::{wgpu::WgpuDevice, Autodiff, Wgpu};
+//! use burn::optim::AdamConfig;
+//! use my_first_rust_DL_app::{load_model, predict_image_file, train, ModelConfig, TrainingConfig};
+//!
+//! let device = WgpuDevice::default();
+//! let config = TrainingConfig::new(ModelConfig::new(10, 512), AdamConfig::new());
+//! train::<Autodiff<Wgpu>>("/tmp/mnist-run", config, device.clone())?;
+//!
+//! let model = load_model::<Wgpu>("/tmp/mnist-run", &device)?;
+//! println!("{}", predict_image_file(&model, &device, "digit.png", false)?);
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+// The package name predates the snake_case convention and is part of the public import path
+#![allow(non_snake_case)]
+
+pub mod ablation;
+pub mod adversarial;
+pub mod artifact;
+pub mod audit;
+pub mod augmentation;
+pub mod activation_stats;
+pub mod adapter;
+pub mod balanced;
+pub mod batch_order;
+pub mod bench;
+pub mod budget;
+pub mod bundle;
+pub mod calibration;
+pub mod coco;
+pub mod convert;
+pub mod corruption;
+pub mod curriculum;
 pub mod data;
+pub mod data_info;
+pub mod download;
+pub mod evaluation;
+pub mod gc;
+pub mod grad_cam;
+pub mod hard_mining;
+pub mod history;
+pub mod holdout;
+pub mod image_folder;
+pub mod labels;
+pub mod leakage;
+pub mod memory;
+pub mod meta;
+pub mod metrics;
 pub mod model;
+pub mod model_card;
+pub mod multilabel;
+pub mod nan_guard;
+pub mod npy;
+#[cfg(feature = "onnx")]
+pub mod onnx;
 pub mod inference;
-pub mod training;
\ No newline at end of file
+pub mod lr_finder;
+pub mod lr_groups;
+pub mod training;
+pub mod optim_stats;
+pub mod params;
+pub mod perturb;
+pub mod plot;
+pub mod preprocess;
+pub mod preview;
+pub mod profile;
+pub mod prometheus;
+pub mod progress;
+pub mod progressive;
+pub mod prune;
+pub mod registry;
+pub mod report;
+pub mod retrieval;
+pub mod run_record;
+pub mod schedule;
+pub mod seed;
+pub mod checkpoint;
+pub mod snapshot;
+pub mod soft_labels;
+#[cfg(feature = "status-server")]
+pub mod status;
+pub mod soup;
+pub mod split;
+pub mod step_valid;
+pub mod store;
+pub mod swa;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod synthetic;
+pub mod verify;
+pub mod weight_diff;
+
+pub use artifact::ArtifactDir;
+pub use augmentation::preview_augmentation;
+pub use bundle::{export_bundle, load_bundle, Bundle};
+pub use coco::{export_predictions_json, ImageId};
+pub use data::{ClassificationDataset, ClassificationItem, MnistBatch, MnistBatcher, SoftLabelBatch};
+pub use evaluation::{
+    evaluate, evaluate_dataset, evaluate_from, evaluate_model, evaluate_with_reject, prediction_margins, sensitivity,
+    EvalAccumulator, EvalError, EvalReport, EvaluationConfig,
+};
+pub use inference::{
+    classify_image_file, detect_digits, infer, load_model, load_model_from, predict_batch, predict_image_file,
+    predict_probabilities, predict_tta, predict_topk, predict_with_reject, ClassProbability, Decision, Detection,
+    InferenceModel, Prediction,
+};
+pub use model::{ArchDescription, AuxTask, Classifier, GlobalPool, HeadInput, Model, ModelConfig, TaskKind};
+pub use multilabel::{MultiLabelBatch, MultiLabelDataset};
+pub use progress::ProgressEvent;
+pub use report::{report, ReportConfig};
+pub use training::{
+    train, train_multilabel_on, train_on, train_with_progress, ConfigError, TrainError, TrainingConfig,
+};
@@ -0,0 +1,289 @@
+use crate::{
+    data::{mnist_dataloader, MnistBatch, MnistBatcher, MnistSplit},
+    lr_groups::{lr_groups, LrMultiplierOptimizer},
+    model::Model,
+    plot::lr_curve_svg,
+    split::{ClassSubset, OneVsRest},
+    training::{seed_backend, ConfigError, OptimizerKind, TrainError, TrainingConfig},
+};
+use burn::{
+    data::dataloader::DataLoader,
+    optim::{GradientsParams, Optimizer},
+    prelude::*,
+    tensor::backend::AutodiffBackend,
+};
+use serde::{Deserialize, Serialize};
+use std::{fmt, io, sync::Arc};
+
+// The range test stops once the loss exceeds the best loss seen so far by this factor
+const DIVERGENCE_FACTOR: f64 = 4.0;
+
+// Sweep of `find_lr`
+pub const DEFAULT_MIN_LR: f64 = 1e-7;
+pub const DEFAULT_MAX_LR: f64 = 1.0;
+pub const DEFAULT_NUM_STEPS: usize = 300;
+
+// The suggested learning rate is this much below the one at the minimum loss
+const SUGGESTION_FACTOR: f64 = 10.0;
+
+// Result of a learning rate range test. `min_loss_lr` and `suggested_lr` are `None` when no
+// step had a finite loss.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LrFinderReport {
+    // `(lr, loss)` of every step, in order
+    pub history: Vec<(f64, f64)>,
+    // The test stopped before its last step because the loss diverged
+    pub diverged: bool,
+    pub min_loss: Option<f64>,
+    pub min_loss_lr: Option<f64>,
+    // One order of magnitude below `min_loss_lr`
+    pub suggested_lr: Option<f64>,
+}
+
+impl LrFinderReport {
+    pub fn new(history: Vec<(f64, f64)>, num_steps: usize) -> Self {
+        let min = history
+            .iter()
+            .copied()
+            .filter(|(_, loss)| loss.is_finite())
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+        let diverged = history.len() < num_steps
+            || history.last().is_some_and(|&(_, loss)| !loss.is_finite());
+        Self {
+            diverged,
+            min_loss: min.map(|(_, loss)| loss),
+            min_loss_lr: min.map(|(lr, _)| lr),
+            suggested_lr: min.map(|(lr, _)| lr / SUGGESTION_FACTOR),
+            history,
+        }
+    }
+
+    // Writes the curve as `lr_finder.csv` (`step,lr,loss`) and `lr_finder.svg` into `artifact_dir`
+    pub fn save(&self, artifact_dir: &str) -> io::Result<()> {
+        std::fs::create_dir_all(artifact_dir)?;
+        let mut csv = String::from("step,lr,loss\n");
+        for (step, (lr, loss)) in self.history.iter().enumerate() {
+            csv.push_str(&format!("{},{lr},{loss}\n", step + 1));
+        }
+        std::fs::write(format!("{artifact_dir}/lr_finder.csv"), csv)?;
+
+        let markers: Vec<(&str, f64)> = [("min loss", self.min_loss_lr), ("suggested", self.suggested_lr)]
+            .into_iter()
+            .filter_map(|(label, lr)| Some((label, lr?)))
+            .collect();
+        std::fs::write(format!("{artifact_dir}/lr_finder.svg"), lr_curve_svg(&self.history, &markers))
+    }
+}
+
+impl fmt::Display for LrFinderReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:>12}  {:>10}", "lr", "loss")?;
+        for (lr, loss) in &self.history {
+            writeln!(f, "{lr:>12.3e}  {loss:>10.4}")?;
+        }
+        if self.diverged {
+            writeln!(f, "Stopped early: the loss diverged")?;
+        }
+        match (self.min_loss_lr, self.suggested_lr) {
+            (Some(min_loss_lr), Some(suggested_lr)) => {
+                write!(f, "Minimum loss at lr {min_loss_lr:.3e}, suggested lr {suggested_lr:.3e}")
+            }
+            _ => write!(f, "No step had a finite loss"),
+        }
+    }
+}
+
+// Learning rate of step `step` out of `num_steps`, growing exponentially from `min_lr` to `max_lr`
+fn step_lr(min_lr: f64, max_lr: f64, step: usize, num_steps: usize) -> f64 {
+    let progress = step as f64 / (num_steps - 1) as f64;
+    min_lr * (max_lr / min_lr).powf(progress)
+}
+
+fn run<B: AutodiffBackend, O: Optimizer<Model<B>, B>>(
+    mut model: Model<B>,
+    mut optimizer: O,
+    dataloader: Arc<dyn DataLoader<MnistBatch<B>>>,
+    min_lr: f64,
+    max_lr: f64,
+    num_steps: usize,
+) -> Vec<(f64, f64)> {
+    let mut history = Vec::with_capacity(num_steps);
+    let mut best_loss = f64::INFINITY;
+
+    // The dataloader is restarted as often as needed to make `num_steps` steps
+    let mut batches = dataloader.iter();
+    for step in 0..num_steps {
+        let batch = match batches.next() {
+            Some(batch) => batch,
+            None => {
+                batches = dataloader.iter();
+                batches.next().expect("The training set has at least one batch")
+            }
+        };
+
+        let lr = step_lr(min_lr, max_lr, step, num_steps);
+        let output = model.forward_classification(batch.images, batch.targets, batch.soft_targets, batch.loss);
+        let loss = output.loss.clone().into_scalar().elem::<f64>();
+        history.push((lr, loss));
+
+        if !loss.is_finite() || loss > DIVERGENCE_FACTOR * best_loss {
+            break;
+        }
+        best_loss = best_loss.min(loss);
+
+        let grads = GradientsParams::from_grads(output.loss.backward(), &model);
+        model = optimizer.step(lr, model, grads);
+    }
+
+    history
+}
+
+// Leslie Smith's learning rate range test: trains a fresh model from `config` for up to
+// `num_steps` steps while the learning rate grows exponentially from `min_lr` to `max_lr`, and
+// returns the `(lr, loss)` of every step. Stops early once the loss diverges (non-finite, or
+// 4x the best loss so far). A good learning rate sits where the loss falls the fastest, usually
+// a little below the minimum of the curve.
+pub fn lr_range_test<B: AutodiffBackend>(
+    config: &TrainingConfig,
+    device: B::Device,
+    min_lr: f64,
+    max_lr: f64,
+    num_steps: usize,
+) -> Result<Vec<(f64, f64)>, TrainError> {
+    let mut errors = config.validate().err().unwrap_or_default();
+    if !(min_lr.is_finite() && min_lr > 0.0) {
+        errors.push(ConfigError::new("min_lr", min_lr, "a finite value > 0"));
+    }
+    if !(max_lr.is_finite() && max_lr > min_lr) {
+        errors.push(ConfigError::new("max_lr", max_lr, "a finite value > min_lr"));
+    }
+    if num_steps < 2 {
+        errors.push(ConfigError::new("num_steps", num_steps, ">= 2"));
+    }
+    if !errors.is_empty() {
+        return Err(TrainError::InvalidConfig(errors));
+    }
+
+    seed_backend::<B>(config.seed);
+
+    // Same class subset or one-vs-rest labels, and head size, as `train_on` would use
+    let mut model_config = config.model.clone();
+    if let Some(classes) = &config.classes {
+        model_config.num_classes = classes.len();
+    } else if config.binary_target.is_some() {
+        model_config.num_classes = 2;
+    }
+
+    let mut batcher = MnistBatcher::<B>::new(device.clone()).with_loss(config.loss_settings());
+    if let Some(alpha) = config.mixup_alpha {
+        batcher = batcher.with_mixup(alpha, model_config.num_classes, config.seed);
+    }
+    if let Some(alpha) = config.cutmix_alpha {
+        batcher = batcher.with_cutmix(alpha, model_config.num_classes, config.seed);
+    }
+    let dataset = config.dataset.load(MnistSplit::Train, config.cache);
+    let dataloader = match (&config.classes, config.binary_target) {
+        (Some(classes), _) => mnist_dataloader(
+            batcher,
+            ClassSubset::new(dataset, classes),
+            config.batch_size,
+            config.seed,
+            config.num_workers,
+            config.prefetch,
+        ),
+        (None, Some(target)) => mnist_dataloader(
+            batcher,
+            OneVsRest::new(dataset, target),
+            config.batch_size,
+            config.seed,
+            config.num_workers,
+            config.prefetch,
+        ),
+        (None, None) => mnist_dataloader(
+            batcher,
+            dataset,
+            config.batch_size,
+            config.seed,
+            config.num_workers,
+            config.prefetch,
+        ),
+    };
+
+    let model = model_config.init::<B>(&device);
+    // The swept learning rate is the base one, which the groups scale as in training
+    let groups = match &config.lr_multipliers {
+        Some(multipliers) => lr_groups(&model, multipliers).map_err(TrainError::InvalidConfig)?,
+        None => Vec::new(),
+    };
+    Ok(match config.optimizer_kind {
+        OptimizerKind::Adam => {
+            let optimizer = LrMultiplierOptimizer::new(config.adam_config().init(), groups);
+            run(model, optimizer, dataloader, min_lr, max_lr, num_steps)
+        }
+        OptimizerKind::Sgd => {
+            let optimizer = LrMultiplierOptimizer::new(config.sgd_config().init(), groups);
+            run(model, optimizer, dataloader, min_lr, max_lr, num_steps)
+        }
+    })
+}
+
+/// The classic learning rate range test on the training data of `config`: [`lr_range_test`]
+/// from 1e-7 to 1 over 300 steps. Reports the learning rate at the minimum loss and a suggested
+/// learning rate one order of magnitude lower, and writes the curve to `lr_finder.csv` and
+/// `lr_finder.svg` in `artifact_dir`.
+///
+/// The model and optimizer trained by the test are dropped with it: a run started afterwards
+/// begins from its own fresh initialization. Training into the same `artifact_dir` clears it,
+/// curve included.
+pub fn find_lr<B: AutodiffBackend>(
+    artifact_dir: &str,
+    config: &TrainingConfig,
+    device: B::Device,
+) -> Result<LrFinderReport, TrainError> {
+    let history = lr_range_test::<B>(config, device, DEFAULT_MIN_LR, DEFAULT_MAX_LR, DEFAULT_NUM_STEPS)?;
+    let report = LrFinderReport::new(history, DEFAULT_NUM_STEPS);
+    report.save(artifact_dir)?;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{data::DatasetSource, synthetic::tiny_config};
+    use burn::backend::{ndarray::NdArrayDevice, Autodiff, NdArray};
+
+    // Four batches of synthetic digits per epoch
+    fn config() -> TrainingConfig {
+        tiny_config().with_dataset(DatasetSource::Synthetic { num_samples: 64, seed: 1 })
+    }
+
+    #[test]
+    fn range_test_sweeps_the_learning_rate_up_over_every_step() {
+        let device = NdArrayDevice::default();
+
+        // More steps than batches: the dataloader is restarted
+        let history = lr_range_test::<Autodiff<NdArray>>(&config(), device, 1e-5, 1e-3, 12).unwrap();
+        assert_eq!(history.len(), 12);
+        assert!(history.windows(2).all(|pair| pair[0].0 < pair[1].0), "{history:?}");
+        assert!((history[0].0 - 1e-5).abs() < 1e-15 && (history[11].0 - 1e-3).abs() < 1e-12);
+        assert!(history.iter().all(|(_, loss)| loss.is_finite()));
+
+        let err = lr_range_test::<Autodiff<NdArray>>(&config(), device, 1e-3, 1e-5, 1).unwrap_err();
+        match err {
+            TrainError::InvalidConfig(errors) => {
+                let fields: Vec<&str> = errors.iter().map(|err| err.field.as_str()).collect();
+                assert_eq!(fields, ["max_lr", "num_steps"]);
+            }
+            other => panic!("expected an invalid config, got {other}"),
+        }
+    }
+
+    #[test]
+    fn range_test_stops_once_the_loss_diverges() {
+        let device = NdArrayDevice::default();
+
+        let history = lr_range_test::<Autodiff<NdArray>>(&config(), device, 1e-4, 1e6, 40).unwrap();
+        assert!(history.len() < 40, "the loss never diverged: {history:?}");
+        assert!(LrFinderReport::new(history, 40).diverged);
+    }
+}
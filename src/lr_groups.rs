@@ -0,0 +1,281 @@
+use crate::{model::Model, params::param_names, training::ConfigError};
+use burn::{
+    module::{ModuleVisitor, ParamId},
+    optim::{GradientsParams, Optimizer},
+    prelude::*,
+    tensor::backend::AutodiffBackend,
+    train::metric::{
+        state::{FormatOptions, NumericMetricState},
+        Metric, MetricEntry, MetricMetadata, Numeric,
+    },
+    LearningRate,
+};
+use std::collections::{BTreeSet, HashSet};
+
+/// The parameters one `TrainingConfig::lr_multipliers` entry applies to, see [`lr_groups`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct LrGroup {
+    pub prefix: String,
+    pub multiplier: f64,
+    // Names of the parameters of the group (`conv1.weight`), sorted
+    pub params: Vec<String>,
+    // Their `ParamId`s, the keys of the gradients
+    ids: HashSet<String>,
+}
+
+// Whether `prefix` is `name` or one of the modules it is in: `conv1` matches `conv1.weight` but
+// not `conv10.weight`
+fn is_prefix(prefix: &str, name: &str) -> bool {
+    name.strip_prefix(prefix).is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+}
+
+/// Groups the parameters of `model` by the `multipliers` entry whose prefix is the longest one
+/// matching them, a layer name of the model summary (`conv1`) or a parameter name
+/// (`conv1.weight`). Parameters matching no entry are left out: they train at the base learning
+/// rate. An entry matching no parameter is an error that lists the valid names.
+pub fn lr_groups<B: Backend>(model: &Model<B>, multipliers: &[(String, f64)]) -> Result<Vec<LrGroup>, Vec<ConfigError>> {
+    let mut groups: Vec<LrGroup> = multipliers
+        .iter()
+        .map(|(prefix, multiplier)| LrGroup {
+            prefix: prefix.clone(),
+            multiplier: *multiplier,
+            params: Vec::new(),
+            ids: HashSet::new(),
+        })
+        .collect();
+
+    let names = param_names(model);
+    for (id, name) in &names {
+        let longest = groups
+            .iter_mut()
+            .filter(|group| is_prefix(&group.prefix, name))
+            .max_by_key(|group| group.prefix.len());
+        if let Some(group) = longest {
+            group.params.push(name.clone());
+            group.ids.insert(id.clone());
+        }
+    }
+
+    let errors: Vec<ConfigError> = groups
+        .iter()
+        .enumerate()
+        .filter(|(_, group)| !names.values().any(|name| is_prefix(&group.prefix, name)))
+        .map(|(position, group)| {
+            // The layers are the modules directly holding parameters
+            let layers: BTreeSet<&str> =
+                names.values().map(|name| name.rsplit_once('.').map_or(name.as_str(), |(layer, _)| layer)).collect();
+            let layers: Vec<&str> = layers.into_iter().collect();
+            ConfigError::new(
+                &format!("lr_multipliers[{position}].prefix"),
+                &group.prefix,
+                &format!("one of the layers {} or one of their parameters", layers.join(", ")),
+            )
+        })
+        .collect();
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    for group in &mut groups {
+        group.params.sort();
+    }
+    Ok(groups)
+}
+
+// Moves the gradients of the parameters in `ids` out of `grads` into a new set
+struct SplitVisitor<'a> {
+    ids: &'a HashSet<String>,
+    grads: &'a mut GradientsParams,
+    group: GradientsParams,
+}
+
+impl<B: AutodiffBackend> ModuleVisitor<B> for SplitVisitor<'_> {
+    fn visit_float<const D: usize>(&mut self, id: &ParamId, _tensor: &Tensor<B, D>) {
+        if !self.ids.contains(&id.to_string()) {
+            return;
+        }
+        if let Some(grad) = self.grads.remove::<B::InnerBackend, D>(id) {
+            self.group.register(id.clone(), grad);
+        }
+    }
+}
+
+// Steps the parameters of each `LrGroup` with the learning rate times its multiplier, and the
+// others with the learning rate itself: one inner step per group, each with only the gradients
+// of its parameters, which the inner optimizer leaves the others (and their state) untouched
+// without. Groups with a multiplier of 0 are not stepped at all, so their weights stay exactly
+// as they are, weight decay included.
+pub struct LrMultiplierOptimizer<O> {
+    inner: O,
+    groups: Vec<LrGroup>,
+}
+
+impl<O> LrMultiplierOptimizer<O> {
+    pub fn new(inner: O, groups: Vec<LrGroup>) -> Self {
+        Self { inner, groups }
+    }
+}
+
+impl<O, B> Optimizer<Model<B>, B> for LrMultiplierOptimizer<O>
+where
+    B: AutodiffBackend,
+    O: Optimizer<Model<B>, B>,
+{
+    type Record = O::Record;
+
+    fn step(&mut self, lr: LearningRate, module: Model<B>, mut grads: GradientsParams) -> Model<B> {
+        let mut steps = Vec::new();
+        for group in &self.groups {
+            let mut visitor = SplitVisitor { ids: &group.ids, grads: &mut grads, group: GradientsParams::new() };
+            module.visit(&mut visitor);
+            steps.push((group.multiplier, visitor.group));
+        }
+
+        let mut module = self.inner.step(lr, module, grads);
+        for (multiplier, grads) in steps {
+            if multiplier != 0.0 && !grads.is_empty() {
+                module = self.inner.step(lr * multiplier, module, grads);
+            }
+        }
+        module
+    }
+
+    fn to_record(&self) -> Self::Record {
+        self.inner.to_record()
+    }
+
+    fn load_record(self, record: Self::Record) -> Self {
+        Self { inner: self.inner.load_record(record), groups: self.groups }
+    }
+}
+
+// Learning rate of the parameters of one `lr_multipliers` entry: the scheduled one times the
+// multiplier, logged as `Learning Rate <prefix>`
+pub struct GroupLearningRateMetric {
+    name: String,
+    multiplier: f64,
+    state: NumericMetricState,
+}
+
+impl GroupLearningRateMetric {
+    pub fn new(prefix: &str, multiplier: f64) -> Self {
+        Self { name: format!("{} {prefix}", <Self as Metric>::NAME), multiplier, state: NumericMetricState::new() }
+    }
+}
+
+impl Metric for GroupLearningRateMetric {
+    const NAME: &'static str = "Learning Rate";
+
+    type Input = ();
+
+    fn update(&mut self, _item: &(), metadata: &MetricMetadata) -> MetricEntry {
+        let lr = metadata.lr.unwrap_or(0.0) * self.multiplier;
+        self.state.update(lr, 1, FormatOptions::new(&self.name).precision(2))
+    }
+
+    fn clear(&mut self) {
+        self.state.reset()
+    }
+}
+
+impl Numeric for GroupLearningRateMetric {
+    fn value(&self) -> f64 {
+        self.state.value()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{params::named_params, training::LossSettings, ModelConfig};
+    use burn::{
+        backend::{ndarray::NdArrayDevice, Autodiff, NdArray},
+        optim::{AdamConfig, SgdConfig},
+        tensor::Distribution,
+    };
+
+    type B = Autodiff<NdArray>;
+
+    // Without dropout, the gradients of a fixed batch are the same for every copy of a model
+    fn model(device: &NdArrayDevice) -> Model<B> {
+        ModelConfig::new(10, 8).with_dropout(0.0).init(device)
+    }
+
+    fn grads(model: &Model<B>, images: &Tensor<B, 3>) -> GradientsParams {
+        let targets = Tensor::from_ints([0, 1, 2, 3], &images.device());
+        let output = model.forward_classification(images.clone(), targets, None, LossSettings::default());
+        GradientsParams::from_grads(output.loss.backward(), model)
+    }
+
+    fn multipliers(entries: &[(&str, f64)]) -> Vec<(String, f64)> {
+        entries.iter().map(|(prefix, multiplier)| (prefix.to_string(), *multiplier)).collect()
+    }
+
+    #[test]
+    fn zero_multiplier_leaves_its_weights_bit_identical() {
+        let device = NdArrayDevice::default();
+        let images = Tensor::<B, 3>::random([4, 28, 28], Distribution::Default, &device);
+        let mut model = model(&device);
+        let initial = named_params(&model);
+
+        let groups = lr_groups(&model, &multipliers(&[("conv1", 0.0)])).unwrap();
+        assert_eq!(groups[0].params, ["conv1.bias", "conv1.weight"]);
+        let mut optimizer = LrMultiplierOptimizer::new(AdamConfig::new().init(), groups);
+        for _ in 0..5 {
+            let grads = grads(&model, &images);
+            model = optimizer.step(1e-2, model, grads);
+        }
+
+        for (before, after) in initial.iter().zip(named_params(&model)) {
+            if before.name.starts_with("conv1.") {
+                assert_eq!(before.values, after.values, "{} moved", before.name);
+            } else {
+                assert_ne!(before.values, after.values, "{} did not train", before.name);
+            }
+        }
+    }
+
+    #[test]
+    fn prefixes_matching_no_parameter_list_the_layers() {
+        let model = model(&NdArrayDevice::default());
+
+        // `conv` is not a layer and `conv10` is not under `conv1`
+        let errors = lr_groups(&model, &multipliers(&[("conv1", 0.1), ("conv", 2.0), ("conv1.weight0", 2.0)]))
+            .unwrap_err();
+        let fields: Vec<&str> = errors.iter().map(|err| err.field.as_str()).collect();
+        assert_eq!(fields, ["lr_multipliers[1].prefix", "lr_multipliers[2].prefix"]);
+        assert_eq!(errors[0].value, "conv");
+        assert!(errors[0].expected.contains("conv1, conv2"), "{}", errors[0].expected);
+
+        // The longest prefix wins
+        let groups = lr_groups(&model, &multipliers(&[("conv1", 0.1), ("conv1.weight", 2.0)])).unwrap();
+        assert_eq!(groups[0].params, ["conv1.bias"]);
+        assert_eq!(groups[1].params, ["conv1.weight"]);
+    }
+
+    #[test]
+    fn head_and_backbone_step_at_their_own_learning_rates() {
+        let device = NdArrayDevice::default();
+        let images = Tensor::<B, 3>::random([4, 28, 28], Distribution::Default, &device);
+        let model = model(&device);
+        let initial = named_params(&model);
+
+        // Plain SGD moves every weight by the learning rate times its gradient
+        let base = SgdConfig::new().init().step(1e-2, model.clone(), grads(&model, &images));
+        let groups = lr_groups(&model, &multipliers(&[("conv1", 0.1), ("conv2", 0.1), ("linear2", 3.0)])).unwrap();
+        let mut optimizer = LrMultiplierOptimizer::new(SgdConfig::new().init(), groups);
+        let grouped = optimizer.step(1e-2, model.clone(), grads(&model, &images));
+
+        for ((before, base), grouped) in initial.iter().zip(named_params(&base)).zip(named_params(&grouped)) {
+            let multiplier = match before.name.split('.').next().unwrap() {
+                "conv1" | "conv2" => 0.1,
+                "linear2" => 3.0,
+                _ => 1.0,
+            };
+            for ((initial, base), grouped) in before.values.iter().zip(&base.values).zip(&grouped.values) {
+                let expected = multiplier * (base - initial);
+                let delta = grouped - initial;
+                assert!((delta - expected).abs() < 1e-6, "{}: {delta} instead of {expected}", before.name);
+            }
+        }
+    }
+}
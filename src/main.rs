@@ -1,29 +1,867 @@
-use burn::optim::AdamConfig;
-use burn::backend::{Autodiff, Wgpu, wgpu::AutoGraphicsApi};
-use burn::data::dataloader::Dataset;
-use my_first_rust_DL_app::model::ModelConfig;
+#![allow(non_snake_case)]
 
+use burn::{
+    backend::{wgpu::AutoGraphicsApi, Autodiff, Wgpu},
+    config::Config,
+};
+use clap::{Parser, Subcommand};
+use my_first_rust_DL_app::{
+    ablation, activation_stats, bench, convert, corruption,
+    data::{DatasetSource, MnistSplit},
+    data_info, gc, inference, leakage, lr_finder, preprocess, retrieval, run_record, store,
+    training::PrecisionKind,
+    ArtifactDir, Decision, EvaluationConfig, InferenceModel, ReportConfig, TrainingConfig,
+};
+use std::time::Duration;
+
+type ModelBackend = Wgpu<AutoGraphicsApi, f32, i32>;
+type ModelAutodiffBackend = Autodiff<ModelBackend>;
+// F16 trains on the CPU through candle: the wgpu kernels of this burn version are f32 only, and
+// candle has no bf16 matmul on the CPU
+#[cfg(feature = "candle")]
+type HalfAutodiffBackend = Autodiff<burn::backend::Candle<burn::tensor::f16, i64>>;
+#[cfg(feature = "candle")]
+const SUPPORTED_PRECISIONS: [PrecisionKind; 2] = [PrecisionKind::F32, PrecisionKind::F16];
+#[cfg(not(feature = "candle"))]
+const SUPPORTED_PRECISIONS: [PrecisionKind; 1] = [PrecisionKind::F32];
+
+const DEFAULT_ARTIFACT_DIR: &str = "/tmp/my_first_rust_DL_app";
+
+#[derive(Parser)]
+#[command(about = "Train and run a CNN digit classifier on MNIST")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Train a model (the default when no command is given)
+    Train {
+        #[arg(long, default_value = DEFAULT_ARTIFACT_DIR)]
+        artifact_dir: String,
+        /// Training config JSON (as written to config.json); defaults to the built-in config, or
+        /// to the config of the --resume-from run. DL_NUM_EPOCHS, DL_BATCH_SIZE, DL_LR,
+        /// DL_NUM_WORKERS, DL_SEED and DL_MAX_TRAIN_SECONDS override its fields
+        #[arg(long)]
+        config: Option<String>,
+        /// Continue from the final weights and optimizer state of this artifact dir, or of the
+        /// s3://bucket/prefix a checkpoint_store uploaded it to
+        #[arg(long)]
+        resume_from: Option<String>,
+        /// Override the learning rate of the config, e.g. lower for fine-tuning a resumed run
+        #[arg(long)]
+        learning_rate: Option<f64>,
+        /// Resume with a fresh optimizer state (drops momentum and Adam moments)
+        #[arg(long, requires = "resume_from")]
+        reset_optimizer: bool,
+        /// Stop training once this many seconds have passed, even mid-epoch, keeping the model
+        /// trained so far (the max_duration budget of the config)
+        #[arg(long)]
+        max_train_seconds: Option<u64>,
+        /// Rebuild the index of every image folder of the dataset from scratch instead of
+        /// updating it from the file sizes and mtimes
+        #[arg(long)]
+        reindex: bool,
+    },
+    /// Classify images with a trained model
+    Infer {
+        /// Artifact dir of a training run, the s3://bucket/prefix its checkpoint_store uploaded
+        /// it to, or a bundle written by export
+        #[arg(long, default_value = DEFAULT_ARTIFACT_DIR)]
+        artifact_dir: String,
+        /// Image file to classify
+        #[arg(required_unless_present_any = ["stdin", "stdin_png"])]
+        image: Option<String>,
+        /// Treat the image as a photo of real handwriting (threshold, invert, center)
+        #[arg(long)]
+        natural: bool,
+        /// Resize filter of the image: nearest, bilinear or lanczos (defaults to the resize
+        /// policy the model was trained with)
+        #[arg(long)]
+        interpolation: Option<inference::Interpolation>,
+        /// Print the prediction as a single JSON object (label, confidence, probabilities)
+        #[arg(long, conflicts_with = "stdin")]
+        json: bool,
+        /// Read one image path or base64 pixel payload per line, write one JSON line per input
+        #[arg(long, conflicts_with = "image")]
+        stdin: bool,
+        /// Read PNG images from stdin, each preceded by its length in bytes as a 4-byte
+        /// big-endian integer, and write one label per line as each is classified
+        #[arg(long, conflicts_with_all = ["image", "stdin", "json"])]
+        stdin_png: bool,
+        /// Maximum number of lines per batch in --stdin mode (defaults to the training batch size)
+        #[arg(long)]
+        batch_size: Option<usize>,
+        /// Milliseconds to wait for more lines before running a partial batch in --stdin mode
+        #[arg(long, default_value_t = 20)]
+        batch_timeout_ms: u64,
+        /// Average the prediction over this many rotated and shifted copies of the image
+        #[arg(long, conflicts_with_all = ["stdin", "stdin_png", "json"])]
+        tta: Option<usize>,
+        /// Answer "not sure" instead of a label when the confidence is below this threshold
+        #[arg(long, conflicts_with_all = ["stdin", "stdin_png"])]
+        reject_below: Option<f32>,
+        /// Directory to write before.png and after.png into: the image on the 28x28 canvas
+        /// before and after the contrast steps the model was trained with
+        #[arg(long, conflicts_with_all = ["stdin", "stdin_png"])]
+        preview: Option<String>,
+        /// Low-rank adapters to apply on top of the model, e.g. the adapter.mpk of an
+        /// `adapters` fine-tuning run
+        #[arg(long)]
+        adapter: Option<String>,
+        /// Add the adapters into the weights once at load time instead of composing them with
+        /// the layers at every prediction
+        #[arg(long, requires = "adapter")]
+        merge_adapter: bool,
+    },
+    /// Classify image files interactively, one path per line on stdin, until EOF
+    Repl {
+        #[arg(long, default_value = DEFAULT_ARTIFACT_DIR)]
+        artifact_dir: String,
+        /// Treat the images as photos of real handwriting (threshold, invert, center)
+        #[arg(long)]
+        natural: bool,
+    },
+    /// Evaluate a trained model on the test set and write eval.json
+    Evaluate {
+        /// Artifact dir of a training run, or the s3://bucket/prefix its checkpoint_store
+        /// uploaded it to (the reports are written to its local copy)
+        #[arg(long, default_value = DEFAULT_ARTIFACT_DIR)]
+        artifact_dir: String,
+        /// Number of confidence bins for the calibration error
+        #[arg(long, default_value_t = 15)]
+        calibration_bins: usize,
+        /// Also write logits.npy, predictions.npy and targets.npy
+        #[arg(long)]
+        export_npy: bool,
+        /// Also write the confusion matrix as confusion_matrix.csv and a confusion_matrix.png heatmap
+        #[arg(long)]
+        export_confusion: bool,
+        /// Also draw the calibration bins as a reliability_diagram.png
+        #[arg(long)]
+        export_reliability: bool,
+        /// Evaluate this ONNX classifier instead, writing its reports into artifact_dir (needs the
+        /// onnx feature)
+        #[arg(long)]
+        onnx: Option<String>,
+        /// Comma-separated confidence thresholds: also write the coverage and accuracy of the
+        /// predictions at least that confident, at each of them, as eval_reject.json
+        #[arg(long, value_delimiter = ',', conflicts_with = "onnx")]
+        reject_thresholds: Vec<f32>,
+    },
+    /// Evaluate a trained model on the test set and write report.html: metrics, confusion
+    /// matrix, per-class scores, calibration and the most confident errors of each class pair
+    Report {
+        #[arg(long, default_value = DEFAULT_ARTIFACT_DIR)]
+        artifact_dir: String,
+        /// Number of confidence bins of the reliability table
+        #[arg(long, default_value_t = 15)]
+        calibration_bins: usize,
+        /// Most confident errors shown per confused class pair
+        #[arg(long, default_value_t = 8)]
+        examples_per_pair: usize,
+        /// Most error images embedded in the page, to bound its size
+        #[arg(long, default_value_t = 200)]
+        max_images: usize,
+    },
+    /// Evaluate a trained model on corrupted copies of the test set and write robustness.json
+    Robustness {
+        #[arg(long, default_value = DEFAULT_ARTIFACT_DIR)]
+        artifact_dir: String,
+        /// Comma-separated corruptions among gaussian_noise, blur, rotation, brightness and
+        /// occlusion; all of them by default
+        #[arg(long, value_delimiter = ',')]
+        corruptions: Vec<corruption::CorruptionKind>,
+    },
+    /// Train the config, then once without each of its dropout and augmentation, and print the
+    /// validation accuracy each removal costs
+    Ablate {
+        /// Directory the runs are written under, one subdirectory each
+        #[arg(long, default_value = DEFAULT_ARTIFACT_DIR)]
+        artifact_dir: String,
+        /// Training config JSON; defaults to the built-in config
+        #[arg(long)]
+        config: Option<String>,
+    },
+    /// Run a learning rate range test and print the loss at each learning rate
+    LrFind {
+        /// Directory to write lr_finder.csv and lr_finder.svg into
+        #[arg(long, default_value = DEFAULT_ARTIFACT_DIR)]
+        artifact_dir: String,
+        /// Training config JSON; defaults to the built-in config
+        #[arg(long)]
+        config: Option<String>,
+        #[arg(long, default_value_t = lr_finder::DEFAULT_MIN_LR)]
+        min_lr: f64,
+        #[arg(long, default_value_t = lr_finder::DEFAULT_MAX_LR)]
+        max_lr: f64,
+        #[arg(long, default_value_t = lr_finder::DEFAULT_NUM_STEPS)]
+        num_steps: usize,
+    },
+    /// Prune the lowest-magnitude weights of a trained model and fine-tune it into <ARTIFACT_DIR>/pruned
+    Prune {
+        #[arg(long, default_value = DEFAULT_ARTIFACT_DIR)]
+        artifact_dir: String,
+        /// Fraction of every weight to zero, in [0, 1)
+        #[arg(long)]
+        sparsity: f64,
+        /// Epochs of fine-tuning after pruning
+        #[arg(long, default_value_t = 1)]
+        finetune_epochs: usize,
+    },
+    /// Embed the training set with a trained model into an index for the `similar` subcommand
+    BuildIndex {
+        #[arg(long, default_value = DEFAULT_ARTIFACT_DIR)]
+        artifact_dir: String,
+        /// Directory to write the index to; defaults to <ARTIFACT_DIR>/embedding_index
+        #[arg(long)]
+        index_dir: Option<String>,
+    },
+    /// List the training samples most similar to an image, from the index of build-index
+    Similar {
+        #[arg(long, default_value = DEFAULT_ARTIFACT_DIR)]
+        artifact_dir: String,
+        /// Image file to find neighbors of
+        image: String,
+        /// Index directory; defaults to <ARTIFACT_DIR>/embedding_index
+        #[arg(long)]
+        index_dir: Option<String>,
+        /// Number of neighbors to return
+        #[arg(long, default_value_t = 5)]
+        k: usize,
+        /// Treat the image as a photo of real handwriting (threshold, invert, center)
+        #[arg(long)]
+        natural: bool,
+        /// Also save the image and its neighbors side by side as a PNG
+        #[arg(long)]
+        contact_sheet: Option<String>,
+    },
+    /// Score every training sample with the trained model into sample_losses.csv, highest loss first
+    Audit {
+        #[arg(long, default_value = DEFAULT_ARTIFACT_DIR)]
+        artifact_dir: String,
+        /// Also write the images of the N highest-loss samples as PNGs
+        #[arg(long)]
+        top: Option<usize>,
+    },
+    /// Write a class-balanced sample of training images as PNGs with a manifest.json, to
+    /// calibrate the post-training quantization of the model with an external toolchain
+    CalibrationSet {
+        #[arg(long, default_value = DEFAULT_ARTIFACT_DIR)]
+        artifact_dir: String,
+        /// Number of images to write
+        #[arg(long, default_value_t = 500)]
+        num_samples: usize,
+        /// Directory to write the images and manifest into
+        #[arg(long)]
+        out: String,
+    },
+    /// Average the weights of several trained models of the same architecture (model soup)
+    Soup {
+        /// Artifact dirs of the models to average
+        #[arg(required = true, num_args = 1..)]
+        artifact_dirs: Vec<String>,
+        /// Artifact dir to write the averaged model to
+        #[arg(long)]
+        out: String,
+    },
+    /// Add seeded relative Gaussian noise to the weights of a trained model, to fine-tune with --resume-from
+    Perturb {
+        #[arg(long, default_value = DEFAULT_ARTIFACT_DIR)]
+        artifact_dir: String,
+        /// Artifact dir to write the perturbed model to
+        #[arg(long)]
+        out: String,
+        /// Standard deviation of the noise, relative to each weight's magnitude
+        #[arg(long)]
+        sigma: f64,
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+    },
+    /// Re-save the weights of a trained model with another burn recorder, into a new artifact dir
+    Convert {
+        #[arg(long, default_value = DEFAULT_ARTIFACT_DIR)]
+        artifact_dir: String,
+        /// Recorder the weights were saved with: compact, named-mpk, named-mpk-gz, bin or json
+        #[arg(long, default_value = "compact")]
+        input_format: convert::RecordFormat,
+        /// Artifact dir to write the converted model to
+        #[arg(long)]
+        out: String,
+        /// Recorder to save the weights with, one of the input formats
+        #[arg(long)]
+        output_format: convert::RecordFormat,
+    },
+    /// Pack a trained model into a single verified .tar.gz bundle, which infer accepts as --artifact-dir
+    Export {
+        #[arg(long, default_value = DEFAULT_ARTIFACT_DIR)]
+        artifact_dir: String,
+        /// Path of the bundle to write
+        #[arg(long, default_value = "model.tar.gz")]
+        out: String,
+    },
+    /// Print the mean, std, share of zeros and max of every layer output of a trained model on
+    /// one validation batch, to spot dead ReLUs and saturated layers
+    Activations {
+        #[arg(long, default_value = DEFAULT_ARTIFACT_DIR)]
+        artifact_dir: String,
+        #[arg(long, default_value_t = 64)]
+        batch_size: usize,
+        /// Also print the share of dead units of every ReLU layer over the whole test set
+        #[arg(long)]
+        dead_units: bool,
+    },
+    /// Measure the inference throughput and latency percentiles of a trained model
+    Bench {
+        #[arg(long, default_value = DEFAULT_ARTIFACT_DIR)]
+        artifact_dir: String,
+        #[arg(long, default_value_t = 1)]
+        batch_size: usize,
+        /// Untimed forward passes before the timed ones
+        #[arg(long, default_value_t = bench::DEFAULT_WARMUP)]
+        warmup: usize,
+        /// Timed forward passes
+        #[arg(long, default_value_t = bench::DEFAULT_ITERATIONS)]
+        iterations: usize,
+    },
+    /// Delete the runs under an artifacts root matching any of the criteria, printing the space
+    /// reclaimed. Directories without a config.json are left alone unless --include-unknown
+    Gc {
+        /// Directory holding the runs, directly or in one sub-directory per sweep
+        root: String,
+        /// Runs not modified for more than this many days
+        #[arg(long)]
+        older_than_days: Option<f64>,
+        /// Runs whose best validation accuracy (in percent) is below this
+        #[arg(long)]
+        min_accuracy: Option<f64>,
+        /// Runs without final weights
+        #[arg(long)]
+        interrupted: bool,
+        /// Every run but the most accurate ones of each sweep
+        #[arg(long)]
+        keep_top_k: Option<usize>,
+        /// Also delete directories without a config.json when they match
+        #[arg(long)]
+        include_unknown: bool,
+        /// Only list the runs that would be deleted
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Write run_record.json: the config, metadata, history, evaluation report and file hashes
+    /// of a run, without its weights, to compare it with runs made elsewhere
+    ExportRun {
+        #[arg(long, default_value = DEFAULT_ARTIFACT_DIR)]
+        artifact_dir: String,
+    },
+    /// Compare two runs, each given as a run_record.json or an artifact directory
+    DiffRuns {
+        a: String,
+        b: String,
+    },
+    /// Compare the weights of two trained models of the same architecture, parameter by
+    /// parameter: the norm of the difference, the relative change and the cosine similarity
+    DiffWeights {
+        /// Artifact dir of the reference model, e.g. the pretrained starting point
+        a: String,
+        /// Artifact dir of the model compared to it, e.g. the fine-tuned one
+        b: String,
+        /// Also write the report as JSON to this file
+        #[arg(long)]
+        json: Option<String>,
+    },
+    /// Print the layers of the model a config builds, with their output shapes, parameter and
+    /// multiply-accumulate counts, and exit. Reads only the config, no weights
+    Summary {
+        #[arg(long, default_value = DEFAULT_ARTIFACT_DIR)]
+        artifact_dir: String,
+        /// Training config JSON to read instead of the config.json of the artifact dir
+        #[arg(long)]
+        config: Option<String>,
+        /// Side of the square input images; defaults to the image size the run trained on, or 28
+        #[arg(long)]
+        image_size: Option<usize>,
+    },
+    /// Render the learning curves of a training run into curves.svg
+    Plot {
+        #[arg(long, default_value = DEFAULT_ARTIFACT_DIR)]
+        artifact_dir: String,
+    },
+    /// Print statistics of a dataset split and write data_info.json
+    DataInfo {
+        #[arg(long, default_value = DEFAULT_ARTIFACT_DIR)]
+        artifact_dir: String,
+        /// Training config JSON whose dataset to inspect; defaults to MNIST
+        #[arg(long)]
+        config: Option<String>,
+        /// Inspect the test split instead of the training split
+        #[arg(long)]
+        test: bool,
+        /// Directory to write a preview.png grid of the first samples of each class into
+        #[arg(long)]
+        preview_dir: Option<String>,
+        /// Number of samples per class in the preview grid
+        #[arg(long, default_value_t = 8)]
+        preview_per_class: usize,
+    },
+    /// Find duplicate training images and training images leaked into the test split, and write
+    /// leakage.json
+    LeakageCheck {
+        #[arg(long, default_value = DEFAULT_ARTIFACT_DIR)]
+        artifact_dir: String,
+        /// Training config JSON whose dataset to check; defaults to MNIST
+        #[arg(long)]
+        config: Option<String>,
+        /// Largest Hamming distance (of 64 bits) between the average hashes of near duplicates
+        #[arg(long, default_value_t = leakage::DEFAULT_THRESHOLD, value_parser = clap::value_parser!(u32).range(0..64))]
+        threshold: u32,
+        /// Number of example index pairs to report per kind of duplicate
+        #[arg(long, default_value_t = 10)]
+        max_examples: usize,
+        /// Also write the training indices without duplicates or leaks as train_subset.json, for
+        /// the `train_subset` config field
+        #[arg(long)]
+        write_subset: bool,
+    },
+    /// Write before/after PNG pairs of the mixup and CutMix augmentation of a training config
+    /// on its first training images, to check the transforms before a full run
+    PreviewAugmentation {
+        /// Training config JSON whose dataset and augmentation to preview
+        #[arg(long)]
+        config: String,
+        /// Number of training images to preview
+        #[arg(long, default_value_t = 8)]
+        num_samples: usize,
+        /// Directory to write the NN-before.png and NN-after.png pairs into
+        #[arg(long)]
+        out_dir: String,
+    },
+}
 
 fn main() {
-    type ModelBackend = Wgpu<AutoGraphicsApi, f32, i32>;
-    type ModelAutodiffBackend = Autodiff<ModelBackend>;
-
-    let device = burn::backend::wgpu::WgpuDevice::default();
-    my_first_rust_DL_app::training::train::<ModelAutodiffBackend>(
-        "/tmp/my_first_rust_DL_app",
-        my_first_rust_DL_app::training::TrainingConfig::new(
-            ModelConfig::new(10, 512),
-            AdamConfig::new(),
-        ),
-        device,
-    );
-
-    // my_first_rust_DL_app::inference::infer::<ModelBackend>(
-    //     "/tmp/my_first_rust_DL_app",
-    //     device,
-    //     burn::data::dataset::vision::MnistDataset::test()
-    //         .get(42)
-    //         .unwrap(),
-    // );
-
-}
\ No newline at end of file
+    let cli = Cli::parse();
+    let command = cli.command.unwrap_or(Command::Train {
+        artifact_dir: DEFAULT_ARTIFACT_DIR.to_string(),
+        config: None,
+        resume_from: None,
+        learning_rate: None,
+        reset_optimizer: false,
+        max_train_seconds: None,
+        reindex: false,
+    });
+
+    match command {
+        Command::Train {
+            artifact_dir,
+            config,
+            resume_from,
+            learning_rate,
+            reset_optimizer,
+            max_train_seconds,
+            reindex,
+        } => {
+            let resume_from = resume_from.map(|dir| fetch_artifact_dir(&dir, true));
+            let resumed_config = resume_from.as_ref().map(|dir| ArtifactDir::new(dir).config_path());
+            let mut config = TrainingConfig::load_or_builtin(config.as_deref().or(resumed_config.as_deref()))
+                .unwrap_or_else(|err| exit_with(&err));
+            if resume_from.is_some() {
+                config.resume_from = resume_from;
+                config.reset_optimizer = reset_optimizer;
+            }
+            if let Some(learning_rate) = learning_rate {
+                config.learning_rate = learning_rate;
+            }
+            if let Some(seconds) = max_train_seconds {
+                config.max_duration = Some(Duration::from_secs(seconds));
+            }
+            if reindex {
+                config.dataset.reindex().unwrap_or_else(|err| exit_with(&err));
+            }
+            train(&ArtifactDir::new(&artifact_dir), config)
+        }
+        Command::Infer {
+            artifact_dir,
+            image,
+            natural,
+            interpolation,
+            json,
+            stdin,
+            stdin_png,
+            batch_size,
+            batch_timeout_ms,
+            tta,
+            reject_below,
+            preview,
+            adapter,
+            merge_adapter,
+        } => {
+            let artifact_dir = fetch_artifact_dir(&artifact_dir, false);
+            let device = burn::backend::wgpu::WgpuDevice::default();
+            let mut loaded =
+                InferenceModel::<ModelBackend>::load(&artifact_dir, &device).unwrap_or_else(|err| exit_with(&err));
+            if let Some(path) = &adapter {
+                loaded.model = inference::with_adapter_file(loaded.model, path, merge_adapter, &device)
+                    .unwrap_or_else(|err| exit_with(&err));
+            }
+            if let Some(filter) = interpolation {
+                loaded.resize_policy = inference::ResizePolicy::Resize { filter };
+            }
+            let InferenceModel { model, labels, resize_policy: policy, preprocess, .. } = &loaded;
+
+            if stdin_png {
+                let stdin = std::io::stdin().lock();
+                inference::infer_framed(model, &device, stdin, std::io::stdout().lock(), natural, labels, *policy, preprocess)
+                    .unwrap_or_else(|err| exit_with(&err));
+            } else if stdin {
+                inference::infer_stream(
+                    model,
+                    &device,
+                    std::io::BufReader::new(std::io::stdin()),
+                    std::io::stdout().lock(),
+                    batch_size.unwrap_or(loaded.batch_size).max(1),
+                    Duration::from_millis(batch_timeout_ms),
+                    labels,
+                    *policy,
+                    preprocess,
+                )
+                .unwrap_or_else(|err| exit_with(&err));
+            } else {
+                let image = image.expect("clap requires an image unless --stdin or --stdin-png is given");
+                let fitted = inference::load_image_with_policy(&image, natural, *policy, &[]).unwrap_or_else(|err| exit_with(&err));
+                if let Some(dir) = &preview {
+                    let [before, after] =
+                        preprocess::write_preview(preprocess, &fitted, dir).unwrap_or_else(|err| exit_with(&err));
+                    eprintln!("Wrote {} and {}", before.display(), after.display());
+                }
+                let pixels = preprocess::apply_steps_raw(preprocess, &fitted);
+                let prediction = match tta {
+                    Some(num_augments) => inference::predict_tta(model, &device, pixels, num_augments, labels),
+                    None => inference::predict(model, &device, pixels, labels),
+                };
+                if let Some(threshold) = reject_below {
+                    let decision = prediction.reject_below(threshold);
+                    match (json, &decision) {
+                        (true, _) => println!("{}", serde_json::to_string(&decision).expect("Decision should serialize")),
+                        (false, Decision::Accepted(prediction)) => println!("Predicted {}", prediction.label),
+                        (false, Decision::Rejected { .. }) => println!("Rejected: {decision}"),
+                    }
+                } else if json {
+                    let json = serde_json::to_string(&prediction).expect("Prediction should serialize");
+                    println!("{json}");
+                } else {
+                    println!("Predicted {}", prediction.label);
+                }
+            }
+        }
+        Command::Repl { artifact_dir, natural } => {
+            let artifact_dir = fetch_artifact_dir(&artifact_dir, false);
+            let device = burn::backend::wgpu::WgpuDevice::default();
+            let InferenceModel { model, labels, resize_policy, preprocess, .. } =
+                InferenceModel::<ModelBackend>::load(&artifact_dir, &device).unwrap_or_else(|err| exit_with(&err));
+            let (input, output) = (std::io::stdin().lock(), std::io::stdout().lock());
+            inference::repl(&model, &device, input, output, natural, &labels, resize_policy, &preprocess)
+                .unwrap_or_else(|err| exit_with(&err));
+        }
+        Command::Report { artifact_dir, calibration_bins, examples_per_pair, max_images } => {
+            let device = burn::backend::wgpu::WgpuDevice::default();
+            let config = ReportConfig::new()
+                .with_calibration_bins(calibration_bins)
+                .with_examples_per_pair(examples_per_pair)
+                .with_max_images(max_images);
+            let report = my_first_rust_DL_app::report::<ModelBackend>(&artifact_dir, &config, &device)
+                .unwrap_or_else(|err| exit_with(&err));
+            println!(
+                "Accuracy: {:.2}% over {} samples, {} confused class pairs",
+                report.eval.accuracy * 100.0,
+                report.eval.num_samples,
+                report.confused_pairs.len()
+            );
+            println!("Report written to {}", ArtifactDir::new(&artifact_dir).report_path());
+        }
+        Command::Evaluate {
+            artifact_dir,
+            calibration_bins,
+            export_npy,
+            export_confusion,
+            export_reliability,
+            onnx,
+            reject_thresholds,
+        } => {
+            let artifact_dir = fetch_artifact_dir(&artifact_dir, false);
+            let device = burn::backend::wgpu::WgpuDevice::default();
+            let config = EvaluationConfig::new()
+                .with_calibration_bins(calibration_bins)
+                .with_export_npy(export_npy)
+                .with_export_confusion(export_confusion)
+                .with_export_reliability(export_reliability);
+            let report = match onnx {
+                Some(path) => evaluate_onnx(&path, &artifact_dir, &config, &device),
+                None => my_first_rust_DL_app::evaluate::<ModelBackend>(&artifact_dir, &config, &device)
+                    .unwrap_or_else(|err| exit_with(&err)),
+            };
+            println!("{report}");
+            if !reject_thresholds.is_empty() {
+                let curve = my_first_rust_DL_app::evaluate_with_reject::<ModelBackend>(&artifact_dir, &reject_thresholds, &device)
+                    .unwrap_or_else(|err| exit_with(&err));
+                for point in curve {
+                    println!("{point}");
+                }
+            }
+        }
+        Command::Robustness { artifact_dir, corruptions } => {
+            let device = burn::backend::wgpu::WgpuDevice::default();
+            let report = corruption::evaluate_corruptions::<ModelBackend>(&artifact_dir, &device, &corruptions)
+                .unwrap_or_else(|err| exit_with(&err));
+            println!("{report}");
+        }
+        Command::Ablate { artifact_dir, config } => {
+            let config = TrainingConfig::load_or_builtin(config.as_deref()).unwrap_or_else(|err| exit_with(&err));
+            let device = burn::backend::wgpu::WgpuDevice::default();
+            let deltas = ablation::ablation_study::<ModelAutodiffBackend>(&artifact_dir, &config, device)
+                .unwrap_or_else(|err| exit_with(&err));
+            if deltas.is_empty() {
+                println!("The config enables no component to ablate");
+            }
+            for (component, delta) in deltas {
+                println!("Without {component}: {delta:+.2} accuracy points");
+            }
+        }
+        Command::LrFind { artifact_dir, config, min_lr, max_lr, num_steps } => {
+            let config = TrainingConfig::load_or_builtin(config.as_deref()).unwrap_or_else(|err| exit_with(&err));
+            let device = burn::backend::wgpu::WgpuDevice::default();
+            let history = lr_finder::lr_range_test::<ModelAutodiffBackend>(&config, device, min_lr, max_lr, num_steps)
+                .unwrap_or_else(|err| exit_with(&err));
+            let report = lr_finder::LrFinderReport::new(history, num_steps);
+            report.save(&artifact_dir).unwrap_or_else(|err| exit_with(&err));
+            println!("{report}");
+            println!("Curve written to {artifact_dir}/lr_finder.csv and lr_finder.svg");
+        }
+        Command::Prune { artifact_dir, sparsity, finetune_epochs } => {
+            let device = burn::backend::wgpu::WgpuDevice::default();
+            let report = my_first_rust_DL_app::prune::prune::<ModelAutodiffBackend>(
+                &artifact_dir, sparsity, finetune_epochs, device,
+            )
+            .unwrap_or_else(|err| exit_with(&err));
+            print!("{report}");
+        }
+        Command::BuildIndex { artifact_dir, index_dir } => {
+            let device = burn::backend::wgpu::WgpuDevice::default();
+            let index_dir = index_dir.unwrap_or_else(|| format!("{artifact_dir}/{}", retrieval::INDEX_DIR));
+            let meta = retrieval::build_index::<ModelBackend>(&artifact_dir, &index_dir, &device)
+                .unwrap_or_else(|err| exit_with(&err));
+            println!("{} {}-d embeddings written to {index_dir}", meta.num_items, meta.embedding_dim);
+        }
+        Command::Similar { artifact_dir, image, index_dir, k, natural, contact_sheet } => {
+            let device = burn::backend::wgpu::WgpuDevice::default();
+            let index_dir = index_dir.unwrap_or_else(|| format!("{artifact_dir}/{}", retrieval::INDEX_DIR));
+            let query = inference::load_image(&image, natural).unwrap_or_else(|err| exit_with(&err));
+            let neighbors = retrieval::similar::<ModelBackend>(&artifact_dir, &index_dir, query, k, &device)
+                .unwrap_or_else(|err| exit_with(&err));
+            print!("{}", retrieval::neighbor_table(&neighbors));
+            if let Some(path) = contact_sheet {
+                retrieval::save_contact_sheet(&artifact_dir, &query, &neighbors, &path)
+                    .unwrap_or_else(|err| exit_with(&err));
+                println!("Contact sheet written to {path}");
+            }
+        }
+        Command::Audit { artifact_dir, top } => {
+            let device = burn::backend::wgpu::WgpuDevice::default();
+            let losses = my_first_rust_DL_app::audit::audit::<ModelBackend>(&artifact_dir, &device)
+                .unwrap_or_else(|err| exit_with(&err));
+            println!("Losses of {} training samples written to {artifact_dir}/sample_losses.csv", losses.len());
+            if let Some(n) = top {
+                let dir = my_first_rust_DL_app::audit::save_top_images(&artifact_dir, &losses, n)
+                    .unwrap_or_else(|err| exit_with(&err));
+                println!("{} highest-loss images written to {dir}", n.min(losses.len()));
+            }
+        }
+        Command::CalibrationSet { artifact_dir, num_samples, out } => {
+            let manifest = my_first_rust_DL_app::calibration::export_calibration_set(&artifact_dir, num_samples, &out)
+                .unwrap_or_else(|err| exit_with(&err));
+            println!("{} calibration images written to {out}, per class {:?}", manifest.images.len(), manifest.class_counts);
+        }
+        Command::Soup { artifact_dirs, out } => {
+            let device = burn::backend::wgpu::WgpuDevice::default();
+            let artifact_dirs: Vec<&str> = artifact_dirs.iter().map(String::as_str).collect();
+            my_first_rust_DL_app::soup::average_checkpoints::<ModelBackend>(&artifact_dirs, &out, &device)
+                .unwrap_or_else(|err| exit_with(&err));
+            println!("Average of {} models written to {out}", artifact_dirs.len());
+        }
+        Command::Perturb { artifact_dir, out, sigma, seed } => {
+            let device = burn::backend::wgpu::WgpuDevice::default();
+            my_first_rust_DL_app::perturb::perturb::<ModelBackend>(&artifact_dir, &out, sigma, seed, &device)
+                .unwrap_or_else(|err| exit_with(&err));
+            println!("Perturbed model written to {out}");
+        }
+        Command::Convert { artifact_dir, input_format, out, output_format } => {
+            let device = burn::backend::wgpu::WgpuDevice::default();
+            convert::convert_record::<ModelBackend>(&artifact_dir, input_format, &out, output_format, &device)
+                .unwrap_or_else(|err| exit_with(&err));
+            println!("Model saved with {} to {out}", output_format.recorder_name());
+        }
+        Command::Export { artifact_dir, out } => {
+            let manifest = my_first_rust_DL_app::export_bundle(&artifact_dir, &out).unwrap_or_else(|err| exit_with(&err));
+            println!("Bundle of {} files written to {out}", manifest.files.len());
+        }
+        Command::Activations { artifact_dir, batch_size, dead_units } => {
+            let device = burn::backend::wgpu::WgpuDevice::default();
+            let layers = activation_stats::valid_activation_report::<ModelBackend>(&artifact_dir, batch_size.max(1), &device)
+                .unwrap_or_else(|err| exit_with(&err));
+            print!("{}", activation_stats::activation_table(&layers));
+            if dead_units {
+                let dead_units = activation_stats::valid_dead_units::<ModelBackend>(&artifact_dir, &device)
+                    .unwrap_or_else(|err| exit_with(&err));
+                for (layer, fraction) in dead_units {
+                    println!("{layer}: {:.1}% dead units", fraction * 100.0);
+                }
+            }
+        }
+        Command::Bench { artifact_dir, batch_size, warmup, iterations } => {
+            let device = burn::backend::wgpu::WgpuDevice::default();
+            let report = bench::benchmark_run::<ModelBackend>(&artifact_dir, &device, batch_size.max(1), warmup, iterations.max(1))
+                .unwrap_or_else(|err| exit_with(&err));
+            println!("{report}");
+        }
+        Command::Gc { root, older_than_days, min_accuracy, interrupted, keep_top_k, include_unknown, dry_run } => {
+            let criteria = gc::GcCriteria {
+                older_than: older_than_days.map(|days| Duration::from_secs_f64(days.max(0.0) * 86_400.0)),
+                min_accuracy,
+                interrupted,
+                keep_top_k,
+                include_unknown,
+            };
+            let report = gc::gc(&root, &criteria, dry_run).unwrap_or_else(|err| exit_with(&err));
+            println!("{report}");
+        }
+        Command::ExportRun { artifact_dir } => {
+            run_record::export_run(&artifact_dir).unwrap_or_else(|err| exit_with(&err));
+            println!("Run record written to {artifact_dir}/{}", run_record::RUN_RECORD_FILE);
+        }
+        Command::DiffRuns { a, b } => {
+            let a = run_record::RunRecord::open(&a).unwrap_or_else(|err| exit_with(&err));
+            let b = run_record::RunRecord::open(&b).unwrap_or_else(|err| exit_with(&err));
+            println!("{}", run_record::diff_runs(&a, &b));
+        }
+        Command::DiffWeights { a, b, json } => {
+            let device = burn::backend::wgpu::WgpuDevice::default();
+            let report = my_first_rust_DL_app::weight_diff::diff_weights::<ModelBackend>(&a, &b, &device)
+                .unwrap_or_else(|err| exit_with(&err));
+            print!("{report}");
+            if let Some(path) = json {
+                let json = serde_json::to_string_pretty(&report).expect("Weight diff should serialize to JSON");
+                std::fs::write(&path, json).unwrap_or_else(|err| exit_with(&err));
+                println!("Weight diff written to {path}");
+            }
+        }
+        Command::Summary { artifact_dir, config, image_size } => {
+            let config_path = config.unwrap_or_else(|| ArtifactDir::new(&artifact_dir).config_path());
+            let config = TrainingConfig::load(&config_path).unwrap_or_else(|err| exit_with(&err));
+            let image_shape = match image_size {
+                Some(size) => [size, size],
+                None => my_first_rust_DL_app::meta::trained_image_shape(&artifact_dir).unwrap_or([28, 28]),
+            };
+            let device = burn::backend::wgpu::WgpuDevice::default();
+            print!("{}", config.model.summary::<ModelBackend>(image_shape, &device));
+        }
+        Command::Plot { artifact_dir } => {
+            my_first_rust_DL_app::plot::plot_learning_curves(&artifact_dir)
+                .unwrap_or_else(|err| exit_with(&err));
+            println!("Learning curves written to {artifact_dir}/curves.svg");
+        }
+        Command::DataInfo { artifact_dir, config, test, preview_dir, preview_per_class } => {
+            let (source, cache) = config_dataset(config.as_deref());
+            let split = if test { MnistSplit::Test } else { MnistSplit::Train };
+            let preview = preview_dir.as_deref().map(|dir| (dir, preview_per_class));
+            let info = data_info::write_data_info(&source, cache, split, &artifact_dir, preview)
+                .unwrap_or_else(|err| exit_with(&err));
+            print!("{info}");
+            if let Some(dir) = preview_dir {
+                println!("Preview written to {dir}/{}", data_info::PREVIEW_FILE);
+            }
+        }
+        Command::LeakageCheck { artifact_dir, config, threshold, max_examples, write_subset } => {
+            let (source, cache) = config_dataset(config.as_deref());
+            let report = leakage::write_leakage_report(&source, cache, &artifact_dir, threshold, max_examples, write_subset)
+                .unwrap_or_else(|err| exit_with(&err));
+            println!("{report}");
+            if write_subset {
+                println!("Training subset written to {artifact_dir}/{}", leakage::TRAIN_SUBSET_FILE);
+            }
+        }
+        Command::PreviewAugmentation { config, num_samples, out_dir } => {
+            let config = TrainingConfig::load(&config).unwrap_or_else(|err| exit_with(&err));
+            let pairs = my_first_rust_DL_app::preview_augmentation(&config, num_samples, &out_dir)
+                .unwrap_or_else(|err| exit_with(&err));
+            println!("{} before/after pairs written to {out_dir}", pairs.len());
+        }
+    }
+}
+
+// The dataset of the training config at `path` and whether to cache it, MNIST without one
+fn config_dataset(path: Option<&str>) -> (DatasetSource, bool) {
+    match path {
+        Some(path) => {
+            let config = TrainingConfig::load(path).unwrap_or_else(|err| exit_with(&err));
+            (config.dataset, config.cache)
+        }
+        None => (DatasetSource::Mnist, false),
+    }
+}
+
+// One backend per precision: the float type is a type parameter of the backend. The trained
+// model is saved, only whether training failed matters here.
+fn train(artifact_dir: &ArtifactDir, config: TrainingConfig) {
+    let trained = match config.precision {
+        PrecisionKind::F32 => {
+            let device = burn::backend::wgpu::WgpuDevice::default();
+            my_first_rust_DL_app::train::<ModelAutodiffBackend>(artifact_dir, config, device).map(drop)
+        }
+        #[cfg(feature = "candle")]
+        PrecisionKind::F16 => {
+            let device = burn::backend::candle::CandleDevice::Cpu;
+            my_first_rust_DL_app::train::<HalfAutodiffBackend>(artifact_dir, config, device).map(drop)
+        }
+        unsupported => exit_with(&format!(
+            "precision {unsupported:?} is not supported by this build, supported precisions: {SUPPORTED_PRECISIONS:?}"
+        )),
+    };
+    trained.unwrap_or_else(|err| exit_with(&err));
+}
+
+// Evaluates the ONNX classifier at `path` like a trained model, named by the class names of
+// `artifact_dir` if it has any
+#[cfg(feature = "onnx")]
+fn evaluate_onnx(
+    path: &str,
+    artifact_dir: &str,
+    config: &EvaluationConfig,
+    device: &burn::backend::wgpu::WgpuDevice,
+) -> my_first_rust_DL_app::EvalReport {
+    my_first_rust_DL_app::onnx::evaluate_onnx::<ModelBackend>(path, artifact_dir, config, device)
+        .unwrap_or_else(|err| exit_with(&err))
+}
+
+#[cfg(not(feature = "onnx"))]
+fn evaluate_onnx(
+    _path: &str,
+    _artifact_dir: &str,
+    _config: &EvaluationConfig,
+    _device: &burn::backend::wgpu::WgpuDevice,
+) -> my_first_rust_DL_app::EvalReport {
+    exit_with(&"this binary was built without the onnx feature, rebuild with --features onnx")
+}
+
+// The local copy of an s3:// artifact dir, downloaded first, or the artifact dir itself
+fn fetch_artifact_dir(artifact_dir: &str, checkpoints: bool) -> String {
+    let local = store::fetch_artifact_dir(artifact_dir, checkpoints).unwrap_or_else(|err| exit_with(&err));
+    if local != artifact_dir {
+        eprintln!("Downloaded {artifact_dir} to {local}");
+    }
+    local
+}
+
+fn exit_with(err: &dyn std::fmt::Display) -> ! {
+    eprintln!("Error: {err}");
+    std::process::exit(1);
+}
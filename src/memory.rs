@@ -0,0 +1,252 @@
+use crate::{
+    model::Model,
+    training::{LossSettings, TrainingConfig},
+};
+use burn::{
+    prelude::*,
+    tensor::backend::AutodiffBackend,
+    train::metric::{
+        state::{FormatOptions, NumericMetricState},
+        Metric, MetricEntry, MetricMetadata, Numeric,
+    },
+};
+use std::{
+    fs,
+    panic::{self, AssertUnwindSafe},
+};
+
+// Batch size `auto_batch_size` settles for when the memory of the backend cannot be measured
+const FALLBACK_BATCH_SIZE: usize = 32;
+// Largest batch size `auto_batch_size` probes
+const MAX_BATCH_SIZE: usize = 1 << 14;
+// Backends whose tensors live in host memory, where the process RSS measures them
+const HOST_BACKENDS: [&str; 1] = ["ndarray"];
+
+// Memory of this process: resident set size and its peak so far (`VmRSS` and `VmHWM` of
+// `/proc/self/status`), in bytes. `None` where it cannot be read, e.g. off Linux.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HostMemory {
+    pub rss: u64,
+    pub peak_rss: u64,
+}
+
+impl HostMemory {
+    pub fn current() -> Option<Self> {
+        let status = fs::read_to_string("/proc/self/status").ok()?;
+        let field = |name: &str| {
+            let line = status.lines().find(|line| line.starts_with(name))?;
+            let kib: u64 = line[name.len()..].trim().trim_end_matches("kB").trim().parse().ok()?;
+            Some(kib * 1024)
+        };
+        Some(Self { rss: field("VmRSS:")?, peak_rss: field("VmHWM:")? })
+    }
+
+    // Lowers the peak back to the current RSS, where the kernel allows it
+    pub fn reset_peak() {
+        fs::write("/proc/self/clear_refs", "5").ok();
+    }
+}
+
+// One training step worth of work on a zero batch of `batch_size` images of `image_shape`:
+// forward and backward, the gradients dropped without an optimizer step. False if the backend
+// panicked, typically on a failed allocation.
+fn probe<B: AutodiffBackend>(model: &Model<B>, device: &B::Device, batch_size: usize, image_shape: [usize; 2]) -> bool {
+    panic::catch_unwind(AssertUnwindSafe(|| {
+        let [height, width] = image_shape;
+        let images = Tensor::<B, 3>::zeros([batch_size, height, width], device);
+        let targets = Tensor::<B, 1, Int>::zeros([batch_size], device);
+        let output = model.forward_classification(images, targets, None, LossSettings::default());
+        output.loss.backward();
+    }))
+    .is_ok()
+}
+
+/// The largest power of two batch size of 28x28 images whose training step fits in
+/// `max_memory_mb` megabytes on top of what the process already uses, by probing batch sizes
+/// 1, 2, 4, ... with dummy forward and backward passes of `model`. Probing stops before the
+/// next size would cross the limit (memory roughly doubles with the batch size), or when a
+/// probe fails. Always at least 1.
+///
+/// Only measurable for backends that hold tensors in host memory (NdArray), through the
+/// process resident set size, and only on Linux. Other backends, e.g. wgpu whose device memory
+/// burn does not report, get a conservative 32.
+pub fn auto_batch_size<B: AutodiffBackend>(model: &Model<B>, device: &B::Device, max_memory_mb: usize) -> usize {
+    let on_host = HOST_BACKENDS.iter().any(|name| B::name().contains(name));
+    let baseline = match HostMemory::current() {
+        Some(memory) if on_host => memory.rss,
+        _ => return FALLBACK_BATCH_SIZE,
+    };
+    let limit = max_memory_mb as u64 * 1024 * 1024;
+
+    let mut best = 1;
+    let mut batch_size = 1;
+    loop {
+        HostMemory::reset_peak();
+        if batch_size > MAX_BATCH_SIZE || !probe(model, device, batch_size, [28, 28]) {
+            break;
+        }
+        // The peak includes every allocation of the probe, even those already freed. Without a
+        // reset it may also be an older peak, which only makes the estimate conservative.
+        let used = HostMemory::current().map_or(u64::MAX, |memory| memory.peak_rss.saturating_sub(baseline));
+        if used > limit {
+            break;
+        }
+        best = batch_size;
+        if used.saturating_mul(2) > limit {
+            break;
+        }
+        batch_size *= 2;
+    }
+    best
+}
+
+// Share of the largest working batch size `find_batch_size` settles for after a failed probe,
+// leaving room for the optimizer state and the allocations of a real training step
+const BATCH_SIZE_MARGIN: f64 = 0.8;
+
+/// The batch size `train` uses when `config.auto_batch_size` is set: a doubling search over
+/// batch sizes 1, 2, 4, ... up to `config.max_batch_size`, running one forward and backward pass
+/// of a fresh `config.model` on a zero batch of 28x28 images at each size until the backend
+/// fails to allocate it. Settles for 80% of the largest size that worked, or for
+/// `config.max_batch_size` itself when none failed.
+///
+/// A failed allocation is caught as the panic the backend raises for it. Where panics abort
+/// the process instead, nothing can be probed: the configured `batch_size` is kept, with a
+/// warning. So are the backends that abort on out-of-memory errors, whose probes never return.
+pub fn find_batch_size<B: AutodiffBackend>(config: &TrainingConfig, device: &B::Device) -> usize {
+    search_batch_size::<B>(config, [28, 28], device)
+}
+
+// `find_batch_size`, for the images of the dataset about to be trained on
+pub(crate) fn search_batch_size<B: AutodiffBackend>(
+    config: &TrainingConfig,
+    image_shape: [usize; 2],
+    device: &B::Device,
+) -> usize {
+    if cfg!(panic = "abort") {
+        eprintln!(
+            "Warning: allocation failures cannot be caught in a build that aborts on panic, keeping batch_size = {}",
+            config.batch_size
+        );
+        return config.batch_size;
+    }
+    let model = config.model.init::<B>(device);
+    let ceiling = config.max_batch_size.max(1);
+
+    let mut largest = None;
+    let mut batch_size = 1;
+    loop {
+        if !probe(&model, device, batch_size, image_shape) {
+            let largest = largest.unwrap_or(1);
+            return ((largest as f64 * BATCH_SIZE_MARGIN) as usize).max(1);
+        }
+        if batch_size == ceiling {
+            return ceiling;
+        }
+        largest = Some(batch_size);
+        batch_size = (batch_size * 2).min(ceiling);
+    }
+}
+
+const BYTES_PER_MB: f64 = 1024.0 * 1024.0;
+
+// Logs one host memory figure of every training step, in MB, NaN where it cannot be read
+fn update_state(state: &mut NumericMetricState, name: &str, bytes: impl FnOnce(HostMemory) -> u64) -> MetricEntry {
+    let mb = HostMemory::current().map_or(f64::NAN, |memory| bytes(memory) as f64 / BYTES_PER_MB);
+    state.update(mb, 1, FormatOptions::new(name).unit("MB").precision(1))
+}
+
+// Resident set size of the training process at every step, in MB. Only the host side is
+// reported: burn exposes no device memory usage for its backends, so on wgpu this is what the
+// host holds (batches, staging buffers) and not what the GPU does. Logs NaN where the host
+// memory cannot be read, rather than failing the run.
+#[derive(Default)]
+pub struct MemoryMetric {
+    state: NumericMetricState,
+}
+
+impl MemoryMetric {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Metric for MemoryMetric {
+    const NAME: &'static str = "Memory";
+
+    type Input = ();
+
+    fn update(&mut self, _input: &(), _metadata: &MetricMetadata) -> MetricEntry {
+        update_state(&mut self.state, Self::NAME, |memory| memory.rss)
+    }
+
+    fn clear(&mut self) {
+        self.state.reset()
+    }
+}
+
+impl Numeric for MemoryMetric {
+    fn value(&self) -> f64 {
+        self.state.value()
+    }
+}
+
+// Peak resident set size of the training process so far, like `MemoryMetric`: the footprint
+// to size `batch_size` against, the max of the learner summary being the peak of the run
+#[derive(Default)]
+pub struct PeakMemoryMetric {
+    state: NumericMetricState,
+}
+
+impl PeakMemoryMetric {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Metric for PeakMemoryMetric {
+    const NAME: &'static str = "Peak Memory";
+
+    type Input = ();
+
+    fn update(&mut self, _input: &(), _metadata: &MetricMetadata) -> MetricEntry {
+        update_state(&mut self.state, Self::NAME, |memory| memory.peak_rss)
+    }
+
+    fn clear(&mut self) {
+        self.state.reset()
+    }
+}
+
+impl Numeric for PeakMemoryMetric {
+    fn value(&self) -> f64 {
+        self.state.value()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ModelConfig;
+    use burn::{
+        backend::{ndarray::NdArrayDevice, Autodiff, NdArray},
+        optim::AdamConfig,
+    };
+
+    #[test]
+    fn batch_sizes_fit_the_limit_and_are_at_least_one() {
+        let device = NdArrayDevice::default();
+        let model = ModelConfig::new(10, 8).init::<Autodiff<NdArray>>(&device);
+
+        // Other tests share the process RSS, which can even shrink during a probe, so only the
+        // bounds are certain
+        for max_memory_mb in [0, 64] {
+            let batch_size = auto_batch_size(&model, &device, max_memory_mb);
+            assert!(batch_size >= 1 && batch_size.is_power_of_two(), "batch size {batch_size}");
+        }
+
+        // No probe fails below the ceiling
+        let config = TrainingConfig::new(ModelConfig::new(10, 8), AdamConfig::new()).with_max_batch_size(8);
+        assert_eq!(find_batch_size::<Autodiff<NdArray>>(&config, &device), 8);
+    }
+}
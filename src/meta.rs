@@ -0,0 +1,262 @@
+use crate::{
+    artifact::ArtifactDir,
+    budget::BudgetStop,
+    checkpoint::LoadError,
+    data::{SourceCount, MNIST_MEAN, MNIST_STD, PIXEL_SCALE},
+    history::History,
+    inference::ResizePolicy,
+    model::ModelConfig,
+    nan_guard::NanEvent,
+    preprocess::PreprocessConfig,
+    store::{CheckpointBackend, StoreError},
+    training::PrecisionKind,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{fs, io, path::Path};
+
+// Version of the `model_meta.json` layout. Bump it whenever a field is added, removed or changes
+// meaning, so that an artifact this build cannot read is detected instead of misread.
+//
+// Version 2 added `binary_target`; version 1 files are read as having none. Version 3 added
+// `precision`; older files are read as trained in F32, the only precision before it. Version 4
+// added `train_sources` and `valid_sources`; older files list none. Version 5 added
+// `resize_policy`; older files are read as resizing with Lanczos, the default before it. Version 6
+// added `budget_stop`; older files are read as having trained every epoch. Version 7 added
+// `preprocess`; older files are read as having no contrast steps, there were none before it.
+// Version 8 added `nan_events`; older files are read as having none.
+pub const FORMAT_VERSION: u32 = 8;
+
+// Oldest version this build still reads
+pub const OLDEST_FORMAT_VERSION: u32 = 1;
+
+pub const META_FILE: &str = "model_meta.json";
+
+// The only recorder artifacts are saved with: named MessagePack with the floats stored as f16,
+// about half the size of full precision. Loading upcasts them to the backend's float type.
+pub(crate) const RECORDER: &str = "CompactRecorder";
+
+// How the batcher maps raw pixels to model inputs: `(pixel / scale - mean) / std`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Normalization {
+    pub scale: f64,
+    pub mean: f64,
+    pub std: f64,
+}
+
+impl Normalization {
+    // The normalization of this build's batcher
+    pub fn current() -> Self {
+        Self { scale: PIXEL_SCALE, mean: MNIST_MEAN, std: MNIST_STD }
+    }
+}
+
+// Everything needed to tell which model a weights file holds, saved as `model_meta.json` next to
+// the `model` weights
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ModelMeta {
+    pub format_version: u32,
+    pub model: ModelConfig,
+    pub num_classes: usize,
+    pub image_shape: [usize; 2],
+    pub normalization: Normalization,
+    pub recorder: String,
+    // Best epoch mean of the validation accuracy, in percent. `None` for multi-label models,
+    // which are not scored by accuracy.
+    pub best_valid_accuracy: Option<f64>,
+    // One-vs-rest models: the original label of class 1, class 0 being every other label
+    #[serde(default)]
+    pub binary_target: Option<usize>,
+    // Float precision the model was trained in. The weights file is f16 whatever it is.
+    #[serde(default = "trained_precision")]
+    pub precision: PrecisionKind,
+    // Items each `DatasetSource` contributed to the training and validation sets, empty when
+    // the model was trained on datasets passed in rather than loaded from the config
+    #[serde(default)]
+    pub train_sources: Vec<SourceCount>,
+    #[serde(default)]
+    pub valid_sources: Vec<SourceCount>,
+    // How the training images that were not 28x28 were fitted onto the canvas, for inference
+    // to read images the same way
+    #[serde(default)]
+    pub resize_policy: ResizePolicy,
+    // The contrast steps every image went through once on the canvas, for inference to replay
+    #[serde(default)]
+    pub preprocess: Vec<PreprocessConfig>,
+    // The budget that ended training before `num_epochs`, if one did
+    #[serde(default)]
+    pub budget_stop: Option<BudgetStop>,
+    // The non-finite steps `nan_guard` skipped and rolled back from
+    #[serde(default)]
+    pub nan_events: Vec<NanEvent>,
+}
+
+// The precision of artifacts from before `precision` was recorded
+fn trained_precision() -> PrecisionKind {
+    PrecisionKind::F32
+}
+
+impl ModelMeta {
+    pub fn new(model: &ModelConfig, image_shape: [usize; 2], history: &History) -> Self {
+        let best_valid_accuracy = history.best_valid("Accuracy");
+
+        Self {
+            format_version: FORMAT_VERSION,
+            model: model.clone(),
+            num_classes: model.num_classes,
+            image_shape,
+            normalization: Normalization::current(),
+            recorder: RECORDER.to_string(),
+            best_valid_accuracy,
+            binary_target: None,
+            precision: PrecisionKind::F32,
+            train_sources: Vec::new(),
+            valid_sources: Vec::new(),
+            resize_policy: ResizePolicy::default(),
+            preprocess: Vec::new(),
+            budget_stop: None,
+            nan_events: Vec::new(),
+        }
+    }
+
+    pub fn with_binary_target(mut self, binary_target: Option<usize>) -> Self {
+        self.binary_target = binary_target;
+        self
+    }
+
+    pub fn with_sources(mut self, train_sources: Vec<SourceCount>, valid_sources: Vec<SourceCount>) -> Self {
+        self.train_sources = train_sources;
+        self.valid_sources = valid_sources;
+        self
+    }
+
+    pub fn with_resize_policy(mut self, resize_policy: ResizePolicy) -> Self {
+        self.resize_policy = resize_policy;
+        self
+    }
+
+    pub fn with_preprocess(mut self, preprocess: Vec<PreprocessConfig>) -> Self {
+        self.preprocess = preprocess;
+        self
+    }
+
+    pub fn with_budget_stop(mut self, budget_stop: Option<BudgetStop>) -> Self {
+        self.budget_stop = budget_stop;
+        self
+    }
+
+    pub fn with_nan_events(mut self, nan_events: Vec<NanEvent>) -> Self {
+        self.nan_events = nan_events;
+        self
+    }
+
+    pub fn with_precision(mut self, precision: PrecisionKind) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    // Human readable name of a predicted class: `7` / `not-7` for a one-vs-rest model of 7s,
+    // the class index otherwise
+    pub fn label_name(&self, label: usize) -> String {
+        match self.binary_target {
+            Some(target) if label == 1 => target.to_string(),
+            Some(target) => format!("not-{target}"),
+            None => label.to_string(),
+        }
+    }
+
+    pub fn save(&self, artifact_dir: &str) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(self).expect("Model metadata should serialize to JSON");
+        fs::write(ArtifactDir::new(artifact_dir).meta_path(), json)
+    }
+
+    // The metadata of `artifact_dir`, `None` for artifacts saved before it was written. The
+    // version is checked before the rest is parsed.
+    pub fn load(artifact_dir: &str) -> Result<Option<Self>, LoadError> {
+        let path = ArtifactDir::new(artifact_dir).meta_path();
+        if !Path::new(&path).exists() {
+            return Ok(None);
+        }
+        let json = fs::read_to_string(&path).map_err(|err| LoadError::Config(format!("{path}: {err}")))?;
+        Self::from_json(&json, &path).map(Some)
+    }
+
+    // `load` of the metadata of a run in a checkpoint store
+    pub fn load_from(backend: &dyn CheckpointBackend) -> Result<Option<Self>, LoadError> {
+        match backend.get(META_FILE) {
+            Ok(json) => Self::from_json(&String::from_utf8_lossy(&json), META_FILE).map(Some),
+            Err(StoreError::NotFound(_)) => Ok(None),
+            Err(err) => Err(LoadError::Config(format!("{META_FILE}: {err}"))),
+        }
+    }
+
+    // Parses metadata read from `source` (a path, for the errors), version first
+    pub fn from_json(json: &str, source: &str) -> Result<Self, LoadError> {
+        let invalid = |err: &dyn std::fmt::Display| LoadError::Config(format!("{source}: {err}"));
+
+        let value: Value = serde_json::from_str(json).map_err(|err| invalid(&err))?;
+        let found = value.get("format_version").and_then(Value::as_u64).unwrap_or(0) as u32;
+        if !(OLDEST_FORMAT_VERSION..=FORMAT_VERSION).contains(&found) {
+            return Err(LoadError::Version { found, supported: FORMAT_VERSION });
+        }
+
+        serde_json::from_value(value).map_err(|err| invalid(&err))
+    }
+
+    // Errors on anything that would make the weights unusable with `config`, naming the values
+    // of both sides
+    pub fn check(&self, config: &ModelConfig) -> Result<(), LoadError> {
+        let mismatch = |field: &str, meta: String, config: String| {
+            Err(LoadError::Incompatible { field: field.to_string(), meta, config })
+        };
+
+        if self.recorder != RECORDER {
+            return mismatch("recorder", self.recorder.clone(), RECORDER.to_string());
+        }
+        if self.normalization != Normalization::current() {
+            return mismatch(
+                "normalization",
+                format!("{:?}", self.normalization),
+                format!("{:?} (this build)", Normalization::current()),
+            );
+        }
+        if self.num_classes != config.num_classes {
+            return mismatch("num_classes", self.num_classes.to_string(), config.num_classes.to_string());
+        }
+        if self.model.hidden_size != config.hidden_size {
+            return mismatch("model.hidden_size", self.model.hidden_size.to_string(), config.hidden_size.to_string());
+        }
+        if self.model.use_bias != config.use_bias {
+            return mismatch("model.use_bias", self.model.use_bias.to_string(), config.use_bias.to_string());
+        }
+        if self.model.batch_norm != config.batch_norm {
+            return mismatch("model.batch_norm", self.model.batch_norm.to_string(), config.batch_norm.to_string());
+        }
+        Ok(())
+    }
+}
+
+// The model config to rebuild the weights of `artifact_dir` with, for a caller using `config`.
+// With metadata, its config is checked against `config` and preferred over it: both describe
+// the same layers, but settings that do not change shapes (dropout, task) come from the
+// metadata. Without metadata `config` is used as is.
+pub fn resolve_model_config(artifact_dir: &str, config: &ModelConfig) -> Result<ModelConfig, LoadError> {
+    resolve_with_meta(ModelMeta::load(artifact_dir)?, config)
+}
+
+// `resolve_model_config` of the metadata `meta`, wherever it was read from
+pub fn resolve_with_meta(meta: Option<ModelMeta>, config: &ModelConfig) -> Result<ModelConfig, LoadError> {
+    match meta {
+        Some(meta) => {
+            meta.check(config)?;
+            Ok(meta.model)
+        }
+        None => Ok(config.clone()),
+    }
+}
+
+// The image shape the model of `artifact_dir` was trained on. Artifacts without metadata are from
+// before non-MNIST datasets, 28x28.
+pub fn trained_image_shape(artifact_dir: &str) -> Result<[usize; 2], LoadError> {
+    Ok(ModelMeta::load(artifact_dir)?.map_or([28, 28], |meta| meta.image_shape))
+}
@@ -0,0 +1,115 @@
+use burn::{
+    module::{Module, ModuleVisitor, ParamId},
+    prelude::*,
+    tensor::backend::AutodiffBackend,
+    train::metric::{
+        state::{FormatOptions, NumericMetricState},
+        Metric, MetricEntry, MetricMetadata, Numeric,
+    },
+};
+
+// Sums the squared gradient of every float parameter the visitor walks over
+struct GradNormVisitor<'a, B: AutodiffBackend> {
+    grads: &'a B::Gradients,
+    sum_squares: Option<Tensor<B::InnerBackend, 1>>,
+}
+
+impl<B: AutodiffBackend> ModuleVisitor<B> for GradNormVisitor<'_, B> {
+    fn visit_float<const D: usize>(&mut self, _id: &ParamId, tensor: &Tensor<B, D>) {
+        // Parameters that did not take part in the loss have no gradient
+        let Some(grad) = tensor.grad(self.grads) else {
+            return;
+        };
+        let squares = grad.powf_scalar(2.0).sum();
+        self.sum_squares = Some(match self.sum_squares.take() {
+            Some(sum) => sum + squares,
+            None => squares,
+        });
+    }
+}
+
+// Global L2 norm of the gradients returned by `loss.backward()`, over every parameter of
+// `module`: the square root of the sum of all squared gradient entries. Returned as a
+// one-element tensor so the caller decides if and when to sync it back to the host.
+pub fn global_grad_norm<B: AutodiffBackend, M: Module<B>>(
+    module: &M,
+    grads: &B::Gradients,
+    device: &B::Device,
+) -> Tensor<B::InnerBackend, 1> {
+    let mut visitor = GradNormVisitor::<B> { grads, sum_squares: None };
+    module.visit(&mut visitor);
+
+    visitor
+        .sum_squares
+        .unwrap_or_else(|| Tensor::zeros([1], device))
+        .sqrt()
+}
+
+// Input of the gradient norm metric: the norm computed by the training step
+pub struct GradNormInput<B: Backend> {
+    norm: Tensor<B, 1>,
+}
+
+impl<B: Backend> GradNormInput<B> {
+    pub fn new(norm: Tensor<B, 1>) -> Self {
+        Self { norm }
+    }
+}
+
+// Global gradient L2 norm of each training step, averaged over the epoch
+#[derive(Default)]
+pub struct GradNormMetric<B: Backend> {
+    state: NumericMetricState,
+    _b: B,
+}
+
+impl<B: Backend> GradNormMetric<B> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<B: Backend> Metric for GradNormMetric<B> {
+    const NAME: &'static str = "Gradient Norm";
+
+    type Input = GradNormInput<B>;
+
+    fn update(&mut self, input: &Self::Input, _metadata: &MetricMetadata) -> MetricEntry {
+        let norm = input.norm.clone().into_scalar().elem::<f64>();
+        // Every step counts once, whatever its batch size
+        self.state.update(norm, 1, FormatOptions::new(Self::NAME).precision(4))
+    }
+
+    fn clear(&mut self) {
+        self.state.reset()
+    }
+}
+
+impl<B: Backend> Numeric for GradNormMetric<B> {
+    fn value(&self) -> f64 {
+        self.state.value()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use burn::{
+        backend::{ndarray::NdArrayDevice, Autodiff, NdArray},
+        nn::LinearConfig,
+    };
+
+    #[test]
+    fn global_grad_norm_is_the_l2_norm_of_every_gradient() {
+        type B = Autodiff<NdArray>;
+        let device = NdArrayDevice::default();
+        let linear = LinearConfig::new(2, 3).init::<B>(&device);
+        let input = Tensor::<B, 2>::from_floats([[1.0, 2.0]], &device);
+
+        // d sum(x W + b) / dW[i][j] = x[i] and / db[j] = 1
+        let grads = linear.forward(input).sum().backward();
+        let norm = global_grad_norm(&linear, &grads, &device).into_scalar();
+        let expected = (3.0 * 1.0 + 3.0 * 4.0 + 3.0 * 1.0f32).sqrt();
+        assert!((norm - expected).abs() < 1e-5, "norm {norm}, expected {expected}");
+    }
+}
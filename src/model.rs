@@ -1,73 +1,1064 @@
 
 use burn::{
+    constant,
     nn::{
         conv::{Conv2d, Conv2dConfig},
         pool::{AdaptiveAvgPool2d, AdaptiveAvgPool2dConfig},
-        Dropout, DropoutConfig, Linear, LinearConfig, Relu
+        BatchNorm, BatchNormConfig, Dropout, DropoutConfig, Linear, LinearConfig, Relu
     },
+    module::Param,
     prelude::*,
 };
+use crate::adapter::{AdapterConfig, Adapters, LoraAdapter};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::fmt;
 
 /*
     - Creating a Deep Learning module with the #[derive(Module)] attribute at the top of a struct
     - This trait makes the module both trainable and (de)serializable while adding related functionalities.
 */
+/// The CNN digit classifier: two conv layers (each optionally batch normalized, see
+/// [`ModelConfig::batch_norm`]), adaptive average pooling (or global pooling, see [`GlobalPool`])
+/// and a two-layer dense head. Build it with [`ModelConfig::init`].
 #[derive(Module, Debug)]
 pub struct Model<B: Backend> {
     conv1: Conv2d<B>,
+    // Of `ModelConfig::batch_norm`, normalizing the output of `conv1`
+    conv1_norm: Option<BatchNorm<B, 2>>,
     conv2: Conv2d<B>,
+    conv2_norm: Option<BatchNorm<B, 2>>,
+    // Of `HeadInput::AdaptiveAvgPool`, unused with `HeadInput::Flatten`
     pool: AdaptiveAvgPool2d,
+    head_input: HeadInput,
+    // Replaces `pool` when not `None`
+    global_pool: GlobalPool,
     dropout: Dropout,
     linear1: Linear<B>,
     linear2: Linear<B>,
-    activation: Relu
+    activation: Relu,
+    // Kept alongside `dropout` (whose probability is private) for `forward_mc_dropout`
+    dropout_prob: f64,
+    // Low-rank updates added to the layers at runtime, see `Model::with_new_adapters`
+    adapters: Option<Adapters<B>>,
+    // The second head of `ModelConfig::aux_task`
+    aux_head: Option<AuxHead<B>>,
 }
 
+/// An auxiliary task learned by a second head alongside the classes, from the same hidden
+/// features (multi-task learning). Its targets are derived from the class targets.
+#[derive(Config, Debug, Copy, PartialEq)]
+pub enum AuxTask {
+    // Whether the class index is even (0) or odd (1)
+    Parity,
+}
+
+// Stored in `Model` as a constant, not saved with the weights
+constant!(AuxTask);
+
+impl AuxTask {
+    pub fn num_classes(&self) -> usize {
+        match self {
+            AuxTask::Parity => 2,
+        }
+    }
+
+    /// The targets `[batch_size]` of the task for the class `targets`.
+    pub fn targets<B: Backend>(&self, targets: Tensor<B, 1, Int>) -> Tensor<B, 1, Int> {
+        match self {
+            AuxTask::Parity => targets.clone() - targets.div_scalar(2).mul_scalar(2),
+        }
+    }
+}
+
+/// The head of an [`AuxTask`]: a linear layer from the `linear1.relu` features to the logits of
+/// the task.
+#[derive(Module, Debug)]
+pub struct AuxHead<B: Backend> {
+    task: AuxTask,
+    linear: Linear<B>,
+}
+
+impl<B: Backend> AuxHead<B> {
+    pub fn task(&self) -> AuxTask {
+        self.task
+    }
+}
+
+/// What the classifier head predicts.
+#[derive(Config, Debug, Copy, PartialEq)]
+pub enum TaskKind {
+    // One label per image: softmax over the classes, trained with cross-entropy
+    SingleLabel,
+    // Any number of labels per image: an independent sigmoid per class, trained with binary
+    // cross-entropy (see `train_multilabel_on`)
+    MultiLabel,
+}
+
+/// How the conv features are reduced before the dense head.
+#[derive(Config, Debug, Copy, PartialEq)]
+pub enum GlobalPool {
+    // Reduced as `ModelConfig::head_input` says
+    None,
+    // Mean of every channel over the whole image: 16 features, whatever the image size
+    Avg,
+    // Maximum of every channel over the whole image
+    Max,
+}
+
+// Stored in `Model` as a constant, not saved with the weights
+constant!(GlobalPool);
+
+/// What the dense head reads of the conv features, when there is no [`GlobalPool`].
+#[derive(Config, Debug, Copy, PartialEq)]
+pub enum HeadInput {
+    // The conv feature maps as they are, flattened: 16 * 24 * 24 features, so only 28x28
+    // images fit the head
+    Flatten,
+    // Adaptive average pooling to `size` x `size`, flattened into 16 * size * size features
+    // whatever the image size
+    AdaptiveAvgPool { size: usize },
+}
+
+// Stored in `Model` as a constant, not saved with the weights
+constant!(HeadInput);
+
+// Side of the conv feature maps of a 28x28 image: each 3x3 convolution trims 2 pixels
+const FLATTEN_SIDE: usize = 28 - 4;
+
+impl GlobalPool {
+    // Number of features the dense head reads, `head_input` applying to `GlobalPool::None`
+    fn num_features(&self, head_input: HeadInput) -> usize {
+        match (self, head_input) {
+            (GlobalPool::None, HeadInput::Flatten) => 16 * FLATTEN_SIDE * FLATTEN_SIDE,
+            (GlobalPool::None, HeadInput::AdaptiveAvgPool { size }) => 16 * size * size,
+            (GlobalPool::Avg | GlobalPool::Max, _) => 16,
+        }
+    }
+}
+
+/// Hyperparameters of [`Model`]; `init` builds the model from them.
 #[derive(Config, Debug)]
 pub struct ModelConfig {
-    num_classes: usize,
-    hidden_size: usize,
+    pub num_classes: usize,
+    pub hidden_size: usize,
     #[config(default = "0.5")]
-    dropout: f64,
+    pub dropout: f64,
+    // Give the conv and linear layers a bias term
+    #[config(default = true)]
+    pub use_bias: bool,
+    // The layers are the same for both tasks, only the loss and the metrics differ
+    #[config(default = "TaskKind::SingleLabel")]
+    pub task: TaskKind,
+    // Global pooling makes the model independent of the image size
+    #[config(default = "GlobalPool::None")]
+    pub global_pool: GlobalPool,
+    // How the conv features become the dense head input without global pooling, which
+    // replaces it when set
+    #[config(default = "HeadInput::AdaptiveAvgPool { size: 8 }")]
+    pub head_input: HeadInput,
+    // A second head learning this task too, weighted in the loss by
+    // `TrainingConfig::aux_loss_weight`. Inference reads the class head only.
+    pub aux_task: Option<AuxTask>,
+    // Batch normalize the output of each conv layer, before its dropout. The running mean and
+    // variance are tracked with `bn_momentum` (the weight of each new batch) and the variance is
+    // offset by `bn_epsilon`, burn's defaults.
+    #[config(default = false)]
+    pub batch_norm: bool,
+    #[config(default = 0.1)]
+    pub bn_momentum: f64,
+    #[config(default = 1e-5)]
+    pub bn_epsilon: f64,
 }
 
 impl ModelConfig {
+    /// Number of features the dense head reads, from `global_pool` and `head_input`.
+    pub fn head_features(&self) -> usize {
+        self.global_pool.num_features(self.head_input)
+    }
+
+    // Output side of the adaptive pooling layer, which `HeadInput::Flatten` leaves unused
+    fn pool_size(&self) -> usize {
+        match self.head_input {
+            HeadInput::Flatten => 1,
+            HeadInput::AdaptiveAvgPool { size } => size,
+        }
+    }
+
+    // The layer configs `init` builds the model from, `init_portable` reading the shapes of the
+    // weights it draws from them too
+    fn conv_configs(&self) -> [Conv2dConfig; 2] {
+        [Conv2dConfig::new([1, 8], [3, 3]), Conv2dConfig::new([8, 16], [3, 3])].map(|conv| conv.with_bias(self.use_bias))
+    }
+
+    /// The batch norm layers of `batch_norm` over the outputs of the two conv layers, `None`
+    /// without it.
+    pub fn batch_norm_configs(&self) -> Option<[BatchNormConfig; 2]> {
+        let norm = |num_features| BatchNormConfig::new(num_features).with_momentum(self.bn_momentum).with_epsilon(self.bn_epsilon);
+        self.batch_norm.then(|| [norm(8), norm(16)])
+    }
+
+    fn linear_configs(&self) -> [LinearConfig; 2] {
+        [
+            LinearConfig::new(self.head_features(), self.hidden_size),
+            LinearConfig::new(self.hidden_size, self.num_classes),
+        ]
+        .map(|linear| linear.with_bias(self.use_bias))
+    }
+
+    fn aux_head_config(&self, task: AuxTask) -> LinearConfig {
+        LinearConfig::new(self.hidden_size, task.num_classes()).with_bias(self.use_bias)
+    }
+
     // Returns the initialized Model
     pub fn init<B: Backend>(&self, device: &B::Device) -> Model<B> {
+        let [conv1, conv2] = self.conv_configs();
+        let [conv1_norm, conv2_norm] = match self.batch_norm_configs() {
+            Some(norms) => norms.map(|norm| Some(norm.init(device))),
+            None => [None, None],
+        };
+        let [linear1, linear2] = self.linear_configs();
         Model {
-            conv1: Conv2dConfig::new([1, 8], [3, 3]).init(device),
-            conv2: Conv2dConfig::new([8, 16], [3, 3]).init(device),
-            pool: AdaptiveAvgPool2dConfig::new([8, 8]).init(),
+            conv1: conv1.init(device),
+            conv1_norm,
+            conv2: conv2.init(device),
+            conv2_norm,
+            pool: AdaptiveAvgPool2dConfig::new([self.pool_size(); 2]).init(),
+            head_input: self.head_input,
+            global_pool: self.global_pool,
             activation: Relu::new(),
-            linear1: LinearConfig::new(16 * 8 * 8, self.hidden_size).init(device),
-            linear2: LinearConfig::new(self.hidden_size, self.num_classes).init(device),
-            dropout: DropoutConfig::new(self.dropout).init()
+            linear1: linear1.init(device),
+            linear2: linear2.init(device),
+            dropout: DropoutConfig::new(self.dropout).init(),
+            dropout_prob: self.dropout,
+            adapters: None,
+            aux_head: self.aux_task.map(|task| AuxHead { task, linear: self.aux_head_config(task).init(device) }),
+        }
+    }
+
+    /// Like [`ModelConfig::init`], with the initial weights drawn on the CPU from `seed` and
+    /// copied to `device`, rather than by the backend's own random generator: the initial
+    /// parameters are then the same on every backend of the same float type. They follow the
+    /// same distributions as burn's default initializers.
+    pub fn init_portable<B: Backend>(&self, seed: u64, device: &B::Device) -> Model<B> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        // The layers' own lazy initializers are replaced before they run. The weights are
+        // drawn layer by layer in forward order, weight then bias.
+        let model = self.init::<B>(device);
+        let [conv1, conv2] = self.conv_configs();
+        let [linear1, linear2] = self.linear_configs();
+        Model {
+            conv1: portable_conv2d(model.conv1, &conv1, &mut rng, device),
+            conv2: portable_conv2d(model.conv2, &conv2, &mut rng, device),
+            linear1: portable_linear(model.linear1, &linear1, &mut rng, device),
+            linear2: portable_linear(model.linear2, &linear2, &mut rng, device),
+            aux_head: model.aux_head.map(|head| AuxHead {
+                linear: portable_linear(head.linear, &self.aux_head_config(head.task), &mut rng, device),
+                ..head
+            }),
+            ..model
         }
     }
 }
 
+// Burn's default initializer of the conv and linear weights and biases, Kaiming uniform with a
+// gain of 1/sqrt(3): uniform in (-1/sqrt(fan_in), 1/sqrt(fan_in)), drawn from `rng`
+fn portable_uniform<B: Backend, const D: usize>(
+    shape: [usize; D],
+    fan_in: usize,
+    rng: &mut StdRng,
+    device: &B::Device,
+) -> Param<Tensor<B, D>> {
+    let bound = 1.0 / (fan_in as f32).sqrt();
+    let values: Vec<f32> = (0..shape.iter().product()).map(|_| rng.gen_range(-bound..bound)).collect();
+    Param::from_tensor(Tensor::<B, 1>::from_floats(values.as_slice(), device).reshape(shape))
+}
+
+// `conv`, plus the update of `adapter` when there is one
+fn conv_forward<B: Backend>(conv: &Conv2d<B>, adapter: Option<&LoraAdapter<B>>, x: Tensor<B, 4>) -> Tensor<B, 4> {
+    match adapter {
+        Some(adapter) => conv.forward(x.clone()) + adapter.forward_conv(x, conv.weight.dims()),
+        None => conv.forward(x),
+    }
+}
+
+// `linear`, plus the update of `adapter` when there is one
+fn linear_forward<B: Backend>(linear: &Linear<B>, adapter: Option<&LoraAdapter<B>>, x: Tensor<B, 2>) -> Tensor<B, 2> {
+    match adapter {
+        Some(adapter) => linear.forward(x.clone()) + adapter.forward_linear(x),
+        None => linear.forward(x),
+    }
+}
+
+// `norm` of the conv output `x`, handed to `capture` as `name`, or `x` as it is without one
+fn batch_norm_forward<B: Backend>(
+    norm: Option<&BatchNorm<B, 2>>,
+    name: &str,
+    x: Tensor<B, 4>,
+    capture: &mut dyn FnMut(&str, Tensor<B, 4>),
+) -> Tensor<B, 4> {
+    let Some(norm) = norm else {
+        return x;
+    };
+    let x = norm.forward(x);
+    capture(name, x.clone());
+    x
+}
+
+// `weight` plus the update `delta` gives for its shape, keeping its parameter id
+fn merge_weight<B: Backend, const D: usize>(
+    weight: Param<Tensor<B, D>>,
+    delta: impl Fn([usize; D]) -> Tensor<B, D>,
+) -> Param<Tensor<B, D>> {
+    weight.map(|weight| {
+        let delta = delta(weight.dims());
+        weight + delta
+    })
+}
+
+// `conv` with its weight, `[out, in / groups, kernel, kernel]` as `config` gives it, and its bias
+// redrawn, its fan in being `in / groups * kernel * kernel`
+fn portable_conv2d<B: Backend>(
+    mut conv: Conv2d<B>,
+    config: &Conv2dConfig,
+    rng: &mut StdRng,
+    device: &B::Device,
+) -> Conv2d<B> {
+    let [in_channels, out_channels] = config.channels;
+    let shape = [out_channels, in_channels / config.groups, config.kernel_size[0], config.kernel_size[1]];
+    let fan_in = shape[1] * shape[2] * shape[3];
+    conv.weight = portable_uniform(shape, fan_in, rng, device);
+    conv.bias = conv.bias.map(|_| portable_uniform([out_channels], fan_in, rng, device));
+    conv
+}
+
+// `linear` with its weight, `[in, out]` as `config` gives it, and its bias redrawn, its fan in
+// being `in`
+fn portable_linear<B: Backend>(
+    mut linear: Linear<B>,
+    config: &LinearConfig,
+    rng: &mut StdRng,
+    device: &B::Device,
+) -> Linear<B> {
+    linear.weight = portable_uniform([config.d_input, config.d_output], config.d_input, rng, device);
+    linear.bias = linear.bias.map(|_| portable_uniform([config.d_output], config.d_input, rng, device));
+    linear
+}
+
+/// One layer of an [`ArchDescription`]: its name (those with an output share the names of
+/// [`Model::activations`]) and hyperparameters.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LayerDescription {
+    pub name: String,
+    #[serde(flatten)]
+    pub layer: LayerKind,
+}
+
+/// A layer type and its hyperparameters, serialized with a `type` tag.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type")]
+pub enum LayerKind {
+    Conv2d { in_channels: usize, out_channels: usize, kernel_size: [usize; 2], bias: bool },
+    BatchNorm { num_features: usize, momentum: f64, epsilon: f64 },
+    Dropout { prob: f64 },
+    Relu,
+    AdaptiveAvgPool2d { output_size: [usize; 2] },
+    // The feature maps passed on as they are, to be flattened
+    Flatten,
+    GlobalAvgPool,
+    GlobalMaxPool,
+    Linear { in_features: usize, out_features: usize, bias: bool },
+}
+
+impl LayerKind {
+    // Number of weights and biases of the layer
+    fn num_params(&self) -> usize {
+        match *self {
+            LayerKind::Conv2d { in_channels, out_channels, kernel_size: [height, width], bias } => {
+                out_channels * in_channels * height * width + if bias { out_channels } else { 0 }
+            }
+            // The scale and shift, and the running mean and variance, which burn counts too
+            LayerKind::BatchNorm { num_features, .. } => 4 * num_features,
+            LayerKind::Linear { in_features, out_features, bias } => {
+                in_features * out_features + if bias { out_features } else { 0 }
+            }
+            _ => 0,
+        }
+    }
+}
+
+impl fmt::Display for LayerKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bias = |bias: bool| if bias { "" } else { ", no bias" };
+        match self {
+            LayerKind::Conv2d { in_channels, out_channels, kernel_size: [height, width], bias: has_bias } => {
+                write!(f, "Conv2d {in_channels}->{out_channels}, {height}x{width}{}", bias(*has_bias))
+            }
+            LayerKind::BatchNorm { num_features, .. } => write!(f, "BatchNorm {num_features}"),
+            LayerKind::Dropout { prob } => write!(f, "Dropout {prob}"),
+            LayerKind::Relu => write!(f, "ReLU"),
+            LayerKind::AdaptiveAvgPool2d { output_size: [height, width] } => {
+                write!(f, "AdaptiveAvgPool2d {height}x{width}")
+            }
+            LayerKind::Flatten => write!(f, "Flatten"),
+            LayerKind::GlobalAvgPool => write!(f, "GlobalAvgPool"),
+            LayerKind::GlobalMaxPool => write!(f, "GlobalMaxPool"),
+            LayerKind::Linear { in_features, out_features, bias: has_bias } => {
+                write!(f, "Linear {in_features}->{out_features}{}", bias(*has_bias))
+            }
+        }
+    }
+}
+
+/// The layers of a [`Model`] in forward order, as built by [`ModelConfig::init`]. Serializable so
+/// that tooling can store and diff the architectures of runs.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ArchDescription {
+    pub task: TaskKind,
+    pub layers: Vec<LayerDescription>,
+}
+
+impl ModelConfig {
+    // Mirrors `init` and the order `forward` applies the layers in, dropout included
+    pub fn describe(&self) -> ArchDescription {
+        let layer = |name: &str, layer| LayerDescription { name: name.to_string(), layer };
+        let dropout = || LayerKind::Dropout { prob: self.dropout };
+        let conv = |in_channels, out_channels| LayerKind::Conv2d {
+            in_channels,
+            out_channels,
+            kernel_size: [3, 3],
+            bias: self.use_bias,
+        };
+        let pool = match (self.global_pool, self.head_input) {
+            (GlobalPool::None, HeadInput::Flatten) => LayerKind::Flatten,
+            (GlobalPool::None, HeadInput::AdaptiveAvgPool { size }) => LayerKind::AdaptiveAvgPool2d { output_size: [size, size] },
+            (GlobalPool::Avg, _) => LayerKind::GlobalAvgPool,
+            (GlobalPool::Max, _) => LayerKind::GlobalMaxPool,
+        };
+
+        let norms = self.batch_norm_configs().map(|norms| {
+            norms.map(|norm| LayerKind::BatchNorm { num_features: norm.num_features, momentum: norm.momentum, epsilon: norm.epsilon })
+        });
+        let mut layers = vec![layer("conv1", conv(1, 8))];
+        if let Some([conv1_norm, _]) = &norms {
+            layers.push(layer("conv1.norm", conv1_norm.clone()));
+        }
+        layers.extend([layer("conv1.dropout", dropout()), layer("conv2", conv(8, 16))]);
+        if let Some([_, conv2_norm]) = &norms {
+            layers.push(layer("conv2.norm", conv2_norm.clone()));
+        }
+        layers.extend([
+            layer("conv2.dropout", dropout()),
+            layer("conv2.relu", LayerKind::Relu),
+            layer("pool", pool),
+            layer(
+                "linear1",
+                LayerKind::Linear { in_features: self.head_features(), out_features: self.hidden_size, bias: self.use_bias },
+            ),
+            layer("linear1.dropout", dropout()),
+            layer("linear1.relu", LayerKind::Relu),
+            layer(
+                "linear2",
+                LayerKind::Linear { in_features: self.hidden_size, out_features: self.num_classes, bias: self.use_bias },
+            ),
+        ]);
+        if let Some(task) = self.aux_task {
+            // Reads `linear1.relu` as `linear2` does
+            layers.push(layer(
+                "aux_head",
+                LayerKind::Linear { in_features: self.hidden_size, out_features: task.num_classes(), bias: self.use_bias },
+            ));
+        }
+
+        ArchDescription { task: self.task, layers }
+    }
+
+    /// A table of the layers of [`ModelConfig::describe`], each with its output shape for one
+    /// image of `image_shape`, its parameters and its multiply-accumulates (see
+    /// [`Model::flops`]), then the totals. Built from the config alone, on freshly initialized
+    /// weights. `image_shape` must be one the model takes: 28x28 with `HeadInput::Flatten`.
+    pub fn summary<B: Backend>(&self, image_shape: [usize; 2], device: &B::Device) -> String {
+        let model = self.init::<B>(device);
+        let [height, width] = image_shape;
+        let images = Tensor::<B, 3>::zeros([1, height, width], device);
+        let dense = |name: &str| name.starts_with("linear") || name == "aux_head";
+        let mut shapes: Vec<(String, String)> = model
+            .activations(images.clone())
+            .into_iter()
+            .map(|(name, output)| {
+                let [_, channels, height, width] = output.dims();
+                let shape = match dense(&name) {
+                    true => channels.to_string(),
+                    false => format!("{channels}x{height}x{width}"),
+                };
+                (name, shape)
+            })
+            .collect();
+        if let (_, Some(aux_output)) = model.forward_aux(images) {
+            shapes.push(("aux_head".to_string(), aux_output.dims()[1].to_string()));
+        }
+        let macs = model.flops([1, height, width]);
+
+        let mut table = format!("| {:<15} | {:<26} | {:>10} | {:>9} | {:>11} |\n", "Layer", "Type", "Output", "Params", "MACs");
+        table.push_str("|-----------------|----------------------------|------------|-----------|-------------|\n");
+        // Dropout keeps the shape of the layer before it
+        let mut shape = format!("1x{height}x{width}");
+        for layer in self.describe().layers {
+            if let Some((_, output)) = shapes.iter().find(|(name, _)| *name == layer.name) {
+                shape.clone_from(output);
+            }
+            let layer_macs = macs.iter().find(|(name, _)| *name == layer.name).map_or(0, |(_, macs)| *macs);
+            table.push_str(&format!(
+                "| {:<15} | {:<26} | {:>10} | {:>9} | {:>11} |\n",
+                layer.name,
+                layer.layer.to_string(),
+                shape,
+                layer.layer.num_params(),
+                layer_macs,
+            ));
+        }
+        table.push_str(&format!("Task: {:?}, input 1x{height}x{width}\n", self.task));
+        table.push_str(&format!("Parameters: {}\n", model.num_params()));
+        table.push_str(&format!("MACs per image: {}\n", macs.iter().map(|(_, macs)| macs).sum::<u64>()));
+        table
+    }
+}
+
+/// The inference side of a classifier: logits `[batch_size, num_classes]` for normalized images
+/// `[batch_size, height, width]`, as [`crate::MnistBatcher`] makes them. What evaluation and
+/// prediction need of a model, so that they take a [`Model`] or, with the `onnx` feature, an
+/// imported `crate::onnx::OnnxModel` alike.
+pub trait Classifier<B: Backend> {
+    fn forward(&self, images: Tensor<B, 3>) -> Tensor<B, 2>;
+}
+
+impl<B: Backend> Classifier<B> for Model<B> {
+    fn forward(&self, images: Tensor<B, 3>) -> Tensor<B, 2> {
+        Model::forward(self, images)
+    }
+}
+
 impl<B: Backend> Model<B> {
     // # Shapes
     //      - Images [batch_size, height, width]
     //      - Output [batch_size, num_classes]
     pub fn forward(&self, images: Tensor<B, 3>) -> Tensor<B, 2> {
+        self.forward_impl(images, None)
+    }
+
+    // Same as `forward` but dropout stays active on any backend (burn only applies it on autodiff
+    // backends), with masks drawn from `rng` so the stochastic passes are reproducible.
+    pub fn forward_mc_dropout(&self, images: Tensor<B, 3>, rng: &mut StdRng) -> Tensor<B, 2> {
+        self.forward_impl(images, Some(rng))
+    }
+
+    pub fn dropout_prob(&self) -> f64 {
+        self.dropout_prob
+    }
+
+    /// Whether the conv layers are batch normalized, see [`ModelConfig::batch_norm`].
+    pub fn has_batch_norm(&self) -> bool {
+        self.conv1_norm.is_some()
+    }
+
+    pub fn aux_head(&self) -> Option<&AuxHead<B>> {
+        self.aux_head.as_ref()
+    }
+
+    /// The logits of `forward` and, with an [`AuxHead`], those `[batch_size, task classes]` of
+    /// its task, from the same pass.
+    pub fn forward_aux(&self, images: Tensor<B, 3>) -> (Tensor<B, 2>, Option<Tensor<B, 2>>) {
+        let Some(head) = &self.aux_head else {
+            return (self.forward(images), None);
+        };
+        let mut features = None;
+        let output = self.forward_impl_with(images, None, &mut |name, x| {
+            if name == "linear1.relu" {
+                features = Some(x);
+            }
+        });
+        let features: Tensor<B, 2> = features.expect("The forward pass goes through linear1.relu").flatten(1, 3);
+        (output, Some(head.linear.forward(features)))
+    }
+
+    /// The same model with all of its weights frozen and fresh [`LoraAdapter`]s on the layers
+    /// `config` targets, for only those to train. Each `down` is drawn from `seed` like burn's
+    /// default initializer would, each `up` is zero: the model computes the same as before.
+    pub fn with_new_adapters(self, config: &AdapterConfig, seed: u64, device: &B::Device) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let scale = config.scale();
+        let mut adapter = |name: &str, [fan_in, fan_out]: [usize; 2]| {
+            config.targets(name).then(|| {
+                let down = portable_uniform([fan_in, config.rank], fan_in, &mut rng, device);
+                let up = Param::from_tensor(Tensor::zeros([config.rank, fan_out], device));
+                LoraAdapter::new(down, up, scale)
+            })
+        };
+        // Convolutions as the projections of their unrolled patches
+        let conv_fans = |conv: &Conv2d<B>| {
+            let [out, input, height, width] = conv.weight.dims();
+            [input * height * width, out]
+        };
+        let adapters = Adapters {
+            conv1: adapter("conv1", conv_fans(&self.conv1)),
+            conv2: adapter("conv2", conv_fans(&self.conv2)),
+            linear1: adapter("linear1", self.linear1.weight.dims()),
+            linear2: adapter("linear2", self.linear2.weight.dims()),
+        };
+        self.no_grad().with_adapters(adapters)
+    }
+
+    /// The same model with `adapters`, such as those [`Adapters::load`] reads, composed with its
+    /// layers at runtime. Replaces any it had.
+    pub fn with_adapters(mut self, adapters: Adapters<B>) -> Self {
+        self.adapters = Some(adapters);
+        self
+    }
+
+    pub fn adapters(&self) -> Option<&Adapters<B>> {
+        self.adapters.as_ref()
+    }
+
+    /// The model without its adapters, and the adapters.
+    pub fn split_adapters(mut self) -> (Self, Option<Adapters<B>>) {
+        let adapters = self.adapters.take();
+        (self, adapters)
+    }
+
+    /// The model with the update of every adapter added into the weight of its layer and the
+    /// adapters dropped: the same outputs as composing them at runtime, without its cost.
+    pub fn merge_adapters(self) -> Self {
+        let (mut model, adapters) = self.split_adapters();
+        let Some(adapters) = adapters else {
+            return model;
+        };
+        if let Some(adapter) = adapters.conv1 {
+            model.conv1.weight = merge_weight(model.conv1.weight, |shape| adapter.conv_delta(shape));
+        }
+        if let Some(adapter) = adapters.conv2 {
+            model.conv2.weight = merge_weight(model.conv2.weight, |shape| adapter.conv_delta(shape));
+        }
+        if let Some(adapter) = adapters.linear1 {
+            model.linear1.weight = merge_weight(model.linear1.weight, |_| adapter.delta());
+        }
+        if let Some(adapter) = adapters.linear2 {
+            model.linear2.weight = merge_weight(model.linear2.weight, |_| adapter.delta());
+        }
+        model
+    }
+
+    /// The FLOPs of every conv and linear layer of a forward pass over images of `input_shape`,
+    /// `[batch_size, height, width]`, counted as multiply-accumulates (one per weight use, the
+    /// convention of most CNN FLOP counts), keyed by layer name as in [`Model::activations`] and
+    /// in forward order. The adapters of the layers, composed at runtime, count as layers of
+    /// their own (`conv1.adapter`...). Bias additions, batch norm, activations and pooling are
+    /// left out.
+    /// Their sum is the cost of the pass.
+    pub fn flops(&self, input_shape: [usize; 3]) -> Vec<(String, u64)> {
+        let [batch_size, height, width] = input_shape.map(|dim| dim as u64);
+        let adapters = self.adapters.as_ref();
+        let mut macs = Vec::new();
+        let (mut height, mut width) = (height, width);
+        for (name, conv, adapter) in [
+            ("conv1", &self.conv1, adapters.and_then(|adapters| adapters.conv1.as_ref())),
+            ("conv2", &self.conv2, adapters.and_then(|adapters| adapters.conv2.as_ref())),
+        ] {
+            let [out, input, kernel_height, kernel_width] = conv.weight.dims().map(|dim| dim as u64);
+            // Stride 1, unpadded
+            (height, width) = ((height + 1).saturating_sub(kernel_height), (width + 1).saturating_sub(kernel_width));
+            let conv_macs = batch_size * out * height * width * input * kernel_height * kernel_width;
+            macs.push((name.to_string(), conv_macs));
+            if let Some(adapter) = adapter {
+                // The kernel of the update, then a convolution with it
+                let rank = adapter.rank() as u64;
+                macs.push((format!("{name}.adapter"), input * kernel_height * kernel_width * rank * out + conv_macs));
+            }
+        }
+        let mut linears = vec![
+            ("linear1", &self.linear1, adapters.and_then(|adapters| adapters.linear1.as_ref())),
+            ("linear2", &self.linear2, adapters.and_then(|adapters| adapters.linear2.as_ref())),
+        ];
+        linears.extend(self.aux_head.as_ref().map(|head| ("aux_head", &head.linear, None)));
+        for (name, linear, adapter) in linears {
+            let [input, out] = linear.weight.dims().map(|dim| dim as u64);
+            macs.push((name.to_string(), batch_size * input * out));
+            if let Some(adapter) = adapter {
+                let rank = adapter.rank() as u64;
+                macs.push((format!("{name}.adapter"), batch_size * (input * rank + rank * out)));
+            }
+        }
+        macs
+    }
+
+    // The conv layers, then the linear layers of the class head, in forward order, and how the
+    // head reads the conv features: what a model exporter needs besides the ReLUs, for
+    // `onnx::export_onnx`
+    #[cfg(feature = "onnx")]
+    pub(crate) fn layers(&self) -> ([&Conv2d<B>; 2], [&Linear<B>; 2], GlobalPool, HeadInput) {
+        ([&self.conv1, &self.conv2], [&self.linear1, &self.linear2], self.global_pool, self.head_input)
+    }
+
+    // The same model with every dropout layer an identity, even on autodiff backends, e.g. to
+    // take deterministic gradients through it
+    pub(crate) fn without_dropout(mut self) -> Self {
+        self.dropout = DropoutConfig::new(0.0).init();
+        self
+    }
+
+    fn apply_dropout<const D: usize>(&self, x: Tensor<B, D>, rng: &mut Option<&mut StdRng>) -> Tensor<B, D> {
+        let Some(rng) = rng else {
+            return self.dropout.forward(x);
+        };
+        if self.dropout_prob == 0.0 {
+            return x;
+        }
+
+        let prob_keep = 1.0 - self.dropout_prob;
+        let shape = x.shape();
+        let mask: Vec<f32> = (0..shape.num_elements())
+            .map(|_| if rng.gen_bool(prob_keep) { 1.0 } else { 0.0 })
+            .collect();
+        let mask = Tensor::<B, 1>::from_floats(mask.as_slice(), &x.device()).reshape(shape);
+
+        x * mask * (1.0 / prob_keep)
+    }
+
+    // Embeddings `[batch_size, hidden_size]` of `images`: the output of the last hidden layer
+    // (`linear1.relu`), which the classification head reads. Dropout is inactive, as at inference.
+    pub fn forward_features(&self, images: Tensor<B, 3>) -> Tensor<B, 2> {
+        let mut features = None;
+        self.forward_impl_with(images, None, &mut |name, x| {
+            if name == "linear1.relu" {
+                features = Some(x);
+            }
+        });
+        let features = features.expect("The forward pass goes through linear1.relu");
+        let [batch_size, hidden_size, _, _] = features.dims();
+        features.reshape([batch_size, hidden_size])
+    }
+
+    // Output of every layer for `images`, in forward order and keyed by layer name. Spatial
+    // layers give [batch_size, channels, height, width]; dense layers are reshaped to
+    // [batch_size, features, 1, 1]. Dropout is not a layer here: it is inactive at inference.
+    pub fn activations(&self, images: Tensor<B, 3>) -> Vec<(String, Tensor<B, 4>)> {
+        let mut activations = Vec::new();
+        self.forward_impl_with(images, None, &mut |name, x| {
+            activations.push((name.to_string(), x));
+        });
+        activations
+    }
+
+    fn forward_impl(&self, images: Tensor<B, 3>, rng: Option<&mut StdRng>) -> Tensor<B, 2> {
+        self.forward_impl_with(images, rng, &mut |_, _| {})
+    }
+
+    // The forward pass, handing each layer output to `capture` (see `activations`)
+    fn forward_impl_with(
+        &self,
+        images: Tensor<B, 3>,
+        mut rng: Option<&mut StdRng>,
+        capture: &mut dyn FnMut(&str, Tensor<B, 4>),
+    ) -> Tensor<B, 2> {
+        let features = self.forward_conv_with(images, &mut rng, capture);
+        self.forward_head_with(features, &mut rng, capture)
+    }
+
+    // The conv stages alone: the feature maps `[batch_size, 16, height - 4, width - 4]` of
+    // `conv2.relu`, the last conv layer, with dropout as in `forward`
+    pub(crate) fn forward_conv(&self, images: Tensor<B, 3>) -> Tensor<B, 4> {
+        self.forward_conv_with(images, &mut None, &mut |_, _| {})
+    }
+
+    // The rest of `forward`, from the `forward_conv` feature maps
+    pub(crate) fn forward_head(&self, features: Tensor<B, 4>) -> Tensor<B, 2> {
+        self.forward_head_with(features, &mut None, &mut |_, _| {})
+    }
+
+    fn forward_conv_with(
+        &self,
+        images: Tensor<B, 3>,
+        rng: &mut Option<&mut StdRng>,
+        capture: &mut dyn FnMut(&str, Tensor<B, 4>),
+    ) -> Tensor<B, 4> {
         let [batch_size, height, width] = images.dims();
 
         // create a channel at the second dimension
         let x = images.reshape([batch_size, 1, height, width]);
 
-        let x = self.conv1.forward(x); // [batch_size, 8, _, _]
-        let x = self.dropout.forward(x); 
-        let x = self.conv2.forward(x); // [batch_size, 16, _, _]
-        let x = self.dropout.forward(x);
+        let adapters = self.adapters.as_ref();
+        let x = conv_forward(&self.conv1, adapters.and_then(|adapters| adapters.conv1.as_ref()), x); // [batch_size, 8, _, _]
+        capture("conv1", x.clone());
+        let x = batch_norm_forward(self.conv1_norm.as_ref(), "conv1.norm", x, capture);
+        let x = self.apply_dropout(x, rng);
+        let x = conv_forward(&self.conv2, adapters.and_then(|adapters| adapters.conv2.as_ref()), x); // [batch_size, 16, _, _]
+        capture("conv2", x.clone());
+        let x = batch_norm_forward(self.conv2_norm.as_ref(), "conv2.norm", x, capture);
+        let x = self.apply_dropout(x, rng);
         let x = self.activation.forward(x);
+        capture("conv2.relu", x.clone());
+        x
+    }
+
+    fn forward_head_with(
+        &self,
+        x: Tensor<B, 4>,
+        rng: &mut Option<&mut StdRng>,
+        capture: &mut dyn FnMut(&str, Tensor<B, 4>),
+    ) -> Tensor<B, 2> {
+        let dense = |x: &Tensor<B, 2>| {
+            let [batch_size, features] = x.dims();
+            x.clone().reshape([batch_size, features, 1, 1])
+        };
 
-        let x = self.pool.forward(x); // [batch_size, 16, 8, 8]
-        let x = x.reshape([batch_size, 16 * 8 * 8]);
-        let x = self.linear1.forward(x);
-        let x = self.dropout.forward(x);
+        let x = match (self.global_pool, self.head_input) {
+            (GlobalPool::None, HeadInput::Flatten) => x, // [batch_size, 16, 24, 24] for 28x28 images
+            (GlobalPool::None, HeadInput::AdaptiveAvgPool { .. }) => self.pool.forward(x), // [batch_size, 16, size, size]
+            (GlobalPool::Avg, _) => x.mean_dim(3).mean_dim(2), // [batch_size, 16, 1, 1]
+            (GlobalPool::Max, _) => x.max_dim(3).max_dim(2),
+        };
+        capture("pool", x.clone());
+        let x: Tensor<B, 2> = x.flatten(1, 3);
+        let adapters = self.adapters.as_ref();
+        let x = linear_forward(&self.linear1, adapters.and_then(|adapters| adapters.linear1.as_ref()), x);
+        capture("linear1", dense(&x));
+        let x = self.apply_dropout(x, rng);
         let x = self.activation.forward(x);
+        capture("linear1.relu", dense(&x));
+
+        let x = linear_forward(&self.linear2, adapters.and_then(|adapters| adapters.linear2.as_ref()), x); // [batch_size, num_classes]
+        capture("linear2", dense(&x));
+        x
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::params::{named_params, NamedParam};
+    use burn::backend::{ndarray::NdArrayDevice, Autodiff, NdArray};
+
+    #[test]
+    fn flops_match_the_layer_shapes_at_28x28() {
+        let model = ModelConfig::new(10, 32).init::<NdArray>(&NdArrayDevice::default());
+
+        // conv1 1->8 3x3 to 26x26, conv2 8->16 3x3 to 24x24, pooled to 16x8x8 for linear1
+        let expected = [
+            ("conv1", 8 * 26 * 26 * 9),
+            ("conv2", 16 * 24 * 24 * 8 * 9),
+            ("linear1", 16 * 8 * 8 * 32),
+            ("linear2", 32 * 10),
+        ];
+        let expected: Vec<(String, u64)> = expected.iter().map(|(name, macs)| (name.to_string(), *macs)).collect();
+        assert_eq!(model.flops([1, 28, 28]), expected);
+        let tripled: Vec<(String, u64)> = expected.into_iter().map(|(name, macs)| (name, 3 * macs)).collect();
+        assert_eq!(model.flops([3, 28, 28]), tripled);
+    }
+
+    #[test]
+    fn summary_of_a_saved_config_lists_the_shapes_and_parameters() {
+        let artifact_dir = std::env::temp_dir().join("my_first_rust_DL_app-model-summary");
+        std::fs::create_dir_all(&artifact_dir).unwrap();
+        let config_path = artifact_dir.join("config.json");
+        crate::TrainingConfig::new(ModelConfig::new(10, 32), burn::optim::AdamConfig::new()).save(&config_path).unwrap();
+
+        // No weights next to the config, as for a run killed before its first save
+        let config = crate::TrainingConfig::load(&config_path).unwrap();
+        let summary = config.model.summary::<NdArray>([28, 28], &NdArrayDevice::default());
+        std::fs::remove_dir_all(&artifact_dir).unwrap();
+
+        // 8*9+8, 16*8*9+16, 16*8*8*32+32 and 32*10+10
+        assert!(summary.contains("Parameters: 34378\n"), "{summary}");
+        for shape in ["8x26x26", "16x24x24", "16x8x8"] {
+            assert!(summary.contains(&format!(" {shape} |")), "no {shape} layer in {summary}");
+        }
+        assert!(summary.lines().all(|line| !line.is_empty()), "{summary}");
+    }
+
+    #[test]
+    fn activations_are_captured_for_every_layer_but_dropout() {
+        let config = ModelConfig::new(10, 32);
+        let device = NdArrayDevice::default();
+        let model = config.init::<NdArray>(&device);
+
+        let activations = model.activations(Tensor::zeros([2, 28, 28], &device));
+        let layers: Vec<String> = config
+            .describe()
+            .layers
+            .into_iter()
+            .filter(|layer| !matches!(layer.layer, LayerKind::Dropout { .. }))
+            .map(|layer| layer.name)
+            .collect();
+        let names: Vec<String> = activations.iter().map(|(name, _)| name.clone()).collect();
+        assert_eq!(names, layers);
+        let shapes: Vec<[usize; 4]> = activations.iter().map(|(_, activation)| activation.dims()).collect();
+        assert_eq!(
+            shapes,
+            [[2, 8, 26, 26], [2, 16, 24, 24], [2, 16, 24, 24], [2, 16, 8, 8], [2, 32, 1, 1], [2, 32, 1, 1], [2, 10, 1, 1]]
+        );
+    }
+
+    #[test]
+    fn bias_free_model_has_the_biases_fewer_parameters() {
+        let device = NdArrayDevice::default();
+        let with_bias = ModelConfig::new(10, 32).init::<NdArray>(&device);
+        let without_bias = ModelConfig::new(10, 32).with_use_bias(false).init::<NdArray>(&device);
+
+        // conv1, conv2, linear1 and linear2 outputs
+        let num_biases = 8 + 16 + 32 + 10;
+        assert_eq!(with_bias.num_params(), 34378);
+        assert_eq!(without_bias.num_params(), with_bias.num_params() - num_biases);
+    }
+
+    #[test]
+    fn description_of_the_default_config_lists_the_layers_in_order() {
+        let description = ModelConfig::new(10, 32).describe();
+
+        let names: Vec<&str> = description.layers.iter().map(|layer| layer.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "conv1",
+                "conv1.dropout",
+                "conv2",
+                "conv2.dropout",
+                "conv2.relu",
+                "pool",
+                "linear1",
+                "linear1.dropout",
+                "linear1.relu",
+                "linear2"
+            ]
+        );
+        assert_eq!(description.layers[0].layer, LayerKind::Conv2d { in_channels: 1, out_channels: 8, kernel_size: [3, 3], bias: true });
+        assert_eq!(description.layers[5].layer, LayerKind::AdaptiveAvgPool2d { output_size: [8, 8] });
+        assert_eq!(description.layers[9].layer, LayerKind::Linear { in_features: 32, out_features: 10, bias: true });
+        let num_params: usize = description.layers.iter().map(|layer| layer.layer.num_params()).sum();
+        assert_eq!(num_params, 34378);
+
+        let json = serde_json::to_string(&description).unwrap();
+        assert_eq!(serde_json::from_str::<ArchDescription>(&json).unwrap(), description);
+    }
 
-        self.linear2.forward(x) // [batch_size, num_classes]
+    #[test]
+    fn batch_norm_layers_take_the_configured_momentum_and_epsilon() {
+        let config = ModelConfig::new(10, 32).with_batch_norm(true).with_bn_momentum(0.25).with_bn_epsilon(1e-3);
+        let configs = config.batch_norm_configs().unwrap();
+        assert_eq!(configs.each_ref().map(|norm| norm.num_features), [8, 16]);
+        assert!(configs.iter().all(|norm| norm.momentum == 0.25 && norm.epsilon == 1e-3));
+        assert!(ModelConfig::new(10, 32).batch_norm_configs().is_none());
+
+        // One training pass moves the running mean, from 0, a momentum of the way to the batch mean
+        let device = NdArrayDevice::default();
+        let model = config.init::<Autodiff<NdArray>>(&device);
+        let images = Tensor::<Autodiff<NdArray>, 3>::random([4, 28, 28], burn::tensor::Distribution::Default, &device);
+        let activations = model.activations(images);
+        let conv1 = activations.iter().find(|(name, _)| name == "conv1").unwrap().1.clone().inner();
+        let batch_mean = conv1.swap_dims(0, 1).flatten::<2>(1, 3).mean_dim(1).flatten::<1>(0, 1);
+        let running_mean = model.conv1_norm.unwrap().into_record().running_mean.val().inner();
+        let expected = batch_mean.into_data().value.iter().map(|mean| mean * 0.25).collect::<Vec<f32>>();
+        for (running, expected) in running_mean.into_data().value.iter().zip(&expected) {
+            assert!((running - expected).abs() < 1e-5, "running mean {running}, expected {expected}");
+        }
+    }
+
+    #[test]
+    fn batch_norm_adds_a_normalized_layer_after_each_conv() {
+        let config = ModelConfig::new(10, 32).with_batch_norm(true);
+        let device = NdArrayDevice::default();
+        let model = config.init::<NdArray>(&device);
+        let description = config.describe();
+
+        let names: Vec<&str> = description.layers.iter().map(|layer| layer.name.as_str()).collect();
+        assert_eq!(names[..6], ["conv1", "conv1.norm", "conv1.dropout", "conv2", "conv2.norm", "conv2.dropout"]);
+        assert_eq!(description.layers[1].layer, LayerKind::BatchNorm { num_features: 8, momentum: 0.1, epsilon: 1e-5 });
+        // A scale, a shift, a running mean and a running variance per channel
+        let num_params: usize = description.layers.iter().map(|layer| layer.layer.num_params()).sum();
+        assert_eq!(num_params, 34378 + 4 * (8 + 16));
+        assert_eq!(model.num_params(), num_params);
+        assert!(model.has_batch_norm() && !ModelConfig::new(10, 32).init::<NdArray>(&device).has_batch_norm());
+
+        let activations: Vec<String> = model.activations(Tensor::zeros([2, 28, 28], &device)).into_iter().map(|(name, _)| name).collect();
+        let layers: Vec<String> = description
+            .layers
+            .into_iter()
+            .filter(|layer| !matches!(layer.layer, LayerKind::Dropout { .. }))
+            .map(|layer| layer.name)
+            .collect();
+        assert_eq!(activations, layers);
+    }
+
+    #[test]
+    fn global_pool_models_take_any_image_size() {
+        let device = NdArrayDevice::default();
+        for global_pool in [GlobalPool::Avg, GlobalPool::Max] {
+            let model = ModelConfig::new(10, 32).with_global_pool(global_pool).init::<NdArray>(&device);
+            for side in [28, 32] {
+                let output = model.forward(Tensor::<NdArray, 3>::ones([2, side, side], &device));
+                assert_eq!(output.dims(), [2, 10]);
+                assert!(output.into_data().value.iter().all(|value| value.is_finite()));
+            }
+        }
+    }
+
+    #[test]
+    fn portable_init_draws_the_same_weights_on_every_backend() {
+        let device = NdArrayDevice::default();
+        let config = ModelConfig::new(10, 8);
+        let plain = named_params(&config.init_portable::<NdArray>(7, &device));
+        let autodiff = named_params(&config.init_portable::<Autodiff<NdArray>>(7, &device));
+        assert_eq!(plain, autodiff);
+        assert_ne!(plain, named_params(&config.init_portable::<NdArray>(8, &device)));
+
+        // The shapes of `init`, each value within burn's default bound of 1/sqrt(fan in)
+        let shapes = |params: Vec<NamedParam>| params.into_iter().map(|param| param.shape).collect::<Vec<_>>();
+        assert_eq!(shapes(plain.clone()), shapes(named_params(&config.init::<NdArray>(&device))));
+        for param in &plain {
+            let fan_in = match param.name.as_str() {
+                "conv1.weight" | "conv1.bias" => 9,
+                "conv2.weight" | "conv2.bias" => 8 * 9,
+                "linear1.weight" | "linear1.bias" => 16 * 8 * 8,
+                _ => 8,
+            };
+            let bound = 1.0 / (fan_in as f32).sqrt();
+            assert!(param.values.iter().all(|value| value.abs() < bound), "{} is out of bounds", param.name);
+        }
+    }
+
+    // Another backend with its own random generator and tensor storage: the same config and
+    // seed give the weights of NdArray there too, the aux head and the flattened input included
+    #[cfg(feature = "candle")]
+    #[test]
+    fn portable_init_draws_the_weights_of_ndarray_on_candle() {
+        use burn::backend::candle::{Candle, CandleDevice};
+
+        let config = ModelConfig::new(10, 8).with_head_input(HeadInput::Flatten).with_aux_task(Some(AuxTask::Parity));
+        let ndarray = named_params(&config.init_portable::<NdArray>(7, &NdArrayDevice::default()));
+        let candle = named_params(&config.init_portable::<Candle>(7, &CandleDevice::Cpu));
+        assert_eq!(ndarray, candle);
+    }
+
+    #[test]
+    fn both_head_inputs_classify_every_image_size_they_take() {
+        let device = NdArrayDevice::default();
+        let flatten = ModelConfig::new(10, 8).with_head_input(HeadInput::Flatten).init::<NdArray>(&device);
+        let pooled = ModelConfig::new(10, 8).init::<NdArray>(&device);
+
+        assert_eq!(flatten.forward(Tensor::zeros([3, 28, 28], &device)).dims(), [3, 10]);
+        for side in [28, 40] {
+            assert_eq!(pooled.forward(Tensor::zeros([3, side, side], &device)).dims(), [3, 10]);
+        }
+        // The raw 16x24x24 conv maps against the 16x8x8 pooled ones
+        assert_eq!(flatten.linear1.weight.dims(), [16 * 24 * 24, 8]);
+        assert_eq!(pooled.linear1.weight.dims(), [16 * 8 * 8, 8]);
+    }
+
+    #[test]
+    fn head_input_defaults_to_the_previous_pooling_and_is_saved() {
+        let json = serde_json::to_value(ModelConfig::new(10, 8)).unwrap();
+        assert_eq!(json["head_input"], serde_json::json!({ "AdaptiveAvgPool": { "size": 8 } }));
+
+        let flatten = ModelConfig::new(10, 8).with_head_input(HeadInput::Flatten);
+        let config: ModelConfig = serde_json::from_str(&serde_json::to_string(&flatten).unwrap()).unwrap();
+        assert_eq!(config.head_input, HeadInput::Flatten);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,135 @@
+use crate::{
+    artifact::ArtifactDir,
+    history::{EpochMetrics, History},
+    training::TrainingConfig,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs, io,
+    process::Command,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+// Version of the `model_card.json` layout
+pub const MODEL_CARD_VERSION: u32 = 1;
+
+pub const MODEL_CARD_FILE: &str = "model_card.json";
+
+/// How a saved model was produced, written as `model_card.json` next to its weights at the end
+/// of training.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ModelCard {
+    pub format_version: u32,
+    pub config: TrainingConfig,
+    // The `DatasetSource` trained on, or the type of the dataset passed in
+    pub dataset: String,
+    // End of training, RFC 3339 in UTC
+    pub trained_at: String,
+    // `HEAD` of the git checkout training ran in, `None` outside of one or without git
+    pub git_commit: Option<String>,
+    pub num_params: usize,
+    // Metrics of the last epoch, `None` when no epoch was logged
+    pub final_metrics: Option<EpochMetrics>,
+    // Best epoch mean of the validation accuracy, in percent. `None` for multi-label models.
+    pub best_valid_accuracy: Option<f64>,
+}
+
+impl ModelCard {
+    pub fn new(config: &TrainingConfig, dataset: String, num_params: usize, history: &History) -> Self {
+        Self {
+            format_version: MODEL_CARD_VERSION,
+            config: config.clone(),
+            dataset,
+            trained_at: utc_timestamp(SystemTime::now()),
+            git_commit: git_commit(),
+            num_params,
+            final_metrics: history.epochs.last().cloned(),
+            best_valid_accuracy: history.best_valid("Accuracy"),
+        }
+    }
+
+    pub fn save(&self, artifact_dir: &str) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(self).expect("Model card should serialize to JSON");
+        fs::write(ArtifactDir::new(artifact_dir).model_card_path(), json)
+    }
+
+    pub fn load(artifact_dir: &str) -> io::Result<Self> {
+        let json = fs::read_to_string(ArtifactDir::new(artifact_dir).model_card_path())?;
+        serde_json::from_str(&json).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+}
+
+// The commit checked out in the working directory
+fn git_commit() -> Option<String> {
+    let output = Command::new("git").args(["rev-parse", "HEAD"]).output().ok()?;
+    let commit = String::from_utf8(output.stdout).ok()?.trim().to_string();
+    (output.status.success() && !commit.is_empty()).then_some(commit)
+}
+
+// `time` as `2024-05-17T08:30:00Z`, to the second
+fn utc_timestamp(time: SystemTime) -> String {
+    let seconds = time.duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs());
+    let (days, seconds) = (seconds / 86_400, seconds % 86_400);
+    let (year, month, day) = civil_date(days as i64);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+// Year, month and day of the `days`-th day since 1970-01-01, in the proleptic Gregorian calendar
+// (Howard Hinnant's `civil_from_days`)
+pub(crate) fn civil_date(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{data::DatasetSource, synthetic::tiny_config, training::train};
+    use burn::{
+        backend::{ndarray::NdArrayDevice, Autodiff, NdArray},
+        module::Module,
+    };
+    use std::time::Duration;
+
+    #[test]
+    fn timestamps_are_utc_calendar_dates() {
+        assert_eq!(utc_timestamp(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        assert_eq!(utc_timestamp(UNIX_EPOCH + Duration::from_secs(1_715_934_600)), "2024-05-17T08:30:00Z");
+        assert_eq!(civil_date(11_016), (2000, 2, 29));
+        assert_eq!(civil_date(-1), (1969, 12, 31));
+    }
+
+    #[test]
+    fn trained_model_has_a_card_with_its_config_and_last_epoch() {
+        let artifact_dir = std::env::temp_dir().join("my_first_rust_DL_app-model-card");
+        let dir = artifact_dir.to_str().unwrap();
+        let config = tiny_config()
+            .with_dataset(DatasetSource::Synthetic { num_samples: 32, seed: 1 })
+            .with_num_epochs(2);
+
+        let model = train::<Autodiff<NdArray>>(dir, config, NdArrayDevice::default()).unwrap();
+        let card = ModelCard::load(dir).unwrap();
+        std::fs::remove_dir_all(&artifact_dir).unwrap();
+
+        assert_eq!(card.format_version, MODEL_CARD_VERSION);
+        assert_eq!((card.config.num_epochs, card.config.model.hidden_size), (2, 8));
+        assert!(card.dataset.starts_with("Synthetic"), "{}", card.dataset);
+        assert_eq!(card.num_params, model.num_params());
+        assert_eq!(card.final_metrics.unwrap().epoch, 2);
+        assert!(card.best_valid_accuracy.is_some_and(|accuracy| (0.0..=100.0).contains(&accuracy)));
+        assert_eq!(card.trained_at.len(), "1970-01-01T00:00:00Z".len());
+    }
+}
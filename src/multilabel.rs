@@ -0,0 +1,313 @@
+use crate::{
+    data::MnistBatcher,
+    metrics::{global_grad_norm, GradNormInput},
+    model::Model,
+    profile,
+    training::Reduction,
+};
+use burn::{
+    data::{dataloader::batcher::Batcher, dataset::Dataset},
+    prelude::*,
+    tensor::{activation::log_sigmoid, backend::AutodiffBackend},
+    train::{
+        metric::{
+            state::{FormatOptions, NumericMetricState},
+            Adaptor, LossInput, Metric, MetricEntry, MetricMetadata, Numeric,
+        },
+        TrainOutput, TrainStep, ValidStep,
+    },
+};
+
+/// A grayscale image dataset where every image carries any number of labels, for
+/// [`train_multilabel_on`](crate::training::train_multilabel_on).
+pub trait MultiLabelDataset: Send + Sync {
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Row-major pixels of item `index` on the MNIST scale ([0, 255], `height * width` values)
+    /// and its labels, each in `0..num_classes()`. An image may have no label at all.
+    fn get(&self, index: usize) -> Option<(Vec<f32>, Vec<usize>)>;
+
+    fn num_classes(&self) -> usize;
+
+    /// `[height, width]` of every image.
+    fn image_shape(&self) -> [usize; 2];
+}
+
+// One image of a multi-label dataset, its labels as a multi-hot row of `num_classes` values
+#[derive(Clone, Debug)]
+pub struct MultiLabelItem {
+    pub pixels: Vec<f32>,
+    pub shape: [usize; 2],
+    pub targets: Vec<f32>,
+}
+
+// A multi-label dataset seen as a burn dataset, with multi-hot rows as wide as the model head
+pub(crate) struct MultiLabelItems<D> {
+    dataset: D,
+    num_classes: usize,
+}
+
+impl<D> MultiLabelItems<D> {
+    pub(crate) fn new(dataset: D, num_classes: usize) -> Self {
+        Self { dataset, num_classes }
+    }
+}
+
+impl<D: MultiLabelDataset> Dataset<MultiLabelItem> for MultiLabelItems<D> {
+    fn get(&self, index: usize) -> Option<MultiLabelItem> {
+        let (pixels, labels) = self.dataset.get(index)?;
+        let mut targets = vec![0.0; self.num_classes];
+        for label in labels {
+            targets[label] = 1.0;
+        }
+        Some(MultiLabelItem { pixels, shape: self.dataset.image_shape(), targets })
+    }
+
+    fn len(&self) -> usize {
+        self.dataset.len()
+    }
+}
+
+/// A batch of normalized images `[batch_size, height, width]` and their multi-hot labels
+/// `[batch_size, num_classes]`.
+#[derive(Clone, Debug)]
+pub struct MultiLabelBatch<B: Backend> {
+    pub images: Tensor<B, 3>,
+    pub targets: Tensor<B, 2>,
+    /// How the steps reduce the losses of the batch, that of the training config in a run.
+    pub reduction: Reduction,
+}
+
+// Same image normalization as the single-label batches. Mixup is not applied.
+impl<B: Backend> Batcher<MultiLabelItem, MultiLabelBatch<B>> for MnistBatcher<B> {
+    fn batch(&self, items: Vec<MultiLabelItem>) -> MultiLabelBatch<B> {
+        let images = self.images(items.iter().map(|item| (&item.pixels, item.shape)));
+
+        let num_classes = items.first().map_or(0, |item| item.targets.len());
+        let targets: Vec<f32> = items.iter().flat_map(|item| item.targets.iter().copied()).collect();
+        let targets = Data::new(targets, Shape::new([items.len(), num_classes]));
+        let targets = Tensor::from_data(targets.convert(), self.device());
+
+        MultiLabelBatch { images, targets, reduction: self.loss().reduction }
+    }
+}
+
+// Output of a multi-label step: the loss, the logits `[batch_size, num_classes]` and the
+// multi-hot targets they are scored against
+pub struct MultiLabelOutput<B: Backend> {
+    pub loss: Tensor<B, 1>,
+    pub output: Tensor<B, 2>,
+    pub targets: Tensor<B, 2>,
+}
+
+impl<B: Backend> Adaptor<LossInput<B>> for MultiLabelOutput<B> {
+    fn adapt(&self) -> LossInput<B> {
+        LossInput::new(self.loss.clone())
+    }
+}
+
+impl<B: Backend> Adaptor<MultiLabelF1Input<B>> for MultiLabelOutput<B> {
+    fn adapt(&self) -> MultiLabelF1Input<B> {
+        MultiLabelF1Input::new(self.output.clone(), self.targets.clone())
+    }
+}
+
+impl<B: Backend> Model<B> {
+    // Binary cross-entropy of each logit against its target, computed from the logits through
+    // log-sigmoid so that large logits do not overflow. The mean reduction averages over every
+    // label of every image, the sum adds them all up.
+    pub fn forward_multilabel(
+        &self,
+        images: Tensor<B, 3>,
+        targets: Tensor<B, 2>,
+        reduction: Reduction,
+    ) -> MultiLabelOutput<B> {
+        let output = self.forward(images);
+
+        let losses = (targets.clone() * log_sigmoid(output.clone())
+            + (targets.ones_like() - targets.clone()) * log_sigmoid(output.clone().neg()))
+        .neg();
+        let loss = match reduction {
+            Reduction::Mean => losses.mean(),
+            Reduction::Sum => losses.sum(),
+        };
+
+        MultiLabelOutput { loss, output, targets }
+    }
+}
+
+// Output of a multi-label training step, with the gradient norm like the single-label one
+pub struct MultiLabelTrainOutput<B: AutodiffBackend> {
+    pub output: MultiLabelOutput<B>,
+    pub grad_norm: Tensor<B::InnerBackend, 1>,
+}
+
+impl<B: AutodiffBackend> Adaptor<LossInput<B>> for MultiLabelTrainOutput<B> {
+    fn adapt(&self) -> LossInput<B> {
+        self.output.adapt()
+    }
+}
+
+impl<B: AutodiffBackend> Adaptor<MultiLabelF1Input<B>> for MultiLabelTrainOutput<B> {
+    fn adapt(&self) -> MultiLabelF1Input<B> {
+        self.output.adapt()
+    }
+}
+
+impl<B: AutodiffBackend> Adaptor<GradNormInput<B::InnerBackend>> for MultiLabelTrainOutput<B> {
+    fn adapt(&self) -> GradNormInput<B::InnerBackend> {
+        GradNormInput::new(self.grad_norm.clone())
+    }
+}
+
+impl<B: AutodiffBackend> TrainStep<MultiLabelBatch<B>, MultiLabelTrainOutput<B>> for Model<B> {
+    fn step(&self, batch: MultiLabelBatch<B>) -> TrainOutput<MultiLabelTrainOutput<B>> {
+        let output = profile::step_span("forward", || self.forward_multilabel(batch.images, batch.targets, batch.reduction));
+        let gradients = profile::step_span("backward", || output.loss.backward());
+        let grad_norm = global_grad_norm(self, &gradients, &output.loss.device());
+
+        TrainOutput::new(self, gradients, MultiLabelTrainOutput { output, grad_norm })
+    }
+}
+
+impl<B: Backend> ValidStep<MultiLabelBatch<B>, MultiLabelOutput<B>> for Model<B> {
+    fn step(&self, batch: MultiLabelBatch<B>) -> MultiLabelOutput<B> {
+        self.forward_multilabel(batch.images, batch.targets, batch.reduction)
+    }
+}
+
+// F1 score of every label over a batch, in percent: a label is predicted when the sigmoid of its
+// logit reaches `threshold`. `None` for the labels neither the targets nor the predictions have,
+// which leave nothing to score. `logits` and `targets` are row-major `[batch_size, num_classes]`.
+pub fn per_label_f1(logits: &[f32], targets: &[f32], num_classes: usize, threshold: f64) -> Vec<Option<f64>> {
+    let mut counts = vec![(0usize, 0usize, 0usize); num_classes]; // true pos, false pos, false neg
+    for (logits, targets) in logits.chunks(num_classes).zip(targets.chunks(num_classes)) {
+        for ((&logit, &target), (true_pos, false_pos, false_neg)) in
+            logits.iter().zip(targets).zip(&mut counts)
+        {
+            let predicted = 1.0 / (1.0 + (-logit as f64).exp()) >= threshold;
+            match (predicted, target >= 0.5) {
+                (true, true) => *true_pos += 1,
+                (true, false) => *false_pos += 1,
+                (false, true) => *false_neg += 1,
+                (false, false) => {}
+            }
+        }
+    }
+
+    counts
+        .into_iter()
+        .map(|(true_pos, false_pos, false_neg)| {
+            let scored = true_pos + false_pos + false_neg;
+            (scored > 0).then(|| 2.0 * true_pos as f64 / (2 * true_pos + false_pos + false_neg) as f64 * 100.0)
+        })
+        .collect()
+}
+
+// Input of the F1 metric: the logits of a step and their multi-hot targets
+pub struct MultiLabelF1Input<B: Backend> {
+    output: Tensor<B, 2>,
+    targets: Tensor<B, 2>,
+}
+
+impl<B: Backend> MultiLabelF1Input<B> {
+    pub fn new(output: Tensor<B, 2>, targets: Tensor<B, 2>) -> Self {
+        Self { output, targets }
+    }
+}
+
+// Macro F1 of the multi-label head, the mean of the per-label F1 scores of each batch. Like
+// burn's accuracy, the epoch value is the batch-size weighted mean of the batch values.
+pub struct MultiLabelF1Metric<B: Backend> {
+    state: NumericMetricState,
+    threshold: f64,
+    _b: B,
+}
+
+impl<B: Backend> MultiLabelF1Metric<B> {
+    pub fn new(threshold: f64) -> Self {
+        Self { state: NumericMetricState::default(), threshold, _b: B::default() }
+    }
+}
+
+impl<B: Backend> Metric for MultiLabelF1Metric<B> {
+    const NAME: &'static str = "F1 Score";
+
+    type Input = MultiLabelF1Input<B>;
+
+    fn update(&mut self, input: &Self::Input, _metadata: &MetricMetadata) -> MetricEntry {
+        let [batch_size, num_classes] = input.output.dims();
+        let logits = input.output.clone().into_data().convert::<f32>().value;
+        let targets = input.targets.clone().into_data().convert::<f32>().value;
+
+        let scores: Vec<f64> = per_label_f1(&logits, &targets, num_classes, self.threshold)
+            .into_iter()
+            .flatten()
+            .collect();
+        // A batch without any positive label nor prediction is scored perfectly
+        let f1 = if scores.is_empty() { 100.0 } else { scores.iter().sum::<f64>() / scores.len() as f64 };
+
+        self.state.update(f1, batch_size, FormatOptions::new(Self::NAME).unit("%").precision(2))
+    }
+
+    fn clear(&mut self) {
+        self.state.reset()
+    }
+}
+
+impl<B: Backend> Numeric for MultiLabelF1Metric<B> {
+    fn value(&self) -> f64 {
+        self.state.value()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::ModelConfig;
+    use burn::backend::{ndarray::NdArrayDevice, NdArray};
+
+    #[test]
+    fn per_label_f1_scores_each_label_of_two_hot_targets() {
+        // Two images of labels {0, 1} and {1, 2}, the first predicted {0, 2}, the second {1, 2}
+        let logits = [4.0, -4.0, 4.0, -4.0, -4.0, 4.0, 4.0, -4.0];
+        let targets = [1.0, 1.0, 0.0, 0.0, 0.0, 1.0, 1.0, 0.0];
+
+        let scores = per_label_f1(&logits, &targets, 4, 0.5);
+        // Label 0 hit once, label 1 hit once and missed once, label 2 hit once with one false
+        // positive, label 3 neither a target nor a prediction
+        assert_eq!(scores[0], Some(100.0));
+        assert!((scores[1].unwrap() - 200.0 / 3.0).abs() < 1e-9);
+        assert!((scores[2].unwrap() - 200.0 / 3.0).abs() < 1e-9);
+        assert_eq!(scores[3], None);
+
+        // Nothing reaches a threshold above every sigmoid
+        let scores = per_label_f1(&logits, &targets, 4, 0.99);
+        assert_eq!(scores, [Some(0.0), Some(0.0), Some(0.0), None]);
+    }
+
+    #[test]
+    fn multilabel_loss_is_the_mean_binary_cross_entropy() {
+        let device = NdArrayDevice::default();
+        let model = ModelConfig::new(3, 8).init::<NdArray>(&device);
+        let images = Tensor::<NdArray, 3>::ones([2, 28, 28], &device);
+        let targets = Tensor::<NdArray, 2>::from_floats([[1.0, 1.0, 0.0], [0.0, 1.0, 1.0]], &device);
+
+        let output = model.forward_multilabel(images, targets, Reduction::Mean);
+        let logits = output.output.into_data().value;
+        let expected: f32 = logits
+            .iter()
+            .zip([1.0, 1.0, 0.0, 0.0, 1.0, 1.0])
+            .map(|(&logit, target)| {
+                let probability = 1.0 / (1.0 + (-logit).exp());
+                -(target * probability.ln() + (1.0 - target) * (1.0 - probability).ln())
+            })
+            .sum::<f32>()
+            / 6.0;
+        assert!((output.loss.into_scalar() - expected).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,341 @@
+use crate::{artifact::ArtifactDir, data::MnistBatch, model::Model};
+use burn::{
+    data::dataloader::{DataLoader, DataLoaderIterator, Progress},
+    module::{ModuleVisitor, ParamId},
+    optim::{GradientsParams, Optimizer},
+    prelude::*,
+    record::{CompactRecorder, Record, Recorder},
+    tensor::backend::AutodiffBackend,
+    train::TrainingInterrupter,
+    LearningRate,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+/// What `TrainingConfig::nan_guard` does with the first step whose gradients are not finite.
+#[derive(Config, Debug, Copy, PartialEq)]
+pub enum NanAction {
+    // Skip the step and stop training with `TrainError::NonFinite`
+    Halt,
+    // Skip the step, restore the weights and optimizer state of the latest epoch checkpoint (the
+    // ones the run started from during its first epoch), halve the learning rate and carry on.
+    // After `max_rollbacks` rollbacks, the next non-finite step halts.
+    Rollback { max_rollbacks: usize },
+}
+
+/// A training step [`NanGuardOptimizer`] did not apply, as logged to `nan_guard.csv` and
+/// recorded in `model_meta.json`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NanEvent {
+    // 1-based, counted across epochs
+    pub step: usize,
+    // 1-based
+    pub epoch: usize,
+    // Training set indices of the items of the batch, when they carry them
+    pub batch_indices: Option<Vec<usize>>,
+    // Global L2 norm of the gradients of the step: `None` when it is not finite, above
+    // `nan_max_grad_norm` otherwise
+    pub grad_norm: Option<f64>,
+    // Epoch of the checkpoint the run rolled back to, 0 for its starting weights, `None` when
+    // the run halted
+    pub rolled_back_to: Option<usize>,
+    // What the scheduled learning rate is multiplied by from the next step on
+    pub lr_scale: f64,
+}
+
+impl fmt::Display for NanEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.grad_norm {
+            Some(norm) => write!(f, "Gradient norm {norm:.3e} above `nan_max_grad_norm`")?,
+            None => write!(f, "Non-finite gradients")?,
+        }
+        write!(f, " at step {} (epoch {})", self.step, self.epoch)?;
+        match &self.batch_indices {
+            Some(indices) => write!(f, ", batch of training items {indices:?}")?,
+            None => write!(f, ", batch of unknown items")?,
+        }
+        match self.rolled_back_to {
+            Some(0) => write!(f, ": rolled back to the starting weights")?,
+            Some(epoch) => write!(f, ": rolled back to the checkpoint of epoch {epoch}")?,
+            None => return write!(f, ": training halted"),
+        }
+        write!(f, ", learning rate now x{}", self.lr_scale)
+    }
+}
+
+// What the guard shares with the training loader and the run: the items of the batch being
+// trained, and the events so far. The interrupter is the learner's, which only exists once the
+// loader is built.
+#[derive(Default)]
+struct GuardState {
+    batch_indices: Option<Vec<usize>>,
+    events: Vec<NanEvent>,
+    interrupter: Option<TrainingInterrupter>,
+}
+
+/// Hands the learner's interrupter to a [`NanGuardOptimizer`], wraps the training loader so the
+/// guard knows the items of each step, and reads the [`NanEvent`]s of the run once training is
+/// over.
+#[derive(Clone, Default)]
+pub struct NanGuardTracker {
+    state: Arc<Mutex<GuardState>>,
+}
+
+impl NanGuardTracker {
+    pub fn watch(&self, interrupter: TrainingInterrupter) {
+        self.state.lock().unwrap().interrupter = Some(interrupter);
+    }
+
+    pub fn batches<B: Backend>(&self, inner: Box<dyn DataLoader<MnistBatch<B>>>) -> NanGuardDataLoader<B> {
+        NanGuardDataLoader { inner, state: self.state.clone() }
+    }
+
+    pub fn events(&self) -> Vec<NanEvent> {
+        self.state.lock().unwrap().events.clone()
+    }
+
+    // The event that halted the run, if one did
+    pub fn halt(&self) -> Option<NanEvent> {
+        self.state.lock().unwrap().events.last().filter(|event| event.rolled_back_to.is_none()).cloned()
+    }
+}
+
+// Records the dataset indices of every batch the training loader yields, the one the learner
+// steps on next
+pub struct NanGuardDataLoader<B: Backend> {
+    inner: Box<dyn DataLoader<MnistBatch<B>>>,
+    state: Arc<Mutex<GuardState>>,
+}
+
+struct NanGuardIterator<'a, B: Backend> {
+    inner: Box<dyn DataLoaderIterator<MnistBatch<B>> + 'a>,
+    state: &'a Mutex<GuardState>,
+}
+
+impl<B: Backend> DataLoader<MnistBatch<B>> for NanGuardDataLoader<B> {
+    fn iter<'a>(&'a self) -> Box<dyn DataLoaderIterator<MnistBatch<B>> + 'a> {
+        Box::new(NanGuardIterator { inner: self.inner.iter(), state: &self.state })
+    }
+
+    fn num_items(&self) -> usize {
+        self.inner.num_items()
+    }
+}
+
+impl<B: Backend> Iterator for NanGuardIterator<'_, B> {
+    type Item = MnistBatch<B>;
+
+    fn next(&mut self) -> Option<MnistBatch<B>> {
+        let batch = self.inner.next()?;
+        self.state.lock().unwrap().batch_indices = batch.indices.clone();
+        Some(batch)
+    }
+}
+
+impl<B: Backend> DataLoaderIterator<MnistBatch<B>> for NanGuardIterator<'_, B> {
+    fn progress(&self) -> Progress {
+        self.inner.progress()
+    }
+}
+
+// Sums the squared gradient of every float parameter of the module the visitor walks over
+struct SumSquaresVisitor<'a, B: AutodiffBackend> {
+    grads: &'a GradientsParams,
+    sum_squares: Option<Tensor<B::InnerBackend, 1>>,
+}
+
+impl<B: AutodiffBackend> ModuleVisitor<B> for SumSquaresVisitor<'_, B> {
+    fn visit_float<const D: usize>(&mut self, id: &ParamId, _tensor: &Tensor<B, D>) {
+        let Some(grad) = self.grads.get::<B::InnerBackend, D>(id) else {
+            return;
+        };
+        let squares = grad.powf_scalar(2.0).sum();
+        self.sum_squares = Some(match self.sum_squares.take() {
+            Some(sum) => sum + squares,
+            None => squares,
+        });
+    }
+}
+
+// The settings and log of an enabled guard
+pub struct NanGuard {
+    action: NanAction,
+    max_grad_norm: Option<f64>,
+    dir: ArtifactDir,
+    steps_per_epoch: usize,
+    tracker: NanGuardTracker,
+    log: File,
+}
+
+impl NanGuard {
+    // A guard of the run in `artifact_dir`, `steps_per_epoch` steps per epoch, logging to
+    // `nan_guard.csv` as `step,epoch,grad_norm,rolled_back_to,lr_scale` (empty for `None`)
+    pub fn new(
+        artifact_dir: &str,
+        action: NanAction,
+        max_grad_norm: Option<f64>,
+        steps_per_epoch: usize,
+    ) -> io::Result<(Self, NanGuardTracker)> {
+        let mut log = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(format!("{artifact_dir}/nan_guard.csv"))?;
+        writeln!(log, "step,epoch,grad_norm,rolled_back_to,lr_scale")?;
+        let tracker = NanGuardTracker::default();
+        let dir = ArtifactDir::new(artifact_dir);
+        let steps_per_epoch = steps_per_epoch.max(1);
+        Ok((Self { action, max_grad_norm, dir, steps_per_epoch, tracker: tracker.clone(), log }, tracker))
+    }
+
+    // `None` when the gradients of the step are fine, the norm the event records otherwise
+    fn check<B: AutodiffBackend>(&self, module: &Model<B>, grads: &GradientsParams) -> Option<Option<f64>> {
+        let mut visitor = SumSquaresVisitor::<B> { grads, sum_squares: None };
+        module.visit(&mut visitor);
+        let norm = visitor.sum_squares.map_or(0.0, |sum| sum.sqrt().into_scalar().elem::<f64>());
+        match norm.is_finite() {
+            false => Some(None),
+            true => self.max_grad_norm.filter(|&max| norm > max).map(|_| Some(norm)),
+        }
+    }
+
+    // The latest epoch with both checkpoint files, which may still be in the learner's write
+    // queue: those that do not load are passed over for older ones
+    fn load_checkpoint<B: AutodiffBackend, R: Record<B>>(&self, module: &Model<B>) -> Option<(usize, Model<B>, R)> {
+        let mut epochs: Vec<usize> = std::fs::read_dir(self.dir.checkpoint_dir())
+            .ok()?
+            .filter_map(|entry| {
+                let name = entry.ok()?.file_name().into_string().ok()?;
+                name.strip_prefix("model-")?.strip_suffix(".mpk")?.parse().ok()
+            })
+            .collect();
+        epochs.sort_unstable_by(|a, b| b.cmp(a));
+        let device = module.devices().into_iter().next().expect("The model should live on a device");
+        let recorder = CompactRecorder::new();
+        epochs.into_iter().find_map(|epoch| {
+            let optimizer_path = self.dir.optimizer_checkpoint_path(epoch);
+            if !Path::new(&format!("{optimizer_path}.mpk")).exists() {
+                return None;
+            }
+            let model = recorder.load(self.dir.checkpoint_path(epoch).into(), &device).ok()?;
+            let record = recorder.load(optimizer_path.into(), &device).ok()?;
+            Some((epoch, module.clone().load_record(model), record))
+        })
+    }
+
+    fn record(&mut self, event: NanEvent) {
+        // Like the learner logs, a failed write does not stop training
+        let optional = |value: Option<String>| value.unwrap_or_default();
+        writeln!(
+            self.log,
+            "{},{},{},{},{}",
+            event.step,
+            event.epoch,
+            optional(event.grad_norm.map(|norm| norm.to_string())),
+            optional(event.rolled_back_to.map(|epoch| epoch.to_string())),
+            event.lr_scale
+        )
+        .ok();
+        let mut state = self.tracker.state.lock().unwrap();
+        if event.rolled_back_to.is_none() {
+            if let Some(interrupter) = &state.interrupter {
+                interrupter.stop();
+            }
+        }
+        state.events.push(event);
+    }
+}
+
+/// Checks the gradients of every step before the wrapped optimizer applies them: a NaN or
+/// infinite loss makes them non-finite, as does a diverging model. A step that fails the check
+/// is not applied, and the run halts or rolls back as its [`NanAction`] says. Checking syncs the
+/// device once per step, for the norm.
+///
+/// Placed outside the optimizers whose state a rollback restores, and inside those that count
+/// steps, which must still count a skipped one.
+pub struct NanGuardOptimizer<O: Optimizer<Model<B>, B>, B: AutodiffBackend> {
+    // Only `None` while a rollback loads the checkpointed state into it
+    inner: Option<O>,
+    guard: Option<NanGuard>,
+    // With rollbacks, the weights and optimizer state before the first step
+    start: Option<(Model<B>, O::Record)>,
+    step: usize,
+    lr_scale: f64,
+}
+
+impl<O: Optimizer<Model<B>, B>, B: AutodiffBackend> NanGuardOptimizer<O, B> {
+    pub fn new(inner: O, guard: Option<NanGuard>) -> Self {
+        Self { inner: Some(inner), guard, start: None, step: 0, lr_scale: 1.0 }
+    }
+
+    fn inner(&mut self) -> &mut O {
+        self.inner.as_mut().expect("The inner optimizer is only taken during a rollback")
+    }
+}
+
+impl<O, B> Optimizer<Model<B>, B> for NanGuardOptimizer<O, B>
+where
+    B: AutodiffBackend,
+    O: Optimizer<Model<B>, B>,
+{
+    type Record = O::Record;
+
+    fn step(&mut self, lr: LearningRate, module: Model<B>, grads: GradientsParams) -> Model<B> {
+        let Some(guard) = &self.guard else {
+            return self.inner().step(lr, module, grads);
+        };
+        self.step += 1;
+        let rollback = match guard.action {
+            NanAction::Rollback { max_rollbacks } => Some(max_rollbacks),
+            NanAction::Halt => None,
+        };
+        if rollback.is_some() && self.start.is_none() {
+            // Cloning a module only bumps tensor reference counts
+            self.start = Some((module.clone(), self.inner.as_ref().unwrap().to_record()));
+        }
+        let Some(grad_norm) = guard.check(&module, &grads) else {
+            let lr = lr * self.lr_scale;
+            return self.inner().step(lr, module, grads);
+        };
+
+        let epoch = (self.step - 1) / guard.steps_per_epoch + 1;
+        let batch_indices = guard.tracker.state.lock().unwrap().batch_indices.clone();
+        let rollbacks = guard.tracker.events().len();
+        let mut event = NanEvent { step: self.step, epoch, batch_indices, grad_norm, rolled_back_to: None, lr_scale: self.lr_scale };
+        let module = match rollback.filter(|&max_rollbacks| rollbacks < max_rollbacks) {
+            Some(_) => {
+                // The starting state is taken back on the next step
+                let (to, module, record) = match guard.load_checkpoint(&module) {
+                    Some(checkpoint) => checkpoint,
+                    None => {
+                        let (model, record) = self.start.take().expect("The starting state is kept with rollbacks");
+                        (0, model, record)
+                    }
+                };
+                self.inner = self.inner.take().map(|inner| inner.load_record(record));
+                self.lr_scale *= 0.5;
+                event.rolled_back_to = Some(to);
+                event.lr_scale = self.lr_scale;
+                module
+            }
+            None => module,
+        };
+        self.guard.as_mut().unwrap().record(event);
+        module
+    }
+
+    fn to_record(&self) -> Self::Record {
+        self.inner.as_ref().expect("The inner optimizer is only taken during a rollback").to_record()
+    }
+
+    fn load_record(self, record: Self::Record) -> Self {
+        let inner = self.inner.map(|inner| inner.load_record(record));
+        Self { inner, ..self }
+    }
+}
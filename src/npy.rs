@@ -0,0 +1,218 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+// Total size of the header written by `NpyWriter`: magic, version, length and the padded dict.
+// Fixed so that the final row count can be patched in place, whatever its number of digits.
+const HEADER_LEN: usize = 128;
+
+// Element types that can be stored in an `.npy` file, little-endian
+pub trait NpyElement: Copy {
+    // NumPy dtype string, e.g. `<f4`
+    const DESCR: &'static str;
+
+    fn write_le(self, out: &mut impl Write) -> io::Result<()>;
+
+    fn read_le(input: &mut impl Read) -> io::Result<Self>;
+}
+
+impl NpyElement for f32 {
+    const DESCR: &'static str = "<f4";
+
+    fn write_le(self, out: &mut impl Write) -> io::Result<()> {
+        out.write_all(&self.to_le_bytes())
+    }
+
+    fn read_le(input: &mut impl Read) -> io::Result<Self> {
+        let mut bytes = [0; 4];
+        input.read_exact(&mut bytes)?;
+        Ok(Self::from_le_bytes(bytes))
+    }
+}
+
+impl NpyElement for i64 {
+    const DESCR: &'static str = "<i8";
+
+    fn write_le(self, out: &mut impl Write) -> io::Result<()> {
+        out.write_all(&self.to_le_bytes())
+    }
+
+    fn read_le(input: &mut impl Read) -> io::Result<Self> {
+        let mut bytes = [0; 8];
+        input.read_exact(&mut bytes)?;
+        Ok(Self::from_le_bytes(bytes))
+    }
+}
+
+// Version 1.0 header of an array of `num_rows` rows of shape `row_shape`
+fn header<T: NpyElement>(num_rows: usize, row_shape: &[usize]) -> Vec<u8> {
+    let dims: Vec<String> = std::iter::once(num_rows)
+        .chain(row_shape.iter().copied())
+        .map(|dim| dim.to_string())
+        .collect();
+    // A 1-d shape needs the trailing comma to be a tuple
+    let shape = if dims.len() == 1 { format!("({},)", dims[0]) } else { format!("({})", dims.join(", ")) };
+    let dict = format!("{{'descr': '{}', 'fortran_order': False, 'shape': {shape}, }}", T::DESCR);
+
+    // magic (6) + version (2) + header length (2), then the dict padded with spaces to a newline
+    let dict_len = HEADER_LEN - 10;
+    let mut bytes = b"\x93NUMPY\x01\x00".to_vec();
+    bytes.extend_from_slice(&(dict_len as u16).to_le_bytes());
+    bytes.extend_from_slice(format!("{dict:<width$}\n", width = dict_len - 1).as_bytes());
+    bytes
+}
+
+// Writes a C-ordered `.npy` array row by row, so that it never has to be held in memory. The
+// row count in the header is a placeholder until `finish` patches it.
+pub struct NpyWriter<T: NpyElement> {
+    file: BufWriter<File>,
+    row_shape: Vec<usize>,
+    row_len: usize,
+    num_values: usize,
+    _element: std::marker::PhantomData<T>,
+}
+
+impl<T: NpyElement> NpyWriter<T> {
+    // `row_shape` is the shape of one row, empty for a 1-d array
+    pub fn create(path: impl AsRef<Path>, row_shape: &[usize]) -> io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(&header::<T>(0, row_shape))?;
+        Ok(Self {
+            file,
+            row_shape: row_shape.to_vec(),
+            row_len: row_shape.iter().product(),
+            num_values: 0,
+            _element: std::marker::PhantomData,
+        })
+    }
+
+    // Appends whole rows, flattened in C order
+    pub fn write(&mut self, values: &[T]) -> io::Result<()> {
+        if !values.len().is_multiple_of(self.row_len.max(1)) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} values do not make whole rows of {}", values.len(), self.row_len),
+            ));
+        }
+        for &value in values {
+            value.write_le(&mut self.file)?;
+        }
+        self.num_values += values.len();
+        Ok(())
+    }
+
+    // Writes the final row count into the header and flushes the file
+    pub fn finish(mut self) -> io::Result<()> {
+        let num_rows = self.num_values / self.row_len.max(1);
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(&header::<T>(num_rows, &self.row_shape))?;
+        self.file.flush()
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// Reads a C-ordered version 1.0 `.npy` array (as `NpyWriter` writes them) row by row, so that it
+// never has to be held in memory either
+pub struct NpyReader<T: NpyElement> {
+    file: BufReader<File>,
+    shape: Vec<usize>,
+    row_len: usize,
+    rows_left: usize,
+    _element: std::marker::PhantomData<T>,
+}
+
+impl<T: NpyElement> NpyReader<T> {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut file = BufReader::new(File::open(path)?);
+        let mut preamble = [0; 10];
+        file.read_exact(&mut preamble)?;
+        if &preamble[..8] != b"\x93NUMPY\x01\x00" {
+            return Err(invalid("not a version 1.0 .npy file".to_string()));
+        }
+        let mut dict = vec![0; u16::from_le_bytes([preamble[8], preamble[9]]) as usize];
+        file.read_exact(&mut dict)?;
+        let dict = String::from_utf8_lossy(&dict);
+
+        if !dict.contains(&format!("'descr': '{}'", T::DESCR)) || !dict.contains("'fortran_order': False") {
+            return Err(invalid(format!("expected a C-ordered {} array, found {}", T::DESCR, dict.trim())));
+        }
+        let shape = dict
+            .split_once("'shape': (")
+            .and_then(|(_, rest)| rest.split_once(')'))
+            .map(|(dims, _)| dims.split(',').map(str::trim).filter(|dim| !dim.is_empty()).map(str::parse).collect())
+            .and_then(Result::ok)
+            .filter(|shape: &Vec<usize>| !shape.is_empty())
+            .ok_or_else(|| invalid(format!("no array shape in {}", dict.trim())))?;
+
+        Ok(Self {
+            file,
+            row_len: shape[1..].iter().product(),
+            rows_left: shape[0],
+            shape,
+            _element: std::marker::PhantomData,
+        })
+    }
+
+    // Shape of the whole array, the number of rows first
+    pub fn shape(&self) -> &[usize] {
+        &self.shape
+    }
+
+    // The next row, flattened in C order, or `None` after the last one
+    pub fn read_row(&mut self) -> io::Result<Option<Vec<T>>> {
+        if self.rows_left == 0 {
+            return Ok(None);
+        }
+        self.rows_left -= 1;
+        (0..self.row_len).map(|_| T::read_le(&mut self.file)).collect::<io::Result<_>>().map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn written_arrays_parse_back_with_their_header() {
+        let dir = std::env::temp_dir().join("my_first_rust_DL_app-npy");
+        std::fs::create_dir_all(&dir).unwrap();
+        let (logits, targets) = (dir.join("logits.npy"), dir.join("targets.npy"));
+
+        // Written a batch at a time, the row count patched in at the end
+        let mut writer = NpyWriter::<f32>::create(&logits, &[2]).unwrap();
+        writer.write(&[0.5, -1.0, 2.25, 3.0]).unwrap();
+        writer.write(&[-0.125, 7.0]).unwrap();
+        assert_eq!(writer.write(&[1.0]).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        writer.finish().unwrap();
+        let mut writer = NpyWriter::<i64>::create(&targets, &[]).unwrap();
+        writer.write(&[3, 1, 4]).unwrap();
+        writer.finish().unwrap();
+
+        let bytes = std::fs::read(&logits).unwrap();
+        assert_eq!(&bytes[..8], b"\x93NUMPY\x01\x00");
+        assert_eq!(u16::from_le_bytes([bytes[8], bytes[9]]) as usize + 10, HEADER_LEN);
+        let dict = String::from_utf8_lossy(&bytes[10..HEADER_LEN]);
+        assert!(dict.starts_with("{'descr': '<f4', 'fortran_order': False, 'shape': (3, 2), }"), "{dict}");
+        assert!(dict.ends_with(" \n"));
+        assert_eq!(bytes.len(), HEADER_LEN + 6 * 4);
+        assert_eq!(&bytes[HEADER_LEN..HEADER_LEN + 4], 0.5f32.to_le_bytes());
+
+        let mut reader = NpyReader::<f32>::open(&logits).unwrap();
+        assert_eq!(reader.shape(), [3, 2]);
+        assert_eq!(reader.read_row().unwrap(), Some(vec![0.5, -1.0]));
+        assert_eq!(reader.read_row().unwrap(), Some(vec![2.25, 3.0]));
+        assert_eq!(reader.read_row().unwrap(), Some(vec![-0.125, 7.0]));
+        assert_eq!(reader.read_row().unwrap(), None);
+        let mut reader = NpyReader::<i64>::open(&targets).unwrap();
+        assert_eq!(reader.shape(), [3]);
+        assert_eq!(reader.read_row().unwrap(), Some(vec![3]));
+        // Reading with the wrong element type is an error, not garbage
+        assert!(NpyReader::<f32>::open(&targets).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,1603 @@
+use crate::{
+    artifact::ArtifactDir,
+    evaluation::{evaluate_model, EvalError, EvalReport, EvaluationConfig},
+    labels::ClassLabels,
+    model::{Classifier, GlobalPool, HeadInput, Model},
+};
+use burn::{
+    module::AutodiffModule,
+    optim::{GradientsParams, Optimizer},
+    tensor::backend::AutodiffBackend,
+    LearningRate,
+};
+use burn::{
+    prelude::*,
+    tensor::{
+        activation::{log_softmax, relu, sigmoid, softmax, tanh},
+        module::{adaptive_avg_pool2d, avg_pool2d, conv2d, max_pool2d},
+        ops::ConvOptions,
+    },
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, fs, io,
+    path::Path,
+};
+
+// Operators of the default domain `OnnxModel` runs, enough for the MNIST CNNs and MLPs that
+// PyTorch and Keras export
+pub const SUPPORTED_OPERATORS: [&str; 22] = [
+    "Add",
+    "AveragePool",
+    "BatchNormalization",
+    "Constant",
+    "Conv",
+    "Div",
+    "Dropout",
+    "Flatten",
+    "Gemm",
+    "GlobalAveragePool",
+    "Identity",
+    "LogSoftmax",
+    "MatMul",
+    "MaxPool",
+    "Mul",
+    "Relu",
+    "Reshape",
+    "Sigmoid",
+    "Softmax",
+    "Sub",
+    "Tanh",
+    "Transpose",
+];
+
+// First opset whose Softmax and LogSoftmax normalize along one axis, instead of over the input
+// flattened to 2D at it
+const SOFTMAX_AXIS_OPSET: i64 = 13;
+
+#[derive(Debug)]
+pub enum OnnxError {
+    Io(io::Error),
+    // The file is not a well-formed ONNX protobuf, or holds tensors this importer cannot read
+    Decode(String),
+    // The graph has no usable input or output
+    Graph(String),
+    Unsupported { op: String, node: String },
+    // A supported operator with attributes or inputs out of what the importer handles
+    Invalid { node: String, reason: String },
+    // A model `export_onnx` cannot write as an ONNX graph
+    Export(String),
+}
+
+impl fmt::Display for OnnxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OnnxError::Io(err) => write!(f, "could not read the ONNX model: {err}"),
+            OnnxError::Decode(reason) => write!(f, "malformed ONNX model: {reason}"),
+            OnnxError::Graph(reason) => write!(f, "unusable ONNX graph: {reason}"),
+            OnnxError::Unsupported { op, node } => {
+                write!(f, "unsupported ONNX operator {op} at node {node}, supported: {}", SUPPORTED_OPERATORS.join(", "))
+            }
+            OnnxError::Invalid { node, reason } => write!(f, "ONNX node {node}: {reason}"),
+            OnnxError::Export(reason) => write!(f, "cannot export the model to ONNX: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for OnnxError {}
+
+impl From<io::Error> for OnnxError {
+    fn from(err: io::Error) -> Self {
+        OnnxError::Io(err)
+    }
+}
+
+fn decode_error(reason: impl Into<String>) -> OnnxError {
+    OnnxError::Decode(reason.into())
+}
+
+// A protobuf field value, by wire type
+enum Wire<'a> {
+    Varint(u64),
+    Fixed64(u64),
+    Bytes(&'a [u8]),
+    Fixed32(u32),
+}
+
+fn read_varint(bytes: &mut &[u8]) -> Result<u64, OnnxError> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = bytes.split_first().ok_or_else(|| decode_error("truncated varint"))?;
+        *bytes = rest;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(decode_error("varint longer than 10 bytes"))
+}
+
+fn take<'a>(bytes: &mut &'a [u8], len: usize) -> Result<&'a [u8], OnnxError> {
+    if bytes.len() < len {
+        return Err(decode_error(format!("field of {len} bytes with {} left", bytes.len())));
+    }
+    let (field, rest) = bytes.split_at(len);
+    *bytes = rest;
+    Ok(field)
+}
+
+// Hands every field of the message in `bytes` to `f`, with its field number
+fn for_each_field<'a>(
+    mut bytes: &'a [u8],
+    mut f: impl FnMut(u32, Wire<'a>) -> Result<(), OnnxError>,
+) -> Result<(), OnnxError> {
+    while !bytes.is_empty() {
+        let key = read_varint(&mut bytes)?;
+        let wire = match key & 7 {
+            0 => Wire::Varint(read_varint(&mut bytes)?),
+            1 => Wire::Fixed64(u64::from_le_bytes(take(&mut bytes, 8)?.try_into().expect("8 bytes"))),
+            2 => {
+                let len = read_varint(&mut bytes)? as usize;
+                Wire::Bytes(take(&mut bytes, len)?)
+            }
+            5 => Wire::Fixed32(u32::from_le_bytes(take(&mut bytes, 4)?.try_into().expect("4 bytes"))),
+            other => return Err(decode_error(format!("unsupported wire type {other}"))),
+        };
+        f((key >> 3) as u32, wire)?;
+    }
+    Ok(())
+}
+
+fn wire_error(expected: &str) -> OnnxError {
+    decode_error(format!("expected {expected} field"))
+}
+
+fn bytes_field(wire: Wire<'_>) -> Result<&[u8], OnnxError> {
+    match wire {
+        Wire::Bytes(bytes) => Ok(bytes),
+        _ => Err(wire_error("a length-delimited")),
+    }
+}
+
+fn string_field(wire: Wire<'_>) -> Result<String, OnnxError> {
+    Ok(String::from_utf8_lossy(bytes_field(wire)?).into_owned())
+}
+
+fn int_field(wire: Wire<'_>) -> Result<i64, OnnxError> {
+    match wire {
+        Wire::Varint(value) => Ok(value as i64),
+        _ => Err(wire_error("a varint")),
+    }
+}
+
+// A repeated integer field, packed or not
+fn push_ints(wire: Wire<'_>, values: &mut Vec<i64>) -> Result<(), OnnxError> {
+    match wire {
+        Wire::Varint(value) => values.push(value as i64),
+        Wire::Bytes(mut bytes) => {
+            while !bytes.is_empty() {
+                values.push(read_varint(&mut bytes)? as i64);
+            }
+        }
+        _ => return Err(wire_error("an integer")),
+    }
+    Ok(())
+}
+
+// A repeated float field, packed or not
+fn push_floats(wire: Wire<'_>, values: &mut Vec<f32>) -> Result<(), OnnxError> {
+    match wire {
+        Wire::Fixed32(bits) => values.push(f32::from_bits(bits)),
+        Wire::Bytes(bytes) if bytes.len() % 4 == 0 => {
+            values.extend(bytes.chunks_exact(4).map(|chunk| f32::from_le_bytes(chunk.try_into().expect("4 bytes"))))
+        }
+        _ => return Err(wire_error("a float")),
+    }
+    Ok(())
+}
+
+// A repeated double field, packed or not, narrowed to f32
+fn push_doubles(wire: Wire<'_>, values: &mut Vec<f32>) -> Result<(), OnnxError> {
+    match wire {
+        Wire::Fixed64(bits) => values.push(f64::from_bits(bits) as f32),
+        Wire::Bytes(bytes) if bytes.len() % 8 == 0 => values
+            .extend(bytes.chunks_exact(8).map(|chunk| f64::from_le_bytes(chunk.try_into().expect("8 bytes")) as f32)),
+        _ => return Err(wire_error("a double")),
+    }
+    Ok(())
+}
+
+// Element types of `TensorProto::data_type` this importer reads
+const FLOAT: i64 = 1;
+const INT32: i64 = 6;
+const INT64: i64 = 7;
+const DOUBLE: i64 = 11;
+
+// An initializer or constant, row-major. Integer tensors only serve as Reshape shapes.
+#[derive(Debug, Clone)]
+enum TensorData {
+    Float { dims: Vec<usize>, values: Vec<f32> },
+    Int { dims: Vec<usize>, values: Vec<i64> },
+}
+
+// A `TensorProto`, with its name
+fn parse_tensor(bytes: &[u8]) -> Result<(String, TensorData), OnnxError> {
+    let (mut name, mut dims, mut data_type, mut external) = (String::new(), Vec::new(), 0, false);
+    let (mut floats, mut ints, mut raw) = (Vec::new(), Vec::new(), None);
+    for_each_field(bytes, |field, wire| {
+        match field {
+            1 => push_ints(wire, &mut dims)?,
+            2 => data_type = int_field(wire)?,
+            4 => push_floats(wire, &mut floats)?,
+            5 | 7 => push_ints(wire, &mut ints)?,
+            8 => name = string_field(wire)?,
+            9 => raw = Some(bytes_field(wire)?),
+            10 => push_doubles(wire, &mut floats)?,
+            // `data_location`, 1 being EXTERNAL
+            14 => external = int_field(wire)? == 1,
+            _ => {}
+        }
+        Ok(())
+    })?;
+    if external {
+        return Err(decode_error(format!("tensor {name} is stored in an external file, save the model with its data embedded")));
+    }
+    let dims: Vec<usize> = dims
+        .into_iter()
+        .map(|dim| usize::try_from(dim).map_err(|_| decode_error(format!("tensor {name} has dimension {dim}"))))
+        .collect::<Result<_, _>>()?;
+
+    let raw_values = |width: usize| -> Vec<&[u8]> { raw.map_or(Vec::new(), |raw| raw.chunks_exact(width).collect()) };
+    let data = match data_type {
+        FLOAT if raw.is_some() => TensorData::Float {
+            dims,
+            values: raw_values(4).into_iter().map(|chunk| f32::from_le_bytes(chunk.try_into().expect("4 bytes"))).collect(),
+        },
+        DOUBLE if raw.is_some() => TensorData::Float {
+            dims,
+            values: raw_values(8).into_iter().map(|chunk| f64::from_le_bytes(chunk.try_into().expect("8 bytes")) as f32).collect(),
+        },
+        FLOAT | DOUBLE => TensorData::Float { dims, values: floats },
+        INT32 if raw.is_some() => TensorData::Int {
+            dims,
+            values: raw_values(4).into_iter().map(|chunk| i32::from_le_bytes(chunk.try_into().expect("4 bytes")) as i64).collect(),
+        },
+        INT64 if raw.is_some() => TensorData::Int {
+            dims,
+            values: raw_values(8).into_iter().map(|chunk| i64::from_le_bytes(chunk.try_into().expect("8 bytes"))).collect(),
+        },
+        // int32_data holds the values as varints, sign-extended like int64_data
+        INT32 | INT64 => TensorData::Int { dims, values: ints },
+        other => {
+            return Err(decode_error(format!(
+                "tensor {name} has data type {other}, only float, double, int32 and int64 tensors are read"
+            )))
+        }
+    };
+
+    let (dims, len) = match &data {
+        TensorData::Float { dims, values } => (dims, values.len()),
+        TensorData::Int { dims, values } => (dims, values.len()),
+    };
+    if dims.iter().product::<usize>() != len {
+        return Err(decode_error(format!("tensor {name} of shape {dims:?} holds {len} values")));
+    }
+    Ok((name, data))
+}
+
+// The fields of an `AttributeProto` the supported operators use
+#[derive(Debug, Default)]
+struct Attribute {
+    float: Option<f32>,
+    int: Option<i64>,
+    ints: Vec<i64>,
+    string: Option<String>,
+    tensor: Option<TensorData>,
+}
+
+fn parse_attribute(bytes: &[u8]) -> Result<(String, Attribute), OnnxError> {
+    let (mut name, mut attribute) = (String::new(), Attribute::default());
+    for_each_field(bytes, |field, wire| {
+        match field {
+            1 => name = string_field(wire)?,
+            2 => match wire {
+                Wire::Fixed32(bits) => attribute.float = Some(f32::from_bits(bits)),
+                _ => return Err(wire_error("a float")),
+            },
+            3 => attribute.int = Some(int_field(wire)?),
+            4 => attribute.string = Some(string_field(wire)?),
+            5 => attribute.tensor = Some(parse_tensor(bytes_field(wire)?)?.1),
+            8 => push_ints(wire, &mut attribute.ints)?,
+            _ => {}
+        }
+        Ok(())
+    })?;
+    Ok((name, attribute))
+}
+
+#[derive(Debug, Default)]
+struct Node {
+    name: String,
+    op_type: String,
+    domain: String,
+    inputs: Vec<String>,
+    outputs: Vec<String>,
+    attributes: HashMap<String, Attribute>,
+}
+
+fn parse_node(bytes: &[u8]) -> Result<Node, OnnxError> {
+    let mut node = Node::default();
+    for_each_field(bytes, |field, wire| {
+        match field {
+            1 => node.inputs.push(string_field(wire)?),
+            2 => node.outputs.push(string_field(wire)?),
+            3 => node.name = string_field(wire)?,
+            4 => node.op_type = string_field(wire)?,
+            5 => {
+                let (name, attribute) = parse_attribute(bytes_field(wire)?)?;
+                node.attributes.insert(name, attribute);
+            }
+            7 => node.domain = string_field(wire)?,
+            _ => {}
+        }
+        Ok(())
+    })?;
+    Ok(node)
+}
+
+// A `ValueInfoProto`: the name of a graph input or output, with its rank when its type declares
+// a shape
+fn parse_value_info(bytes: &[u8]) -> Result<(String, Option<usize>), OnnxError> {
+    let (mut name, mut rank) = (String::new(), None);
+    for_each_field(bytes, |field, wire| {
+        match field {
+            1 => name = string_field(wire)?,
+            // TypeProto.tensor_type, then TypeProto.Tensor.shape, then its repeated dim
+            2 => for_each_field(bytes_field(wire)?, |field, wire| match field {
+                1 => for_each_field(bytes_field(wire)?, |field, wire| match field {
+                    2 => {
+                        let mut dims = 0;
+                        for_each_field(bytes_field(wire)?, |field, _| {
+                            dims += (field == 1) as usize;
+                            Ok(())
+                        })?;
+                        rank = Some(dims);
+                        Ok(())
+                    }
+                    _ => Ok(()),
+                }),
+                _ => Ok(()),
+            })?,
+            _ => {}
+        }
+        Ok(())
+    })?;
+    Ok((name, rank))
+}
+
+#[derive(Debug, Default)]
+struct Graph {
+    nodes: Vec<Node>,
+    initializers: Vec<(String, TensorData)>,
+    inputs: Vec<(String, Option<usize>)>,
+    outputs: Vec<(String, Option<usize>)>,
+}
+
+fn parse_graph(bytes: &[u8]) -> Result<Graph, OnnxError> {
+    let mut graph = Graph::default();
+    for_each_field(bytes, |field, wire| {
+        match field {
+            1 => graph.nodes.push(parse_node(bytes_field(wire)?)?),
+            5 => graph.initializers.push(parse_tensor(bytes_field(wire)?)?),
+            11 => graph.inputs.push(parse_value_info(bytes_field(wire)?)?),
+            12 => graph.outputs.push(parse_value_info(bytes_field(wire)?)?),
+            _ => {}
+        }
+        Ok(())
+    })?;
+    Ok(graph)
+}
+
+// A `ModelProto`: its graph, and the version of the default operator set it was exported with
+fn parse_model(bytes: &[u8]) -> Result<(Graph, i64), OnnxError> {
+    let (mut graph, mut opset) = (None, None);
+    for_each_field(bytes, |field, wire| {
+        match field {
+            7 => graph = Some(parse_graph(bytes_field(wire)?)?),
+            8 => {
+                let (mut domain, mut version) = (String::new(), 0);
+                for_each_field(bytes_field(wire)?, |field, wire| {
+                    match field {
+                        1 => domain = string_field(wire)?,
+                        2 => version = int_field(wire)?,
+                        _ => {}
+                    }
+                    Ok(())
+                })?;
+                if domain.is_empty() || domain == "ai.onnx" {
+                    opset = Some(version);
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    })?;
+    let graph = graph.ok_or_else(|| decode_error("no graph"))?;
+    Ok((graph, opset.ok_or_else(|| decode_error("no opset import for the default domain"))?))
+}
+
+// A tensor during the forward pass. Burn tensors have a static rank, so every value is held as
+// rank 4, its `rank` dimensions right-aligned behind 1s: numpy broadcasting, which ONNX follows,
+// pads shapes the same way.
+#[derive(Debug, Clone)]
+struct Value<B: Backend> {
+    tensor: Tensor<B, 4>,
+    rank: usize,
+}
+
+fn padded(dims: &[usize]) -> [usize; 4] {
+    let mut shape = [1; 4];
+    shape[4 - dims.len()..].copy_from_slice(dims);
+    shape
+}
+
+impl<B: Backend> Value<B> {
+    fn reshaped(tensor: Tensor<B, 4>, dims: &[usize]) -> Self {
+        assert!(dims.len() <= 4, "ONNX values of rank {} are not supported, at most 4", dims.len());
+        Self { tensor: tensor.reshape(padded(dims)), rank: dims.len() }
+    }
+
+    fn dims(&self) -> Vec<usize> {
+        self.tensor.dims()[4 - self.rank..].to_vec()
+    }
+
+    fn matrix(self, op: &str) -> Tensor<B, 2> {
+        assert_eq!(self.rank, 2, "{op} takes 2D inputs, got a tensor of shape {:?}", self.dims());
+        let [_, _, rows, columns] = self.tensor.dims();
+        self.tensor.reshape([rows, columns])
+    }
+
+    fn image(self, op: &str) -> Tensor<B, 4> {
+        assert_eq!(self.rank, 4, "{op} takes [batch, channels, height, width] inputs, got shape {:?}", self.dims());
+        self.tensor
+    }
+
+    // Physical dimension of the logical `axis`, negative counting from the end
+    fn axis(&self, axis: i64) -> usize {
+        let rank = self.rank as i64;
+        assert!(-rank <= axis && axis < rank.max(1), "axis {axis} is out of a tensor of rank {rank}");
+        (axis.rem_euclid(rank.max(1)) + 4 - rank) as usize
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Op {
+    Conv { strides: [usize; 2], pads: [usize; 2], dilations: [usize; 2], groups: usize },
+    MaxPool { kernel: [usize; 2], strides: [usize; 2], pads: [usize; 2], dilations: [usize; 2] },
+    AveragePool { kernel: [usize; 2], strides: [usize; 2], pads: [usize; 2], count_include_pad: bool },
+    GlobalAveragePool,
+    // Inference mode, with the running statistics of the inputs
+    BatchNormalization { epsilon: f32 },
+    Gemm { alpha: f32, beta: f32, trans_a: bool, trans_b: bool },
+    MatMul,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Relu,
+    Sigmoid,
+    Tanh,
+    Flatten { axis: i64 },
+    Reshape { shape: Vec<i64> },
+    // Permutation of the logical dimensions
+    Transpose { perm: Vec<usize> },
+    Identity,
+    // `coerce_2d` normalizes over the input flattened to 2D at `axis`, as before opset 13
+    Softmax { axis: i64, coerce_2d: bool, log: bool },
+}
+
+// One node of the graph, checked and with its attributes resolved
+#[derive(Debug, Clone)]
+struct Step {
+    op: Op,
+    // Empty names of omitted optional inputs are `None`
+    inputs: Vec<Option<String>>,
+    output: String,
+}
+
+impl Op {
+    fn apply<B: Backend>(&self, mut inputs: Vec<Option<Value<B>>>) -> Value<B> {
+        inputs.resize(5, None);
+        let mut inputs = inputs.into_iter();
+        let mut input = || inputs.next().flatten();
+        let mut x = input().expect("Required inputs are checked at load");
+        match self {
+            Op::Conv { strides, pads, dilations, groups } => {
+                let weight = input().expect("Conv has a weight").image("Conv");
+                let bias = input().map(|bias| {
+                    let len: usize = bias.dims().iter().product();
+                    bias.tensor.reshape([len])
+                });
+                let options = ConvOptions::new(*strides, *pads, *dilations, *groups);
+                Value { tensor: conv2d(x.image("Conv"), weight, bias, options), rank: 4 }
+            }
+            Op::MaxPool { kernel, strides, pads, dilations } => {
+                Value { tensor: max_pool2d(x.image("MaxPool"), *kernel, *strides, *pads, *dilations), rank: 4 }
+            }
+            Op::AveragePool { kernel, strides, pads, count_include_pad } => Value {
+                tensor: avg_pool2d(x.image("AveragePool"), *kernel, *strides, *pads, *count_include_pad),
+                rank: 4,
+            },
+            Op::GlobalAveragePool => Value { tensor: adaptive_avg_pool2d(x.image("GlobalAveragePool"), [1, 1]), rank: 4 },
+            Op::BatchNormalization { epsilon } => {
+                assert!(x.rank >= 2, "BatchNormalization takes [batch, channels, ...] inputs, got shape {:?}", x.dims());
+                // Per channel vectors, along the channels of `x`
+                let mut shape = [1; 4];
+                shape[x.axis(1)] = x.dims()[1];
+                let mut channel = || input().expect("BatchNormalization has 5 inputs").tensor.reshape(shape);
+                let (scale, bias, mean, var) = (channel(), channel(), channel(), channel());
+                let tensor = (x.tensor - mean) / var.add_scalar(*epsilon).sqrt() * scale + bias;
+                Value { tensor, rank: x.rank }
+            }
+            Op::Gemm { alpha, beta, trans_a, trans_b } => {
+                let a = x.matrix("Gemm");
+                let b = input().expect("Gemm has a B input").matrix("Gemm");
+                let a = if *trans_a { a.transpose() } else { a };
+                let b = if *trans_b { b.transpose() } else { b };
+                let product = a.matmul(b).mul_scalar(*alpha);
+                let [rows, columns] = product.dims();
+                let mut tensor = product.reshape([1, 1, rows, columns]);
+                if let Some(c) = input() {
+                    tensor = tensor + c.tensor.mul_scalar(*beta);
+                }
+                Value { tensor, rank: 2 }
+            }
+            Op::MatMul => {
+                let b = input().expect("MatMul has two inputs");
+                assert!(x.rank >= 2 && b.rank >= 2, "MatMul of 1D tensors is not supported");
+                Value { rank: x.rank.max(b.rank), tensor: x.tensor.matmul(b.tensor) }
+            }
+            Op::Add | Op::Sub | Op::Mul | Op::Div => {
+                let b = input().expect("Binary operators have two inputs");
+                let rank = x.rank.max(b.rank);
+                let tensor = match self {
+                    Op::Add => x.tensor + b.tensor,
+                    Op::Sub => x.tensor - b.tensor,
+                    Op::Mul => x.tensor * b.tensor,
+                    _ => x.tensor / b.tensor,
+                };
+                Value { tensor, rank }
+            }
+            Op::Relu => Value { tensor: relu(x.tensor), rank: x.rank },
+            Op::Sigmoid => Value { tensor: sigmoid(x.tensor), rank: x.rank },
+            Op::Tanh => Value { tensor: tanh(x.tensor), rank: x.rank },
+            Op::Flatten { axis } => {
+                let dims = x.dims();
+                let axis = if *axis < 0 { axis + dims.len() as i64 } else { *axis } as usize;
+                let (outer, inner) = dims.split_at(axis.min(dims.len()));
+                let shape = [outer.iter().product(), inner.iter().product()];
+                Value::reshaped(x.tensor, &shape)
+            }
+            Op::Reshape { shape } => {
+                let dims = x.dims();
+                let known: usize = shape
+                    .iter()
+                    .enumerate()
+                    .map(|(i, &dim)| match dim {
+                        0 => dims[i],
+                        -1 => 1,
+                        dim => dim as usize,
+                    })
+                    .product();
+                let total: usize = dims.iter().product();
+                let shape: Vec<usize> = shape
+                    .iter()
+                    .enumerate()
+                    .map(|(i, &dim)| match dim {
+                        0 => dims[i],
+                        -1 => total / known.max(1),
+                        dim => dim as usize,
+                    })
+                    .collect();
+                Value::reshaped(x.tensor, &shape)
+            }
+            Op::Transpose { perm } => {
+                assert_eq!(perm.len(), x.rank, "Transpose permutation {perm:?} of a tensor of rank {}", x.rank);
+                let offset = 4 - x.rank;
+                let mut physical = [0, 1, 2, 3];
+                for (to, &from) in perm.iter().enumerate() {
+                    physical[offset + to] = (offset + from) as isize;
+                }
+                x.tensor = x.tensor.permute(physical);
+                x
+            }
+            Op::Identity => x,
+            Op::Softmax { axis, coerce_2d, log } => {
+                let normalize = |tensor: Tensor<B, 4>, dim: usize| match log {
+                    true => log_softmax(tensor, dim),
+                    false => softmax(tensor, dim),
+                };
+                if !coerce_2d {
+                    let dim = x.axis(*axis);
+                    return Value { tensor: normalize(x.tensor, dim), rank: x.rank };
+                }
+                let (dims, physical) = (x.dims(), x.tensor.dims());
+                let axis = x.axis(*axis);
+                let [outer, inner] = [physical[..axis].iter().product(), physical[axis..].iter().product()];
+                let tensor = normalize(x.tensor.reshape([1, 1, outer, inner]), 3);
+                Value::reshaped(tensor, &dims)
+            }
+        }
+    }
+}
+
+/// A classifier imported from an ONNX file, run by interpreting its graph on any burn backend.
+/// It sees the images normalized the way [`crate::MnistBatcher`] does, as a graph input of
+/// shape `[batch, 784]`, `[batch, 28, 28]` or `[batch, 1, 28, 28]` after its declared rank, and
+/// its first graph output must be the `[batch, num_classes]` logits.
+///
+/// Only the operators of [`SUPPORTED_OPERATORS`] are run, on float tensors of rank up to 4.
+/// Everything else is rejected at load.
+#[derive(Debug, Clone)]
+pub struct OnnxModel<B: Backend> {
+    // Float initializers and constants, by value name
+    constants: HashMap<String, Value<B>>,
+    steps: Vec<Step>,
+    input: String,
+    input_rank: usize,
+    output: String,
+}
+
+// Reads the `[usize; 2]` of a 2D op attribute, `default` for each when absent
+fn pair(node: &Node, label: &str, name: &str, default: usize) -> Result<[usize; 2], OnnxError> {
+    let Some(attribute) = node.attributes.get(name) else {
+        return Ok([default; 2]);
+    };
+    match attribute.ints[..] {
+        [a, b] if a >= 0 && b >= 0 => Ok([a as usize, b as usize]),
+        _ => Err(invalid(label, format!("{name} {:?}, only 2D operators are supported", attribute.ints))),
+    }
+}
+
+fn invalid(node: &str, reason: impl Into<String>) -> OnnxError {
+    OnnxError::Invalid { node: node.to_string(), reason: reason.into() }
+}
+
+fn int_attribute(node: &Node, name: &str, default: i64) -> i64 {
+    node.attributes.get(name).and_then(|attribute| attribute.int).unwrap_or(default)
+}
+
+fn float_attribute(node: &Node, name: &str, default: f32) -> f32 {
+    node.attributes.get(name).and_then(|attribute| attribute.float).unwrap_or(default)
+}
+
+// Symmetric padding of a 2D op, as burn pads: ONNX lists `[top, left, bottom, right]`
+fn pads(node: &Node, label: &str) -> Result<[usize; 2], OnnxError> {
+    match node.attributes.get("auto_pad").and_then(|attribute| attribute.string.as_deref()) {
+        None | Some("NOTSET") => {}
+        Some("VALID") => return Ok([0, 0]),
+        Some(other) => return Err(invalid(label, format!("auto_pad {other} is not supported, only NOTSET and VALID"))),
+    }
+    let Some(attribute) = node.attributes.get("pads") else {
+        return Ok([0, 0]);
+    };
+    match attribute.ints[..] {
+        [top, left, bottom, right] if top == bottom && left == right && top >= 0 && left >= 0 => {
+            Ok([top as usize, left as usize])
+        }
+        _ => Err(invalid(label, format!("pads {:?}, only symmetric 2D padding is supported", attribute.ints))),
+    }
+}
+
+fn no_ceil_mode(node: &Node, label: &str) -> Result<(), OnnxError> {
+    match int_attribute(node, "ceil_mode", 0) {
+        0 => Ok(()),
+        _ => Err(invalid(label, "ceil_mode is not supported")),
+    }
+}
+
+// Resolves the operator of `node`, reading the shape of a Reshape from `int_constants`
+fn compile_op(node: &Node, label: &str, opset: i64, int_constants: &HashMap<String, Vec<i64>>) -> Result<Op, OnnxError> {
+    let op = match node.op_type.as_str() {
+        "Conv" => Op::Conv {
+            strides: pair(node, label, "strides", 1)?,
+            pads: pads(node, label)?,
+            dilations: pair(node, label, "dilations", 1)?,
+            groups: int_attribute(node, "group", 1).max(1) as usize,
+        },
+        "MaxPool" => {
+            no_ceil_mode(node, label)?;
+            if node.outputs.len() > 1 && !node.outputs[1].is_empty() {
+                return Err(invalid(label, "the Indices output of MaxPool is not supported"));
+            }
+            Op::MaxPool {
+                kernel: pair(node, label, "kernel_shape", 1)?,
+                strides: pair(node, label, "strides", 1)?,
+                pads: pads(node, label)?,
+                dilations: pair(node, label, "dilations", 1)?,
+            }
+        }
+        "AveragePool" => {
+            no_ceil_mode(node, label)?;
+            Op::AveragePool {
+                kernel: pair(node, label, "kernel_shape", 1)?,
+                strides: pair(node, label, "strides", 1)?,
+                pads: pads(node, label)?,
+                count_include_pad: int_attribute(node, "count_include_pad", 0) != 0,
+            }
+        }
+        "GlobalAveragePool" => Op::GlobalAveragePool,
+        "BatchNormalization" => {
+            let statistics = node.outputs.iter().skip(1).any(|name| !name.is_empty());
+            if int_attribute(node, "training_mode", 0) != 0 || statistics {
+                return Err(invalid(label, "only inference mode, without the running statistics outputs, is supported"));
+            }
+            Op::BatchNormalization { epsilon: float_attribute(node, "epsilon", 1e-5) }
+        }
+        "Gemm" => Op::Gemm {
+            alpha: float_attribute(node, "alpha", 1.0),
+            beta: float_attribute(node, "beta", 1.0),
+            trans_a: int_attribute(node, "transA", 0) != 0,
+            trans_b: int_attribute(node, "transB", 0) != 0,
+        },
+        "MatMul" => Op::MatMul,
+        "Add" => Op::Add,
+        "Sub" => Op::Sub,
+        "Mul" => Op::Mul,
+        "Div" => Op::Div,
+        "Relu" => Op::Relu,
+        "Sigmoid" => Op::Sigmoid,
+        "Tanh" => Op::Tanh,
+        "Flatten" => Op::Flatten { axis: int_attribute(node, "axis", 1) },
+        "Reshape" => {
+            if int_attribute(node, "allowzero", 0) != 0 {
+                return Err(invalid(label, "allowzero is not supported"));
+            }
+            let shape = node.inputs.get(1).and_then(|name| int_constants.get(name)).ok_or_else(|| {
+                invalid(label, "the shape of a Reshape must be an int64 initializer or constant")
+            })?;
+            if shape.len() > 4 || shape.iter().filter(|&&dim| dim == -1).count() > 1 || shape.iter().any(|&dim| dim < -1) {
+                return Err(invalid(label, format!("shape {shape:?}, expected at most 4 dimensions and one -1")));
+            }
+            Op::Reshape { shape: shape.clone() }
+        }
+        "Transpose" => {
+            let perm = node.attributes.get("perm").map(|attribute| attribute.ints.clone()).unwrap_or_default();
+            let mut sorted = perm.clone();
+            sorted.sort_unstable();
+            if perm.is_empty() || perm.len() > 4 || sorted != (0..perm.len() as i64).collect::<Vec<_>>() {
+                return Err(invalid(label, format!("perm {perm:?}, expected a permutation of at most 4 dimensions")));
+            }
+            Op::Transpose { perm: perm.into_iter().map(|dim| dim as usize).collect() }
+        }
+        "Identity" | "Dropout" => Op::Identity,
+        "Softmax" | "LogSoftmax" => Op::Softmax {
+            axis: int_attribute(node, "axis", if opset >= SOFTMAX_AXIS_OPSET { -1 } else { 1 }),
+            coerce_2d: opset < SOFTMAX_AXIS_OPSET,
+            log: node.op_type == "LogSoftmax",
+        },
+        op => return Err(OnnxError::Unsupported { op: op.to_string(), node: label.to_string() }),
+    };
+    Ok(op)
+}
+
+// Float inputs each operator reads, required first. A Reshape reads its shape at load.
+fn input_arity(op: &Op) -> (usize, usize) {
+    match op {
+        Op::Conv { .. } | Op::Gemm { .. } => (2, 3),
+        Op::BatchNormalization { .. } => (5, 5),
+        Op::MatMul | Op::Add | Op::Sub | Op::Mul | Op::Div => (2, 2),
+        _ => (1, 1),
+    }
+}
+
+// Stores an initializer or constant on `device`, or with the integer ones when it is one
+fn add_constant<B: Backend>(
+    constants: &mut HashMap<String, Value<B>>,
+    int_constants: &mut HashMap<String, Vec<i64>>,
+    name: String,
+    data: TensorData,
+    device: &B::Device,
+) -> Result<(), OnnxError> {
+    match data {
+        TensorData::Float { dims, values } => {
+            if dims.len() > 4 {
+                return Err(decode_error(format!("tensor {name} has rank {}, at most 4 is supported", dims.len())));
+            }
+            let tensor = Tensor::<B, 1>::from_floats(values.as_slice(), device);
+            constants.insert(name, Value::reshaped(tensor.reshape([1, 1, 1, values.len()]), &dims));
+        }
+        TensorData::Int { values, .. } => {
+            int_constants.insert(name, values);
+        }
+    }
+    Ok(())
+}
+
+impl<B: Backend> OnnxModel<B> {
+    /// Reads the ONNX file at `path` onto `device`.
+    pub fn load(path: impl AsRef<Path>, device: &B::Device) -> Result<Self, OnnxError> {
+        Self::from_bytes(&std::fs::read(path)?, device)
+    }
+
+    /// Reads an ONNX model held in memory onto `device`. Every node is checked here, so that a
+    /// model that loads also runs: an operator out of [`SUPPORTED_OPERATORS`] is an
+    /// [`OnnxError::Unsupported`] naming it and its node.
+    pub fn from_bytes(bytes: &[u8], device: &B::Device) -> Result<Self, OnnxError> {
+        let (graph, opset) = parse_model(bytes)?;
+
+        let mut constants = HashMap::new();
+        let mut int_constants = HashMap::new();
+        for (name, data) in graph.initializers {
+            add_constant(&mut constants, &mut int_constants, name, data, device)?;
+        }
+
+        // Older exporters list the initializers among the graph inputs too
+        let initializers: HashSet<String> = constants.keys().chain(int_constants.keys()).cloned().collect();
+        let (input, input_rank) = graph
+            .inputs
+            .iter()
+            .find(|(name, _)| !initializers.contains(name))
+            .cloned()
+            .ok_or_else(|| OnnxError::Graph("no graph input besides the initializers".to_string()))?;
+        let input_rank = match input_rank {
+            Some(rank @ 2..=4) => rank,
+            Some(rank) => {
+                return Err(OnnxError::Graph(format!(
+                    "input {input} has rank {rank}, expected [batch, pixels], [batch, height, width] or [batch, 1, height, width]"
+                )))
+            }
+            None => return Err(OnnxError::Graph(format!("input {input} declares no shape"))),
+        };
+
+        let mut steps = Vec::new();
+        let mut known: HashSet<String> = HashSet::from([input.clone()]);
+        for (index, node) in graph.nodes.iter().enumerate() {
+            let label = match node.name.is_empty() {
+                true => format!("#{index} ({})", node.op_type),
+                false => node.name.clone(),
+            };
+            if !node.domain.is_empty() && node.domain != "ai.onnx" {
+                return Err(OnnxError::Unsupported { op: format!("{}.{}", node.domain, node.op_type), node: label });
+            }
+            let output = node.outputs.first().filter(|name| !name.is_empty()).cloned();
+            let output = output.ok_or_else(|| invalid(&label, "no output"))?;
+
+            if node.op_type == "Constant" {
+                let data = node.attributes.get("value").and_then(|attribute| attribute.tensor.clone());
+                let data = data.ok_or_else(|| invalid(&label, "only Constant nodes with a tensor value are supported"))?;
+                add_constant(&mut constants, &mut int_constants, output, data, device)?;
+                continue;
+            }
+
+            let op = compile_op(node, &label, opset, &int_constants)?;
+            let (required, allowed) = input_arity(&op);
+            let inputs: Vec<Option<String>> = node
+                .inputs
+                .iter()
+                .take(allowed)
+                .map(|name| Some(name.clone()).filter(|name| !name.is_empty()))
+                .collect();
+            if inputs.iter().take(required).filter(|name| name.is_some()).count() < required {
+                return Err(invalid(&label, format!("{} expects at least {required} inputs", node.op_type)));
+            }
+            for name in inputs.iter().flatten() {
+                if int_constants.contains_key(name) {
+                    return Err(invalid(&label, format!("int64 tensor {name} is only read as the shape of a Reshape")));
+                }
+                if !known.contains(name) && !constants.contains_key(name) {
+                    return Err(invalid(&label, format!("input {name} is not produced by any earlier node")));
+                }
+            }
+            known.insert(output.clone());
+            steps.push(Step { op, inputs, output });
+        }
+
+        let (output, output_rank) =
+            graph.outputs.first().cloned().ok_or_else(|| OnnxError::Graph("no graph output".to_string()))?;
+        if output_rank.is_some_and(|rank| rank != 2) {
+            return Err(OnnxError::Graph(format!("output {output} should be [batch, num_classes] logits")));
+        }
+        if !known.contains(&output) {
+            return Err(OnnxError::Graph(format!("output {output} is not computed from the input")));
+        }
+
+        Ok(Self { constants, steps, input, input_rank, output })
+    }
+}
+
+impl<B: Backend> Classifier<B> for OnnxModel<B> {
+    fn forward(&self, images: Tensor<B, 3>) -> Tensor<B, 2> {
+        let [batch_size, height, width] = images.dims();
+        let input = match self.input_rank {
+            2 => Value { tensor: images.reshape([1, 1, batch_size, height * width]), rank: 2 },
+            3 => Value { tensor: images.reshape([1, batch_size, height, width]), rank: 3 },
+            _ => Value { tensor: images.reshape([batch_size, 1, height, width]), rank: 4 },
+        };
+
+        let mut values: HashMap<&str, Value<B>> = HashMap::from([(self.input.as_str(), input)]);
+        for step in &self.steps {
+            let inputs = step
+                .inputs
+                .iter()
+                .map(|name| {
+                    let name = name.as_deref()?;
+                    let value = values.get(name).or_else(|| self.constants.get(name));
+                    Some(value.expect("Inputs are checked at load").clone())
+                })
+                .collect();
+            values.insert(step.output.as_str(), step.op.apply(inputs));
+        }
+
+        let output = values.remove(self.output.as_str()).expect("The output is checked at load");
+        output.matrix("The graph output")
+    }
+}
+
+/// [`evaluate_model`] of the ONNX classifier at `path`, like a trained model: named by the class
+/// names of `artifact_dir` if it has any, its reports written there.
+pub fn evaluate_onnx<B: Backend>(
+    path: &str,
+    artifact_dir: &str,
+    config: &EvaluationConfig,
+    device: &B::Device,
+) -> Result<EvalReport, EvalError> {
+    let model = OnnxModel::<B>::load(path, device).map_err(|err| EvalError::Model(err.to_string()))?;
+    fs::create_dir_all(artifact_dir)?;
+    evaluate_model(&model, ClassLabels::load(artifact_dir), None, config, artifact_dir, device)
+}
+
+// Version of the default operator set exported graphs import, and the IR version it came with
+const EXPORT_OPSET: i64 = 13;
+const EXPORT_IR_VERSION: i64 = 7;
+
+// `AttributeProto::type` of the attributes `export_onnx` writes
+const ATTRIBUTE_INT: i64 = 2;
+const ATTRIBUTE_INTS: i64 = 7;
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+// A varint field, negative values as their 64-bit two's complement like protobuf int64s
+fn write_int(out: &mut Vec<u8>, field: u32, value: i64) {
+    write_varint(out, (field as u64) << 3);
+    write_varint(out, value as u64);
+}
+
+fn write_bytes(out: &mut Vec<u8>, field: u32, bytes: &[u8]) {
+    write_varint(out, (field as u64) << 3 | 2);
+    write_varint(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+// A float `TensorProto` holding `values` as raw little-endian data
+fn encode_tensor(name: &str, dims: &[usize], values: &[f32]) -> Vec<u8> {
+    let mut tensor = Vec::new();
+    for &dim in dims {
+        write_int(&mut tensor, 1, dim as i64);
+    }
+    write_int(&mut tensor, 2, FLOAT);
+    write_bytes(&mut tensor, 8, name.as_bytes());
+    let raw: Vec<u8> = values.iter().flat_map(|value| value.to_le_bytes()).collect();
+    write_bytes(&mut tensor, 9, &raw);
+    tensor
+}
+
+// An `AttributeProto` of one int, or of a list of them
+fn encode_attribute(name: &str, ints: &[i64], list: bool) -> Vec<u8> {
+    let mut attribute = Vec::new();
+    write_bytes(&mut attribute, 1, name.as_bytes());
+    match list {
+        true => ints.iter().for_each(|&value| write_int(&mut attribute, 8, value)),
+        false => write_int(&mut attribute, 3, ints[0]),
+    }
+    write_int(&mut attribute, 20, if list { ATTRIBUTE_INTS } else { ATTRIBUTE_INT });
+    attribute
+}
+
+// A float `ValueInfoProto`, its first dimension the symbolic `batch`
+fn encode_value_info(name: &str, dims: &[usize]) -> Vec<u8> {
+    let mut shape = Vec::new();
+    let mut batch = Vec::new();
+    write_bytes(&mut batch, 2, b"batch");
+    write_bytes(&mut shape, 1, &batch);
+    for &dim in dims {
+        let mut value = Vec::new();
+        write_int(&mut value, 1, dim as i64);
+        write_bytes(&mut shape, 1, &value);
+    }
+    let mut tensor_type = Vec::new();
+    write_int(&mut tensor_type, 1, FLOAT);
+    write_bytes(&mut tensor_type, 2, &shape);
+    let mut value_type = Vec::new();
+    write_bytes(&mut value_type, 1, &tensor_type);
+    let mut value_info = Vec::new();
+    write_bytes(&mut value_info, 1, name.as_bytes());
+    write_bytes(&mut value_info, 2, &value_type);
+    value_info
+}
+
+// The nodes and initializers of a graph being exported, each node reading the output of the
+// one before
+struct GraphWriter {
+    graph: Vec<u8>,
+    last: String,
+}
+
+impl GraphWriter {
+    // Appends a node of `op` reading the last output and `params`, the initializers it adds as
+    // `{name}.weight`... The node's output, named after it, becomes the last output.
+    fn node(&mut self, name: &str, op: &str, params: &[(&str, Vec<usize>, Vec<f32>)], attributes: &[Vec<u8>]) {
+        let mut node = Vec::new();
+        write_bytes(&mut node, 1, self.last.as_bytes());
+        for (param, dims, values) in params {
+            let param = format!("{name}.{param}");
+            write_bytes(&mut node, 1, param.as_bytes());
+            write_bytes(&mut self.graph, 5, &encode_tensor(&param, dims, values));
+        }
+        write_bytes(&mut node, 2, name.as_bytes());
+        write_bytes(&mut node, 3, name.as_bytes());
+        write_bytes(&mut node, 4, op.as_bytes());
+        for attribute in attributes {
+            write_bytes(&mut node, 5, attribute);
+        }
+        write_bytes(&mut self.graph, 1, &node);
+        self.last = name.to_string();
+    }
+}
+
+// The values of a tensor with its shape, as initializers hold them
+fn param<B: Backend, const D: usize>(name: &'static str, tensor: Tensor<B, D>) -> (&'static str, Vec<usize>, Vec<f32>) {
+    let dims = tensor.dims().to_vec();
+    let values = tensor.into_data().convert::<f32>().value;
+    (name, dims, values)
+}
+
+/// Checks that [`export_onnx`] can write a model reading the conv features as `global_pool` and
+/// `head_input` say, with or without `batch_norm`, for images of `image_shape`.
+pub fn check_export(
+    global_pool: GlobalPool,
+    head_input: HeadInput,
+    batch_norm: bool,
+    image_shape: [usize; 2],
+) -> Result<(), OnnxError> {
+    if batch_norm {
+        return Err(OnnxError::Export("batch normalized conv layers are not exported".to_string()));
+    }
+    let [height, width] = image_shape;
+    if height < 5 || width < 5 {
+        return Err(OnnxError::Export(format!("{height}x{width} images are too small for the two 3x3 convolutions")));
+    }
+    if let (GlobalPool::None, HeadInput::AdaptiveAvgPool { size }) = (global_pool, head_input) {
+        let [feature_height, feature_width] = [height - 4, width - 4];
+        if size == 0 || !feature_height.is_multiple_of(size) || !feature_width.is_multiple_of(size) {
+            return Err(OnnxError::Export(format!(
+                "{feature_height}x{feature_width} feature maps do not divide into the {size}x{size} adaptive pooling"
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Writes the class head of `model` as an ONNX model (opset 13) for images of `image_shape`,
+/// `[height, width]`, normalized like [`crate::MnistBatcher`] does: graph input `images`,
+/// `[batch, 1, height, width]`, graph output `logits`, `[batch, num_classes]`. Adapters are
+/// merged into the weights, dropout and any auxiliary head are left out. Models with
+/// `ModelConfig::batch_norm` are not written.
+///
+/// [`OnnxModel`] imports the graph back, except with `GlobalPool::Max`, written as a
+/// `GlobalMaxPool` it does not run. `HeadInput::AdaptiveAvgPool` becomes an `AveragePool`,
+/// so the conv feature maps, 4 pixels smaller than the images, must divide into its size.
+pub fn export_onnx<B: Backend>(model: &Model<B>, image_shape: [usize; 2]) -> Result<Vec<u8>, OnnxError> {
+    let model = model.clone().merge_adapters();
+    let (convs, linears, global_pool, head_input) = model.layers();
+    check_export(global_pool, head_input, model.has_batch_norm(), image_shape)?;
+    let [height, width] = image_shape;
+    let mut writer = GraphWriter { graph: Vec::new(), last: "images".to_string() };
+
+    for (name, conv) in ["conv1", "conv2"].into_iter().zip(convs) {
+        let mut params = vec![param("weight", conv.weight.val())];
+        params.extend(conv.bias.as_ref().map(|bias| param("bias", bias.val())));
+        let attributes = [encode_attribute("kernel_shape", &[3, 3], true)];
+        writer.node(name, "Conv", &params, &attributes);
+    }
+    writer.node("conv2.relu", "Relu", &[], &[]);
+
+    let [feature_height, feature_width] = [height - 4, width - 4];
+    match (global_pool, head_input) {
+        (GlobalPool::None, HeadInput::Flatten) => {}
+        (GlobalPool::None, HeadInput::AdaptiveAvgPool { size }) => {
+            let kernel = [(feature_height / size) as i64, (feature_width / size) as i64];
+            let attributes = [encode_attribute("kernel_shape", &kernel, true), encode_attribute("strides", &kernel, true)];
+            writer.node("pool", "AveragePool", &[], &attributes);
+        }
+        (GlobalPool::Avg, _) => writer.node("pool", "GlobalAveragePool", &[], &[]),
+        (GlobalPool::Max, _) => writer.node("pool", "GlobalMaxPool", &[], &[]),
+    }
+    writer.node("flatten", "Flatten", &[], &[encode_attribute("axis", &[1], false)]);
+
+    for (name, linear) in ["linear1", "linear2"].into_iter().zip(linears) {
+        // Burn lays linear weights out `[fan_in, fan_out]`, Gemm's B without transB
+        let mut params = vec![param("weight", linear.weight.val())];
+        params.extend(linear.bias.as_ref().map(|bias| param("bias", bias.val())));
+        writer.node(name, "Gemm", &params, &[]);
+        if name == "linear1" {
+            writer.node("linear1.relu", "Relu", &[], &[]);
+        }
+    }
+    let num_classes = linears[1].weight.dims()[1];
+    writer.node("logits", "Identity", &[], &[]);
+
+    let mut graph = writer.graph;
+    write_bytes(&mut graph, 2, b"model");
+    write_bytes(&mut graph, 11, &encode_value_info("images", &[1, height, width]));
+    write_bytes(&mut graph, 12, &encode_value_info("logits", &[num_classes]));
+
+    let mut opset = Vec::new();
+    write_bytes(&mut opset, 1, b"");
+    write_int(&mut opset, 2, EXPORT_OPSET);
+    let mut onnx = Vec::new();
+    write_int(&mut onnx, 1, EXPORT_IR_VERSION);
+    write_bytes(&mut onnx, 2, env!("CARGO_PKG_NAME").as_bytes());
+    write_bytes(&mut onnx, 3, env!("CARGO_PKG_VERSION").as_bytes());
+    write_bytes(&mut onnx, 7, &graph);
+    write_bytes(&mut onnx, 8, &opset);
+    Ok(onnx)
+}
+
+/// Writes [`export_onnx`] of `model` to `path`, through a temporary file renamed into place, so
+/// that a reader polling the path never loads a half-written model.
+pub fn save_onnx<B: Backend>(model: &Model<B>, image_shape: [usize; 2], path: impl AsRef<Path>) -> Result<(), OnnxError> {
+    let path = path.as_ref();
+    let bytes = export_onnx(model, image_shape)?;
+    let temporary = path.with_extension("onnx.tmp");
+    fs::write(&temporary, bytes)?;
+    Ok(fs::rename(temporary, path)?)
+}
+
+// The ONNX snapshots of `TrainingConfig::onnx_export_every`: the model of every `every`-th
+// epoch, exported when its last step is taken
+pub struct OnnxSnapshots {
+    artifact_dir: ArtifactDir,
+    every: usize,
+    steps_per_epoch: usize,
+    image_shape: [usize; 2],
+    step: usize,
+}
+
+impl OnnxSnapshots {
+    pub fn new(artifact_dir: &str, every: usize, steps_per_epoch: usize, image_shape: [usize; 2]) -> Self {
+        Self { artifact_dir: ArtifactDir::new(artifact_dir), every, steps_per_epoch, image_shape, step: 0 }
+    }
+
+    // Counts a training step, and exports `model` if it ends a snapshot epoch
+    fn after_step<B: AutodiffBackend>(&mut self, model: &Model<B>) {
+        self.step += 1;
+        if !self.step.is_multiple_of(self.steps_per_epoch) {
+            return;
+        }
+        let epoch = self.step / self.steps_per_epoch;
+        if !epoch.is_multiple_of(self.every) {
+            return;
+        }
+        let path = self.artifact_dir.onnx_snapshot_path(epoch);
+        // Like the learner logs, a failed export does not stop training
+        if let Err(err) = save_onnx(&model.valid(), self.image_shape, &path) {
+            eprintln!("Warning: could not write the ONNX snapshot {path}: {err}");
+        }
+    }
+}
+
+// Writes the ONNX snapshots of `snapshots`, if any, after every step of the wrapped optimizer,
+// the only point the learner hands out the model between two steps. A snapshot copies the
+// weights off the device and writes a file, without holding up more than that one step.
+pub struct OnnxSnapshotOptimizer<O> {
+    inner: O,
+    snapshots: Option<OnnxSnapshots>,
+}
+
+impl<O> OnnxSnapshotOptimizer<O> {
+    pub fn new(inner: O, snapshots: Option<OnnxSnapshots>) -> Self {
+        Self { inner, snapshots }
+    }
+}
+
+impl<O, B> Optimizer<Model<B>, B> for OnnxSnapshotOptimizer<O>
+where
+    B: AutodiffBackend,
+    O: Optimizer<Model<B>, B>,
+{
+    type Record = O::Record;
+
+    fn step(&mut self, lr: LearningRate, module: Model<B>, grads: GradientsParams) -> Model<B> {
+        let module = self.inner.step(lr, module, grads);
+        if let Some(snapshots) = &mut self.snapshots {
+            snapshots.after_step(&module);
+        }
+        module
+    }
+
+    fn to_record(&self) -> Self::Record {
+        self.inner.to_record()
+    }
+
+    fn load_record(self, record: Self::Record) -> Self {
+        Self { inner: self.inner.load_record(record), snapshots: self.snapshots }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        data::DatasetSource,
+        synthetic::tiny_config,
+        training::train,
+        ModelConfig,
+    };
+    use burn::{
+        backend::{ndarray::NdArrayDevice, Autodiff, NdArray},
+        tensor::Distribution,
+    };
+
+    fn max_difference(a: Tensor<NdArray, 2>, b: Tensor<NdArray, 2>) -> f32 {
+        (a - b).abs().max().into_scalar()
+    }
+
+    // A graph of a few nodes, reading the input `x` of shape `[batch, ..input_dims]`
+    struct TestGraph {
+        graph: Vec<u8>,
+    }
+
+    impl TestGraph {
+        fn new(input_dims: &[usize]) -> Self {
+            let mut graph = Vec::new();
+            write_bytes(&mut graph, 11, &encode_value_info("x", input_dims));
+            Self { graph }
+        }
+
+        fn float(mut self, name: &str, dims: &[usize], values: &[f32]) -> Self {
+            write_bytes(&mut self.graph, 5, &encode_tensor(name, dims, values));
+            self
+        }
+
+        // An int64 initializer, the shape of a Reshape
+        fn shape(mut self, name: &str, values: &[i64]) -> Self {
+            let mut tensor = Vec::new();
+            write_int(&mut tensor, 1, values.len() as i64);
+            write_int(&mut tensor, 2, INT64);
+            values.iter().for_each(|&value| write_int(&mut tensor, 7, value));
+            write_bytes(&mut tensor, 8, name.as_bytes());
+            write_bytes(&mut self.graph, 5, &tensor);
+            self
+        }
+
+        fn node(mut self, op: &str, inputs: &[&str], output: &str, attributes: &[Vec<u8>]) -> Self {
+            let mut node = Vec::new();
+            inputs.iter().for_each(|input| write_bytes(&mut node, 1, input.as_bytes()));
+            write_bytes(&mut node, 2, output.as_bytes());
+            write_bytes(&mut node, 4, op.as_bytes());
+            attributes.iter().for_each(|attribute| write_bytes(&mut node, 5, attribute));
+            write_bytes(&mut self.graph, 1, &node);
+            self
+        }
+
+        // Runs the graph on `images` of `dims`, `output` being the logits
+        fn run(mut self, output: &str, dims: [usize; 3], images: &[f32]) -> (Vec<usize>, Vec<f32>) {
+            let mut value_info = Vec::new();
+            write_bytes(&mut value_info, 1, output.as_bytes());
+            write_bytes(&mut self.graph, 12, &value_info);
+            let mut opset = Vec::new();
+            write_bytes(&mut opset, 1, b"");
+            write_int(&mut opset, 2, EXPORT_OPSET);
+            let mut onnx = Vec::new();
+            write_int(&mut onnx, 1, EXPORT_IR_VERSION);
+            write_bytes(&mut onnx, 7, &self.graph);
+            write_bytes(&mut onnx, 8, &opset);
+
+            let device = NdArrayDevice::default();
+            let model = OnnxModel::<NdArray>::from_bytes(&onnx, &device).unwrap();
+            let images = Tensor::<NdArray, 1>::from_floats(images, &device).reshape(dims);
+            let logits = Classifier::forward(&model, images);
+            (logits.dims().to_vec(), logits.into_data().value)
+        }
+    }
+
+    fn encode_float(name: &str, value: f32) -> Vec<u8> {
+        let mut attribute = Vec::new();
+        write_bytes(&mut attribute, 1, name.as_bytes());
+        write_varint(&mut attribute, 2 << 3 | 5);
+        attribute.extend_from_slice(&value.to_le_bytes());
+        write_int(&mut attribute, 20, 1);
+        attribute
+    }
+
+    fn pixels(len: usize) -> Vec<f32> {
+        (0..len).map(|i| (i as f32 * 1.7).sin() * 3.0).collect()
+    }
+
+    fn assert_close(actual: &[f32], expected: &[f32], what: &str) {
+        assert_eq!(actual.len(), expected.len(), "{what}");
+        for (actual, expected) in actual.iter().zip(expected) {
+            assert!((actual - expected).abs() < 1e-4, "{what}: {actual:?} instead of {expected:?}");
+        }
+    }
+
+    // `[m, k]` by `[k, n]`, both row-major
+    fn matmul(a: &[f32], b: &[f32], [m, k, n]: [usize; 3]) -> Vec<f32> {
+        let mut product = vec![0.0; m * n];
+        for i in 0..m {
+            for j in 0..n {
+                product[i * n + j] = (0..k).map(|l| a[i * k + l] * b[l * n + j]).sum();
+            }
+        }
+        product
+    }
+
+    fn transposed(a: &[f32], [rows, columns]: [usize; 2]) -> Vec<f32> {
+        (0..columns).flat_map(|j| (0..rows).map(move |i| a[i * columns + j])).collect()
+    }
+
+    #[test]
+    fn burn_import_model1_computes_the_logits_of_tract() {
+        let bytes = include_bytes!("../tests/data/burn_import_model1.onnx");
+        let device = NdArrayDevice::default();
+        let model = OnnxModel::<NdArray>::from_bytes(bytes, &device).unwrap();
+        let images: Vec<f32> = (0..64)
+            .map(|i| (i as f32 * 0.37).sin())
+            .chain((0..64).map(|i| (i as f32 * 0.11).cos() * 2.0 - 0.5))
+            .collect();
+        let images = Tensor::<NdArray, 1>::from_floats(images.as_slice(), &device).reshape([2, 8, 8]);
+
+        // tract-onnx 0.23 on each image, see tests/data/README.md
+        let expected = [
+            -2.2428858, -2.33203, -2.0689635, -2.2807646, -2.3389688, -2.265612, -2.4620981, -2.307056, -2.300193,
+            -2.4891036, -1.9978771, -3.501308, -1.717793, -3.2103603, -1.8780326, -2.2439787, -3.6167452, -2.133505,
+            -1.97319, -2.642477,
+        ];
+        let logits = Classifier::forward(&model, images);
+        assert_eq!(logits.dims(), [2, 10]);
+        assert_close(&logits.into_data().value, &expected, "model1");
+    }
+
+    #[test]
+    fn transpose_permutes_the_dimensions() {
+        let x = pixels(2 * 3 * 4);
+        let (dims, y) = TestGraph::new(&[3, 4])
+            .node("Transpose", &["x"], "t", &[encode_attribute("perm", &[2, 0, 1], true)])
+            .node("Flatten", &["t"], "y", &[])
+            .run("y", [2, 3, 4], &x);
+        // t[k][b][i] = x[b][i][k]
+        let expected: Vec<f32> =
+            (0..4).flat_map(|k| (0..2).flat_map(move |b| (0..3).map(move |i| (b * 3 + i) * 4 + k))).map(|i| x[i]).collect();
+        assert_eq!(dims, [4, 6]);
+        assert_close(&y, &expected, "perm [2, 0, 1]");
+
+        let x = pixels(2 * 5);
+        let (dims, y) = TestGraph::new(&[5])
+            .node("Transpose", &["x"], "y", &[encode_attribute("perm", &[1, 0], true)])
+            .run("y", [2, 1, 5], &x);
+        assert_eq!(dims, [5, 2]);
+        assert_close(&y, &transposed(&x, [2, 5]), "perm [1, 0]");
+    }
+
+    #[test]
+    fn reshape_reads_0_as_the_input_dimension_and_infers_minus_1() {
+        let x = pixels(2 * 4 * 4);
+        for (shape, expected) in [([-1, 8], [4, 8]), ([0, -1], [2, 16])] {
+            let (dims, y) = TestGraph::new(&[1, 4, 4])
+                .shape("shape", &shape)
+                .node("Reshape", &["x", "shape"], "y", &[])
+                .run("y", [2, 4, 4], &x);
+            assert_eq!(dims, expected, "{shape:?}");
+            assert_close(&y, &x, &format!("{shape:?}"));
+        }
+
+        // To [batch, 4, 4], each image then transposed
+        let (dims, y) = TestGraph::new(&[16])
+            .shape("shape", &[0, 4, -1])
+            .node("Reshape", &["x", "shape"], "r", &[])
+            .node("Transpose", &["r"], "t", &[encode_attribute("perm", &[0, 2, 1], true)])
+            .node("Flatten", &["t"], "y", &[])
+            .run("y", [2, 4, 4], &x);
+        let expected: Vec<f32> = x.chunks(16).flat_map(|image| transposed(image, [4, 4])).collect();
+        assert_eq!(dims, [2, 16]);
+        assert_close(&y, &expected, "[0, 4, -1]");
+    }
+
+    #[test]
+    fn max_pool_follows_its_kernel_strides_pads_and_dilations() {
+        // Max pooling of a size x size image, padded with -inf
+        fn max_pool(x: &[f32], size: usize, kernel: usize, stride: usize, pad: usize, dilation: usize) -> Vec<f32> {
+            let out = (size + 2 * pad - dilation * (kernel - 1) - 1) / stride + 1;
+            let mut y = Vec::new();
+            for row in 0..out {
+                for column in 0..out {
+                    let mut max = f32::NEG_INFINITY;
+                    for i in 0..kernel {
+                        for j in 0..kernel {
+                            let r = (row * stride + i * dilation) as isize - pad as isize;
+                            let c = (column * stride + j * dilation) as isize - pad as isize;
+                            if (0..size as isize).contains(&r) && (0..size as isize).contains(&c) {
+                                max = max.max(x[r as usize * size + c as usize]);
+                            }
+                        }
+                    }
+                    y.push(max);
+                }
+            }
+            y
+        }
+
+        let x = pixels(5 * 5);
+        for (kernel, stride, pad, dilation) in [(2, 2, 0, 1), (3, 1, 1, 1), (2, 1, 0, 2), (3, 2, 1, 1)] {
+            let attributes = [
+                encode_attribute("kernel_shape", &[kernel; 2], true),
+                encode_attribute("strides", &[stride; 2], true),
+                encode_attribute("pads", &[pad; 4], true),
+                encode_attribute("dilations", &[dilation; 2], true),
+            ];
+            let (_, y) = TestGraph::new(&[1, 5, 5])
+                .node("MaxPool", &["x"], "p", &attributes)
+                .node("Flatten", &["p"], "y", &[])
+                .run("y", [1, 5, 5], &x);
+            let expected = max_pool(&x, 5, kernel as usize, stride as usize, pad as usize, dilation as usize);
+            assert_close(&y, &expected, &format!("kernel {kernel}, stride {stride}, pad {pad}, dilation {dilation}"));
+        }
+    }
+
+    #[test]
+    fn matmul_multiplies_matrices_and_broadcasts_over_the_batch() {
+        let (x, b) = (pixels(2 * 6), pixels(6 * 3));
+        let (dims, y) =
+            TestGraph::new(&[6]).float("b", &[6, 3], &b).node("MatMul", &["x", "b"], "y", &[]).run("y", [2, 1, 6], &x);
+        assert_eq!(dims, [2, 3]);
+        assert_close(&y, &matmul(&x, &b, [2, 6, 3]), "[2, 6] by [6, 3]");
+
+        // [2, 2, 3] by [3, 2], one product per image
+        let (x, b) = (pixels(2 * 2 * 3), pixels(3 * 2).into_iter().rev().collect::<Vec<_>>());
+        let (dims, y) = TestGraph::new(&[2, 3])
+            .float("b", &[3, 2], &b)
+            .node("MatMul", &["x", "b"], "m", &[])
+            .node("Flatten", &["m"], "y", &[])
+            .run("y", [2, 2, 3], &x);
+        let expected: Vec<f32> = x.chunks(6).flat_map(|image| matmul(image, &b, [2, 3, 2])).collect();
+        assert_eq!(dims, [2, 4]);
+        assert_close(&y, &expected, "[2, 2, 3] by [3, 2]");
+    }
+
+    #[test]
+    fn gemm_transposes_and_scales_its_inputs() {
+        // A is the [2, 3] input, B and C are stored as the transposes say
+        let a = pixels(2 * 3);
+        let cases = [
+            (false, true, [4, 3], vec![4], [2, 3, 4]),
+            (true, false, [2, 4], vec![4], [3, 2, 4]),
+            (true, true, [4, 2], vec![3, 4], [3, 2, 4]),
+        ];
+        for (trans_a, trans_b, b_dims, c_dims, [m, k, n]) in cases {
+            let b = pixels(b_dims.iter().product()).into_iter().map(|value| value * 0.5 + 1.0).collect::<Vec<_>>();
+            let c: Vec<f32> = (0..c_dims.iter().product()).map(|i| i as f32 - 2.0).collect();
+            let attributes = [
+                encode_attribute("transA", &[trans_a as i64], false),
+                encode_attribute("transB", &[trans_b as i64], false),
+                encode_float("alpha", 0.5),
+                encode_float("beta", 2.0),
+            ];
+            let (dims, y) = TestGraph::new(&[3])
+                .float("b", &b_dims, &b)
+                .float("c", &c_dims, &c)
+                .node("Gemm", &["x", "b", "c"], "y", &attributes)
+                .run("y", [2, 1, 3], &a);
+
+            let a = if trans_a { transposed(&a, [2, 3]) } else { a.clone() };
+            let b = if trans_b { transposed(&b, b_dims) } else { b };
+            let expected: Vec<f32> =
+                matmul(&a, &b, [m, k, n]).iter().enumerate().map(|(i, ab)| 0.5 * ab + 2.0 * c[i % c.len()]).collect();
+            assert_eq!(dims, [m, n]);
+            assert_close(&y, &expected, &format!("transA {trans_a}, transB {trans_b}"));
+        }
+    }
+
+    #[test]
+    fn batch_normalization_normalizes_each_channel() {
+        // [batch, channels, width]: the 3 rows of each image are the channels
+        let x = pixels(2 * 3 * 2);
+        let [scale, bias, mean, var] = [[1.5, -0.5, 2.0], [0.1, 0.2, -0.3], [0.5, -1.0, 0.0], [1.0, 0.25, 4.0]];
+        let (dims, y) = TestGraph::new(&[3, 2])
+            .float("scale", &[3], &scale)
+            .float("bias", &[3], &bias)
+            .float("mean", &[3], &mean)
+            .float("var", &[3], &var)
+            .node("BatchNormalization", &["x", "scale", "bias", "mean", "var"], "n", &[encode_float("epsilon", 1e-3)])
+            .node("Flatten", &["n"], "y", &[])
+            .run("y", [2, 3, 2], &x);
+        let expected: Vec<f32> = x
+            .iter()
+            .enumerate()
+            .map(|(i, value)| {
+                let channel = i / 2 % 3;
+                (value - mean[channel]) / (var[channel] + 1e-3f32).sqrt() * scale[channel] + bias[channel]
+            })
+            .collect();
+        assert_eq!(dims, [2, 6]);
+        assert_close(&y, &expected, "BatchNormalization");
+    }
+
+    #[test]
+    fn imported_graphs_compute_the_logits_of_the_model() {
+        let device = NdArrayDevice::default();
+        let images = Tensor::<NdArray, 3>::random([3, 28, 28], Distribution::Normal(0.0, 1.0), &device);
+        let configs = [
+            ModelConfig::new(10, 8),
+            ModelConfig::new(10, 8).with_head_input(HeadInput::Flatten),
+            ModelConfig::new(4, 8).with_global_pool(GlobalPool::Avg).with_use_bias(false),
+        ];
+        for config in configs {
+            let model = config.init::<NdArray>(&device);
+            let onnx = OnnxModel::<NdArray>::from_bytes(&export_onnx(&model, [28, 28]).unwrap(), &device).unwrap();
+            let expected = Classifier::forward(&model, images.clone());
+            let imported = Classifier::forward(&onnx, images.clone());
+            assert_eq!(imported.dims(), expected.dims());
+            let difference = max_difference(imported, expected);
+            assert!(difference < 1e-4, "{config:?}: logits differ by {difference}");
+        }
+    }
+
+    #[test]
+    fn unsupported_operators_and_malformed_files_fail_to_load() {
+        let device = NdArrayDevice::default();
+        let model = ModelConfig::new(10, 8).with_global_pool(GlobalPool::Max).init::<NdArray>(&device);
+        match OnnxModel::<NdArray>::from_bytes(&export_onnx(&model, [28, 28]).unwrap(), &device) {
+            Err(OnnxError::Unsupported { op, node }) => {
+                assert_eq!((op.as_str(), node.as_str()), ("GlobalMaxPool", "pool"))
+            }
+            Err(err) => panic!("expected an unsupported operator, got {err}"),
+            Ok(_) => panic!("GlobalMaxPool is not supported"),
+        }
+
+        // A length running past the end of the file
+        match OnnxModel::<NdArray>::from_bytes(&[0x3a, 0x10, 0x01], &device) {
+            Err(OnnxError::Decode(_)) => {}
+            Err(err) => panic!("expected a decode error, got {err}"),
+            Ok(_) => panic!("a truncated file loaded"),
+        }
+        let err = OnnxModel::<NdArray>::load("/nonexistent/model.onnx", &device).err().unwrap();
+        assert!(matches!(err, OnnxError::Io(_)), "{err}");
+        let batch_norm = ModelConfig::new(10, 8).with_batch_norm(true).init::<NdArray>(&device);
+        assert!(matches!(export_onnx(&batch_norm, [28, 28]), Err(OnnxError::Export(_))));
+    }
+
+    #[test]
+    fn snapshots_are_written_at_every_configured_epoch() {
+        let artifact_dir = std::env::temp_dir().join("my_first_rust_DL_app-onnx-snapshots");
+        let dir = artifact_dir.to_str().unwrap();
+        let config = tiny_config()
+            .with_dataset(DatasetSource::Synthetic { num_samples: 32, seed: 1 })
+            .with_onnx_export_every(Some(2))
+            .with_num_epochs(4);
+        let device = NdArrayDevice::default();
+
+        let model = train::<Autodiff<NdArray>>(dir, config, device).unwrap().valid();
+        let mut snapshots: Vec<String> = fs::read_dir(&artifact_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .filter(|name| name.contains(".onnx"))
+            .collect();
+        snapshots.sort();
+        let artifacts = ArtifactDir::new(dir);
+        let epoch_4 = OnnxModel::<NdArray>::load(artifacts.onnx_snapshot_path(4), &device).unwrap();
+        fs::remove_dir_all(&artifact_dir).unwrap();
+
+        assert_eq!(snapshots, ["model_epoch2.onnx", "model_epoch4.onnx"]);
+        let images = Tensor::<NdArray, 3>::random([4, 28, 28], Distribution::Normal(0.0, 1.0), &device);
+        // The last epoch's snapshot is the trained model
+        let difference = max_difference(Classifier::forward(&epoch_4, images.clone()), model.forward(images));
+        assert!(difference < 1e-4, "the epoch 4 snapshot differs from the trained model by {difference}");
+    }
+}
@@ -0,0 +1,378 @@
+use crate::training::OptimizerKind;
+use burn::{
+    module::{AutodiffModule, ModuleVisitor, ParamId},
+    optim::{GradientsParams, Optimizer},
+    record::{FullPrecisionSettings, Record},
+    tensor::{backend::AutodiffBackend, ElementConversion, Tensor},
+    LearningRate,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    fs::OpenOptions,
+    io::{self, Write},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+// Where `export_optimizer_stats` appends the statistics, inside the artifact dir
+pub const OPTIMIZER_STATS_FILE: &str = "optimizer_stats.jsonl";
+// Where `log_update_ratios` appends the update-to-weight ratios, inside the artifact dir
+pub const UPDATE_RATIOS_FILE: &str = "update_ratios.jsonl";
+
+// Statistics of the optimizer state of one parameter, tagged with the optimizer kind
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "optimizer")]
+pub enum StateStats {
+    // First (exp_avg) and second (exp_avg_sq) moment estimates of Adam. The first moment is
+    // signed, its statistics are of absolute values. A parameter whose exp_avg_sq has collapsed
+    // towards 0 gets almost no gradient any more.
+    Adam { exp_avg_mean_abs: f32, exp_avg_max_abs: f32, exp_avg_sq_mean: f32, exp_avg_sq_max: f32 },
+    // L2 norm of the momentum buffer, `None` when SGD runs without momentum
+    Sgd { momentum_norm: Option<f32> },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ParamStats {
+    // Name of the parameter in the model, e.g. `conv1.weight`
+    pub name: String,
+    pub num_elements: usize,
+    #[serde(flatten)]
+    pub state: StateStats,
+}
+
+// One line of `optimizer_stats.jsonl`: the optimizer state checkpointed at the end of `epoch`
+// (1-based), one entry per parameter, sorted by name
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct OptimizerStats {
+    pub epoch: usize,
+    pub params: Vec<ParamStats>,
+}
+
+// Ratio of the L2 norm of the updates the optimizer applied to a layer, to the L2 norm of the
+// layer's parameters before the update, over the steps of one epoch. Around 1e-3 is healthy:
+// much higher and the learning rate is too high, much lower and the layer barely learns.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LayerUpdateRatio {
+    // Name of the layer in the model, e.g. `conv1`, its weight and bias together
+    pub layer: String,
+    pub mean: f32,
+    pub max: f32,
+    // Ratio of the last step of the epoch
+    pub last: f32,
+}
+
+// One line of `update_ratios.jsonl`: the ratios of the `num_steps` steps of `epoch` (1-based),
+// one entry per layer, sorted by name
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UpdateRatios {
+    pub epoch: usize,
+    pub num_steps: usize,
+    pub layers: Vec<LayerUpdateRatio>,
+}
+
+// The first value under `key`, at any depth. The states are wrapped in the record version and
+// the tensor rank, e.g. `{"V1": {"Rank2": {"momentum": {"moment_1": ...}}}}`.
+fn find<'a>(value: &'a Value, key: &str) -> Option<&'a Value> {
+    let fields = value.as_object()?;
+    fields.get(key).or_else(|| fields.values().find_map(|child| find(child, key)))
+}
+
+// A serialized tensor looks like `{"value": [...], "shape": [...]}`
+fn tensor_values(value: &Value) -> Option<Vec<f32>> {
+    let values = value.get("value")?.as_array()?;
+    Some(values.iter().filter_map(Value::as_f64).map(|value| value as f32).collect())
+}
+
+fn mean(values: &[f32]) -> f32 {
+    values.iter().map(|&value| value as f64).sum::<f64>() as f32 / values.len().max(1) as f32
+}
+
+fn max(values: impl Iterator<Item = f32>) -> f32 {
+    values.fold(0.0, f32::max)
+}
+
+// The adapter of each optimizer kind from its serialized per-parameter state
+fn state_stats(kind: &OptimizerKind, state: &Value) -> Option<(usize, StateStats)> {
+    match kind {
+        OptimizerKind::Adam => {
+            let exp_avg: Vec<f32> = tensor_values(find(state, "moment_1")?)?.iter().map(|value| value.abs()).collect();
+            let exp_avg_sq = tensor_values(find(state, "moment_2")?)?;
+            let stats = StateStats::Adam {
+                exp_avg_mean_abs: mean(&exp_avg),
+                exp_avg_max_abs: max(exp_avg.iter().copied()),
+                exp_avg_sq_mean: mean(&exp_avg_sq),
+                exp_avg_sq_max: max(exp_avg_sq.iter().copied()),
+            };
+            Some((exp_avg.len(), stats))
+        }
+        OptimizerKind::Sgd => {
+            let velocity = find(state, "velocity").and_then(tensor_values);
+            let num_elements = velocity.as_ref().map_or(0, Vec::len);
+            let momentum_norm = velocity.map(|values| values.iter().map(|value| value * value).sum::<f32>().sqrt());
+            Some((num_elements, StateStats::Sgd { momentum_norm }))
+        }
+    }
+}
+
+struct StatsExport {
+    path: PathBuf,
+    kind: OptimizerKind,
+    // Parameter names by `ParamId`, see `param_names`
+    names: HashMap<String, String>,
+}
+
+impl StatsExport {
+    fn append(&self, epoch: usize, record: &Value) -> io::Result<()> {
+        let mut params: Vec<ParamStats> = record
+            .as_object()
+            .into_iter()
+            .flatten()
+            .filter_map(|(id, state)| {
+                let name = self.names.get(id)?.clone();
+                let (num_elements, state) = state_stats(&self.kind, state)?;
+                Some(ParamStats { name, num_elements, state })
+            })
+            .collect();
+        params.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let line = serde_json::to_string(&OptimizerStats { epoch, params }).expect("Optimizer stats should serialize to JSON");
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        writeln!(file, "{line}")
+    }
+}
+
+// Squared L2 norms of one step, summed per layer
+#[derive(Default)]
+struct LayerNorms {
+    update: f64,
+    weight: f64,
+}
+
+// Running statistics of the ratios of one layer since the last checkpoint
+#[derive(Default)]
+struct RatioSums {
+    sum: f64,
+    max: f32,
+    last: f32,
+    count: usize,
+}
+
+// The parameters of a module before a step, flattened, by `ParamId`
+struct SnapshotVisitor<'a, B: AutodiffBackend> {
+    params: &'a mut HashMap<String, Tensor<B::InnerBackend, 1>>,
+}
+
+impl<B: AutodiffBackend> ModuleVisitor<B> for SnapshotVisitor<'_, B> {
+    fn visit_float<const D: usize>(&mut self, id: &ParamId, tensor: &Tensor<B, D>) {
+        self.params.insert(id.to_string(), tensor.clone().inner().flatten(0, D - 1));
+    }
+}
+
+// Norms of the difference between the parameters after a step and their snapshot, per layer
+struct UpdateVisitor<'a, B: AutodiffBackend> {
+    before: &'a HashMap<String, Tensor<B::InnerBackend, 1>>,
+    names: &'a HashMap<String, String>,
+    layers: HashMap<String, LayerNorms>,
+}
+
+impl<B: AutodiffBackend> ModuleVisitor<B> for UpdateVisitor<'_, B> {
+    fn visit_float<const D: usize>(&mut self, id: &ParamId, tensor: &Tensor<B, D>) {
+        let id = id.to_string();
+        let (Some(before), Some(name)) = (self.before.get(&id), self.names.get(&id)) else {
+            return;
+        };
+        let after: Tensor<B::InnerBackend, 1> = tensor.clone().inner().flatten(0, D - 1);
+        let update = (after - before.clone()).powf_scalar(2.0).sum().into_scalar().elem::<f64>();
+        let weight = before.clone().powf_scalar(2.0).sum().into_scalar().elem::<f64>();
+
+        // `conv1.weight` and `conv1.bias` make up the layer `conv1`
+        let layer = name.rsplit_once('.').map_or(name.as_str(), |(layer, _)| layer);
+        let norms = self.layers.entry(layer.to_string()).or_default();
+        norms.update += update;
+        norms.weight += weight;
+    }
+}
+
+struct RatioExport {
+    path: PathBuf,
+    // Parameter names by `ParamId`, see `param_names`
+    names: HashMap<String, String>,
+    // Behind a lock since checkpoints only get `&self`
+    sums: Mutex<HashMap<String, RatioSums>>,
+}
+
+impl RatioExport {
+    fn record<B: AutodiffBackend>(&self, before: &HashMap<String, Tensor<B::InnerBackend, 1>>, module: &impl AutodiffModule<B>) {
+        let mut visitor = UpdateVisitor::<B> { before, names: &self.names, layers: HashMap::new() };
+        module.visit(&mut visitor);
+
+        let mut sums = self.sums.lock().expect("Update ratio lock should not be poisoned");
+        for (layer, norms) in visitor.layers {
+            // A layer whose weights are all 0 has no meaningful ratio
+            if norms.weight == 0.0 {
+                continue;
+            }
+            let ratio = (norms.update / norms.weight).sqrt() as f32;
+            let sums = sums.entry(layer).or_default();
+            sums.sum += ratio as f64;
+            sums.max = sums.max.max(ratio);
+            sums.last = ratio;
+            sums.count += 1;
+        }
+    }
+
+    // Appends the ratios since the last checkpoint, and starts over for the next epoch
+    fn append(&self, epoch: usize) -> io::Result<()> {
+        let sums = std::mem::take(&mut *self.sums.lock().expect("Update ratio lock should not be poisoned"));
+        let num_steps = sums.values().map(|sums| sums.count).max().unwrap_or(0);
+        let mut layers: Vec<LayerUpdateRatio> = sums
+            .into_iter()
+            .map(|(layer, sums)| LayerUpdateRatio {
+                layer,
+                mean: (sums.sum / sums.count.max(1) as f64) as f32,
+                max: sums.max,
+                last: sums.last,
+            })
+            .collect();
+        layers.sort_by(|a, b| a.layer.cmp(&b.layer));
+
+        let line = serde_json::to_string(&UpdateRatios { epoch, num_steps, layers })
+            .expect("Update ratios should serialize to JSON");
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        writeln!(file, "{line}")
+    }
+}
+
+// Passes the optimizer through, and when exporting, appends the statistics of its state to
+// `optimizer_stats.jsonl`, and those of the update-to-weight ratios of every layer to
+// `update_ratios.jsonl`, every time the learner checkpoints it: once per epoch. Without an
+// export it only delegates.
+pub struct StatsOptimizer<O> {
+    inner: O,
+    export: Option<StatsExport>,
+    ratios: Option<RatioExport>,
+    checkpoints: AtomicUsize,
+}
+
+impl<O> StatsOptimizer<O> {
+    pub fn new(inner: O) -> Self {
+        Self { inner, export: None, ratios: None, checkpoints: AtomicUsize::new(0) }
+    }
+
+    // `names` maps the `ParamId` of every parameter to its name
+    pub fn with_export(mut self, path: PathBuf, kind: OptimizerKind, names: HashMap<String, String>) -> Self {
+        self.export = Some(StatsExport { path, kind, names });
+        self
+    }
+
+    // Measures the update of every step. Costs a copy of the parameters and a sync with the
+    // device per parameter and step.
+    pub fn with_update_ratios(mut self, path: PathBuf, names: HashMap<String, String>) -> Self {
+        self.ratios = Some(RatioExport { path, names, sums: Mutex::default() });
+        self
+    }
+}
+
+impl<M, B, O> Optimizer<M, B> for StatsOptimizer<O>
+where
+    M: AutodiffModule<B>,
+    B: AutodiffBackend,
+    O: Optimizer<M, B>,
+{
+    type Record = O::Record;
+
+    fn step(&mut self, lr: LearningRate, module: M, grads: GradientsParams) -> M {
+        let Some(ratios) = &self.ratios else {
+            return self.inner.step(lr, module, grads);
+        };
+        let mut before = HashMap::new();
+        module.visit(&mut SnapshotVisitor::<B> { params: &mut before });
+        let module = self.inner.step(lr, module, grads);
+        ratios.record(&before, &module);
+        module
+    }
+
+    // Only the learner checkpoints call this, after every epoch
+    fn to_record(&self) -> Self::Record {
+        let epoch = self.checkpoints.fetch_add(1, Ordering::Relaxed) + 1;
+        if let Some(ratios) = &self.ratios {
+            if let Err(err) = ratios.append(epoch) {
+                eprintln!("Could not write the update ratios to {}: {err}", ratios.path.display());
+            }
+        }
+        if let Some(export) = &self.export {
+            let item = self.inner.to_record().into_item::<FullPrecisionSettings>();
+            let record = serde_json::to_value(item).expect("Optimizer record should serialize to JSON");
+            if let Err(err) = export.append(epoch, &record) {
+                eprintln!("Could not write the optimizer stats to {}: {err}", export.path.display());
+            }
+        }
+        self.inner.to_record()
+    }
+
+    fn load_record(self, record: Self::Record) -> Self {
+        Self { inner: self.inner.load_record(record), ..self }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        params::named_params,
+        synthetic::{temp_artifact_dir, tiny_config, train_synthetic},
+    };
+
+    #[test]
+    fn stats_have_an_entry_per_parameter_every_epoch() {
+        let artifact_dir = temp_artifact_dir("optimizer-stats");
+        let config = tiny_config().with_export_optimizer_stats(true).with_num_epochs(2);
+
+        let model = train_synthetic(&artifact_dir, config, 32, 16).unwrap();
+        let lines = std::fs::read_to_string(artifact_dir.join(OPTIMIZER_STATS_FILE)).unwrap();
+        std::fs::remove_dir_all(&artifact_dir).unwrap();
+
+        let names: Vec<String> = named_params(&model).into_iter().map(|param| param.name).collect();
+        let stats: Vec<OptimizerStats> = lines.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(stats.iter().map(|stats| stats.epoch).collect::<Vec<_>>(), [1, 2]);
+        for epoch in &stats {
+            let logged: Vec<&str> = epoch.params.iter().map(|param| param.name.as_str()).collect();
+            assert_eq!(logged, names);
+            for param in &epoch.params {
+                match param.state {
+                    StateStats::Adam { exp_avg_sq_mean, exp_avg_sq_max, .. } => {
+                        assert!(exp_avg_sq_mean >= 0.0 && exp_avg_sq_max >= exp_avg_sq_mean, "{param:?}")
+                    }
+                    StateStats::Sgd { .. } => panic!("{} has SGD statistics under Adam", param.name),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn update_ratios_are_finite_for_every_layer() {
+        let artifact_dir = temp_artifact_dir("update-ratios");
+        let config = tiny_config().with_log_update_ratios(true).with_num_epochs(2);
+
+        train_synthetic(&artifact_dir, config, 32, 16).unwrap();
+        let lines = std::fs::read_to_string(artifact_dir.join(UPDATE_RATIOS_FILE)).unwrap();
+        std::fs::remove_dir_all(&artifact_dir).unwrap();
+
+        let epochs: Vec<UpdateRatios> = lines.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(epochs.iter().map(|ratios| ratios.epoch).collect::<Vec<_>>(), [1, 2]);
+        for ratios in &epochs {
+            assert_eq!(ratios.num_steps, 2);
+            let layers: Vec<&str> = ratios.layers.iter().map(|layer| layer.layer.as_str()).collect();
+            assert_eq!(layers, ["conv1", "conv2", "linear1", "linear2"]);
+            for layer in &ratios.layers {
+                assert!(layer.mean.is_finite() && layer.mean > 0.0, "{layer:?}");
+                assert!(layer.max >= layer.mean && layer.last.is_finite(), "{layer:?}");
+            }
+        }
+    }
+}
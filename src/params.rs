@@ -0,0 +1,107 @@
+use crate::model::Model;
+use burn::{
+    prelude::*,
+    record::{FullPrecisionSettings, Record},
+};
+use serde_json::Value;
+use std::collections::HashMap;
+
+type ModelItem<B> = <<Model<B> as Module<B>>::Record as Record<B>>::Item<FullPrecisionSettings>;
+
+// A single model parameter flattened to f32 values, keyed by its path in the module tree
+// (e.g. `conv1.weight`, `linear2.bias`). The names are the field names burn uses in the saved
+// record, so they stay valid whatever layers `ModelConfig` ends up building.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NamedParam {
+    pub name: String,
+    pub shape: Vec<usize>,
+    pub values: Vec<f32>,
+}
+
+impl NamedParam {
+    pub fn num_elements(&self) -> usize {
+        self.shape.iter().product()
+    }
+}
+
+fn record_to_value<B: Backend>(model: &Model<B>) -> Value {
+    let item: ModelItem<B> = model.clone().into_record().into_item();
+    serde_json::to_value(item).expect("Model record should serialize to JSON")
+}
+
+// A serialized param looks like `{"id": "...", "param": {"value": [...], "shape": [...]}}`
+fn as_param(value: &Value) -> Option<&serde_json::Map<String, Value>> {
+    let data = value.get("id").and(value.get("param"))?.as_object()?;
+    (data.contains_key("value") && data.contains_key("shape")).then_some(data)
+}
+
+fn collect(value: &Value, prefix: &str, params: &mut Vec<NamedParam>) {
+    if let Some(data) = as_param(value) {
+        let to_vec = |key: &str| data[key].as_array().cloned().unwrap_or_default();
+        params.push(NamedParam {
+            name: prefix.to_string(),
+            shape: to_vec("shape").iter().filter_map(Value::as_u64).map(|d| d as usize).collect(),
+            values: to_vec("value").iter().filter_map(Value::as_f64).map(|v| v as f32).collect(),
+        });
+    } else if let Value::Object(fields) = value {
+        for (field, child) in fields {
+            let name = if prefix.is_empty() { field.clone() } else { format!("{prefix}.{field}") };
+            collect(child, &name, params);
+        }
+    }
+}
+
+fn replace(value: &mut Value, prefix: &str, params: &[NamedParam]) {
+    if as_param(value).is_some() {
+        if let Some(param) = params.iter().find(|param| param.name == prefix) {
+            value["param"]["value"] = param.values.iter().map(|&v| Value::from(v)).collect();
+            value["param"]["shape"] = param.shape.iter().map(|&d| Value::from(d)).collect();
+        }
+    } else if let Value::Object(fields) = value {
+        for (field, child) in fields.iter_mut() {
+            let name = if prefix.is_empty() { field.clone() } else { format!("{prefix}.{field}") };
+            replace(child, &name, params);
+        }
+    }
+}
+
+// Every parameter of the model, sorted by name
+pub fn named_params<B: Backend>(model: &Model<B>) -> Vec<NamedParam> {
+    let mut params = Vec::new();
+    collect(&record_to_value(model), "", &mut params);
+    params
+}
+
+fn collect_ids(value: &Value, prefix: &str, names: &mut HashMap<String, String>) {
+    if let Some(id) = as_param(value).and(value.get("id")).and_then(Value::as_str) {
+        names.insert(id.to_string(), prefix.to_string());
+    } else if let Value::Object(fields) = value {
+        for (field, child) in fields {
+            let name = if prefix.is_empty() { field.clone() } else { format!("{prefix}.{field}") };
+            collect_ids(child, &name, names);
+        }
+    }
+}
+
+// Name of every parameter of the model (as in `named_params`) by its `ParamId`, the key of the
+// optimizer records
+pub fn param_names<B: Backend>(model: &Model<B>) -> HashMap<String, String> {
+    let mut names = HashMap::new();
+    collect_ids(&record_to_value(model), "", &mut names);
+    names
+}
+
+// Overwrites the parameters whose name appears in `params`, leaving the others untouched.
+// Parameter ids are kept, so optimizer state keyed on them stays attached to the same tensors.
+pub fn with_named_params<B: Backend>(
+    model: Model<B>,
+    params: &[NamedParam],
+    device: &B::Device,
+) -> Model<B> {
+    let mut value = record_to_value(&model);
+    replace(&mut value, "", params);
+
+    let item: ModelItem<B> =
+        serde_json::from_value(value).expect("Updated record should match the model structure");
+    model.load_record(Record::from_item(item, device))
+}
@@ -0,0 +1,184 @@
+use crate::{
+    artifact::ArtifactDir,
+    checkpoint::LoadError,
+    inference::load_model,
+    labels::ClassLabels,
+    meta::ModelMeta,
+    model::Model,
+    params::{named_params, with_named_params, NamedParam},
+    training::{create_artifact_dir, TrainingConfig},
+};
+use burn::{
+    prelude::*,
+    record::{CompactRecorder, RecorderError},
+};
+use rand::{rngs::StdRng, SeedableRng};
+use rand_distr::{Distribution, StandardNormal};
+use std::{fmt, fs, io};
+
+// The weights written by `CompactRecorder` to `model`
+const WEIGHTS_FILE: &str = "model.mpk";
+
+#[derive(Debug)]
+pub enum PerturbError {
+    // The noise scale is negative or not finite
+    InvalidSigma(f64),
+    Load(LoadError),
+    Io(io::Error),
+    Record(RecorderError),
+}
+
+impl fmt::Display for PerturbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PerturbError::InvalidSigma(sigma) => write!(f, "sigma must be a finite value >= 0, got {sigma}"),
+            PerturbError::Load(err) => write!(f, "cannot perturb the model: {err}"),
+            PerturbError::Io(err) => write!(f, "could not write the perturbed model: {err}"),
+            PerturbError::Record(err) => write!(f, "could not save the perturbed model: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for PerturbError {}
+
+impl From<io::Error> for PerturbError {
+    fn from(err: io::Error) -> Self {
+        PerturbError::Io(err)
+    }
+}
+
+impl From<RecorderError> for PerturbError {
+    fn from(err: RecorderError) -> Self {
+        PerturbError::Record(err)
+    }
+}
+
+// Adds N(0, sigma * |w|) to every weight `w`, in parameter order, from one rng
+fn add_noise(params: Vec<NamedParam>, sigma: f64, seed: u64) -> Vec<NamedParam> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    params
+        .into_iter()
+        .map(|param| {
+            let values = param
+                .values
+                .iter()
+                .map(|&value| {
+                    let noise: f64 = StandardNormal.sample(&mut rng);
+                    (value as f64 + noise * sigma * (value as f64).abs()) as f32
+                })
+                .collect();
+            NamedParam { values, ..param }
+        })
+        .collect()
+}
+
+/// Perturb and fine-tune: the model trained into `artifact_dir` with seeded Gaussian noise
+/// added to every weight, saved into the `output` artifact dir (wiped first) along with its
+/// config, class names and metadata. Fine-tuning it with `resume_from` set to `output`, once per
+/// seed, gives a cheap ensemble from a single training run. Returns the perturbed model.
+///
+/// The noise of a weight `w` is drawn from N(0, sigma * |w|), relative to the weight so that
+/// layers of every magnitude are perturbed in proportion. Weights at 0 (pruned ones) stay at 0.
+/// `sigma` 0 copies the weights file byte for byte.
+pub fn perturb<B: Backend>(
+    artifact_dir: &str,
+    output: &str,
+    sigma: f64,
+    seed: u64,
+    device: &B::Device,
+) -> Result<Model<B>, PerturbError> {
+    if !(sigma.is_finite() && sigma >= 0.0) {
+        return Err(PerturbError::InvalidSigma(sigma));
+    }
+    let config = TrainingConfig::load(ArtifactDir::new(artifact_dir).config_path())
+        .map_err(|err| PerturbError::Load(LoadError::Config(err.to_string())))?;
+    let model = load_model::<B>(artifact_dir, device).map_err(PerturbError::Load)?;
+    let meta = ModelMeta::load(artifact_dir).map_err(PerturbError::Load)?;
+    let labels = ClassLabels::load(artifact_dir);
+
+    // Without noise the weights file is copied as is: saving the loaded model again would give
+    // its parameters new ids, and a file that differs from the original
+    let (perturbed, unchanged) = if sigma == 0.0 {
+        (model, Some(fs::read(format!("{artifact_dir}/{WEIGHTS_FILE}"))?))
+    } else {
+        let params = add_noise(named_params(&model), sigma, seed);
+        (with_named_params(model, &params, device), None)
+    };
+
+    create_artifact_dir(output)?;
+    config.save(format!("{output}/config.json"))?;
+    labels.save(output)?;
+    match unchanged {
+        Some(weights) => fs::write(format!("{output}/{WEIGHTS_FILE}"), weights)?,
+        None => perturbed.clone().save_file(format!("{output}/model"), &CompactRecorder::new())?,
+    }
+    if let Some(mut meta) = meta {
+        // The perturbed model was never validated
+        meta.best_valid_accuracy = None;
+        meta.save(output)?;
+    }
+    Ok(perturbed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{artifact::ModelKind, evaluation::dataset_predictions, model::ModelConfig, synthetic::SyntheticDigits};
+    use burn::{
+        backend::{ndarray::NdArrayDevice, NdArray},
+        optim::AdamConfig,
+    };
+
+    fn dir(name: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("my_first_rust_DL_app-perturb-{name}"));
+        dir.to_str().unwrap().to_string()
+    }
+
+    // An untrained model saved as a run
+    fn saved_run(name: &str, device: &NdArrayDevice) -> String {
+        let dir = ArtifactDir::new(dir(name));
+        fs::create_dir_all(dir.as_str()).unwrap();
+        let config = TrainingConfig::new(ModelConfig::new(10, 8), AdamConfig::new());
+        config.save(dir.config_path()).unwrap();
+        config.model.init::<NdArray>(device).save_file(dir.model_path(ModelKind::Final), &CompactRecorder::new()).unwrap();
+        dir.as_str().to_string()
+    }
+
+    #[test]
+    fn zero_sigma_copies_the_model_and_its_predictions() {
+        let device = NdArrayDevice::default();
+        let run = saved_run("zero-run", &device);
+        let output = dir("zero");
+
+        let perturbed = perturb::<NdArray>(&run, &output, 0.0, 1, &device).unwrap();
+        assert_eq!(fs::read(format!("{run}/{WEIGHTS_FILE}")).unwrap(), fs::read(format!("{output}/{WEIGHTS_FILE}")).unwrap());
+        let dataset = SyntheticDigits::new(20, 1);
+        let original = dataset_predictions(&load_model::<NdArray>(&run, &device).unwrap(), &dataset, &device);
+        let reloaded = dataset_predictions(&load_model::<NdArray>(&output, &device).unwrap(), &dataset, &device);
+        assert_eq!(original, reloaded);
+        assert_eq!(dataset_predictions(&perturbed, &dataset, &device), original);
+        fs::remove_dir_all(output).unwrap();
+        fs::remove_dir_all(run).unwrap();
+    }
+
+    #[test]
+    fn noise_is_relative_to_the_weights_and_seeded() {
+        let device = NdArrayDevice::default();
+        let run = saved_run("noisy-run", &device);
+        let original = named_params(&load_model::<NdArray>(&run, &device).unwrap());
+
+        let params = |seed: u64| named_params(&perturb::<NdArray>(&run, &dir("noisy"), 0.1, seed, &device).unwrap());
+        let (first, again, other) = (params(1), params(1), params(2));
+        assert_eq!(first, again);
+        assert_ne!(first, other);
+        for (perturbed, original) in first.iter().zip(&original) {
+            for (perturbed, original) in perturbed.values.iter().zip(&original.values) {
+                // Well within ten standard deviations
+                assert!((perturbed - original).abs() <= original.abs() + 1e-6, "{}", perturbed);
+            }
+        }
+        assert!(matches!(perturb::<NdArray>(&run, &dir("noisy"), -0.1, 1, &device), Err(PerturbError::InvalidSigma(_))));
+        fs::remove_dir_all(dir("noisy")).unwrap();
+        fs::remove_dir_all(run).unwrap();
+    }
+}
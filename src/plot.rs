@@ -0,0 +1,267 @@
+use crate::{artifact::ArtifactDir, history::History};
+use std::{fs, io};
+
+// Size of one chart, in SVG user units; the charts are stacked vertically
+const CHART_WIDTH: f64 = 640.0;
+const CHART_HEIGHT: f64 = 320.0;
+// Room around the plot area for the title, tick labels and legend
+const MARGIN_LEFT: f64 = 64.0;
+const MARGIN_RIGHT: f64 = 120.0;
+const MARGIN_TOP: f64 = 36.0;
+const MARGIN_BOTTOM: f64 = 44.0;
+const NUM_Y_TICKS: usize = 5;
+
+const TRAIN_COLOR: &str = "#1f77b4";
+const VALID_COLOR: &str = "#ff7f0e";
+
+// One line of a chart: (epoch, value, partial) points of a split. An epoch a training budget cut
+// short sits at the share of it trained, e.g. 2.25 for a quarter of epoch 3.
+struct Series {
+    label: &'static str,
+    color: &'static str,
+    points: Vec<(f64, f64, bool)>,
+}
+
+// Pulls `metric` out of the history for both splits, dropping NaN and infinite values.
+// Returns the series and the number of values dropped.
+fn series(history: &History, metric: &str) -> (Vec<Series>, usize) {
+    let mut skipped = 0;
+    let mut split = |label, color, is_train: bool| {
+        let points = history
+            .epochs
+            .iter()
+            .filter_map(|record| {
+                let values = if is_train { &record.train } else { &record.valid };
+                let value = *values.get(metric)?;
+                if !value.is_finite() {
+                    skipped += 1;
+                    return None;
+                }
+                match record.fraction {
+                    Some(fraction) => Some((record.epoch as f64 - 1.0 + fraction, value, true)),
+                    None => Some((record.epoch as f64, value, false)),
+                }
+            })
+            .collect();
+        Series { label, color, points }
+    };
+
+    let lines = vec![split("train", TRAIN_COLOR, true), split("valid", VALID_COLOR, false)];
+    (lines, skipped)
+}
+
+// Value range of the axis, padded so that a single value or a flat line stays drawable
+fn range(values: impl Iterator<Item = f64>) -> (f64, f64) {
+    let (min, max) = values.fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), value| {
+        (min.min(value), max.max(value))
+    });
+    if min > max {
+        (0.0, 1.0)
+    } else if min == max {
+        let pad = if min == 0.0 { 1.0 } else { min.abs() * 0.1 };
+        (min - pad, max + pad)
+    } else {
+        (min, max)
+    }
+}
+
+fn chart(svg: &mut String, title: &str, lines: &[Series], skipped: usize, num_epochs: usize, top: f64) {
+    let (left, right) = (MARGIN_LEFT, CHART_WIDTH - MARGIN_RIGHT);
+    let (plot_top, plot_bottom) = (top + MARGIN_TOP, top + CHART_HEIGHT - MARGIN_BOTTOM);
+
+    // Half an epoch of padding on each side keeps single epoch runs centered
+    let (x_min, x_max) = (0.5, num_epochs.max(1) as f64 + 0.5);
+    let (y_min, y_max) = range(lines.iter().flat_map(|line| line.points.iter().map(|point| point.1)));
+    let x = |epoch: f64| left + (epoch - x_min) / (x_max - x_min) * (right - left);
+    let y = |value: f64| plot_bottom - (value - y_min) / (y_max - y_min) * (plot_bottom - plot_top);
+
+    let mut out = |text: String| svg.push_str(&text);
+    out(format!(
+        r#"<text x="{}" y="{}" font-size="16" text-anchor="middle">{title}</text>"#,
+        (left + right) / 2.0,
+        top + 22.0
+    ));
+    out(format!(
+        r#"<polyline points="{left},{plot_top} {left},{plot_bottom} {right},{plot_bottom}" fill="none" stroke="black"/>"#
+    ));
+
+    // Ticks on every epoch for short runs, about ten of them for long ones
+    let step = num_epochs.div_ceil(10).max(1);
+    for epoch in (1..=num_epochs).step_by(step) {
+        let tick_x = x(epoch as f64);
+        out(format!(
+            r#"<line x1="{tick_x}" y1="{plot_bottom}" x2="{tick_x}" y2="{}" stroke="black"/><text x="{tick_x}" y="{}" font-size="11" text-anchor="middle">{epoch}</text>"#,
+            plot_bottom + 5.0,
+            plot_bottom + 18.0
+        ));
+    }
+    out(format!(
+        r#"<text x="{}" y="{}" font-size="12" text-anchor="middle">epoch</text>"#,
+        (left + right) / 2.0,
+        plot_bottom + 36.0
+    ));
+
+    for tick in 0..NUM_Y_TICKS {
+        let value = y_min + (y_max - y_min) * tick as f64 / (NUM_Y_TICKS - 1) as f64;
+        let tick_y = y(value);
+        out(format!(
+            r##"<line x1="{}" y1="{tick_y}" x2="{right}" y2="{tick_y}" stroke="#ddd"/><text x="{}" y="{}" font-size="11" text-anchor="end">{value:.3}</text>"##,
+            left - 5.0,
+            left - 8.0,
+            tick_y + 4.0
+        ));
+    }
+
+    for (index, line) in lines.iter().enumerate() {
+        let points: Vec<String> =
+            line.points.iter().map(|&(epoch, value, _)| format!("{},{}", x(epoch), y(value))).collect();
+        out(format!(
+            r#"<polyline points="{}" fill="none" stroke="{}" stroke-width="2"/>"#,
+            points.join(" "),
+            line.color
+        ));
+        for &(epoch, value, partial) in &line.points {
+            // Hollow for a partial epoch, which is not comparable with the whole ones
+            let (fill, stroke) = if partial { ("white", line.color) } else { (line.color, "none") };
+            out(format!(
+                r#"<circle cx="{}" cy="{}" r="3" fill="{fill}" stroke="{stroke}" stroke-width="1.5"/>"#,
+                x(epoch),
+                y(value)
+            ));
+        }
+
+        let legend_y = plot_top + 10.0 + 18.0 * index as f64;
+        out(format!(
+            r#"<line x1="{}" y1="{legend_y}" x2="{}" y2="{legend_y}" stroke="{}" stroke-width="2"/><text x="{}" y="{}" font-size="12">{}</text>"#,
+            right + 12.0,
+            right + 32.0,
+            line.color,
+            right + 38.0,
+            legend_y + 4.0,
+            line.label
+        ));
+    }
+
+    if skipped > 0 {
+        out(format!(
+            r##"<text x="{}" y="{}" font-size="11" fill="#a00">{skipped} non-finite value(s) not drawn</text>"##,
+            right + 12.0,
+            plot_top + 56.0
+        ));
+    }
+    if lines.iter().any(|line| line.points.iter().any(|point| point.2)) {
+        out(format!(
+            r#"<text x="{}" y="{}" font-size="11">hollow: partial epoch</text>"#,
+            right + 12.0,
+            plot_top + 74.0
+        ));
+    }
+}
+
+// Renders the loss and accuracy (F1 score for multi-label runs) of both splits per epoch as an
+// SVG document
+pub fn learning_curves_svg(history: &History) -> String {
+    let num_epochs = history.epochs.iter().map(|record| record.epoch).max().unwrap_or(0);
+    // Multi-label runs are scored with F1 instead of accuracy
+    let multilabel = history.epochs.iter().any(|record| record.valid.contains_key("F1 Score"));
+    let score = if multilabel { ("F1 Score", "F1 Score (%)") } else { ("Accuracy", "Accuracy (%)") };
+    let charts = [("Loss", "Loss"), score];
+
+    let mut svg = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{CHART_WIDTH}" height="{}" font-family="sans-serif">"#,
+        CHART_HEIGHT * charts.len() as f64
+    );
+    svg.push_str(r#"<rect width="100%" height="100%" fill="white"/>"#);
+    for (index, (metric, title)) in charts.iter().enumerate() {
+        let (lines, skipped) = series(history, metric);
+        chart(&mut svg, title, &lines, skipped, num_epochs, CHART_HEIGHT * index as f64);
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+// Writes `curves.svg` into the artifact dir from the run's `history.json`
+pub fn plot_learning_curves(artifact_dir: &str) -> io::Result<()> {
+    let dir = ArtifactDir::new(artifact_dir);
+    let history = History::load(dir.history_path())?;
+    fs::write(dir.curves_path(), learning_curves_svg(&history))
+}
+
+// Renders the `(lr, loss)` points of a learning rate range test as an SVG chart, with the
+// learning rate on a log scale. `markers` are drawn as labeled vertical lines, e.g. the
+// suggested learning rate. Non-finite losses are left out.
+pub fn lr_curve_svg(points: &[(f64, f64)], markers: &[(&str, f64)]) -> String {
+    let (left, right) = (MARGIN_LEFT, CHART_WIDTH - MARGIN_RIGHT);
+    let (plot_top, plot_bottom) = (MARGIN_TOP, CHART_HEIGHT - MARGIN_BOTTOM);
+    let finite: Vec<(f64, f64)> =
+        points.iter().copied().filter(|&(lr, loss)| lr > 0.0 && loss.is_finite()).collect();
+    let skipped = points.len() - finite.len();
+
+    let (x_min, x_max) = range(finite.iter().map(|&(lr, _)| lr.log10()));
+    let (y_min, y_max) = range(finite.iter().map(|&(_, loss)| loss));
+    let x = |lr: f64| left + (lr.log10() - x_min) / (x_max - x_min) * (right - left);
+    let y = |loss: f64| plot_bottom - (loss - y_min) / (y_max - y_min) * (plot_bottom - plot_top);
+
+    let mut svg = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{CHART_WIDTH}" height="{CHART_HEIGHT}" font-family="sans-serif">"#
+    );
+    let mut out = |text: String| svg.push_str(&text);
+    out(r#"<rect width="100%" height="100%" fill="white"/>"#.to_string());
+    out(format!(
+        r#"<text x="{}" y="22" font-size="16" text-anchor="middle">Learning rate range test</text>"#,
+        (left + right) / 2.0
+    ));
+    out(format!(
+        r#"<polyline points="{left},{plot_top} {left},{plot_bottom} {right},{plot_bottom}" fill="none" stroke="black"/>"#
+    ));
+
+    // A tick on every power of ten
+    for exponent in x_min.ceil() as i32..=x_max.floor() as i32 {
+        let tick_x = x(10f64.powi(exponent));
+        out(format!(
+            r#"<line x1="{tick_x}" y1="{plot_bottom}" x2="{tick_x}" y2="{}" stroke="black"/><text x="{tick_x}" y="{}" font-size="11" text-anchor="middle">1e{exponent}</text>"#,
+            plot_bottom + 5.0,
+            plot_bottom + 18.0
+        ));
+    }
+    out(format!(
+        r#"<text x="{}" y="{}" font-size="12" text-anchor="middle">learning rate</text>"#,
+        (left + right) / 2.0,
+        plot_bottom + 36.0
+    ));
+
+    for tick in 0..NUM_Y_TICKS {
+        let value = y_min + (y_max - y_min) * tick as f64 / (NUM_Y_TICKS - 1) as f64;
+        let tick_y = y(value);
+        out(format!(
+            r##"<line x1="{}" y1="{tick_y}" x2="{right}" y2="{tick_y}" stroke="#ddd"/><text x="{}" y="{}" font-size="11" text-anchor="end">{value:.3}</text>"##,
+            left - 5.0,
+            left - 8.0,
+            tick_y + 4.0
+        ));
+    }
+
+    let line: Vec<String> = finite.iter().map(|&(lr, loss)| format!("{},{}", x(lr), y(loss))).collect();
+    out(format!(
+        r#"<polyline points="{}" fill="none" stroke="{TRAIN_COLOR}" stroke-width="2"/>"#,
+        line.join(" ")
+    ));
+
+    for (index, &(label, lr)) in markers.iter().enumerate() {
+        let marker_x = x(lr);
+        out(format!(
+            r##"<line x1="{marker_x}" y1="{plot_top}" x2="{marker_x}" y2="{plot_bottom}" stroke="{VALID_COLOR}" stroke-dasharray="4 3"/><text x="{}" y="{}" font-size="12">{label} {lr:.1e}</text>"##,
+            right + 12.0,
+            plot_top + 10.0 + 18.0 * index as f64
+        ));
+    }
+    if skipped > 0 {
+        out(format!(
+            r##"<text x="{}" y="{}" font-size="11" fill="#a00">{skipped} non-finite value(s) not drawn</text>"##,
+            right + 12.0,
+            plot_top + 56.0
+        ));
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
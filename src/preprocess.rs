@@ -0,0 +1,318 @@
+use crate::{data::ClassificationDataset, inference::RawImage};
+use burn::prelude::*;
+use image::{imageops::FilterType, GrayImage, Luma};
+use std::path::Path;
+
+// Number of gray levels of a u8 image
+const LEVELS: usize = 256;
+
+// Side of each pixel in the before/after previews, 28x28 being too small to eyeball
+const PREVIEW_SCALE: u32 = 8;
+
+/// One contrast operation on the raw u8 image, after it is fitted onto the 28x28 canvas and
+/// before it becomes a tensor. A list of them is `TrainingConfig::preprocess`, applied in order
+/// to every training and validation image and recorded in `model_meta.json`, so that inference
+/// replays the exact pipeline.
+#[derive(Config, Debug, Copy, PartialEq)]
+pub enum PreprocessConfig {
+    // Histogram equalization of the whole image: the gray levels are remapped so that their
+    // cumulative histogram is as linear as possible
+    Equalize,
+    // Contrast limited adaptive histogram equalization: each `tile_size` x `tile_size` tile is
+    // equalized on its own, its histogram clipped at `clip_limit` times the mean bin count
+    // first, and pixels blend the mappings of the nearest tiles
+    Clahe { tile_size: u32, clip_limit: f32 },
+    // Linear stretch of the levels between the two percentiles (in [0, 100]) to 0-255, the
+    // pixels outside them clipped
+    Stretch { low_percentile: f32, high_percentile: f32 },
+}
+
+impl PreprocessConfig {
+    pub fn apply(&self, image: &GrayImage) -> GrayImage {
+        match *self {
+            PreprocessConfig::Equalize => equalize_histogram(image),
+            PreprocessConfig::Clahe { tile_size, clip_limit } => clahe(image, tile_size, clip_limit),
+            PreprocessConfig::Stretch { low_percentile, high_percentile } => {
+                contrast_stretch(image, low_percentile, high_percentile)
+            }
+        }
+    }
+}
+
+/// `image` through every step of `steps`, in order.
+pub fn apply_steps(steps: &[PreprocessConfig], image: &GrayImage) -> GrayImage {
+    steps.iter().fold(image.clone(), |image, step| step.apply(&image))
+}
+
+/// [`apply_steps`] on raw MNIST pixels, rounded to u8 first.
+pub fn apply_steps_raw(steps: &[PreprocessConfig], image: &RawImage) -> RawImage {
+    if steps.is_empty() {
+        return *image;
+    }
+    let gray = GrayImage::from_fn(28, 28, |x, y| Luma([to_level(image[y as usize][x as usize])]));
+    let gray = apply_steps(steps, &gray);
+    let mut pixels = [[0.0; 28]; 28];
+    for (x, y, Luma([value])) in gray.enumerate_pixels() {
+        pixels[y as usize][x as usize] = *value as f32;
+    }
+    pixels
+}
+
+// A pixel of the MNIST scale as a gray level
+fn to_level(pixel: f32) -> u8 {
+    pixel.round().clamp(0.0, 255.0) as u8
+}
+
+fn histogram(image: &GrayImage) -> [u32; LEVELS] {
+    let mut counts = [0; LEVELS];
+    for Luma([value]) in image.pixels() {
+        counts[*value as usize] += 1;
+    }
+    counts
+}
+
+/// Global histogram equalization: the darkest level present becomes 0, the brightest 255, and
+/// the levels in between are spaced by how many pixels they hold. A single-level image is
+/// returned as is.
+pub fn equalize_histogram(image: &GrayImage) -> GrayImage {
+    let counts = histogram(image);
+    let total = image.pixels().len() as u64;
+    let mut cumulative = [0u64; LEVELS];
+    let mut sum = 0;
+    for (level, count) in counts.iter().enumerate() {
+        sum += *count as u64;
+        cumulative[level] = sum;
+    }
+    let Some(first) = cumulative.iter().copied().find(|&count| count > 0) else {
+        return image.clone();
+    };
+    if first == total {
+        return image.clone();
+    }
+    let lookup: Vec<u8> = cumulative
+        .iter()
+        .map(|&count| ((count.saturating_sub(first)) as f64 * 255.0 / (total - first) as f64).round() as u8)
+        .collect();
+    remap(image, |_, _, value| lookup[value as usize])
+}
+
+/// Linear contrast stretch: the level at `low_percentile` of the pixels becomes 0, the one at
+/// `high_percentile` 255, those beyond them are clipped. An image whose two percentiles fall on
+/// the same level is returned as is.
+pub fn contrast_stretch(image: &GrayImage, low_percentile: f32, high_percentile: f32) -> GrayImage {
+    let counts = histogram(image);
+    let total = image.pixels().len() as f64;
+    // The first level with at least `percentile`% of the pixels at or below it
+    let level_at = |percentile: f32| {
+        let target = (percentile as f64 / 100.0 * total).max(1.0);
+        let mut sum = 0.0;
+        (0..LEVELS)
+            .find(|&level| {
+                sum += counts[level] as f64;
+                sum >= target
+            })
+            .unwrap_or(LEVELS - 1) as f32
+    };
+    let (low, high) = (level_at(low_percentile), level_at(high_percentile));
+    if high <= low {
+        return image.clone();
+    }
+    remap(image, |_, _, value| ((value as f32 - low) / (high - low) * 255.0).round().clamp(0.0, 255.0) as u8)
+}
+
+/// CLAHE: the image is cut into `tile_size` x `tile_size` tiles (those of the last row and
+/// column may be smaller), each tile's histogram is clipped at `clip_limit` times its mean bin
+/// count, the excess spread evenly over every level, and equalized into a mapping of its own.
+/// Every pixel is bilinearly interpolated between the mappings of the four tiles whose centers
+/// surround it, so that no tile edge shows.
+pub fn clahe(image: &GrayImage, tile_size: u32, clip_limit: f32) -> GrayImage {
+    let (width, height) = image.dimensions();
+    let tile_size = tile_size.max(1);
+    let (tiles_x, tiles_y) = (width.div_ceil(tile_size), height.div_ceil(tile_size));
+    if tiles_x == 0 || tiles_y == 0 {
+        return image.clone();
+    }
+
+    let mut lookups = Vec::with_capacity((tiles_x * tiles_y) as usize);
+    for tile_y in 0..tiles_y {
+        for tile_x in 0..tiles_x {
+            let (x0, y0) = (tile_x * tile_size, tile_y * tile_size);
+            let tile = image::imageops::crop_imm(image, x0, y0, tile_size.min(width - x0), tile_size.min(height - y0));
+            lookups.push(clipped_equalization(&tile.to_image(), clip_limit));
+        }
+    }
+
+    // Position of a pixel in tile units, relative to the centers of the first tiles, and the
+    // two tiles it blends with their weights
+    let neighbours = |position: u32, tiles: u32| {
+        let offset = ((position as f32 + 0.5) / tile_size as f32 - 0.5).clamp(0.0, (tiles - 1) as f32);
+        let first = offset.floor() as u32;
+        let second = (first + 1).min(tiles - 1);
+        (first, second, offset - first as f32)
+    };
+    remap(image, |x, y, value| {
+        let (left, right, weight_x) = neighbours(x, tiles_x);
+        let (top, bottom, weight_y) = neighbours(y, tiles_y);
+        let level = |tile_x: u32, tile_y: u32| lookups[(tile_y * tiles_x + tile_x) as usize][value as usize];
+        let upper = level(left, top) * (1.0 - weight_x) + level(right, top) * weight_x;
+        let lower = level(left, bottom) * (1.0 - weight_x) + level(right, bottom) * weight_x;
+        (upper * (1.0 - weight_y) + lower * weight_y).round().clamp(0.0, 255.0) as u8
+    })
+}
+
+// The equalization mapping of one CLAHE tile, to levels in [0, 255]: the cumulative share of
+// its clipped histogram
+fn clipped_equalization(tile: &GrayImage, clip_limit: f32) -> [f32; LEVELS] {
+    let total = tile.pixels().len() as f32;
+    let limit = clip_limit * total / LEVELS as f32;
+    let mut counts = histogram(tile).map(|count| count as f32);
+    let excess: f32 = counts.iter().map(|count| (count - limit).max(0.0)).sum();
+    for count in counts.iter_mut() {
+        *count = count.min(limit) + excess / LEVELS as f32;
+    }
+
+    let mut lookup = [0.0; LEVELS];
+    let mut sum = 0.0;
+    for (level, count) in counts.iter().enumerate() {
+        sum += count;
+        lookup[level] = sum / total * 255.0;
+    }
+    lookup
+}
+
+fn remap(image: &GrayImage, mut f: impl FnMut(u32, u32, u8) -> u8) -> GrayImage {
+    GrayImage::from_fn(image.width(), image.height(), |x, y| Luma([f(x, y, image.get_pixel(x, y)[0])]))
+}
+
+/// Writes `before.png` and `after.png` into `dir`: the 28x28 canvas of `image` as fitted, and
+/// through `steps`, each pixel drawn as an 8x8 block. Returns the two paths.
+pub fn write_preview(
+    steps: &[PreprocessConfig],
+    image: &RawImage,
+    dir: impl AsRef<Path>,
+) -> image::ImageResult<[std::path::PathBuf; 2]> {
+    let dir = dir.as_ref();
+    std::fs::create_dir_all(dir)?;
+    let paths = [dir.join("before.png"), dir.join("after.png")];
+    for (path, pixels) in paths.iter().zip([*image, apply_steps_raw(steps, image)]) {
+        let gray = GrayImage::from_fn(28, 28, |x, y| Luma([to_level(pixels[y as usize][x as usize])]));
+        image::imageops::resize(&gray, 28 * PREVIEW_SCALE, 28 * PREVIEW_SCALE, FilterType::Nearest).save(path)?;
+    }
+    Ok(paths)
+}
+
+// A classification dataset with every image through the `TrainingConfig::preprocess` steps
+pub struct PreprocessedDataset<D> {
+    dataset: D,
+    steps: Vec<PreprocessConfig>,
+}
+
+impl<D> PreprocessedDataset<D> {
+    pub fn new(dataset: D, steps: Vec<PreprocessConfig>) -> Self {
+        Self { dataset, steps }
+    }
+}
+
+impl<D: ClassificationDataset> ClassificationDataset for PreprocessedDataset<D> {
+    fn len(&self) -> usize {
+        self.dataset.len()
+    }
+
+    fn get(&self, index: usize) -> Option<(Vec<f32>, usize)> {
+        let (pixels, label) = self.dataset.get(index)?;
+        if self.steps.is_empty() {
+            return Some((pixels, label));
+        }
+        let [height, width] = self.dataset.image_shape();
+        let gray = GrayImage::from_fn(width as u32, height as u32, |x, y| {
+            Luma([to_level(pixels[y as usize * width + x as usize])])
+        });
+        let pixels = apply_steps(&self.steps, &gray).pixels().map(|Luma([value])| *value as f32).collect();
+        Some((pixels, label))
+    }
+
+    fn num_classes(&self) -> usize {
+        self.dataset.num_classes()
+    }
+
+    fn image_shape(&self) -> [usize; 2] {
+        self.dataset.image_shape()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::synthetic::SyntheticDigits;
+
+    // 16x16 pixels, row-major values from `level`
+    fn image(level: impl Fn(u32) -> u8) -> GrayImage {
+        GrayImage::from_fn(16, 16, |x, y| Luma([level(y * 16 + x)]))
+    }
+
+    #[test]
+    fn equalization_spreads_equally_filled_levels_evenly() {
+        // 16 levels of 16 pixels each
+        let equalized = equalize_histogram(&image(|index| 100 + (index / 16) as u8));
+        let counts = histogram(&equalized);
+        for level in 0..16 {
+            assert_eq!(counts[level * 17], 16, "level {}", level * 17);
+        }
+        let single_level = image(|_| 42);
+        assert_eq!(equalize_histogram(&single_level), single_level);
+    }
+
+    #[test]
+    fn stretching_maps_the_percentiles_to_the_full_range() {
+        // Levels 64 to 191, two pixels each
+        let ramp = image(|index| 64 + (index / 2) as u8);
+        let stretched = contrast_stretch(&ramp, 0.0, 100.0);
+        let levels: Vec<u8> = stretched.pixels().map(|Luma([value])| *value).collect();
+        assert_eq!((levels[0], levels[255]), (0, 255));
+        assert!(levels.windows(2).all(|pair| pair[0] <= pair[1]));
+
+        // 5% and 95% of 256 pixels: the 7 darkest and the 7 brightest levels clip
+        let clipped = histogram(&contrast_stretch(&ramp, 5.0, 95.0));
+        assert_eq!((clipped[0], clipped[255]), (14, 14));
+        assert_eq!(contrast_stretch(&image(|_| 9), 5.0, 95.0), image(|_| 9));
+    }
+
+    #[test]
+    fn clahe_raises_local_contrast() {
+        let uniform = clahe(&image(|_| 80), 4, 2.0);
+        assert!(uniform.pixels().all(|pixel| pixel == uniform.get_pixel(0, 0)));
+
+        // A dim, low-contrast gradient along every row
+        let dim = image(|index| 40 + (index % 16) as u8);
+        let enhanced = clahe(&dim, 4, 40.0);
+        let range = |image: &GrayImage| {
+            let levels = image.pixels().map(|Luma([value])| *value);
+            levels.clone().max().unwrap() - levels.min().unwrap()
+        };
+        assert!(range(&enhanced) > 2 * range(&dim), "range {} from {}", range(&enhanced), range(&dim));
+        // Tiles only blend along the gradient: the rows stay identical
+        for x in 0..16 {
+            assert!((0..16).all(|y| enhanced.get_pixel(x, y) == enhanced.get_pixel(x, 0)));
+        }
+    }
+
+    #[test]
+    fn datasets_and_raw_images_go_through_the_same_steps() {
+        let steps =
+            vec![PreprocessConfig::Stretch { low_percentile: 1.0, high_percentile: 99.0 }, PreprocessConfig::Equalize];
+        let digits = SyntheticDigits::new(3, 1);
+        let preprocessed = PreprocessedDataset::new(SyntheticDigits::new(3, 1), steps.clone());
+
+        for index in 0..3 {
+            let (raw, label) = ClassificationDataset::get(&digits, index).unwrap();
+            let (pixels, preprocessed_label) = preprocessed.get(index).unwrap();
+            assert_eq!(label, preprocessed_label);
+            let mut image = [[0.0; 28]; 28];
+            for (position, value) in raw.iter().enumerate() {
+                image[position / 28][position % 28] = *value;
+            }
+            assert_eq!(apply_steps_raw(&steps, &image).concat(), pixels);
+            assert_eq!(apply_steps_raw(&[], &image), image);
+        }
+    }
+}
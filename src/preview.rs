@@ -0,0 +1,328 @@
+use crate::{
+    data::{ClassificationDataset, ClassificationItem, MnistBatch},
+    inference::{Prediction, DEFAULT_TOP_K},
+    labels::ClassLabels,
+    step_valid::valid_subset,
+};
+use burn::{
+    data::dataloader::{DataLoader, DataLoaderIterator, Progress},
+    prelude::*,
+    train::metric::{Metric, MetricEntry, MetricMetadata},
+};
+use image::{Rgb, RgbImage};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::OpenOptions,
+    io::{self, Write},
+    path::PathBuf,
+};
+
+// Where `preview_samples` writes the previews, inside the artifact dir
+pub const PREVIEW_DIR: &str = "previews";
+// The predictions of every epoch, one line each, inside `PREVIEW_DIR`
+pub const PREVIEW_PREDICTIONS_FILE: &str = "predictions.jsonl";
+
+// Images are drawn at this many pixels per image pixel
+const PREVIEW_SCALE: u32 = 2;
+// Gap around the tiles of the grid, in pixels
+const PREVIEW_GAP: u32 = 6;
+// Labels are drawn with a 3x5 font, each font pixel this many pixels wide
+const FONT_SCALE: u32 = 2;
+// Height of the confidence bar under each label line
+const BAR_HEIGHT: u32 = 4;
+// Narrowest tile, room for two 2-digit class indices
+const MIN_TILE_WIDTH: u32 = 48;
+
+const BACKGROUND: Rgb<u8> = Rgb([255, 255, 255]);
+const TEXT: Rgb<u8> = Rgb([0, 0, 0]);
+const CORRECT: Rgb<u8> = Rgb([0, 140, 0]);
+const WRONG: Rgb<u8> = Rgb([200, 0, 0]);
+const BAR_BACKGROUND: Rgb<u8> = Rgb([220, 220, 220]);
+
+// 3x5 digits, one row of three bits per entry, most significant bit on the left
+const DIGITS: [[u8; 5]; 10] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b111, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b010, 0b010, 0b010],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+];
+
+// The validation samples previewed at every epoch end: drawn from the seed once (or listed in the
+// config), the same for every epoch so that the progression is comparable
+#[derive(Clone)]
+pub struct PreviewSamples {
+    // Indices of the samples in the validation set, ascending when drawn
+    pub indices: Vec<usize>,
+    // Row-major pixels of each sample, on the MNIST scale, and its label
+    pub images: Vec<(Vec<f32>, usize)>,
+    pub image_shape: [usize; 2],
+}
+
+impl PreviewSamples {
+    pub fn select<D: ClassificationDataset + ?Sized>(dataset: &D, num_samples: usize, seed: u64) -> Self {
+        let indices = valid_subset(dataset.len(), num_samples.min(dataset.len()), seed);
+        Self::from_indices(dataset, indices)
+    }
+
+    // The samples at `indices`, in that order. Unreadable ones are skipped.
+    pub fn from_indices<D: ClassificationDataset + ?Sized>(dataset: &D, indices: Vec<usize>) -> Self {
+        let (indices, images) = indices.into_iter().filter_map(|index| Some((index, dataset.get(index)?))).unzip();
+        Self { indices, images, image_shape: dataset.image_shape() }
+    }
+
+    pub fn items(&self) -> Vec<ClassificationItem> {
+        self.images
+            .iter()
+            .map(|(pixels, label)| ClassificationItem {
+                pixels: pixels.clone(),
+                shape: self.image_shape,
+                label: *label,
+                index: None,
+            })
+            .collect()
+    }
+}
+
+// Attaches the preview batch to the first batch of every epoch of a validation dataloader, so
+// the validation step predicts it once per epoch with the current weights
+pub struct PreviewDataLoader<B: Backend> {
+    inner: Box<dyn DataLoader<MnistBatch<B>>>,
+    preview: MnistBatch<B>,
+}
+
+impl<B: Backend> PreviewDataLoader<B> {
+    pub fn new(inner: Box<dyn DataLoader<MnistBatch<B>>>, preview: MnistBatch<B>) -> Self {
+        Self { inner, preview }
+    }
+}
+
+struct PreviewIterator<'a, B: Backend> {
+    inner: Box<dyn DataLoaderIterator<MnistBatch<B>> + 'a>,
+    preview: Option<MnistBatch<B>>,
+}
+
+impl<B: Backend> DataLoader<MnistBatch<B>> for PreviewDataLoader<B> {
+    fn iter<'a>(&'a self) -> Box<dyn DataLoaderIterator<MnistBatch<B>> + 'a> {
+        Box::new(PreviewIterator { inner: self.inner.iter(), preview: Some(self.preview.clone()) })
+    }
+
+    fn num_items(&self) -> usize {
+        self.inner.num_items()
+    }
+}
+
+impl<B: Backend> Iterator for PreviewIterator<'_, B> {
+    type Item = MnistBatch<B>;
+
+    fn next(&mut self) -> Option<MnistBatch<B>> {
+        let mut batch = self.inner.next()?;
+        if let Some(preview) = self.preview.take() {
+            batch.preview = Some(Box::new(preview));
+        }
+        Some(batch)
+    }
+}
+
+impl<B: Backend> DataLoaderIterator<MnistBatch<B>> for PreviewIterator<'_, B> {
+    fn progress(&self) -> Progress {
+        self.inner.progress()
+    }
+}
+
+// Class probabilities `[num_samples * num_classes]` of the preview samples, row-major: only the
+// validation step that carried the preview batch has them
+pub struct PreviewInput {
+    probabilities: Option<Vec<f32>>,
+}
+
+impl PreviewInput {
+    pub fn new(probabilities: Option<Vec<f32>>) -> Self {
+        Self { probabilities }
+    }
+}
+
+// The prediction for one preview sample at one epoch end
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PreviewPrediction {
+    // Index of the sample in the validation set
+    pub index: usize,
+    pub target: usize,
+    pub target_label: String,
+    pub prediction: Prediction,
+}
+
+// One line of `previews/predictions.jsonl`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PreviewRecord {
+    pub epoch: usize,
+    pub num_correct: usize,
+    pub predictions: Vec<PreviewPrediction>,
+}
+
+fn draw_rect(image: &mut RgbImage, x: u32, y: u32, width: u32, height: u32, color: Rgb<u8>) {
+    for dy in 0..height {
+        for dx in 0..width {
+            if x + dx < image.width() && y + dy < image.height() {
+                image.put_pixel(x + dx, y + dy, color);
+            }
+        }
+    }
+}
+
+// Draws `number` at `(x, y)` in the 3x5 font
+fn draw_number(image: &mut RgbImage, x: u32, y: u32, number: usize, color: Rgb<u8>) {
+    let mut x = x;
+    for digit in number.to_string().bytes().map(|byte| (byte - b'0') as usize) {
+        for (row, bits) in DIGITS[digit].iter().enumerate() {
+            for column in 0..3 {
+                if bits & (0b100 >> column) != 0 {
+                    let (px, py) = (x + column * FONT_SCALE, y + row as u32 * FONT_SCALE);
+                    draw_rect(image, px, py, FONT_SCALE, FONT_SCALE, color);
+                }
+            }
+        }
+        x += 4 * FONT_SCALE;
+    }
+}
+
+/// Draws the preview samples with their predictions as a grid, one tile per sample: the image,
+/// then the true class index on the left and the predicted one on the right (green when right,
+/// red when wrong), over a bar filled to the confidence of the prediction. Class names are in
+/// the predictions JSONL, the tiles only have room for indices.
+pub fn preview_grid(samples: &PreviewSamples, predictions: &[PreviewPrediction]) -> RgbImage {
+    let [height, width] = samples.image_shape;
+    let tile_width = (width as u32 * PREVIEW_SCALE).max(MIN_TILE_WIDTH);
+    let label_height = 5 * FONT_SCALE + 2;
+    let tile_height = height as u32 * PREVIEW_SCALE + 2 + label_height + BAR_HEIGHT;
+
+    let columns = (predictions.len() as f64).sqrt().ceil().max(1.0) as u32;
+    let rows = (predictions.len() as u32).div_ceil(columns).max(1);
+    let mut grid = RgbImage::from_pixel(
+        columns * (tile_width + PREVIEW_GAP) + PREVIEW_GAP,
+        rows * (tile_height + PREVIEW_GAP) + PREVIEW_GAP,
+        BACKGROUND,
+    );
+
+    for (slot, ((pixels, _), prediction)) in samples.images.iter().zip(predictions).enumerate() {
+        let x0 = PREVIEW_GAP + (slot as u32 % columns) * (tile_width + PREVIEW_GAP);
+        let y0 = PREVIEW_GAP + (slot as u32 / columns) * (tile_height + PREVIEW_GAP);
+        for (offset, &pixel) in pixels.iter().enumerate() {
+            let (x, y) = ((offset % width) as u32, (offset / width) as u32);
+            let value = pixel.clamp(0.0, 255.0) as u8;
+            draw_rect(&mut grid, x0 + x * PREVIEW_SCALE, y0 + y * PREVIEW_SCALE, PREVIEW_SCALE, PREVIEW_SCALE, Rgb([value; 3]));
+        }
+
+        let correct = prediction.prediction.index == prediction.target;
+        let color = if correct { CORRECT } else { WRONG };
+        let label_y = y0 + height as u32 * PREVIEW_SCALE + 2;
+        draw_number(&mut grid, x0, label_y, prediction.target, TEXT);
+        let predicted_width = prediction.prediction.index.to_string().len() as u32 * 4 * FONT_SCALE - FONT_SCALE;
+        draw_number(&mut grid, x0 + tile_width - predicted_width, label_y, prediction.prediction.index, color);
+
+        let bar_y = label_y + label_height;
+        draw_rect(&mut grid, x0, bar_y, tile_width, BAR_HEIGHT, BAR_BACKGROUND);
+        let filled = (prediction.prediction.confidence.clamp(0.0, 1.0) * tile_width as f32).round() as u32;
+        draw_rect(&mut grid, x0, bar_y, filled, BAR_HEIGHT, color);
+    }
+    grid
+}
+
+/// Writes `previews/epoch-XX.png` and appends to `previews/predictions.jsonl` at every epoch
+/// end, from the probabilities the validation step computed on the preview batch. Logs the
+/// number of correctly predicted previews.
+pub struct PreviewMetric {
+    dir: PathBuf,
+    samples: PreviewSamples,
+    labels: ClassLabels,
+    num_correct: usize,
+}
+
+impl PreviewMetric {
+    pub fn new(artifact_dir: &str, samples: PreviewSamples, labels: ClassLabels) -> io::Result<Self> {
+        let dir = PathBuf::from(artifact_dir).join(PREVIEW_DIR);
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self { dir, samples, labels, num_correct: 0 })
+    }
+
+    fn save(&self, record: &PreviewRecord) -> io::Result<()> {
+        let png = self.dir.join(format!("epoch-{:02}.png", record.epoch));
+        preview_grid(&self.samples, &record.predictions).save(&png).map_err(io::Error::other)?;
+
+        let line = serde_json::to_string(record).expect("Preview predictions should serialize to JSON");
+        let mut file = OpenOptions::new().create(true).append(true).open(self.dir.join(PREVIEW_PREDICTIONS_FILE))?;
+        writeln!(file, "{line}")
+    }
+}
+
+impl Metric for PreviewMetric {
+    const NAME: &'static str = "Preview";
+
+    type Input = PreviewInput;
+
+    fn update(&mut self, item: &PreviewInput, metadata: &MetricMetadata) -> MetricEntry {
+        if let Some(probabilities) = &item.probabilities {
+            let num_classes = probabilities.len() / self.samples.images.len().max(1);
+            let predictions: Vec<PreviewPrediction> = self
+                .samples
+                .indices
+                .iter()
+                .zip(&self.samples.images)
+                .zip(probabilities.chunks(num_classes.max(1)))
+                .map(|((&index, &(_, target)), probabilities)| PreviewPrediction {
+                    index,
+                    target,
+                    target_label: self.labels.name(target),
+                    prediction: Prediction::from_probabilities(probabilities.to_vec(), &self.labels, DEFAULT_TOP_K),
+                })
+                .collect();
+            self.num_correct = predictions.iter().filter(|preview| preview.prediction.index == preview.target).count();
+
+            let record = PreviewRecord { epoch: metadata.epoch, num_correct: self.num_correct, predictions };
+            if let Err(err) = self.save(&record) {
+                eprintln!("Could not write the previews of epoch {} to {}: {err}", metadata.epoch, self.dir.display());
+            }
+        }
+        let formatted = format!("{}/{} correct", self.num_correct, self.samples.images.len());
+        MetricEntry::new(Self::NAME.to_string(), formatted, self.num_correct.to_string())
+    }
+
+    fn clear(&mut self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::synthetic::{temp_artifact_dir, tiny_config, train_synthetic};
+
+    #[test]
+    fn every_epoch_previews_the_same_samples() {
+        let artifact_dir = temp_artifact_dir("preview");
+        let config = tiny_config().with_preview_samples(Some(6)).with_num_epochs(3);
+
+        train_synthetic(&artifact_dir, config, 32, 32).unwrap();
+        let dir = artifact_dir.join(PREVIEW_DIR);
+        let grids: Vec<bool> = (1..=4).map(|epoch| dir.join(format!("epoch-{epoch:02}.png")).exists()).collect();
+        let grid = image::open(dir.join("epoch-01.png")).unwrap();
+        let lines = std::fs::read_to_string(dir.join(PREVIEW_PREDICTIONS_FILE)).unwrap();
+        std::fs::remove_dir_all(&artifact_dir).unwrap();
+
+        assert_eq!(grids, [true, true, true, false]);
+        assert!(grid.width() > 0 && grid.height() > 0);
+        let records: Vec<PreviewRecord> = lines.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(records.iter().map(|record| record.epoch).collect::<Vec<_>>(), [1, 2, 3]);
+        let indices = |record: &PreviewRecord| record.predictions.iter().map(|preview| preview.index).collect::<Vec<_>>();
+        assert_eq!(indices(&records[0]).len(), 6);
+        assert!(records.iter().all(|record| indices(record) == indices(&records[0])));
+        for preview in &records[0].predictions {
+            // Synthetic digits are labeled by their index
+            assert_eq!(preview.target, preview.index % 10);
+            assert_eq!(preview.target_label, preview.target.to_string());
+        }
+    }
+}
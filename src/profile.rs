@@ -0,0 +1,315 @@
+use burn::{
+    data::dataloader::{DataLoader, DataLoaderIterator, Progress},
+    module::AutodiffModule,
+    optim::{GradientsParams, Optimizer},
+    record::{FileRecorder, Record, Recorder, RecorderError},
+    tensor::backend::{AutodiffBackend, Backend},
+    LearningRate,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Value};
+use std::{
+    cell::Cell,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Instant,
+};
+
+// Spans of a training run, collected by the hooks below while a profile is active and written
+// out in the Chrome trace event format (chrome://tracing, Perfetto). The hooks are reached from
+// burn's learner, which only hands them its own arguments, so the profile is process-wide:
+// there can only be one profiled run at a time.
+//
+// Spans time the host side. GPU backends queue work asynchronously, so on those a forward span
+// mostly measures the enqueueing, and the wait for results shows up wherever it is read back.
+struct Profile {
+    start: Instant,
+    // Per-step spans are only recorded for the first `max_steps` steps of each epoch
+    max_steps: usize,
+    epoch: usize,
+    step: usize,
+    epoch_start: Option<Instant>,
+    step_start: Option<Instant>,
+    events: Vec<Value>,
+}
+
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+static PROFILE: Mutex<Option<Profile>> = Mutex::new(None);
+static NEXT_TID: AtomicU64 = AtomicU64::new(1);
+
+thread_local! {
+    // Small per-thread track ids for the trace viewer, 0 until the thread's first event
+    static TID: Cell<u64> = const { Cell::new(0) };
+}
+
+fn thread_id() -> (u64, bool) {
+    TID.with(|tid| match tid.get() {
+        0 => {
+            let id = NEXT_TID.fetch_add(1, Ordering::Relaxed);
+            tid.set(id);
+            (id, true)
+        }
+        id => (id, false),
+    })
+}
+
+fn with_profile(f: impl FnOnce(&mut Profile)) {
+    if !ACTIVE.load(Ordering::Relaxed) {
+        return;
+    }
+    if let Some(profile) = PROFILE.lock().unwrap().as_mut() {
+        f(profile);
+    }
+}
+
+impl Profile {
+    fn micros(&self, instant: Instant) -> f64 {
+        instant.saturating_duration_since(self.start).as_secs_f64() * 1e6
+    }
+
+    fn complete(&mut self, name: &str, category: &str, start: Instant, end: Instant) {
+        let (tid, new_thread) = thread_id();
+        if new_thread {
+            let thread = std::thread::current();
+            let thread_name = thread.name().map_or_else(|| format!("thread {tid}"), str::to_string);
+            self.events.push(json!({
+                "name": "thread_name", "ph": "M", "pid": 1, "tid": tid,
+                "args": { "name": thread_name },
+            }));
+        }
+        self.events.push(json!({
+            "name": name, "cat": category, "ph": "X", "pid": 1, "tid": tid,
+            "ts": self.micros(start),
+            "dur": end.saturating_duration_since(start).as_secs_f64() * 1e6,
+        }));
+    }
+
+    fn in_profiled_step(&self) -> bool {
+        self.step < self.max_steps
+    }
+}
+
+// Starts collecting spans, keeping the per-step ones for the first `max_steps` steps of every
+// epoch. Replaces any profile that was not finished.
+pub fn start(max_steps: usize) {
+    *PROFILE.lock().unwrap() = Some(Profile {
+        start: Instant::now(),
+        max_steps,
+        epoch: 0,
+        step: 0,
+        epoch_start: None,
+        step_start: None,
+        events: Vec::new(),
+    });
+    ACTIVE.store(true, Ordering::Relaxed);
+}
+
+// Stops collecting and writes the trace JSON to `path`
+pub fn finish(path: &Path) -> io::Result<()> {
+    ACTIVE.store(false, Ordering::Relaxed);
+    let Some(profile) = PROFILE.lock().unwrap().take() else {
+        return Ok(());
+    };
+    let trace = json!({ "traceEvents": profile.events, "displayTimeUnit": "ms" });
+    fs::write(path, serde_json::to_string(&trace).expect("Trace should serialize to JSON"))
+}
+
+// Times a training step phase (forward, backward) of a profiled step
+pub fn step_span<T>(name: &str, f: impl FnOnce() -> T) -> T {
+    if !ACTIVE.load(Ordering::Relaxed) {
+        return f();
+    }
+    let start = Instant::now();
+    let result = f();
+    let end = Instant::now();
+    with_profile(|profile| {
+        if profile.in_profiled_step() {
+            profile.complete(name, "step", start, end);
+        }
+    });
+    result
+}
+
+// Times a pass the training loop runs between steps, such as hard-negative mining, whatever
+// step it comes after
+pub fn span<T>(name: &str, category: &str, f: impl FnOnce() -> T) -> T {
+    if !ACTIVE.load(Ordering::Relaxed) {
+        return f();
+    }
+    let start = Instant::now();
+    let result = f();
+    let end = Instant::now();
+    with_profile(|profile| profile.complete(name, category, start, end));
+    result
+}
+
+// Whether the dataloader feeds training or validation: training iterations open the epoch and
+// its steps, the end of validation closes the epoch
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LoaderKind {
+    Train,
+    Valid,
+}
+
+// Records the epoch, validation and batch load spans around a learner dataloader
+pub struct ProfiledDataLoader<O> {
+    inner: Box<dyn DataLoader<O>>,
+    kind: LoaderKind,
+}
+
+impl<O> ProfiledDataLoader<O> {
+    pub fn new(inner: Box<dyn DataLoader<O>>, kind: LoaderKind) -> Self {
+        Self { inner, kind }
+    }
+}
+
+struct ProfiledIterator<'a, O> {
+    inner: Box<dyn DataLoaderIterator<O> + 'a>,
+    kind: LoaderKind,
+    start: Instant,
+}
+
+impl<O> DataLoader<O> for ProfiledDataLoader<O> {
+    fn iter<'a>(&'a self) -> Box<dyn DataLoaderIterator<O> + 'a> {
+        let start = Instant::now();
+        if self.kind == LoaderKind::Train {
+            with_profile(|profile| {
+                profile.epoch += 1;
+                profile.step = 0;
+                profile.epoch_start = Some(start);
+            });
+        }
+        Box::new(ProfiledIterator { inner: self.inner.iter(), kind: self.kind, start })
+    }
+
+    fn num_items(&self) -> usize {
+        self.inner.num_items()
+    }
+}
+
+impl<O> Iterator for ProfiledIterator<'_, O> {
+    type Item = O;
+
+    fn next(&mut self) -> Option<O> {
+        let start = Instant::now();
+        let item = self.inner.next();
+        let end = Instant::now();
+
+        with_profile(|profile| match (self.kind, &item) {
+            (LoaderKind::Train, Some(_)) => {
+                profile.step_start = Some(start);
+                if profile.in_profiled_step() {
+                    profile.complete("batch load", "step", start, end);
+                }
+            }
+            (LoaderKind::Valid, None) => {
+                profile.complete("validation", "epoch", self.start, end);
+                if let Some(epoch_start) = profile.epoch_start.take() {
+                    let name = format!("epoch {}", profile.epoch);
+                    profile.complete(&name, "epoch", epoch_start, end);
+                }
+            }
+            _ => {}
+        });
+        item
+    }
+}
+
+impl<O> DataLoaderIterator<O> for ProfiledIterator<'_, O> {
+    fn progress(&self) -> Progress {
+        self.inner.progress()
+    }
+}
+
+// Records the optimizer step, and closes the span of the whole training step
+pub struct ProfiledOptimizer<O> {
+    inner: O,
+}
+
+impl<O> ProfiledOptimizer<O> {
+    pub fn new(inner: O) -> Self {
+        Self { inner }
+    }
+}
+
+impl<M, B, O> Optimizer<M, B> for ProfiledOptimizer<O>
+where
+    M: AutodiffModule<B>,
+    B: AutodiffBackend,
+    O: Optimizer<M, B>,
+{
+    type Record = O::Record;
+
+    fn step(&mut self, lr: LearningRate, module: M, grads: GradientsParams) -> M {
+        let start = Instant::now();
+        let module = self.inner.step(lr, module, grads);
+        let end = Instant::now();
+
+        with_profile(|profile| {
+            if profile.in_profiled_step() {
+                profile.complete("optimizer step", "step", start, end);
+                if let Some(step_start) = profile.step_start.take() {
+                    let name = format!("step {}", profile.step + 1);
+                    profile.complete(&name, "step", step_start, end);
+                }
+            }
+            profile.step += 1;
+        });
+        module
+    }
+
+    fn to_record(&self) -> Self::Record {
+        self.inner.to_record()
+    }
+
+    fn load_record(self, record: Self::Record) -> Self {
+        Self { inner: self.inner.load_record(record) }
+    }
+}
+
+// Records every learner checkpoint write. Burn writes checkpoints from its own thread, so they
+// show up on a track of their own.
+#[derive(Clone, Debug, Default)]
+pub struct ProfiledRecorder<R> {
+    inner: R,
+}
+
+impl<R> ProfiledRecorder<R> {
+    pub fn new(inner: R) -> Self {
+        Self { inner }
+    }
+}
+
+impl<B: Backend, R: FileRecorder<B>> Recorder<B> for ProfiledRecorder<R> {
+    type Settings = R::Settings;
+    type RecordArgs = PathBuf;
+    type RecordOutput = ();
+    type LoadArgs = PathBuf;
+
+    // Delegated as a whole so the file metadata names the inner recorder
+    fn record<T: Record<B>>(&self, record: T, args: PathBuf) -> Result<(), RecorderError> {
+        let start = Instant::now();
+        let result = self.inner.record(record, args);
+        let end = Instant::now();
+        with_profile(|profile| profile.complete("checkpoint write", "checkpoint", start, end));
+        result
+    }
+
+    fn save_item<I: Serialize>(&self, item: I, args: PathBuf) -> Result<(), RecorderError> {
+        self.inner.save_item(item, args)
+    }
+
+    fn load_item<I: DeserializeOwned>(&self, args: PathBuf) -> Result<I, RecorderError> {
+        self.inner.load_item(args)
+    }
+}
+
+impl<B: Backend, R: FileRecorder<B>> FileRecorder<B> for ProfiledRecorder<R> {
+    fn file_extension() -> &'static str {
+        R::file_extension()
+    }
+}
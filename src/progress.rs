@@ -0,0 +1,376 @@
+#[cfg(feature = "status-server")]
+use crate::status::{StatusSender, StatusUpdate};
+use crate::{history::EpochMetrics, prometheus::PrometheusExporter, training::Verbosity};
+use burn::train::{
+    renderer::{MetricState, MetricsRenderer, SelectedMetricsRenderer, TrainingProgress},
+    TrainingInterrupter,
+};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::mpsc::Sender,
+    time::Instant,
+};
+
+/// Training progress, streamed by [`train_with_progress`](crate::training::train_with_progress).
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProgressEvent {
+    /// An epoch (1-based) started training.
+    EpochStarted { epoch: usize },
+    /// A training step completed. `step` counts from 1 across epochs, `loss` is the batch loss
+    /// averaged over the last `loss_smoothing_window` steps of the config.
+    BatchCompleted { step: usize, loss: f64 },
+    /// An epoch finished training and validation, with the mean of every metric over each split
+    /// (as in `history.json`).
+    EpochCompleted { metrics: EpochMetrics },
+}
+
+// Per-metric weighted sum and total weight of the values logged during the current epoch
+type Sums = BTreeMap<String, (f64, usize)>;
+
+fn means(sums: &Sums) -> BTreeMap<String, f64> {
+    sums.iter().map(|(name, (sum, count))| (name.clone(), sum / *count as f64)).collect()
+}
+
+// "Loss 0.1234, Accuracy 97.50", in metric name order
+fn format_means(means: &BTreeMap<String, f64>) -> String {
+    let values: Vec<String> = means.iter().map(|(name, value)| format!("{name} {value:.4}")).collect();
+    values.join(", ")
+}
+
+/// Moving average of the last `window` values pushed, across epochs: the training loss as the
+/// dashboard and the progress events show it. A window of 1 passes values through.
+#[derive(Debug, Clone)]
+pub struct LossSmoother {
+    window: usize,
+    values: VecDeque<f64>,
+    sum: f64,
+}
+
+impl LossSmoother {
+    pub fn new(window: usize) -> Self {
+        let window = window.max(1);
+        Self { window, values: VecDeque::with_capacity(window), sum: 0.0 }
+    }
+
+    /// Adds `value` and returns the mean of the window, over the values pushed so far until
+    /// it fills up.
+    pub fn push(&mut self, value: f64) -> f64 {
+        if self.values.len() == self.window {
+            self.sum -= self.values.pop_front().expect("A full window");
+        }
+        self.values.push_back(value);
+        self.sum += value;
+        self.sum / self.values.len() as f64
+    }
+}
+
+// Sends progress events from what the learner hands its renderer (to `sender`, when there is
+// one), then passes everything on to burn's own renderer with `Verbosity::Full` so the dashboard
+// stays as it is. `Verbosity::Summary` prints one line per epoch instead, `Verbosity::Silent`
+// nothing. A dropped receiver only stops the events.
+pub struct ProgressRenderer {
+    inner: Option<SelectedMetricsRenderer>,
+    sender: Option<Sender<ProgressEvent>>,
+    print_epochs: bool,
+    // Whether the epoch metrics carry their `accuracy_gap`
+    accuracy_gap: bool,
+    epoch: usize,
+    num_epochs: usize,
+    step: usize,
+    loss: f64,
+    smoother: LossSmoother,
+    train: Sums,
+    valid: Sums,
+    // When the current epoch started, and the training items it went through so far
+    epoch_start: Instant,
+    epoch_items: usize,
+    // Writes `metrics.prom` at every epoch end, with `prometheus_metrics`
+    prometheus: Option<PrometheusExporter>,
+    // Where the `status_port` server takes its updates from
+    #[cfg(feature = "status-server")]
+    status: Option<StatusSender>,
+}
+
+impl ProgressRenderer {
+    // `interrupter` is the learner's, so that quitting the dashboard still stops training
+    pub fn new(
+        sender: Option<Sender<ProgressEvent>>,
+        interrupter: TrainingInterrupter,
+        verbosity: Verbosity,
+        accuracy_gap: bool,
+        loss_smoothing_window: usize,
+    ) -> Self {
+        Self {
+            inner: (verbosity == Verbosity::Full).then(|| SelectedMetricsRenderer::new(interrupter, None)),
+            sender,
+            print_epochs: verbosity == Verbosity::Summary,
+            accuracy_gap,
+            epoch: 0,
+            num_epochs: 0,
+            step: 0,
+            loss: f64::NAN,
+            smoother: LossSmoother::new(loss_smoothing_window),
+            train: Sums::new(),
+            valid: Sums::new(),
+            epoch_start: Instant::now(),
+            epoch_items: 0,
+            prometheus: None,
+            #[cfg(feature = "status-server")]
+            status: None,
+        }
+    }
+
+    // Also writes the metrics of every epoch with `prometheus`
+    pub(crate) fn with_prometheus(mut self, prometheus: Option<PrometheusExporter>) -> Self {
+        self.prometheus = prometheus;
+        self
+    }
+
+    // Also pushes every training step and epoch to the status server of `status`
+    #[cfg(feature = "status-server")]
+    pub(crate) fn with_status(mut self, status: Option<StatusSender>) -> Self {
+        self.status = status;
+        self
+    }
+
+    #[cfg(feature = "status-server")]
+    fn update_status(&self, update: StatusUpdate) {
+        if let Some(status) = &self.status {
+            // The server only stops once training is over
+            status.send(update).ok();
+        }
+    }
+
+    fn send(&self, event: ProgressEvent) {
+        if let Some(sender) = &self.sender {
+            // The receiver is gone, nobody is listening anymore
+            sender.send(event).ok();
+        }
+    }
+
+    // Reports the current epoch as completed, once
+    fn complete_epoch(&mut self) {
+        if self.epoch == 0 || (self.train.is_empty() && self.valid.is_empty()) {
+            return;
+        }
+        let mut metrics = EpochMetrics {
+            epoch: self.epoch,
+            train: means(&self.train),
+            valid: means(&self.valid),
+            accuracy_gap: None,
+            fraction: None,
+        };
+        if self.accuracy_gap {
+            metrics.accuracy_gap = metrics.split_gap("Accuracy");
+        }
+        self.train.clear();
+        self.valid.clear();
+        #[cfg(feature = "status-server")]
+        if !metrics.valid.is_empty() {
+            self.update_status(StatusUpdate::Valid(metrics.valid.clone()));
+        }
+        if let Some(prometheus) = &self.prometheus {
+            let duration = self.epoch_start.elapsed();
+            if let Err(err) = prometheus.write(&metrics, self.num_epochs, self.epoch_items, duration) {
+                eprintln!("Warning: could not write the Prometheus metrics: {err}");
+            }
+        }
+        if self.print_epochs {
+            let gap = metrics.accuracy_gap.map_or(String::new(), |gap| format!(" | accuracy gap {gap:+.4}"));
+            println!(
+                "Epoch {}/{}: train {} | valid {}{gap}",
+                metrics.epoch,
+                self.num_epochs,
+                format_means(&metrics.train),
+                format_means(&metrics.valid)
+            );
+        }
+        self.send(ProgressEvent::EpochCompleted { metrics });
+    }
+}
+
+// Batch values are weighted by their batch size when the metric logs one (`value,batch_size`),
+// which is how burn aggregates the logs `history.json` is read from
+fn record(sums: &mut Sums, state: &MetricState) {
+    if let MetricState::Numeric(entry, value) = state {
+        let weight = match entry.serialize.split_once(',') {
+            Some((_, batch_size)) => batch_size.parse().unwrap_or(1),
+            None => 1,
+        };
+        let (sum, count) = sums.entry(entry.name.clone()).or_insert((0.0, 0));
+        *sum += value * weight as f64;
+        *count += weight;
+    }
+}
+
+impl MetricsRenderer for ProgressRenderer {
+    // The epoch sums take the raw loss, the dashboard the smoothed one
+    fn update_train(&mut self, mut state: MetricState) {
+        record(&mut self.train, &state);
+        if let MetricState::Numeric(entry, value) = &mut state {
+            if entry.name == "Loss" {
+                self.loss = self.smoother.push(*value);
+                if self.smoother.window > 1 {
+                    // burn formats it as "epoch <mean> - batch <loss>"
+                    let epoch = entry.formatted.split(" - batch").next().unwrap_or_default();
+                    entry.formatted = format!("{epoch} - smoothed {:.4}", self.loss);
+                    *value = self.loss;
+                }
+            }
+            #[cfg(feature = "status-server")]
+            self.update_status(StatusUpdate::Train { name: entry.name.clone(), value: *value });
+        }
+        if let Some(inner) = &mut self.inner {
+            inner.update_train(state);
+        }
+    }
+
+    fn update_valid(&mut self, state: MetricState) {
+        record(&mut self.valid, &state);
+        if let Some(inner) = &mut self.inner {
+            inner.update_valid(state);
+        }
+    }
+
+    // Called after the metric updates of each training step
+    fn render_train(&mut self, item: TrainingProgress) {
+        if item.epoch != self.epoch {
+            // The metrics of the step that opened the new epoch are already in `train`
+            let opening = std::mem::take(&mut self.train);
+            self.complete_epoch();
+            self.train = opening;
+            self.epoch = item.epoch;
+            self.num_epochs = item.epoch_total;
+            self.epoch_start = Instant::now();
+            self.send(ProgressEvent::EpochStarted { epoch: item.epoch });
+        }
+        self.step += 1;
+        self.epoch_items = item.progress.items_processed;
+        self.send(ProgressEvent::BatchCompleted { step: self.step, loss: self.loss });
+        #[cfg(feature = "status-server")]
+        self.update_status(StatusUpdate::Step {
+            epoch: item.epoch,
+            num_epochs: item.epoch_total,
+            items_processed: item.progress.items_processed,
+            items_total: item.progress.items_total,
+        });
+        if let Some(inner) = &mut self.inner {
+            inner.render_train(item);
+        }
+    }
+
+    fn render_valid(&mut self, item: TrainingProgress) {
+        let done = item.progress.items_processed >= item.progress.items_total;
+        if let Some(inner) = &mut self.inner {
+            inner.render_valid(item);
+        }
+        if done {
+            self.complete_epoch();
+        }
+    }
+}
+
+impl Drop for ProgressRenderer {
+    // An epoch without validation items is only known to be over once training is
+    fn drop(&mut self) {
+        self.complete_epoch();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        data::DatasetSource,
+        synthetic::{temp_artifact_dir, tiny_config},
+        training::train_with_progress,
+    };
+    use burn::backend::{ndarray::NdArrayDevice, Autodiff, NdArray};
+    use std::sync::mpsc;
+
+    #[test]
+    fn events_follow_the_epochs_and_steps_of_a_run() {
+        let artifact_dir = temp_artifact_dir("progress-events");
+        // Four batches per epoch
+        let config = tiny_config()
+            .with_dataset(DatasetSource::Synthetic { num_samples: 64, seed: 1 })
+            .with_num_epochs(2);
+        let (sender, receiver) = mpsc::channel();
+        train_with_progress::<Autodiff<NdArray>>(&artifact_dir, config, NdArrayDevice::default(), sender).unwrap();
+
+        let events: Vec<ProgressEvent> = receiver.into_iter().collect();
+        assert_eq!(events.len(), 2 * (1 + 4 + 1));
+        let mut step = 0;
+        for (epoch, events) in events.chunks(6).enumerate() {
+            assert_eq!(events[0], ProgressEvent::EpochStarted { epoch: epoch + 1 });
+            for event in &events[1..5] {
+                step += 1;
+                match event {
+                    ProgressEvent::BatchCompleted { step: event_step, loss } => {
+                        assert_eq!(*event_step, step);
+                        assert!(loss.is_finite());
+                    }
+                    event => panic!("expected a completed batch, got {event:?}"),
+                }
+            }
+            match &events[5] {
+                ProgressEvent::EpochCompleted { metrics } => {
+                    assert_eq!(metrics.epoch, epoch + 1);
+                    assert!(metrics.train.contains_key("Loss"));
+                    assert!(metrics.valid.contains_key("Accuracy"));
+                }
+                event => panic!("expected a completed epoch, got {event:?}"),
+            }
+        }
+
+        std::fs::remove_dir_all(&artifact_dir).unwrap();
+    }
+
+    #[test]
+    fn smoother_averages_the_last_window_values() {
+        let mut smoother = LossSmoother::new(3);
+        let smoothed: Vec<f64> = [1.0, 2.0, 3.0, 4.0, 8.0].into_iter().map(|value| smoother.push(value)).collect();
+        assert_eq!(smoothed, [1.0, 1.5, 2.0, 3.0, 5.0]);
+
+        // A window of 0 is clamped to one value
+        for window in [0, 1] {
+            let mut smoother = LossSmoother::new(window);
+            assert_eq!([3.0, -1.0].map(|value| smoother.push(value)), [3.0, -1.0]);
+        }
+    }
+
+    #[test]
+    fn batch_events_carry_the_loss_smoothed_across_epochs() {
+        let artifact_dir = temp_artifact_dir("progress-smoothing");
+        // Four batches per epoch
+        let config = tiny_config()
+            .with_dataset(DatasetSource::Synthetic { num_samples: 64, seed: 1 })
+            .with_loss_smoothing_window(3)
+            .with_num_epochs(2);
+        let (sender, receiver) = mpsc::channel();
+        train_with_progress::<Autodiff<NdArray>>(&artifact_dir, config, NdArrayDevice::default(), sender).unwrap();
+
+        let smoothed: Vec<f64> = receiver
+            .into_iter()
+            .filter_map(|event| match event {
+                ProgressEvent::BatchCompleted { loss, .. } => Some(loss),
+                _ => None,
+            })
+            .collect();
+        // The logs keep the loss of every batch
+        let raw: Vec<f64> = (1..=2)
+            .flat_map(|epoch| {
+                let log = std::fs::read_to_string(artifact_dir.join(format!("train/epoch-{epoch}/Loss.log"))).unwrap();
+                log.lines().map(|line| line.split(',').next().unwrap().parse::<f64>().unwrap()).collect::<Vec<_>>()
+            })
+            .collect();
+        std::fs::remove_dir_all(&artifact_dir).unwrap();
+
+        assert_eq!((smoothed.len(), raw.len()), (8, 8));
+        for (step, loss) in smoothed.iter().enumerate() {
+            let window = &raw[step.saturating_sub(2)..=step];
+            let expected = window.iter().sum::<f64>() / window.len() as f64;
+            assert!((loss - expected).abs() < 1e-6, "step {}: {loss} instead of {expected}", step + 1);
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use crate::data::MnistBatch;
+use burn::{
+    data::dataloader::{DataLoader, DataLoaderIterator, Progress},
+    prelude::*,
+    tensor::module::adaptive_avg_pool2d,
+};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Side of the training images at the 1-based `epoch` under `schedule`, a list of
+/// `(first_epoch, size)` entries sorted by epoch (see `TrainingConfig::progressive_resize`).
+/// `None` before the first entry: the images keep their size.
+pub fn scheduled_size(schedule: &[(usize, usize)], epoch: usize) -> Option<usize> {
+    schedule.iter().take_while(|&&(first_epoch, _)| first_epoch <= epoch).last().map(|&(_, size)| size)
+}
+
+// Averages `images` [batch_size, height, width] down to `size`x`size`
+pub fn downsample<B: Backend>(images: Tensor<B, 3>, size: usize) -> Tensor<B, 3> {
+    let [batch_size, height, width] = images.dims();
+    if [height, width] == [size, size] {
+        return images;
+    }
+    let pooled = adaptive_avg_pool2d(images.reshape([batch_size, 1, height, width]), [size, size]);
+    pooled.reshape([batch_size, size, size])
+}
+
+// Downsamples the batches of a training dataloader to the size `scheduled_size` gives their
+// epoch. Each `iter` is one epoch of the learner; only the images change, the targets and
+// indices stay as they are.
+pub struct ProgressiveResizeDataLoader<B: Backend> {
+    inner: Box<dyn DataLoader<MnistBatch<B>>>,
+    schedule: Vec<(usize, usize)>,
+    epoch: AtomicUsize,
+}
+
+impl<B: Backend> ProgressiveResizeDataLoader<B> {
+    pub fn new(inner: Box<dyn DataLoader<MnistBatch<B>>>, schedule: Vec<(usize, usize)>) -> Self {
+        Self { inner, schedule, epoch: AtomicUsize::new(0) }
+    }
+}
+
+struct ProgressiveResizeIterator<'a, B: Backend> {
+    inner: Box<dyn DataLoaderIterator<MnistBatch<B>> + 'a>,
+    size: Option<usize>,
+}
+
+impl<B: Backend> DataLoader<MnistBatch<B>> for ProgressiveResizeDataLoader<B> {
+    fn iter<'a>(&'a self) -> Box<dyn DataLoaderIterator<MnistBatch<B>> + 'a> {
+        let epoch = self.epoch.fetch_add(1, Ordering::Relaxed) + 1;
+        Box::new(ProgressiveResizeIterator { inner: self.inner.iter(), size: scheduled_size(&self.schedule, epoch) })
+    }
+
+    fn num_items(&self) -> usize {
+        self.inner.num_items()
+    }
+}
+
+impl<B: Backend> Iterator for ProgressiveResizeIterator<'_, B> {
+    type Item = MnistBatch<B>;
+
+    fn next(&mut self) -> Option<MnistBatch<B>> {
+        let batch = self.inner.next()?;
+        Some(match self.size {
+            Some(size) => MnistBatch { images: downsample(batch.images, size), ..batch },
+            None => batch,
+        })
+    }
+}
+
+impl<B: Backend> DataLoaderIterator<MnistBatch<B>> for ProgressiveResizeIterator<'_, B> {
+    fn progress(&self) -> Progress {
+        self.inner.progress()
+    }
+}
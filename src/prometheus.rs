@@ -0,0 +1,250 @@
+use crate::history::EpochMetrics;
+use std::{
+    fmt::Write as _,
+    fs, io,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+// Written into the artifact dir when `TrainingConfig::prometheus_metrics` is set
+pub const PROMETHEUS_FILE: &str = "metrics.prom";
+
+// Prefix of every exported metric name
+const PREFIX: &str = "mnist_training";
+
+/// Writes the progress of a run to `metrics.prom` in the Prometheus text exposition format,
+/// every epoch, for the textfile collector of the node exporter (or a cron job pushing it to a
+/// Pushgateway) to pick up. Each write replaces the whole file through a rename, so that a
+/// scrape never reads it half written.
+///
+/// Every metric is a gauge:
+///
+/// | Metric | Value |
+/// |---|---|
+/// | `mnist_training_epoch` | last completed epoch, from 1 |
+/// | `mnist_training_epochs_total` | epochs the run trains for |
+/// | `mnist_training_loss{split}` | mean loss of the epoch, `split` being `train` or `valid` |
+/// | `mnist_training_accuracy{split}` | mean accuracy of the epoch, in [0, 1] |
+/// | `mnist_training_items_per_second` | training items of the epoch over its duration, validation included |
+/// | `mnist_training_elapsed_seconds` | since training started |
+/// | `mnist_training_metric{name, split}` | mean of every metric logged over the epoch, as in `history.json` |
+#[derive(Debug)]
+pub struct PrometheusExporter {
+    path: PathBuf,
+    start: Instant,
+}
+
+impl PrometheusExporter {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into(), start: Instant::now() }
+    }
+
+    /// Replaces the file with the metrics of the epoch that just completed, which trained on
+    /// `num_items` items in `duration`.
+    pub fn write(&self, metrics: &EpochMetrics, num_epochs: usize, num_items: usize, duration: Duration) -> io::Result<()> {
+        let text = exposition(metrics, num_epochs, num_items, duration, self.start.elapsed());
+        let temporary = self.path.with_extension("prom.tmp");
+        fs::write(&temporary, text)?;
+        fs::rename(temporary, &self.path)
+    }
+}
+
+/// The Prometheus text exposition of the metrics of one epoch, see [`PrometheusExporter`].
+pub fn exposition(
+    metrics: &EpochMetrics,
+    num_epochs: usize,
+    num_items: usize,
+    duration: Duration,
+    elapsed: Duration,
+) -> String {
+    let mut text = String::new();
+    let mut gauge = |name: &str, help: &str, samples: Vec<(String, f64)>| {
+        if samples.is_empty() {
+            return;
+        }
+        writeln!(text, "# HELP {PREFIX}_{name} {help}").unwrap();
+        writeln!(text, "# TYPE {PREFIX}_{name} gauge").unwrap();
+        for (labels, value) in samples {
+            writeln!(text, "{PREFIX}_{name}{labels} {}", number(value)).unwrap();
+        }
+    };
+    let splits = [("train", &metrics.train), ("valid", &metrics.valid)];
+    let by_split = |metric: &str, scale: f64| -> Vec<(String, f64)> {
+        splits
+            .iter()
+            .filter_map(|(split, means)| means.get(metric).map(|value| (labels(&[("split", split)]), value * scale)))
+            .collect()
+    };
+
+    gauge("epoch", "Last completed training epoch, from 1.", vec![(String::new(), metrics.epoch as f64)]);
+    gauge("epochs_total", "Number of epochs the run trains for.", vec![(String::new(), num_epochs as f64)]);
+    gauge("loss", "Mean loss of the last completed epoch.", by_split("Loss", 1.0));
+    // burn logs the accuracy in percent
+    gauge("accuracy", "Mean accuracy of the last completed epoch, in [0, 1].", by_split("Accuracy", 0.01));
+    let seconds = duration.as_secs_f64();
+    if seconds > 0.0 {
+        gauge(
+            "items_per_second",
+            "Training items per second over the last completed epoch, validation included.",
+            vec![(String::new(), num_items as f64 / seconds)],
+        );
+    }
+    gauge("elapsed_seconds", "Seconds since training started.", vec![(String::new(), elapsed.as_secs_f64())]);
+    let all = splits
+        .iter()
+        .flat_map(|(split, means)| {
+            means.iter().map(move |(name, value)| (labels(&[("name", name), ("split", split)]), *value))
+        })
+        .collect();
+    gauge("metric", "Mean of a logged metric over the last completed epoch, as in history.json.", all);
+    text
+}
+
+// `{name="value",...}` with the values escaped as the exposition format requires
+fn labels(pairs: &[(&str, &str)]) -> String {
+    let pairs: Vec<String> = pairs
+        .iter()
+        .map(|(name, value)| {
+            let value = value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+            format!("{name}=\"{value}\"")
+        })
+        .collect();
+    format!("{{{}}}", pairs.join(","))
+}
+
+// Sample values as Prometheus spells the non-finite ones
+fn number(value: f64) -> String {
+    match value {
+        value if value.is_nan() => "NaN".to_string(),
+        value if value == f64::INFINITY => "+Inf".to_string(),
+        value if value == f64::NEG_INFINITY => "-Inf".to_string(),
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{artifact::ArtifactDir, data::DatasetSource, synthetic::tiny_config, training::train};
+    use burn::backend::{ndarray::NdArrayDevice, Autodiff, NdArray};
+    use std::collections::{BTreeMap, HashSet};
+
+    // The samples of an exposition as `name{labels}` to value, checking its grammar: every
+    // sample follows the HELP and TYPE lines of its metric, names are valid, label values are
+    // quoted and the text ends with a newline
+    fn parse(text: &str) -> Result<BTreeMap<String, f64>, String> {
+        let valid_name = |name: &str| {
+            name.chars().enumerate().all(|(i, c)| c.is_ascii_alphabetic() || c == '_' || (i > 0 && c.is_ascii_digit()))
+        };
+        if !text.ends_with('\n') {
+            return Err("no final newline".to_string());
+        }
+        let (mut helped, mut typed) = (HashSet::new(), HashSet::new());
+        let mut samples = BTreeMap::new();
+        for line in text.lines() {
+            if let Some(comment) = line.strip_prefix("# ") {
+                let mut words = comment.splitn(3, ' ');
+                match (words.next(), words.next(), words.next()) {
+                    (Some("HELP"), Some(name), Some(_)) => helped.insert(name.to_string()),
+                    (Some("TYPE"), Some(name), Some("gauge")) => typed.insert(name.to_string()),
+                    _ => return Err(format!("bad comment {line:?}")),
+                };
+                continue;
+            }
+            let (series, value) = line.rsplit_once(' ').ok_or(format!("no value in {line:?}"))?;
+            let name = series.split('{').next().unwrap();
+            if !valid_name(name) || !helped.contains(name) || !typed.contains(name) {
+                return Err(format!("{name:?} is invalid or has no HELP and TYPE before {line:?}"));
+            }
+            if let Some(labels) = series.strip_prefix(name).filter(|labels| !labels.is_empty()) {
+                let pairs = labels.strip_prefix('{').and_then(|labels| labels.strip_suffix("\"}"));
+                let pairs = pairs.ok_or(format!("bad labels in {line:?}"))?;
+                for pair in pairs.split("\",") {
+                    let (label, value) = pair.split_once("=\"").ok_or(format!("unquoted label in {line:?}"))?;
+                    if !valid_name(label) || value.replace("\\\\", "").replace("\\\"", "").contains('"') {
+                        return Err(format!("bad label {pair:?} in {line:?}"));
+                    }
+                }
+            }
+            let value = match value {
+                "+Inf" => f64::INFINITY,
+                "-Inf" => f64::NEG_INFINITY,
+                value => value.parse().map_err(|_| format!("bad value in {line:?}"))?,
+            };
+            samples.insert(series.to_string(), value);
+        }
+        Ok(samples)
+    }
+
+    fn metrics() -> EpochMetrics {
+        let means = |loss: f64, accuracy: f64| BTreeMap::from([("Loss".into(), loss), ("Accuracy".into(), accuracy)]);
+        EpochMetrics {
+            epoch: 3,
+            train: means(0.5, 80.0),
+            valid: means(f64::NAN, 75.0),
+            accuracy_gap: None,
+            fraction: None,
+        }
+    }
+
+    #[test]
+    fn exposition_has_a_gauge_per_metric_and_split() {
+        let text = exposition(&metrics(), 10, 600, Duration::from_secs(2), Duration::from_secs(7));
+        let samples = parse(&text).unwrap();
+
+        assert_eq!(samples["mnist_training_epoch"], 3.0);
+        assert_eq!(samples["mnist_training_epochs_total"], 10.0);
+        assert_eq!(samples["mnist_training_loss{split=\"train\"}"], 0.5);
+        assert!(samples["mnist_training_loss{split=\"valid\"}"].is_nan());
+        assert_eq!(samples["mnist_training_accuracy{split=\"valid\"}"], 0.75);
+        assert_eq!(samples["mnist_training_items_per_second"], 300.0);
+        assert_eq!(samples["mnist_training_elapsed_seconds"], 7.0);
+        assert_eq!(samples["mnist_training_metric{name=\"Accuracy\",split=\"train\"}"], 80.0);
+        assert_eq!(samples.len(), 12);
+        // No throughput without a duration to divide by
+        let instant = exposition(&metrics(), 10, 600, Duration::ZERO, Duration::ZERO);
+        assert!(!parse(&instant).unwrap().contains_key("mnist_training_items_per_second"));
+    }
+
+    #[test]
+    fn label_values_are_escaped() {
+        assert_eq!(labels(&[("name", "a\"b\\c\nd")]), "{name=\"a\\\"b\\\\c\\nd\"}");
+        let mut metrics = metrics();
+        metrics.train.insert("Odd \"name\"".to_string(), 1.0);
+        let text = exposition(&metrics, 10, 600, Duration::from_secs(1), Duration::from_secs(1));
+        assert!(parse(&text).unwrap().contains_key("mnist_training_metric{name=\"Odd \\\"name\\\"\",split=\"train\"}"));
+    }
+
+    #[test]
+    fn training_rewrites_the_file_every_epoch() {
+        let artifact_dir = std::env::temp_dir().join("my_first_rust_DL_app-prometheus");
+        let dir = artifact_dir.to_str().unwrap();
+        let config = |prometheus_metrics| {
+            tiny_config()
+                .with_dataset(DatasetSource::Synthetic { num_samples: 32, seed: 1 })
+                .with_prometheus_metrics(prometheus_metrics)
+                .with_num_epochs(2)
+        };
+
+        train::<Autodiff<NdArray>>(dir, config(true), NdArrayDevice::default()).unwrap();
+        let text = std::fs::read_to_string(ArtifactDir::new(dir).prometheus_path()).unwrap();
+        let files: Vec<String> = std::fs::read_dir(&artifact_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        train::<Autodiff<NdArray>>(dir, config(false), NdArrayDevice::default()).unwrap();
+        let disabled = std::path::Path::new(&ArtifactDir::new(dir).prometheus_path()).exists();
+        std::fs::remove_dir_all(&artifact_dir).unwrap();
+
+        let samples = parse(&text).unwrap();
+        assert_eq!((samples["mnist_training_epoch"], samples["mnist_training_epochs_total"]), (2.0, 2.0));
+        for split in ["train", "valid"] {
+            let accuracy = samples[&format!("mnist_training_accuracy{{split=\"{split}\"}}")];
+            assert!((0.0..=1.0).contains(&accuracy), "{accuracy}");
+            assert!(samples[&format!("mnist_training_loss{{split=\"{split}\"}}")].is_finite());
+        }
+        assert!(samples["mnist_training_items_per_second"] > 0.0);
+        assert!(!files.iter().any(|file| file.ends_with(".tmp")), "{files:?}");
+        assert!(!disabled);
+    }
+}
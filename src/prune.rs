@@ -0,0 +1,407 @@
+use crate::{
+    artifact::ArtifactDir,
+    checkpoint::LoadError,
+    data::{mnist_dataloader, MnistBatcher, MnistSplit},
+    inference::load_model,
+    labels::ClassLabels,
+    meta::ModelMeta,
+    model::{Model, TaskKind},
+    params::{named_params, with_named_params, NamedParam},
+    schedule::LrSchedule,
+    split::{ClassSubset, OneVsRest},
+    swa::{score, SplitScore},
+    training::{create_artifact_dir, train_classification, ConfigError, RunOptions, TrainError, TrainingConfig},
+};
+use burn::{
+    module::{AutodiffModule, ModuleMapper, ModuleVisitor, ParamId},
+    optim::{GradientsParams, Optimizer},
+    prelude::*,
+    record::CompactRecorder,
+    tensor::backend::AutodiffBackend,
+    LearningRate,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fmt};
+
+// Where `prune` writes the pruned model, inside the artifact dir of the model it prunes
+const PRUNED_DIR: &str = "pruned";
+
+// 0/1 masks of the weights pruning zeroed, flattened and keyed by parameter id. Only the kernels
+// and matrices (parameters of rank 2 and more) are pruned, biases are left whole.
+#[derive(Debug, Clone)]
+pub struct WeightMasks<B: AutodiffBackend> {
+    masks: HashMap<ParamId, Tensor<B::InnerBackend, 1>>,
+}
+
+impl<B: AutodiffBackend> Default for WeightMasks<B> {
+    fn default() -> Self {
+        Self { masks: HashMap::new() }
+    }
+}
+
+// 0/1 mask of `values` zeroing its `sparsity` fraction of lowest-magnitude values
+fn magnitude_mask(values: &[f32], sparsity: f64) -> Vec<f32> {
+    let mut order: Vec<usize> = (0..values.len()).collect();
+    order.sort_by(|&a, &b| values[a].abs().total_cmp(&values[b].abs()));
+
+    let mut mask = vec![1.0f32; values.len()];
+    let num_pruned = (sparsity * values.len() as f64) as usize;
+    for &index in &order[..num_pruned] {
+        mask[index] = 0.0;
+    }
+    mask
+}
+
+// Masks out the `sparsity` fraction of lowest-magnitude values of every weight, layer by layer
+struct MaskBuilder<'a, B: AutodiffBackend> {
+    sparsity: f64,
+    device: &'a B::Device,
+    masks: HashMap<ParamId, Tensor<B::InnerBackend, 1>>,
+}
+
+impl<B: AutodiffBackend> ModuleVisitor<B> for MaskBuilder<'_, B> {
+    fn visit_float<const D: usize>(&mut self, id: &ParamId, tensor: &Tensor<B, D>) {
+        if D < 2 {
+            return;
+        }
+        let values = tensor.to_data().convert::<f32>().value;
+        let mask = Data::new(magnitude_mask(&values, self.sparsity), Shape::new([values.len()]));
+        self.masks.insert(id.clone(), Tensor::from_data(mask.convert(), self.device));
+    }
+}
+
+// Multiplies every masked parameter by its mask. The product is computed on the inner backend
+// so the parameters stay leaves of the autodiff graph, as the optimizers leave them.
+struct ApplyMasks<'a, B: AutodiffBackend> {
+    masks: &'a HashMap<ParamId, Tensor<B::InnerBackend, 1>>,
+}
+
+impl<B: AutodiffBackend> ModuleMapper<B> for ApplyMasks<'_, B> {
+    fn map_float<const D: usize>(&mut self, id: &ParamId, tensor: Tensor<B, D>) -> Tensor<B, D> {
+        let Some(mask) = self.masks.get(id) else {
+            return tensor;
+        };
+        let require_grad = tensor.is_require_grad();
+        let shape = tensor.shape();
+        let masked = tensor.inner() * mask.clone().reshape(shape);
+        Tensor::from_inner(masked).set_require_grad(require_grad)
+    }
+}
+
+impl<B: AutodiffBackend> WeightMasks<B> {
+    pub fn from_magnitudes(model: &Model<B>, sparsity: f64, device: &B::Device) -> Self {
+        let mut builder = MaskBuilder { sparsity, device, masks: HashMap::new() };
+        model.visit(&mut builder);
+        Self { masks: builder.masks }
+    }
+
+    pub fn apply(&self, model: Model<B>) -> Model<B> {
+        if self.masks.is_empty() {
+            return model;
+        }
+        model.map(&mut ApplyMasks { masks: &self.masks })
+    }
+}
+
+// Reapplies the masks after every step of the wrapped optimizer, so that fine-tuning cannot
+// revive pruned weights. The optimizer state of pruned weights keeps moving but never shows.
+pub struct MaskedOptimizer<O, B: AutodiffBackend> {
+    inner: O,
+    masks: WeightMasks<B>,
+}
+
+impl<O, B: AutodiffBackend> MaskedOptimizer<O, B> {
+    pub fn new(inner: O, masks: WeightMasks<B>) -> Self {
+        Self { inner, masks }
+    }
+}
+
+impl<O, B> Optimizer<Model<B>, B> for MaskedOptimizer<O, B>
+where
+    B: AutodiffBackend,
+    O: Optimizer<Model<B>, B>,
+{
+    type Record = O::Record;
+
+    fn step(&mut self, lr: LearningRate, module: Model<B>, grads: GradientsParams) -> Model<B> {
+        let module = self.inner.step(lr, module, grads);
+        self.masks.apply(module)
+    }
+
+    fn to_record(&self) -> Self::Record {
+        self.inner.to_record()
+    }
+
+    fn load_record(self, record: Self::Record) -> Self {
+        Self { inner: self.inner.load_record(record), masks: self.masks }
+    }
+}
+
+// Share of zeros in one pruned weight
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LayerSparsity {
+    pub name: String,
+    pub num_weights: usize,
+    pub num_zeros: usize,
+    pub sparsity: f64,
+}
+
+// Outcome of `prune`, saved as `pruned/prune.json`: the sparsity achieved in every weight and
+// the validation scores before and after
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PruneReport {
+    pub target_sparsity: f64,
+    pub finetune_epochs: usize,
+    pub layers: Vec<LayerSparsity>,
+    pub original: SplitScore,
+    pub pruned: SplitScore,
+    // Pruned minus original accuracy, in percentage points
+    pub accuracy_delta: f64,
+}
+
+fn layer_sparsity<B: Backend>(model: &Model<B>) -> Vec<LayerSparsity> {
+    named_params(model)
+        .into_iter()
+        .filter(|param| param.shape.len() >= 2)
+        .map(|param| {
+            let num_zeros = param.values.iter().filter(|&&value| value == 0.0).count();
+            let num_weights = param.num_elements();
+            LayerSparsity {
+                name: param.name,
+                num_weights,
+                num_zeros,
+                sparsity: num_zeros as f64 / num_weights.max(1) as f64,
+            }
+        })
+        .collect()
+}
+
+impl fmt::Display for PruneReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Pruned to {:.1}% sparsity, fine-tuned for {} epoch(s):", self.target_sparsity * 100.0, self.finetune_epochs)?;
+        writeln!(f, "| {:<16} | {:>9} | {:>8} |", "Weight", "Zeros", "Sparsity")?;
+        writeln!(f, "|------------------|-----------|----------|")?;
+        for layer in &self.layers {
+            writeln!(f, "| {:<16} | {:>9} | {:>7.2}% |", layer.name, layer.num_zeros, layer.sparsity * 100.0)?;
+        }
+        writeln!(
+            f,
+            "Validation accuracy: {:.2}% -> {:.2}% ({:+.2} points)",
+            self.original.accuracy, self.pruned.accuracy, self.accuracy_delta
+        )
+    }
+}
+
+// Share of zeros over all the weights `layer_sparsity` counts
+fn global_sparsity(layers: &[LayerSparsity]) -> f64 {
+    let num_zeros: usize = layers.iter().map(|layer| layer.num_zeros).sum();
+    let num_weights: usize = layers.iter().map(|layer| layer.num_weights).sum();
+    num_zeros as f64 / num_weights.max(1) as f64
+}
+
+/// Magnitude pruning of `model` in memory, without fine-tuning: zeroes the `sparsity` fraction
+/// of lowest-magnitude values of every weight (convolution kernels and linear matrices, not the
+/// biases), which prunes that fraction of all of them. Returns the pruned model and the sparsity
+/// it reached over those weights, zeros it had before pruning included.
+///
+/// Panics unless `sparsity` is in [0, 1).
+pub fn prune_model<B: Backend>(model: Model<B>, sparsity: f64) -> (Model<B>, f64) {
+    assert!((0.0..1.0).contains(&sparsity), "sparsity should be in [0, 1), got {sparsity}");
+    let device = model.devices().into_iter().next().expect("A model should have parameters on a device");
+
+    let params: Vec<NamedParam> = named_params(&model)
+        .into_iter()
+        .filter(|param| param.shape.len() >= 2)
+        .map(|param| {
+            let mask = magnitude_mask(&param.values, sparsity);
+            let values = param.values.iter().zip(mask).map(|(value, keep)| value * keep).collect();
+            NamedParam { values, ..param }
+        })
+        .collect();
+    let pruned = with_named_params(model, &params, &device);
+    let achieved = global_sparsity(&layer_sparsity(&pruned));
+    (pruned, achieved)
+}
+
+// Validation scores of `model`, on the same labels the run trained on
+fn score_valid<B: Backend>(model: &Model<B>, config: &TrainingConfig, device: &B::Device) -> SplitScore {
+    let batcher = MnistBatcher::<B>::new(device.clone());
+    let dataset = config.dataset.load(MnistSplit::Test, config.cache);
+    let (batch_size, seed, num_workers, prefetch) = (config.batch_size, config.seed, config.num_workers, config.prefetch);
+    let dataloader = match (&config.classes, config.binary_target) {
+        (Some(classes), _) => mnist_dataloader(
+            batcher,
+            ClassSubset::new(dataset, classes),
+            batch_size,
+            seed,
+            num_workers,
+            prefetch,
+        ),
+        (None, Some(target)) => {
+            mnist_dataloader(batcher, OneVsRest::new(dataset, target), batch_size, seed, num_workers, prefetch)
+        }
+        (None, None) => mnist_dataloader(batcher, dataset, batch_size, seed, num_workers, prefetch),
+    };
+    score(model, dataloader.as_ref())
+}
+
+/// Magnitude pruning of the model trained into `artifact_dir`: zeroes the `sparsity` fraction of
+/// lowest-magnitude values of every weight (convolution kernels and linear matrices, not the
+/// biases), then fine-tunes what is left for `finetune_epochs` with the training config of the
+/// run, the pruned weights held at zero after every optimizer step.
+///
+/// The pruned model is written to `artifact_dir/pruned` like any trained model, so
+/// [`load_model`] reads it, with its `prune.json` report. The fine-tune uses a constant learning
+/// rate and neither SWA nor a curriculum. A `sparsity` of 0 prunes nothing and skips the
+/// fine-tune, the pruned model is the original one; so does a `finetune_epochs` of 0 after
+/// pruning.
+///
+/// ```no_run
+/// use burn::backend::{wgpu::WgpuDevice, Autodiff, Wgpu};
+/// use my_first_rust_DL_app::prune::prune;
+///
+/// let report = prune::<Autodiff<Wgpu>>("/tmp/mnist-run", 0.8, 1, WgpuDevice::default())?;
+/// print!("{report}");
+/// # Ok::<(), my_first_rust_DL_app::TrainError>(())
+/// ```
+pub fn prune<B: AutodiffBackend>(
+    artifact_dir: &str,
+    sparsity: f64,
+    finetune_epochs: usize,
+    device: B::Device,
+) -> Result<PruneReport, TrainError> {
+    let mut errors = Vec::new();
+    if !(0.0..1.0).contains(&sparsity) {
+        errors.push(ConfigError::new("sparsity", sparsity, "a value in [0, 1)"));
+    }
+    let config = TrainingConfig::load(ArtifactDir::new(artifact_dir).config_path())
+        .map_err(|err| TrainError::Load(LoadError::Config(err.to_string())))?;
+    if config.model.task != TaskKind::SingleLabel {
+        errors.push(ConfigError::new("model.task", format!("{:?}", config.model.task), "SingleLabel"));
+    }
+    if !errors.is_empty() {
+        return Err(TrainError::InvalidConfig(errors));
+    }
+
+    let original = load_model::<B>(artifact_dir, &device).map_err(TrainError::Load)?;
+    let original_score = score_valid(&original.valid(), &config, &device);
+
+    let masks = if sparsity > 0.0 {
+        WeightMasks::from_magnitudes(&original, sparsity, &device)
+    } else {
+        WeightMasks::default()
+    };
+    let masked = masks.apply(original);
+
+    let pruned_dir = format!("{artifact_dir}/{PRUNED_DIR}");
+    let pruned = if sparsity == 0.0 || finetune_epochs == 0 {
+        create_artifact_dir(&pruned_dir)?;
+        config.save(format!("{pruned_dir}/config.json"))?;
+        ClassLabels::load(artifact_dir).save(&pruned_dir)?;
+        masked.clone().save_file(format!("{pruned_dir}/model"), &CompactRecorder::new())?;
+        if let Some(meta) = ModelMeta::load(artifact_dir).map_err(TrainError::Load)? {
+            meta.save(&pruned_dir)?;
+        }
+        masked
+    } else {
+        let mut finetune = config.clone();
+        finetune.num_epochs = finetune_epochs;
+        finetune.lr_schedule = LrSchedule::Constant;
+        finetune.swa = None;
+        finetune.curriculum = None;
+
+        let train_set = finetune.dataset.load(MnistSplit::Train, finetune.cache);
+        let valid_set = finetune.dataset.load(MnistSplit::Test, finetune.cache);
+        let options = RunOptions { start: Some((masked, masks)), ..RunOptions::default() };
+        train_classification(&pruned_dir, finetune, train_set, valid_set, device.clone(), options)?
+    };
+
+    let pruned_score = score_valid(&pruned.valid(), &config, &device);
+    let report = PruneReport {
+        target_sparsity: sparsity,
+        finetune_epochs,
+        layers: layer_sparsity(&pruned),
+        original: original_score,
+        pruned: pruned_score,
+        accuracy_delta: pruned_score.accuracy - original_score.accuracy,
+    };
+    std::fs::write(
+        format!("{pruned_dir}/prune.json"),
+        serde_json::to_string_pretty(&report).expect("Prune report should serialize to JSON"),
+    )?;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{artifact::ModelKind, data::DatasetSource, synthetic::tiny_config, ModelConfig};
+    use burn::backend::{ndarray::NdArrayDevice, Autodiff, NdArray};
+
+    type B = Autodiff<NdArray>;
+
+    // An untrained model saved as a run on synthetic digits
+    fn saved_run(name: &str, device: &NdArrayDevice) -> String {
+        let dir = std::env::temp_dir().join(format!("my_first_rust_DL_app-prune-{name}"));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let dir = ArtifactDir::new(dir.to_str().unwrap());
+        let config = tiny_config().with_dataset(DatasetSource::Synthetic { num_samples: 64, seed: 1 });
+        config.save(dir.config_path()).unwrap();
+        config.model.init::<B>(device).save_file(dir.model_path(ModelKind::Final), &CompactRecorder::new()).unwrap();
+        dir.as_str().to_string()
+    }
+
+    #[test]
+    fn sparsity_outside_the_unit_interval_is_rejected() {
+        let device = NdArrayDevice::default();
+        let run = saved_run("invalid", &device);
+
+        for sparsity in [-0.1, 1.0] {
+            match prune::<B>(&run, sparsity, 0, device) {
+                Err(TrainError::InvalidConfig(errors)) => assert_eq!(errors[0].field, "sparsity"),
+                other => panic!("expected an invalid sparsity, got {:?}", other.map(|report| report.target_sparsity)),
+            }
+        }
+    }
+
+    #[test]
+    fn zero_sparsity_keeps_the_model_and_its_accuracy() {
+        let device = NdArrayDevice::default();
+        let run = saved_run("zero", &device);
+
+        let report = prune::<B>(&run, 0.0, 1, device).unwrap();
+        assert_eq!(report.accuracy_delta, 0.0);
+        assert_eq!(report.original, report.pruned);
+        assert!(load_model::<B>(&format!("{run}/{PRUNED_DIR}"), &device).is_ok());
+    }
+
+    #[test]
+    fn fine_tuning_keeps_the_pruned_weights_at_zero() {
+        let device = NdArrayDevice::default();
+        let run = saved_run("finetune", &device);
+
+        let report = prune::<B>(&run, 0.5, 1, device).unwrap();
+        assert_eq!(report.layers.len(), 4);
+        for layer in &report.layers {
+            assert!(layer.num_zeros >= layer.num_weights / 2, "{} is {} sparse", layer.name, layer.sparsity);
+        }
+        let saved: PruneReport =
+            serde_json::from_str(&std::fs::read_to_string(format!("{run}/{PRUNED_DIR}/prune.json")).unwrap()).unwrap();
+        assert_eq!(saved, report);
+    }
+
+    #[test]
+    fn pruning_half_of_the_weights_zeroes_half_of_each_layer() {
+        let device = NdArrayDevice::default();
+        let model = ModelConfig::new(10, 32).init::<NdArray>(&device);
+
+        let (pruned, sparsity) = prune_model(model, 0.5);
+        assert!((sparsity - 0.5).abs() < 1e-3, "pruned to {sparsity}");
+        for layer in layer_sparsity(&pruned) {
+            assert_eq!(layer.num_zeros, layer.num_weights / 2, "{}", layer.name);
+        }
+        let output = pruned.forward(Tensor::<NdArray, 3>::ones([2, 28, 28], &device));
+        assert!(output.into_data().value.iter().all(|value| value.is_finite()));
+    }
+}
@@ -0,0 +1,182 @@
+use crate::{
+    artifact::ArtifactDir,
+    checkpoint::{load_weights, LoadError},
+    inference::{self, Prediction, RawImage},
+    labels::ClassLabels,
+    meta::resolve_model_config,
+    model::Model,
+    training::TrainingConfig,
+};
+use burn::prelude::*;
+use std::{collections::HashMap, fmt};
+
+// A model loaded from an artifact dir, with the config it was trained with and its class names
+pub struct LoadedModel<B: Backend> {
+    pub model: Model<B>,
+    pub config: TrainingConfig,
+    pub labels: ClassLabels,
+    pub artifact_dir: String,
+    device: B::Device,
+}
+
+impl<B: Backend> LoadedModel<B> {
+    pub fn load(artifact_dir: &str, device: &B::Device) -> Result<Self, LoadError> {
+        let mut config = TrainingConfig::load(ArtifactDir::new(artifact_dir).config_path())
+            .map_err(|err| LoadError::Config(err.to_string()))?;
+        config.model = resolve_model_config(artifact_dir, &config.model)?;
+        let (model, _) = load_weights::<B>(artifact_dir, &config.model, true, device)?;
+        let labels = ClassLabels::load(artifact_dir);
+        Ok(Self { model, config, labels, artifact_dir: artifact_dir.to_string(), device: device.clone() })
+    }
+
+    pub fn predict(&self, image: RawImage) -> Prediction {
+        inference::predict(&self.model, &self.device, image, &self.labels)
+    }
+
+    // Runs `iterations` predictions of a blank image, waiting for the device after each, so
+    // that the backend compiles (and, on wgpu, autotunes) the kernels `predict` uses before
+    // real traffic pays for it. Call it right after `load`: one iteration compiles the kernels,
+    // `bench::DEFAULT_WARMUP` also settles the allocator and autotune caches and is the
+    // recommended count for latency-sensitive deployments.
+    pub fn warmup(&self, iterations: usize) {
+        for _ in 0..iterations {
+            let _ = self.predict([[0.0; 28]; 28]);
+            B::sync(&self.device);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RegistryError {
+    // No model is registered under the name; lists the names that are
+    UnknownModel { name: String, available: Vec<String> },
+    // The model could not be loaded from its artifact dir
+    Load { name: String, error: LoadError },
+}
+
+impl fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegistryError::UnknownModel { name, available } => {
+                write!(f, "no model registered as `{name}`, available: {available:?}")
+            }
+            RegistryError::Load { name, error } => write!(f, "could not load model `{name}`: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for RegistryError {}
+
+// Several loaded models held side by side under a name each, e.g. the versions of an A/B test
+pub struct ModelRegistry<B: Backend> {
+    models: HashMap<String, LoadedModel<B>>,
+}
+
+impl<B: Backend> Default for ModelRegistry<B> {
+    fn default() -> Self {
+        Self { models: HashMap::new() }
+    }
+}
+
+impl<B: Backend> ModelRegistry<B> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Loads the model in `artifact_dir` under `name`, replacing any model already registered
+    // under it. On error the registry is left unchanged.
+    pub fn register(&mut self, name: &str, artifact_dir: &str, device: &B::Device) -> Result<(), RegistryError> {
+        let model = LoadedModel::load(artifact_dir, device)
+            .map_err(|error| RegistryError::Load { name: name.to_string(), error })?;
+        self.models.insert(name.to_string(), model);
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Result<&LoadedModel<B>, RegistryError> {
+        self.models.get(name).ok_or_else(|| RegistryError::UnknownModel {
+            name: name.to_string(),
+            available: self.names(),
+        })
+    }
+
+    // Registered names, sorted
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.models.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    // Classifies `image` with the model registered as `name`
+    pub fn predict(&self, name: &str, image: RawImage) -> Result<Prediction, RegistryError> {
+        Ok(self.get(name)?.predict(image))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{artifact::ModelKind, ModelConfig};
+    use burn::{
+        backend::{ndarray::NdArrayDevice, NdArray},
+        optim::AdamConfig,
+        record::CompactRecorder,
+    };
+
+    // An artifact dir holding the config and final weights of a fresh `num_classes` model
+    fn saved_run(name: &str, num_classes: usize, device: &NdArrayDevice) -> String {
+        let dir = std::env::temp_dir().join(format!("my_first_rust_DL_app-registry-{name}"));
+        std::fs::create_dir_all(&dir).unwrap();
+        let dir = ArtifactDir::new(dir.to_str().unwrap());
+        let config = TrainingConfig::new(ModelConfig::new(num_classes, 8), AdamConfig::new());
+        config.save(dir.config_path()).unwrap();
+        let model = config.model.init::<NdArray>(device);
+        model.save_file(dir.model_path(ModelKind::Final), &CompactRecorder::new()).unwrap();
+        dir.as_str().to_string()
+    }
+
+    #[test]
+    fn predictions_are_routed_to_the_model_of_the_name() {
+        let device = NdArrayDevice::default();
+        let (digits, binary) = (saved_run("digits", 10, &device), saved_run("binary", 2, &device));
+        let mut registry = ModelRegistry::<NdArray>::new();
+        registry.register("a", &digits, &device).unwrap();
+        registry.register("b", &binary, &device).unwrap();
+        let missing = registry.register("c", "/nonexistent/my_first_rust_DL_app-registry", &device);
+        std::fs::remove_dir_all(&digits).unwrap();
+        std::fs::remove_dir_all(&binary).unwrap();
+
+        assert!(matches!(missing, Err(RegistryError::Load { name, .. }) if name == "c"));
+        assert_eq!(registry.names(), ["a", "b"]);
+        let image = [[0.5; 28]; 28];
+        assert_eq!(registry.predict("a", image).unwrap().probabilities.len(), 10);
+        let binary = registry.predict("b", image).unwrap();
+        assert_eq!(binary.probabilities.len(), 2);
+        assert!(binary.index < 2);
+        match registry.predict("d", image) {
+            Err(RegistryError::UnknownModel { name, available }) => {
+                assert_eq!(name, "d");
+                assert_eq!(available, ["a", "b"]);
+            }
+            other => panic!("predicting with an unknown name gave {:?}", other.map(|prediction| prediction.index)),
+        }
+    }
+
+    #[test]
+    fn warmup_leaves_predictions_unchanged() {
+        let device = NdArrayDevice::default();
+        let dir = saved_run("warmup", 10, &device);
+        let model = LoadedModel::<NdArray>::load(&dir, &device).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let mut image = [[0.0; 28]; 28];
+        for row in image.iter_mut().skip(4).take(20) {
+            row[14] = 1.0;
+        }
+        let before = model.predict(image);
+        model.warmup(0);
+        model.warmup(3);
+        let after = model.predict(image);
+        assert_eq!(after.index, before.index);
+        assert_eq!(after.probabilities, before.probabilities);
+    }
+}
@@ -0,0 +1,441 @@
+use crate::{
+    artifact::ArtifactDir,
+    checkpoint::LoadError,
+    data::MNIST_NUM_CLASSES,
+    evaluation::{
+        batch_outcomes, class_scores, dataset_pass, reliability_diagram_image, relabel_binary, ClassScores,
+        EvalAccumulator, EvalReport, HEATMAP_EMPTY, HEATMAP_FULL,
+    },
+    inference::load_model,
+    labels::ClassLabels,
+    meta::ModelMeta,
+    model::Classifier,
+};
+use base64::Engine;
+use burn::{
+    data::{dataloader::Dataset, dataset::vision::{MnistDataset, MnistItem}},
+    prelude::*,
+};
+use image::{DynamicImage, GrayImage, ImageOutputFormat, Luma};
+use std::{collections::BTreeMap, io::Cursor, path::Path};
+
+// Written into the artifact dir by `report`
+pub const REPORT_FILE: &str = "report.html";
+
+// Montage pixels per image pixel, and gap between the images, in pixels
+const MONTAGE_SCALE: u32 = 2;
+const MONTAGE_GAP: u32 = 4;
+const MONTAGE_BACKGROUND: u8 = 255;
+
+// The page `render_html` fills in: every `{{name}}` is replaced by a section
+const TEMPLATE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>Failure report: {{title}}</title>
+<style>
+body { font-family: sans-serif; margin: 2em; color: #222; }
+table { border-collapse: collapse; margin-bottom: 1.5em; }
+th, td { border: 1px solid #ccc; padding: 0.25em 0.6em; text-align: right; }
+th { background: #f4f4f4; }
+.bar { display: inline-block; height: 0.8em; background: #4682b4; }
+.heat { display: block; margin: -0.25em -0.6em; padding: 0.25em 0.6em; }
+.pair { margin-bottom: 1.5em; }
+img { image-rendering: pixelated; }
+</style>
+</head>
+<body>
+<h1>Failure report: {{title}}</h1>
+<h2>Headline metrics</h2>
+{{metrics}}
+<h2>Confusion matrix</h2>
+<p>Rows are the true classes, columns the predicted ones. Each row is normalized to the share of the samples of its class.</p>
+{{confusion}}
+<h2>Per-class scores</h2>
+{{classes}}
+<h2>Reliability</h2>
+{{reliability}}
+<h2>Most confident errors</h2>
+{{errors}}
+</body>
+</html>
+"#;
+
+/// Options of [`report`].
+#[derive(Config, Debug)]
+pub struct ReportConfig {
+    // Number of equal-width confidence bins of the reliability table
+    #[config(default = 15)]
+    pub calibration_bins: usize,
+    // Most confident errors kept per confused (true, predicted) class pair
+    #[config(default = 8)]
+    pub examples_per_pair: usize,
+    // Most error images embedded in the page over all pairs, the most frequent pairs first, so
+    // that the size of the page does not grow with the test set
+    #[config(default = 200)]
+    pub max_images: usize,
+}
+
+/// One misclassified test sample of a [`FailureReport`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErrorExample {
+    // Position in the test set
+    pub index: usize,
+    // Softmax probability of the wrong prediction
+    pub confidence: f32,
+    // Raw pixels, for the examples embedded in the page only
+    pub image: Option<[[f32; 28]; 28]>,
+}
+
+/// The samples of one true class predicted as another, with the most confident of them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfusedPair {
+    pub target: usize,
+    pub predicted: usize,
+    pub count: u32,
+    // Most confident first, at most `examples_per_pair`
+    pub examples: Vec<ErrorExample>,
+}
+
+/// Everything [`report`] writes into `report.html`.
+#[derive(Debug, Clone)]
+pub struct FailureReport {
+    // The scores `evaluate` writes into `eval.json`
+    pub eval: EvalReport,
+    pub classes: Vec<ClassScores>,
+    // Pairs with at least one error, most frequent first
+    pub confused_pairs: Vec<ConfusedPair>,
+}
+
+impl FailureReport {
+    // Number of classes of the model, the rows of the confusion matrix that can be filled
+    fn num_classes(&self) -> usize {
+        self.eval.labels.len().clamp(1, MNIST_NUM_CLASSES)
+    }
+}
+
+/// Evaluates the model trained in `artifact_dir` on the test set and writes `report.html`
+/// there: one self-contained page (images inline as base64, no scripts) of the headline
+/// metrics, the row-normalized confusion matrix, the per-class precision, recall and F1, the
+/// reliability bins with their diagram, and montages of the most confident errors of every
+/// confused class pair. The numbers are those of [`evaluate`](crate::evaluate).
+pub fn report<B: Backend>(
+    artifact_dir: impl AsRef<Path>,
+    config: &ReportConfig,
+    device: &B::Device,
+) -> Result<FailureReport, LoadError> {
+    let dir = ArtifactDir::new(artifact_dir);
+    dir.validate()?;
+    let model = load_model::<B>(&dir, device)?;
+    let binary_target = ModelMeta::load(dir.as_str())?.and_then(|meta| meta.binary_target);
+    let dataset = MnistDataset::test();
+    let report = failure_report(&model, &dataset, ClassLabels::load(dir.as_str()), binary_target, config, device);
+
+    let title = Path::new(dir.as_str()).file_name().map_or(dir.as_str().into(), |name| name.to_string_lossy());
+    std::fs::write(dir.report_path(), render_html(&report, &title))
+        .expect("Report should be saved successfully!");
+    Ok(report)
+}
+
+/// The [`FailureReport`] of a model already loaded over any dataset of MNIST items, read in a
+/// single pass: only the `examples_per_pair` most confident errors of each pair are kept along
+/// the way, and only the pixels of the first `max_images` of them are read back.
+pub fn failure_report<B: Backend, M: Classifier<B> + ?Sized, D: Dataset<MnistItem>>(
+    model: &M,
+    dataset: &D,
+    labels: ClassLabels,
+    binary_target: Option<usize>,
+    config: &ReportConfig,
+    device: &B::Device,
+) -> FailureReport {
+    let mut accumulator = EvalAccumulator::new(config.calibration_bins, binary_target);
+    let mut errors: BTreeMap<(usize, usize), Vec<ErrorExample>> = BTreeMap::new();
+    dataset_pass(model, dataset, device, |start, output, batch| {
+        let outcomes = batch_outcomes(output, batch.targets);
+        accumulator.extend(outcomes.iter().copied());
+        for (offset, outcome) in relabel_binary(outcomes, binary_target).into_iter().enumerate() {
+            if outcome.is_correct() || config.examples_per_pair == 0 {
+                continue;
+            }
+            let examples = errors.entry((outcome.target, outcome.predicted)).or_default();
+            examples.push(ErrorExample { index: start + offset, confidence: outcome.confidence, image: None });
+            examples.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+            examples.truncate(config.examples_per_pair);
+        }
+    });
+    let eval = accumulator.report(labels);
+    let classes = class_scores(&eval.confusion_matrix, eval.labels.len());
+
+    let mut confused_pairs: Vec<ConfusedPair> = (0..MNIST_NUM_CLASSES)
+        .flat_map(|target| (0..MNIST_NUM_CLASSES).map(move |predicted| (target, predicted)))
+        .filter(|&(target, predicted)| target != predicted && eval.confusion_matrix[target][predicted] > 0)
+        .map(|(target, predicted)| ConfusedPair {
+            target,
+            predicted,
+            count: eval.confusion_matrix[target][predicted],
+            examples: errors.remove(&(target, predicted)).unwrap_or_default(),
+        })
+        .collect();
+    // Stable: equally frequent pairs stay in class order
+    confused_pairs.sort_by_key(|pair| std::cmp::Reverse(pair.count));
+
+    let mut remaining = config.max_images;
+    for example in confused_pairs.iter_mut().flat_map(|pair| pair.examples.iter_mut()) {
+        if remaining == 0 {
+            break;
+        }
+        example.image = dataset.get(example.index).map(|item| item.image);
+        remaining -= 1;
+    }
+
+    FailureReport { eval, classes, confused_pairs }
+}
+
+/// `report` as the page `report.html` holds, titled `title`.
+pub fn render_html(report: &FailureReport, title: &str) -> String {
+    let sections = [
+        ("title", escape(title)),
+        ("metrics", metrics_table(report)),
+        ("confusion", confusion_table(report)),
+        ("classes", classes_table(report)),
+        ("reliability", reliability_section(report)),
+        ("errors", errors_section(report)),
+    ];
+    sections
+        .iter()
+        .fold(TEMPLATE.to_string(), |page, (name, section)| page.replace(&format!("{{{{{name}}}}}"), section))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn percent(value: f32) -> String {
+    format!("{:.2}%", value * 100.0)
+}
+
+// A `<table>` of a header row and the given rows, every cell already HTML
+fn table(header: &[String], rows: impl IntoIterator<Item = Vec<String>>) -> String {
+    let mut html = String::from("<table>\n<tr>");
+    for cell in header {
+        html.push_str(&format!("<th>{cell}</th>"));
+    }
+    html.push_str("</tr>\n");
+    for row in rows {
+        html.push_str("<tr>");
+        for cell in row {
+            html.push_str(&format!("<td>{cell}</td>"));
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</table>");
+    html
+}
+
+fn metrics_table(report: &FailureReport) -> String {
+    let eval = &report.eval;
+    let errors = eval.num_samples - (eval.accuracy * eval.num_samples as f32).round() as usize;
+    let macro_f1 = report.classes.iter().map(|class| class.f1).sum::<f32>() / report.classes.len().max(1) as f32;
+    let mut rows = vec![
+        vec!["Samples".to_string(), eval.num_samples.to_string()],
+        vec!["Accuracy".to_string(), percent(eval.accuracy)],
+        vec!["Errors".to_string(), errors.to_string()],
+        vec!["Macro F1".to_string(), format!("{macro_f1:.4}")],
+        vec!["ECE".to_string(), format!("{:.4}", eval.calibration.ece)],
+        vec!["MCE".to_string(), format!("{:.4}", eval.calibration.mce)],
+    ];
+    if let Some(binary) = &eval.binary {
+        let roc_auc = binary.roc_auc.map_or("n/a".to_string(), |auc| format!("{auc:.4}"));
+        rows.push(vec![format!("{} vs rest ROC-AUC", binary.positive_class), roc_auc]);
+    }
+    table(&["Metric".to_string(), "Value".to_string()], rows)
+}
+
+fn confusion_table(report: &FailureReport) -> String {
+    let num_classes = report.num_classes();
+    let labels = &report.eval.labels;
+    let mut header = vec!["true \\ predicted".to_string()];
+    header.extend((0..num_classes).map(|class| escape(&labels.name(class))));
+    let rows = report.eval.confusion_matrix.iter().take(num_classes).enumerate().map(|(target, row)| {
+        let total: u32 = row.iter().sum();
+        let mut cells = vec![format!("<b>{}</b>", escape(&labels.name(target)))];
+        cells.extend(row.iter().take(num_classes).map(|&count| {
+            let share = count as f32 / total.max(1) as f32;
+            let [red, green, blue]: [u8; 3] = std::array::from_fn(|channel| {
+                let (empty, full) = (HEATMAP_EMPTY[channel] as f32, HEATMAP_FULL[channel] as f32);
+                (empty + (full - empty) * share).round() as u8
+            });
+            let text = if share > 0.5 { "#fff" } else { "#222" };
+            format!(
+                "<span class=\"heat\" style=\"background:rgb({red},{green},{blue});color:{text}\" title=\"{count} samples\">{share:.3}</span>"
+            )
+        }));
+        cells
+    });
+    table(&header, rows)
+}
+
+fn classes_table(report: &FailureReport) -> String {
+    let header = ["Class", "Precision", "Recall", "F1", "Support"].map(String::from);
+    let rows = report.classes.iter().map(|class| {
+        vec![
+            escape(&report.eval.labels.name(class.class)),
+            format!("{:.4}", class.precision),
+            format!("{:.4}", class.recall),
+            format!("{:.4}", class.f1),
+            class.support.to_string(),
+        ]
+    });
+    table(&header, rows)
+}
+
+fn reliability_section(report: &FailureReport) -> String {
+    let calibration = &report.eval.calibration;
+    let header = ["Confidence", "Mean confidence", "Accuracy", "Samples", ""].map(String::from);
+    let rows = calibration.bins.iter().map(|bin| {
+        vec![
+            format!("({:.2}, {:.2}]", bin.lower, bin.upper),
+            format!("{:.4}", bin.confidence),
+            format!("{:.4}", bin.accuracy),
+            bin.count.to_string(),
+            format!("<span class=\"bar\" style=\"width:{:.1}em\"></span>", bin.accuracy * 10.0),
+        ]
+    });
+    let diagram = DynamicImage::ImageRgb8(reliability_diagram_image(&calibration.bins));
+    format!("{}\n<p>{}</p>", table(&header, rows), img_tag(&diagram, "Reliability diagram"))
+}
+
+fn errors_section(report: &FailureReport) -> String {
+    if report.confused_pairs.is_empty() {
+        return "<p>No errors.</p>".to_string();
+    }
+    let labels = &report.eval.labels;
+    let mut html = String::new();
+    let mut not_embedded = 0;
+    for pair in &report.confused_pairs {
+        let (target, predicted) = (escape(&labels.name(pair.target)), escape(&labels.name(pair.predicted)));
+        html.push_str(&format!(
+            "<div class=\"pair\">\n<h3>{target} predicted as {predicted}: {} errors</h3>\n",
+            pair.count
+        ));
+        let images: Vec<_> = pair.examples.iter().filter_map(|example| example.image).collect();
+        not_embedded += pair.examples.len() - images.len();
+        if !images.is_empty() {
+            html.push_str(&format!("<p>{}</p>\n", img_tag(&montage(&images), &format!("{target} predicted as {predicted}"))));
+        }
+        let examples: Vec<String> = pair
+            .examples
+            .iter()
+            .map(|example| format!("#{} ({})", example.index, percent(example.confidence)))
+            .collect();
+        html.push_str(&format!("<p>Most confident: {}</p>\n</div>\n", examples.join(", ")));
+    }
+    if not_embedded > 0 {
+        html.push_str(&format!("<p>{not_embedded} more examples are listed without their image (max_images).</p>\n"));
+    }
+    html
+}
+
+// The images side by side, in order
+fn montage(images: &[[[f32; 28]; 28]]) -> DynamicImage {
+    let side = 28 * MONTAGE_SCALE;
+    let width = images.len() as u32 * (side + MONTAGE_GAP) + MONTAGE_GAP;
+    let mut montage = GrayImage::from_pixel(width, side + 2 * MONTAGE_GAP, Luma([MONTAGE_BACKGROUND]));
+    for (position, image) in images.iter().enumerate() {
+        let x0 = MONTAGE_GAP + position as u32 * (side + MONTAGE_GAP);
+        for (y, row) in image.iter().enumerate() {
+            for (x, &pixel) in row.iter().enumerate() {
+                let value = Luma([pixel.round().clamp(0.0, 255.0) as u8]);
+                for dy in 0..MONTAGE_SCALE {
+                    for dx in 0..MONTAGE_SCALE {
+                        let (x, y) = (x0 + x as u32 * MONTAGE_SCALE + dx, MONTAGE_GAP + y as u32 * MONTAGE_SCALE + dy);
+                        montage.put_pixel(x, y, value);
+                    }
+                }
+            }
+        }
+    }
+    DynamicImage::ImageLuma8(montage)
+}
+
+// An `<img>` of `image` as an inline PNG
+fn img_tag(image: &DynamicImage, alt: &str) -> String {
+    let mut png = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)
+        .expect("An in-memory PNG should encode");
+    let data = base64::engine::general_purpose::STANDARD.encode(png);
+    format!("<img src=\"data:image/png;base64,{data}\" alt=\"{alt}\">")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{evaluation::dataset_predictions, synthetic::SyntheticDigits, ModelConfig};
+    use burn::backend::{ndarray::NdArrayDevice, NdArray};
+
+    #[test]
+    fn report_keeps_the_most_confident_errors_of_every_pair() {
+        let device = NdArrayDevice::default();
+        let model = ModelConfig::new(10, 8).init::<NdArray>(&device);
+        let dataset = SyntheticDigits::new(200, 1);
+        let config = ReportConfig::new().with_examples_per_pair(3).with_max_images(5);
+
+        let report = failure_report(&model, &dataset, ClassLabels::indices(10), None, &config, &device);
+        let outcomes = dataset_predictions(&model, &dataset, &device);
+        let mut accumulator = EvalAccumulator::new(config.calibration_bins, None);
+        accumulator.extend(outcomes.iter().copied());
+        assert_eq!(report.eval, accumulator.report(ClassLabels::indices(10)));
+
+        let num_errors = outcomes.iter().filter(|outcome| !outcome.is_correct()).count();
+        assert!(num_errors > 0, "an untrained model should make errors");
+        assert_eq!(report.confused_pairs.iter().map(|pair| pair.count as usize).sum::<usize>(), num_errors);
+        assert!(report.confused_pairs.windows(2).all(|pairs| pairs[0].count >= pairs[1].count));
+        for pair in &report.confused_pairs {
+            let mut expected: Vec<(usize, f32)> = outcomes
+                .iter()
+                .enumerate()
+                .filter(|(_, outcome)| (outcome.target, outcome.predicted) == (pair.target, pair.predicted))
+                .map(|(index, outcome)| (index, outcome.confidence))
+                .collect();
+            expected.sort_by(|a, b| b.1.total_cmp(&a.1));
+            expected.truncate(3);
+            let examples: Vec<(usize, f32)> =
+                pair.examples.iter().map(|example| (example.index, example.confidence)).collect();
+            assert_eq!(examples, expected);
+        }
+        let images: Vec<&ErrorExample> = report
+            .confused_pairs
+            .iter()
+            .flat_map(|pair| &pair.examples)
+            .filter(|example| example.image.is_some())
+            .collect();
+        assert_eq!(images.len(), 5.min(report.confused_pairs.iter().map(|pair| pair.examples.len()).sum()));
+        for example in images {
+            assert_eq!(example.image, Some(dataset.get(example.index).unwrap().image));
+        }
+    }
+
+    #[test]
+    fn page_is_self_contained_html() {
+        let device = NdArrayDevice::default();
+        let model = ModelConfig::new(10, 8).init::<NdArray>(&device);
+        let dataset = SyntheticDigits::new(50, 1);
+        let labels = ClassLabels::new((0..10).map(|class| format!("<{class}>")).collect());
+        let report = failure_report(&model, &dataset, labels, None, &ReportConfig::new(), &device);
+
+        let html = render_html(&report, "run & co");
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(!html.contains("{{"), "a section was not filled in");
+        assert!(!html.contains("<script"));
+        assert!(!html.contains("src=\"http"));
+        assert!(html.contains("<title>Failure report: run &amp; co</title>"));
+        assert!(html.contains("&lt;3&gt;") && !html.contains("<3>"));
+        assert!(html.contains("<img src=\"data:image/png;base64,"));
+        // The diagram and one montage per confused pair
+        assert_eq!(html.matches("<img ").count(), 1 + report.confused_pairs.len());
+        // A header row, then one row per class, in the confusion and per-class tables
+        assert_eq!(html.matches("<span class=\"heat\"").count(), 10 * 10);
+        assert_eq!(html.matches("<tr>").count(), 7 + 11 + 11 + 1 + report.eval.calibration.bins.len());
+    }
+}
@@ -0,0 +1,248 @@
+use crate::{
+    artifact::ArtifactDir,
+    checkpoint::LoadError,
+    data::{ClassificationDataset, ClassificationItem, MnistBatcher, MnistSplit},
+    inference::{load_model, RawImage},
+    model::{Model, ModelConfig},
+    npy::{NpyReader, NpyWriter},
+    training::TrainingConfig,
+};
+use burn::{data::dataloader::batcher::Batcher, prelude::*};
+use image::{GrayImage, Luma};
+use serde::{Deserialize, Serialize};
+use std::{fmt, io, path::Path};
+
+// Version of the index layout, recorded in `index.json`
+pub const INDEX_FORMAT_VERSION: u32 = 1;
+// Where `build_index` writes the index, inside the artifact dir of the model
+pub const INDEX_DIR: &str = "embedding_index";
+
+const INDEX_FILE: &str = "index.json";
+const EMBEDDINGS_FILE: &str = "embeddings.npy";
+const INDICES_FILE: &str = "indices.npy";
+const LABELS_FILE: &str = "labels.npy";
+
+// Number of training images embedded at once
+const INDEX_BATCH_SIZE: usize = 256;
+// Gap between the images of a contact sheet, in pixels
+const SHEET_GAP: u32 = 4;
+
+// `index.json`: what the embeddings of an index were computed with. The embeddings themselves
+// are in `embeddings.npy` ([num_items, embedding_dim], f32), with the training set index and
+// label of every row in `indices.npy` and `labels.npy`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IndexMeta {
+    pub format_version: u32,
+    pub model: ModelConfig,
+    pub embedding_dim: usize,
+    pub num_items: usize,
+}
+
+// One training sample close to a query, by cosine similarity of their embeddings
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Neighbor {
+    // Index of the sample in the training split of `config.dataset`
+    pub index: usize,
+    pub label: usize,
+    pub similarity: f32,
+    // Cosine distance, 1 - similarity
+    pub distance: f32,
+}
+
+#[derive(Debug)]
+pub enum IndexError {
+    Load(LoadError),
+    Io(io::Error),
+    // The index was built from a model other than the one embedding the query
+    Incompatible { index: String, index_dim: usize, query_dim: usize },
+}
+
+impl fmt::Display for IndexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IndexError::Load(err) => write!(f, "cannot embed with the model: {err}"),
+            IndexError::Io(err) => write!(f, "could not read or write the embedding index: {err}"),
+            IndexError::Incompatible { index, index_dim, query_dim } => write!(
+                f,
+                "the embedding index {index} was built by another model config ({index_dim}-d embeddings), \
+                 the query model embeds in {query_dim} dimensions; rebuild the index with this model"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for IndexError {}
+
+impl From<io::Error> for IndexError {
+    fn from(err: io::Error) -> Self {
+        IndexError::Io(err)
+    }
+}
+
+impl From<image::ImageError> for IndexError {
+    fn from(err: image::ImageError) -> Self {
+        IndexError::Io(io::Error::other(err))
+    }
+}
+
+fn load_config(artifact_dir: &str) -> Result<TrainingConfig, IndexError> {
+    TrainingConfig::load(ArtifactDir::new(artifact_dir).config_path())
+        .map_err(|err| IndexError::Load(LoadError::Config(err.to_string())))
+}
+
+/// Embeds the whole training split of the run in `artifact_dir` with
+/// [`forward_features`](Model::forward_features) of its model, once, into an index that
+/// [`similar`] searches without running the model again. Written to `index_dir` (by default
+/// `artifact_dir/embedding_index`) as `embeddings.npy`, `indices.npy` and `labels.npy`, plus the
+/// `index.json` metadata. Labels are those of the dataset, before any `classes` relabeling.
+pub fn build_index<B: Backend>(artifact_dir: &str, index_dir: &str, device: &B::Device) -> Result<IndexMeta, IndexError> {
+    let config = load_config(artifact_dir)?;
+    let model = load_model::<B>(artifact_dir, device).map_err(IndexError::Load)?;
+    let dataset = config.dataset.load(MnistSplit::Train, config.cache);
+    let batcher = MnistBatcher::<B>::new(device.clone());
+    let embedding_dim = config.model.hidden_size;
+
+    std::fs::create_dir_all(index_dir)?;
+    let dir = Path::new(index_dir);
+    let mut embeddings = NpyWriter::<f32>::create(dir.join(EMBEDDINGS_FILE), &[embedding_dim])?;
+    let mut indices = NpyWriter::<i64>::create(dir.join(INDICES_FILE), &[])?;
+    let mut labels = NpyWriter::<i64>::create(dir.join(LABELS_FILE), &[])?;
+    let mut num_items = 0;
+
+    for start in (0..dataset.len()).step_by(INDEX_BATCH_SIZE) {
+        let end = usize::min(start + INDEX_BATCH_SIZE, dataset.len());
+        let items: Vec<ClassificationItem> = (start..end)
+            .filter_map(|index| {
+                let (pixels, label) = ClassificationDataset::get(&dataset, index)?;
+                Some(ClassificationItem { pixels, shape: dataset.image_shape(), label, index: Some(index) })
+            })
+            .collect();
+        if items.is_empty() {
+            continue;
+        }
+        let batch_labels: Vec<i64> = items.iter().map(|item| item.label as i64).collect();
+
+        let batch = batcher.batch(items);
+        let batch_indices = batch.indices.expect("Every indexed item has an index");
+        let features = model.forward_features(batch.images).into_data().convert::<f32>().value;
+
+        embeddings.write(&features)?;
+        indices.write(&batch_indices.iter().map(|&index| index as i64).collect::<Vec<_>>())?;
+        labels.write(&batch_labels)?;
+        num_items += batch_indices.len();
+    }
+    embeddings.finish()?;
+    indices.finish()?;
+    labels.finish()?;
+
+    let meta = IndexMeta { format_version: INDEX_FORMAT_VERSION, model: config.model, embedding_dim, num_items };
+    let json = serde_json::to_string_pretty(&meta).expect("Index metadata should serialize to JSON");
+    std::fs::write(dir.join(INDEX_FILE), json)?;
+    Ok(meta)
+}
+
+fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(a, b)| a * b).sum();
+    let norm = |x: &[f32]| x.iter().map(|x| x * x).sum::<f32>().sqrt();
+    // A zero embedding (every ReLU off) is similar to nothing
+    let norms = norm(a) * norm(b);
+    if norms == 0.0 {
+        0.0
+    } else {
+        (dot / norms).clamp(-1.0, 1.0)
+    }
+}
+
+/// The `k` training samples most similar to `image`, by cosine similarity between its
+/// embedding under the model of `artifact_dir` and those of the index in `index_dir` (see
+/// [`build_index`]), most similar first. The index is streamed from disk one row at a time.
+///
+/// Fails with [`IndexError::Incompatible`] when the index metadata says it was built by a model
+/// config whose embeddings differ from those of the query model.
+pub fn similar<B: Backend>(
+    artifact_dir: &str,
+    index_dir: &str,
+    image: RawImage,
+    k: usize,
+    device: &B::Device,
+) -> Result<Vec<Neighbor>, IndexError> {
+    let config = load_config(artifact_dir)?;
+    let dir = Path::new(index_dir);
+    let meta: IndexMeta = serde_json::from_str(&std::fs::read_to_string(dir.join(INDEX_FILE))?)
+        .map_err(|err| IndexError::Io(io::Error::new(io::ErrorKind::InvalidData, err)))?;
+    if meta.model.describe() != config.model.describe() || meta.embedding_dim != config.model.hidden_size {
+        return Err(IndexError::Incompatible {
+            index: index_dir.to_string(),
+            index_dim: meta.embedding_dim,
+            query_dim: config.model.hidden_size,
+        });
+    }
+
+    let model: Model<B> = load_model(artifact_dir, device).map_err(IndexError::Load)?;
+    let pixels = image.iter().flatten().copied().collect();
+    let batch = MnistBatcher::<B>::new(device.clone())
+        .batch(vec![ClassificationItem { pixels, shape: [28, 28], label: 0, index: None }]);
+    let query = model.forward_features(batch.images).into_data().convert::<f32>().value;
+
+    let mut embeddings = NpyReader::<f32>::open(dir.join(EMBEDDINGS_FILE))?;
+    let mut indices = NpyReader::<i64>::open(dir.join(INDICES_FILE))?;
+    let mut labels = NpyReader::<i64>::open(dir.join(LABELS_FILE))?;
+    if embeddings.shape() != [meta.num_items, meta.embedding_dim] {
+        let message = format!("{EMBEDDINGS_FILE} has shape {:?}, index.json describes {meta:?}", embeddings.shape());
+        return Err(IndexError::Io(io::Error::new(io::ErrorKind::InvalidData, message)));
+    }
+
+    // The best `k` so far, most similar first
+    let mut best: Vec<Neighbor> = Vec::with_capacity(k + 1);
+    while let Some(embedding) = embeddings.read_row()? {
+        let (Some(index), Some(label)) = (indices.read_row()?, labels.read_row()?) else {
+            break;
+        };
+        let similarity = cosine(&query, &embedding);
+        if best.len() == k && best.last().is_none_or(|worst| similarity <= worst.similarity) {
+            continue;
+        }
+        let neighbor = Neighbor { index: index[0] as usize, label: label[0] as usize, similarity, distance: 1.0 - similarity };
+        let position = best.partition_point(|other| other.similarity >= similarity);
+        best.insert(position, neighbor);
+        best.truncate(k);
+    }
+    Ok(best)
+}
+
+// The neighbors as a table, one row each, in order
+pub fn neighbor_table(neighbors: &[Neighbor]) -> String {
+    let mut table = format!("{:>8}  {:>5}  {:>10}  {:>8}\n", "index", "label", "similarity", "distance");
+    for neighbor in neighbors {
+        table.push_str(&format!(
+            "{:>8}  {:>5}  {:>10.4}  {:>8.4}\n",
+            neighbor.index, neighbor.label, neighbor.similarity, neighbor.distance
+        ));
+    }
+    table
+}
+
+/// Writes the query image followed by its neighbors, left to right, as one grayscale PNG.
+pub fn save_contact_sheet(artifact_dir: &str, query: &RawImage, neighbors: &[Neighbor], path: &str) -> Result<(), IndexError> {
+    let config = load_config(artifact_dir)?;
+    let dataset = config.dataset.load(MnistSplit::Train, config.cache);
+    let [height, width] = dataset.image_shape();
+    let (height, width) = (height.max(28) as u32, width.max(28) as u32);
+
+    let num_images = neighbors.len() as u32 + 1;
+    let mut sheet = GrayImage::from_pixel(num_images * (width + SHEET_GAP) - SHEET_GAP, height, Luma([128]));
+    let mut paste = |slot: u32, pixels: &[f32], row_len: usize| {
+        for (offset, &pixel) in pixels.iter().enumerate() {
+            let (x, y) = ((offset % row_len) as u32, (offset / row_len) as u32);
+            sheet.put_pixel(slot * (width + SHEET_GAP) + x, y, Luma([pixel.clamp(0.0, 255.0) as u8]));
+        }
+    };
+    paste(0, &query.iter().flatten().copied().collect::<Vec<_>>(), 28);
+    for (slot, neighbor) in neighbors.iter().enumerate() {
+        if let Some((pixels, _)) = ClassificationDataset::get(&dataset, neighbor.index) {
+            paste(slot as u32 + 1, &pixels, dataset.image_shape()[1]);
+        }
+    }
+    sheet.save(path)?;
+    Ok(())
+}
@@ -0,0 +1,395 @@
+use crate::{artifact::ArtifactDir, download::sha256_file, history::History};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{collections::BTreeMap, collections::BTreeSet, fmt, fs, io, path::Path};
+
+// Version of the `run_record.json` layout
+pub const RUN_RECORD_VERSION: u32 = 1;
+
+pub const RUN_RECORD_FILE: &str = "run_record.json";
+
+// Files of an artifact dir that are hashed rather than copied into the record
+const HASHED_FILES: [&str; 3] = ["model.mpk", "model_swa.mpk", "model_meta.json"];
+
+#[derive(Debug)]
+pub enum RunRecordError {
+    Io { path: String, err: io::Error },
+    // A file is not the JSON it should be
+    Invalid { path: String, message: String },
+}
+
+impl fmt::Display for RunRecordError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RunRecordError::Io { path, err } => write!(f, "could not read or write {path}: {err}"),
+            RunRecordError::Invalid { path, message } => write!(f, "{path} is invalid: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for RunRecordError {}
+
+/// Everything about a training run but its weights, to compare runs made on different
+/// machines: saved as `run_record.json` by [`export_run`].
+///
+/// The config, metadata and evaluation report are kept as the JSON they were read from, so that
+/// records of builds with different fields still load and diff.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RunRecord {
+    pub format_version: u32,
+    // `config.json`
+    pub config: Value,
+    // `model_meta.json`, `None` for runs that saved no weights
+    pub meta: Option<Value>,
+    // `history.json`, empty for runs interrupted before it was written
+    pub history: History,
+    // `eval.json`, `None` until the run is evaluated
+    pub eval: Option<Value>,
+    // Hex SHA-256 of every weights and metadata file of the run, by file name
+    pub hashes: BTreeMap<String, String>,
+}
+
+fn read_json(path: &Path) -> Result<Value, RunRecordError> {
+    let display = path.display().to_string();
+    let json = fs::read_to_string(path).map_err(|err| RunRecordError::Io { path: display.clone(), err })?;
+    serde_json::from_str(&json).map_err(|err| RunRecordError::Invalid { path: display, message: err.to_string() })
+}
+
+// `read_json` of a file that may not exist
+fn read_optional_json(path: &Path) -> Result<Option<Value>, RunRecordError> {
+    path.exists().then(|| read_json(path)).transpose()
+}
+
+impl RunRecord {
+    /// The record of the run in `artifact_dir`, read without writing anything. Only the
+    /// `config.json` is required.
+    pub fn read(artifact_dir: &str) -> Result<Self, RunRecordError> {
+        let dir = Path::new(artifact_dir);
+        let history = match read_optional_json(&dir.join("history.json"))? {
+            Some(history) => serde_json::from_value(history).map_err(|err| RunRecordError::Invalid {
+                path: dir.join("history.json").display().to_string(),
+                message: err.to_string(),
+            })?,
+            None => History::default(),
+        };
+
+        let mut hashes = BTreeMap::new();
+        for name in HASHED_FILES {
+            let path = dir.join(name);
+            if path.exists() {
+                let hash = sha256_file(&path).map_err(|err| RunRecordError::Io { path: path.display().to_string(), err })?;
+                hashes.insert(name.to_string(), hash);
+            }
+        }
+
+        Ok(Self {
+            format_version: RUN_RECORD_VERSION,
+            config: read_json(&dir.join("config.json"))?,
+            meta: read_optional_json(&dir.join("model_meta.json"))?,
+            history,
+            eval: read_optional_json(&dir.join("eval.json"))?,
+            hashes,
+        })
+    }
+
+    // Reads a `run_record.json`
+    pub fn load(path: &str) -> Result<Self, RunRecordError> {
+        serde_json::from_value(read_json(Path::new(path))?)
+            .map_err(|err| RunRecordError::Invalid { path: path.to_string(), message: err.to_string() })
+    }
+
+    /// [`RunRecord::load`] of a record file, or [`RunRecord::read`] of an artifact dir.
+    pub fn open(path: &str) -> Result<Self, RunRecordError> {
+        if Path::new(path).is_dir() {
+            Self::read(path)
+        } else {
+            Self::load(path)
+        }
+    }
+}
+
+/// Reads the record of the run in `artifact_dir` and saves it there as `run_record.json`.
+pub fn export_run(artifact_dir: &str) -> Result<RunRecord, RunRecordError> {
+    let record = RunRecord::read(artifact_dir)?;
+    let path = ArtifactDir::new(artifact_dir).run_record_path();
+    let json = serde_json::to_string_pretty(&record).expect("Run record should serialize to JSON");
+    fs::write(&path, json).map_err(|err| RunRecordError::Io { path, err })?;
+    Ok(record)
+}
+
+// The leaves of `value` by dotted path (`model.hidden_size`). Arrays are leaves: a list of
+// classes differs as a whole.
+fn flatten(value: &Value) -> BTreeMap<String, Value> {
+    fn walk(prefix: &str, value: &Value, leaves: &mut BTreeMap<String, Value>) {
+        match value {
+            Value::Object(fields) if !fields.is_empty() => {
+                for (name, value) in fields {
+                    let path = if prefix.is_empty() { name.clone() } else { format!("{prefix}.{name}") };
+                    walk(&path, value, leaves);
+                }
+            }
+            leaf => {
+                leaves.insert(prefix.to_string(), leaf.clone());
+            }
+        }
+    }
+    let mut leaves = BTreeMap::new();
+    walk("", value, &mut leaves);
+    leaves
+}
+
+// A field that differs between two records, `None` where a record lacks it
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldDiff {
+    pub field: String,
+    pub a: Option<Value>,
+    pub b: Option<Value>,
+}
+
+// A numeric value of both records, `None` where a record lacks it
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricDiff {
+    // `train/Loss` in the history, the dotted path in the evaluation report
+    pub metric: String,
+    pub a: Option<f64>,
+    pub b: Option<f64>,
+}
+
+impl MetricDiff {
+    // `b - a`, when both records have the value
+    pub fn delta(&self) -> Option<f64> {
+        Some(self.b? - self.a?)
+    }
+}
+
+// The metrics of one epoch of either record
+#[derive(Debug, Clone, PartialEq)]
+pub struct EpochDiff {
+    pub epoch: usize,
+    pub metrics: Vec<MetricDiff>,
+}
+
+/// What differs between two runs, see [`diff_runs`]. Displays as a report.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunDiff {
+    // Config and metadata fields whose values differ, metadata ones prefixed with `meta.`
+    pub config: Vec<FieldDiff>,
+    // Hashed files whose contents (or presence) differ
+    pub files: Vec<FieldDiff>,
+    // Every epoch of either history, by epoch number
+    pub epochs: Vec<EpochDiff>,
+    // The numbers of the evaluation reports (accuracy, calibration errors...), lists left out
+    pub eval: Vec<MetricDiff>,
+}
+
+fn field_diffs(a: &BTreeMap<String, Value>, b: &BTreeMap<String, Value>) -> Vec<FieldDiff> {
+    let fields: BTreeSet<&String> = a.keys().chain(b.keys()).collect();
+    fields
+        .into_iter()
+        .filter(|field| a.get(*field) != b.get(*field))
+        .map(|field| FieldDiff { field: field.clone(), a: a.get(field).cloned(), b: b.get(field).cloned() })
+        .collect()
+}
+
+fn metric_diffs(a: &BTreeMap<String, f64>, b: &BTreeMap<String, f64>) -> Vec<MetricDiff> {
+    let metrics: BTreeSet<&String> = a.keys().chain(b.keys()).collect();
+    metrics
+        .into_iter()
+        .map(|metric| MetricDiff { metric: metric.clone(), a: a.get(metric).copied(), b: b.get(metric).copied() })
+        .collect()
+}
+
+// The numeric leaves of an evaluation report
+fn eval_numbers(eval: Option<&Value>) -> BTreeMap<String, f64> {
+    let Some(eval) = eval else {
+        return BTreeMap::new();
+    };
+    flatten(eval).into_iter().filter_map(|(path, value)| Some((path, value.as_f64()?))).collect()
+}
+
+/// Compares two run records: the config and metadata fields that differ, the weights and
+/// metadata files that differ, and every metric of every epoch and of the evaluation reports
+/// side by side. Epochs are aligned on their number, so runs of different lengths compare
+/// over their common epochs with the others marked missing on one side.
+pub fn diff_runs(a: &RunRecord, b: &RunRecord) -> RunDiff {
+    let manifest = |record: &RunRecord| {
+        let mut fields = flatten(&record.config);
+        if let Some(meta) = &record.meta {
+            fields.extend(flatten(meta).into_iter().map(|(field, value)| (format!("meta.{field}"), value)));
+        }
+        fields
+    };
+    let hashes =
+        |record: &RunRecord| record.hashes.iter().map(|(name, hash)| (name.clone(), Value::from(hash.as_str()))).collect();
+
+    let by_epoch = |history: &History| -> BTreeMap<usize, BTreeMap<String, f64>> {
+        history
+            .epochs
+            .iter()
+            .map(|record| {
+                let train = record.train.iter().map(|(name, &value)| (format!("train/{name}"), value));
+                let valid = record.valid.iter().map(|(name, &value)| (format!("valid/{name}"), value));
+                (record.epoch, train.chain(valid).collect())
+            })
+            .collect()
+    };
+    let (epochs_a, epochs_b) = (by_epoch(&a.history), by_epoch(&b.history));
+    let empty = BTreeMap::new();
+    let epochs = epochs_a
+        .keys()
+        .chain(epochs_b.keys())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .map(|&epoch| EpochDiff {
+            epoch,
+            metrics: metric_diffs(epochs_a.get(&epoch).unwrap_or(&empty), epochs_b.get(&epoch).unwrap_or(&empty)),
+        })
+        .collect();
+
+    RunDiff {
+        config: field_diffs(&manifest(a), &manifest(b)),
+        files: field_diffs(&hashes(a), &hashes(b)),
+        epochs,
+        eval: metric_diffs(&eval_numbers(a.eval.as_ref()), &eval_numbers(b.eval.as_ref())),
+    }
+}
+
+fn value_cell(value: &Option<Value>) -> String {
+    value.as_ref().map_or("missing".to_string(), Value::to_string)
+}
+
+fn metric_cell(value: Option<f64>) -> String {
+    value.map_or("missing".to_string(), |value| format!("{value:.4}"))
+}
+
+impl fmt::Display for RunDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Config: {} field(s) differ", self.config.len())?;
+        for diff in &self.config {
+            writeln!(f, "  {}: {} -> {}", diff.field, value_cell(&diff.a), value_cell(&diff.b))?;
+        }
+        match self.files.is_empty() {
+            true => writeln!(f, "Files: weights and metadata identical")?,
+            false => {
+                let names: Vec<&str> = self.files.iter().map(|diff| diff.field.as_str()).collect();
+                writeln!(f, "Files: {} differ", names.join(", "))?;
+            }
+        }
+
+        let rows = |f: &mut fmt::Formatter<'_>, label: &str, metrics: &[MetricDiff]| -> fmt::Result {
+            for metric in metrics {
+                let delta = metric.delta().map_or(String::new(), |delta| format!("{delta:+.4}"));
+                writeln!(
+                    f,
+                    "| {label:>5} | {:<24} | {:>10} | {:>10} | {delta:>10} |",
+                    metric.metric,
+                    metric_cell(metric.a),
+                    metric_cell(metric.b)
+                )?;
+            }
+            Ok(())
+        };
+        let header = |f: &mut fmt::Formatter<'_>, first: &str| -> fmt::Result {
+            writeln!(f, "| {first:>5} | {:<24} | {:>10} | {:>10} | {:>10} |", "Metric", "A", "B", "Delta")?;
+            writeln!(f, "|-------|--------------------------|------------|------------|------------|")
+        };
+
+        writeln!(f, "Epochs:")?;
+        header(f, "Epoch")?;
+        for epoch in &self.epochs {
+            rows(f, &epoch.epoch.to_string(), &epoch.metrics)?;
+        }
+        write!(f, "Evaluation:")?;
+        if self.eval.is_empty() {
+            return write!(f, " neither run is evaluated");
+        }
+        writeln!(f)?;
+        header(f, "")?;
+        rows(f, "", &self.eval)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::EpochMetrics;
+    use serde_json::json;
+
+    // A record of `losses.len()` epochs, one validation loss each
+    fn record(config: Value, losses: &[f64], eval: Option<Value>, model_hash: &str) -> RunRecord {
+        let epochs = losses
+            .iter()
+            .enumerate()
+            .map(|(index, &loss)| EpochMetrics {
+                epoch: index + 1,
+                train: BTreeMap::new(),
+                valid: BTreeMap::from([("Loss".to_string(), loss)]),
+                accuracy_gap: None,
+                fraction: None,
+            })
+            .collect();
+        RunRecord {
+            format_version: RUN_RECORD_VERSION,
+            config,
+            meta: None,
+            history: History { epochs },
+            eval,
+            hashes: BTreeMap::from([("model.mpk".to_string(), model_hash.to_string())]),
+        }
+    }
+
+    #[test]
+    fn diff_lists_changed_fields_and_aligns_epochs_of_different_runs() {
+        let a = record(
+            json!({"learning_rate": 1e-3, "model": {"hidden_size": 32, "dropout": 0.5}}),
+            &[0.9, 0.5],
+            None,
+            "aa",
+        );
+        let b = record(
+            json!({"learning_rate": 1e-3, "model": {"hidden_size": 64, "dropout": 0.5}, "seed": 7}),
+            &[0.8, 0.4, 0.3],
+            Some(json!({"accuracy": 97.5, "per_class": [1, 2]})),
+            "bb",
+        );
+
+        let diff = diff_runs(&a, &b);
+        let fields: Vec<(&str, Option<Value>, Option<Value>)> =
+            diff.config.iter().map(|field| (field.field.as_str(), field.a.clone(), field.b.clone())).collect();
+        assert_eq!(fields, [("model.hidden_size", Some(json!(32)), Some(json!(64))), ("seed", None, Some(json!(7)))]);
+        assert_eq!(diff.files[0].field, "model.mpk");
+
+        assert_eq!(diff.epochs.iter().map(|epoch| epoch.epoch).collect::<Vec<_>>(), [1, 2, 3]);
+        let loss = &diff.epochs[1].metrics[0];
+        assert_eq!(loss.metric, "valid/Loss");
+        assert!((loss.delta().unwrap() + 0.1).abs() < 1e-12);
+        // Only the longer run has a third epoch
+        assert_eq!((diff.epochs[2].metrics[0].a, diff.epochs[2].metrics[0].delta()), (None, None));
+        assert_eq!(diff.eval, [MetricDiff { metric: "accuracy".to_string(), a: None, b: Some(97.5) }]);
+
+        let same = diff_runs(&a, &a);
+        assert!(same.config.is_empty() && same.files.is_empty());
+        assert!(same.epochs.iter().flat_map(|epoch| &epoch.metrics).all(|metric| metric.delta() == Some(0.0)));
+    }
+
+    #[test]
+    fn exported_records_load_back_without_the_weights() {
+        let dir = std::env::temp_dir().join("my_first_rust_DL_app-run-record");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("config.json"), r#"{"learning_rate": 0.001}"#).unwrap();
+        fs::write(dir.join("model.mpk"), "abc").unwrap();
+        let dir = dir.to_str().unwrap();
+
+        let exported = export_run(dir).unwrap();
+        let loaded = RunRecord::open(&ArtifactDir::new(dir).run_record_path()).unwrap();
+        let read = RunRecord::open(dir).unwrap();
+        fs::remove_dir_all(dir).unwrap();
+
+        assert_eq!(loaded, exported);
+        assert_eq!(read, exported);
+        // SHA-256 of "abc"
+        assert_eq!(exported.hashes["model.mpk"], "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        assert!(exported.history.epochs.is_empty() && exported.meta.is_none() && exported.eval.is_none());
+    }
+}
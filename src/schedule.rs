@@ -0,0 +1,225 @@
+use burn::{
+    lr_scheduler::LrScheduler,
+    prelude::*,
+    train::{
+        checkpoint::{CheckpointingAction, CheckpointingStrategy},
+        metric::store::EventStoreClient,
+        metric::{
+            state::{FormatOptions, NumericMetricState},
+            Metric, MetricEntry, MetricMetadata, Numeric,
+        },
+    },
+    LearningRate,
+};
+use std::{fs, io, path::Path};
+
+/// How the learning rate evolves over training, starting from `TrainingConfig::learning_rate`.
+#[derive(Config, Debug, PartialEq)]
+pub enum LrSchedule {
+    // The learning rate never changes
+    Constant,
+    // SGDR: cosine annealing from the base learning rate down to `min_lr` over a cycle of
+    // `t_initial` epochs, then a restart at the base learning rate. Each cycle is `t_mult` times
+    // longer than the previous one. Whole epochs keep every restart on an epoch boundary, where
+    // the validation pass and checkpoint happen.
+    CosineWarmRestarts { t_initial: usize, t_mult: usize, min_lr: f64 },
+}
+
+impl LrSchedule {
+    // Epochs at whose end the learning rate restarts, within a run of `num_epochs` epochs
+    pub fn restart_epochs(&self, num_epochs: usize) -> Vec<usize> {
+        let LrSchedule::CosineWarmRestarts { t_initial, t_mult, .. } = self else {
+            return Vec::new();
+        };
+
+        let mut restarts = Vec::new();
+        let (mut end, mut length) = (*t_initial, *t_initial);
+        while length > 0 && end < num_epochs {
+            restarts.push(end);
+            length *= t_mult;
+            end += length;
+        }
+        restarts
+    }
+}
+
+// Number of batches a dataloader built by `mnist_dataloader` yields per epoch: burn splits the
+// dataset evenly between the workers (the last one takes the remainder), and each worker ends
+// with its own partial batch. Without workers the dataloader is a single one.
+pub fn batches_per_epoch(num_items: usize, batch_size: usize, num_workers: usize) -> usize {
+    let num_workers = num_workers.max(1);
+    let part = num_items / num_workers;
+    let last = num_items - part * (num_workers - 1);
+    part.div_ceil(batch_size) * (num_workers - 1) + last.div_ceil(batch_size)
+}
+
+// Burn scheduler for an `LrSchedule`, stepped once per training iteration
+#[derive(Clone, Debug)]
+pub struct Scheduler {
+    schedule: LrSchedule,
+    base_lr: f64,
+    steps_per_epoch: usize,
+    step: usize,
+}
+
+impl Scheduler {
+    pub fn new(schedule: LrSchedule, base_lr: f64, steps_per_epoch: usize) -> Self {
+        Self { schedule, base_lr, steps_per_epoch, step: 0 }
+    }
+
+    // Learning rate of the 0-based training step `step`
+    pub fn lr_at(&self, step: usize) -> f64 {
+        let LrSchedule::CosineWarmRestarts { t_initial, t_mult, min_lr } = self.schedule else {
+            return self.base_lr;
+        };
+
+        // Find the cycle `step` falls in; a t_mult of 1 makes every cycle the same length
+        let (mut start, mut length) = (0, t_initial * self.steps_per_epoch);
+        while length > 0 && step >= start + length {
+            start += length;
+            length *= t_mult;
+        }
+        if length == 0 {
+            return self.base_lr;
+        }
+
+        let progress = (step - start) as f64 / length as f64;
+        min_lr + (self.base_lr - min_lr) * (1.0 + (std::f64::consts::PI * progress).cos()) / 2.0
+    }
+
+    // 0-based steps at which the learning rate restarts, within `num_epochs` epochs
+    pub fn restart_steps(&self, num_epochs: usize) -> Vec<usize> {
+        self.schedule
+            .restart_epochs(num_epochs)
+            .into_iter()
+            .map(|epoch| epoch * self.steps_per_epoch)
+            .collect()
+    }
+}
+
+impl<B: Backend> LrScheduler<B> for Scheduler {
+    // The step count is the only state
+    type Record = usize;
+
+    fn step(&mut self) -> LearningRate {
+        let lr = self.lr_at(self.step);
+        self.step += 1;
+        lr
+    }
+
+    fn to_record(&self) -> Self::Record {
+        self.step
+    }
+
+    fn load_record(mut self, record: Self::Record) -> Self {
+        self.step = record;
+        self
+    }
+}
+
+// Logs 1 on the training iterations where the learning rate restarts and 0 elsewhere, so the
+// restart boundaries show up in the iteration logs next to the learning rate
+pub struct RestartMetric {
+    state: NumericMetricState,
+    restart_steps: Vec<usize>,
+    steps_per_epoch: usize,
+}
+
+impl RestartMetric {
+    pub fn new(restart_steps: Vec<usize>, steps_per_epoch: usize) -> Self {
+        Self { state: NumericMetricState::new(), restart_steps, steps_per_epoch }
+    }
+}
+
+impl Metric for RestartMetric {
+    const NAME: &'static str = "LR Restart";
+
+    type Input = ();
+
+    fn update(&mut self, _item: &(), metadata: &MetricMetadata) -> MetricEntry {
+        // Burn counts epochs and iterations from 1
+        let step = (metadata.epoch - 1) * self.steps_per_epoch + metadata.iteration - 1;
+        let restart = if self.restart_steps.contains(&step) { 1.0 } else { 0.0 };
+        self.state.update(restart, 1, FormatOptions::new(Self::NAME).precision(0))
+    }
+
+    fn clear(&mut self) {
+        self.state.reset()
+    }
+}
+
+impl Numeric for RestartMetric {
+    fn value(&self) -> f64 {
+        self.state.value()
+    }
+}
+
+// Wraps a checkpointing strategy so that the checkpoints of the given epochs (restarts, SWA
+// epochs) are always saved and never deleted
+pub struct KeepEpochCheckpoints<S> {
+    inner: S,
+    epochs: Vec<usize>,
+}
+
+impl<S> KeepEpochCheckpoints<S> {
+    pub fn new(inner: S, epochs: Vec<usize>) -> Self {
+        Self { inner, epochs }
+    }
+}
+
+impl<S: CheckpointingStrategy> CheckpointingStrategy for KeepEpochCheckpoints<S> {
+    fn checkpointing(&mut self, epoch: usize, collector: &EventStoreClient) -> Vec<CheckpointingAction> {
+        let mut actions = self.inner.checkpointing(epoch, collector);
+        actions.retain(|action| match action {
+            CheckpointingAction::Delete(epoch) => !self.epochs.contains(epoch),
+            CheckpointingAction::Save => true,
+        });
+        if self.epochs.contains(&epoch) && !actions.contains(&CheckpointingAction::Save) {
+            actions.push(CheckpointingAction::Save);
+        }
+        actions
+    }
+}
+
+// Copies the learner checkpoint of each restart epoch into its own `checkpoint-restart-<n>`
+// directory (n counting restarts from 1), with the epoch suffix dropped from the file names
+pub fn tag_restart_checkpoints(artifact_dir: &str, restart_epochs: &[usize]) -> io::Result<()> {
+    let checkpoints = Path::new(artifact_dir).join("checkpoint");
+
+    for (index, epoch) in restart_epochs.iter().enumerate() {
+        let tagged = Path::new(artifact_dir).join(format!("checkpoint-restart-{}", index + 1));
+        fs::create_dir_all(&tagged)?;
+
+        let suffix = format!("-{epoch}.mpk");
+        for entry in fs::read_dir(&checkpoints)? {
+            let name = entry?.file_name().to_string_lossy().into_owned();
+            if let Some(kind) = name.strip_suffix(&suffix) {
+                fs::copy(checkpoints.join(&name), tagged.join(format!("{kind}.mpk")))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn learning_rate_is_back_at_the_base_on_each_restart() {
+        let schedule = LrSchedule::CosineWarmRestarts { t_initial: 2, t_mult: 2, min_lr: 1e-4 };
+        let scheduler = Scheduler::new(schedule, 1e-2, 10);
+        // Cycles of 2 then 4 epochs of 10 steps
+        assert_eq!(scheduler.restart_steps(8), [20, 60]);
+
+        let cosine = |progress: f64| 1e-4 + (1e-2 - 1e-4) * (1.0 + (std::f64::consts::PI * progress).cos()) / 2.0;
+        assert_eq!(scheduler.lr_at(0), 1e-2);
+        assert!((scheduler.lr_at(19) - cosine(19.0 / 20.0)).abs() < 1e-12);
+        for (restart, cycle_length) in [(20, 40.0), (60, 80.0)] {
+            assert_eq!(scheduler.lr_at(restart), 1e-2);
+            let after = scheduler.lr_at(restart + 1);
+            assert!((after - cosine(1.0 / cycle_length)).abs() < 1e-12);
+            assert!(after < 1e-2);
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+// Bumping it changes every derived seed, and with them the results of every sub-run: never
+// change it for anything else than a broken scheme
+const SEED_SCHEME: &[u8] = b"seed-v1";
+
+/// The seed of the sub-run `label` of a run seeded with `root`, e.g. `fold-3` of a k-fold or
+/// `trial-12` of a sweep, so that sub-runs shuffle, split, augment and initialize independently
+/// of each other instead of all reusing `root`.
+///
+/// The seed is the first 8 bytes, little-endian, of the SHA-256 of `seed-v1`, the 8 bytes of
+/// `root` in little-endian and the UTF-8 bytes of `label`. It depends on nothing else, so it
+/// is the same on every platform and in every release. These values are part of the scheme, a
+/// change of any of them breaks the reproducibility of every published sub-run:
+///
+/// | `root` | `label`   | seed                 |
+/// |--------|-----------|----------------------|
+/// | 42     | `fold-0`  | `0x2054b893cfb43389` |
+/// | 42     | `fold-1`  | `0xd6d2de6b393c7003` |
+/// | 42     | `trial-0` | `0x177cbaee439c1711` |
+/// | 0      | (empty)   | `0xc3ec59275e307b09` |
+pub fn derive_seed(root: u64, label: &str) -> u64 {
+    let digest = Sha256::new()
+        .chain_update(SEED_SCHEME)
+        .chain_update(root.to_le_bytes())
+        .chain_update(label.as_bytes())
+        .finalize();
+    u64::from_le_bytes(digest[..8].try_into().unwrap())
+}
+
+/// Where the seed of a sub-run came from, recorded in its `config.json` next to the derived
+/// seed itself.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SeedOrigin {
+    pub root: u64,
+    pub label: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ModelConfig, TrainingConfig};
+    use burn::optim::AdamConfig;
+
+    #[test]
+    fn derived_seeds_match_the_documented_values() {
+        assert_eq!(derive_seed(42, "fold-0"), 0x2054b893cfb43389);
+        assert_eq!(derive_seed(42, "fold-1"), 0xd6d2de6b393c7003);
+        assert_eq!(derive_seed(42, "trial-0"), 0x177cbaee439c1711);
+        assert_eq!(derive_seed(0, ""), 0xc3ec59275e307b09);
+    }
+
+    #[test]
+    fn sub_runs_derive_from_the_root_seed() {
+        let config = TrainingConfig::new(ModelConfig::new(10, 8), AdamConfig::new()).with_seed(42);
+        let fold = config.sub_run("fold-1");
+        assert_eq!(fold.seed, derive_seed(42, "fold-1"));
+        assert_eq!(fold.seed_origin, Some(SeedOrigin { root: 42, label: "fold-1".to_string() }));
+
+        // A sub-run of a sub-run is a sibling, not a grandchild
+        let trial = fold.sub_run("trial-0");
+        assert_eq!(trial.seed, derive_seed(42, "trial-0"));
+        assert_eq!(trial.seed_origin, Some(SeedOrigin { root: 42, label: "trial-0".to_string() }));
+        assert_eq!(config.seed, 42);
+        assert_eq!(config.seed_origin, None);
+    }
+}
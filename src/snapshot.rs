@@ -0,0 +1,153 @@
+use crate::{
+    artifact::ArtifactDir,
+    data::{ClassificationDataset, ClassificationItem, MnistBatcher},
+    inference::argmax,
+    model::{Model, ModelConfig},
+};
+use burn::{
+    data::dataloader::batcher::Batcher,
+    prelude::*,
+    record::{CompactRecorder, Recorder, RecorderError},
+    tensor::activation::softmax,
+};
+use serde::{Deserialize, Serialize};
+use std::{fmt, path::Path};
+
+// Number of validation images scored at once
+const SNAPSHOT_BATCH_SIZE: usize = 256;
+
+// Accuracy (in percent) of the snapshot ensemble and of the final model alone on the validation
+// split, saved as `snapshot_ensemble.json`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SnapshotEnsembleReport {
+    // Epochs whose end-of-epoch weights are members of the ensemble, the last one being the
+    // final model
+    pub epochs: Vec<usize>,
+    pub num_samples: usize,
+    pub ensemble_accuracy: f64,
+    pub final_accuracy: f64,
+}
+
+impl SnapshotEnsembleReport {
+    // Gain of the ensemble over the final model, in percentage points
+    pub fn delta(&self) -> f64 {
+        self.ensemble_accuracy - self.final_accuracy
+    }
+}
+
+impl fmt::Display for SnapshotEnsembleReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Snapshot ensemble of {} snapshots (epochs {:?}), on the validation split:",
+            self.epochs.len(),
+            self.epochs
+        )?;
+        writeln!(f, "| {:<8} | {:>10} |", "Model", "Accuracy")?;
+        writeln!(f, "|----------|------------|")?;
+        writeln!(f, "| {:<8} | {:>9.2}% |", "final", self.final_accuracy)?;
+        writeln!(f, "| {:<8} | {:>9.2}% |", "ensemble", self.ensemble_accuracy)?;
+        write!(f, "Ensemble vs final: {:+.2} points", self.delta())
+    }
+}
+
+// Snapshot ensembling of a warm-restart run: the softmax outputs of the model at the end of
+// every cycle are averaged, the final model closing the last cycle. The snapshots are the
+// learner checkpoints of `restart_epochs` (those missing, e.g. after a budget stop, are left out)
+// and `final_epoch` is the epoch the final model ended.
+//
+// The snapshots are loaded one at a time and scored over `dataset` in order, so only one of them
+// and the sums of the probabilities of every sample are in memory at once.
+pub fn snapshot_ensemble<B: Backend, D: ClassificationDataset + ?Sized>(
+    artifact_dir: &str,
+    config: &ModelConfig,
+    restart_epochs: &[usize],
+    final_model: &Model<B>,
+    final_epoch: usize,
+    dataset: &D,
+    device: &B::Device,
+) -> Result<SnapshotEnsembleReport, RecorderError> {
+    let (dir, recorder) = (ArtifactDir::new(artifact_dir), CompactRecorder::new());
+    let mut epochs: Vec<usize> = restart_epochs
+        .iter()
+        .copied()
+        .filter(|epoch| *epoch < final_epoch)
+        .filter(|epoch| Path::new(&format!("{}.mpk", dir.checkpoint_path(*epoch))).exists())
+        .collect();
+
+    let mut sums: Vec<f32> = Vec::new();
+    let mut labels = Vec::new();
+    for epoch in &epochs {
+        let record = recorder.load(dir.checkpoint_path(*epoch).into(), device)?;
+        let snapshot = config.init::<B>(device).load_record(record);
+        accumulate_probabilities(&snapshot, dataset, &mut sums, &mut labels, device);
+    }
+    let final_correct = accumulate_probabilities(final_model, dataset, &mut sums, &mut labels, device);
+    epochs.push(final_epoch);
+
+    let num_classes = config.num_classes;
+    let ensemble_correct = sums
+        .chunks(num_classes)
+        .zip(&labels)
+        .filter(|(sums, &label)| argmax(sums) == label)
+        .count();
+    let num_samples = labels.len();
+    let percent = |correct: usize| correct as f64 / num_samples.max(1) as f64 * 100.0;
+    Ok(SnapshotEnsembleReport {
+        epochs,
+        num_samples,
+        ensemble_accuracy: percent(ensemble_correct),
+        final_accuracy: percent(final_correct),
+    })
+}
+
+// Adds the softmax outputs of `model` over the readable items of `dataset`, in order, to `sums`
+// (`num_classes` values per item), collecting the labels on the first pass. Returns the number
+// of items `model` alone gets right.
+fn accumulate_probabilities<B: Backend, D: ClassificationDataset + ?Sized>(
+    model: &Model<B>,
+    dataset: &D,
+    sums: &mut Vec<f32>,
+    labels: &mut Vec<usize>,
+    device: &B::Device,
+) -> usize {
+    let batcher = MnistBatcher::<B>::new(device.clone());
+    let first_pass = sums.is_empty();
+    let (mut offset, mut correct) = (0, 0);
+
+    for start in (0..dataset.len()).step_by(SNAPSHOT_BATCH_SIZE) {
+        let end = usize::min(start + SNAPSHOT_BATCH_SIZE, dataset.len());
+        let items: Vec<ClassificationItem> = (start..end)
+            .filter_map(|index| {
+                let (pixels, label) = dataset.get(index)?;
+                Some(ClassificationItem { pixels, shape: dataset.image_shape(), label, index: Some(index) })
+            })
+            .collect();
+        if items.is_empty() {
+            continue;
+        }
+        if first_pass {
+            labels.extend(items.iter().map(|item| item.label));
+        }
+
+        let batch = batcher.batch(items);
+        let probabilities = softmax(model.forward(batch.images), 1).into_data().convert::<f32>().value;
+        if first_pass {
+            sums.extend_from_slice(&probabilities);
+        } else {
+            for (sum, probability) in sums[offset..offset + probabilities.len()].iter_mut().zip(&probabilities) {
+                *sum += probability;
+            }
+        }
+
+        let num_classes = probabilities.len() / batch.targets.dims()[0];
+        let batch_labels = &labels[offset / num_classes..];
+        correct += probabilities
+            .chunks(num_classes)
+            .zip(batch_labels)
+            .filter(|(probabilities, &label)| argmax(probabilities) == label)
+            .count();
+        offset += probabilities.len();
+    }
+    correct
+}
@@ -0,0 +1,149 @@
+use std::{fmt, fs, io, path::Path};
+
+/// Per-sample label distributions to train against instead of the hard labels, e.g. the
+/// probabilities of a teacher model: row `i` is the target of item `i` of the training set.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SoftLabels {
+    num_classes: usize,
+    // Row-major `[num_samples, num_classes]`, every row summing to 1
+    probabilities: Vec<f32>,
+}
+
+#[derive(Debug)]
+pub enum SoftLabelsError {
+    Io(io::Error),
+    // A field of a row is not a number (`line` counts from 1)
+    Parse { line: usize, field: String },
+    // A row does not have one probability per class
+    Dimension { line: usize, found: usize, expected: usize },
+    // A row has a negative or non-finite value, or sums to 0
+    Invalid { line: usize, reason: String },
+    // The file does not have one row per training item
+    Count { found: usize, expected: usize },
+}
+
+impl fmt::Display for SoftLabelsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SoftLabelsError::Io(err) => write!(f, "could not read the soft labels: {err}"),
+            SoftLabelsError::Parse { line, field } => {
+                write!(f, "soft labels line {line}: {field:?} is not a number")
+            }
+            SoftLabelsError::Dimension { line, found, expected } => write!(
+                f,
+                "soft labels line {line} has {found} values, expected {expected} (one per class)"
+            ),
+            SoftLabelsError::Invalid { line, reason } => write!(f, "soft labels line {line} {reason}"),
+            SoftLabelsError::Count { found, expected } => write!(
+                f,
+                "the soft labels have {found} rows, expected {expected} (one per training item, in order)"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SoftLabelsError {}
+
+impl From<io::Error> for SoftLabelsError {
+    fn from(err: io::Error) -> Self {
+        SoftLabelsError::Io(err)
+    }
+}
+
+impl SoftLabels {
+    /// Reads a CSV of `num_samples` rows of `num_classes` comma-separated probabilities, no
+    /// header, row `i` being the target of training item `i`. Blank lines are skipped. Rows are
+    /// normalized to sum to 1, so that rounded teacher outputs load as they are.
+    pub fn load(path: impl AsRef<Path>, num_samples: usize, num_classes: usize) -> Result<Self, SoftLabelsError> {
+        let csv = fs::read_to_string(path)?;
+        let mut probabilities = Vec::with_capacity(num_samples * num_classes);
+        let mut found = 0;
+        for (position, row) in csv.lines().enumerate() {
+            let line = position + 1;
+            if row.trim().is_empty() {
+                continue;
+            }
+            let values = row
+                .split(',')
+                .map(|field| field.trim().parse::<f32>().map_err(|_| SoftLabelsError::Parse { line, field: field.to_string() }))
+                .collect::<Result<Vec<_>, _>>()?;
+            if values.len() != num_classes {
+                return Err(SoftLabelsError::Dimension { line, found: values.len(), expected: num_classes });
+            }
+            if let Some(value) = values.iter().find(|value| !(value.is_finite() && **value >= 0.0)) {
+                return Err(SoftLabelsError::Invalid { line, reason: format!("has {value}, expected values >= 0") });
+            }
+            let sum: f32 = values.iter().sum();
+            if sum <= 0.0 {
+                return Err(SoftLabelsError::Invalid { line, reason: "sums to 0".to_string() });
+            }
+            probabilities.extend(values.iter().map(|value| value / sum));
+            found += 1;
+        }
+        if found != num_samples {
+            return Err(SoftLabelsError::Count { found, expected: num_samples });
+        }
+        Ok(Self { num_classes, probabilities })
+    }
+
+    pub fn num_classes(&self) -> usize {
+        self.num_classes
+    }
+
+    pub fn len(&self) -> usize {
+        self.probabilities.len() / self.num_classes.max(1)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.probabilities.is_empty()
+    }
+
+    // The distribution of training item `index`
+    pub fn row(&self, index: usize) -> Option<&[f32]> {
+        self.probabilities.get(index * self.num_classes..(index + 1) * self.num_classes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(csv: &str, num_samples: usize, num_classes: usize) -> Result<SoftLabels, SoftLabelsError> {
+        // Named after the content: the tests run in parallel
+        let name: String = csv.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect();
+        let path = std::env::temp_dir().join(format!("my_first_rust_DL_app-soft-labels-{name}-{num_samples}.csv"));
+        fs::write(&path, csv).unwrap();
+        let result = SoftLabels::load(&path, num_samples, num_classes);
+        fs::remove_file(&path).unwrap();
+        result
+    }
+
+    #[test]
+    fn rows_are_normalized_and_blank_lines_skipped() {
+        let labels = load("0.2, 0.2, 0.6\n\n1,1,2\n0,0,3\n", 3, 3).unwrap();
+        assert_eq!((labels.len(), labels.num_classes()), (3, 3));
+        let first = labels.row(0).unwrap();
+        assert!((first[0] - 0.2).abs() < 1e-6 && (first[2] - 0.6).abs() < 1e-6);
+        assert_eq!(labels.row(1).unwrap(), [0.25, 0.25, 0.5]);
+        assert_eq!(labels.row(2).unwrap(), [0.0, 0.0, 1.0]);
+        assert_eq!(labels.row(3), None);
+    }
+
+    #[test]
+    fn errors_name_the_line_they_are_on() {
+        assert!(matches!(
+            load("1,0\n\n0,x\n", 2, 2),
+            Err(SoftLabelsError::Parse { line: 3, field }) if field == "x"
+        ));
+        assert!(matches!(
+            load("1,0\n0,1,0\n", 2, 2),
+            Err(SoftLabelsError::Dimension { line: 2, found: 3, expected: 2 })
+        ));
+        assert!(matches!(load("1,-0.5\n", 1, 2), Err(SoftLabelsError::Invalid { line: 1, .. })));
+        assert!(matches!(load("1,NaN\n", 1, 2), Err(SoftLabelsError::Invalid { line: 1, .. })));
+        assert!(matches!(load("1,0\n0,0\n", 2, 2), Err(SoftLabelsError::Invalid { line: 2, .. })));
+        assert!(matches!(load("1,0\n0,1\n", 3, 2), Err(SoftLabelsError::Count { found: 2, expected: 3 })));
+        let missing = SoftLabels::load("/nonexistent/my_first_rust_DL_app-soft-labels.csv", 1, 2);
+        assert!(matches!(missing, Err(SoftLabelsError::Io(_))));
+    }
+}
@@ -0,0 +1,192 @@
+use crate::{
+    artifact::ArtifactDir,
+    checkpoint::{LoadError, LoadReport},
+    inference::load_model,
+    labels::ClassLabels,
+    meta::ModelMeta,
+    model::Model,
+    params::{named_params, with_named_params, NamedParam},
+    training::{create_artifact_dir, TrainingConfig},
+};
+use burn::{
+    prelude::*,
+    record::{CompactRecorder, RecorderError},
+};
+use std::{fmt, io};
+
+#[derive(Debug)]
+pub enum SoupError {
+    // No artifact dirs to average
+    Empty,
+    // A model could not be loaded, or does not match the first one
+    Load { artifact_dir: String, err: LoadError },
+    Io(io::Error),
+    Record(RecorderError),
+}
+
+impl fmt::Display for SoupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SoupError::Empty => write!(f, "no models to average"),
+            SoupError::Load { artifact_dir, err } => write!(f, "cannot average {artifact_dir}: {err}"),
+            SoupError::Io(err) => write!(f, "could not write the averaged model: {err}"),
+            SoupError::Record(err) => write!(f, "could not save the averaged model: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for SoupError {}
+
+impl From<io::Error> for SoupError {
+    fn from(err: io::Error) -> Self {
+        SoupError::Io(err)
+    }
+}
+
+impl From<RecorderError> for SoupError {
+    fn from(err: RecorderError) -> Self {
+        SoupError::Record(err)
+    }
+}
+
+// The labels a run's classes stand for must agree too, or the averaged classes mean nothing
+fn check_labels(first: &TrainingConfig, other: &TrainingConfig) -> Result<(), LoadError> {
+    let incompatible = |field: &str, first: String, other: String| LoadError::Incompatible {
+        field: field.to_string(),
+        meta: other,
+        config: first,
+    };
+    if first.classes != other.classes {
+        return Err(incompatible("classes", format!("{:?}", first.classes), format!("{:?}", other.classes)));
+    }
+    if first.binary_target != other.binary_target {
+        let (first, other) = (format!("{:?}", first.binary_target), format!("{:?}", other.binary_target));
+        return Err(incompatible("binary_target", first, other));
+    }
+    Ok(())
+}
+
+/// Model soup: the element-wise mean of the parameters of the models trained into
+/// `artifact_dirs`, saved into the `output` artifact dir (wiped first) along with the config and
+/// metadata of the first model, so that [`load_model`] reads it like any trained model. Returns
+/// the averaged model.
+///
+/// Every model must have exactly the parameters of the first one, names and shapes, and be
+/// trained on the same labels (`classes`, `binary_target`). The models are averaged one at a
+/// time, only the running sums and one model are in memory. Soups work best from models
+/// fine-tuned from a common starting point; independently initialized ones rarely average well.
+pub fn average_checkpoints<B: Backend>(
+    artifact_dirs: &[&str],
+    output: &str,
+    device: &B::Device,
+) -> Result<Model<B>, SoupError> {
+    let (&first_dir, _) = artifact_dirs.split_first().ok_or(SoupError::Empty)?;
+    let load = |artifact_dir: &str| {
+        let wrap = |err| SoupError::Load { artifact_dir: artifact_dir.to_string(), err };
+        let config = TrainingConfig::load(ArtifactDir::new(artifact_dir).config_path())
+            .map_err(|err| wrap(LoadError::Config(err.to_string())))?;
+        let model = load_model::<B>(artifact_dir, device).map_err(wrap)?;
+        Ok::<_, SoupError>((config, named_params(&model)))
+    };
+
+    let (config, first) = load(first_dir)?;
+    let mut sums: Vec<Vec<f64>> =
+        first.iter().map(|param| param.values.iter().map(|&value| value as f64).collect()).collect();
+    for &artifact_dir in &artifact_dirs[1..] {
+        let (other_config, params) = load(artifact_dir)?;
+        let wrap = |err| SoupError::Load { artifact_dir: artifact_dir.to_string(), err };
+        let report = LoadReport::compare(&first, &params);
+        if !report.is_exact() {
+            return Err(wrap(LoadError::Mismatch(report)));
+        }
+        check_labels(&config, &other_config).map_err(wrap)?;
+
+        for (sum, param) in sums.iter_mut().zip(&first) {
+            let values = &params.iter().find(|other| other.name == param.name).expect("Compared above").values;
+            for (sum, &value) in sum.iter_mut().zip(values) {
+                *sum += value as f64;
+            }
+        }
+    }
+
+    let n = artifact_dirs.len() as f64;
+    let average: Vec<NamedParam> = first
+        .into_iter()
+        .zip(sums)
+        .map(|(param, sum)| NamedParam { values: sum.iter().map(|&sum| (sum / n) as f32).collect(), ..param })
+        .collect();
+    let meta = ModelMeta::load(first_dir).map_err(|err| SoupError::Load { artifact_dir: first_dir.to_string(), err })?;
+    let model_config = meta.as_ref().map_or(&config.model, |meta| &meta.model);
+    let soup = with_named_params(model_config.init::<B>(device), &average, device);
+
+    create_artifact_dir(output)?;
+    config.save(format!("{output}/config.json"))?;
+    ClassLabels::load(first_dir).save(output)?;
+    soup.clone().save_file(format!("{output}/model"), &CompactRecorder::new())?;
+    if let Some(mut meta) = meta {
+        // The soup was never validated
+        meta.best_valid_accuracy = None;
+        meta.save(output)?;
+    }
+    Ok(soup)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{artifact::ModelKind, ModelConfig};
+    use burn::{
+        backend::{ndarray::NdArrayDevice, NdArray},
+        optim::AdamConfig,
+    };
+
+    // An artifact dir holding a fresh `hidden_size` model, and its parameters as saved
+    fn saved_run(name: &str, hidden_size: usize, device: &NdArrayDevice) -> (String, Vec<NamedParam>) {
+        let dir = std::env::temp_dir().join(format!("my_first_rust_DL_app-soup-{name}"));
+        std::fs::create_dir_all(&dir).unwrap();
+        let dir = ArtifactDir::new(dir.to_str().unwrap());
+        let config = TrainingConfig::new(ModelConfig::new(10, hidden_size), AdamConfig::new());
+        config.save(dir.config_path()).unwrap();
+        let model = config.model.init::<NdArray>(device);
+        model.save_file(dir.model_path(ModelKind::Final), &CompactRecorder::new()).unwrap();
+        // Through the f16 of the file
+        let params = named_params(&load_model::<NdArray>(dir.as_str(), device).unwrap());
+        (dir.as_str().to_string(), params)
+    }
+
+    #[test]
+    fn soup_is_the_mean_of_the_weights() {
+        let device = NdArrayDevice::default();
+        let (a, a_params) = saved_run("a", 8, &device);
+        let (b, b_params) = saved_run("b", 8, &device);
+        let (wide, _) = saved_run("wide", 16, &device);
+        let output = std::env::temp_dir().join("my_first_rust_DL_app-soup-output");
+        let output = output.to_str().unwrap();
+
+        let same = named_params(&average_checkpoints::<NdArray>(&[&a, &a], output, &device).unwrap());
+        let mixed = named_params(&average_checkpoints::<NdArray>(&[&a, &b], output, &device).unwrap());
+        let reloaded = named_params(&load_model::<NdArray>(output, &device).unwrap());
+        let mismatch = average_checkpoints::<NdArray>(&[&a, &wide], output, &device);
+        let empty = average_checkpoints::<NdArray>(&[], output, &device);
+        for dir in [a.as_str(), &b, &wide, output] {
+            std::fs::remove_dir_all(dir).unwrap();
+        }
+
+        assert_eq!(same, a_params);
+        for ((mixed, a), b) in mixed.iter().zip(&a_params).zip(&b_params) {
+            assert_eq!(mixed.name, a.name);
+            let mean = a.values.iter().zip(&b.values).map(|(a, b)| (a + b) / 2.0);
+            assert!(mixed.values.iter().zip(mean).all(|(mixed, mean)| (mixed - mean).abs() < 1e-6), "{}", a.name);
+        }
+        // Saved like a trained model, in half precision
+        let close = reloaded.iter().zip(&mixed).all(|(reloaded, mixed)| {
+            reloaded.values.iter().zip(&mixed.values).all(|(reloaded, mixed)| (reloaded - mixed).abs() < 1e-3)
+        });
+        assert!(close);
+        match mismatch {
+            Err(SoupError::Load { artifact_dir, err: LoadError::Mismatch(_) }) => assert_eq!(artifact_dir, wide),
+            other => panic!("averaging different architectures gave {:?}", other.err()),
+        }
+        assert!(matches!(empty, Err(SoupError::Empty)));
+    }
+}
@@ -0,0 +1,473 @@
+use crate::data::ClassificationDataset;
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{fmt, fs, io, path::Path, sync::Arc};
+
+// How far the split fractions may sum from 1.0
+const FRACTION_EPSILON: f64 = 1e-6;
+
+// Written into the artifact dir by `train`, read back by `TrainingConfig::split_from`
+pub const SPLIT_FILE: &str = "split_indices.json";
+
+// Version of the `split_indices.json` layout
+pub const SPLIT_FORMAT_VERSION: u32 = 1;
+
+// Items a `DatasetFingerprint` hashes, evenly spaced over the dataset
+const FINGERPRINT_SAMPLES: usize = 64;
+
+// A subset of a classification dataset, selected by index. Clones share the parent dataset.
+pub struct SubsetDataset<D> {
+    dataset: Arc<D>,
+    indices: Vec<usize>,
+}
+
+impl<D> Clone for SubsetDataset<D> {
+    fn clone(&self) -> Self {
+        Self { dataset: self.dataset.clone(), indices: self.indices.clone() }
+    }
+}
+
+impl<D> SubsetDataset<D> {
+    pub fn new(dataset: Arc<D>, indices: Vec<usize>) -> Self {
+        Self { dataset, indices }
+    }
+
+    // Indices of the selected items in the parent dataset, in the order the subset serves them
+    // (ascending for the splits of `stratified_split`)
+    pub fn indices(&self) -> &[usize] {
+        &self.indices
+    }
+}
+
+impl<D: ClassificationDataset> ClassificationDataset for SubsetDataset<D> {
+    fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    fn get(&self, index: usize) -> Option<(Vec<f32>, usize)> {
+        self.dataset.get(*self.indices.get(index)?)
+    }
+
+    fn num_classes(&self) -> usize {
+        self.dataset.num_classes()
+    }
+
+    fn image_shape(&self) -> [usize; 2] {
+        self.dataset.image_shape()
+    }
+}
+
+// The items of a dataset whose label is one of `classes`, relabeled by their position in that
+// list: with `classes = [3, 7]`, 3s become label 0 and 7s label 1
+pub struct ClassSubset<D> {
+    subset: SubsetDataset<D>,
+    classes: Vec<usize>,
+}
+
+impl<D: ClassificationDataset> ClassSubset<D> {
+    // Reads every item once to find the selected ones; unreadable items are left out
+    pub fn new(dataset: D, classes: &[usize]) -> Self {
+        let indices = (0..dataset.len())
+            .filter(|&index| dataset.get(index).is_some_and(|(_, label)| classes.contains(&label)))
+            .collect();
+        Self { subset: SubsetDataset::new(Arc::new(dataset), indices), classes: classes.to_vec() }
+    }
+
+    // Indices of the selected items in the parent dataset, ascending
+    pub fn indices(&self) -> &[usize] {
+        self.subset.indices()
+    }
+}
+
+impl<D: ClassificationDataset> ClassificationDataset for ClassSubset<D> {
+    fn len(&self) -> usize {
+        self.subset.len()
+    }
+
+    fn get(&self, index: usize) -> Option<(Vec<f32>, usize)> {
+        let (pixels, label) = self.subset.get(index)?;
+        let label = self.classes.iter().position(|&class| class == label)?;
+        Some((pixels, label))
+    }
+
+    fn num_classes(&self) -> usize {
+        self.classes.len()
+    }
+
+    fn image_shape(&self) -> [usize; 2] {
+        self.subset.image_shape()
+    }
+}
+
+// A dataset relabeled for one-vs-rest training: items of `positive` become label 1, every other
+// item label 0
+pub struct OneVsRest<D> {
+    dataset: D,
+    positive: usize,
+}
+
+impl<D> OneVsRest<D> {
+    pub fn new(dataset: D, positive: usize) -> Self {
+        Self { dataset, positive }
+    }
+}
+
+impl<D: ClassificationDataset> ClassificationDataset for OneVsRest<D> {
+    fn len(&self) -> usize {
+        self.dataset.len()
+    }
+
+    fn get(&self, index: usize) -> Option<(Vec<f32>, usize)> {
+        let (pixels, label) = self.dataset.get(index)?;
+        Some((pixels, (label == self.positive) as usize))
+    }
+
+    fn num_classes(&self) -> usize {
+        2
+    }
+
+    fn image_shape(&self) -> [usize; 2] {
+        self.dataset.image_shape()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SplitError {
+    // No fractions, a negative or non-finite one, or a sum other than 1.0
+    InvalidFractions(Vec<f64>),
+}
+
+impl fmt::Display for SplitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SplitError::InvalidFractions(fractions) => write!(
+                f,
+                "split fractions {fractions:?} are invalid, expected non-negative values summing to 1.0"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SplitError {}
+
+// Number of items of a class of `count` items each split gets. Every split gets its rounded
+// down share, and the rounding leftovers go to the largest split. A class that has items always
+// keeps at least one in the first (training) split, taken from the largest split that has some.
+fn allocate(count: usize, fractions: &[f64]) -> Vec<usize> {
+    let mut counts: Vec<usize> = fractions
+        .iter()
+        .map(|fraction| (count as f64 * fraction).floor() as usize)
+        .collect();
+
+    let largest = (0..fractions.len())
+        .max_by(|&a, &b| fractions[a].total_cmp(&fractions[b]).then(b.cmp(&a)))
+        .expect("There is at least one fraction");
+    // Fractions summing slightly above 1.0 can over-allocate by a few items
+    let allocated: usize = counts.iter().sum();
+    counts[largest] = (counts[largest] + count).saturating_sub(allocated);
+
+    if count > 0 && counts[0] == 0 {
+        let donor = (1..counts.len())
+            .filter(|&split| counts[split] > 0)
+            .max_by(|&a, &b| counts[a].cmp(&counts[b]).then(b.cmp(&a)))
+            .expect("The class items are allocated somewhere");
+        counts[donor] -= 1;
+        counts[0] += 1;
+    }
+    counts
+}
+
+// Splits `dataset` into one subset per fraction, with (near) identical class ratios in each.
+//
+// Indices are grouped by label, shuffled within each group with `seed`, and handed out to the
+// splits in order according to `allocate`. The same dataset, fractions and seed always give the
+// same splits. The first split is treated as the training split.
+pub fn stratified_split<D: ClassificationDataset>(
+    dataset: D,
+    fractions: &[f64],
+    seed: u64,
+) -> Result<Vec<SubsetDataset<D>>, SplitError> {
+    let valid = !fractions.is_empty()
+        && fractions.iter().all(|fraction| fraction.is_finite() && *fraction >= 0.0)
+        && (fractions.iter().sum::<f64>() - 1.0).abs() <= FRACTION_EPSILON;
+    if !valid {
+        return Err(SplitError::InvalidFractions(fractions.to_vec()));
+    }
+
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    for index in 0..dataset.len() {
+        // Unreadable items are left out of every split
+        let Some((_, label)) = dataset.get(index) else {
+            continue;
+        };
+        if groups.len() <= label {
+            groups.resize(label + 1, Vec::new());
+        }
+        groups[label].push(index);
+    }
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut splits = vec![Vec::new(); fractions.len()];
+    for mut group in groups {
+        group.shuffle(&mut rng);
+
+        let mut rest = group.as_slice();
+        for (split, count) in splits.iter_mut().zip(allocate(group.len(), fractions)) {
+            let (taken, remaining) = rest.split_at(count);
+            split.extend_from_slice(taken);
+            rest = remaining;
+        }
+    }
+
+    let dataset = Arc::new(dataset);
+    Ok(splits
+        .into_iter()
+        .map(|mut indices| {
+            indices.sort_unstable();
+            SubsetDataset::new(dataset.clone(), indices)
+        })
+        .collect())
+}
+
+/// What a [`SplitIndices`] file checks a dataset against before indexing it: its length and
+/// the SHA-256 of the pixels and labels of 64 items evenly spaced over it.
+/// Cheap enough to compute on every run, and the same as long as the dataset serves the same
+/// items in the same order.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DatasetFingerprint {
+    pub len: usize,
+    // Hex digest
+    pub hash: String,
+}
+
+impl DatasetFingerprint {
+    pub fn of<D: ClassificationDataset + ?Sized>(dataset: &D) -> Self {
+        let len = dataset.len();
+        let mut hasher = Sha256::new();
+        for sample in 0..FINGERPRINT_SAMPLES.min(len) {
+            let index = sample * len / FINGERPRINT_SAMPLES.min(len);
+            hasher.update((index as u64).to_le_bytes());
+            // Unreadable items hash as their index alone
+            if let Some((pixels, label)) = dataset.get(index) {
+                hasher.update((label as u64).to_le_bytes());
+                for pixel in pixels {
+                    hasher.update(pixel.to_le_bytes());
+                }
+            }
+        }
+        Self { len, hash: format!("{:x}", hasher.finalize()) }
+    }
+}
+
+impl fmt::Display for DatasetFingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} items, hash {}", self.len, self.hash)
+    }
+}
+
+#[derive(Debug)]
+pub enum SplitFileError {
+    Io(io::Error),
+    // Not a split file, or one of a newer layout
+    Parse(String),
+    // The dataset differs from the one the split was saved from
+    Fingerprint { split: &'static str, saved: DatasetFingerprint, found: DatasetFingerprint },
+    // An index past the end of the dataset it selects from
+    OutOfRange { split: &'static str, index: usize, len: usize },
+}
+
+impl fmt::Display for SplitFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SplitFileError::Io(err) => write!(f, "could not read or write the split indices: {err}"),
+            SplitFileError::Parse(err) => write!(f, "invalid split indices: {err}"),
+            SplitFileError::Fingerprint { split, saved, found } => write!(
+                f,
+                "the {split} dataset does not match the split indices: saved from {saved}, found {found}"
+            ),
+            SplitFileError::OutOfRange { split, index, len } => {
+                write!(f, "the {split} split indices have {index}, past the {len} items of the dataset")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SplitFileError {}
+
+impl From<io::Error> for SplitFileError {
+    fn from(err: io::Error) -> Self {
+        SplitFileError::Io(err)
+    }
+}
+
+/// A training/validation split as `split_indices.json`: the indices of either split in the
+/// dataset they select from, and the fingerprint of that dataset. `train` writes the split of
+/// every run, the training indices in the training source (all of them unless
+/// `train_subset` picks some) and the validation ones in the validation source, and
+/// `TrainingConfig::split_from` trains on a saved one again. Splits of a single dataset (see
+/// [`SplitIndices::of_subsets`]) have no `valid_fingerprint`, both splits indexing the same
+/// dataset.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SplitIndices {
+    pub format_version: u32,
+    pub train: Vec<usize>,
+    pub valid: Vec<usize>,
+    pub fingerprint: DatasetFingerprint,
+    #[serde(default)]
+    pub valid_fingerprint: Option<DatasetFingerprint>,
+}
+
+impl SplitIndices {
+    pub fn new(
+        train: Vec<usize>,
+        valid: Vec<usize>,
+        fingerprint: DatasetFingerprint,
+        valid_fingerprint: Option<DatasetFingerprint>,
+    ) -> Self {
+        Self { format_version: SPLIT_FORMAT_VERSION, train, valid, fingerprint, valid_fingerprint }
+    }
+
+    /// The split of two subsets (such as the first two of [`stratified_split`]), each of
+    /// whose parent is fingerprinted, the validation one only when it is not the training one's.
+    pub fn of_subsets<D: ClassificationDataset>(train: &SubsetDataset<D>, valid: &SubsetDataset<D>) -> Self {
+        let valid_fingerprint =
+            (!Arc::ptr_eq(&train.dataset, &valid.dataset)).then(|| DatasetFingerprint::of(valid.dataset.as_ref()));
+        Self::new(
+            train.indices.clone(),
+            valid.indices.clone(),
+            DatasetFingerprint::of(train.dataset.as_ref()),
+            valid_fingerprint,
+        )
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let json = serde_json::to_string(self).expect("Split indices should serialize to JSON");
+        fs::write(path, json)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SplitFileError> {
+        let path = path.as_ref();
+        let json = fs::read_to_string(path)?;
+        let split: Self =
+            serde_json::from_str(&json).map_err(|err| SplitFileError::Parse(format!("{}: {err}", path.display())))?;
+        if split.format_version > SPLIT_FORMAT_VERSION {
+            return Err(SplitFileError::Parse(format!(
+                "{}: format version {}, this build reads up to {SPLIT_FORMAT_VERSION}",
+                path.display(),
+                split.format_version
+            )));
+        }
+        Ok(split)
+    }
+
+    /// Errors unless `train` (and `valid`, for a split with a `valid_fingerprint`) is the
+    /// dataset the split was saved from, and every index is in range.
+    pub fn check<D: ClassificationDataset + ?Sized>(&self, train: &D, valid: &D) -> Result<(), SplitFileError> {
+        let mut checks = vec![("training", &self.fingerprint, train)];
+        checks.extend(self.valid_fingerprint.as_ref().map(|saved| ("validation", saved, valid)));
+        for (split, saved, dataset) in checks {
+            let found = DatasetFingerprint::of(dataset);
+            if &found != saved {
+                return Err(SplitFileError::Fingerprint { split, saved: saved.clone(), found });
+            }
+        }
+        let valid_len = match self.valid_fingerprint {
+            Some(_) => valid.len(),
+            None => train.len(),
+        };
+        for (split, indices, len) in [("training", &self.train, train.len()), ("validation", &self.valid, valid_len)] {
+            if let Some(&index) = indices.iter().find(|&&index| index >= len) {
+                return Err(SplitFileError::OutOfRange { split, index, len });
+            }
+        }
+        Ok(())
+    }
+
+    /// Both splits of `dataset`, for a split of a single dataset such as those of
+    /// [`SplitIndices::of_subsets`] on [`stratified_split`], after checking it.
+    pub fn subsets<D: ClassificationDataset>(&self, dataset: D) -> Result<[SubsetDataset<D>; 2], SplitFileError> {
+        self.check(&dataset, &dataset)?;
+        let dataset = Arc::new(dataset);
+        Ok([SubsetDataset::new(dataset.clone(), self.train.clone()), SubsetDataset::new(dataset, self.valid.clone())])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::synthetic::SyntheticDigits;
+
+    // One-pixel images labeled as given, the pixel being the index
+    struct Labels(Vec<usize>);
+
+    impl ClassificationDataset for Labels {
+        fn len(&self) -> usize {
+            self.0.len()
+        }
+
+        fn get(&self, index: usize) -> Option<(Vec<f32>, usize)> {
+            Some((vec![index as f32], *self.0.get(index)?))
+        }
+
+        fn num_classes(&self) -> usize {
+            3
+        }
+
+        fn image_shape(&self) -> [usize; 2] {
+            [1, 1]
+        }
+    }
+
+    // 10 items of class 0, 5 of class 1 and a single one of class 2, interleaved
+    fn labels() -> Labels {
+        let mut labels = [vec![0; 10], vec![1; 5], vec![2]].concat();
+        labels.shuffle(&mut StdRng::seed_from_u64(0));
+        Labels(labels)
+    }
+
+    fn class_counts(split: &SubsetDataset<Labels>) -> Vec<usize> {
+        (0..3).map(|class| (0..split.len()).filter(|&index| split.get(index).unwrap().1 == class).count()).collect()
+    }
+
+    #[test]
+    fn splits_keep_the_class_ratios_and_the_single_sample_class() {
+        let splits = stratified_split(labels(), &[0.6, 0.2, 0.2], 7).unwrap();
+
+        let counts: Vec<Vec<usize>> = splits.iter().map(class_counts).collect();
+        assert_eq!(counts, [vec![6, 3, 1], vec![2, 1, 0], vec![2, 1, 0]]);
+        let mut indices: Vec<usize> = splits.iter().flat_map(|split| split.indices().to_vec()).collect();
+        indices.sort();
+        assert_eq!(indices, (0..16).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn splits_are_the_same_for_the_same_seed() {
+        let indices = |seed| -> Vec<Vec<usize>> {
+            let splits = stratified_split(labels(), &[0.6, 0.2, 0.2], seed).unwrap();
+            splits.iter().map(|split| split.indices().to_vec()).collect()
+        };
+
+        assert_eq!(indices(7), indices(7));
+        assert_ne!(indices(7), indices(8));
+        assert_eq!(
+            stratified_split(labels(), &[0.6, 0.3], 7).err(),
+            Some(SplitError::InvalidFractions(vec![0.6, 0.3]))
+        );
+    }
+
+    #[test]
+    fn class_subset_relabels_the_selected_classes_contiguously() {
+        let digits = SyntheticDigits::new(30, 1);
+
+        let subset = ClassSubset::new(digits.clone(), &[0, 1]);
+        assert_eq!((subset.len(), subset.num_classes()), (6, 2));
+        assert_eq!(subset.indices(), [0, 1, 10, 11, 20, 21]);
+        let labels: Vec<usize> = (0..subset.len()).map(|index| subset.get(index).unwrap().1).collect();
+        assert_eq!(labels, [0, 1, 0, 1, 0, 1]);
+        // Labels follow the order of the list, not the original digits
+        let swapped = ClassSubset::new(digits, &[7, 3]);
+        assert_eq!(swapped.get(0).map(|(_, label)| label), Some(1));
+        assert_eq!(swapped.indices()[..2], [3, 7]);
+    }
+}
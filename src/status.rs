@@ -0,0 +1,278 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+// How often the server thread wakes up to apply updates and look for connections
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+// A client that does not send its request (or read the response) within this is dropped
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(1);
+// Longest request head read, far more than `GET /status` needs
+const MAX_REQUEST_BYTES: usize = 8192;
+
+/// What `GET /status` of the [`StatusServer`] returns, as JSON: where training is and the
+/// latest metric values. Metrics are `null` until first logged.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct TrainingStatus {
+    // 1-based, 0 before the first step
+    pub epoch: usize,
+    pub num_epochs: usize,
+    // Training steps done, counting from 1 across epochs
+    pub step: usize,
+    // Training items of the current epoch done, out of `items_total`
+    pub items_processed: usize,
+    pub items_total: usize,
+    // Of the latest step, the loss smoothed as on the dashboard
+    pub train_loss: Option<f64>,
+    pub train_accuracy: Option<f64>,
+    // Means of the validation of the latest finished epoch
+    pub valid_loss: Option<f64>,
+    pub valid_accuracy: Option<f64>,
+    // Of the latest step when the run logs it (`lr_schedule`, `lr_multipliers`), the config's
+    // otherwise
+    pub learning_rate: Option<f64>,
+    // Training items per second over the current epoch
+    pub items_per_second: Option<f64>,
+    pub elapsed_seconds: f64,
+    // From the share of the epochs done so far, validation included
+    pub eta_seconds: Option<f64>,
+}
+
+// What the progress renderer pushes to the server, from the learner's metric updates
+#[derive(Debug, Clone)]
+pub(crate) enum StatusUpdate {
+    // A training step is done
+    Step { epoch: usize, num_epochs: usize, items_processed: usize, items_total: usize },
+    // A metric value of the latest training step
+    Train { name: String, value: f64 },
+    // The validation means of the epoch that just finished
+    Valid(BTreeMap<String, f64>),
+}
+
+pub(crate) type StatusSender = Sender<StatusUpdate>;
+
+// The status as updates come in, with the times it is derived from
+struct StatusState {
+    status: TrainingStatus,
+    start: Instant,
+    epoch_start: Instant,
+}
+
+impl StatusState {
+    fn apply(&mut self, update: StatusUpdate) {
+        let status = &mut self.status;
+        match update {
+            StatusUpdate::Step { epoch, num_epochs, items_processed, items_total } => {
+                if epoch != status.epoch {
+                    self.epoch_start = Instant::now();
+                }
+                status.epoch = epoch;
+                status.num_epochs = num_epochs;
+                status.step += 1;
+                status.items_processed = items_processed;
+                status.items_total = items_total;
+            }
+            StatusUpdate::Train { name, value } => match name.as_str() {
+                "Loss" => status.train_loss = Some(value),
+                "Accuracy" => status.train_accuracy = Some(value),
+                "Learning Rate" => status.learning_rate = Some(value),
+                _ => {}
+            },
+            StatusUpdate::Valid(means) => {
+                status.valid_loss = means.get("Loss").copied();
+                status.valid_accuracy = means.get("Accuracy").copied();
+            }
+        }
+    }
+
+    // The status as of now
+    fn snapshot(&self) -> TrainingStatus {
+        let mut status = self.status.clone();
+        status.elapsed_seconds = self.start.elapsed().as_secs_f64();
+        let epoch_seconds = self.epoch_start.elapsed().as_secs_f64();
+        status.items_per_second =
+            (status.items_processed > 0 && epoch_seconds > 0.0).then(|| status.items_processed as f64 / epoch_seconds);
+        if status.epoch > 0 && status.num_epochs > 0 && status.items_total > 0 {
+            let epoch_share = status.items_processed as f64 / status.items_total as f64;
+            let done = ((status.epoch - 1) as f64 + epoch_share) / status.num_epochs as f64;
+            status.eta_seconds = (done > 0.0).then(|| status.elapsed_seconds * (1.0 - done) / done);
+        }
+        status
+    }
+}
+
+/// A minimal HTTP server answering `GET /status` with the [`TrainingStatus`] of the run, for
+/// polling a remote training. It runs on a thread of its own that applies the updates training
+/// pushes through a channel, so training never waits on it, and stops when dropped.
+pub struct StatusServer {
+    address: SocketAddr,
+    sender: StatusSender,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl StatusServer {
+    /// Listens on `port` of every interface, any free port for 0. `learning_rate` is reported
+    /// until the run logs one.
+    pub fn start(port: u16, learning_rate: f64) -> io::Result<Self> {
+        let listener = TcpListener::bind(("0.0.0.0", port))?;
+        listener.set_nonblocking(true)?;
+        let address = listener.local_addr()?;
+        let (sender, receiver) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let state = StatusState {
+            status: TrainingStatus { learning_rate: Some(learning_rate), ..TrainingStatus::default() },
+            start: Instant::now(),
+            epoch_start: Instant::now(),
+        };
+        let thread = {
+            let stop = stop.clone();
+            thread::spawn(move || serve(listener, receiver, state, &stop))
+        };
+        Ok(Self { address, sender, stop, thread: Some(thread) })
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    pub(crate) fn sender(&self) -> StatusSender {
+        self.sender.clone()
+    }
+}
+
+impl Drop for StatusServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+    }
+}
+
+fn serve(listener: TcpListener, receiver: Receiver<StatusUpdate>, mut state: StatusState, stop: &AtomicBool) {
+    while !stop.load(Ordering::Relaxed) {
+        while let Ok(update) = receiver.try_recv() {
+            state.apply(update);
+        }
+        match listener.accept() {
+            // A client that misbehaves only loses its own response
+            Ok((stream, _)) => {
+                respond(stream, &state).ok();
+            }
+            Err(_) => thread::sleep(POLL_INTERVAL),
+        }
+    }
+}
+
+fn respond(mut stream: TcpStream, state: &StatusState) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+
+    // Only the request line matters, the head is read up to its end so the client sees a
+    // complete exchange
+    let mut request = Vec::new();
+    let mut buffer = [0; 1024];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") && request.len() < MAX_REQUEST_BYTES {
+        let read = stream.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        request.extend_from_slice(&buffer[..read]);
+    }
+    let request = String::from_utf8_lossy(&request);
+    let mut request_line = request.lines().next().unwrap_or_default().split_whitespace();
+    let (method, path) = (request_line.next().unwrap_or_default(), request_line.next().unwrap_or_default());
+
+    let (status, content_type, body) = match (method, path.split('?').next().unwrap_or_default()) {
+        ("GET", "/status") => (
+            "200 OK",
+            "application/json",
+            serde_json::to_string(&state.snapshot()).expect("Training status should serialize to JSON"),
+        ),
+        ("GET", _) => ("404 Not Found", "text/plain", "not found, see /status\n".to_string()),
+        _ => ("405 Method Not Allowed", "text/plain", "only GET is supported\n".to_string()),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The status line and body of `request` sent to the server at `address`
+    fn exchange(address: SocketAddr, request: &str) -> (String, String) {
+        let mut stream = TcpStream::connect(("127.0.0.1", address.port())).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        (head.lines().next().unwrap().to_string(), body.to_string())
+    }
+
+    fn status(address: SocketAddr) -> TrainingStatus {
+        let (status_line, body) = exchange(address, "GET /status HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert_eq!(status_line, "HTTP/1.1 200 OK");
+        serde_json::from_str(&body).unwrap()
+    }
+
+    #[test]
+    fn status_follows_the_updates_training_pushes() {
+        let server = StatusServer::start(0, 0.01).unwrap();
+        let address = server.address();
+        let initial = status(address);
+        assert_eq!((initial.epoch, initial.step, initial.learning_rate), (0, 0, Some(0.01)));
+        assert_eq!((initial.train_loss, initial.eta_seconds), (None, None));
+
+        let sender = server.sender();
+        sender.send(StatusUpdate::Step { epoch: 1, num_epochs: 2, items_processed: 16, items_total: 32 }).unwrap();
+        sender.send(StatusUpdate::Train { name: "Loss".to_string(), value: 0.5 }).unwrap();
+        sender.send(StatusUpdate::Train { name: "Learning Rate".to_string(), value: 0.02 }).unwrap();
+        sender.send(StatusUpdate::Train { name: "Other".to_string(), value: 7.0 }).unwrap();
+        let means = BTreeMap::from([("Loss".to_string(), 0.4), ("Accuracy".to_string(), 0.9)]);
+        sender.send(StatusUpdate::Valid(means)).unwrap();
+        // The server applies the updates between connections
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut current = status(address);
+        while current.valid_accuracy.is_none() && Instant::now() < deadline {
+            thread::sleep(POLL_INTERVAL);
+            current = status(address);
+        }
+
+        assert_eq!((current.epoch, current.num_epochs, current.step), (1, 2, 1));
+        assert_eq!((current.items_processed, current.items_total), (16, 32));
+        assert_eq!((current.train_loss, current.train_accuracy), (Some(0.5), None));
+        assert_eq!((current.valid_loss, current.valid_accuracy), (Some(0.4), Some(0.9)));
+        assert_eq!(current.learning_rate, Some(0.02));
+        // A quarter of the run is done
+        let eta = current.eta_seconds.unwrap();
+        assert!((eta - 3.0 * current.elapsed_seconds).abs() < 1e-6, "eta {eta} after {}", current.elapsed_seconds);
+    }
+
+    #[test]
+    fn other_paths_and_methods_are_refused() {
+        let server = StatusServer::start(0, 0.01).unwrap();
+        let (not_found, _) = exchange(server.address(), "GET /metrics HTTP/1.1\r\n\r\n");
+        assert_eq!(not_found, "HTTP/1.1 404 Not Found");
+        let (not_allowed, body) = exchange(server.address(), "POST /status HTTP/1.1\r\n\r\n");
+        assert_eq!(not_allowed, "HTTP/1.1 405 Method Not Allowed");
+        assert_eq!(body, "only GET is supported\n");
+        assert_eq!(status(server.address()).step, 0);
+    }
+}
@@ -0,0 +1,156 @@
+use crate::{data::MnistBatch, model::Model, profile, swa::score};
+use burn::{
+    data::dataloader::DataLoader,
+    module::AutodiffModule,
+    optim::{GradientsParams, Optimizer},
+    tensor::backend::AutodiffBackend,
+    LearningRate,
+};
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+};
+
+// Indices of the `size` validation items (out of `len`) the mid-epoch validations score, drawn
+// from `seed` and ascending, so that every evaluation of a run sees the same items
+pub fn valid_subset(len: usize, size: usize, seed: u64) -> Vec<usize> {
+    let mut indices: Vec<usize> = (0..len).collect();
+    indices.shuffle(&mut StdRng::seed_from_u64(seed));
+    indices.truncate(size);
+    indices.sort_unstable();
+    indices
+}
+
+// The mid-epoch validations of a run: every `every` training steps, except at epoch ends where
+// the learner validates on the whole split anyway. Scores are appended to `valid_steps.csv` as
+// `step,epoch,split,accuracy,loss`, `split` being `valid_subset` for a subset and `valid` for
+// the whole split, so they never mix with each other nor with the epoch-end history.
+pub struct StepValidation<B: AutodiffBackend> {
+    every: usize,
+    steps_per_epoch: usize,
+    split: &'static str,
+    dataloader: Box<dyn DataLoader<MnistBatch<B::InnerBackend>>>,
+    log: File,
+    step: usize,
+}
+
+impl<B: AutodiffBackend> StepValidation<B> {
+    pub fn new(
+        artifact_dir: &str,
+        every: usize,
+        steps_per_epoch: usize,
+        subset: bool,
+        dataloader: Box<dyn DataLoader<MnistBatch<B::InnerBackend>>>,
+    ) -> io::Result<Self> {
+        let mut log = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(format!("{artifact_dir}/valid_steps.csv"))?;
+        writeln!(log, "step,epoch,split,accuracy,loss")?;
+        let split = if subset { "valid_subset" } else { "valid" };
+        Ok(Self { every, steps_per_epoch, split, dataloader, log, step: 0 })
+    }
+
+    // Counts a training step, and validates `model` if it is due
+    fn after_step(&mut self, model: &Model<B>) {
+        self.step += 1;
+        if !self.step.is_multiple_of(self.every) || self.step.is_multiple_of(self.steps_per_epoch) {
+            return;
+        }
+        let scores = profile::step_span("validation", || score(&model.valid(), self.dataloader.as_ref()));
+        let epoch = self.step / self.steps_per_epoch + 1;
+        // Like the learner logs, a failed write does not stop training
+        writeln!(self.log, "{},{epoch},{},{},{}", self.step, self.split, scores.accuracy, scores.loss).ok();
+    }
+}
+
+// Runs the mid-epoch validations of `validation`, if any, after every step of the wrapped
+// optimizer: the only point the learner hands out the model between two steps.
+pub struct StepValidatedOptimizer<O, B: AutodiffBackend> {
+    inner: O,
+    validation: Option<StepValidation<B>>,
+}
+
+impl<O, B: AutodiffBackend> StepValidatedOptimizer<O, B> {
+    pub fn new(inner: O, validation: Option<StepValidation<B>>) -> Self {
+        Self { inner, validation }
+    }
+}
+
+impl<O, B> Optimizer<Model<B>, B> for StepValidatedOptimizer<O, B>
+where
+    B: AutodiffBackend,
+    O: Optimizer<Model<B>, B>,
+{
+    type Record = O::Record;
+
+    fn step(&mut self, lr: LearningRate, module: Model<B>, grads: GradientsParams) -> Model<B> {
+        let module = self.inner.step(lr, module, grads);
+        if let Some(validation) = &mut self.validation {
+            validation.after_step(&module);
+        }
+        module
+    }
+
+    fn to_record(&self) -> Self::Record {
+        self.inner.to_record()
+    }
+
+    fn load_record(self, record: Self::Record) -> Self {
+        Self { inner: self.inner.load_record(record), validation: self.validation }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        data::{boxed_mnist_dataloader, MnistBatcher},
+        split::SubsetDataset,
+        synthetic::SyntheticDigits,
+        ModelConfig,
+    };
+    use burn::backend::{ndarray::NdArrayDevice, Autodiff, NdArray};
+    use std::sync::Arc;
+
+    #[test]
+    fn mid_epoch_validations_score_the_same_subset() {
+        let subset = valid_subset(100, 32, 7);
+        assert_eq!(subset, valid_subset(100, 32, 7));
+        assert_eq!(subset.len(), 32);
+        assert!(subset.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_ne!(subset, valid_subset(100, 32, 8));
+
+        let dir = std::env::temp_dir().join("my_first_rust_DL_app-step-valid");
+        std::fs::create_dir_all(&dir).unwrap();
+        let device = NdArrayDevice::default();
+        let valid_set = SubsetDataset::new(Arc::new(SyntheticDigits::new(100, 2)), subset);
+        let dataloader = boxed_mnist_dataloader(MnistBatcher::<NdArray>::new(device), valid_set, 16, 0, 1, 0);
+        let mut validation =
+            StepValidation::<Autodiff<NdArray>>::new(dir.to_str().unwrap(), 2, 10, true, dataloader).unwrap();
+
+        // The weights do not change between steps, so the same items give the same scores, up to
+        // the order the shuffled batches sum them in
+        let model = ModelConfig::new(10, 8).init::<Autodiff<NdArray>>(&device);
+        for _ in 0..12 {
+            validation.after_step(&model);
+        }
+        drop(validation);
+        let log = std::fs::read_to_string(dir.join("valid_steps.csv")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let rows: Vec<Vec<&str>> = log.lines().skip(1).map(|line| line.split(',').collect()).collect();
+        // Step 10 ends the first epoch, which the learner validates in full
+        let steps: Vec<&str> = rows.iter().map(|row| row[0]).collect();
+        assert_eq!(steps, ["2", "4", "6", "8", "12"]);
+        assert_eq!(rows[4][1], "2");
+        assert!(rows.iter().all(|row| row[2] == "valid_subset"));
+        let scores = |row: &[&str]| [row[3].parse::<f64>().unwrap(), row[4].parse::<f64>().unwrap()];
+        for row in &rows {
+            let (score, first) = (scores(row), scores(&rows[0]));
+            assert!((score[0] - first[0]).abs() < 1e-9 && (score[1] - first[1]).abs() < 1e-5, "{log}");
+        }
+    }
+}
@@ -0,0 +1,640 @@
+use crate::artifact::{ArtifactDir, LOCK_FILE};
+use burn::{
+    record::{FileRecorder, Record, Recorder, RecorderError},
+    tensor::backend::Backend,
+};
+use futures::TryStreamExt;
+use object_store::{aws::AmazonS3Builder, path::Path as ObjectPath, prefix::PrefixStore, ObjectStore, ObjectStoreExt};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    collections::BTreeSet,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+use tokio::runtime::{Builder, Runtime};
+
+// Scheme of the object store URLs `open` reads as S3 buckets, `s3://bucket/prefix`
+const S3_SCHEME: &str = "s3://";
+
+// Directory of the artifact dir holding the learner checkpoints, which inference does without
+const CHECKPOINT_DIR: &str = "checkpoint";
+
+#[derive(Debug)]
+pub enum StoreError {
+    // The store URL names no bucket, or a key is not a valid object path
+    Config(String),
+    Io(io::Error),
+    // The object store could not be reached, or failed a request
+    ObjectStore(object_store::Error),
+    // `get` of a key the store does not have
+    NotFound(String),
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::Config(message) => write!(f, "invalid checkpoint store: {message}"),
+            StoreError::Io(err) => write!(f, "checkpoint store I/O error: {err}"),
+            StoreError::ObjectStore(err) => write!(f, "checkpoint store request failed: {err}"),
+            StoreError::NotFound(key) => write!(f, "{key:?} is not in the checkpoint store"),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+impl From<io::Error> for StoreError {
+    fn from(err: io::Error) -> Self {
+        StoreError::Io(err)
+    }
+}
+
+/// Where the files of a training run are kept, by key: the path of the file in the artifact
+/// dir, `/`-separated, such as `config.json` or `checkpoint/model-3.mpk`. A backend holds the
+/// files of one run, under its own root or prefix.
+pub trait CheckpointBackend: Send + Sync {
+    fn put(&self, key: &str, bytes: &[u8]) -> Result<(), StoreError>;
+
+    fn get(&self, key: &str) -> Result<Vec<u8>, StoreError>;
+
+    /// Every key of the run, in no particular order.
+    fn list(&self) -> Result<Vec<String>, StoreError>;
+
+    /// Removes the key, which the store may not have.
+    fn delete(&self, key: &str) -> Result<(), StoreError>;
+}
+
+/// The default backend: the files in a directory of the local filesystem.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocalBackend {
+    root: PathBuf,
+}
+
+impl LocalBackend {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+}
+
+impl CheckpointBackend for LocalBackend {
+    fn put(&self, key: &str, bytes: &[u8]) -> Result<(), StoreError> {
+        let path = self.root.join(key);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        Ok(fs::write(path, bytes)?)
+    }
+
+    fn get(&self, key: &str) -> Result<Vec<u8>, StoreError> {
+        match fs::read(self.root.join(key)) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Err(StoreError::NotFound(key.to_string())),
+            read => Ok(read?),
+        }
+    }
+
+    fn list(&self) -> Result<Vec<String>, StoreError> {
+        if !self.root.is_dir() {
+            return Ok(Vec::new());
+        }
+        Ok(relative_files(&self.root)?.into_iter().map(|(key, _)| key).collect())
+    }
+
+    fn delete(&self, key: &str) -> Result<(), StoreError> {
+        match fs::remove_file(self.root.join(key)) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            removed => Ok(removed?),
+        }
+    }
+}
+
+/// A store of the `object_store` crate, such as the S3-compatible ones (AWS S3, MinIO, Ceph,
+/// R2...) [`open`] builds: the files of the run are the objects under `prefix`.
+#[derive(Debug)]
+pub struct ObjectStoreBackend {
+    store: Arc<dyn ObjectStore>,
+    // The requests of the store are async: every call of the backend runs its own to completion
+    runtime: Runtime,
+}
+
+impl ObjectStoreBackend {
+    /// The objects of `store` under `prefix`, `/`-separated, empty for the whole store.
+    pub fn new(store: impl ObjectStore, prefix: &str) -> Result<Self, StoreError> {
+        let prefix = object_path(prefix.trim_matches('/'))?;
+        let store: Arc<dyn ObjectStore> = match prefix.as_ref().is_empty() {
+            true => Arc::new(store),
+            false => Arc::new(PrefixStore::new(store, prefix)),
+        };
+        let runtime = Builder::new_current_thread().enable_all().build()?;
+        Ok(Self { store, runtime })
+    }
+
+    /// The backend of `bucket` and `prefix`, with the settings of the `AWS_*` environment
+    /// variables `object_store` reads: `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY`, the optional
+    /// `AWS_SESSION_TOKEN` and `AWS_REGION`, and for a store other than AWS `AWS_ENDPOINT_URL`,
+    /// such as `http://localhost:9000` (with `AWS_ALLOW_HTTP=true`).
+    pub fn s3_from_env(bucket: &str, prefix: &str) -> Result<Self, StoreError> {
+        let store = AmazonS3Builder::from_env().with_bucket_name(bucket).build().map_err(StoreError::ObjectStore)?;
+        Self::new(store, prefix)
+    }
+
+    // The error of a request on `key`
+    fn error(key: &str, err: object_store::Error) -> StoreError {
+        match err {
+            object_store::Error::NotFound { .. } => StoreError::NotFound(key.to_string()),
+            err => StoreError::ObjectStore(err),
+        }
+    }
+}
+
+impl CheckpointBackend for ObjectStoreBackend {
+    fn put(&self, key: &str, bytes: &[u8]) -> Result<(), StoreError> {
+        let path = object_path(key)?;
+        let put = self.store.put(&path, bytes.to_vec().into());
+        self.runtime.block_on(put).map(|_| ()).map_err(|err| Self::error(key, err))
+    }
+
+    fn get(&self, key: &str) -> Result<Vec<u8>, StoreError> {
+        let path = object_path(key)?;
+        let bytes = self.runtime.block_on(async { self.store.get(&path).await?.bytes().await });
+        bytes.map(|bytes| bytes.to_vec()).map_err(|err| Self::error(key, err))
+    }
+
+    fn list(&self) -> Result<Vec<String>, StoreError> {
+        let objects = self.runtime.block_on(self.store.list(None).try_collect::<Vec<_>>());
+        let objects = objects.map_err(StoreError::ObjectStore)?;
+        Ok(objects.into_iter().map(|object| object.location.to_string()).collect())
+    }
+
+    fn delete(&self, key: &str) -> Result<(), StoreError> {
+        let path = object_path(key)?;
+        match self.runtime.block_on(self.store.delete(&path)) {
+            Err(object_store::Error::NotFound { .. }) => Ok(()),
+            deleted => deleted.map_err(|err| Self::error(key, err)),
+        }
+    }
+}
+
+// The object path of a key, which must have no empty, `.` or `..` part
+fn object_path(key: &str) -> Result<ObjectPath, StoreError> {
+    ObjectPath::parse(key).map_err(|err| StoreError::Config(format!("invalid key {key:?}: {err}")))
+}
+
+// The bucket and prefix of an `s3://bucket/prefix` URL, `None` for a local directory
+fn s3_location(url: &str) -> Result<Option<(&str, &str)>, StoreError> {
+    let Some(location) = url.strip_prefix(S3_SCHEME) else {
+        return Ok(None);
+    };
+    let (bucket, prefix) = location.split_once('/').unwrap_or((location, ""));
+    if bucket.is_empty() {
+        return Err(StoreError::Config(format!("{url} names no bucket, expected s3://bucket/prefix")));
+    }
+    Ok(Some((bucket, prefix)))
+}
+
+/// Checks that a store URL names a bucket or a directory, without opening it: an `s3://` store
+/// reads its settings when it is opened.
+pub fn check_url(url: &str) -> Result<(), StoreError> {
+    s3_location(url).map(|_| ())
+}
+
+/// The backend of a store URL: `s3://bucket/prefix` for an S3-compatible store configured
+/// through the environment (see [`ObjectStoreBackend::s3_from_env`]), anything else a local
+/// directory, with or without a `file://` scheme.
+pub fn open(url: &str) -> Result<Box<dyn CheckpointBackend>, StoreError> {
+    match s3_location(url)? {
+        Some((bucket, prefix)) => Ok(Box::new(ObjectStoreBackend::s3_from_env(bucket, prefix)?)),
+        None => Ok(Box::new(LocalBackend::new(url.strip_prefix("file://").unwrap_or(url)))),
+    }
+}
+
+pub fn is_remote(url: &str) -> bool {
+    url.starts_with(S3_SCHEME)
+}
+
+// Every file under `root`, with its `/`-separated path relative to it
+fn relative_files(root: &Path) -> io::Result<Vec<(String, PathBuf)>> {
+    let mut files = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                pending.push(path);
+                continue;
+            }
+            let relative = path.strip_prefix(root).expect("read_dir lists paths under the dir");
+            let key: Vec<String> = relative.components().map(|part| part.as_os_str().to_string_lossy().into_owned()).collect();
+            files.push((key.join("/"), path));
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Uploads every file of the artifact dir, its config, learner checkpoints and final model
+/// included, but its lock. Returns the number of files uploaded.
+pub fn upload_run(backend: &dyn CheckpointBackend, dir: &ArtifactDir) -> Result<usize, StoreError> {
+    let files = relative_files(dir.as_ref())?;
+    let files: Vec<_> = files.into_iter().filter(|(key, _)| key != LOCK_FILE).collect();
+    for (key, path) in &files {
+        backend.put(key, &fs::read(path)?)?;
+    }
+    Ok(files.len())
+}
+
+/// The upload of a run to its store while it trains: each learner checkpoint as the learner
+/// writes it (see [`UploadingRecorder`]), then the rest of the artifact dir once training is
+/// over ([`RunUpload::finish`]). Clones share the files uploaded so far.
+#[derive(Clone)]
+pub struct RunUpload {
+    backend: Arc<dyn CheckpointBackend>,
+    url: String,
+    dir: PathBuf,
+    // Keys uploaded during training, which `finish` does not upload again
+    uploaded: Arc<Mutex<BTreeSet<String>>>,
+}
+
+impl RunUpload {
+    /// The upload of the run of `dir` to the store of `url`, see [`open`].
+    pub fn open(url: &str, dir: &ArtifactDir) -> Result<Self, StoreError> {
+        Ok(Self::new(open(url)?, url, dir))
+    }
+
+    pub fn new(backend: impl Into<Arc<dyn CheckpointBackend>>, url: &str, dir: &ArtifactDir) -> Self {
+        let dir = dir.as_ref().to_path_buf();
+        Self { backend: backend.into(), url: url.to_string(), dir, uploaded: Arc::default() }
+    }
+
+    /// Uploads a file of the artifact dir, under its path relative to the dir.
+    pub fn upload(&self, path: &Path) -> Result<(), StoreError> {
+        let relative = path
+            .strip_prefix(&self.dir)
+            .map_err(|_| StoreError::Config(format!("{} is not in the artifact dir", path.display())))?;
+        let key: Vec<String> = relative.components().map(|part| part.as_os_str().to_string_lossy().into_owned()).collect();
+        let key = key.join("/");
+        self.backend.put(&key, &fs::read(path)?)?;
+        self.uploaded.lock().unwrap().insert(key);
+        Ok(())
+    }
+
+    /// Uploads the files of the finished run that are not in the store yet, all but its lock,
+    /// and removes the checkpoints the learner uploaded then deleted. Returns the number of
+    /// files of the run in the store.
+    pub fn finish(&self) -> Result<usize, StoreError> {
+        let files = relative_files(&self.dir)?;
+        let mut uploaded = self.uploaded.lock().unwrap();
+        for (key, path) in &files {
+            if key != LOCK_FILE && !uploaded.contains(key) {
+                self.backend.put(key, &fs::read(path)?)?;
+                uploaded.insert(key.clone());
+            }
+        }
+        let kept: BTreeSet<&String> = files.iter().map(|(key, _)| key).collect();
+        let deleted: Vec<String> = uploaded.iter().filter(|key| !kept.contains(key)).cloned().collect();
+        for key in deleted {
+            self.backend.delete(&key)?;
+            uploaded.remove(&key);
+        }
+        Ok(uploaded.len())
+    }
+}
+
+impl fmt::Display for RunUpload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.url)
+    }
+}
+
+impl fmt::Debug for RunUpload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RunUpload").field("url", &self.url).field("dir", &self.dir).finish_non_exhaustive()
+    }
+}
+
+/// Uploads every learner checkpoint the inner recorder writes to the store of the run, so a run
+/// that dies mid-training leaves its last checkpoints behind. A failed upload only warns: the
+/// learner writes checkpoints from its own thread, which an error would bring down, and
+/// [`RunUpload::finish`] uploads the file again.
+#[derive(Clone, Debug, Default)]
+pub struct UploadingRecorder<R> {
+    inner: R,
+    upload: Option<RunUpload>,
+}
+
+impl<R> UploadingRecorder<R> {
+    pub fn new(inner: R, upload: Option<RunUpload>) -> Self {
+        Self { inner, upload }
+    }
+}
+
+impl<B: Backend, R: FileRecorder<B>> Recorder<B> for UploadingRecorder<R> {
+    type Settings = R::Settings;
+    type RecordArgs = PathBuf;
+    type RecordOutput = ();
+    type LoadArgs = PathBuf;
+
+    fn record<T: Record<B>>(&self, record: T, args: PathBuf) -> Result<(), RecorderError> {
+        self.inner.record(record, args.clone())?;
+        if let Some(upload) = &self.upload {
+            let path = args.with_extension(R::file_extension());
+            if let Err(err) = upload.upload(&path) {
+                eprintln!("Warning: could not upload {} to {upload} ({err}), retrying once training is over", path.display());
+            }
+        }
+        Ok(())
+    }
+
+    fn save_item<I: Serialize>(&self, item: I, args: PathBuf) -> Result<(), RecorderError> {
+        self.inner.save_item(item, args)
+    }
+
+    fn load_item<I: DeserializeOwned>(&self, args: PathBuf) -> Result<I, RecorderError> {
+        self.inner.load_item(args)
+    }
+}
+
+impl<B: Backend, R: FileRecorder<B>> FileRecorder<B> for UploadingRecorder<R> {
+    fn file_extension() -> &'static str {
+        R::file_extension()
+    }
+}
+
+/// Downloads the run of the store into `dir`, replacing the files it already has, without the
+/// learner checkpoints unless `checkpoints` is set. Returns the number of files downloaded.
+pub fn download_run(backend: &dyn CheckpointBackend, dir: impl AsRef<Path>, checkpoints: bool) -> Result<usize, StoreError> {
+    let dir = dir.as_ref();
+    let checkpoint_prefix = format!("{CHECKPOINT_DIR}/");
+    let keys: Vec<String> = backend
+        .list()?
+        .into_iter()
+        .filter(|key| checkpoints || !key.starts_with(&checkpoint_prefix))
+        .collect();
+    for key in &keys {
+        // Keys come from the store: none may write outside `dir`
+        if key.split('/').any(|part| part.is_empty() || part == "." || part == "..") {
+            return Err(StoreError::Config(format!("the store holds an invalid key {key:?}")));
+        }
+        let path = dir.join(key);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, backend.get(key)?)?;
+    }
+    Ok(keys.len())
+}
+
+// Where the runs of `s3://` URLs are downloaded to, one directory per bucket and prefix
+fn store_cache_dir(url: &str) -> PathBuf {
+    let location = url.strip_prefix(S3_SCHEME).unwrap_or(url).trim_matches('/');
+    let mut dir = dirs::home_dir().unwrap_or_default().join(".cache").join("my_first_rust_DL_app").join("store");
+    dir.extend(location.split('/').filter(|part| !part.is_empty() && *part != "." && *part != ".."));
+    dir
+}
+
+/// The local artifact dir of `artifact_dir`: itself unless it is an `s3://bucket/prefix` URL,
+/// whose run is downloaded into `~/.cache/my_first_rust_DL_app/store/bucket/prefix` (the
+/// learner checkpoints only when `checkpoints` is set, as resuming needs them) for the commands
+/// reading artifact dirs to load from.
+pub fn fetch_artifact_dir(artifact_dir: &str, checkpoints: bool) -> Result<String, StoreError> {
+    if !is_remote(artifact_dir) {
+        return Ok(artifact_dir.to_string());
+    }
+    let backend = open(artifact_dir)?;
+    let dir = store_cache_dir(artifact_dir);
+    // Files of an earlier download the run no longer has must not be loaded with it
+    if dir.is_dir() {
+        fs::remove_dir_all(&dir)?;
+    }
+    let count = download_run(backend.as_ref(), &dir, checkpoints)?;
+    if count == 0 {
+        return Err(StoreError::NotFound(artifact_dir.to_string()));
+    }
+    Ok(dir.to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        data::{DatasetSource, MnistBatcher},
+        checkpoint::LoadError,
+        inference::{load_model, load_model_from},
+        synthetic::{temp_artifact_dir, tiny_config, SyntheticDigits},
+        training::train,
+        ModelConfig,
+    };
+    use burn::{
+        backend::{ndarray::NdArrayDevice, Autodiff, NdArray},
+        data::{dataloader::batcher::Batcher, dataset::Dataset},
+        module::Module,
+        record::CompactRecorder,
+    };
+    use object_store::memory::InMemory;
+    use std::{collections::BTreeMap, sync::Mutex};
+
+    // A store that keeps any key, even one no object store would, to check what reads it
+    #[derive(Default)]
+    struct MemoryBackend(Mutex<BTreeMap<String, Vec<u8>>>);
+
+    impl CheckpointBackend for MemoryBackend {
+        fn put(&self, key: &str, bytes: &[u8]) -> Result<(), StoreError> {
+            self.0.lock().unwrap().insert(key.to_string(), bytes.to_vec());
+            Ok(())
+        }
+
+        fn get(&self, key: &str) -> Result<Vec<u8>, StoreError> {
+            self.0.lock().unwrap().get(key).cloned().ok_or_else(|| StoreError::NotFound(key.to_string()))
+        }
+
+        fn list(&self) -> Result<Vec<String>, StoreError> {
+            Ok(self.0.lock().unwrap().keys().cloned().collect())
+        }
+
+        fn delete(&self, key: &str) -> Result<(), StoreError> {
+            self.0.lock().unwrap().remove(key);
+            Ok(())
+        }
+    }
+
+    // Every file under `dir` by key, and its bytes
+    fn files(dir: &Path) -> BTreeMap<String, Vec<u8>> {
+        relative_files(dir).unwrap().into_iter().map(|(key, path)| (key, fs::read(path).unwrap())).collect()
+    }
+
+    #[test]
+    fn trained_run_round_trips_through_a_local_store() {
+        let root = std::env::temp_dir().join("my_first_rust_DL_app-store");
+        let _ = fs::remove_dir_all(&root);
+        let (artifact_dir, store, downloaded) = (root.join("run"), root.join("store"), root.join("downloaded"));
+        let config = tiny_config()
+            .with_dataset(DatasetSource::Synthetic { num_samples: 32, seed: 1 })
+            .with_checkpoint_store(Some(format!("file://{}", store.display())))
+            .with_num_epochs(2);
+        let device = NdArrayDevice::default();
+
+        train::<Autodiff<NdArray>>(&artifact_dir, config, device).unwrap();
+        let run = files(&artifact_dir);
+        let original = load_model::<NdArray>(&artifact_dir, &device).unwrap();
+        let stored = files(&store);
+        let backend = open(store.to_str().unwrap()).unwrap();
+        let count = download_run(backend.as_ref(), &downloaded, false).unwrap();
+        let loaded = load_model::<NdArray>(&downloaded, &device).unwrap();
+        let inference_files = files(&downloaded);
+        fs::remove_dir_all(&root).unwrap();
+
+        // All of the run but its lock, which is released by then
+        let expected: BTreeMap<_, _> = run.into_iter().filter(|(key, _)| key != LOCK_FILE).collect();
+        assert_eq!(stored, expected);
+        assert!(stored.contains_key("config.json") && stored.contains_key("checkpoint/model-2.mpk"));
+        // Inference goes without the learner checkpoints
+        assert_eq!(count, inference_files.len());
+        let checkpoints: Vec<&String> = stored.keys().filter(|key| key.starts_with("checkpoint/")).collect();
+        assert_eq!(count + checkpoints.len(), stored.len());
+        assert!(inference_files.keys().all(|key| !key.starts_with("checkpoint/")));
+        let images = MnistBatcher::<NdArray>::new(device).batch(SyntheticDigits::new(8, 3).iter().collect()).images;
+        assert_eq!(loaded.forward(images.clone()).into_data(), original.forward(images).into_data());
+    }
+
+    #[test]
+    fn run_without_save_model_uploads_no_weights() {
+        let root = std::env::temp_dir().join("my_first_rust_DL_app-store-no-save");
+        let _ = fs::remove_dir_all(&root);
+        let (artifact_dir, store) = (root.join("run"), root.join("store"));
+        let config = tiny_config()
+            .with_dataset(DatasetSource::Synthetic { num_samples: 32, seed: 1 })
+            .with_checkpoint_store(Some(store.to_string_lossy().into_owned()))
+            .with_save_model(false)
+            .with_num_epochs(2);
+
+        train::<Autodiff<NdArray>>(&artifact_dir, config, NdArrayDevice::default()).unwrap();
+        let stored = files(&store);
+        fs::remove_dir_all(&root).unwrap();
+
+        assert!(stored.contains_key("config.json") && stored.contains_key("history.json"), "{:?}", stored.keys());
+        assert!(stored.keys().all(|key| !key.starts_with("checkpoint/") && !key.ends_with(".mpk")), "{:?}", stored.keys());
+    }
+
+    #[test]
+    fn trained_model_loads_for_inference_straight_from_a_store() {
+        let artifact_dir = temp_artifact_dir("store-load");
+        let config = tiny_config().with_dataset(DatasetSource::Synthetic { num_samples: 32, seed: 1 });
+        let device = NdArrayDevice::default();
+
+        train::<Autodiff<NdArray>>(&artifact_dir, config, device).unwrap();
+        let original = load_model::<NdArray>(&artifact_dir, &device).unwrap();
+        let backend = MemoryBackend::default();
+        upload_run(&backend, &ArtifactDir::new(&artifact_dir)).unwrap();
+        fs::remove_dir_all(&artifact_dir).unwrap();
+        let loaded = load_model_from::<NdArray>(&backend, &device).unwrap();
+        backend.delete("model.mpk").unwrap();
+        let missing = load_model_from::<NdArray>(&backend, &device).map(|_| ());
+
+        let images = MnistBatcher::<NdArray>::new(device).batch(SyntheticDigits::new(8, 3).iter().collect()).images;
+        assert_eq!(loaded.forward(images.clone()).into_data(), original.forward(images).into_data());
+        assert!(matches!(missing, Err(LoadError::Record(_))), "{missing:?}");
+    }
+
+    #[test]
+    fn uploaded_files_download_back_identical_with_their_checkpoints() {
+        let root = std::env::temp_dir().join("my_first_rust_DL_app-store-memory");
+        let _ = fs::remove_dir_all(&root);
+        let source = root.join("run");
+        for (key, content) in [("config.json", "{}"), ("checkpoint/optim-1.mpk", "optim"), ("a/b/c.txt", "nested")] {
+            let path = source.join(key);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        fs::write(source.join(LOCK_FILE), "123").unwrap();
+        let backend = ObjectStoreBackend::new(InMemory::new(), "runs/1").unwrap();
+
+        assert_eq!(upload_run(&backend, &ArtifactDir::new(&source)).unwrap(), 3);
+        let mut keys = backend.list().unwrap();
+        keys.sort();
+        assert_eq!(keys, ["a/b/c.txt", "checkpoint/optim-1.mpk", "config.json"]);
+        assert_eq!(download_run(&backend, root.join("copy"), true).unwrap(), 3);
+        let mut expected = files(&source);
+        expected.remove(LOCK_FILE);
+        let copy = files(&root.join("copy"));
+        // A key escaping the directory is refused before anything is written
+        let escaping = MemoryBackend::default();
+        escaping.put("../escaped", b"x").unwrap();
+        let backend_escaped = backend.put("../escaped", b"x");
+        let escaped = download_run(&escaping, root.join("escape"), true);
+        let written = root.join("escaped").exists();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(copy, expected);
+        assert!(matches!(escaped, Err(StoreError::Config(_))), "{escaped:?}");
+        assert!(matches!(backend_escaped, Err(StoreError::Config(_))), "{backend_escaped:?}");
+        assert!(!written);
+        assert!(matches!(backend.get("missing"), Err(StoreError::NotFound(key)) if key == "missing"));
+    }
+
+    #[test]
+    fn checkpoints_are_uploaded_as_they_are_written() {
+        let dir = std::env::temp_dir().join("my_first_rust_DL_app-store-checkpoints");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("checkpoint")).unwrap();
+        let backend = Arc::new(MemoryBackend::default());
+        let upload = RunUpload::new(Arc::clone(&backend) as Arc<dyn CheckpointBackend>, "memory", &ArtifactDir::new(&dir));
+        let recorder = UploadingRecorder::new(CompactRecorder::new(), Some(upload.clone()));
+        let device = NdArrayDevice::default();
+        let model = ModelConfig::new(10, 8).init::<NdArray>(&device);
+
+        for epoch in [1, 2] {
+            Recorder::<NdArray>::record(&recorder, model.clone().into_record(), dir.join(format!("checkpoint/model-{epoch}"))).unwrap();
+        }
+        let during = backend.list().unwrap();
+        // The learner drops the older checkpoint, the run writes its final files
+        fs::remove_file(dir.join("checkpoint/model-1.mpk")).unwrap();
+        fs::write(dir.join("config.json"), "{}").unwrap();
+        fs::write(dir.join(LOCK_FILE), "123").unwrap();
+        let count = upload.finish().unwrap();
+        let stored = backend.0.lock().unwrap().clone();
+        let checkpoint = fs::read(dir.join("checkpoint/model-2.mpk")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(during, ["checkpoint/model-1.mpk", "checkpoint/model-2.mpk"]);
+        assert_eq!(count, 2);
+        assert_eq!(stored.keys().collect::<Vec<_>>(), ["checkpoint/model-2.mpk", "config.json"]);
+        assert_eq!(stored["checkpoint/model-2.mpk"], checkpoint);
+    }
+
+    #[test]
+    fn store_urls_name_a_bucket_or_a_directory() {
+        assert!(matches!(open("s3:///prefix").map(|_| ()), Err(StoreError::Config(_))));
+        assert!(matches!(check_url("s3:///prefix"), Err(StoreError::Config(_))));
+        assert!(check_url("s3://bucket/runs").is_ok() && check_url("/tmp/store").is_ok());
+        assert!(is_remote("s3://bucket/runs") && !is_remote("/tmp/store") && !is_remote("file:///tmp/store"));
+        let local = LocalBackend::new(std::env::temp_dir().join("my_first_rust_DL_app-store-missing"));
+        assert_eq!(local.list().unwrap(), Vec::<String>::new());
+        assert!(matches!(local.get("config.json"), Err(StoreError::NotFound(_))));
+    }
+
+    #[test]
+    fn object_store_backend_keeps_to_its_prefix() {
+        let store = Arc::new(InMemory::new());
+        let run = ObjectStoreBackend::new(Arc::clone(&store), "/runs/a/").unwrap();
+        let other = ObjectStoreBackend::new(Arc::clone(&store), "runs/ab").unwrap();
+        let bucket = ObjectStoreBackend::new(Arc::clone(&store), "").unwrap();
+
+        run.put("checkpoint/model-1.mpk", b"model").unwrap();
+        run.put("config.json", b"{}").unwrap();
+        other.put("config.json", b"other").unwrap();
+        // Overwritten in place
+        run.put("config.json", b"{\"seed\": 1}").unwrap();
+
+        let mut keys = run.list().unwrap();
+        keys.sort();
+        assert_eq!(keys, ["checkpoint/model-1.mpk", "config.json"]);
+        assert_eq!(run.get("config.json").unwrap(), b"{\"seed\": 1}");
+        assert_eq!(other.list().unwrap(), ["config.json"]);
+        let mut objects = bucket.list().unwrap();
+        objects.sort();
+        assert_eq!(objects, ["runs/a/checkpoint/model-1.mpk", "runs/a/config.json", "runs/ab/config.json"]);
+        assert!(matches!(run.get("missing.json"), Err(StoreError::NotFound(key)) if key == "missing.json"));
+        assert!(matches!(run.put("a//b", b""), Err(StoreError::Config(_))));
+        assert!(matches!(ObjectStoreBackend::new(InMemory::new(), "a/../b"), Err(StoreError::Config(_))));
+    }
+}
@@ -0,0 +1,208 @@
+use crate::{
+    artifact::ArtifactDir,
+    data::MnistBatch,
+    model::{Model, ModelConfig},
+    params::{named_params, with_named_params, NamedParam},
+    training::{LossSettings, Reduction},
+};
+use burn::{
+    data::dataloader::DataLoader,
+    prelude::*,
+    record::{CompactRecorder, Recorder, RecorderError},
+    tensor::backend::AutodiffBackend,
+};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Stochastic Weight Averaging of the tail of training, see `TrainingConfig::swa`.
+#[derive(Config, Debug, PartialEq)]
+pub struct SwaConfig {
+    // First (1-based) epoch whose end-of-epoch weights are averaged
+    pub start_epoch: usize,
+    // Average the weights of every this many epochs from `start_epoch` on: 1 takes every epoch,
+    // 2 every other one
+    #[config(default = 1)]
+    pub frequency: usize,
+}
+
+// Epochs whose end-of-epoch weights go into the SWA average of a `num_epochs` run
+pub fn swa_epochs(config: &SwaConfig, num_epochs: usize) -> Vec<usize> {
+    (config.start_epoch..=num_epochs).step_by(config.frequency.max(1)).collect()
+}
+
+// Stochastic Weight Averaging: the mean of the weights the learner checkpointed at the end of
+// each of `epochs`, updated one checkpoint at a time so only the running average and a single
+// checkpoint are in memory.
+//
+// The running statistics of `ModelConfig::batch_norm` are averaged like the weights, which
+// leaves them matching none of the averaged models: they need `recompute_batch_norm` before
+// the average is used.
+pub fn average_checkpoints<B: Backend>(
+    artifact_dir: &str,
+    config: &ModelConfig,
+    epochs: &[usize],
+    device: &B::Device,
+) -> Result<Model<B>, RecorderError> {
+    let recorder = CompactRecorder::new();
+    let mut average: Vec<NamedParam> = Vec::new();
+
+    for (count, epoch) in epochs.iter().enumerate() {
+        let record = recorder.load(ArtifactDir::new(artifact_dir).checkpoint_path(*epoch).into(), device)?;
+        let params = named_params(&config.init::<B>(device).load_record(record));
+
+        if average.is_empty() {
+            average = params;
+            continue;
+        }
+        // Running mean: avg += (x - avg) / n
+        let n = (count + 1) as f32;
+        for (mean, param) in average.iter_mut().zip(&params) {
+            for (mean, value) in mean.values.iter_mut().zip(&param.values) {
+                *mean += (value - *mean) / n;
+            }
+        }
+    }
+
+    Ok(with_named_params(config.init::<B>(device), &average, device))
+}
+
+// Re-estimates the running statistics of the `ModelConfig::batch_norm` layers of `model` for
+// its own weights, with one pass over `dataloader` in training mode. Batch `n` of the pass is
+// weighted in by a momentum of `1 / n`, so the statistics end as the mean over every batch
+// rather than leaning on the last ones. A model without batch norm is returned as is.
+pub fn recompute_batch_norm<B: AutodiffBackend>(
+    mut model: Model<B>,
+    config: &ModelConfig,
+    dataloader: &dyn DataLoader<MnistBatch<B>>,
+    device: &B::Device,
+) -> Model<B> {
+    if !config.batch_norm {
+        return model;
+    }
+    // The momentum is a constant of the layers, not part of their record: set by rebuilding them
+    for (count, batch) in dataloader.iter().enumerate() {
+        let momentum = 1.0 / (count + 1) as f64;
+        model = config.clone().with_bn_momentum(momentum).init::<B>(device).load_record(model.into_record());
+        model.forward(batch.images);
+    }
+    config.init::<B>(device).load_record(model.into_record())
+}
+
+// Accuracy (in percent) and mean loss of a model over a whole split
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct SplitScore {
+    pub accuracy: f64,
+    pub loss: f64,
+}
+
+pub fn score<B: Backend>(model: &Model<B>, dataloader: &dyn DataLoader<MnistBatch<B>>) -> SplitScore {
+    let (mut correct, mut loss_sum, mut num_samples) = (0usize, 0.0f64, 0usize);
+
+    for batch in dataloader.iter() {
+        let batch_size = batch.targets.dims()[0];
+        // Summed, for the mean over the whole split
+        let loss = LossSettings { reduction: Reduction::Sum, ..batch.loss };
+        let output = model.forward_classification(batch.images, batch.targets, batch.soft_targets, loss);
+
+        let predicted = output.output.argmax(1).flatten::<1>(0, 1);
+        correct += predicted.equal(output.targets).int().sum().into_scalar().elem::<i64>() as usize;
+        loss_sum += output.loss.into_scalar().elem::<f64>();
+        num_samples += batch_size;
+    }
+
+    let num_samples = num_samples.max(1) as f64;
+    SplitScore { accuracy: correct as f64 / num_samples * 100.0, loss: loss_sum / num_samples }
+}
+
+// Validation scores of the final and the SWA model, saved as `swa.json`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SwaReport {
+    // Epochs averaged into the SWA model
+    pub epochs: Vec<usize>,
+    pub model: SplitScore,
+    pub swa: SplitScore,
+}
+
+impl fmt::Display for SwaReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (first, last) = (self.epochs.first().unwrap_or(&0), self.epochs.last().unwrap_or(&0));
+        writeln!(f, "SWA over epochs {first}-{last}, on the validation split:")?;
+        writeln!(f, "| {:<6} | {:>10} | {:>8} |", "Model", "Accuracy", "Loss")?;
+        writeln!(f, "|--------|------------|----------|")?;
+        for (name, score) in [("final", &self.model), ("swa", &self.swa)] {
+            writeln!(f, "| {:<6} | {:>9.2}% | {:>8.4} |", name, score.accuracy, score.loss)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        artifact::ModelKind,
+        data::{boxed_mnist_dataloader, MnistBatcher},
+        synthetic::{temp_artifact_dir, tiny_config, train_synthetic, SyntheticDigits},
+    };
+    use burn::{
+        backend::{ndarray::NdArrayDevice, Autodiff, NdArray},
+        module::AutodiffModule,
+    };
+
+    #[test]
+    fn swa_epochs_follow_the_start_and_frequency() {
+        assert_eq!(swa_epochs(&SwaConfig::new(3), 5), [3, 4, 5]);
+        assert_eq!(swa_epochs(&SwaConfig::new(2).with_frequency(2), 7), [2, 4, 6]);
+        assert_eq!(swa_epochs(&SwaConfig::new(6), 5), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn batch_norm_statistics_are_recomputed_over_the_whole_pass() {
+        type B = Autodiff<NdArray>;
+        let device = NdArrayDevice::default();
+        let config = ModelConfig::new(10, 8).with_batch_norm(true);
+        let dataloader = |batch_size| {
+            boxed_mnist_dataloader(MnistBatcher::<B>::new(device), SyntheticDigits::new(32, 1), batch_size, 1, 1, 0)
+        };
+
+        let model = recompute_batch_norm(config.init::<B>(&device), &config, dataloader(8).as_ref(), &device).valid();
+        // The mean of the conv1 outputs over the 32 images, from 4 batches of the same size
+        let images = Tensor::cat(dataloader(32).iter().map(|batch| batch.images.inner()).collect(), 0);
+        let conv1 = model.activations(images).into_iter().find(|(name, _)| name == "conv1").unwrap().1;
+        let [batch_size, channels, height, width] = conv1.dims();
+        let expected = conv1.swap_dims(0, 1).reshape([channels, batch_size * height * width]).mean_dim(1);
+        let running_mean = named_params(&model).into_iter().find(|param| param.name == "conv1_norm.running_mean").unwrap();
+        let expected = expected.into_data().value;
+        assert_eq!(running_mean.values.len(), expected.len());
+        for (mean, expected) in running_mean.values.iter().zip(&expected) {
+            assert!((mean - expected).abs() < 1e-4, "running mean {mean} instead of {expected}");
+        }
+    }
+
+    #[test]
+    fn swa_model_is_saved_and_differs_from_the_final_model() {
+        let artifact_dir = temp_artifact_dir("swa");
+        let config = tiny_config().with_swa(Some(SwaConfig::new(2))).with_learning_rate(1e-2).with_num_epochs(3);
+
+        let device = NdArrayDevice::default();
+        let model_config = config.model.clone();
+        let model = train_synthetic(&artifact_dir, config, 32, 16).unwrap();
+        let dir = ArtifactDir::new(artifact_dir.to_str().unwrap());
+        let swa = model_config
+            .init::<NdArray>(&device)
+            .load_file(dir.model_path(ModelKind::Swa), &CompactRecorder::new(), &device)
+            .unwrap();
+        let report: SwaReport =
+            serde_json::from_str(&std::fs::read_to_string(artifact_dir.join("swa.json")).unwrap()).unwrap();
+        std::fs::remove_dir_all(&artifact_dir).unwrap();
+
+        assert_eq!(report.epochs, [2, 3]);
+        assert!((0.0..=100.0).contains(&report.swa.accuracy) && report.swa.loss.is_finite());
+        let differences = named_params(&model.valid())
+            .iter()
+            .zip(named_params(&swa))
+            .map(|(last, swa)| last.values.iter().zip(&swa.values).map(|(a, b)| (a - b).abs()).fold(0.0, f32::max))
+            .fold(0.0, f32::max);
+        assert!(differences > 1e-3, "the SWA weights are within {differences} of the last epoch's");
+    }
+}
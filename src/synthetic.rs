@@ -0,0 +1,166 @@
+use burn::data::dataset::{vision::MnistItem, Dataset};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rand_distr::{Distribution, Normal};
+
+// Seven-segment layout of each digit: top, top-left, top-right, middle, bottom-left,
+// bottom-right, bottom
+const SEGMENTS: [[bool; 7]; 10] = [
+    [true, true, true, false, true, true, true],
+    [false, false, true, false, false, true, false],
+    [true, false, true, true, true, false, true],
+    [true, false, true, true, false, true, true],
+    [false, true, true, true, false, true, false],
+    [true, true, false, true, false, true, true],
+    [true, true, false, true, true, true, true],
+    [true, false, true, false, false, true, false],
+    [true, true, true, true, true, true, true],
+    [true, true, true, true, false, true, true],
+];
+
+// Standard deviation of the pixel noise, on the [0, 255] MNIST scale
+const NOISE_STD: f32 = 30.0;
+
+// Procedurally generated digits in the MNIST item format, for exercising the training loop
+// without downloading MNIST. Each item is a seven-segment glyph of its label drawn with a random
+// offset and stroke width, plus Gaussian pixel noise. Items are generated on access and only
+// depend on the seed and their index, so the dataset is deterministic and takes no memory.
+#[derive(Clone, Debug)]
+pub struct SyntheticDigits {
+    num_samples: usize,
+    seed: u64,
+    // Items whose pixels are all NaN
+    nan_items: Vec<usize>,
+}
+
+impl SyntheticDigits {
+    pub fn new(num_samples: usize, seed: u64) -> Self {
+        Self { num_samples, seed, nan_items: Vec::new() }
+    }
+
+    // Poisons the items at `indices` with NaN pixels, so that any batch holding one trains on a
+    // NaN loss, as `TrainingConfig::nan_guard` catches
+    pub fn with_nan_items(mut self, indices: Vec<usize>) -> Self {
+        self.nan_items = indices;
+        self
+    }
+}
+
+// Sets every pixel of the axis-aligned rectangle [x0, x1) x [y0, y1) to full ink
+fn fill(image: &mut [[f32; 28]; 28], (x0, y0): (i32, i32), (x1, y1): (i32, i32)) {
+    for y in y0.max(0)..y1.min(28) {
+        for x in x0.max(0)..x1.min(28) {
+            image[y as usize][x as usize] = 255.0;
+        }
+    }
+}
+
+fn draw_glyph(label: usize, rng: &mut StdRng) -> [[f32; 28]; 28] {
+    let mut image = [[0.0; 28]; 28];
+
+    // A 12x18 glyph box, jittered around the center of the canvas
+    let (width, height) = (12, 18);
+    let left = 8 + rng.gen_range(-2..=2);
+    let top = 5 + rng.gen_range(-2..=2);
+    let (right, middle, bottom) = (left + width, top + height / 2, top + height);
+    let stroke = rng.gen_range(2..=3);
+
+    let segments = [
+        ((left, top), (right, top + stroke)),
+        ((left, top), (left + stroke, middle)),
+        ((right - stroke, top), (right, middle)),
+        ((left, middle - stroke / 2), (right, middle + stroke - stroke / 2)),
+        ((left, middle), (left + stroke, bottom)),
+        ((right - stroke, middle), (right, bottom)),
+        ((left, bottom - stroke), (right, bottom)),
+    ];
+    for (&(start, end), _) in segments.iter().zip(SEGMENTS[label]).filter(|(_, on)| *on) {
+        fill(&mut image, start, end);
+    }
+
+    let noise = Normal::new(0.0, NOISE_STD).expect("Noise std is finite and positive");
+    for pixel in image.iter_mut().flatten() {
+        *pixel = (*pixel + noise.sample(rng)).clamp(0.0, 255.0).round();
+    }
+    image
+}
+
+impl Dataset<MnistItem> for SyntheticDigits {
+    fn get(&self, index: usize) -> Option<MnistItem> {
+        if index >= self.num_samples {
+            return None;
+        }
+
+        // Labels cycle through the digits so every class is equally represented
+        let label = index % SEGMENTS.len();
+        let mut rng = StdRng::seed_from_u64(self.seed ^ (index as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+        let image = match self.nan_items.contains(&index) {
+            true => [[f32::NAN; 28]; 28],
+            false => draw_glyph(label, &mut rng),
+        };
+        Some(MnistItem { image, label: label as u8 })
+    }
+
+    fn len(&self) -> usize {
+        self.num_samples
+    }
+}
+
+// A model trained for an epoch on synthetic digits, shared by the unit tests that need one that
+// beats chance. Trained once per test binary, the weights kept as bytes: models are not `Sync`.
+#[cfg(test)]
+pub(crate) fn trained_model() -> crate::Model<burn::backend::NdArray> {
+    use crate::{convert::RecordFormat, ModelConfig};
+    use burn::{backend::ndarray::NdArrayDevice, module::AutodiffModule};
+    use std::sync::OnceLock;
+
+    static WEIGHTS: OnceLock<Vec<u8>> = OnceLock::new();
+    let model = ModelConfig::new(10, 16);
+    let weights = WEIGHTS.get_or_init(|| {
+        let artifact_dir = temp_artifact_dir("trained-model");
+        let mut config = tiny_config().with_learning_rate(3e-3);
+        config.model = model.clone();
+        let trained =
+            train_synthetic(&artifact_dir, config, 256, 64).expect("Training on synthetic digits should succeed");
+        std::fs::remove_dir_all(&artifact_dir).ok();
+        RecordFormat::NamedMpk.encode(trained.valid())
+    });
+    let device = NdArrayDevice::default();
+    RecordFormat::NamedMpk.decode(model.init(&device), weights, &device).expect("The weights were just encoded")
+}
+
+// The tiny run most unit tests train: a `ModelConfig::new(10, 8)` model for one silent epoch of
+// 16-image batches on one worker. Tests override what they exercise on top of it.
+#[cfg(test)]
+pub(crate) fn tiny_config() -> crate::TrainingConfig {
+    use crate::{training::Verbosity, ModelConfig, TrainingConfig};
+
+    TrainingConfig::new(ModelConfig::new(10, 8), burn::optim::AdamConfig::new())
+        .with_num_epochs(1)
+        .with_batch_size(16)
+        .with_num_workers(1)
+        .with_verbosity(Verbosity::Silent)
+}
+
+// An artifact dir named after the test in the temp dir, emptied of any earlier run's files
+#[cfg(test)]
+pub(crate) fn temp_artifact_dir(name: &str) -> std::path::PathBuf {
+    let artifact_dir = std::env::temp_dir().join(format!("my_first_rust_DL_app-{name}"));
+    let _ = std::fs::remove_dir_all(&artifact_dir);
+    artifact_dir
+}
+
+// Trains `config` into `artifact_dir` on `num_train` synthetic digits (seed 1), validated on
+// `num_valid` others (seed 2)
+#[cfg(test)]
+pub(crate) fn train_synthetic(
+    artifact_dir: impl AsRef<std::path::Path>,
+    config: crate::TrainingConfig,
+    num_train: usize,
+    num_valid: usize,
+) -> Result<crate::Model<burn::backend::Autodiff<burn::backend::NdArray>>, crate::TrainError> {
+    use burn::backend::{ndarray::NdArrayDevice, Autodiff, NdArray};
+
+    let artifact_dir = artifact_dir.as_ref().to_str().expect("Test artifact dirs are UTF-8");
+    let (train_set, valid_set) = (SyntheticDigits::new(num_train, 1), SyntheticDigits::new(num_valid, 2));
+    crate::train_on::<Autodiff<NdArray>, _>(artifact_dir, config, train_set, valid_set, NdArrayDevice::default())
+}
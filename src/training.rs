@@ -1,28 +1,104 @@
 use crate::{
-    data::{MnistBatch, MnistBatcher},
-    model::{Model, ModelConfig},
+    adapter::{AdapterConfig, ADAPTER_TARGETS},
+    artifact::{ArtifactDir, LockError, ModelKind},
+    balanced::BalancedSampler,
+    batch_order::{BatchOrder, BatchOrderDataLoader},
+    budget::{BudgetDataLoader, BudgetStop, BudgetTracker},
+    curriculum::{score_samples, CurriculumConfig, CurriculumDataLoader, CurriculumOrder},
+    hard_mining::{HardMining, HardMiningConfig, HardMiningOptimizer},
+    data::{
+        boxed_dataloader, boxed_mnist_dataloader, ClassificationDataset, DatasetSource, IndexedDataset, MnistBatch,
+        MnistBatcher, MnistSplit, SourceCount, MNIST_NUM_CLASSES,
+    },
+    checkpoint::{load_partial_params, load_weights, read_safetensors, LoadError},
+    history::History,
+    memory::{search_batch_size, MemoryMetric, PeakMemoryMetric},
+    labels::ClassLabels,
+    lr_groups::{lr_groups, GroupLearningRateMetric, LrGroup, LrMultiplierOptimizer},
+    meta::ModelMeta,
+    model_card::ModelCard,
+    holdout::{holdout_items, load_holdout, HoldoutAccuracyMetric, HoldoutDataLoader, HoldoutError, HoldoutInput},
+    inference::{Interpolation, ResizePolicy},
+    metrics::{global_grad_norm, GradNormInput, GradNormMetric},
+    model::{AuxTask, GlobalPool, HeadInput, Model, ModelConfig, TaskKind},
+    optim_stats::{StatsOptimizer, OPTIMIZER_STATS_FILE, UPDATE_RATIOS_FILE},
+    nan_guard::{NanAction, NanEvent, NanGuard, NanGuardOptimizer, NanGuardTracker},
+    multilabel::{
+        MultiLabelBatch, MultiLabelDataset, MultiLabelF1Metric, MultiLabelItems, MultiLabelOutput,
+        MultiLabelTrainOutput,
+    },
+    params::{param_names, NamedParam},
+    preprocess::{PreprocessConfig, PreprocessedDataset},
+    plot::plot_learning_curves,
+    preview::{PreviewDataLoader, PreviewInput, PreviewMetric, PreviewSamples},
+    progress::{ProgressEvent, ProgressRenderer},
+    progressive::ProgressiveResizeDataLoader,
+    prune::{MaskedOptimizer, WeightMasks},
+    prometheus::PrometheusExporter,
+    profile::{self, LoaderKind, ProfiledDataLoader, ProfiledOptimizer, ProfiledRecorder},
+    split::{stratified_split, ClassSubset, DatasetFingerprint, OneVsRest, SplitFileError, SplitIndices, SubsetDataset},
+    step_valid::{valid_subset, StepValidatedOptimizer, StepValidation},
+    store::{self, RunUpload, StoreError, UploadingRecorder},
+    seed::{derive_seed, SeedOrigin},
+    snapshot::snapshot_ensemble,
+    soft_labels::{SoftLabels, SoftLabelsError},
+    schedule::{
+        batches_per_epoch, tag_restart_checkpoints, KeepEpochCheckpoints, LrSchedule,
+        RestartMetric, Scheduler,
+    },
+    swa::{average_checkpoints, recompute_batch_norm, score, swa_epochs, SwaConfig, SwaReport},
+    verify::{verify_items, DatasetError},
 };
+#[cfg(feature = "onnx")]
+use crate::onnx::{check_export, OnnxSnapshotOptimizer, OnnxSnapshots};
 use burn::{
-    data::{dataloader::DataLoaderBuilder, dataset::vision::MnistDataset},
+    data::{
+        dataloader::{batcher::Batcher, DataLoader},
+        dataset::{vision::MnistItem, Dataset},
+    },
+    module::AutodiffModule,
     nn::loss::CrossEntropyLossConfig,
-    optim::AdamConfig,
+    optim::{momentum::MomentumConfig, AdamConfig, Optimizer, SgdConfig},
     prelude::*,
-    record::CompactRecorder,
-    tensor::backend::AutodiffBackend,
+    record::{CompactRecorder, HalfPrecisionSettings, NamedMpkBytesRecorder, Recorder, RecorderError},
+    tensor::{
+        activation::{log_softmax, softmax},
+        backend::AutodiffBackend,
+        bf16, f16,
+    },
     train::{
-        metric::{AccuracyMetric, LossMetric},
-        ClassificationOutput, LearnerBuilder, TrainOutput, TrainStep, ValidStep,
+        checkpoint::{ComposedCheckpointingStrategy, KeepLastNCheckpoints, MetricCheckpointingStrategy},
+        metric::{
+            store::{Aggregate, Direction, Split},
+            AccuracyInput, AccuracyMetric, Adaptor, LearningRateMetric, LossInput, LossMetric,
+        },
+        ClassificationOutput, LearnerBuilder, LearnerSummary, TrainOutput, TrainStep, ValidStep,
     },
 };
+#[cfg(feature = "status-server")]
+use crate::status::StatusServer;
+use std::{
+    any::{type_name, TypeId},
+    path::{Path, PathBuf},
+    sync::{mpsc::Sender, Arc},
+    time::Duration,
+};
 
 impl <B: Backend> Model<B> {
+    // With `soft_targets` (label distributions, e.g. from mixup) the loss is the cross-entropy
+    // against those distributions; `targets` is then only used by the accuracy metric. With an
+    // auxiliary head, its cross-entropy scaled by the `aux_loss_weight` of `loss` is added to the
+    // loss.
     pub fn forward_classification(
         &self,
         images: Tensor<B, 3>,
-        targets: Tensor<B, 1, Int>
+        targets: Tensor<B, 1, Int>,
+        soft_targets: Option<Tensor<B, 2>>,
+        loss: LossSettings,
     ) -> ClassificationOutput<B> {
+        let LossSettings { reduction, aux_loss_weight } = loss;
 
-        let output = self.forward(images);
+        let (output, aux_output) = self.forward_aux(images);
         /* 
             Please take note that tensor operations receive owned tensors as input. 
             For reusing a tensor multiple times, you need to use the clone() function. 
@@ -32,108 +108,3629 @@ impl <B: Backend> Model<B> {
 
             In summary, our API has been designed with owned tensors to optimize performance.
          */
-        let loss = CrossEntropyLossConfig::new()
-            .init(&output.device())
-            .forward(output.clone(), targets.clone());
+        let loss = match soft_targets {
+            Some(soft_targets) => {
+                let log_probs = log_softmax(output.clone(), 1);
+                let losses = (soft_targets * log_probs).sum_dim(1).neg();
+                match reduction {
+                    Reduction::Mean => losses.mean(),
+                    Reduction::Sum => losses.sum(),
+                }
+            }
+            None => {
+                let loss = CrossEntropyLossConfig::new()
+                    .init(&output.device())
+                    .forward(output.clone(), targets.clone());
+                match reduction {
+                    // Burn's cross-entropy is always the batch mean
+                    Reduction::Mean => loss,
+                    Reduction::Sum => loss * targets.dims()[0] as f32,
+                }
+            }
+        };
+        let loss = match (self.aux_head(), aux_output) {
+            (Some(head), Some(aux_output)) if aux_loss_weight > 0.0 => {
+                loss + aux_loss(head.task(), aux_output, targets.clone(), reduction) * aux_loss_weight
+            }
+            _ => loss,
+        };
+
+        ClassificationOutput::new(loss, output, targets)
+    }
+
+    // Distillation loss against the label distributions `soft_labels` `[batch_size, num_classes]`:
+    // per sample, the KL divergence KL(soft_labels || softmax(output)) of the predictions from
+    // the targets, mixed with the hard-label cross-entropy by `hard_weight`. With one-hot soft
+    // labels the divergence is the cross-entropy itself. `targets` are also what the accuracy
+    // metric reads.
+    pub fn forward_soft(
+        &self,
+        images: Tensor<B, 3>,
+        targets: Tensor<B, 1, Int>,
+        soft_labels: Tensor<B, 2>,
+        hard_weight: f32,
+        reduction: Reduction,
+    ) -> ClassificationOutput<B> {
+        let output = self.forward(images);
+        let log_probs = log_softmax(output.clone(), 1);
+        let batch_size = targets.dims()[0];
+
+        // p ln p of the targets, 0 where p is: the clamp only keeps the log finite
+        let target_terms = soft_labels.clone() * soft_labels.clone().clamp_min(1e-12).log();
+        let divergence = (target_terms - soft_labels * log_probs.clone()).sum_dim(1).reshape([batch_size]);
+        let cross_entropy = log_probs.gather(1, targets.clone().reshape([batch_size, 1])).neg().reshape([batch_size]);
+        let losses = divergence * (1.0 - hard_weight) + cross_entropy * hard_weight;
+        let loss = match reduction {
+            Reduction::Mean => losses.mean(),
+            Reduction::Sum => losses.sum(),
+        };
 
         ClassificationOutput::new(loss, output, targets)
     }
+
+    // The cross-entropy of every sample of the batch `[batch_size]`, before any reduction, with
+    // the logits `[batch_size, num_classes]` it was computed from
+    pub fn per_sample_losses(&self, images: Tensor<B, 3>, targets: Tensor<B, 1, Int>) -> (Tensor<B, 1>, Tensor<B, 2>) {
+        let output = self.forward(images);
+        let batch_size = targets.dims()[0];
+        let losses = log_softmax(output.clone(), 1).gather(1, targets.reshape([batch_size, 1])).neg();
+        (losses.reshape([batch_size]), output)
+    }
+}
+
+// The cross-entropy of the auxiliary head's logits against the `task` targets of `targets`,
+// reduced as the class loss is
+fn aux_loss<B: Backend>(
+    task: AuxTask,
+    aux_output: Tensor<B, 2>,
+    targets: Tensor<B, 1, Int>,
+    reduction: Reduction,
+) -> Tensor<B, 1> {
+    let batch_size = targets.dims()[0];
+    let loss = CrossEntropyLossConfig::new().init(&aux_output.device()).forward(aux_output, task.targets(targets));
+    match reduction {
+        Reduction::Mean => loss,
+        Reduction::Sum => loss * batch_size as f32,
+    }
+}
+
+
+// Output of a training step: the classification output the accuracy and loss metrics read,
+// plus the global gradient norm of the step for the gradient norm metric
+pub struct TrainStepOutput<B: AutodiffBackend> {
+    pub classification: ClassificationOutput<B>,
+    pub grad_norm: Tensor<B::InnerBackend, 1>,
+}
+
+impl<B: AutodiffBackend> Adaptor<AccuracyInput<B>> for TrainStepOutput<B> {
+    fn adapt(&self) -> AccuracyInput<B> {
+        self.classification.adapt()
+    }
+}
+
+impl<B: AutodiffBackend> Adaptor<LossInput<B>> for TrainStepOutput<B> {
+    fn adapt(&self) -> LossInput<B> {
+        self.classification.adapt()
+    }
+}
+
+impl<B: AutodiffBackend> Adaptor<GradNormInput<B::InnerBackend>> for TrainStepOutput<B> {
+    fn adapt(&self) -> GradNormInput<B::InnerBackend> {
+        GradNormInput::new(self.grad_norm.clone())
+    }
 }
 
 
 // Implementation of the training and validation steps for our model
 
-impl <B: AutodiffBackend> TrainStep<MnistBatch<B>, ClassificationOutput<B>> for Model<B> {
-    fn step(&self, batch: MnistBatch<B>) -> TrainOutput<ClassificationOutput<B>> {
-        let item = self.forward_classification(batch.images, batch.targets);
+impl <B: AutodiffBackend> TrainStep<MnistBatch<B>, TrainStepOutput<B>> for Model<B> {
+    fn step(&self, batch: MnistBatch<B>) -> TrainOutput<TrainStepOutput<B>> {
+        let item = profile::step_span("forward", || match batch.soft_labels {
+            Some(soft_labels) => self.forward_soft(
+                batch.images,
+                batch.targets,
+                soft_labels.probabilities,
+                soft_labels.hard_weight,
+                batch.loss.reduction,
+            ),
+            None => self.forward_classification(batch.images, batch.targets, batch.soft_targets, batch.loss),
+        });
 
         /*
             Note that contrary to PyTorch, gradients are not stored alongside each tensor parameter, 
             but are rather returned by the backward pass, as such: 
             let gradients = loss.backward();
         */
+        let gradients = profile::step_span("backward", || item.loss.backward());
+        // Lazy: only synced to the host when the gradient norm metric is registered
+        let grad_norm = global_grad_norm(self, &gradients, &item.loss.device());
+
+        TrainOutput::new(self, gradients, TrainStepOutput { classification: item, grad_norm })
+    }
+}
+
+// Output of a validation step: the classification output, plus the holdout accuracy and the
+// preview probabilities on the step that carried the holdout and preview batches
+pub struct ValidStepOutput<B: Backend> {
+    pub classification: ClassificationOutput<B>,
+    pub holdout_accuracy: Option<f64>,
+    pub preview_probabilities: Option<Vec<f32>>,
+}
+
+impl<B: Backend> Adaptor<AccuracyInput<B>> for ValidStepOutput<B> {
+    fn adapt(&self) -> AccuracyInput<B> {
+        self.classification.adapt()
+    }
+}
 
-        TrainOutput::new(self, item.loss.backward(), item)
+impl<B: Backend> Adaptor<LossInput<B>> for ValidStepOutput<B> {
+    fn adapt(&self) -> LossInput<B> {
+        self.classification.adapt()
     }
 }
 
-impl <B: Backend> ValidStep<MnistBatch<B>, ClassificationOutput<B>> for Model<B> {
-    fn step(&self, batch: MnistBatch<B>) -> ClassificationOutput<B> {
-        self.forward_classification(batch.images, batch.targets)
-    }   
+impl<B: Backend> Adaptor<HoldoutInput> for ValidStepOutput<B> {
+    fn adapt(&self) -> HoldoutInput {
+        HoldoutInput::new(self.holdout_accuracy)
+    }
+}
+
+impl<B: Backend> Adaptor<PreviewInput> for ValidStepOutput<B> {
+    fn adapt(&self) -> PreviewInput {
+        PreviewInput::new(self.preview_probabilities.clone())
+    }
+}
+
+impl <B: Backend> ValidStep<MnistBatch<B>, ValidStepOutput<B>> for Model<B> {
+    fn step(&self, batch: MnistBatch<B>) -> ValidStepOutput<B> {
+        let holdout_accuracy = batch.holdout.map(|holdout| {
+            let predicted = self.forward(holdout.images).argmax(1).flatten::<1>(0, 1);
+            let num_images = holdout.targets.dims()[0];
+            let correct = predicted.equal(holdout.targets).int().sum().into_scalar().elem::<i64>();
+            correct as f64 / num_images as f64 * 100.0
+        });
+        let preview_probabilities = batch
+            .preview
+            .map(|preview| softmax(self.forward(preview.images), 1).into_data().convert::<f32>().value);
+        let classification = self.forward_classification(batch.images, batch.targets, batch.soft_targets, batch.loss);
+
+        ValidStepOutput { classification, holdout_accuracy, preview_probabilities }
+    }
 }
 
 
 // Establishing the Training Configurations
 
+/// How the per-sample losses of a batch are combined into the training loss.
+#[derive(Config, Debug, Copy, PartialEq)]
+pub enum Reduction {
+    // Batch mean: the gradient scale does not depend on the batch size
+    Mean,
+    // Batch sum: accumulating gradients over batches then matches one large batch
+    Sum,
+}
+
+/// How the train and validation steps compute the loss of a batch, from the [`TrainingConfig`]
+/// of the run (see [`TrainingConfig::loss_settings`]). The batches carry it to the steps, so
+/// that the model holds its architecture only.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LossSettings {
+    pub reduction: Reduction,
+    // What the loss of the auxiliary head is scaled by, 0 leaving it out
+    pub aux_loss_weight: f64,
+}
+
+// The batch mean of the class loss alone
+impl Default for LossSettings {
+    fn default() -> Self {
+        Self { reduction: Reduction::Mean, aux_loss_weight: 0.0 }
+    }
+}
+
+/// Which optimizer [`train`] builds.
+#[derive(Config, Debug, PartialEq)]
+pub enum OptimizerKind {
+    // Uses the `optimizer` Adam config, with the `adam_*` betas and epsilon
+    Adam,
+    // Plain SGD, with optional (Nesterov) momentum from `momentum` / `nesterov`
+    Sgd,
+}
+
+/// What training prints to stdout. The logs, history and every other file of the artifact dir
+/// are written whatever it is.
+#[derive(Config, Debug, Copy, PartialEq)]
+pub enum Verbosity {
+    // Nothing at all, for CI
+    Silent,
+    // One line of metric means per epoch, the messages of the run and burn's final summary
+    Summary,
+    // Burn's live dashboard of every step on top of `Summary`, minus the epoch lines
+    Full,
+}
+
+/// Float element type a run trains with. It is the backend's: the caller picks a backend of
+/// that float type, and training rejects a config whose precision is not the backend's.
+#[derive(Config, Debug, Copy, PartialEq)]
+pub enum PrecisionKind {
+    F32,
+    // Half precision: about half the memory of F32, on the backends that support it
+    F16,
+    // bfloat16: the exponent range of F32 with the memory of F16
+    Bf16,
+}
+
+impl PrecisionKind {
+    // The precision of the float element type `E`, `None` for one training does not support
+    // (such as f64)
+    pub fn of<E: 'static>() -> Option<Self> {
+        let id = TypeId::of::<E>();
+        [(TypeId::of::<f32>(), Self::F32), (TypeId::of::<f16>(), Self::F16), (TypeId::of::<bf16>(), Self::Bf16)]
+            .into_iter()
+            .find_map(|(type_id, precision)| (type_id == id).then_some(precision))
+    }
+}
+
+/// Everything [`train`] needs besides the artifact directory and device. Saved as `config.json`
+/// next to the trained model so that inference and evaluation can rebuild it.
 #[derive(Config)]
 pub struct TrainingConfig {
     pub model: ModelConfig,
     pub optimizer: AdamConfig,
+    #[config(default = "DatasetSource::Mnist")]
+    pub dataset: DatasetSource,
+    #[config(default = "OptimizerKind::Adam")]
+    pub optimizer_kind: OptimizerKind,
+    // Must be the float type of the backend training runs on. Losses and metrics are reduced in
+    // that type on the device, then accumulated in f64 on the host.
+    #[config(default = "PrecisionKind::F32")]
+    pub precision: PrecisionKind,
+    #[config(default = "Reduction::Mean")]
+    pub reduction: Reduction,
+    // What the loss of the `model.aux_task` head is scaled by before it is added to the class
+    // loss, in training and validation alike. 0 leaves the head untrained.
+    #[config(default = 1.0)]
+    pub aux_loss_weight: f64,
+    // SGD only: momentum factor, 0 disables momentum
+    #[config(default = 0.0)]
+    pub momentum: f64,
+    // SGD only: use Nesterov momentum (requires momentum > 0)
+    #[config(default = false)]
+    pub nesterov: bool,
+    // Adam only: the decay rates of the first and second moment estimates and the term added
+    // to their denominator, Burn's defaults. `adam_config` builds the optimizer with these, the
+    // betas and epsilon of `optimizer` being ignored.
+    #[config(default = 0.9)]
+    pub adam_beta1: f32,
+    #[config(default = 0.999)]
+    pub adam_beta2: f32,
+    #[config(default = 1e-5)]
+    pub adam_epsilon: f32,
     #[config(default = 5)]
     pub num_epochs: usize,
+    // Training budgets, on top of `num_epochs`: whichever runs out first ends the run. A budget
+    // running out mid-epoch ends that epoch after the batch in flight, which is still validated
+    // and checkpointed, and marked as partial in the history. See `budget::BudgetStop`.
+    pub max_steps: Option<usize>,
+    pub max_duration: Option<Duration>,
     #[config(default = 64)]
     pub batch_size: usize,
+    // Batches whose gradients are summed into each optimizer step, 1 stepping after every batch.
+    // Burn drops the gradients of the batches left over at the end of an epoch. The step counts
+    // of `valid_every_steps` are optimizer steps, those of `max_steps` and `lr_schedule` batches.
+    #[config(default = 1)]
+    pub grad_accumulation: usize,
+    // Threads loading the training batches. 0 loads them on the training thread, as burn's
+    // `DataLoaderBuilder` does.
     #[config(default = 4)]
     pub num_workers: usize,
     #[config(default = 42)]
     pub seed: u64,
+    // Set on the sub-runs of a multi-run feature (see `TrainingConfig::sub_run`), whose `seed`
+    // is derived from the seed of the whole run
+    pub seed_origin: Option<SeedOrigin>,
+    // Draw the initial weights on the CPU from `seed` (see `ModelConfig::init_portable`), so
+    // that runs on different backends start from the same weights
+    #[config(default = false)]
+    pub portable_init: bool,
     #[config(default = 1.0e-4)]
     pub learning_rate: f64,
+    #[config(default = "LrSchedule::Constant")]
+    pub lr_schedule: LrSchedule,
+    // `(prefix, multiplier)` entries: the parameters under a layer of the model summary
+    // (`conv1`) or with that name (`conv1.weight`) train at the scheduled learning rate times the
+    // multiplier, the longest matching prefix winning. E.g. `[["conv1", 0.0]]` freezes the first
+    // convolution, and `[["conv1", 0.1], ["conv2", 0.1], ["linear2", 3.0]]` fine-tunes the
+    // backbone slowly and the head fast. Each group's learning rate is logged as
+    // `Learning Rate <prefix>`. Supported by both `optimizer_kind`s on every backend, in training
+    // and in the learning rate range test: the groups only split the gradients between steps of
+    // the same optimizer, whose per-parameter state is untouched.
+    pub lr_multipliers: Option<Vec<(String, f64)>>,
+    // Keep the decoded dataset in memory and in a cache file to speed up repeated runs
+    #[config(default = false)]
+    pub cache: bool,
+    // Read every item of both splits before training and stop on a wrong item count, label,
+    // image size or pixel value (see `verify_dataset`), rather than train on a corrupted download
+    #[config(default = false)]
+    pub verify_dataset: bool,
+    // JSON list of the training split indices to train on, in that order, such as the
+    // de-duplicated `train_subset.json` of `leakage-check`. Applied by `train` and
+    // `train_with_progress` after `verify_dataset`, the validation split is left whole.
+    pub train_subset: Option<PathBuf>,
+    // The `split_indices.json` of an earlier run, to train and validate on the same items of
+    // the same datasets again. Its fingerprints must match the loaded datasets, and it replaces
+    // `train_subset`, which must be unset.
+    pub split_from: Option<PathBuf>,
+    // Validate on this fraction of the training split, held out of training with the class
+    // ratios of the whole split and drawn from `seed`, instead of on the test split. Applied by
+    // `train` and `train_with_progress`, the held-out items are saved in `split_indices.json`.
+    pub valid_fraction: Option<f64>,
+    // What is printed while training: burn's dashboard, epoch summaries or nothing
+    #[config(default = "Verbosity::Full")]
+    pub verbosity: Verbosity,
+    // Record the train minus validation accuracy of every epoch as its `accuracy_gap` in the
+    // history (and the progress events), and print it: with the epoch lines of
+    // `Verbosity::Summary`, as a table once training is over with `Verbosity::Full`
+    #[config(default = false)]
+    pub log_accuracy_gap: bool,
+    // Steps the training loss shown by the dashboard and the progress events is averaged over,
+    // 1 showing the loss of each batch. The logs, the history and the gradients keep the raw
+    // loss.
+    #[config(default = 1)]
+    pub loss_smoothing_window: usize,
+    // Also write the per-epoch metrics as a W&B-importable `metrics.csv`
+    #[config(default = false)]
+    pub metrics_csv: bool,
+    // Also write burn's learner summary and the model architecture to `summary.txt`, whatever
+    // the verbosity
+    #[config(default = false)]
+    pub summary_file: bool,
+    // Serve the progress of the run as JSON on `GET /status` of this port, on every interface,
+    // for as long as it trains (see `status::StatusServer`). Needs the `status-server` feature.
+    pub status_port: Option<u16>,
+    // Write the metrics of the latest epoch (epoch, loss, accuracy, throughput) to
+    // `metrics.prom` in the Prometheus text format at every epoch end, for the node exporter's
+    // textfile collector, see `PrometheusExporter`
+    #[config(default = false)]
+    pub prometheus_metrics: bool,
+    // Upload the run to this store: each learner checkpoint as it is written, then the rest of
+    // the artifact dir (config, final model, reports) once training is over, see
+    // `store::RunUpload`. An `s3://bucket/prefix` URL names an S3-compatible object store set up
+    // by the `AWS_*` environment variables of `ObjectStoreBackend::s3_from_env`, anything else a
+    // local directory. The commands reading artifact dirs load from the same `s3://` URL, and so
+    // does `resume_from`. Without `save_model` only the config, logs and history are uploaded.
+    pub checkpoint_store: Option<String>,
+    // Export the model to `model_epoch{n}.onnx` in the artifact dir at the end of every n-th
+    // epoch, while training goes on, e.g. for a demo service to reload (see
+    // `onnx::export_onnx`). Needs the `onnx` feature.
+    pub onnx_export_every: Option<usize>,
+    // Save the trained weights (`model`, `model_swa`, their `model_meta.json`) and keep the
+    // learner checkpoints. Off, a run only leaves its config, logs and history behind, to keep
+    // the disk usage of large hyperparameter sweeps down.
+    #[config(default = true)]
+    pub save_model: bool,
+    // Learner checkpoints of the most recent epochs to keep, the older ones deleted after each
+    // save. The checkpoint of the epoch with the best validation loss is kept as well, as are
+    // those `lr_schedule` restarts and `swa` read. `None` keeps the last 2, burn's default.
+    pub keep_last: Option<usize>,
+    // Batches the dataloader workers may prepare ahead of the training loop, 0 for burn's
+    // default queue. Deeper queues keep the GPU fed at the cost of device memory, see
+    // `mnist_dataloader`.
+    #[config(default = 0)]
+    pub prefetch: usize,
+    // Log the global L2 norm of the gradients of every training step, as the "Gradient Norm"
+    // train metric (also collected into the history)
+    #[config(default = false)]
+    pub log_grad_norm: bool,
+    // Check the gradients of every training step before they are applied, and skip the first
+    // step whose gradients are not finite (as a NaN or infinite loss makes them), halting or
+    // rolling back as `nan_action` says, see `NanGuardOptimizer`. Events are logged to
+    // `nan_guard.csv` and recorded in `model_meta.json`. Costs a device sync per step. The
+    // epoch's train loss still averages in the loss of a skipped step.
+    #[config(default = true)]
+    pub nan_guard: bool,
+    #[config(default = "NanAction::Halt")]
+    pub nan_action: NanAction,
+    // With `nan_guard`, also treat a step whose global gradient L2 norm exceeds this as
+    // non-finite, to catch a diverging run before it overflows
+    pub nan_max_grad_norm: Option<f64>,
+    // Mixup regularization of the training batches: images and labels are blended in pairs with
+    // a weight drawn from Beta(alpha, alpha). `None` disables it; validation is never mixed.
+    pub mixup_alpha: Option<f64>,
+    // CutMix regularization of the training batches: a rectangle of each image is replaced by
+    // the same rectangle of another, covering 1 - lambda of it for lambda drawn from
+    // Beta(alpha, alpha), and the labels are mixed by the pasted area. With `mixup_alpha` also
+    // set, each batch is mixed one way or the other on a coin flip. `None` disables it.
+    pub cutmix_alpha: Option<f64>,
+    // Train against per-sample label distributions (e.g. of a teacher model) read from this CSV
+    // with `SoftLabels::load`, row `i` for training item `i`, minimizing the KL divergence to
+    // them: see `Model::forward_soft`. Validation still scores the hard labels.
+    pub soft_labels: Option<PathBuf>,
+    // With `soft_labels`, the share of the hard-label cross-entropy mixed into the loss, in
+    // [0, 1]: 0 trains on the soft labels alone
+    #[config(default = 0.0)]
+    pub hard_label_weight: f64,
+    // Stochastic Weight Averaging: average the weights of the epoch ends of the tail of training
+    // (see `SwaConfig`) into a second model, saved as `model_swa`. With `model.batch_norm`, its
+    // running statistics are then recomputed with one more pass over the training set. `None`
+    // disables it.
+    pub swa: Option<SwaConfig>,
+    // Snapshot ensembling: average the softmax outputs of the model at the end of every
+    // `CosineWarmRestarts` cycle, and report its validation accuracy next to the final model's
+    // in `snapshot_ensemble.json` (see `snapshot::snapshot_ensemble`)
+    #[config(default = false)]
+    pub snapshot_ensemble: bool,
+    // Train on these labels only: both datasets are filtered to them and relabeled 0..k in list
+    // order, and `model.num_classes` is set to k. The model then predicts positions in this list.
+    pub classes: Option<Vec<usize>>,
+    // One-vs-rest: learn "this label or not" as two classes, 1 for the items of this label and 0
+    // for all others, with `model.num_classes` set to 2. Recorded in `model_meta.json`.
+    pub binary_target: Option<usize>,
+    // Progressive resizing: `(first_epoch, size)` entries, sorted by epoch, train from the
+    // 1-based `first_epoch` on with the images averaged down to `size`x`size`, until the next
+    // entry. E.g. `[[1, 14], [4, 28]]` trains three epochs at 14x14 then at full size. Epochs
+    // before the first entry train at full size, and validation always does.
+    pub progressive_resize: Option<Vec<(usize, usize)>>,
+    // Serve the training samples from easy to hard for the first epochs, see `CurriculumConfig`.
+    // The order is saved as `curriculum.json`.
+    pub curriculum: Option<CurriculumConfig>,
+    // Oversample the training samples the model gets most wrong, rescored every few epochs, see
+    // `HardMiningConfig`. The passes are logged to `hard_mining.csv`.
+    pub hard_mining: Option<HardMiningConfig>,
+    // Draw every training epoch with replacement, as many samples of every class give or take
+    // one, so that rare classes are oversampled and batches are about class-balanced, see
+    // `BalancedSampler`. Seeded by `seed` and the epoch.
+    #[config(default = false)]
+    pub balanced_sampling: bool,
+    // Write a Chrome trace (chrome://tracing, Perfetto) of the run to this path: epochs,
+    // validation, checkpoint writes and, for the first `profile_steps` steps of each epoch, the
+    // batch load, forward, backward and optimizer step of every step
+    pub profile: Option<PathBuf>,
+    #[config(default = 20)]
+    pub profile_steps: usize,
+    // Directory of extra labeled images (file names start with the label, e.g. `7_slanted.png`)
+    // scored once per epoch during validation, as the "Holdout Accuracy" valid metric
+    pub holdout_dir: Option<String>,
+    // Preprocess the holdout images like `infer --natural` (photos, scans) instead of only
+    // resizing them
+    #[config(default = false)]
+    pub holdout_natural: bool,
+    // How images that are not 28x28 (the holdout images) are fitted onto the MNIST canvas.
+    // Recorded in `model_meta.json`, for `infer` to read images the same way.
+    #[config(default = "ResizePolicy::Resize { filter: Interpolation::Lanczos }")]
+    pub resize_policy: ResizePolicy,
+    // Contrast steps (histogram equalization, CLAHE, percentile stretch) applied in order to
+    // every training, validation and holdout image, on the raw pixels before they become
+    // tensors. Recorded in `model_meta.json`, for `infer` to replay them.
+    #[config(default = "Vec::new()")]
+    pub preprocess: Vec<PreprocessConfig>,
+    // Predict this many validation samples, drawn from `seed` once for the whole run, at every
+    // epoch end, into `previews/epoch-XX.png` (image, true and predicted class, confidence) and
+    // `previews/predictions.jsonl`
+    pub preview_samples: Option<usize>,
+    // Same as `preview_samples` with the validation samples listed by index, in grid order, to
+    // follow hand-picked (e.g. known hard) samples from run to run
+    pub preview_indices: Option<Vec<usize>>,
+    // Multi-label models only: a label is predicted when the sigmoid of its logit reaches this
+    // probability, for the "F1 Score" metric
+    #[config(default = 0.5)]
+    pub multilabel_threshold: f64,
+    // Also validate every this many training steps, mid-epoch, into `valid_steps.csv`. The end of
+    // every epoch still validates on the whole split. `None` validates at epoch ends only.
+    pub valid_every_steps: Option<usize>,
+    // Score the mid-epoch validations on this many validation items only, the same ones every
+    // time so the curve stays comparable: drawn from `seed` and saved as `valid_subset.json`
+    pub valid_subset_size: Option<usize>,
+    // Recompute the activations of the conv stages during the backward pass instead of keeping
+    // them alive from the forward pass, trading compute for memory. Not supported yet: the only
+    // way burn offers is the `BalancedCheckpointing` autodiff strategy, whose backward pass
+    // panics on any chain of two operations in burn 0.13.0, so `validate` rejects `true`.
+    #[config(default = false)]
+    pub activation_checkpointing: bool,
+    // Replace `batch_size` with the one `find_batch_size` picks for the device, before the
+    // dataloaders are built. The `config.json` of the run records the size it picked.
+    #[config(default = false)]
+    pub auto_batch_size: bool,
+    // Largest batch size `auto_batch_size` tries
+    #[config(default = 4096)]
+    pub max_batch_size: usize,
+    // Record the training set indices of every batch into `batch_order.json`, step by step, to
+    // audit which samples each step saw. Holds every index of every epoch in memory until the
+    // run ends, so it is off by default.
+    #[config(default = false)]
+    pub export_batch_order: bool,
+    // Debugging aid: append statistics of the optimizer state of every parameter (Adam moment
+    // estimates, SGD momentum norms) to `optimizer_stats.jsonl` at every epoch checkpoint
+    #[config(default = false)]
+    pub export_optimizer_stats: bool,
+    // Debugging aid: measure the ratio of the norm of each step's update to the norm of the
+    // weights, per layer, and append its mean, max and last value over every epoch to
+    // `update_ratios.jsonl`. Ratios far above 1e-3 hint at a too high learning rate, far below
+    // at a too low one.
+    #[config(default = false)]
+    pub log_update_ratios: bool,
+    // Warm start: before training, copy the tensors of this safetensors file into the fresh
+    // model wherever their name and shape match a parameter, see `load_partial_weights`
+    pub pretrained_weights: Option<PathBuf>,
+    // Continue from the artifact dir of an earlier run, or the `s3://bucket/prefix` its
+    // `checkpoint_store` uploaded it to: start from its final weights, and from its latest
+    // optimizer checkpoint unless `reset_optimizer` is set. Everything else comes from
+    // this config: the run starts over at epoch 1 with a fresh `learning_rate` schedule, so
+    // lowering `learning_rate` fine-tunes the resumed weights.
+    //
+    // That includes `batch_size`, which may differ from the resumed run's: the dataloaders and
+    // the steps per epoch of the schedule follow the new one, and the optimizer state, kept per
+    // parameter, does not depend on it. The learning rate is not rescaled, though. A larger
+    // batch averages out more gradient noise, and SGD usually wants the learning rate scaled
+    // with the batch size (linear scaling, with a warmup) to keep the same progress per epoch;
+    // Adam is less sensitive, square-root scaling being the usual guess. With `Reduction::Sum`
+    // the gradients themselves grow with the batch, which SGD sees as a larger step. With
+    // `ModelConfig::batch_norm`, the running mean and variance are resumed too, tracked over
+    // batches of the old size: each new batch moves them by `bn_momentum`, so they follow the
+    // new batch size within a few dozen steps at the default 0.1, noisier with smaller batches.
+    pub resume_from: Option<String>,
+    // Start the resumed run with a fresh optimizer state. The kept state is Adam's moment
+    // estimates or SGD's momentum buffers: both accumulate raw gradients, not updates, so they
+    // stay valid under a new learning rate, which scales the next steps right away. Resetting
+    // drops the momentum, and restarts Adam's bias correction as on a first step.
+    #[config(default = false)]
+    pub reset_optimizer: bool,
+    // Fine-tune low-rank adapters of the layers instead of the weights, which stay frozen: see
+    // `AdapterConfig`. Needs `resume_from` or `pretrained_weights` to adapt. `model.mpk` holds
+    // the untouched base weights, `adapter.mpk` the adapters, for `infer --adapter`.
+    pub adapters: Option<AdapterConfig>,
+}
+
+// Written into the artifact dir when `TrainingConfig::summary_file` is set
+pub const SUMMARY_FILE: &str = "summary.txt";
+
+// Metrics logged by the learner, and collected into the run history
+const TRACKED_METRICS: [&str; 7] =
+    ["Accuracy", "F1 Score", "Loss", "Gradient Norm", "Holdout Accuracy", "Memory", "Peak Memory"];
+
+/// A single invalid setting found by [`TrainingConfig::validate`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigError {
+    /// Name of the offending field, e.g. `model.dropout`.
+    pub field: String,
+    /// The rejected value, formatted for display.
+    pub value: String,
+    /// Human readable description of what would have been accepted.
+    pub expected: String,
 }
 
-fn create_artifact_dir(artifact_dir: &str){
+impl ConfigError {
+    pub(crate) fn new(field: &str, value: impl std::fmt::Display, expected: &str) -> Self {
+        Self {
+            field: field.to_string(),
+            value: value.to_string(),
+            expected: expected.to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "`{}` = {} is invalid, expected {}", self.field, self.value, self.expected)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl TrainingConfig {
+    /// This config for the sub-run `label` of a multi-run feature, e.g. `fold-3` of a k-fold or
+    /// `trial-12` of a sweep: its `seed`, and with it the initialization, shuffles, splits and
+    /// augmentations, is [`derive_seed`] of this seed and `label`, recorded in `seed_origin`.
+    /// Deriving from a sub-run derives from the seed of the whole run again.
+    pub fn sub_run(&self, label: &str) -> Self {
+        let root = self.seed_origin.as_ref().map_or(self.seed, |origin| origin.root);
+        let mut config = self.clone();
+        config.seed = derive_seed(root, label);
+        config.seed_origin = Some(SeedOrigin { root, label: label.to_string() });
+        config
+    }
+
+    // Checks every field (and the combinations of fields) that would otherwise make burn panic
+    // or train garbage. All violations are collected instead of stopping at the first one.
+    pub fn validate(&self) -> Result<(), Vec<ConfigError>> {
+        // Every `DatasetSource` yields MNIST-style labels, one per image
+        let mut errors = self.validate_for(MNIST_NUM_CLASSES, TaskKind::SingleLabel).err().unwrap_or_default();
+        errors.extend(validate_source(&self.dataset, "dataset"));
+        if let Some(fraction) = self.valid_fraction {
+            if !(fraction > 0.0 && fraction < 1.0) {
+                errors.push(ConfigError::new("valid_fraction", fraction, "a value in (0, 1)"));
+            }
+            // Both decide which items are validated on
+            let conflicts = [("split_from", self.split_from.is_some()), ("train_subset", self.train_subset.is_some())];
+            for (field, is_set) in conflicts {
+                if is_set {
+                    errors.push(ConfigError::new(field, "set", "unset when `valid_fraction` is set"));
+                }
+            }
+        }
+        // Cross-field: every `DatasetSource` yields 28x28 images
+        if let HeadInput::AdaptiveAvgPool { size } = self.model.head_input {
+            if self.model.global_pool == GlobalPool::None && min_image_side(&self.model) > 28 {
+                errors.push(ConfigError::new(
+                    "model.head_input.size",
+                    size,
+                    &format!("<= {} (the side of the conv features of the 28x28 dataset images)", 28 - (MIN_IMAGE_SIZE - 1)),
+                ));
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Checks that `precision` is the float type of the backend `B`, the one precision training
+    /// on `B` supports.
+    pub fn check_precision<B: Backend>(&self) -> Result<(), ConfigError> {
+        let supported = PrecisionKind::of::<B::FloatElem>();
+        if supported == Some(self.precision) {
+            return Ok(());
+        }
+        let expected = match supported {
+            Some(supported) => format!("{supported:?}, the only precision of the backend"),
+            None => format!("the precision of the backend, whose {} floats training does not support", type_name::<B::FloatElem>()),
+        };
+        Err(ConfigError::new("precision", format!("{:?}", self.precision), &expected))
+    }
+
+    /// [`check_precision`](Self::check_precision), along with the checks of what else the model
+    /// needs of the backend `B`: the candle backend, the one of burn to train in F16 on, has no
+    /// adaptive average pooling.
+    pub fn check_backend<B: Backend>(&self) -> Result<(), Vec<ConfigError>> {
+        let mut errors: Vec<ConfigError> = self.check_precision::<B>().err().into_iter().collect();
+        if is_candle::<B>() && self.model.global_pool == GlobalPool::None {
+            if let HeadInput::AdaptiveAvgPool { .. } = self.model.head_input {
+                errors.push(ConfigError::new(
+                    "model.head_input",
+                    "AdaptiveAvgPool",
+                    "Flatten or a `model.global_pool` on the candle backend, which has no adaptive average pooling",
+                ));
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    // Same as `validate`, for training a `task` model on a dataset with `num_classes` labels
+    fn validate_for(&self, num_classes: usize, task: TaskKind) -> Result<(), Vec<ConfigError>> {
+        let mut errors = Vec::new();
+
+        if self.model.task != task {
+            let expected = match task {
+                TaskKind::SingleLabel => "SingleLabel (multi-label models train with `train_multilabel_on`)",
+                TaskKind::MultiLabel => "MultiLabel (`train_multilabel_on` trains multi-label models)",
+            };
+            errors.push(ConfigError::new("model.task", format!("{:?}", self.model.task), expected));
+        }
+        if task == TaskKind::MultiLabel {
+            // Built around single labels: mixed one-hot targets, accuracy scores, label subsets
+            let unsupported = [
+                ("mixup_alpha", self.mixup_alpha.is_some()),
+                ("cutmix_alpha", self.cutmix_alpha.is_some()),
+                ("soft_labels", self.soft_labels.is_some()),
+                ("swa", self.swa.is_some()),
+                ("snapshot_ensemble", self.snapshot_ensemble),
+                ("classes", self.classes.is_some()),
+                ("binary_target", self.binary_target.is_some()),
+                ("curriculum", self.curriculum.is_some()),
+                ("hard_mining", self.hard_mining.is_some()),
+                ("balanced_sampling", self.balanced_sampling),
+                ("progressive_resize", self.progressive_resize.is_some()),
+                ("holdout_dir", self.holdout_dir.is_some()),
+                ("preprocess", !self.preprocess.is_empty()),
+                ("adapters", self.adapters.is_some()),
+                ("preview_samples", self.preview_samples.is_some()),
+                ("preview_indices", self.preview_indices.is_some()),
+                ("valid_every_steps", self.valid_every_steps.is_some()),
+                ("auto_batch_size", self.auto_batch_size),
+                ("export_batch_order", self.export_batch_order),
+                ("log_accuracy_gap", self.log_accuracy_gap),
+                ("model.aux_task", self.model.aux_task.is_some()),
+            ];
+            for (field, is_set) in unsupported {
+                if is_set {
+                    errors.push(ConfigError::new(field, "set", "unset for multi-label models"));
+                }
+            }
+        }
+        if !(self.multilabel_threshold > 0.0 && self.multilabel_threshold < 1.0) {
+            errors.push(ConfigError::new(
+                "multilabel_threshold",
+                self.multilabel_threshold,
+                "a value in (0, 1)",
+            ));
+        }
+
+        if self.num_epochs == 0 {
+            errors.push(ConfigError::new("num_epochs", self.num_epochs, ">= 1"));
+        }
+        if self.batch_size == 0 {
+            errors.push(ConfigError::new("batch_size", self.batch_size, ">= 1"));
+        }
+        if self.grad_accumulation == 0 {
+            errors.push(ConfigError::new("grad_accumulation", self.grad_accumulation, ">= 1"));
+        }
+        if !(0.0..=1.0).contains(&self.hard_label_weight) {
+            errors.push(ConfigError::new("hard_label_weight", self.hard_label_weight, "a value in [0, 1]"));
+        }
+        if self.soft_labels.is_some() {
+            // The rows are matched to the items by their index in the training set, which these
+            // reorder or re-index, and mixing blends hard labels
+            let conflicts = [
+                ("train_subset", self.train_subset.is_some()),
+                ("curriculum", self.curriculum.is_some()),
+                ("hard_mining", self.hard_mining.is_some()),
+                ("balanced_sampling", self.balanced_sampling),
+                ("mixup_alpha", self.mixup_alpha.is_some()),
+                ("cutmix_alpha", self.cutmix_alpha.is_some()),
+            ];
+            for (field, is_set) in conflicts {
+                if is_set {
+                    errors.push(ConfigError::new(field, "set", "unset when `soft_labels` is set"));
+                }
+            }
+        }
+        if !(self.aux_loss_weight.is_finite() && self.aux_loss_weight >= 0.0) {
+            errors.push(ConfigError::new("aux_loss_weight", self.aux_loss_weight, "a finite value >= 0"));
+        }
+        if self.model.aux_task.is_some() {
+            // The auxiliary targets are derived from the class index of the image: mixed
+            // images have none, and remapped labels are no longer digits
+            let conflicts = [
+                ("mixup_alpha", self.mixup_alpha.is_some()),
+                ("cutmix_alpha", self.cutmix_alpha.is_some()),
+                ("soft_labels", self.soft_labels.is_some()),
+                ("classes", self.classes.is_some()),
+                ("binary_target", self.binary_target.is_some()),
+            ];
+            for (field, is_set) in conflicts {
+                if is_set {
+                    errors.push(ConfigError::new(field, "set", "unset when `model.aux_task` is set"));
+                }
+            }
+        }
+        if self.split_from.is_some() && self.train_subset.is_some() {
+            errors.push(ConfigError::new("train_subset", "set", "unset when `split_from` is set"));
+        }
+        if let Some(url) = &self.checkpoint_store {
+            if let Err(err) = store::check_url(url) {
+                errors.push(ConfigError::new("checkpoint_store", url, &format!("an s3://bucket/prefix URL or a directory ({err})")));
+            }
+        }
+        if self.onnx_export_every == Some(0) {
+            errors.push(ConfigError::new("onnx_export_every", 0, ">= 1"));
+        }
+        if self.onnx_export_every.is_some() && !cfg!(feature = "onnx") {
+            errors.push(ConfigError::new("onnx_export_every", "set", "unset (built without the `onnx` feature)"));
+        }
+        if self.status_port.is_some() && !cfg!(feature = "status-server") {
+            errors.push(ConfigError::new("status_port", "set", "unset (built without the `status-server` feature)"));
+        }
+        if self.keep_last == Some(0) {
+            // Resuming and the end-of-run weights read the latest checkpoint
+            errors.push(ConfigError::new("keep_last", 0, ">= 1 or unset"));
+        }
+        if self.max_steps == Some(0) {
+            errors.push(ConfigError::new("max_steps", 0, ">= 1 or unset"));
+        }
+        if self.max_duration == Some(Duration::ZERO) {
+            errors.push(ConfigError::new("max_duration", "0s", "> 0 or unset"));
+        }
+        if let Some(max) = self.nan_max_grad_norm.filter(|max| !(max.is_finite() && *max > 0.0)) {
+            errors.push(ConfigError::new("nan_max_grad_norm", max, "a finite value > 0 or unset"));
+        }
+        if self.loss_smoothing_window == 0 {
+            errors.push(ConfigError::new("loss_smoothing_window", self.loss_smoothing_window, ">= 1"));
+        }
+        if !(self.learning_rate.is_finite() && self.learning_rate > 0.0) {
+            errors.push(ConfigError::new("learning_rate", self.learning_rate, "a finite value > 0"));
+        }
+        for (position, (prefix, multiplier)) in self.lr_multipliers.iter().flatten().enumerate() {
+            if !(multiplier.is_finite() && *multiplier >= 0.0) {
+                errors.push(ConfigError::new(
+                    &format!("lr_multipliers[{position}].multiplier"),
+                    multiplier,
+                    "a finite value >= 0",
+                ));
+            }
+            if self.lr_multipliers.iter().flatten().take(position).any(|(previous, _)| previous == prefix) {
+                errors.push(ConfigError::new(
+                    &format!("lr_multipliers[{position}].prefix"),
+                    prefix,
+                    "a prefix no earlier entry has",
+                ));
+            }
+        }
+
+        if self.model.hidden_size == 0 {
+            errors.push(ConfigError::new("model.hidden_size", self.model.hidden_size, ">= 1"));
+        }
+        if !(0.0..1.0).contains(&self.model.dropout) {
+            errors.push(ConfigError::new("model.dropout", self.model.dropout, "a value in [0, 1)"));
+        }
+        if !(0.0..=1.0).contains(&self.model.bn_momentum) {
+            errors.push(ConfigError::new("model.bn_momentum", self.model.bn_momentum, "a value in [0, 1]"));
+        }
+        if !(self.model.bn_epsilon.is_finite() && self.model.bn_epsilon > 0.0) {
+            errors.push(ConfigError::new("model.bn_epsilon", self.model.bn_epsilon, "a finite value > 0"));
+        }
+        match (self.model.global_pool, self.model.head_input) {
+            (GlobalPool::None, HeadInput::AdaptiveAvgPool { size: 0 }) => {
+                errors.push(ConfigError::new("model.head_input.size", 0, ">= 1"));
+            }
+            (GlobalPool::None, HeadInput::Flatten) if self.progressive_resize.is_some() => {
+                // The flattened head only takes full-size images
+                errors.push(ConfigError::new("progressive_resize", "set", "unset when `model.head_input` is Flatten"));
+            }
+            (GlobalPool::Avg | GlobalPool::Max, HeadInput::Flatten) => errors.push(ConfigError::new(
+                "model.head_input",
+                "Flatten",
+                "AdaptiveAvgPool when `model.global_pool` is set (it replaces the head input)",
+            )),
+            _ => {}
+        }
+        if let Some(classes) = &self.classes {
+            // `model.num_classes` is overridden with the subset size, no need to check it
+            if classes.is_empty() {
+                errors.push(ConfigError::new("classes", "[]", "at least one class"));
+            }
+            for (position, &class) in classes.iter().enumerate() {
+                if class >= num_classes {
+                    errors.push(ConfigError::new(
+                        &format!("classes[{position}]"),
+                        class,
+                        &format!("< {num_classes} (the number of labels in the dataset)"),
+                    ));
+                } else if classes[..position].contains(&class) {
+                    errors.push(ConfigError::new(&format!("classes[{position}]"), class, "no duplicate"));
+                }
+            }
+        } else if self.binary_target.is_some() {
+            // `model.num_classes` is overridden with 2, no need to check it
+        } else if self.model.num_classes < num_classes {
+            // Cross-field: the dataset labels must fit in the classifier head
+            errors.push(ConfigError::new(
+                "model.num_classes",
+                self.model.num_classes,
+                &format!(">= {num_classes} (the number of labels in the dataset)"),
+            ));
+        }
+
+        if let Some(target) = self.binary_target {
+            if self.classes.is_some() {
+                errors.push(ConfigError::new("binary_target", target, "unset when `classes` is set"));
+            } else if target >= num_classes {
+                errors.push(ConfigError::new(
+                    "binary_target",
+                    target,
+                    &format!("< {num_classes} (the number of labels in the dataset)"),
+                ));
+            }
+        }
+
+        if let LrSchedule::CosineWarmRestarts { t_initial, t_mult, min_lr } = self.lr_schedule {
+            if t_initial == 0 {
+                errors.push(ConfigError::new("lr_schedule.t_initial", t_initial, ">= 1"));
+            }
+            if t_mult == 0 {
+                errors.push(ConfigError::new("lr_schedule.t_mult", t_mult, ">= 1"));
+            }
+            if !(min_lr >= 0.0 && min_lr < self.learning_rate) {
+                errors.push(ConfigError::new(
+                    "lr_schedule.min_lr",
+                    min_lr,
+                    "a value in [0, learning_rate)",
+                ));
+            }
+        }
+
+        if self.valid_every_steps == Some(0) {
+            errors.push(ConfigError::new("valid_every_steps", 0, ">= 1"));
+        }
+        if let Some(size) = self.valid_subset_size {
+            if self.valid_every_steps.is_none() {
+                errors.push(ConfigError::new("valid_subset_size", size, "unset unless `valid_every_steps` is set"));
+            } else if size == 0 {
+                errors.push(ConfigError::new("valid_subset_size", size, ">= 1"));
+            }
+        }
+
+        if self.resume_from.is_some() && self.pretrained_weights.is_some() {
+            errors.push(ConfigError::new("pretrained_weights", "set", "unset when `resume_from` is set"));
+        }
+
+        if let Some(adapters) = &self.adapters {
+            if self.resume_from.is_none() && self.pretrained_weights.is_none() {
+                errors.push(ConfigError::new("adapters", "set", "unset unless `resume_from` or `pretrained_weights` is set"));
+            }
+            // Both average the checkpoints of the whole model, not of its adapters
+            if self.swa.is_some() {
+                errors.push(ConfigError::new("swa", "set", "unset when `adapters` is set"));
+            }
+            if self.snapshot_ensemble {
+                errors.push(ConfigError::new("snapshot_ensemble", true, "false when `adapters` is set"));
+            }
+            if adapters.rank == 0 {
+                errors.push(ConfigError::new("adapters.rank", 0, ">= 1"));
+            }
+            if !(adapters.alpha.is_finite() && adapters.alpha > 0.0) {
+                errors.push(ConfigError::new("adapters.alpha", adapters.alpha, "a finite value > 0"));
+            }
+            if adapters.target_modules.is_empty() {
+                errors.push(ConfigError::new("adapters.target_modules", "[]", "at least one layer"));
+            }
+            for (position, target) in adapters.target_modules.iter().enumerate() {
+                let field = format!("adapters.target_modules[{position}]");
+                if !ADAPTER_TARGETS.contains(&target.as_str()) {
+                    errors.push(ConfigError::new(&field, target, &format!("one of {ADAPTER_TARGETS:?}")));
+                } else if adapters.target_modules[..position].contains(target) {
+                    errors.push(ConfigError::new(&field, target, "a layer not listed before"));
+                }
+            }
+        }
+
+        if self.preview_samples == Some(0) {
+            errors.push(ConfigError::new("preview_samples", 0, ">= 1"));
+        }
+        if let Some(indices) = &self.preview_indices {
+            if self.preview_samples.is_some() {
+                errors.push(ConfigError::new("preview_indices", "set", "unset when `preview_samples` is set"));
+            } else if indices.is_empty() {
+                errors.push(ConfigError::new("preview_indices", "[]", "at least one index"));
+            }
+        }
+
+        if self.auto_batch_size && self.max_batch_size == 0 {
+            errors.push(ConfigError::new("max_batch_size", 0, ">= 1"));
+        }
+        if self.activation_checkpointing {
+            errors.push(ConfigError::new(
+                "activation_checkpointing",
+                true,
+                "false (burn 0.13.0 cannot backpropagate through checkpointed activations)",
+            ));
+        }
+
+        let alphas = [("mixup_alpha", self.mixup_alpha), ("cutmix_alpha", self.cutmix_alpha)];
+        for (field, alpha) in alphas {
+            if let Some(alpha) = alpha.filter(|alpha| !(alpha.is_finite() && *alpha > 0.0)) {
+                errors.push(ConfigError::new(field, alpha, "a finite value > 0"));
+            }
+        }
+        // Cross-field: images are mixed with the others of their batch, a single image only with
+        // itself, and accumulating the gradients of several batches does not change that
+        if self.batch_size == 1 && alphas.iter().any(|(_, alpha)| alpha.is_some()) {
+            let expected = match self.grad_accumulation {
+                1 => ">= 2 when `mixup_alpha` or `cutmix_alpha` is set".to_string(),
+                accumulation => format!(
+                    ">= 2 when `mixup_alpha` or `cutmix_alpha` is set, even with a `grad_accumulation` of {accumulation}"
+                ),
+            };
+            errors.push(ConfigError::new("batch_size", 1, &expected));
+        }
+
+        if let Some(swa) = &self.swa {
+            if swa.start_epoch == 0 || swa.start_epoch > self.num_epochs {
+                errors.push(ConfigError::new(
+                    "swa.start_epoch",
+                    swa.start_epoch,
+                    &format!("a value in [1, {}] (num_epochs)", self.num_epochs),
+                ));
+            }
+            if swa.frequency == 0 {
+                errors.push(ConfigError::new("swa.frequency", swa.frequency, ">= 1"));
+            }
+        }
+
+        if self.snapshot_ensemble && self.lr_schedule.restart_epochs(self.num_epochs).is_empty() {
+            errors.push(ConfigError::new(
+                "snapshot_ensemble",
+                true,
+                "an lr_schedule of CosineWarmRestarts that restarts within num_epochs",
+            ));
+        }
+
+        if let Some(curriculum) = &self.curriculum {
+            if curriculum.num_epochs == 0 || curriculum.num_epochs > self.num_epochs {
+                errors.push(ConfigError::new(
+                    "curriculum.num_epochs",
+                    curriculum.num_epochs,
+                    &format!("a value in [1, {}] (num_epochs)", self.num_epochs),
+                ));
+            }
+        }
+
+        if let Some(mining) = &self.hard_mining {
+            if mining.interval_epochs == 0 {
+                errors.push(ConfigError::new("hard_mining.interval_epochs", mining.interval_epochs, ">= 1"));
+            }
+            if !(mining.top_fraction > 0.0 && mining.top_fraction <= 1.0) {
+                errors.push(ConfigError::new("hard_mining.top_fraction", mining.top_fraction, "a value in (0, 1]"));
+            }
+            if !(mining.oversample_factor.is_finite() && mining.oversample_factor >= 1.0) {
+                errors.push(ConfigError::new("hard_mining.oversample_factor", mining.oversample_factor, "a finite value >= 1"));
+            }
+            // Both decide which samples each epoch serves
+            if self.curriculum.is_some() {
+                errors.push(ConfigError::new("hard_mining", "set", "unset when `curriculum` is set"));
+            }
+        }
+        if self.balanced_sampling {
+            // Each decides which samples each epoch serves
+            let conflicts = [("curriculum", self.curriculum.is_some()), ("hard_mining", self.hard_mining.is_some())];
+            for (field, is_set) in conflicts {
+                if is_set {
+                    errors.push(ConfigError::new(field, "set", "unset when `balanced_sampling` is set"));
+                }
+            }
+        }
+
+        for (position, step) in self.preprocess.iter().enumerate() {
+            match *step {
+                PreprocessConfig::Equalize => {}
+                PreprocessConfig::Clahe { tile_size, clip_limit } => {
+                    if tile_size == 0 {
+                        errors.push(ConfigError::new(&format!("preprocess[{position}].tile_size"), tile_size, ">= 1"));
+                    }
+                    // 1 clips every bin to the mean count, which leaves the tile as it is
+                    if !(clip_limit.is_finite() && clip_limit >= 1.0) {
+                        errors.push(ConfigError::new(&format!("preprocess[{position}].clip_limit"), clip_limit, "a finite value >= 1"));
+                    }
+                }
+                PreprocessConfig::Stretch { low_percentile, high_percentile } => {
+                    if !(0.0..=100.0).contains(&low_percentile) {
+                        errors.push(ConfigError::new(
+                            &format!("preprocess[{position}].low_percentile"),
+                            low_percentile,
+                            "a value in [0, 100]",
+                        ));
+                    }
+                    if !(high_percentile <= 100.0 && high_percentile > low_percentile) {
+                        errors.push(ConfigError::new(
+                            &format!("preprocess[{position}].high_percentile"),
+                            high_percentile,
+                            "a value in (low_percentile, 100]",
+                        ));
+                    }
+                }
+            }
+        }
+
+        if let Some(schedule) = &self.progressive_resize {
+            if schedule.is_empty() {
+                errors.push(ConfigError::new("progressive_resize", "[]", "at least one entry"));
+            }
+            for (position, &(first_epoch, size)) in schedule.iter().enumerate() {
+                let previous = position.checked_sub(1).map_or(0, |previous| schedule[previous].0);
+                if first_epoch <= previous || first_epoch > self.num_epochs {
+                    errors.push(ConfigError::new(
+                        &format!("progressive_resize[{position}].first_epoch"),
+                        first_epoch,
+                        &format!("a value in [{}, {}] (after the previous entry, num_epochs)", previous + 1, self.num_epochs),
+                    ));
+                }
+                let min_side = min_image_side(&self.model);
+                if size < min_side {
+                    errors.push(ConfigError::new(
+                        &format!("progressive_resize[{position}].size"),
+                        size,
+                        &format!(">= {min_side}, the smallest image the model takes"),
+                    ));
+                }
+            }
+        }
+
+        if !(0.0..1.0).contains(&self.momentum) {
+            errors.push(ConfigError::new("momentum", self.momentum, "a value in [0, 1)"));
+        }
+        if self.nesterov && self.momentum == 0.0 {
+            errors.push(ConfigError::new(
+                "nesterov",
+                self.nesterov,
+                "false when momentum is 0 (Nesterov needs momentum)",
+            ));
+        }
+        for (field, beta) in [("adam_beta1", self.adam_beta1), ("adam_beta2", self.adam_beta2)] {
+            if !(0.0..1.0).contains(&beta) {
+                errors.push(ConfigError::new(field, beta, "a value in [0, 1)"));
+            }
+        }
+        if !(self.adam_epsilon.is_finite() && self.adam_epsilon > 0.0) {
+            errors.push(ConfigError::new("adam_epsilon", self.adam_epsilon, "a finite value > 0"));
+        }
+        // Cross-field: the squares of small gradients underflow to 0 in f16, leaving epsilon alone
+        // to divide their Adam steps by, which smaller values blow up
+        let adam = self.optimizer_kind == OptimizerKind::Adam;
+        if self.precision == PrecisionKind::F16 && adam && self.adam_epsilon < F16_MIN_ADAM_EPSILON {
+            errors.push(ConfigError::new(
+                "adam_epsilon",
+                self.adam_epsilon,
+                &format!(">= {F16_MIN_ADAM_EPSILON} when precision is F16"),
+            ));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    // The label the model learns for a dataset label, once `classes` or `binary_target` have
+    // remapped it. `None` for the labels `classes` leaves out.
+    pub fn relabel(&self, label: usize) -> Option<usize> {
+        match (&self.classes, self.binary_target) {
+            (Some(classes), _) => classes.iter().position(|&class| class == label),
+            (None, Some(target)) => Some((label == target) as usize),
+            (None, None) => Some(label),
+        }
+    }
+
+    // The `reduction` and `aux_loss_weight` of the steps
+    pub fn loss_settings(&self) -> LossSettings {
+        LossSettings { reduction: self.reduction, aux_loss_weight: self.aux_loss_weight }
+    }
+
+    // The SGD optimizer config described by `momentum` and `nesterov`
+    pub fn sgd_config(&self) -> SgdConfig {
+        let momentum = (self.momentum > 0.0).then(|| {
+            MomentumConfig::new()
+                .with_momentum(self.momentum)
+                .with_dampening(0.0)
+                .with_nesterov(self.nesterov)
+        });
+
+        SgdConfig::new().with_momentum(momentum)
+    }
+
+    // The Adam config of the `adam_*` betas and epsilon, with the weight decay and gradient
+    // clipping of `optimizer`
+    pub fn adam_config(&self) -> AdamConfig {
+        self.optimizer
+            .clone()
+            .with_beta_1(self.adam_beta1)
+            .with_beta_2(self.adam_beta2)
+            .with_epsilon(self.adam_epsilon)
+    }
+
+    /// Loads the config JSON at `path`, then overrides its fields from the environment variables
+    /// of [`ENV_OVERRIDES`] that are set, e.g. `DL_NUM_EPOCHS=1`. Every override must parse and
+    /// pass the [`TrainingConfig::validate`] checks of its field; the errors name the variable.
+    pub fn load_with_env_overrides(path: impl AsRef<std::path::Path>) -> Result<Self, EnvConfigError> {
+        let mut config = Self::load(path).map_err(EnvConfigError::Load)?;
+        let mut errors = Vec::new();
+        let mut applied = Vec::new();
+        for &(var, field, apply) in &ENV_OVERRIDES {
+            let value = match std::env::var(var) {
+                Ok(value) => value,
+                Err(std::env::VarError::NotPresent) => continue,
+                Err(std::env::VarError::NotUnicode(value)) => {
+                    errors.push(ConfigError::new(var, value.to_string_lossy(), "valid UTF-8"));
+                    continue;
+                }
+            };
+            match apply(&mut config, value.trim()) {
+                Ok(()) => applied.push((var, field, value)),
+                Err(expected) => errors.push(ConfigError::new(var, &value, expected)),
+            }
+        }
+        // Only the overridden fields: the file itself is validated when training starts
+        let invalid = config.validate().err().unwrap_or_default();
+        for (var, field, value) in applied {
+            errors.extend(
+                invalid.iter().filter(|error| error.field == field).map(|error| ConfigError::new(var, &value, &error.expected)),
+            );
+        }
+        match errors.is_empty() {
+            true => Ok(config),
+            false => Err(EnvConfigError::InvalidOverrides(errors)),
+        }
+    }
+
+    /// [`load_with_env_overrides`](Self::load_with_env_overrides) of the config JSON at `path`,
+    /// or without one the built-in config the binary trains with: a 512-unit model with Adam and
+    /// every other default.
+    pub fn load_or_builtin(path: Option<&str>) -> Result<Self, EnvConfigError> {
+        match path {
+            Some(path) => Self::load_with_env_overrides(path),
+            None => Ok(Self::new(ModelConfig::new(10, 512), AdamConfig::new())),
+        }
+    }
+}
+
+// Sets a config field from the value of its environment variable, or returns what it expected
+type ApplyOverride = fn(&mut TrainingConfig, &str) -> Result<(), &'static str>;
+
+/// The environment variables [`TrainingConfig::load_with_env_overrides`] reads, with the config
+/// field each one sets.
+pub const ENV_OVERRIDES: [(&str, &str, ApplyOverride); 6] = [
+    ("DL_NUM_EPOCHS", "num_epochs", |config, value| {
+        config.num_epochs = value.parse().map_err(|_| "an integer")?;
+        Ok(())
+    }),
+    ("DL_BATCH_SIZE", "batch_size", |config, value| {
+        config.batch_size = value.parse().map_err(|_| "an integer")?;
+        Ok(())
+    }),
+    ("DL_LR", "learning_rate", |config, value| {
+        config.learning_rate = value.parse().map_err(|_| "a number")?;
+        Ok(())
+    }),
+    ("DL_NUM_WORKERS", "num_workers", |config, value| {
+        config.num_workers = value.parse().map_err(|_| "an integer")?;
+        Ok(())
+    }),
+    ("DL_SEED", "seed", |config, value| {
+        config.seed = value.parse().map_err(|_| "an integer")?;
+        Ok(())
+    }),
+    // The wall-clock budget of a cluster job, set from its job script
+    ("DL_MAX_TRAIN_SECONDS", "max_duration", |config, value| {
+        config.max_duration = Some(Duration::from_secs(value.parse().map_err(|_| "an integer number of seconds")?));
+        Ok(())
+    }),
+];
+
+/// Why [`TrainingConfig::load_with_env_overrides`] could not load a config.
+#[derive(Debug)]
+pub enum EnvConfigError {
+    /// The JSON file could not be read or parsed.
+    Load(burn::config::ConfigError),
+    /// Environment overrides that did not parse or failed validation, by variable name.
+    InvalidOverrides(Vec<ConfigError>),
+}
+
+impl std::fmt::Display for EnvConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EnvConfigError::Load(err) => write!(f, "could not load the training config: {err}"),
+            EnvConfigError::InvalidOverrides(errors) => {
+                write!(f, "invalid environment overrides:")?;
+                for error in errors {
+                    write!(f, "\n  {error}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for EnvConfigError {}
+
+/// Why [`train`] could not complete.
+#[derive(Debug)]
+pub enum TrainError {
+    /// The config failed [`TrainingConfig::validate`]; nothing was written to disk.
+    InvalidConfig(Vec<ConfigError>),
+    /// The artifact directory or one of its files could not be written.
+    Io(std::io::Error),
+    /// The trained model could not be saved.
+    Record(RecorderError),
+    /// The learner's metric logs could not be read back into the run history.
+    Logs(String),
+    /// The pretrained model scoring the curriculum could not be loaded.
+    Curriculum(LoadError),
+    /// The holdout images could not be read.
+    Holdout(HoldoutError),
+    /// The trained model to start from (see [`prune`](crate::prune::prune)), the
+    /// `pretrained_weights` or the `resume_from` run could not be loaded.
+    Load(LoadError),
+    /// `verify_dataset` found an inconsistent item in the dataset.
+    Dataset(DatasetError),
+    /// Another run is writing the artifact directory, or its lock could not be taken.
+    Locked(LockError),
+    /// The `soft_labels` file could not be read, or does not match the training set.
+    SoftLabels(SoftLabelsError),
+    /// `nan_guard` halted training on a step with non-finite gradients.
+    NonFinite(NanEvent),
+    /// The `split_from` file could not be read, or does not match the datasets.
+    Split(SplitFileError),
+    /// The run could not be uploaded to the `checkpoint_store`.
+    Store(StoreError),
+}
+
+impl std::fmt::Display for TrainError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TrainError::InvalidConfig(errors) => {
+                write!(f, "invalid training config:")?;
+                for error in errors {
+                    write!(f, "\n  {error}")?;
+                }
+                Ok(())
+            }
+            TrainError::Io(err) => write!(f, "could not write the training artifacts: {err}"),
+            TrainError::Record(err) => write!(f, "could not save the trained model: {err}"),
+            TrainError::Logs(err) => write!(f, "could not read the training logs: {err}"),
+            TrainError::Curriculum(err) => write!(f, "could not score the curriculum: {err}"),
+            TrainError::Holdout(err) => write!(f, "{err}"),
+            TrainError::Load(err) => write!(f, "could not load the model to start from: {err}"),
+            TrainError::Dataset(err) => write!(f, "the dataset failed verification: {err}"),
+            TrainError::Locked(err) => write!(f, "{err}"),
+            TrainError::SoftLabels(err) => write!(f, "{err}"),
+            TrainError::NonFinite(event) => write!(f, "{event}"),
+            TrainError::Split(err) => write!(f, "{err}"),
+            TrainError::Store(err) => write!(f, "could not upload the run: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for TrainError {}
+
+impl From<std::io::Error> for TrainError {
+    fn from(err: std::io::Error) -> Self {
+        TrainError::Io(err)
+    }
+}
+
+impl From<LockError> for TrainError {
+    fn from(err: LockError) -> Self {
+        TrainError::Locked(err)
+    }
+}
+
+impl From<RecorderError> for TrainError {
+    fn from(err: RecorderError) -> Self {
+        TrainError::Record(err)
+    }
+}
+
+// With `verify_dataset`, checks both loaded splits of `config.dataset`
+fn verify_splits<D: ClassificationDataset + ?Sized>(
+    config: &TrainingConfig,
+    train_set: &D,
+    valid_set: &D,
+) -> Result<(), TrainError> {
+    if !config.verify_dataset {
+        return Ok(());
+    }
+    for (dataset, split) in [(train_set, MnistSplit::Train), (valid_set, MnistSplit::Test)] {
+        verify_items(dataset, split, config.dataset.expected_len(split)).map_err(TrainError::Dataset)?;
+    }
+    Ok(())
+}
+
+// A loaded split of `config.dataset`
+type MnistSet = Arc<dyn Dataset<MnistItem>>;
+
+// `train_set` restricted to the `train_subset` indices, when the config has some
+fn apply_train_subset(
+    config: &TrainingConfig,
+    train_set: MnistSet,
+) -> Result<(MnistSet, Vec<usize>), TrainError> {
+    let Some(path) = &config.train_subset else {
+        let indices = (0..Dataset::len(train_set.as_ref())).collect();
+        return Ok((train_set, indices));
+    };
+    let invalid =
+        |value: String, expected: &str| TrainError::InvalidConfig(vec![ConfigError::new("train_subset", value, expected)]);
+    let json = std::fs::read_to_string(path)
+        .map_err(|err| invalid(path.display().to_string(), &format!("a readable file ({err})")))?;
+    let indices: Vec<usize> = serde_json::from_str(&json)
+        .map_err(|err| invalid(path.display().to_string(), &format!("a JSON list of indices ({err})")))?;
+    let len = Dataset::len(train_set.as_ref());
+    if let Some(&index) = indices.iter().find(|&&index| index >= len) {
+        return Err(invalid(
+            format!("{} with index {index}", path.display()),
+            &format!("indices < {len} (the size of the training split)"),
+        ));
+    }
+    Ok((Arc::new(IndexedDataset::new(train_set, indices.clone())), indices))
+}
+
+// The training and validation sets of the run and their split, from the `split_from` file
+// when the config has one, `train_subset` and the whole validation split otherwise
+fn apply_split(
+    config: &TrainingConfig,
+    train_set: MnistSet,
+    valid_set: MnistSet,
+) -> Result<(MnistSet, MnistSet, SplitIndices), TrainError> {
+    if let Some(fraction) = config.valid_fraction {
+        let invalid = |_| TrainError::InvalidConfig(vec![ConfigError::new("valid_fraction", fraction, "a value in (0, 1)")]);
+        let splits = stratified_split(train_set.clone(), &[1.0 - fraction, fraction], config.seed).map_err(invalid)?;
+        let split = SplitIndices::of_subsets(&splits[0], &splits[1]);
+        let valid_set: MnistSet = Arc::new(IndexedDataset::new(train_set.clone(), split.valid.clone()));
+        let train_set: MnistSet = Arc::new(IndexedDataset::new(train_set, split.train.clone()));
+        return Ok((train_set, valid_set, split));
+    }
+    let Some(path) = &config.split_from else {
+        let valid = (0..Dataset::len(valid_set.as_ref())).collect();
+        let fingerprint = DatasetFingerprint::of(&train_set);
+        let valid_fingerprint = DatasetFingerprint::of(&valid_set);
+        let (train_set, train) = apply_train_subset(config, train_set)?;
+        return Ok((train_set, valid_set, SplitIndices::new(train, valid, fingerprint, Some(valid_fingerprint))));
+    };
+    let split = SplitIndices::load(path).map_err(TrainError::Split)?;
+    split.check(&train_set, &valid_set).map_err(TrainError::Split)?;
+    // A split of one dataset validates on items of the training split
+    let valid_source = match split.valid_fingerprint {
+        Some(_) => valid_set,
+        None => train_set.clone(),
+    };
+    let train_set: MnistSet = Arc::new(IndexedDataset::new(train_set, split.train.clone()));
+    let valid_set: MnistSet = Arc::new(IndexedDataset::new(valid_source, split.valid.clone()));
+    Ok((train_set, valid_set, split))
+}
+
+pub(crate) fn create_artifact_dir(artifact_dir: &str) -> std::io::Result<()> {
     // Remove existing artifacts to get an accurate learner summary
     std::fs::remove_dir_all(artifact_dir).ok();
-    std::fs::create_dir_all(artifact_dir).ok();
+    std::fs::create_dir_all(artifact_dir)
+}
+
+/// Trains a model on MNIST and saves everything needed to reuse it into `artifact_dir`:
+/// `config.json`, the `model` weights and their `model_meta.json`, the learner checkpoints and
+/// logs, `history.json` and the `curves.svg` learning curves. With `swa` set, also
+/// the averaged `model_swa` and `swa.json`, its validation scores next to those of the final
+/// model. With `save_model` off, no weights are left on disk, only the config, logs and history.
+///
+/// Weights and checkpoints are stored in half precision (f16), which roughly halves their size;
+/// [`load_model`](crate::load_model) upcasts them back to the backend's float type.
+///
+/// The directory is wiped first, and locked for the whole run: training into a directory
+/// another run is writing is a [`TrainError::Locked`]. The config is validated before anything
+/// touches the disk. Returns the trained model. `artifact_dir` is a path or an
+/// [`ArtifactDir`](crate::artifact::ArtifactDir).
+///
+/// ```no_run
+/// use burn::backend::{wgpu::WgpuDevice, Autodiff, Wgpu};
+/// use burn::optim::AdamConfig;
+/// use my_first_rust_DL_app::{train, ModelConfig, TrainingConfig};
+///
+/// let config = TrainingConfig::new(ModelConfig::new(10, 512), AdamConfig::new())
+///     .with_num_epochs(1);
+/// let model = train::<Autodiff<Wgpu>>("/tmp/mnist-run", config, WgpuDevice::default())?;
+/// # Ok::<(), my_first_rust_DL_app::TrainError>(())
+/// ```
+pub fn train<B: AutodiffBackend>(
+    artifact_dir: impl AsRef<Path>,
+    config: TrainingConfig,
+    device: B::Device,
+) -> Result<Model<B>, TrainError> {
+    train_reporting(artifact_dir.as_ref(), config, device, None)
+}
+
+/// Same as [`train`], also streaming [`ProgressEvent`]s to `sender` as training goes: the start
+/// of every epoch, the loss of every step and the metric means of every finished epoch.
+///
+/// Training does not wait on the receiver, and keeps going if it is dropped.
+///
+/// ```no_run
+/// use burn::backend::{wgpu::WgpuDevice, Autodiff, Wgpu};
+/// use burn::optim::AdamConfig;
+/// use my_first_rust_DL_app::{train_with_progress, ModelConfig, ProgressEvent, TrainingConfig};
+/// use std::{sync::mpsc, thread};
+///
+/// let config = TrainingConfig::new(ModelConfig::new(10, 512), AdamConfig::new());
+/// let (sender, receiver) = mpsc::channel();
+/// let watcher = thread::spawn(move || {
+///     for event in receiver {
+///         if let ProgressEvent::EpochCompleted { metrics } = event {
+///             println!("epoch {}: {:?}", metrics.epoch, metrics.valid);
+///         }
+///     }
+/// });
+/// train_with_progress::<Autodiff<Wgpu>>("/tmp/mnist-run", config, WgpuDevice::default(), sender)?;
+/// watcher.join().unwrap();
+/// # Ok::<(), my_first_rust_DL_app::TrainError>(())
+/// ```
+pub fn train_with_progress<B: AutodiffBackend>(
+    artifact_dir: impl AsRef<Path>,
+    config: TrainingConfig,
+    device: B::Device,
+    sender: Sender<ProgressEvent>,
+) -> Result<Model<B>, TrainError> {
+    train_reporting(artifact_dir.as_ref(), config, device, Some(sender))
+}
+
+// `train`, streaming its progress to `progress` when set
+fn train_reporting<B: AutodiffBackend>(
+    artifact_dir: &Path,
+    config: TrainingConfig,
+    device: B::Device,
+    progress: Option<Sender<ProgressEvent>>,
+) -> Result<Model<B>, TrainError> {
+    // Before loading the dataset, which may have to download it
+    config.validate().map_err(TrainError::InvalidConfig)?;
+    config.check_backend::<B>().map_err(TrainError::InvalidConfig)?;
+
+    let (train_set, train_sources) = config.dataset.load_counted(MnistSplit::Train, config.cache);
+    let (valid_set, valid_sources) = config.dataset.load_counted(MnistSplit::Test, config.cache);
+    verify_splits(&config, &train_set, &valid_set)?;
+    let (train_set, valid_set, split) = apply_split(&config, train_set, valid_set)?;
+    let options = RunOptions {
+        progress,
+        sources: Some((train_sources, valid_sources)),
+        split: Some(split),
+        ..RunOptions::default()
+    };
+    train_classification(ArtifactDir::new(artifact_dir).as_str(), config, train_set, valid_set, device, options)
+}
+
+// Smallest `adam_epsilon` that trains in F16
+const F16_MIN_ADAM_EPSILON: f32 = 1e-3;
+
+// Smallest image side the model accepts: each of its two 3x3 convolutions trims 2 pixels
+const MIN_IMAGE_SIZE: usize = 5;
+
+// Smallest image side `model` takes: one whose conv features are at least as large as the
+// adaptive pooling of its head, which would otherwise only repeat them
+fn min_image_side(model: &ModelConfig) -> usize {
+    match (model.global_pool, model.head_input) {
+        (GlobalPool::None, HeadInput::AdaptiveAvgPool { size }) => MIN_IMAGE_SIZE.max(size + MIN_IMAGE_SIZE - 1),
+        _ => MIN_IMAGE_SIZE,
+    }
+}
+
+// The dataset checks `train_on` adds to the config validation, from the image shapes of both
+// datasets and the size of the training one
+fn validate_datasets(
+    model: &ModelConfig,
+    train_shape: [usize; 2],
+    valid_shape: [usize; 2],
+    train_len: usize,
+) -> Vec<ConfigError> {
+    let mut errors = Vec::new();
+    let [height, width] = train_shape;
+    // The flattened feature maps of other image sizes do not fit the dense head
+    if model.global_pool == GlobalPool::None && model.head_input == HeadInput::Flatten && train_shape != [28, 28] {
+        errors.push(ConfigError::new(
+            "model.head_input",
+            format!("Flatten with {height}x{width} images"),
+            "AdaptiveAvgPool unless the dataset images are 28x28",
+        ));
+    }
+    let min_side = min_image_side(model);
+    if height < min_side || width < min_side {
+        errors.push(ConfigError::new(
+            "dataset.image_shape",
+            format!("{height}x{width}"),
+            &format!("at least {min_side}x{min_side}, the smallest image the model takes"),
+        ));
+    }
+    if valid_shape != train_shape {
+        let [valid_height, valid_width] = valid_shape;
+        errors.push(ConfigError::new(
+            "valid_set.image_shape",
+            format!("{valid_height}x{valid_width}"),
+            &format!("{height}x{width} (the training image shape)"),
+        ));
+    }
+    if train_len == 0 {
+        errors.push(ConfigError::new("train_set.len", 0, ">= 1"));
+    }
+    errors
+}
+
+// `onnx_export_every` needs a model `export_onnx` can write, for images of `image_shape`
+#[cfg(feature = "onnx")]
+fn check_onnx_export(config: &TrainingConfig, image_shape: [usize; 2]) -> Option<ConfigError> {
+    config.onnx_export_every?;
+    let model = &config.model;
+    let err = check_export(model.global_pool, model.head_input, model.batch_norm, image_shape).err()?;
+    Some(ConfigError::new("onnx_export_every", "set", &format!("unset for a model ONNX export does not support ({err})")))
+}
+
+// The ONNX snapshots of `onnx_export_every`, if set, at the steps per epoch of `num_items` items
+#[cfg(feature = "onnx")]
+fn onnx_snapshots(artifact_dir: &str, config: &TrainingConfig, num_items: usize, image_shape: [usize; 2]) -> Option<OnnxSnapshots> {
+    let every = config.onnx_export_every?;
+    Some(OnnxSnapshots::new(artifact_dir, every, optimizer_steps_per_epoch(num_items, config), image_shape))
+}
+
+// Checks that the sources of every `Concat` in `source` (at `field` of the config) can be joined:
+// at least one of them, all with the class names of the first. Mismatched names are listed
+// rather than merged by label.
+fn validate_source(source: &DatasetSource, field: &str) -> Vec<ConfigError> {
+    let DatasetSource::Concat(sources) = source else {
+        return Vec::new();
+    };
+    if sources.is_empty() {
+        return vec![ConfigError::new(field, "Concat([])", "at least one source")];
+    }
+
+    let mut errors = Vec::new();
+    let expected = sources[0].class_names();
+    for (position, child) in sources.iter().enumerate() {
+        let child_field = format!("{field}.Concat[{position}]");
+        errors.extend(validate_source(child, &child_field));
+
+        let names = child.class_names();
+        let missing = "(none)".to_string();
+        let mismatched: Vec<String> = (0..expected.len().max(names.len()))
+            .filter(|&label| expected.get(label) != names.get(label))
+            .map(|label| {
+                let (first, found) = (expected.get(label).unwrap_or(&missing), names.get(label).unwrap_or(&missing));
+                format!("label {label} is {found:?} instead of {first:?}")
+            })
+            .collect();
+        if !mismatched.is_empty() {
+            errors.push(ConfigError::new(
+                &child_field,
+                format!("{} classes", names.len()),
+                &format!("the class names of `{field}.Concat[0]`, but {}", mismatched.join(", ")),
+            ));
+        }
+    }
+    errors
+}
+
+/// Like [`train`], but on any [`ClassificationDataset`] instead of the config's dataset
+/// source. `config.dataset` is ignored; the model must have at least as many classes as the
+/// datasets have labels, unless `config.classes` selects a subset of them or
+/// `config.binary_target` makes them two.
+///
+/// ```no_run
+/// use burn::backend::{wgpu::WgpuDevice, Autodiff, Wgpu};
+/// use burn::optim::AdamConfig;
+/// use my_first_rust_DL_app::{train_on, ClassificationDataset, ModelConfig, TrainingConfig};
+///
+/// // Three 12x12 classes: an empty, a half-filled and a full image
+/// struct Levels;
+///
+/// impl ClassificationDataset for Levels {
+///     fn len(&self) -> usize { 300 }
+///     fn get(&self, index: usize) -> Option<(Vec<f32>, usize)> {
+///         let label = index % 3;
+///         Some((vec![label as f32 * 127.5; 144], label))
+///     }
+///     fn num_classes(&self) -> usize { 3 }
+///     fn image_shape(&self) -> [usize; 2] { [12, 12] }
+/// }
+///
+/// let config = TrainingConfig::new(ModelConfig::new(3, 32), AdamConfig::new());
+/// train_on::<Autodiff<Wgpu>, _>("/tmp/levels", config, Levels, Levels, WgpuDevice::default())?;
+/// # Ok::<(), my_first_rust_DL_app::TrainError>(())
+/// ```
+pub fn train_on<B: AutodiffBackend, D: ClassificationDataset + 'static>(
+    artifact_dir: &str,
+    config: TrainingConfig,
+    train_set: D,
+    valid_set: D,
+    device: B::Device,
+) -> Result<Model<B>, TrainError> {
+    train_classification(artifact_dir, config, train_set, valid_set, device, RunOptions::default())
+}
+
+// What a single-label run starts from besides its config and datasets
+pub(crate) struct RunOptions<B: AutodiffBackend> {
+    // Where `train_with_progress` streams the progress events
+    pub progress: Option<Sender<ProgressEvent>>,
+    // The weights `prune` fine-tunes instead of a fresh model, with the masks holding their
+    // pruned weights at zero
+    pub start: Option<(Model<B>, WeightMasks<B>)>,
+    // Items of each `config.dataset` source in the training and validation sets, recorded in
+    // the model metadata. `None` when the datasets were passed in.
+    pub sources: Option<(Vec<SourceCount>, Vec<SourceCount>)>,
+    // The split of the loaded datasets the run trains and validates on, saved as its
+    // `split_indices.json`. `None` when the datasets were passed in.
+    pub split: Option<SplitIndices>,
+}
+
+impl<B: AutodiffBackend> Default for RunOptions<B> {
+    fn default() -> Self {
+        Self { progress: None, start: None, sources: None, split: None }
+    }
+}
+
+// `train_on`, with the run options of the other entry points
+pub(crate) fn train_classification<B: AutodiffBackend, D: ClassificationDataset + 'static>(
+    artifact_dir: &str,
+    mut config: TrainingConfig,
+    train_set: D,
+    valid_set: D,
+    device: B::Device,
+    options: RunOptions<B>,
+) -> Result<Model<B>, TrainError> {
+    let num_classes = train_set.num_classes().max(valid_set.num_classes());
+    let mut errors = config.validate_for(num_classes, TaskKind::SingleLabel).err().unwrap_or_default();
+    errors.extend(config.check_backend::<B>().err().unwrap_or_default());
+    let train_set = PreprocessedDataset::new(train_set, config.preprocess.clone());
+    let valid_set = PreprocessedDataset::new(valid_set, config.preprocess.clone());
+
+    match (config.classes.clone(), config.binary_target) {
+        (Some(classes), _) if errors.is_empty() => {
+            config.model.num_classes = classes.len();
+            let train_set = ClassSubset::new(train_set, &classes);
+            let valid_set = ClassSubset::new(valid_set, &classes);
+            errors.extend(validate_datasets(&config.model, train_set.image_shape(), valid_set.image_shape(), train_set.len()));
+            run(artifact_dir, config, train_set, valid_set, device, errors, options)
+        }
+        (None, Some(target)) if errors.is_empty() => {
+            config.model.num_classes = 2;
+            let train_set = OneVsRest::new(train_set, target);
+            let valid_set = OneVsRest::new(valid_set, target);
+            errors.extend(validate_datasets(&config.model, train_set.image_shape(), valid_set.image_shape(), train_set.len()));
+            run(artifact_dir, config, train_set, valid_set, device, errors, options)
+        }
+        _ => {
+            errors.extend(validate_datasets(&config.model, train_set.image_shape(), valid_set.image_shape(), train_set.len()));
+            run(artifact_dir, config, train_set, valid_set, device, errors, options)
+        }
+    }
+}
+
+// The `pretrained_weights` of the config, read before the artifact dir is wiped since the file
+// may be in it
+fn read_pretrained(config: &TrainingConfig) -> Result<Option<(String, Vec<NamedParam>)>, TrainError> {
+    let Some(path) = &config.pretrained_weights else {
+        return Ok(None);
+    };
+    let params = read_safetensors(path).map_err(TrainError::Load)?;
+    Ok(Some((path.display().to_string(), params)))
+}
+
+// A fresh model from the config, warm-started from the pretrained weights read by
+// `read_pretrained`
+fn init_model<B: Backend>(config: &TrainingConfig, pretrained: Option<(String, Vec<NamedParam>)>, device: &B::Device) -> Model<B> {
+    let model = match config.portable_init {
+        true => config.model.init_portable::<B>(config.seed, device),
+        false => config.model.init::<B>(device),
+    };
+    let Some((source, params)) = pretrained else {
+        return model;
+    };
+    let (model, report) = load_partial_params(model, params, &source);
+    if config.verbosity != Verbosity::Silent {
+        println!("Warm start from {source}: {report}");
+    }
+    model
+}
+
+// What a `resume_from` run starts from: the final model of the resumed run, and the encoded
+// optimizer record of its latest checkpoint, unless `reset_optimizer` is set
+struct Resume<B: Backend> {
+    model: Model<B>,
+    optimizer: Option<Vec<u8>>,
+}
+
+// Epoch of the latest optimizer checkpoint in `dir`, from the `optim-{epoch}.mpk` file names
+fn latest_optimizer_checkpoint(dir: &Path) -> Option<usize> {
+    std::fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| {
+            let name = entry.ok()?.file_name().into_string().ok()?;
+            name.strip_prefix("optim-")?.strip_suffix(".mpk")?.parse().ok()
+        })
+        .max()
+}
+
+// Opens the `checkpoint_store` of the config, if any, before training: the store settings are
+// better missing now than once the checkpoints are written
+fn open_upload(dir: &ArtifactDir, config: &TrainingConfig) -> Result<Option<RunUpload>, TrainError> {
+    let Some(url) = &config.checkpoint_store else {
+        return Ok(None);
+    };
+    RunUpload::open(url, dir).map(Some).map_err(TrainError::Store)
+}
+
+// Uploads what the learner checkpoints left of the finished run to its store
+fn finish_upload(dir: &ArtifactDir, config: &TrainingConfig, upload: Option<RunUpload>) -> Result<(), TrainError> {
+    let Some(upload) = upload else {
+        return Ok(());
+    };
+    let count = upload.finish().map_err(TrainError::Store)?;
+    if config.verbosity != Verbosity::Silent {
+        println!("Uploaded {count} files of {dir} to {upload}");
+    }
+    Ok(())
+}
+
+// The `resume_from` run of the config, read before the artifact dir is wiped since it may be
+// the same dir
+fn read_resume<B: Backend>(config: &TrainingConfig, device: &B::Device) -> Result<Option<Resume<B>>, TrainError> {
+    let Some(url) = &config.resume_from else {
+        return Ok(None);
+    };
+    // A run in a checkpoint store is downloaded first, checkpoints included
+    let dir = &store::fetch_artifact_dir(url, true).map_err(TrainError::Store)?;
+    let resumed = TrainingConfig::load(format!("{dir}/config.json"))
+        .map_err(|err| TrainError::Load(LoadError::Config(err.to_string())))?;
+    let (model, _) = load_weights::<B>(dir, &config.model, true, device).map_err(TrainError::Load)?;
+    if config.verbosity != Verbosity::Silent {
+        if let Some(notice) = batch_size_notice(url, &resumed, config) {
+            println!("{notice}");
+        }
+    }
+    if config.reset_optimizer {
+        if config.verbosity != Verbosity::Silent {
+            println!("Resuming from {url} with a fresh optimizer state");
+        }
+        return Ok(Some(Resume { model, optimizer: None }));
+    }
+
+    // The checkpoint only decodes into the state of the same optimizer
+    if resumed.optimizer_kind != config.optimizer_kind {
+        let error = ConfigError::new(
+            "reset_optimizer",
+            false,
+            &format!("true to resume {url}, trained with {:?}, with {:?}", resumed.optimizer_kind, config.optimizer_kind),
+        );
+        return Err(TrainError::InvalidConfig(vec![error]));
+    }
+    let checkpoints = Path::new(dir).join("checkpoint");
+    let optimizer = match latest_optimizer_checkpoint(&checkpoints) {
+        Some(epoch) => {
+            if config.verbosity != Verbosity::Silent {
+                println!("Resuming from {url} with the optimizer state of epoch {epoch}");
+            }
+            Some(std::fs::read(checkpoints.join(format!("optim-{epoch}.mpk")))?)
+        }
+        None => {
+            if config.verbosity != Verbosity::Silent {
+                println!("Resuming from {url}, which has no optimizer checkpoint: with a fresh optimizer state");
+            }
+            None
+        }
+    };
+    Ok(Some(Resume { model, optimizer }))
+}
+
+// What `read_resume` prints when the run resumes `dir`, of the `resumed` config, at another
+// batch size: the learning rates a rescaling would give
+fn batch_size_notice(dir: &str, resumed: &TrainingConfig, config: &TrainingConfig) -> Option<String> {
+    if resumed.batch_size == config.batch_size {
+        return None;
+    }
+    let scale = config.batch_size as f64 / resumed.batch_size as f64;
+    Some(format!(
+        "Resuming from {dir}, trained at batch size {}, at batch size {}: the learning rate is not rescaled \
+         ({} scaled linearly, {} by the square root)",
+        resumed.batch_size,
+        config.batch_size,
+        config.learning_rate * scale,
+        config.learning_rate * scale.sqrt(),
+    ))
+}
+
+// Loads the optimizer record read by `read_resume` into a fresh optimizer
+fn resume_optimizer<B: AutodiffBackend, O: Optimizer<Model<B>, B>>(
+    optimizer: O,
+    record: Option<&[u8]>,
+    device: &B::Device,
+) -> Result<O, TrainError> {
+    let Some(record) = record else {
+        return Ok(optimizer);
+    };
+    let record = NamedMpkBytesRecorder::<HalfPrecisionSettings>::new().load(record.to_vec(), device)?;
+    Ok(optimizer.load_record(record))
+}
+
+// The `lr_multipliers` prefixes that match no parameter of a fresh model, checked before the
+// artifact dir is wiped: the trained model has the same parameters whatever it starts from
+fn check_lr_multipliers<B: Backend>(config: &TrainingConfig, device: &B::Device) -> Vec<ConfigError> {
+    let Some(multipliers) = &config.lr_multipliers else {
+        return Vec::new();
+    };
+    lr_groups(&config.model.init::<B>(device), multipliers).err().unwrap_or_default()
+}
+
+// The `lr_multipliers` groups of `model`, printed with their starting learning rates unless
+// silent
+fn resolve_lr_groups<B: Backend>(config: &TrainingConfig, model: &Model<B>) -> Result<Vec<LrGroup>, TrainError> {
+    let Some(multipliers) = &config.lr_multipliers else {
+        return Ok(Vec::new());
+    };
+    let groups = lr_groups(model, multipliers).map_err(TrainError::InvalidConfig)?;
+    if config.verbosity != Verbosity::Silent {
+        println!("Learning rates: {:e} for the other parameters", config.learning_rate);
+        for group in &groups {
+            println!(
+                "  {}: {:e} ({}x) for {}",
+                group.prefix,
+                config.learning_rate * group.multiplier,
+                group.multiplier,
+                group.params.join(", ")
+            );
+        }
+    }
+    Ok(groups)
 }
 
-pub fn train<B: AutodiffBackend>(artifact_dir: &str, config: TrainingConfig, device: B::Device){
+// Everything `train_on` does once the datasets are final; `errors` are those validation found
+fn run<B: AutodiffBackend, D: ClassificationDataset + 'static>(
+    artifact_dir: &str,
+    mut config: TrainingConfig,
+    train_set: D,
+    valid_set: D,
+    device: B::Device,
+    mut errors: Vec<ConfigError>,
+    options: RunOptions<B>,
+) -> Result<Model<B>, TrainError> {
+    // Holdout images are loaded as 28x28 MNIST canvases
+    if config.holdout_dir.is_some() && train_set.image_shape() != [28, 28] {
+        let [height, width] = train_set.image_shape();
+        errors.push(ConfigError::new(
+            "holdout_dir",
+            format!("set with {height}x{width} images"),
+            "unset unless the dataset images are 28x28",
+        ));
+    }
+    #[cfg(feature = "onnx")]
+    errors.extend(check_onnx_export(&config, train_set.image_shape()));
+    if let Some(size) = config.valid_subset_size {
+        if size > valid_set.len() {
+            errors.push(ConfigError::new(
+                "valid_subset_size",
+                size,
+                &format!("<= {} (the size of the validation set)", valid_set.len()),
+            ));
+        }
+    }
+    // Images are only ever averaged down
+    for (position, &(_, size)) in config.progressive_resize.iter().flatten().enumerate() {
+        let [height, width] = train_set.image_shape();
+        if size > height.min(width) {
+            errors.push(ConfigError::new(
+                &format!("progressive_resize[{position}].size"),
+                size,
+                &format!("<= {} (the side of the {height}x{width} dataset images)", height.min(width)),
+            ));
+        }
+    }
+    for (position, &index) in config.preview_indices.iter().flatten().enumerate() {
+        if index >= valid_set.len() {
+            errors.push(ConfigError::new(
+                &format!("preview_indices[{position}]"),
+                index,
+                &format!("< {} (the size of the validation set)", valid_set.len()),
+            ));
+        }
+    }
+    errors.extend(check_lr_multipliers::<B>(&config, &device));
+    if !errors.is_empty() {
+        return Err(TrainError::InvalidConfig(errors));
+    }
+    let image_shape = train_set.image_shape();
+    if config.auto_batch_size {
+        config.batch_size = search_batch_size::<B>(&config, image_shape, &device);
+        if config.verbosity != Verbosity::Silent {
+            println!("Auto batch size: {}", config.batch_size);
+        }
+    }
+    let valid_subset = config.valid_subset_size.map(|size| valid_subset(valid_set.len(), size, config.seed));
+
+    // Scored before the artifact dir is wiped, it may hold the pretrained scoring model
+    let holdout = match &config.holdout_dir {
+        Some(dir) => Some(
+            load_holdout(dir, config.holdout_natural, config.resize_policy, &config.preprocess, |label| {
+                config.relabel(label)
+            })
+                .map_err(TrainError::Holdout)?,
+        ),
+        None => None,
+    };
+
+    // One distribution per class of the model head
+    let soft_labels = match &config.soft_labels {
+        Some(path) => Some(
+            SoftLabels::load(path, train_set.len(), config.model.num_classes).map_err(TrainError::SoftLabels)?,
+        ),
+        None => None,
+    };
+
+    // A run starting from a given model does not start from the pretrained or resumed weights
+    let pretrained = if options.start.is_none() { read_pretrained(&config)? } else { None };
+    let resume = if options.start.is_none() { read_resume::<B>(&config, &device)? } else { None };
+
+    let curriculum = match &config.curriculum {
+        Some(curriculum) => {
+            let scores = score_samples::<B::InnerBackend, D>(&train_set, &curriculum.score, &device)
+                .map_err(TrainError::Curriculum)?;
+            Some((curriculum.num_epochs, CurriculumOrder::from_scores(scores)))
+        }
+        None => None,
+    };
 
-    create_artifact_dir(artifact_dir);
-    config
-        .save(format!("{artifact_dir}/config.json"))
-        .expect("Config should be saved successfully!");
+    let dir = ArtifactDir::new(artifact_dir);
+    let upload = open_upload(&dir, &config)?;
+    // Held until the run is over
+    let lock = dir.lock()?;
+    dir.reset(&lock)?;
+    config.save(dir.config_path())?;
+    if let Some(split) = &options.split {
+        split.save(dir.split_path())?;
+    }
+    ClassLabels::from_config(&config).save(artifact_dir)?;
+    if let Some((_, order)) = &curriculum {
+        std::fs::write(
+            format!("{artifact_dir}/curriculum.json"),
+            serde_json::to_string(order).expect("Curriculum order should serialize to JSON"),
+        )?;
+    }
+    if let Some(indices) = &valid_subset {
+        std::fs::write(
+            format!("{artifact_dir}/valid_subset.json"),
+            serde_json::to_string(indices).expect("Validation subset should serialize to JSON"),
+        )?;
+    }
 
-    B::seed(config.seed);
+    seed_backend::<B>(config.seed);
 
-    let batcher_train = MnistBatcher::<B>::new(device.clone());
-    let batcher_val = MnistBatcher::<B::InnerBackend>::new(device.clone()); 
+    let mut batcher_train = MnistBatcher::<B>::new(device.clone()).with_loss(config.loss_settings());
+    if let Some(alpha) = config.mixup_alpha {
+        batcher_train = batcher_train.with_mixup(alpha, config.model.num_classes, config.seed);
+    }
+    if let Some(alpha) = config.cutmix_alpha {
+        batcher_train = batcher_train.with_cutmix(alpha, config.model.num_classes, config.seed);
+    }
+    if let Some(soft_labels) = soft_labels {
+        batcher_train = batcher_train.with_soft_labels(Arc::new(soft_labels), config.hard_label_weight as f32);
+    }
+    let batcher_val = MnistBatcher::<B::InnerBackend>::new(device.clone()).with_loss(config.loss_settings());
     
     // create the dataloaders
     
-    let dataLoader_train = DataLoaderBuilder::new(batcher_train)
-        .batch_size(config.batch_size)
-        .shuffle(config.seed)
-        .num_workers(config.num_workers)
-        .build(MnistDataset::train());
-
-    let dataLoader_test = DataLoaderBuilder::new(batcher_val)
-        .batch_size(config.batch_size)
-        .shuffle(config.seed)
-        .num_workers(config.num_workers)
-        .build(MnistDataset::test());
-
-    let learner = LearnerBuilder::new(artifact_dir)
+    // Shared, for SWA to go over the training set again once the training loader is done
+    let indices = (0..train_set.len()).collect();
+    let train_set = SubsetDataset::new(Arc::new(train_set), indices);
+    let swa_set = train_set.clone();
+    let mut hard_mining = None;
+    let mut dataloader_train: Box<dyn DataLoader<MnistBatch<B>>> = match curriculum {
+        Some((curriculum_epochs, order)) => {
+            let train_set = Arc::new(train_set);
+            let all = SubsetDataset::new(train_set.clone(), (0..train_set.len()).collect());
+            let shuffled = boxed_mnist_dataloader(
+                batcher_train.clone(),
+                all,
+                config.batch_size,
+                config.seed,
+                config.num_workers,
+                config.prefetch,
+            );
+            let num_batches =
+                batches_per_epoch(shuffled.num_items(), config.batch_size, config.num_workers);
+            let ordered = SubsetDataset::new(train_set, order.order);
+            Box::new(CurriculumDataLoader::new(shuffled, ordered, batcher_train, num_batches, curriculum_epochs))
+        }
+        None => match config.hard_mining.clone() {
+            Some(mining_config) => {
+                let steps_per_epoch = optimizer_steps_per_epoch(train_set.len(), &config);
+                let (mining, resampled) = HardMining::new(
+                    artifact_dir,
+                    mining_config,
+                    train_set,
+                    config.seed,
+                    steps_per_epoch,
+                    config.num_epochs,
+                )?;
+                hard_mining = Some(mining);
+                boxed_mnist_dataloader(
+                    batcher_train,
+                    resampled,
+                    config.batch_size,
+                    config.seed,
+                    config.num_workers,
+                    config.prefetch,
+                )
+            }
+            None if config.balanced_sampling => {
+                let (sampler, resampled) = BalancedSampler::new(train_set, config.seed);
+                Box::new(sampler.loader(boxed_mnist_dataloader(
+                    batcher_train,
+                    resampled,
+                    config.batch_size,
+                    config.seed,
+                    config.num_workers,
+                    config.prefetch,
+                )))
+            }
+            None => boxed_mnist_dataloader(
+                batcher_train,
+                train_set,
+                config.batch_size,
+                config.seed,
+                config.num_workers,
+                config.prefetch,
+            ),
+        },
+    };
+
+    let batch_order = config.export_batch_order.then(BatchOrder::default);
+    if let Some(order) = &batch_order {
+        dataloader_train = Box::new(BatchOrderDataLoader::new(dataloader_train, order.clone()));
+    }
+    if let Some(schedule) = &config.progressive_resize {
+        dataloader_train = Box::new(ProgressiveResizeDataLoader::new(dataloader_train, schedule.clone()));
+    }
+    let (nan_guard, nan_tracker) = match config.nan_guard {
+        true => {
+            let (guard, tracker) = init_nan_guard(artifact_dir, &config, dataloader_train.num_items())?;
+            dataloader_train = Box::new(tracker.batches(dataloader_train));
+            (Some(guard), Some(tracker))
+        }
+        false => (None, None),
+    };
+
+    // The mid-epoch validations have a loader of their own, over the subset when there is one
+    let valid_set = Arc::new(valid_set);
+    let all = || (0..valid_set.len()).collect();
+    let dataloader_steps = config.valid_every_steps.map(|_| {
+        boxed_mnist_dataloader(
+            MnistBatcher::<B::InnerBackend>::new(device.clone()).with_loss(config.loss_settings()),
+            SubsetDataset::new(valid_set.clone(), valid_subset.clone().unwrap_or_else(all)),
+            config.batch_size,
+            config.seed,
+            config.num_workers,
+            config.prefetch,
+        )
+    });
+    let mut dataloader_test = boxed_mnist_dataloader(
+        batcher_val,
+        SubsetDataset::new(valid_set.clone(), all()),
+        config.batch_size,
+        config.seed,
+        config.num_workers,
+        config.prefetch,
+    );
+    if let Some(holdout) = holdout {
+        let holdout =
+            MnistBatcher::<B::InnerBackend>::new(device.clone()).batch(holdout_items(holdout));
+        dataloader_test = Box::new(HoldoutDataLoader::new(dataloader_test, holdout));
+    }
+    let samples = match (config.preview_samples, &config.preview_indices) {
+        (Some(num_samples), _) => Some(PreviewSamples::select(valid_set.as_ref(), num_samples, config.seed)),
+        (None, Some(indices)) => Some(PreviewSamples::from_indices(valid_set.as_ref(), indices.clone())),
+        (None, None) => None,
+    };
+    let preview = match samples {
+        Some(samples) => {
+            let batch = MnistBatcher::<B::InnerBackend>::new(device.clone()).batch(samples.items());
+            dataloader_test = Box::new(PreviewDataLoader::new(dataloader_test, batch));
+            Some(PreviewMetric::new(artifact_dir, samples, ClassLabels::from_config(&config))?)
+        }
+        None => None,
+    };
+
+    let (dataloader_train, dataloader_test, budget) = learner_dataloaders(&config, dataloader_train, dataloader_test);
+
+    let (model, optimizer_record) = match resume {
+        Some(resume) => (Some(resume.model), resume.optimizer),
+        None => (None, None),
+    };
+    let (model, masks) = match (options.start, model) {
+        (Some(start), _) => start,
+        (None, Some(model)) => (model, WeightMasks::default()),
+        (None, None) => (init_model::<B>(&config, pretrained, &device), WeightMasks::default()),
+    };
+    // The resumed optimizer state is of the weights, which adapter runs freeze
+    let (model, optimizer_record) = match &config.adapters {
+        Some(adapters) => {
+            let seed = derive_seed(config.seed, "adapters");
+            (model.with_new_adapters(adapters, seed, &device), None)
+        }
+        None => (model, optimizer_record),
+    };
+    let step_validation = match (config.valid_every_steps, dataloader_steps) {
+        (Some(every), Some(dataloader)) => {
+            let steps_per_epoch = optimizer_steps_per_epoch(dataloader_train.num_items(), &config);
+            Some(StepValidation::new(artifact_dir, every, steps_per_epoch, valid_subset.is_some(), dataloader)?)
+        }
+        _ => None,
+    };
+    let progress = options.progress;
+    let dataset = match options.sources.is_some() {
+        true => format!("{:?}", config.dataset),
+        false => type_name::<D>().to_string(),
+    };
+    let (train_sources, valid_sources) = options.sources.unwrap_or_default();
+    let groups = resolve_lr_groups(&config, &model)?;
+    #[cfg(feature = "onnx")]
+    let onnx_snapshots = onnx_snapshots(artifact_dir, &config, dataloader_train.num_items(), image_shape);
+    let (model_trained, budget_stop) = match config.optimizer_kind {
+        OptimizerKind::Adam => {
+            let optimizer = resume_optimizer(config.adam_config().init(), optimizer_record.as_deref(), &device)?;
+            let optimizer = LrMultiplierOptimizer::new(optimizer, groups);
+            let optimizer = MaskedOptimizer::new(optimizer, masks);
+            let optimizer = NanGuardOptimizer::new(optimizer, nan_guard);
+            let optimizer = StepValidatedOptimizer::new(optimizer, step_validation);
+            let optimizer = HardMiningOptimizer::new(optimizer, hard_mining);
+            #[cfg(feature = "onnx")]
+            let optimizer = OnnxSnapshotOptimizer::new(optimizer, onnx_snapshots);
+            let metrics = |builder, config: &_| single_label_metrics(builder, config, preview);
+            fit(artifact_dir, &config, model, optimizer, metrics, dataloader_train, dataloader_test.clone(), budget, nan_tracker.as_ref(), progress, upload.as_ref())?
+        }
+        OptimizerKind::Sgd => {
+            let optimizer = resume_optimizer(config.sgd_config().init(), optimizer_record.as_deref(), &device)?;
+            let optimizer = LrMultiplierOptimizer::new(optimizer, groups);
+            let optimizer = MaskedOptimizer::new(optimizer, masks);
+            let optimizer = NanGuardOptimizer::new(optimizer, nan_guard);
+            let optimizer = StepValidatedOptimizer::new(optimizer, step_validation);
+            let optimizer = HardMiningOptimizer::new(optimizer, hard_mining);
+            #[cfg(feature = "onnx")]
+            let optimizer = OnnxSnapshotOptimizer::new(optimizer, onnx_snapshots);
+            let metrics = |builder, config: &_| single_label_metrics(builder, config, preview);
+            fit(artifact_dir, &config, model, optimizer, metrics, dataloader_train, dataloader_test.clone(), budget, nan_tracker.as_ref(), progress, upload.as_ref())?
+        }
+    };
+
+    finish_fit(artifact_dir, &config)?;
+    let nan_events = nan_events(&config, nan_tracker);
+    if let Some(order) = batch_order {
+        order.save(artifact_dir)?;
+    }
+    if config.save_model {
+        // Cloning a module only bumps tensor reference counts
+        let (base, adapters) = model_trained.clone().split_adapters();
+        base.save_file(dir.model_path(ModelKind::Final), &CompactRecorder::new())?;
+        if let Some(adapters) = adapters {
+            adapters.save(&dir.adapter_path())?;
+        }
+    }
+    if let Some(adapters) = model_trained.adapters() {
+        if config.verbosity != Verbosity::Silent {
+            println!("{}", parameter_counts(&model_trained, adapters.num_params()));
+        }
+    }
+
+    if let Some(swa) = &config.swa {
+        let epochs = swa_epochs(swa, config.num_epochs);
+        let model_swa = average_checkpoints::<B>(artifact_dir, &config.model, &epochs, &device)?;
+        let dataloader = boxed_mnist_dataloader(
+            MnistBatcher::<B>::new(device.clone()),
+            swa_set,
+            config.batch_size,
+            config.seed,
+            config.num_workers,
+            config.prefetch,
+        );
+        let model_swa = recompute_batch_norm(model_swa, &config.model, dataloader.as_ref(), &device).valid();
+        if config.save_model {
+            model_swa
+                .clone()
+                .save_file(dir.model_path(ModelKind::Swa), &CompactRecorder::new())?;
+        }
+
+        let report = SwaReport {
+            model: score(&model_trained.valid(), dataloader_test.as_ref()),
+            swa: score(&model_swa, dataloader_test.as_ref()),
+            epochs,
+        };
+        if config.verbosity != Verbosity::Silent {
+            println!("{report}");
+        }
+        std::fs::write(
+            format!("{artifact_dir}/swa.json"),
+            serde_json::to_string_pretty(&report).expect("SWA report should serialize to JSON"),
+        )?;
+    }
+
+    if config.snapshot_ensemble {
+        let final_epoch = budget_stop.as_ref().map_or(config.num_epochs, |stop| stop.epoch);
+        let report = snapshot_ensemble::<B::InnerBackend, _>(
+            artifact_dir,
+            &config.model,
+            &config.lr_schedule.restart_epochs(config.num_epochs),
+            &model_trained.valid(),
+            final_epoch,
+            valid_set.as_ref(),
+            &device,
+        )?;
+        if config.verbosity != Verbosity::Silent {
+            println!("{report}");
+        }
+        std::fs::write(
+            format!("{artifact_dir}/snapshot_ensemble.json"),
+            serde_json::to_string_pretty(&report).expect("Snapshot ensemble report should serialize to JSON"),
+        )?;
+    }
+
+    let history = save_history(&dir, &config, budget_stop.as_ref())?;
+    if config.summary_file {
+        save_summary(&dir, &model_trained)?;
+    }
+    if config.save_model {
+        ModelMeta::new(&config.model, image_shape, &history)
+            .with_precision(config.precision)
+            .with_binary_target(config.binary_target)
+            .with_sources(train_sources, valid_sources)
+            .with_resize_policy(config.resize_policy)
+            .with_preprocess(config.preprocess.clone())
+            .with_budget_stop(budget_stop)
+            .with_nan_events(nan_events)
+            .save(artifact_dir)?;
+        ModelCard::new(&config, dataset, model_trained.num_params(), &history).save(artifact_dir)?;
+    } else {
+        discard_checkpoints(artifact_dir)?;
+    }
+    finish_upload(&dir, &config, upload)?;
+    Ok(model_trained)
+}
+
+/// Like [`train_on`], for a multi-label model (`model.task` set to `MultiLabel`): each image
+/// may carry any number of labels, the model is trained with a binary cross-entropy per label
+/// and scored with the "F1 Score" metric instead of accuracy, at `multilabel_threshold`.
+///
+/// Mixup, SWA, `classes`, curriculum and holdout images only apply to single-label training and
+/// must be unset. `model.num_classes` must cover every label of the datasets.
+pub fn train_multilabel_on<B: AutodiffBackend, D: MultiLabelDataset + 'static>(
+    artifact_dir: &str,
+    config: TrainingConfig,
+    train_set: D,
+    valid_set: D,
+    device: B::Device,
+) -> Result<Model<B>, TrainError> {
+    let num_classes = train_set.num_classes().max(valid_set.num_classes());
+    let mut errors = config.validate_for(num_classes, TaskKind::MultiLabel).err().unwrap_or_default();
+    errors.extend(config.check_backend::<B>().err().unwrap_or_default());
+    errors.extend(validate_datasets(&config.model, train_set.image_shape(), valid_set.image_shape(), train_set.len()));
+    #[cfg(feature = "onnx")]
+    errors.extend(check_onnx_export(&config, train_set.image_shape()));
+    errors.extend(check_lr_multipliers::<B>(&config, &device));
+    if !errors.is_empty() {
+        return Err(TrainError::InvalidConfig(errors));
+    }
+    let image_shape = train_set.image_shape();
+    let pretrained = read_pretrained(&config)?;
+    let resume = read_resume::<B>(&config, &device)?;
+
+    let dir = ArtifactDir::new(artifact_dir);
+    let upload = open_upload(&dir, &config)?;
+    // Held until the run is over
+    let lock = dir.lock()?;
+    dir.reset(&lock)?;
+    config.save(dir.config_path())?;
+    ClassLabels::from_config(&config).save(artifact_dir)?;
+
+    seed_backend::<B>(config.seed);
+
+    let num_classes = config.model.num_classes;
+    let dataloader_train: Box<dyn DataLoader<MultiLabelBatch<B>>> = boxed_dataloader(
+        MnistBatcher::<B>::new(device.clone()).with_loss(config.loss_settings()),
+        MultiLabelItems::new(train_set, num_classes),
+        config.batch_size,
+        config.seed,
+        config.num_workers,
+        config.prefetch,
+    );
+    let dataloader_test: Box<dyn DataLoader<MultiLabelBatch<B::InnerBackend>>> = boxed_dataloader(
+        MnistBatcher::<B::InnerBackend>::new(device.clone()).with_loss(config.loss_settings()),
+        MultiLabelItems::new(valid_set, num_classes),
+        config.batch_size,
+        config.seed,
+        config.num_workers,
+        config.prefetch,
+    );
+    // Multi-label batches carry no item indices for the events
+    let (nan_guard, nan_tracker) = match config.nan_guard {
+        true => {
+            let (guard, tracker) = init_nan_guard(artifact_dir, &config, dataloader_train.num_items())?;
+            (Some(guard), Some(tracker))
+        }
+        false => (None, None),
+    };
+    let (dataloader_train, dataloader_test, budget) = learner_dataloaders(&config, dataloader_train, dataloader_test);
+
+    let (model, optimizer_record) = match resume {
+        Some(resume) => (resume.model, resume.optimizer),
+        None => (init_model::<B>(&config, pretrained, &device), None),
+    };
+    let groups = resolve_lr_groups(&config, &model)?;
+    #[cfg(feature = "onnx")]
+    let onnx_snapshots = onnx_snapshots(artifact_dir, &config, dataloader_train.num_items(), image_shape);
+    let (model_trained, budget_stop) = match config.optimizer_kind {
+        OptimizerKind::Adam => {
+            let optimizer = resume_optimizer(config.adam_config().init(), optimizer_record.as_deref(), &device)?;
+            let optimizer = LrMultiplierOptimizer::new(optimizer, groups);
+            let optimizer = NanGuardOptimizer::new(optimizer, nan_guard);
+            #[cfg(feature = "onnx")]
+            let optimizer = OnnxSnapshotOptimizer::new(optimizer, onnx_snapshots);
+            fit(artifact_dir, &config, model, optimizer, multi_label_metrics, dataloader_train, dataloader_test, budget, nan_tracker.as_ref(), None, upload.as_ref())?
+        }
+        OptimizerKind::Sgd => {
+            let optimizer = resume_optimizer(config.sgd_config().init(), optimizer_record.as_deref(), &device)?;
+            let optimizer = LrMultiplierOptimizer::new(optimizer, groups);
+            let optimizer = NanGuardOptimizer::new(optimizer, nan_guard);
+            #[cfg(feature = "onnx")]
+            let optimizer = OnnxSnapshotOptimizer::new(optimizer, onnx_snapshots);
+            fit(artifact_dir, &config, model, optimizer, multi_label_metrics, dataloader_train, dataloader_test, budget, nan_tracker.as_ref(), None, upload.as_ref())?
+        }
+    };
+
+    finish_fit(artifact_dir, &config)?;
+    let nan_events = nan_events(&config, nan_tracker);
+    let history = save_history(&dir, &config, budget_stop.as_ref())?;
+    if config.summary_file {
+        save_summary(&dir, &model_trained)?;
+    }
+    if config.save_model {
+        model_trained
+            .clone()
+            .save_file(dir.model_path(ModelKind::Final), &CompactRecorder::new())?;
+        ModelMeta::new(&config.model, image_shape, &history)
+            .with_precision(config.precision)
+            .with_budget_stop(budget_stop)
+            .with_nan_events(nan_events)
+            .save(artifact_dir)?;
+        let dataset = type_name::<D>().to_string();
+        ModelCard::new(&config, dataset, model_trained.num_params(), &history).save(artifact_dir)?;
+    } else {
+        discard_checkpoints(artifact_dir)?;
+    }
+    finish_upload(&dir, &config, upload)?;
+    Ok(model_trained)
+}
+
+// Shares the dataloaders with the learner, the training one cut short by the budgets when there
+// are any, and both wrapped in the profiling hooks (and the profile started) when the run is
+// profiled
+#[allow(clippy::type_complexity)]
+fn learner_dataloaders<TI: 'static, VI: 'static>(
+    config: &TrainingConfig,
+    mut dataloader_train: Box<dyn DataLoader<TI>>,
+    dataloader_test: Box<dyn DataLoader<VI>>,
+) -> (Arc<dyn DataLoader<TI>>, Arc<dyn DataLoader<VI>>, Option<BudgetTracker>) {
+    let mut budget = None;
+    if config.max_steps.is_some() || config.max_duration.is_some() {
+        let (loader, tracker) = BudgetDataLoader::new(dataloader_train, config.max_steps, config.max_duration);
+        dataloader_train = Box::new(loader);
+        budget = Some(tracker);
+    }
+    match &config.profile {
+        Some(_) => {
+            profile::start(config.profile_steps);
+            (
+                Arc::new(ProfiledDataLoader::new(dataloader_train, LoaderKind::Train)),
+                Arc::new(ProfiledDataLoader::new(dataloader_test, LoaderKind::Valid)),
+                budget,
+            )
+        }
+        None => (Arc::from(dataloader_train), Arc::from(dataloader_test), budget),
+    }
+}
+
+// The `nan_guard` of a run whose training loader serves `num_items` items per epoch
+fn init_nan_guard(artifact_dir: &str, config: &TrainingConfig, num_items: usize) -> std::io::Result<(NanGuard, NanGuardTracker)> {
+    NanGuard::new(artifact_dir, config.nan_action, config.nan_max_grad_norm, optimizer_steps_per_epoch(num_items, config))
+}
+
+// Seeds the random draws of `B` (dropout masks, and the initial weights without
+// `portable_init`), except on candle, which cannot seed them and panics when asked to: those
+// draws are not reproducible there
+pub(crate) fn seed_backend<B: Backend>(seed: u64) {
+    if !is_candle::<B>() {
+        B::seed(seed);
+    }
+}
+
+// Whether `B` is burn's candle backend, with or without autodiff
+fn is_candle<B: Backend>() -> bool {
+    B::name().contains("candle")
+}
+
+// Number of optimizer steps per epoch of a training loader serving `num_items` items, one per
+// `grad_accumulation` batches
+fn optimizer_steps_per_epoch(num_items: usize, config: &TrainingConfig) -> usize {
+    batches_per_epoch(num_items, config.batch_size, config.num_workers) / config.grad_accumulation
+}
+
+// The rollbacks of a run that trained to the end, printed unless silent
+fn nan_events(config: &TrainingConfig, tracker: Option<NanGuardTracker>) -> Vec<NanEvent> {
+    let events = tracker.map(|tracker| tracker.events()).unwrap_or_default();
+    if config.verbosity != Verbosity::Silent {
+        for event in &events {
+            println!("{event}");
+        }
+    }
+    events
+}
+
+// What follows the learner fit of every run: the trace is written and the restart checkpoints
+// tagged, unless the run does not keep its weights
+fn finish_fit(artifact_dir: &str, config: &TrainingConfig) -> Result<(), TrainError> {
+    if let Some(path) = &config.profile {
+        profile::finish(path)?;
+    }
+    if config.save_model {
+        tag_restart_checkpoints(artifact_dir, &config.lr_schedule.restart_epochs(config.num_epochs))?;
+    }
+    Ok(())
+}
+
+// Removes the learner checkpoints of a run without `save_model`, once nothing reads them anymore
+fn discard_checkpoints(artifact_dir: &str) -> std::io::Result<()> {
+    let checkpoints = Path::new(artifact_dir).join("checkpoint");
+    if checkpoints.exists() {
+        std::fs::remove_dir_all(checkpoints)?;
+    }
+    Ok(())
+}
+
+// Collects the learner logs of `dir` into `history.json` (and `metrics.csv`) and plots them, the epoch
+// a budget cut short marked with the share of it trained
+fn save_history(dir: &ArtifactDir, config: &TrainingConfig, budget_stop: Option<&BudgetStop>) -> Result<History, TrainError> {
+    let mut history = History::from_logs(dir.as_str(), &TRACKED_METRICS).map_err(TrainError::Logs)?;
+    if let Some(stop) = budget_stop {
+        if config.verbosity != Verbosity::Silent {
+            println!("{stop}");
+        }
+        if let Some(record) = history.epochs.iter_mut().find(|record| stop.is_mid_epoch() && record.epoch == stop.epoch) {
+            record.fraction = Some(stop.epoch_fraction);
+        }
+    }
+    if config.log_accuracy_gap {
+        history = history.with_accuracy_gap();
+        // The epoch lines of `Verbosity::Summary` already showed it
+        if config.verbosity == Verbosity::Full {
+            println!("| {:>5} | {:>12} |", "Epoch", "Accuracy gap");
+            println!("|-------|--------------|");
+            for record in &history.epochs {
+                let gap = record.accuracy_gap.map_or("-".to_string(), |gap| format!("{gap:+.4}"));
+                println!("| {:>5} | {gap:>12} |", record.epoch);
+            }
+        }
+    }
+    history.save(dir.history_path())?;
+    if config.metrics_csv {
+        history.write_wandb_csv(dir.metrics_path())?;
+    }
+    plot_learning_curves(dir.as_str())?;
+    Ok(history)
+}
+
+// Writes the learner summary `Verbosity::Summary` prints once training is over to
+// `summary.txt`, followed by the architecture of the trained model
+fn save_summary<B: Backend>(dir: &ArtifactDir, model: &Model<B>) -> Result<(), TrainError> {
+    let summary = LearnerSummary::new(dir.as_str(), &TRACKED_METRICS).map_err(TrainError::Logs)?;
+    let trainable = model.adapters().map_or(model.num_params(), |adapters| adapters.num_params());
+    let counts = parameter_counts(model, trainable);
+    std::fs::write(dir.summary_path(), format!("{summary}\nModel:\n{model}\n{counts}\n"))?;
+    Ok(())
+}
+
+// How many of the parameters of `model` trained, out of all of them
+fn parameter_counts<B: Backend>(model: &Model<B>, trainable: usize) -> String {
+    format!("Parameters: {trainable} trainable, {} frozen", model.num_params() - trainable)
+}
+
+type Builder<B, T, V, O> = LearnerBuilder<B, T, V, Model<B>, ProfiledOptimizer<StatsOptimizer<O>>, Scheduler>;
+
+// The metrics only single-label runs log: accuracy, the holdout accuracy when configured, and
+// the epoch-end previews of `preview`
+fn single_label_metrics<B: AutodiffBackend, O: Optimizer<Model<B>, B>>(
+    builder: Builder<B, TrainStepOutput<B>, ValidStepOutput<B::InnerBackend>, O>,
+    config: &TrainingConfig,
+    preview: Option<PreviewMetric>,
+) -> Builder<B, TrainStepOutput<B>, ValidStepOutput<B::InnerBackend>, O> {
+    let mut builder = builder
         .metric_train_numeric(AccuracyMetric::new())
-        .metric_valid_numeric(AccuracyMetric::new())
+        .metric_valid_numeric(AccuracyMetric::new());
+    if config.holdout_dir.is_some() {
+        builder = builder.metric_valid_numeric(HoldoutAccuracyMetric::new());
+    }
+    if let Some(preview) = preview {
+        builder = builder.metric_valid(preview);
+    }
+    builder
+}
+
+// The metric multi-label runs log in place of accuracy
+fn multi_label_metrics<B: AutodiffBackend, O: Optimizer<Model<B>, B>>(
+    builder: Builder<B, MultiLabelTrainOutput<B>, MultiLabelOutput<B::InnerBackend>, O>,
+    config: &TrainingConfig,
+) -> Builder<B, MultiLabelTrainOutput<B>, MultiLabelOutput<B::InnerBackend>, O> {
+    builder
+        .metric_train_numeric(MultiLabelF1Metric::new(config.multilabel_threshold))
+        .metric_valid_numeric(MultiLabelF1Metric::new(config.multilabel_threshold))
+}
+
+// Builds the learner around `model` and the chosen optimizer, with the task metrics `metrics`
+// registers, and runs it. With `progress`, the dashboard also streams the progress events. The
+// `status_port` server, when there is one, runs for as long as the learner does.
+#[allow(clippy::too_many_arguments)]
+fn fit<B, O, TI, VI, T, V>(
+    artifact_dir: &str,
+    config: &TrainingConfig,
+    model: Model<B>,
+    optimizer: O,
+    metrics: impl FnOnce(Builder<B, T, V, O>, &TrainingConfig) -> Builder<B, T, V, O>,
+    dataloader_train: Arc<dyn DataLoader<TI>>,
+    dataloader_test: Arc<dyn DataLoader<VI>>,
+    budget: Option<BudgetTracker>,
+    nan_guard: Option<&NanGuardTracker>,
+    progress: Option<Sender<ProgressEvent>>,
+    upload: Option<&RunUpload>,
+) -> Result<(Model<B>, Option<BudgetStop>), TrainError>
+where
+    B: AutodiffBackend,
+    O: Optimizer<Model<B>, B> + 'static,
+    TI: Send + 'static,
+    VI: Send + 'static,
+    T: Adaptor<LossInput<B>> + Adaptor<GradNormInput<B::InnerBackend>> + Send + 'static,
+    V: Adaptor<LossInput<B::InnerBackend>> + Send + 'static,
+    Model<B>: TrainStep<TI, T>,
+    Model<B::InnerBackend>: ValidStep<VI, V>,
+{
+    let mut builder = metrics(LearnerBuilder::new(artifact_dir), config)
         .metric_train_numeric(LossMetric::new())
         .metric_valid_numeric(LossMetric::new())
-        .with_file_checkpointer(CompactRecorder::new())
-        .devices(vec![device.clone()])
-        .num_epochs(config.num_epochs)
-        .summary()
-        .build(
-            config.model.init::<B>(&device),
-            config.optimizer.init(),
-            config.learning_rate
+        .metric_train_numeric(MemoryMetric::new())
+        .metric_train_numeric(PeakMemoryMetric::new());
+    if config.log_grad_norm {
+        builder = builder.metric_train_numeric(GradNormMetric::new());
+    }
+    #[cfg(feature = "status-server")]
+    let status = match config.status_port {
+        Some(port) => {
+            let server = StatusServer::start(port, config.learning_rate)?;
+            if config.verbosity != Verbosity::Silent {
+                println!("Serving the training status on http://{}/status", server.address());
+            }
+            Some(server)
+        }
+        None => None,
+    };
+    if progress.is_some()
+        || config.verbosity != Verbosity::Full
+        || config.loss_smoothing_window > 1
+        || config.status_port.is_some()
+        || config.prometheus_metrics
+    {
+        let interrupter = builder.interrupter();
+        let renderer = ProgressRenderer::new(
+            progress,
+            interrupter,
+            config.verbosity,
+            config.log_accuracy_gap,
+            config.loss_smoothing_window,
+        )
+        .with_prometheus(
+            config.prometheus_metrics.then(|| PrometheusExporter::new(ArtifactDir::new(artifact_dir).prometheus_path())),
         );
+        #[cfg(feature = "status-server")]
+        let renderer = renderer.with_status(status.as_ref().map(StatusServer::sender));
+        builder = builder.renderer(renderer);
+    }
 
-    let model_trained = learner.fit(dataLoader_train, dataLoader_test);
+    let steps_per_epoch =
+        batches_per_epoch(dataloader_train.num_items(), config.batch_size, config.num_workers);
+    let scheduler = Scheduler::new(config.lr_schedule.clone(), config.learning_rate, steps_per_epoch);
+
+    let restart_epochs = config.lr_schedule.restart_epochs(config.num_epochs);
+    let mut kept_epochs = restart_epochs.clone();
+    if let Some(swa) = &config.swa {
+        kept_epochs.extend(swa_epochs(swa, config.num_epochs));
+    }
+    if config.lr_schedule != LrSchedule::Constant {
+        let restart_steps = scheduler.restart_steps(config.num_epochs);
+        builder = builder
+            .metric_train_numeric(LearningRateMetric::new())
+            .metric_train_numeric(RestartMetric::new(restart_steps, steps_per_epoch));
+    }
+    if let Some(multipliers) = &config.lr_multipliers {
+        if config.lr_schedule == LrSchedule::Constant {
+            builder = builder.metric_train_numeric(LearningRateMetric::new());
+        }
+        for (prefix, multiplier) in multipliers {
+            builder = builder.metric_train_numeric(GroupLearningRateMetric::new(prefix, *multiplier));
+        }
+    }
+    // Burn's default strategy, with `keep_last` recent checkpoints and without the deletion of
+    // restart and SWA epoch checkpoints
+    let default_strategy = ComposedCheckpointingStrategy::builder()
+        .add(KeepLastNCheckpoints::new(config.keep_last.unwrap_or(2)))
+        .add(MetricCheckpointingStrategy::new::<LossMetric<B>>(
+            Aggregate::Mean,
+            Direction::Lowest,
+            Split::Valid,
+        ))
+        .build();
+    builder.with_checkpointing_strategy(KeepEpochCheckpoints::new(default_strategy, kept_epochs));
+
+    let mut optimizer = StatsOptimizer::new(optimizer);
+    if config.export_optimizer_stats {
+        let path = Path::new(artifact_dir).join(OPTIMIZER_STATS_FILE);
+        optimizer = optimizer.with_export(path, config.optimizer_kind.clone(), param_names(&model));
+    }
+    if config.log_update_ratios {
+        let path = Path::new(artifact_dir).join(UPDATE_RATIOS_FILE);
+        optimizer = optimizer.with_update_ratios(path, param_names(&model));
+    }
 
-    model_trained.save_file(format!("{artifact_dir}/model"), &CompactRecorder::new())
-        .expect("Trained Model should be saved successfully!");
+    if let Some(budget) = &budget {
+        budget.watch(builder.interrupter());
+    }
+    if let Some(nan_guard) = nan_guard {
+        nan_guard.watch(builder.interrupter());
+    }
 
+    let mut builder = builder
+        // Without `save_model` the checkpoints are discarded, and never uploaded
+        .with_file_checkpointer(UploadingRecorder::new(
+            ProfiledRecorder::new(CompactRecorder::new()),
+            upload.filter(|_| config.save_model).cloned(),
+        ))
+        .devices(vec![model.devices()[0].clone()])
+        .num_epochs(config.num_epochs);
+    if config.grad_accumulation > 1 {
+        builder = builder.grads_accumulation(config.grad_accumulation);
+    }
+    if config.verbosity != Verbosity::Silent {
+        builder = builder.summary();
+    }
+    let learner = builder
+        .build(
+            model,
+            ProfiledOptimizer::new(optimizer),
+            scheduler,
+        );
 
+    let model = learner.fit(dataloader_train, dataloader_test);
+    if let Some(event) = nan_guard.and_then(NanGuardTracker::halt) {
+        return Err(TrainError::NonFinite(event));
+    }
+    Ok((model, budget.and_then(|budget| budget.stop())))
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        budget::BudgetLimit,
+        curriculum::CurriculumScore,
+        params::named_params,
+        synthetic::{temp_artifact_dir, tiny_config, train_synthetic, SyntheticDigits},
+    };
+    use burn::backend::{ndarray::NdArrayDevice, Autodiff, NdArray};
+
+    // A change of a valid config breaking one validation rule
+    type BreakRule = fn(&mut TrainingConfig);
+
+    // A config every rule accepts
+    fn valid_config() -> TrainingConfig {
+        TrainingConfig::new(ModelConfig::new(10, 32), AdamConfig::new())
+    }
+
+    fn invalid_fields(config: &TrainingConfig) -> Vec<String> {
+        config.validate().err().unwrap_or_default().into_iter().map(|err| err.field).collect()
+    }
+
+    fn with_adapters(config: &mut TrainingConfig) -> &mut AdapterConfig {
+        config.pretrained_weights = Some("model.safetensors".into());
+        config.adapters.insert(AdapterConfig::new(4, 8.0))
+    }
+
+    fn with_restarts(config: &mut TrainingConfig) {
+        config.num_epochs = 4;
+        config.lr_schedule = LrSchedule::CosineWarmRestarts { t_initial: 2, t_mult: 1, min_lr: 0.0 };
+    }
+
+    #[test]
+    fn validate_accepts_the_default_config() {
+        assert_eq!(valid_config().validate(), Ok(()));
+    }
+
+    #[test]
+    fn validate_reports_the_field_of_every_rule() {
+        // The field reported for each rule
+        let rules: Vec<(&str, BreakRule)> = vec![
+            ("model.task", |config| config.model.task = TaskKind::MultiLabel),
+            ("multilabel_threshold", |config| config.multilabel_threshold = 1.0),
+            ("num_epochs", |config| config.num_epochs = 0),
+            ("batch_size", |config| config.batch_size = 0),
+            ("grad_accumulation", |config| config.grad_accumulation = 0),
+            ("hard_label_weight", |config| config.hard_label_weight = 1.5),
+            ("balanced_sampling", |config| {
+                config.soft_labels = Some("soft_labels.npy".into());
+                config.balanced_sampling = true;
+            }),
+            ("aux_loss_weight", |config| config.aux_loss_weight = -1.0),
+            ("binary_target", |config| {
+                config.model.aux_task = Some(AuxTask::Parity);
+                config.binary_target = Some(3);
+            }),
+            ("train_subset", |config| {
+                config.split_from = Some("split_indices.json".into());
+                config.train_subset = Some("train_subset.json".into());
+            }),
+            ("checkpoint_store", |config| config.checkpoint_store = Some("s3:///prefix".to_string())),
+            ("onnx_export_every", |config| config.onnx_export_every = Some(0)),
+            ("keep_last", |config| config.keep_last = Some(0)),
+            ("max_steps", |config| config.max_steps = Some(0)),
+            ("max_duration", |config| config.max_duration = Some(Duration::ZERO)),
+            ("nan_max_grad_norm", |config| config.nan_max_grad_norm = Some(f64::INFINITY)),
+            ("loss_smoothing_window", |config| config.loss_smoothing_window = 0),
+            ("learning_rate", |config| config.learning_rate = -1e-3),
+            ("lr_multipliers[0].multiplier", |config| config.lr_multipliers = Some(vec![("conv1".to_string(), -1.0)])),
+            ("lr_multipliers[1].prefix", |config| {
+                config.lr_multipliers = Some(vec![("conv1".to_string(), 0.1), ("conv1".to_string(), 0.5)]);
+            }),
+            ("model.hidden_size", |config| config.model.hidden_size = 0),
+            ("model.dropout", |config| config.model.dropout = 1.0),
+            ("model.bn_momentum", |config| config.model.bn_momentum = 1.5),
+            ("model.bn_epsilon", |config| config.model.bn_epsilon = 0.0),
+            ("model.head_input.size", |config| config.model.head_input = HeadInput::AdaptiveAvgPool { size: 0 }),
+            ("model.head_input.size", |config| config.model.head_input = HeadInput::AdaptiveAvgPool { size: 25 }),
+            ("progressive_resize", |config| {
+                config.model.head_input = HeadInput::Flatten;
+                config.progressive_resize = Some(vec![(1, 16)]);
+            }),
+            ("model.head_input", |config| {
+                config.model.global_pool = GlobalPool::Avg;
+                config.model.head_input = HeadInput::Flatten;
+            }),
+            ("classes", |config| config.classes = Some(Vec::new())),
+            ("classes[0]", |config| config.classes = Some(vec![10])),
+            ("classes[1]", |config| config.classes = Some(vec![1, 1])),
+            ("model.num_classes", |config| config.model.num_classes = 5),
+            ("binary_target", |config| {
+                config.classes = Some(vec![1, 2]);
+                config.binary_target = Some(1);
+            }),
+            ("binary_target", |config| config.binary_target = Some(10)),
+            ("lr_schedule.t_initial", |config| {
+                config.lr_schedule = LrSchedule::CosineWarmRestarts { t_initial: 0, t_mult: 1, min_lr: 0.0 };
+            }),
+            ("lr_schedule.t_mult", |config| {
+                config.lr_schedule = LrSchedule::CosineWarmRestarts { t_initial: 1, t_mult: 0, min_lr: 0.0 };
+            }),
+            ("lr_schedule.min_lr", |config| {
+                config.lr_schedule = LrSchedule::CosineWarmRestarts { t_initial: 1, t_mult: 1, min_lr: 1.0 };
+            }),
+            ("valid_every_steps", |config| config.valid_every_steps = Some(0)),
+            ("valid_subset_size", |config| config.valid_subset_size = Some(16)),
+            ("valid_subset_size", |config| {
+                config.valid_every_steps = Some(100);
+                config.valid_subset_size = Some(0);
+            }),
+            ("pretrained_weights", |config| {
+                config.resume_from = Some("runs/previous".to_string());
+                config.pretrained_weights = Some("model.safetensors".into());
+            }),
+            ("adapters", |config| config.adapters = Some(AdapterConfig::new(4, 8.0))),
+            ("swa", |config| {
+                with_adapters(config);
+                config.swa = Some(SwaConfig::new(1));
+            }),
+            ("snapshot_ensemble", |config| {
+                with_adapters(config);
+                with_restarts(config);
+                config.snapshot_ensemble = true;
+            }),
+            ("adapters.rank", |config| with_adapters(config).rank = 0),
+            ("adapters.alpha", |config| with_adapters(config).alpha = 0.0),
+            ("adapters.target_modules", |config| with_adapters(config).target_modules = Vec::new()),
+            ("adapters.target_modules[0]", |config| with_adapters(config).target_modules = vec!["conv3".to_string()]),
+            ("adapters.target_modules[1]", |config| {
+                with_adapters(config).target_modules = vec!["linear1".to_string(), "linear1".to_string()];
+            }),
+            ("preview_samples", |config| config.preview_samples = Some(0)),
+            ("preview_indices", |config| {
+                config.preview_samples = Some(4);
+                config.preview_indices = Some(vec![0]);
+            }),
+            ("preview_indices", |config| config.preview_indices = Some(Vec::new())),
+            ("max_batch_size", |config| {
+                config.auto_batch_size = true;
+                config.max_batch_size = 0;
+            }),
+            ("activation_checkpointing", |config| config.activation_checkpointing = true),
+            ("mixup_alpha", |config| config.mixup_alpha = Some(0.0)),
+            ("cutmix_alpha", |config| config.cutmix_alpha = Some(f64::NAN)),
+            ("batch_size", |config| {
+                config.batch_size = 1;
+                config.grad_accumulation = 32;
+                config.mixup_alpha = Some(0.2);
+            }),
+            ("swa.start_epoch", |config| config.swa = Some(SwaConfig::new(0))),
+            ("swa.start_epoch", |config| config.swa = Some(SwaConfig::new(config.num_epochs + 1))),
+            ("swa.frequency", |config| config.swa = Some(SwaConfig::new(1).with_frequency(0))),
+            ("snapshot_ensemble", |config| config.snapshot_ensemble = true),
+            ("curriculum.num_epochs", |config| {
+                config.curriculum = Some(CurriculumConfig::new(CurriculumScore::PixelCount).with_num_epochs(0));
+            }),
+            ("hard_mining.interval_epochs", |config| {
+                config.hard_mining = Some(HardMiningConfig::new().with_interval_epochs(0));
+            }),
+            ("hard_mining.top_fraction", |config| config.hard_mining = Some(HardMiningConfig::new().with_top_fraction(0.0))),
+            ("hard_mining.oversample_factor", |config| {
+                config.hard_mining = Some(HardMiningConfig::new().with_oversample_factor(0.5));
+            }),
+            ("hard_mining", |config| {
+                config.curriculum = Some(CurriculumConfig::new(CurriculumScore::PixelCount));
+                config.hard_mining = Some(HardMiningConfig::new());
+            }),
+            ("curriculum", |config| {
+                config.curriculum = Some(CurriculumConfig::new(CurriculumScore::PixelCount));
+                config.balanced_sampling = true;
+            }),
+            ("preprocess[0].tile_size", |config| {
+                config.preprocess = vec![PreprocessConfig::Clahe { tile_size: 0, clip_limit: 2.0 }];
+            }),
+            ("preprocess[0].clip_limit", |config| {
+                config.preprocess = vec![PreprocessConfig::Clahe { tile_size: 8, clip_limit: 0.5 }];
+            }),
+            ("preprocess[0].low_percentile", |config| {
+                config.preprocess = vec![PreprocessConfig::Stretch { low_percentile: -1.0, high_percentile: 99.0 }];
+            }),
+            ("preprocess[0].high_percentile", |config| {
+                config.preprocess = vec![PreprocessConfig::Stretch { low_percentile: 50.0, high_percentile: 50.0 }];
+            }),
+            ("progressive_resize", |config| config.progressive_resize = Some(Vec::new())),
+            ("progressive_resize[1].first_epoch", |config| config.progressive_resize = Some(vec![(1, 16), (1, 28)])),
+            ("progressive_resize[0].size", |config| {
+                config.model.head_input = HeadInput::AdaptiveAvgPool { size: 1 };
+                config.progressive_resize = Some(vec![(1, 4)]);
+            }),
+            // The conv features of 10x10 images are 6x6, smaller than the 8x8 pooling
+            ("progressive_resize[0].size", |config| config.progressive_resize = Some(vec![(1, 10)])),
+            ("momentum", |config| config.momentum = 1.0),
+            ("nesterov", |config| config.nesterov = true),
+            ("adam_beta1", |config| config.adam_beta1 = 1.0),
+            ("adam_beta2", |config| config.adam_beta2 = -0.1),
+            ("adam_epsilon", |config| config.adam_epsilon = 0.0),
+            ("adam_epsilon", |config| {
+                config.precision = PrecisionKind::F16;
+                config.adam_epsilon = 1e-5;
+            }),
+            ("valid_fraction", |config| config.valid_fraction = Some(1.5)),
+            ("split_from", |config| {
+                config.valid_fraction = Some(0.1);
+                config.split_from = Some("split_indices.json".into());
+            }),
+            ("train_subset", |config| {
+                config.valid_fraction = Some(0.1);
+                config.train_subset = Some("train_subset.json".into());
+            }),
+            ("dataset", |config| config.dataset = DatasetSource::Concat(Vec::new())),
+        ];
+
+        for (field, break_rule) in rules {
+            let mut config = valid_config();
+            break_rule(&mut config);
+            let fields = invalid_fields(&config);
+            assert!(fields.iter().any(|found| found == field), "expected `{field}` to be reported, got {fields:?}");
+        }
+    }
+
+    #[test]
+    fn validate_rejects_settings_multi_label_models_do_not_support() {
+        let mut config = valid_config();
+        config.model.task = TaskKind::MultiLabel;
+        config.mixup_alpha = Some(0.2);
+        config.balanced_sampling = true;
+
+        let errors = config.validate_for(MNIST_NUM_CLASSES, TaskKind::MultiLabel).unwrap_err();
+        let fields: Vec<&str> = errors.iter().map(|err| err.field.as_str()).collect();
+        assert_eq!(fields, ["mixup_alpha", "balanced_sampling"]);
+    }
+
+    #[test]
+    fn validate_rejects_features_the_build_does_not_have() {
+        let mut config = valid_config();
+        config.onnx_export_every = Some(1);
+        config.status_port = Some(8080);
+
+        let fields = invalid_fields(&config);
+        assert_eq!(fields.contains(&"onnx_export_every".to_string()), !cfg!(feature = "onnx"));
+        assert_eq!(fields.contains(&"status_port".to_string()), !cfg!(feature = "status-server"));
+    }
+
+    #[test]
+    fn validate_collects_every_error() {
+        let mut config = valid_config();
+        config.batch_size = 0;
+        config.learning_rate = -0.01;
+        config.num_epochs = 0;
+        config.valid_fraction = Some(1.5);
+
+        let errors = config.validate().unwrap_err();
+        let fields: Vec<&str> = errors.iter().map(|err| err.field.as_str()).collect();
+        assert_eq!(fields, ["num_epochs", "batch_size", "learning_rate", "valid_fraction"]);
+        assert_eq!(errors[1], ConfigError::new("batch_size", 0, ">= 1"));
+        assert_eq!(errors[3].to_string(), "`valid_fraction` = 1.5 is invalid, expected a value in (0, 1)");
+    }
+
+    #[test]
+    fn check_backend_requires_the_float_type_of_the_backend() {
+        let mut config = valid_config();
+        assert_eq!(config.check_backend::<NdArray>(), Ok(()));
+
+        config.precision = PrecisionKind::F16;
+        let errors = config.check_backend::<NdArray>().unwrap_err();
+        assert_eq!(errors, [ConfigError::new("precision", "F16", "F32, the only precision of the backend")]);
+
+        // Both entry points reject it before loading (here downloading) MNIST
+        config.adam_epsilon = F16_MIN_ADAM_EPSILON;
+        let artifact_dir = std::env::temp_dir().join("my_first_rust_DL_app-check-backend");
+        let device = NdArrayDevice::default();
+        let trained = train::<Autodiff<NdArray>>(&artifact_dir, config.clone(), device);
+        assert!(matches!(trained, Err(TrainError::InvalidConfig(found)) if found == errors));
+        let (sender, _receiver) = std::sync::mpsc::channel();
+        let streamed = train_with_progress::<Autodiff<NdArray>>(&artifact_dir, config, device, sender);
+        assert!(matches!(streamed, Err(TrainError::InvalidConfig(found)) if found == errors));
+        assert!(!artifact_dir.exists());
+    }
+
+    #[test]
+    fn validate_datasets_checks_the_images_against_the_model() {
+        let model = ModelConfig::new(10, 32);
+        let flatten = model.clone().with_head_input(HeadInput::Flatten);
+        let cases = [
+            (&model, [28, 28], [28, 28], 100, vec![]),
+            (&flatten, [32, 32], [32, 32], 100, vec!["model.head_input"]),
+            (&model, [4, 4], [4, 4], 100, vec!["dataset.image_shape"]),
+            // 6x6 conv features, smaller than the 8x8 pooling
+            (&model, [10, 10], [10, 10], 100, vec!["dataset.image_shape"]),
+            (&model, [28, 28], [32, 32], 100, vec!["valid_set.image_shape"]),
+            (&model, [28, 28], [28, 28], 0, vec!["train_set.len"]),
+        ];
+
+        for (model, train_shape, valid_shape, train_len, expected) in cases {
+            let errors = validate_datasets(model, train_shape, valid_shape, train_len);
+            let fields: Vec<&str> = errors.iter().map(|err| err.field.as_str()).collect();
+            assert_eq!(fields, expected, "{train_shape:?} and {valid_shape:?} images");
+        }
+    }
+
+    #[test]
+    fn valid_fraction_validates_on_held_out_training_items() {
+        let mut config = valid_config();
+        config.valid_fraction = Some(0.2);
+        let train_set: MnistSet = Arc::new(SyntheticDigits::new(100, 1));
+        let test_set: MnistSet = Arc::new(SyntheticDigits::new(50, 2));
+
+        let (train, valid, split) = apply_split(&config, train_set.clone(), test_set).unwrap();
+        assert_eq!((Dataset::len(train.as_ref()), Dataset::len(valid.as_ref())), (80, 20));
+        // Both index the training split, whose labels cycle through the classes: two of each
+        // are held out
+        assert_eq!(split.valid_fingerprint, None);
+        let mut counts = [0; 10];
+        for (position, &index) in split.valid.iter().enumerate() {
+            let (_, label) = ClassificationDataset::get(&valid, position).unwrap();
+            assert_eq!(label, ClassificationDataset::get(&train_set, index).unwrap().1);
+            counts[label] += 1;
+        }
+        assert_eq!(counts, [2; 10]);
+        let mut indices = [split.train, split.valid].concat();
+        indices.sort_unstable();
+        assert_eq!(indices, (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn sgd_config_carries_the_momentum_settings() {
+        // burn keeps the fields of `SgdConfig` private, its JSON shows them
+        let momentum = |config: &TrainingConfig| serde_json::to_value(config.sgd_config()).unwrap()["momentum"].clone();
+        let mut config = valid_config();
+        assert!(momentum(&config).is_null(), "momentum 0 should be plain SGD");
+
+        config.momentum = 0.9;
+        config.nesterov = true;
+        assert_eq!(momentum(&config), serde_json::json!({ "momentum": 0.9, "dampening": 0.0, "nesterov": true }));
+    }
+
+    // The `train_on` example dataset: three 12x12 classes, an empty, a half-filled and a full
+    // image. The smallest images whose conv features cover the 8x8 pooling.
+    struct Levels;
+
+    impl ClassificationDataset for Levels {
+        fn len(&self) -> usize {
+            48
+        }
+
+        fn get(&self, index: usize) -> Option<(Vec<f32>, usize)> {
+            let label = index % 3;
+            (index < 48).then(|| (vec![label as f32 * 127.5; 144], label))
+        }
+
+        fn num_classes(&self) -> usize {
+            3
+        }
+
+        fn image_shape(&self) -> [usize; 2] {
+            [12, 12]
+        }
+    }
+
+    #[test]
+    fn train_on_learns_from_a_custom_dataset() {
+        let artifact_dir = temp_artifact_dir("train-levels");
+        let config = TrainingConfig::new(ModelConfig::new(3, 8), AdamConfig::new())
+            .with_num_epochs(1)
+            .with_batch_size(16)
+            .with_num_workers(1)
+            .with_verbosity(Verbosity::Silent);
+
+        let device = NdArrayDevice::default();
+        let model = train_on::<Autodiff<NdArray>, _>(artifact_dir.to_str().unwrap(), config, Levels, Levels, device)
+            .unwrap()
+            .valid();
+        let history = History::load(ArtifactDir::new(artifact_dir.to_str().unwrap()).history_path()).unwrap();
+        std::fs::remove_dir_all(&artifact_dir).unwrap();
+
+        assert_eq!(history.epochs.len(), 1);
+        assert!(history.epochs[0].train["Loss"].is_finite());
+        let logits = model.forward(Tensor::zeros([2, 12, 12], &device));
+        assert_eq!(logits.dims(), [2, 3]);
+    }
+
+    #[test]
+    fn sum_reduced_loss_is_the_mean_times_the_batch_size() {
+        let device = NdArrayDevice::default();
+        let model = ModelConfig::new(10, 8).init::<NdArray>(&device);
+        let items = SyntheticDigits::new(6, 1).iter().map(crate::data::ClassificationItem::from).collect();
+        let batch = MnistBatcher::<NdArray>::new(device).with_mixup(0.4, 10, 0).batch(items);
+
+        let loss = |reduction, soft_targets| {
+            let loss = LossSettings { reduction, ..LossSettings::default() };
+            let output = model.forward_classification(batch.images.clone(), batch.targets.clone(), soft_targets, loss);
+            output.loss.into_scalar()
+        };
+        // Both the hard label and the soft (mixed) label losses
+        for soft_targets in [None, batch.soft_targets.clone()] {
+            let (mean, sum) = (loss(Reduction::Mean, soft_targets.clone()), loss(Reduction::Sum, soft_targets));
+            assert!((sum - mean * 6.0).abs() < 1e-4 * sum.abs().max(1.0), "sum {sum}, mean {mean}");
+        }
+    }
+
+    #[test]
+    fn batch_norm_model_is_saved_with_its_running_statistics() {
+        let artifact_dir = temp_artifact_dir("train-batch-norm");
+        let mut config = tiny_config();
+        config.model.batch_norm = true;
+
+        let device = NdArrayDevice::default();
+        let model = train_synthetic(&artifact_dir, config, 32, 16).unwrap().valid();
+        let loaded = crate::inference::load_model::<NdArray>(&artifact_dir, &device).unwrap();
+        std::fs::remove_dir_all(&artifact_dir).unwrap();
+
+        // Inference normalizes with the running statistics, which training moved off 0. The
+        // weights are saved in half precision.
+        let images = MnistBatcher::<NdArray>::new(device).batch(SyntheticDigits::new(8, 3).iter().collect()).images;
+        let difference = (loaded.forward(images.clone()) - model.forward(images)).abs().max().into_scalar();
+        assert!(difference < 1e-2, "the loaded model is {difference} off");
+        let params = named_params(&loaded);
+        let running_mean = params.iter().find(|param| param.name == "conv2_norm.running_mean").unwrap();
+        assert!(running_mean.values.iter().any(|mean| *mean != 0.0), "{running_mean:?}");
+    }
+
+    // A one-epoch run on synthetic digits at batch size 16, to resume from
+    fn resumable_run(name: &str) -> (String, TrainingConfig) {
+        let artifact_dir = temp_artifact_dir(&format!("resume-{name}")).to_str().unwrap().to_string();
+        let config = tiny_config().with_learning_rate(1e-3);
+        train_synthetic(&artifact_dir, config.clone(), 32, 16).unwrap();
+        (artifact_dir, config)
+    }
+
+    #[test]
+    fn resumed_run_trains_the_loaded_weights_at_the_overridden_learning_rate() {
+        let (resumed, config) = resumable_run("lr");
+        let artifact_dir = format!("{resumed}-finetune");
+        let _ = std::fs::remove_dir_all(&artifact_dir);
+        // Too low to move the weights noticeably, and annealed from the first step on
+        let config = config
+            .with_resume_from(Some(resumed.clone()))
+            .with_learning_rate(1e-9)
+            .with_lr_schedule(LrSchedule::CosineWarmRestarts { t_initial: 1, t_mult: 1, min_lr: 0.0 });
+
+        let device = NdArrayDevice::default();
+        let model = train_synthetic(&artifact_dir, config, 32, 16).unwrap();
+        let learning_rates = std::fs::read_to_string(format!("{artifact_dir}/train/epoch-1/Learning_Rate.log")).unwrap();
+        let loaded = named_params(&crate::inference::load_model::<NdArray>(&resumed, &device).unwrap());
+        std::fs::remove_dir_all(&resumed).unwrap();
+        std::fs::remove_dir_all(&artifact_dir).unwrap();
+
+        let learning_rate: f64 = learning_rates.lines().next().unwrap().split(',').next().unwrap().parse().unwrap();
+        assert!((learning_rate - 1e-9).abs() < 1e-12, "started at a learning rate of {learning_rate}");
+        for (trained, loaded) in named_params(&model.valid()).iter().zip(&loaded) {
+            let close = trained.values.iter().zip(&loaded.values).all(|(a, b)| (a - b).abs() < 1e-5);
+            assert!(close, "{} moved away from the resumed weights", trained.name);
+        }
+    }
+
+    #[test]
+    fn run_without_save_model_leaves_only_the_config_and_history() {
+        let artifact_dir = temp_artifact_dir("no-save-model");
+        let config = tiny_config().with_save_model(false);
+
+        train_synthetic(&artifact_dir, config, 32, 16).unwrap();
+        let dir = ArtifactDir::new(artifact_dir.to_str().unwrap());
+        let exists = |path: String| Path::new(&path).exists();
+        let model_exists = exists(format!("{}.mpk", dir.model_path(ModelKind::Final)));
+        let checkpoints_exist = artifact_dir.join("checkpoint").exists();
+        let (config_exists, history_exists) = (exists(dir.config_path()), exists(dir.history_path()));
+        std::fs::remove_dir_all(&artifact_dir).unwrap();
+
+        assert!(!model_exists && !checkpoints_exist);
+        assert!(config_exists && history_exists);
+    }
+
+    #[test]
+    fn accuracy_gap_is_the_train_minus_the_valid_accuracy_of_every_epoch() {
+        let artifact_dir = temp_artifact_dir("accuracy-gap");
+        let config = tiny_config().with_log_accuracy_gap(true).with_num_epochs(2);
+
+        train_synthetic(&artifact_dir, config, 32, 16).unwrap();
+        let history = History::load(ArtifactDir::new(artifact_dir.to_str().unwrap()).history_path()).unwrap();
+        std::fs::remove_dir_all(&artifact_dir).unwrap();
+
+        assert_eq!(history.epochs.len(), 2);
+        for record in &history.epochs {
+            let gap = record.train["Accuracy"] - record.valid["Accuracy"];
+            assert_eq!(record.accuracy_gap, Some(gap), "epoch {}", record.epoch);
+        }
+    }
+
+    #[test]
+    fn environment_variables_override_the_loaded_config() {
+        let path = std::env::temp_dir().join("my_first_rust_DL_app-env-overrides.json");
+        valid_config().with_num_epochs(5).with_batch_size(32).save(&path).unwrap();
+        // The only test reading these variables
+        let vars = ["DL_NUM_EPOCHS", "DL_BATCH_SIZE", "DL_LR", "DL_NUM_WORKERS", "DL_SEED"];
+        for var in vars {
+            std::env::remove_var(var);
+        }
+
+        let unset = TrainingConfig::load_with_env_overrides(&path).unwrap();
+        std::env::set_var("DL_NUM_EPOCHS", "1");
+        std::env::set_var("DL_LR", " 0.5 ");
+        let overridden = TrainingConfig::load_with_env_overrides(&path).unwrap();
+        std::env::set_var("DL_BATCH_SIZE", "many");
+        std::env::set_var("DL_NUM_EPOCHS", "0");
+        let invalid = TrainingConfig::load_with_env_overrides(&path);
+        for var in vars {
+            std::env::remove_var(var);
+        }
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!((unset.num_epochs, unset.batch_size), (5, 32));
+        assert_eq!((overridden.num_epochs, overridden.batch_size, overridden.learning_rate), (1, 32, 0.5));
+        match invalid {
+            Err(EnvConfigError::InvalidOverrides(errors)) => {
+                let fields: Vec<&str> = errors.iter().map(|err| err.field.as_str()).collect();
+                assert_eq!(fields, ["DL_BATCH_SIZE", "DL_NUM_EPOCHS"]);
+                assert_eq!((errors[0].value.as_str(), errors[0].expected.as_str()), ("many", "an integer"));
+            }
+            other => panic!("expected invalid overrides, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn step_budget_ends_the_run_mid_epoch_or_between_epochs() {
+        // Four steps per epoch: three end the first one early, four end it whole
+        for (max_steps, fraction) in [(3, 0.75), (4, 1.0)] {
+            let artifact_dir = temp_artifact_dir(&format!("budget-{max_steps}"));
+            let config = tiny_config().with_max_steps(Some(max_steps)).with_num_epochs(3);
+
+            let dir = artifact_dir.to_str().unwrap();
+            train_synthetic(dir, config, 64, 16).unwrap();
+            let stop = ModelMeta::load(dir).unwrap().unwrap().budget_stop.unwrap();
+            let history = History::load(ArtifactDir::new(dir).history_path()).unwrap();
+            let steps = std::fs::read_to_string(artifact_dir.join("train/epoch-1/Loss.log")).unwrap().lines().count();
+            let second_epoch = artifact_dir.join("train/epoch-2").exists();
+            std::fs::remove_dir_all(&artifact_dir).unwrap();
+
+            assert_eq!(stop.budget, BudgetLimit::MaxSteps(max_steps));
+            assert_eq!((stop.step, stop.epoch, stop.epoch_fraction), (max_steps, 1, fraction));
+            assert_eq!((steps, second_epoch), (max_steps, false));
+            assert_eq!(history.epochs.len(), 1);
+            // The cut-short epoch is still validated
+            assert!(history.epochs[0].valid.contains_key("Accuracy"));
+            assert_eq!(history.epochs[0].fraction, (fraction < 1.0).then_some(fraction));
+        }
+    }
+
+    #[test]
+    fn max_train_seconds_sets_the_time_budget() {
+        let (_, field, apply) = ENV_OVERRIDES.iter().find(|(var, ..)| *var == "DL_MAX_TRAIN_SECONDS").unwrap();
+        assert_eq!(*field, "max_duration");
+        let mut config = valid_config();
+        apply(&mut config, "5400").unwrap();
+        assert_eq!(config.max_duration, Some(Duration::from_secs(5400)));
+        assert_eq!(apply(&mut config, "1.5"), Err("an integer number of seconds"));
+        assert_eq!(apply(&mut config, "-1"), Err("an integer number of seconds"));
+        assert_eq!(config.max_duration, Some(Duration::from_secs(5400)));
+    }
+
+    #[test]
+    fn max_train_seconds_stops_training_early_and_saves_the_model() {
+        let artifact_dir = temp_artifact_dir("max-train-seconds");
+        let (_, _, apply) = ENV_OVERRIDES.iter().find(|(var, ..)| *var == "DL_MAX_TRAIN_SECONDS").unwrap();
+        // Far more epochs than a second trains
+        let mut config = tiny_config().with_num_epochs(10_000);
+        apply(&mut config, "1").unwrap();
+
+        let device = NdArrayDevice::default();
+        let dir = artifact_dir.to_str().unwrap();
+        train_synthetic(dir, config, 64, 16).unwrap();
+        let stop = ModelMeta::load(dir).unwrap().unwrap().budget_stop.unwrap();
+        let history = History::load(ArtifactDir::new(dir).history_path()).unwrap();
+        let loaded = crate::inference::load_model::<NdArray>(dir, &device);
+        std::fs::remove_dir_all(&artifact_dir).unwrap();
+
+        assert_eq!(stop.budget, BudgetLimit::MaxDuration(Duration::from_secs(1)));
+        assert!(stop.elapsed_seconds >= 1.0 && stop.epoch < 10_000, "{stop:?}");
+        assert_eq!(history.epochs.len(), stop.epoch);
+        assert!(history.epochs.last().unwrap().valid.contains_key("Accuracy"));
+        assert!(loaded.is_ok(), "the model of the stopped run should load: {:?}", loaded.err());
+    }
+
+    #[test]
+    fn summary_file_holds_the_learner_summary_and_the_architecture() {
+        for summary_file in [true, false] {
+            let artifact_dir = temp_artifact_dir(&format!("summary-{summary_file}"));
+            let config = tiny_config().with_summary_file(summary_file);
+            let model = train_synthetic(&artifact_dir, config, 32, 16).unwrap();
+            let summary = std::fs::read_to_string(artifact_dir.join(SUMMARY_FILE));
+            std::fs::remove_dir_all(&artifact_dir).unwrap();
+
+            if !summary_file {
+                assert!(summary.is_err(), "summary.txt written without summary_file");
+                continue;
+            }
+            let summary = summary.unwrap();
+            assert!(summary.contains("Accuracy") && summary.contains("Loss"), "{summary}");
+            let num_params = model.num_params();
+            let architecture =
+                format!("\nModel:\nModel[num_params={num_params}]\nParameters: {num_params} trainable, 0 frozen\n");
+            assert!(summary.ends_with(&architecture), "{summary}");
+        }
+    }
+
+    #[test]
+    fn keep_last_keeps_the_recent_checkpoints_and_the_best_one() {
+        for keep_last in [1, 3] {
+            let artifact_dir = temp_artifact_dir(&format!("keep-last-{keep_last}"));
+            let dir = ArtifactDir::new(&artifact_dir);
+            let config = tiny_config().with_keep_last(Some(keep_last)).with_num_epochs(4);
+            train_synthetic(dir.as_str(), config, 32, 16).unwrap();
+            let history = History::load(dir.history_path()).unwrap();
+            let kept: Vec<usize> =
+                (1..=4).filter(|&epoch| Path::new(&format!("{}.mpk", dir.checkpoint_path(epoch))).exists()).collect();
+            std::fs::remove_dir_all(&artifact_dir).unwrap();
+
+            let best = history
+                .epochs
+                .iter()
+                .min_by(|a, b| a.valid["Loss"].total_cmp(&b.valid["Loss"]))
+                .map(|record| record.epoch)
+                .unwrap();
+            let mut expected: Vec<usize> = (5 - keep_last..=4).chain([best]).collect();
+            expected.sort();
+            expected.dedup();
+            assert_eq!(kept, expected, "keep_last {keep_last}, best epoch {best}");
+        }
+    }
+
+    #[test]
+    fn one_hot_soft_labels_give_the_hard_label_loss() {
+        let device = NdArrayDevice::default();
+        let model = ModelConfig::new(10, 8).init::<NdArray>(&device);
+        let digits = SyntheticDigits::new(12, 1);
+        let batch = MnistBatcher::<NdArray>::new(device).batch(digits.iter().collect());
+        let rows: Vec<f32> =
+            digits.iter().flat_map(|item| (0..10).map(move |class| f32::from(class == item.label))).collect();
+        let one_hot = Tensor::<NdArray, 1>::from_floats(rows.as_slice(), &device).reshape([12, 10]);
+
+        let hard = model
+            .forward_classification(batch.images.clone(), batch.targets.clone(), None, LossSettings::default())
+            .loss
+            .into_scalar();
+        for hard_weight in [0.0, 0.3, 1.0] {
+            let soft = model
+                .forward_soft(batch.images.clone(), batch.targets.clone(), one_hot.clone(), hard_weight, Reduction::Mean)
+                .loss
+                .into_scalar();
+            assert!((soft - hard).abs() < 1e-5, "hard weight {hard_weight}: {soft} against {hard}");
+        }
+    }
+
+    #[test]
+    fn soft_labels_of_the_wrong_count_leave_the_artifact_dir_untouched() {
+        let artifact_dir = temp_artifact_dir("soft-labels-count");
+        std::fs::create_dir_all(&artifact_dir).unwrap();
+        let previous = artifact_dir.join("previous-run.txt");
+        std::fs::write(&previous, "kept").unwrap();
+        let csv = artifact_dir.join("soft.csv");
+        std::fs::write(&csv, "1,0,0,0,0,0,0,0,0,0\n".repeat(31)).unwrap();
+        let config = tiny_config().with_soft_labels(Some(csv));
+
+        let result = train_synthetic(&artifact_dir, config, 32, 16);
+        let kept = previous.exists();
+        std::fs::remove_dir_all(&artifact_dir).unwrap();
+        assert!(matches!(result, Err(TrainError::SoftLabels(SoftLabelsError::Count { found: 31, expected: 32 }))));
+        assert!(kept);
+    }
+
+    #[test]
+    fn saved_split_is_reused_on_the_same_datasets_only() {
+        let dir = std::env::temp_dir().join("my_first_rust_DL_app-split-from");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("split.json");
+        let train_set: MnistSet = Arc::new(SyntheticDigits::new(100, 1));
+        let test_set: MnistSet = Arc::new(SyntheticDigits::new(50, 2));
+        let saved = SplitIndices::new(
+            vec![5, 3, 99],
+            vec![7, 0],
+            DatasetFingerprint::of(&train_set),
+            Some(DatasetFingerprint::of(&test_set)),
+        );
+        saved.save(&path).unwrap();
+        let mut config = valid_config();
+        config.split_from = Some(path.clone());
+
+        let (train, valid, split) = apply_split(&config, train_set.clone(), test_set.clone()).unwrap();
+        assert_eq!(split, saved);
+        let labels = |dataset: &MnistSet| -> Vec<usize> {
+            let len = Dataset::len(dataset.as_ref());
+            (0..len).map(|index| ClassificationDataset::get(dataset, index).unwrap().1).collect()
+        };
+        let label = |dataset: &MnistSet, index| ClassificationDataset::get(dataset, index).unwrap().1;
+        assert_eq!(labels(&train), [5, 3, 99].map(|index| label(&train_set, index)));
+        assert_eq!(labels(&valid), [7, 0].map(|index| label(&test_set, index)));
+
+        // Another training set: both fingerprints are reported
+        let other: MnistSet = Arc::new(SyntheticDigits::new(100, 3));
+        let err = apply_split(&config, other.clone(), test_set.clone()).map(|_| ()).unwrap_err();
+        let TrainError::Split(SplitFileError::Fingerprint { split, saved: expected, found }) = &err else {
+            panic!("{err:?}");
+        };
+        assert_eq!((*split, expected, found), ("training", &saved.fingerprint, &DatasetFingerprint::of(&other)));
+        let message = err.to_string();
+        assert!(message.contains(&saved.fingerprint.hash) && message.contains(&found.hash), "{message}");
+        // The length is part of the fingerprint
+        let shorter: MnistSet = Arc::new(SyntheticDigits::new(60, 1));
+        let err = apply_split(&config, shorter, test_set.clone()).map(|_| ()).unwrap_err();
+        assert!(matches!(err, TrainError::Split(SplitFileError::Fingerprint { split: "training", .. })), "{err:?}");
+
+        config.train_subset = Some(dir.join("train_subset.json"));
+        assert_eq!(invalid_fields(&config), ["train_subset"]);
+        config.train_subset = None;
+        config.split_from = Some(dir.join("missing.json"));
+        let err = apply_split(&config, train_set, test_set).map(|_| ()).unwrap_err();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(matches!(err, TrainError::Split(SplitFileError::Io(_))), "{err:?}");
+    }
+
+    #[test]
+    fn run_saves_its_split_for_a_later_run_to_train_on() {
+        let artifact_dir = std::env::temp_dir().join("my_first_rust_DL_app-split-save");
+        let dir = artifact_dir.to_str().unwrap();
+        let reused_dir = format!("{dir}-reused");
+        let split_path = std::env::temp_dir().join("my_first_rust_DL_app-split-save.json");
+        let config = tiny_config().with_dataset(DatasetSource::Synthetic { num_samples: 32, seed: 1 });
+        let device = NdArrayDevice::default();
+
+        train::<Autodiff<NdArray>>(dir, config.clone(), device).unwrap();
+        let split = SplitIndices::load(ArtifactDir::new(dir).split_path()).unwrap();
+        // Half of the training items, in another order
+        let half = SplitIndices {
+            train: split.train.iter().rev().step_by(2).copied().collect(),
+            ..split.clone()
+        };
+        half.save(&split_path).unwrap();
+        train::<Autodiff<NdArray>>(&reused_dir, config.with_split_from(Some(split_path.clone())), device).unwrap();
+        let reused = SplitIndices::load(ArtifactDir::new(&reused_dir).split_path()).unwrap();
+        let steps = std::fs::read_to_string(format!("{reused_dir}/train/epoch-1/Loss.log")).unwrap().lines().count();
+        std::fs::remove_dir_all(&artifact_dir).unwrap();
+        std::fs::remove_dir_all(&reused_dir).unwrap();
+        std::fs::remove_file(&split_path).unwrap();
+
+        assert_eq!(split.train, (0..32).collect::<Vec<_>>());
+        assert_eq!(split.valid, (0..32).collect::<Vec<_>>());
+        assert!(split.valid_fingerprint.is_some());
+        assert_eq!(reused, half);
+        assert_eq!(steps, 1);
+    }
+
+    #[test]
+    fn adam_config_carries_the_configured_betas_and_epsilon() {
+        // burn keeps the fields of `AdamConfig` private, its JSON shows them
+        let json = |adam: AdamConfig| serde_json::to_value(adam).unwrap();
+        let mut config = valid_config();
+        assert_eq!(json(config.adam_config()), json(AdamConfig::new()));
+
+        config.adam_beta1 = 0.8;
+        config.adam_beta2 = 0.98;
+        config.adam_epsilon = 1e-8;
+        let weight_decay = burn::optim::decay::WeightDecayConfig::new(1e-4);
+        config.optimizer = AdamConfig::new().with_beta_1(0.8).with_weight_decay(Some(weight_decay));
+        let adam = json(config.adam_config());
+        assert_eq!((&adam["beta_1"], &adam["beta_2"]), (&serde_json::json!(0.8f32), &serde_json::json!(0.98f32)));
+        assert_eq!(adam["epsilon"], serde_json::json!(1e-8f32));
+        // The rest of `optimizer` is kept
+        let mut expected = json(config.optimizer.clone());
+        for (field, value) in [("beta_1", 0.8f32), ("beta_2", 0.98), ("epsilon", 1e-8)] {
+            expected[field] = serde_json::json!(value);
+        }
+        assert_eq!(adam, expected);
+        // Those of `optimizer` are ignored
+        config.optimizer = config.optimizer.clone().with_beta_2(0.5).with_epsilon(1e-3);
+        assert_eq!(json(config.adam_config()), adam);
+        assert_eq!(config.validate(), Ok(()));
+    }
+
+    #[test]
+    fn aux_loss_is_added_by_its_weight() {
+        let device = NdArrayDevice::default();
+        let model = ModelConfig::new(10, 8).with_aux_task(Some(AuxTask::Parity)).init::<NdArray>(&device);
+        let batch = MnistBatcher::<NdArray>::new(device).batch(SyntheticDigits::new(12, 1).iter().collect());
+        let cross_entropy = |output, targets| {
+            CrossEntropyLossConfig::new().init(&device).forward(output, targets).into_scalar()
+        };
+        let (output, aux_output) = model.forward_aux(batch.images.clone());
+        let primary = cross_entropy(output.clone(), batch.targets.clone());
+        let parity = AuxTask::Parity.targets(batch.targets.clone());
+        let labels = batch.targets.clone().into_data().convert::<i64>().value;
+        let expected: Vec<i64> = labels.iter().map(|label| label % 2).collect();
+        assert_eq!(parity.clone().into_data().convert::<i64>().value, expected);
+        let aux = cross_entropy(aux_output.unwrap(), parity);
+        assert_eq!(model.forward(batch.images.clone()).into_data(), output.into_data());
+
+        let loss = |aux_loss_weight| {
+            let loss = LossSettings { aux_loss_weight, ..LossSettings::default() };
+            model.forward_classification(batch.images.clone(), batch.targets.clone(), None, loss).loss.into_scalar()
+        };
+        assert_eq!(loss(0.0), primary);
+        let weighted = loss(0.5);
+        assert!((weighted - (primary + 0.5 * aux)).abs() < 1e-5, "{weighted} against {primary} + 0.5 * {aux}");
+
+        // The batches of a run carry the weight of its config to the steps
+        let mut config = valid_config();
+        config.aux_loss_weight = 0.5;
+        let batcher = MnistBatcher::<NdArray>::new(device).with_loss(config.loss_settings());
+        let batch = batcher.batch(SyntheticDigits::new(12, 1).iter().collect());
+        let stepped = ValidStep::step(&model, batch).classification.loss.into_scalar();
+        assert_eq!(stepped, weighted);
+    }
+
+    #[test]
+    fn aux_task_rejects_the_options_that_hide_the_digit() {
+        let mut config = valid_config();
+        config.model.aux_task = Some(AuxTask::Parity);
+        config.mixup_alpha = Some(0.4);
+        config.binary_target = Some(3);
+        let fields = invalid_fields(&config);
+        for field in ["mixup_alpha", "binary_target"] {
+            assert!(fields.iter().any(|invalid| invalid == field), "{fields:?}");
+        }
+        config.mixup_alpha = None;
+        config.binary_target = None;
+        assert_eq!(config.validate(), Ok(()));
+    }
+
+    #[test]
+    fn resume_at_another_batch_size_keeps_the_optimizer_state() {
+        let (resumed, config) = resumable_run("batch-size");
+        let artifact_dir = format!("{resumed}-larger");
+        let _ = std::fs::remove_dir_all(&artifact_dir);
+        let config = config.with_resume_from(Some(resumed.clone())).with_batch_size(32);
+        let device = NdArrayDevice::default();
+
+        let saved_config = TrainingConfig::load(format!("{resumed}/config.json")).unwrap();
+        let notice = batch_size_notice(&resumed, &saved_config, &config);
+        let resume = read_resume::<Autodiff<NdArray>>(&config, &device).unwrap().unwrap();
+        let saved = std::fs::read(format!("{resumed}/checkpoint/optim-1.mpk")).unwrap();
+        let reset = config.clone().with_reset_optimizer(true);
+        let fresh = read_resume::<Autodiff<NdArray>>(&reset, &device).unwrap().unwrap();
+        let model = train_synthetic(&artifact_dir, config, 32, 16).unwrap();
+        let steps = std::fs::read_to_string(format!("{artifact_dir}/train/epoch-1/Loss.log")).unwrap().lines().count();
+        let history = History::load(ArtifactDir::new(&artifact_dir).history_path()).unwrap();
+        std::fs::remove_dir_all(&resumed).unwrap();
+        std::fs::remove_dir_all(&artifact_dir).unwrap();
+
+        assert_eq!(
+            notice.unwrap(),
+            format!(
+                "Resuming from {resumed}, trained at batch size 16, at batch size 32: the learning rate is not \
+                 rescaled (0.002 scaled linearly, {} by the square root)",
+                1e-3 * 2f64.sqrt()
+            )
+        );
+        assert_eq!(resume.optimizer, Some(saved));
+        assert_eq!(fresh.optimizer, None);
+        // The 32 training items in a single batch of the new size
+        assert_eq!(steps, 1);
+        assert!(history.epochs[0].valid["Loss"].is_finite());
+        assert_eq!(model.num_params(), fresh.model.num_params());
+        assert_eq!(batch_size_notice(&resumed, &saved_config, &saved_config), None);
+    }
+}
@@ -0,0 +1,142 @@
+use crate::data::{ClassificationDataset, DatasetSource, MnistSplit};
+use std::fmt;
+
+// The first inconsistency found in a dataset split: what a truncated or corrupted download leaves
+#[derive(Debug, Clone, PartialEq)]
+pub enum DatasetError {
+    // The split does not have as many items as its source always has
+    Length { split: MnistSplit, expected: usize, found: usize },
+    // The dataset reported an item it could not return
+    Missing { split: MnistSplit, index: usize },
+    Label { split: MnistSplit, index: usize, label: usize, num_classes: usize },
+    // The number of pixels of an item is not `height * width` of the dataset image shape
+    Shape { split: MnistSplit, index: usize, num_pixels: usize, image_shape: [usize; 2] },
+    // A pixel is not a finite value in [0, 255]
+    Pixel { split: MnistSplit, index: usize, value: f32 },
+}
+
+impl fmt::Display for DatasetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DatasetError::Length { split, expected, found } => {
+                write!(f, "the {split:?} split has {found} items, expected {expected}")
+            }
+            DatasetError::Missing { split, index } => write!(f, "item {index} of the {split:?} split cannot be read"),
+            DatasetError::Label { split, index, label, num_classes } => write!(
+                f,
+                "item {index} of the {split:?} split has label {label}, expected one in 0..{num_classes}"
+            ),
+            DatasetError::Shape { split, index, num_pixels, image_shape: [height, width] } => write!(
+                f,
+                "item {index} of the {split:?} split has {num_pixels} pixels, expected {height}x{width}"
+            ),
+            DatasetError::Pixel { split, index, value } => {
+                write!(f, "item {index} of the {split:?} split has pixel value {value}, expected one in [0, 255]")
+            }
+        }
+    }
+}
+
+impl std::error::Error for DatasetError {}
+
+impl DatasetSource {
+    // Number of items `split` always has, `None` when the source does not know it
+    pub fn expected_len(&self, split: MnistSplit) -> Option<usize> {
+        match self {
+            DatasetSource::Mnist => Some(match split {
+                MnistSplit::Train => 60_000,
+                MnistSplit::Test => 10_000,
+            }),
+            #[cfg(any(test, feature = "test-utils"))]
+            DatasetSource::Synthetic { num_samples, .. } => Some(*num_samples),
+            DatasetSource::Concat(sources) => sources.iter().map(|source| source.expected_len(split)).sum(),
+            DatasetSource::ImageFolder { .. } => None,
+        }
+    }
+}
+
+/// Reads every item of `dataset`, the `split` of some source, and checks that it has
+/// `expected_len` items (when known), each readable, labelled in `0..num_classes()` and of the
+/// dataset image shape, with pixels in [0, 255]. Stops at the first inconsistency.
+pub fn verify_items<D: ClassificationDataset + ?Sized>(
+    dataset: &D,
+    split: MnistSplit,
+    expected_len: Option<usize>,
+) -> Result<(), DatasetError> {
+    if let Some(expected) = expected_len {
+        if dataset.len() != expected {
+            return Err(DatasetError::Length { split, expected, found: dataset.len() });
+        }
+    }
+
+    let (num_classes, image_shape) = (dataset.num_classes(), dataset.image_shape());
+    for index in 0..dataset.len() {
+        let (pixels, label) = dataset.get(index).ok_or(DatasetError::Missing { split, index })?;
+        if label >= num_classes {
+            return Err(DatasetError::Label { split, index, label, num_classes });
+        }
+        if pixels.len() != image_shape[0] * image_shape[1] {
+            return Err(DatasetError::Shape { split, index, num_pixels: pixels.len(), image_shape });
+        }
+        if let Some(&value) = pixels.iter().find(|&&value| !(0.0..=255.0).contains(&value)) {
+            return Err(DatasetError::Pixel { split, index, value });
+        }
+    }
+    Ok(())
+}
+
+/// Loads both splits of `source` and checks them with [`verify_items`], the training split
+/// first: a full pass over the data, to run before committing to a long training run.
+pub fn verify_dataset(source: &DatasetSource, cache: bool) -> Result<(), DatasetError> {
+    for split in [MnistSplit::Train, MnistSplit::Test] {
+        let dataset = source.load(split, cache);
+        verify_items(&dataset, split, source.expected_len(split))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::synthetic::SyntheticDigits;
+
+    // Synthetic digits whose item 5 has label 10, one past the last class
+    struct OutOfRangeLabel(SyntheticDigits);
+
+    impl ClassificationDataset for OutOfRangeLabel {
+        fn len(&self) -> usize {
+            ClassificationDataset::len(&self.0)
+        }
+
+        fn get(&self, index: usize) -> Option<(Vec<f32>, usize)> {
+            let (pixels, label) = ClassificationDataset::get(&self.0, index)?;
+            Some((pixels, if index == 5 { 10 } else { label }))
+        }
+
+        fn num_classes(&self) -> usize {
+            self.0.num_classes()
+        }
+
+        fn image_shape(&self) -> [usize; 2] {
+            self.0.image_shape()
+        }
+    }
+
+    #[test]
+    fn synthetic_digits_pass_verification() {
+        assert_eq!(verify_dataset(&DatasetSource::Synthetic { num_samples: 20, seed: 1 }, false), Ok(()));
+    }
+
+    #[test]
+    fn out_of_range_label_fails_verification() {
+        let dataset = OutOfRangeLabel(SyntheticDigits::new(20, 1));
+
+        let error = verify_items(&dataset, MnistSplit::Train, Some(20)).unwrap_err();
+        assert_eq!(error, DatasetError::Label { split: MnistSplit::Train, index: 5, label: 10, num_classes: 10 });
+        assert_eq!(error.to_string(), "item 5 of the Train split has label 10, expected one in 0..10");
+        assert_eq!(
+            verify_items(&dataset, MnistSplit::Test, Some(30)),
+            Err(DatasetError::Length { split: MnistSplit::Test, expected: 30, found: 20 })
+        );
+    }
+}
@@ -0,0 +1,167 @@
+use crate::{
+    checkpoint::{LoadError, LoadReport},
+    inference::load_model,
+    params::{named_params, NamedParam},
+};
+use burn::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+// How far one parameter (or all of them, for the totals) moved from model A to model B. Norms
+// are L2 norms over the flattened values, accumulated in f64.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ParamDiff {
+    pub name: String,
+    pub num_elements: usize,
+    // Norm of B - A
+    pub diff_norm: f64,
+    pub norm_a: f64,
+    pub norm_b: f64,
+    // `diff_norm / norm_a`, `None` when A is all zeros
+    pub relative_change: Option<f64>,
+    // Cosine similarity of A and B, `None` when either is all zeros
+    pub cosine_similarity: Option<f64>,
+}
+
+impl ParamDiff {
+    fn new(name: &str, a: &[f32], b: &[f32]) -> Self {
+        let (mut diff, mut norm_a, mut norm_b, mut dot) = (0.0, 0.0, 0.0, 0.0);
+        for (&a, &b) in a.iter().zip(b) {
+            let (a, b) = (a as f64, b as f64);
+            diff += (b - a) * (b - a);
+            norm_a += a * a;
+            norm_b += b * b;
+            dot += a * b;
+        }
+        let (diff_norm, norm_a, norm_b) = (diff.sqrt(), norm_a.sqrt(), norm_b.sqrt());
+        Self {
+            name: name.to_string(),
+            num_elements: a.len(),
+            diff_norm,
+            norm_a,
+            norm_b,
+            relative_change: (norm_a > 0.0).then(|| diff_norm / norm_a),
+            cosine_similarity: (norm_a > 0.0 && norm_b > 0.0).then(|| dot / (norm_a * norm_b)),
+        }
+    }
+}
+
+/// How far the weights of one model moved from those of another of the same architecture, as
+/// returned by [`diff_weights`]: per named parameter, sorted by name, and over all of them.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WeightDiffReport {
+    pub params: Vec<ParamDiff>,
+    pub total: ParamDiff,
+}
+
+impl fmt::Display for WeightDiffReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cell = |value: Option<f64>| value.map_or("n/a".to_string(), |value| format!("{value:.6}"));
+        writeln!(
+            f,
+            "| {:<16} | {:>10} | {:>12} | {:>12} | {:>12} | {:>10} | {:>10} |",
+            "Parameter", "Elements", "|B - A|", "|A|", "|B|", "Relative", "Cosine"
+        )?;
+        writeln!(
+            f,
+            "|------------------|------------|--------------|--------------|--------------|------------|------------|"
+        )?;
+        for diff in self.params.iter().chain([&self.total]) {
+            writeln!(
+                f,
+                "| {:<16} | {:>10} | {:>12.6} | {:>12.6} | {:>12.6} | {:>10} | {:>10} |",
+                diff.name,
+                diff.num_elements,
+                diff.diff_norm,
+                diff.norm_a,
+                diff.norm_b,
+                cell(diff.relative_change),
+                cell(diff.cosine_similarity)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// [`diff_weights`] of parameters already read, e.g. with
+/// [`named_params`](crate::params::named_params). Parameters that are missing from either side
+/// or differ in shape are returned as the report of which ones.
+pub fn diff_params(a: &[NamedParam], b: &[NamedParam]) -> Result<WeightDiffReport, LoadReport> {
+    let report = LoadReport::compare(a, b);
+    if !report.is_exact() {
+        return Err(report);
+    }
+
+    let mut params: Vec<ParamDiff> = a
+        .iter()
+        .map(|param_a| {
+            let param_b = b.iter().find(|param_b| param_b.name == param_a.name).expect("Compared as present");
+            ParamDiff::new(&param_a.name, &param_a.values, &param_b.values)
+        })
+        .collect();
+    params.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let all = |params: &[NamedParam]| -> Vec<f32> {
+        let mut params: Vec<&NamedParam> = params.iter().collect();
+        params.sort_by(|a, b| a.name.cmp(&b.name));
+        params.iter().flat_map(|param| param.values.iter().copied()).collect()
+    };
+    let total = ParamDiff::new("total", &all(a), &all(b));
+    Ok(WeightDiffReport { params, total })
+}
+
+/// Compares the weights of the models trained in `artifact_a` and `artifact_b`, e.g. a
+/// fine-tuned model and its pretrained starting point. The two must have the same parameters:
+/// otherwise the error is a [`LoadError::Mismatch`] listing the differences.
+pub fn diff_weights<B: Backend>(
+    artifact_a: &str,
+    artifact_b: &str,
+    device: &B::Device,
+) -> Result<WeightDiffReport, LoadError> {
+    let a = named_params(&load_model::<B>(artifact_a, device)?);
+    let b = named_params(&load_model::<B>(artifact_b, device)?);
+    diff_params(&a, &b).map_err(LoadError::Mismatch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn param(name: &str, values: &[f32]) -> NamedParam {
+        NamedParam { name: name.to_string(), shape: vec![values.len()], values: values.to_vec() }
+    }
+
+    #[test]
+    fn norms_and_similarities_of_hand_computed_params() {
+        let a = [param("w", &[3.0, 4.0]), param("b", &[0.0, 0.0])];
+        let b = [param("b", &[1.0, 0.0]), param("w", &[-3.0, -4.0])];
+        let report = diff_params(&a, &b).unwrap();
+
+        let names: Vec<&str> = report.params.iter().map(|diff| diff.name.as_str()).collect();
+        assert_eq!(names, ["b", "w"]);
+        let (bias, weight) = (&report.params[0], &report.params[1]);
+        assert_eq!((bias.diff_norm, bias.norm_a, bias.norm_b), (1.0, 0.0, 1.0));
+        assert_eq!((bias.relative_change, bias.cosine_similarity), (None, None));
+        assert_eq!((weight.num_elements, weight.diff_norm, weight.norm_a, weight.norm_b), (2, 10.0, 5.0, 5.0));
+        assert_eq!((weight.relative_change, weight.cosine_similarity), (Some(2.0), Some(-1.0)));
+        // b then w: [0, 0, 3, 4] against [1, 0, -3, -4]
+        assert_eq!(report.total.num_elements, 4);
+        assert!((report.total.diff_norm - 101.0f64.sqrt()).abs() < 1e-12);
+        assert!((report.total.cosine_similarity.unwrap() + 25.0 / (5.0 * 26.0f64.sqrt())).abs() < 1e-12);
+        assert_eq!(diff_params(&a, &a).unwrap().total.relative_change, Some(0.0));
+
+        let table = report.to_string();
+        assert_eq!(table.lines().count(), 5);
+        assert!(table.lines().nth(2).unwrap().contains("n/a"));
+    }
+
+    #[test]
+    fn params_missing_or_of_another_shape_are_reported() {
+        let a = [param("w", &[1.0, 2.0]), param("b", &[0.0])];
+        let reshaped = [param("w", &[1.0, 2.0, 3.0]), param("b", &[0.0])];
+        let report = diff_params(&a, &reshaped).unwrap_err();
+        assert_eq!(report.shape_mismatches.len(), 1);
+        let report = diff_params(&a, &a[..1]).unwrap_err();
+        assert_eq!([report.missing, report.unexpected].concat(), ["b"]);
+    }
+}
@@ -0,0 +1,53 @@
+use burn::{
+    backend::{
+        candle::{Candle, CandleDevice},
+        Autodiff,
+    },
+    module::AutodiffModule,
+    optim::AdamConfig,
+    tensor::f16,
+};
+use my_first_rust_DL_app::{
+    evaluation::dataset_predictions,
+    meta::ModelMeta,
+    synthetic::SyntheticDigits,
+    train_on,
+    training::{PrecisionKind, Verbosity},
+    HeadInput, ModelConfig, TrainingConfig,
+};
+
+#[test]
+fn trains_above_chance_in_f16() {
+    let artifact_dir = std::env::temp_dir().join("my_first_rust_DL_app-train_f16");
+    // A run killed halfway leaves its lock behind
+    let _ = std::fs::remove_dir_all(&artifact_dir);
+
+    // Candle has no adaptive average pooling
+    let model = ModelConfig::new(10, 32).with_head_input(HeadInput::Flatten);
+    let config = TrainingConfig::new(model, AdamConfig::new())
+        .with_precision(PrecisionKind::F16)
+        .with_adam_epsilon(1e-3)
+        .with_num_epochs(3)
+        .with_batch_size(32)
+        .with_num_workers(1)
+        .with_learning_rate(1e-3)
+        .with_verbosity(Verbosity::Silent);
+    let device = CandleDevice::Cpu;
+    // The nan guard fails the run on the first step with non-finite gradients
+    let model = train_on::<Autodiff<Candle<f16, i64>>, _>(
+        artifact_dir.to_str().unwrap(),
+        config,
+        SyntheticDigits::new(512, 1),
+        SyntheticDigits::new(128, 2),
+        device,
+    )
+    .unwrap();
+
+    let outcomes = dataset_predictions(&model.valid(), &SyntheticDigits::new(128, 2), &device);
+    let accuracy = outcomes.iter().filter(|outcome| outcome.is_correct()).count() as f32 / outcomes.len() as f32;
+    assert!(accuracy > 0.1, "validation accuracy {accuracy} is not above chance");
+    let meta = ModelMeta::load(artifact_dir.to_str().unwrap()).unwrap().unwrap();
+    assert_eq!(meta.precision, PrecisionKind::F16);
+
+    std::fs::remove_dir_all(&artifact_dir).unwrap();
+}
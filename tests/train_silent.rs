@@ -0,0 +1,56 @@
+use burn::{
+    backend::{ndarray::NdArrayDevice, Autodiff, NdArray},
+    optim::AdamConfig,
+};
+use my_first_rust_DL_app::{
+    artifact::ArtifactDir, history::History, synthetic::SyntheticDigits, train_on, training::Verbosity, ModelConfig,
+    TrainingConfig,
+};
+use std::process::Command;
+
+// Set in the child process that trains, so that its stdout is nothing but the run's
+const CHILD_ENV: &str = "MY_FIRST_RUST_DL_APP_SILENT_CHILD";
+// Printed by the child around the run, to tell the run's output from the test harness'
+const START: &str = "[silent run start]";
+const END: &str = "[silent run end]";
+
+#[test]
+fn silent_run_prints_nothing_and_still_writes_its_history() {
+    let artifact_dir = std::env::temp_dir().join("my_first_rust_DL_app-train_silent");
+
+    if std::env::var_os(CHILD_ENV).is_some() {
+        // A run killed halfway leaves its lock behind
+        let _ = std::fs::remove_dir_all(&artifact_dir);
+        let config = TrainingConfig::new(ModelConfig::new(10, 8), AdamConfig::new())
+            .with_num_epochs(2)
+            .with_batch_size(16)
+            .with_num_workers(1)
+            .with_verbosity(Verbosity::Silent);
+        println!("{START}");
+        train_on::<Autodiff<NdArray>, _>(
+            artifact_dir.to_str().unwrap(),
+            config,
+            SyntheticDigits::new(32, 1),
+            SyntheticDigits::new(16, 2),
+            NdArrayDevice::default(),
+        )
+        .unwrap();
+        println!("{END}");
+        return;
+    }
+
+    let output = Command::new(std::env::current_exe().unwrap())
+        .args(["--exact", "silent_run_prints_nothing_and_still_writes_its_history", "--nocapture", "--test-threads=1"])
+        .env(CHILD_ENV, "1")
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let (start, end) = (stdout.find(START).unwrap(), stdout.find(END).unwrap());
+    assert_eq!(stdout[start + START.len()..end].trim(), "", "the silent run printed");
+
+    let history = History::load(ArtifactDir::new(artifact_dir.to_str().unwrap()).history_path()).unwrap();
+    assert_eq!(history.epochs.len(), 2);
+    assert!(history.epochs.iter().all(|epoch| epoch.valid["Accuracy"].is_finite()));
+    std::fs::remove_dir_all(&artifact_dir).unwrap();
+}
@@ -0,0 +1,38 @@
+use burn::{
+    backend::{ndarray::NdArrayDevice, Autodiff, NdArray},
+    module::AutodiffModule,
+    optim::AdamConfig,
+};
+use my_first_rust_DL_app::{
+    evaluation::dataset_predictions, synthetic::SyntheticDigits, train_on, training::Verbosity, ModelConfig,
+    TrainingConfig,
+};
+
+#[test]
+fn trains_above_chance_on_synthetic_digits() {
+    let artifact_dir = std::env::temp_dir().join("my_first_rust_DL_app-train_synthetic");
+    // A run killed halfway leaves its lock behind
+    let _ = std::fs::remove_dir_all(&artifact_dir);
+
+    let config = TrainingConfig::new(ModelConfig::new(10, 32), AdamConfig::new())
+        .with_num_epochs(2)
+        .with_batch_size(32)
+        .with_num_workers(1)
+        .with_learning_rate(3e-3)
+        .with_verbosity(Verbosity::Silent);
+    let device = NdArrayDevice::default();
+    let model = train_on::<Autodiff<NdArray>, _>(
+        artifact_dir.to_str().unwrap(),
+        config,
+        SyntheticDigits::new(512, 1),
+        SyntheticDigits::new(128, 2),
+        device,
+    )
+    .unwrap();
+
+    let outcomes = dataset_predictions(&model.valid(), &SyntheticDigits::new(128, 2), &device);
+    let accuracy = outcomes.iter().filter(|outcome| outcome.is_correct()).count() as f32 / outcomes.len() as f32;
+    assert!(accuracy > 0.1, "validation accuracy {accuracy} is not above chance");
+
+    std::fs::remove_dir_all(&artifact_dir).unwrap();
+}